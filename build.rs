@@ -0,0 +1,39 @@
+// Oxid OS
+// The build script which embeds the version metadata (git hash and build date) in the kernel. 
+// They are read by the version module using env!.
+//
+// Author:  Ardalan Ahanchi
+// Date:    Mar 2021
+// License: GPLv2
+
+use std::process::Command;
+
+/// A function which runs a command and returns it's trimmed output.
+///
+/// # Parameters
+/// `program` : The program which we're running.
+/// `args` : The arguments passed to it.
+///
+/// # Returns
+/// The output if the command succeeded, "unknown" otherwise.
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program).args(args).output().ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|output| output.trim().to_string())
+        .filter(|output| !output.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+fn main() {
+    // The short hash of the current commit, and the build date (UTC).
+    let git_hash = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let build_date = command_output("date", &["-u", "+%Y-%m-%d %H:%M"]);
+
+    println!("cargo:rustc-env=OXID_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=OXID_BUILD_DATE={}", build_date);
+
+    // Only rerun when the commit changes.
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
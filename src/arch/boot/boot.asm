@@ -56,6 +56,7 @@ PD:                                                 ; Page directory
     resb 4096
 PT:                                                 ; Page tables (512 of them)
     resb 4096 * 512
+PT_END:                                             ; The end of the page tables
 
 stack_end:                                          ; Define the stack
     resb 16384                                      ; Reseve 16 KiloBytes for it
@@ -73,6 +74,13 @@ section .data
 ; http://www.jamesmolloy.co.uk/tutorial_html/4.-The%20GDT%20and%20IDT.html
 global gdt
 global gdt.tss
+global boot_id_mapped_size
+
+; The number of bytes which were identity mapped by _identity_map_first_gb.
+; It is read by the kernel (get_boot_id_mapped_size) so it knows what to unmap.
+align 8
+boot_id_mapped_size:
+    dq 0
 
 align 8
 gdt:                       ; Define a x86_64 GDT. See AMD programming ch. 4.8.
@@ -175,6 +183,8 @@ _identity_map_first_gb:
     cmp ecx , PAGING_NUM_ENTRIES
     jne _identity_map_first_gb.map_pd      	; If not done, countinue loop 1.
 
+    mov [boot_id_mapped_size] , ebx         ; Store how much was mapped.
+
     ; Restore the current value of registers from the stack.
     pop edi
     pop edx
@@ -246,6 +256,27 @@ bits 64
 ; Define the external function which is the kernel's main function
 extern kernel_main
 
+global get_boot_id_mapped_size
+global get_boot_pts_start
+global get_boot_pts_end
+
+; A sub-routine which returns the number of bytes which were identity mapped
+; during boot (starting at 0x0).
+get_boot_id_mapped_size:
+    mov rax , [boot_id_mapped_size]
+    ret
+
+; A sub-routine which returns the address of the first boot page table (PT).
+; The rest of them follow it (each one maps the next 2MB).
+get_boot_pts_start:
+    mov rax , PT
+    ret
+
+; A sub-routine which returns the address after the last boot page table.
+get_boot_pts_end:
+    mov rax , PT_END
+    ret
+
 ; The starting point of long mode. The kernel would be called from here.
 _start_64:
     ; Reset all the segment selectors (to override the old GDT offsets)
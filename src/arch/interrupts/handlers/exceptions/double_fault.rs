@@ -12,11 +12,16 @@ use crate::arch::proc;
 
 /// A function which is registered to handle the Double fault exception. Since it is an
 /// exception, it should not resume execution until the problem is solved. By default, this 
-/// will display an error message corresponding to the error and halt.
+/// will display an error message corresponding to the error and halt. It runs on it's own stack
+/// (see arch::proc::process::init), so a kernel stack which overflowed into it's guard page (the
+/// page fault could not be pushed) is reported as well.
 ///
 /// # Parameters
 /// `info` : The context before the interrupt happended (registers, error code, etc.).
 pub fn handle(_info: *const context::Context) {
+    // The address of the page fault which could not be delivered (if that was the cause).
+    unsafe { crate::mem::page_fault::check_guard(crate::arch::registers::get_cr2() & (!0xFFF)); }
+    
     oxid_err!("Double fault exception recieved. Halting the system.");
     unsafe { proc::halt(); }
 }
@@ -32,7 +32,8 @@ mod simd_fp;
 pub fn init() {
     super::register_trap(0x0, divide_by_zero::handle);
     super::register_trap(0x1, trap_flag::handle);
-    super::register_trap(0x2, non_maskable_int::handle);
+    // The NMI can arrive while the interrupts are enabled, so it's not interrupted by an IRQ.
+    super::register_int(0x2, non_maskable_int::handle);
     super::register_trap(0x3, break_point::handle);
     super::register_trap(0x4, overflow::handle);
     super::register_trap(0x5, bound_range::handle);
@@ -10,13 +10,25 @@
 use crate::arch::interrupts::handlers::context;
 use crate::arch::proc;
 
-/// A function which is registered to handle the Non-maskable interrupt exception. Since it is an
-/// exception, it should not resume execution until the problem is solved. By default, this 
-/// will display an error message corresponding to the error and halt.
+/// The port which reports the sources of the hardware NMIs (system control port B).
+const NMI_STATUS_PORT: u16 = 0x61;
+
+/// The bits in the status port which are set by a memory parity error, or an I/O channel check.
+const NMI_HARDWARE_BITS: u8 = (1 << 7) | (1 << 6);
+
+/// A function which is registered to handle the Non-maskable interrupt exception. The periodic NMIs
+/// of the watchdog (see proc::watchdog) are passed to it. Otherwise, it is a hardware failure, so 
+/// this will display an error message corresponding to the error and halt.
 ///
 /// # Parameters
 /// `info` : The context before the interrupt happended (registers, error code, etc.).
-pub fn handle(_info: *const context::Context) {
+pub fn handle(info: *const context::Context) {
+    let hardware = unsafe { crate::arch::interrupts::pic::in_b(NMI_STATUS_PORT) } 
+        & NMI_HARDWARE_BITS != 0;
+    if !hardware && unsafe { crate::proc::watchdog::nmi(info as *mut u8) } {
+        return;
+    }
+    
     oxid_err!("Non-maskable interrupt exception recieved. Halting the system.");
     unsafe { proc::halt(); }
 }
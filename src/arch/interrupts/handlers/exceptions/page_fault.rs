@@ -19,6 +19,11 @@ use crate::mem::bitwise::BitWise;
 /// `info` : The context before the interrupt happended (registers, error code, etc.).
 pub fn handle(info: *const context::Context) {
     unsafe {
+        // The faults caused by probing the memory are expected (they are just reported).
+        if crate::arch::mem::probe::fixup(info as *mut context::Context) {
+            return;
+        }
+        
         // Get the address of the page which cause the fault from the CR2 register, and clear 
         // out the properties bits (first 12 bits).
         let page_addr = crate::arch::registers::get_cr2() & (!0xFFF);
@@ -8,13 +8,15 @@
 
 pub mod context;
 pub mod exceptions;
+pub mod threaded;
 
 use crate::proc::mutex::Mutex;
 use crate::arch::interrupts::idt;
 use crate::arch::proc;
+use crate::arch::time::tsc;
 
 /// Define a type for interrupt handlers (which is just a function which accepts a context).
-type InterruptHandler = unsafe fn(*const context::Context);
+pub type InterruptHandler = unsafe fn(*const context::Context);
 
 /// Define an array of handlers protected with a mutex. Additionally, make the interrupt handlers 
 /// optional (since not all of them might be registered). By default none of them are registered.
@@ -23,6 +25,44 @@ static mut HANDLERS: [Option<InterruptHandler>; idt::NUM_IDT_ENTRIES] = [None; i
 /// Define a basic mutex for the handlers.
 static mut HANDLERS_MUTEX: Mutex = Mutex::new();
 
+/// The number of hardware interrupts (IRQs) which were dispatched.
+static mut IRQS_ENTERED: usize = 0;
+
+/// The number of EOIs which were sent (it should match IRQS_ENTERED once the handlers return).
+static mut EOIS_SENT: usize = 0;
+
+/// The number of hardware interrupt handlers which are currently running (they can be nested).
+static mut IRQ_DEPTH: usize = 0;
+
+/// The number of interrupt handlers of any kind (including the exceptions) which are currently 
+/// running. The trap gates leave the interrupts enabled, so the timer can nest in them.
+static mut INT_DEPTH: usize = 0;
+
+/// The number of IRQ lines (the vectors starting at IRQ_OFFSET are hardware interrupts).
+const NUM_IRQ_LINES: u8 = 16;
+
+/// A structure which represents how long the interrupts were masked by the handlers of a line.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MaskedStats {
+    pub count: u64,                            // The number of measured interrupts.
+    pub total_cycles: u64,                     // The sum of the masked windows (TSC cycles).
+    pub max_cycles: u64,                       // The longest masked window (TSC cycles).
+}
+
+impl MaskedStats {
+    /// A method which calculates the average length of the masked window.
+    ///
+    /// # Returns
+    /// The average number of TSC cycles (0 if nothing was measured).
+    pub fn mean_cycles(&self) -> u64 {
+        if self.count == 0 { 0 } else { self.total_cycles / self.count }
+    }
+}
+
+/// The masked windows of each IRQ line (from the dispatch to the EOI).
+static mut MASKED: [MaskedStats; NUM_IRQ_LINES as usize] = [MaskedStats { count: 0, 
+    total_cycles: 0, max_cycles: 0 }; NUM_IRQ_LINES as usize];
+
 /// The main entry point for the interrupts. The interrupt number and the context is passed by the 
 /// assembly code at arch/interrupts/idt/isr.asm (which actually calls this function). Based on the 
 /// registered interrupts, it calls the corresponding high-level handler.
@@ -32,6 +72,22 @@ static mut HANDLERS_MUTEX: Mutex = Mutex::new();
 /// `info` : The context structure which determines what was going on before the interrupt.
 #[no_mangle]
 unsafe extern "sysv64" fn main_handler(int_num: u8, info: *const context::Context) {
+    INT_DEPTH += 1;
+    let is_irq = is_irq(int_num);
+    let entered_at = if is_irq { tsc::read() } else { 0 };
+    if is_irq {
+        IRQS_ENTERED += 1;
+        IRQ_DEPTH += 1;
+        
+        // The threaded lines run their top half (and queue the bottom half) instead.
+        if threaded::dispatch(int_num - super::IRQ_OFFSET, info) {
+            IRQ_DEPTH -= 1;
+            end_of_interrupt(int_num);
+            record_masked(int_num, entered_at);
+            return;
+        }
+    }
+    
     // Check if the handler is registered currently. Since we're not modifying anything in the 
     // handlers, we don't need to modify the mutex.
     match HANDLERS[int_num as usize] {
@@ -44,6 +100,121 @@ unsafe extern "sysv64" fn main_handler(int_num: u8, info: *const context::Contex
             proc::halt();
         }
     };
+    
+    // The hardware interrupts are acknowledged exactly once (here), after their handler returns.
+    if is_irq {
+        IRQ_DEPTH -= 1;
+        end_of_interrupt(int_num);
+        record_masked(int_num, entered_at);
+    }
+}
+
+/// The hook which is called by the assembly code right before it returns from an interrupt (after
+/// main_handler). If the timer wanted to switch while it was nested in another handler, the switch
+/// is done here, once the outermost handler is done (see scheduler::schedule). The handler is only
+/// counted as finished here, so an NMI which arrives in between is still seen as nested.
+///
+/// # Parameters
+/// `info` : The context which is restored when returning.
+#[no_mangle]
+unsafe extern "sysv64" fn exit_handler(info: *mut context::Context) {
+    let were_enabled = super::save_and_disable();
+    
+    // The nested handlers return to the handler they interrupted (it does the switch).
+    if INT_DEPTH == 1 && crate::proc::scheduler::switch_pending() {
+        crate::proc::scheduler::schedule_pending(info as *mut u8);
+    }
+    
+    INT_DEPTH -= 1;
+    super::restore(were_enabled);
+}
+
+/// A helper which records the masked window of an IRQ (from the dispatch until after the EOI).
+///
+/// # Parameters
+/// `int_num` : The interrupt number of the IRQ.
+/// `entered_at` : The TSC when the dispatch started.
+#[inline]
+unsafe fn record_masked(int_num: u8, entered_at: u64) {
+    let cycles = tsc::read().saturating_sub(entered_at);
+    let stats = &mut MASKED[(int_num - super::IRQ_OFFSET) as usize];
+    stats.count += 1;
+    stats.total_cycles += cycles;
+    stats.max_cycles = core::cmp::max(stats.max_cycles, cycles);
+}
+
+/// A helper which checks if an interrupt number belongs to a hardware interrupt (an IRQ).
+///
+/// # Parameters
+/// `int_num` : The interrupt number (0-255).
+///
+/// # Returns
+/// True if it's an IRQ, False otherwise.
+#[inline]
+fn is_irq(int_num: u8) -> bool {
+    int_num >= super::IRQ_OFFSET && int_num < super::IRQ_OFFSET + NUM_IRQ_LINES
+}
+
+/// A helper which sends the end of interrupt to the interrupt controller (only the PIC is 
+/// supported for now, the APIC would be acknowledged here as well).
+///
+/// # Parameters
+/// `int_num` : The interrupt number of the IRQ.
+#[inline]
+unsafe fn end_of_interrupt(int_num: u8) {
+    super::pic::end_of_interrupt(int_num - super::IRQ_OFFSET);
+    EOIS_SENT += 1;
+}
+
+/// A function which checks if a hardware interrupt handler is currently running. The code which
+/// runs in them can't wait for the scheduler (their EOI is only sent after they return).
+///
+/// # Returns
+/// True if it's called from an IRQ handler, False otherwise.
+pub fn in_irq() -> bool {
+    unsafe { IRQ_DEPTH > 0 }
+}
+
+/// A simple getter for the number of interrupt handlers which are currently running (1 in an 
+/// interrupt which was not nested, 0 outside of the handlers).
+///
+/// # Returns
+/// The nesting depth.
+pub fn int_depth() -> usize {
+    unsafe { INT_DEPTH }
+}
+
+/// A simple getter for the number of hardware interrupts which were dispatched, and the number of 
+/// EOIs which were sent. They only differ while the handlers are running.
+///
+/// # Returns
+/// A tuple of (entered, eois).
+pub fn irq_counts() -> (usize, usize) {
+    unsafe {
+        let were_enabled = super::save_and_disable();
+        let counts = (IRQS_ENTERED, EOIS_SENT);
+        super::restore(were_enabled);
+        
+        counts
+    }
+}
+
+/// A simple getter for the masked windows of an IRQ line (how long the interrupts stayed masked 
+/// while it was handled).
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+///
+/// # Returns
+/// A copy of the statistics (all zero for an invalid line).
+pub fn masked_stats(irq: u8) -> MaskedStats {
+    unsafe {
+        let were_enabled = super::save_and_disable();
+        let stats = MASKED.get(irq as usize).copied().unwrap_or_default();
+        super::restore(were_enabled);
+        
+        stats
+    }
 }
 
 
@@ -109,6 +280,17 @@ pub fn register_int(int_num: u8, handler: InterruptHandler) {
 }
 
 
+/// A function which checks if a handler is registered for an interrupt.
+///
+/// # Parameters
+/// `int_num` : The interrupt number (0-255).
+///
+/// # Returns
+/// True if there is a handler, False otherwise.
+pub fn is_registered(int_num: u8) -> bool {
+    unsafe { HANDLERS[int_num as usize].is_some() }
+}
+
 /// A function which unregisters an interrupt, and falls back to the default handler.
 ///
 /// # Parameters
@@ -0,0 +1,212 @@
+//! A sub-module which splits the hardware interrupt handlers into a top half and a bottom half. The
+//! top half runs in the interrupt (with the interrupts masked), and it should only do the minimum
+//! (such as reading a byte from the device). If it asks for it, the bottom half is queued in the
+//! work queue, and it runs later in the kworker thread with the interrupts enabled. The bottom half
+//! is only queued once, even if the line fires again before it runs (so it should drain whatever
+//! the top half collected).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use super::context::Context;
+use crate::arch::interrupts::{self, idt};
+use crate::proc::mutex::Mutex;
+
+/// The number of IRQ lines which can have a threaded handler.
+const NUM_LINES: usize = super::NUM_IRQ_LINES as usize;
+
+/// An enum which represents what the top half did with the interrupt.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IrqAck {
+    Handled,                    // The interrupt was fully handled in the top half.
+    Defer,                      // The bottom half should be queued.
+    NotMine,                    // The device did not raise the interrupt.
+}
+
+/// The type of the top halves (they get the context of the interrupt).
+pub type TopHalf = fn(&Context) -> IrqAck;
+
+/// The type of the bottom halves (they run in the kworker thread).
+pub type BottomHalf = fn();
+
+/// A structure which represents the handlers of a threaded IRQ line.
+#[derive(Copy, Clone)]
+struct Threaded {
+    top: TopHalf,                              // Called in the interrupt.
+    bottom: BottomHalf,                        // Called from the work queue (if deferred).
+}
+
+/// The threaded handlers of each line (None if the line is not threaded).
+static mut LINES: [Option<Threaded>; NUM_LINES] = [None; NUM_LINES];
+
+/// True while the bottom half of a line is queued (but has not started yet).
+static mut PENDING: [bool; NUM_LINES] = [false; NUM_LINES];
+
+/// The number of interrupts on each line which were not raised by the device (NotMine).
+static mut UNHANDLED: [usize; NUM_LINES] = [0; NUM_LINES];
+
+/// The number of bottom halves which could not be queued (the work queue was full).
+static mut DROPPED: usize = 0;
+
+/// A mutex which protects the registration of the lines.
+static mut LINES_MUTEX: Mutex = Mutex::new();
+
+/// A function which registers a threaded handler for an IRQ line. The vector is marked as an
+/// interrupt in the IDT (so the top half always runs with the interrupts masked).
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+/// `top` : The top half which is called in the interrupt.
+/// `bottom` : The bottom half which is queued if the top half returns Defer.
+///
+/// # Returns
+/// Ok if it was registered, Err if the line is invalid or already has a handler.
+pub fn request_irq_threaded(irq: u8, top: TopHalf, bottom: BottomHalf) -> Result<(), ()> {
+    if irq as usize >= NUM_LINES {
+        return Err(());
+    }
+
+    let vector = irq + interrupts::IRQ_OFFSET;
+
+    unsafe {
+        LINES_MUTEX.lock();
+        let result = if LINES[irq as usize].is_some() || super::is_registered(vector) {
+            Err(())
+        } else {
+            LINES[irq as usize] = Some(Threaded { top, bottom });
+            idt::IDT[vector as usize].set_interrupt_type();
+            Ok(())
+        };
+        LINES_MUTEX.unlock();
+
+        result
+    }
+}
+
+/// A function which removes the threaded handler of an IRQ line. A bottom half which is already
+/// queued still runs.
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+pub fn free_irq_threaded(irq: u8) {
+    if (irq as usize) < NUM_LINES {
+        unsafe {
+            LINES_MUTEX.lock();
+            LINES[irq as usize] = None;
+            LINES_MUTEX.unlock();
+        }
+    }
+}
+
+/// A function which checks if an IRQ line has a threaded handler.
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+///
+/// # Returns
+/// True if it's threaded, False otherwise.
+pub fn is_threaded(irq: u8) -> bool {
+    (irq as usize) < NUM_LINES && unsafe { LINES[irq as usize].is_some() }
+}
+
+/// A function which is called by the interrupt dispatch for the IRQs. It runs the top half, and
+/// queues the bottom half if it's needed.
+///
+/// # Parameters
+/// `irq` : The IRQ line which fired.
+/// `info` : The context of the interrupt.
+///
+/// # Returns
+/// True if the line is threaded (so it was handled here), False otherwise.
+pub(super) unsafe fn dispatch(irq: u8, info: *const Context) -> bool {
+    let threaded = match LINES.get(irq as usize) {
+        Some(Some(threaded)) => *threaded,
+        _ => return false,
+    };
+
+    match (threaded.top)(&*info) {
+        IrqAck::Handled => (),
+        IrqAck::Defer => { schedule_bottom(irq); },
+        IrqAck::NotMine => UNHANDLED[irq as usize] += 1,
+    }
+
+    true
+}
+
+/// A function which queues the bottom half of a line (unless it's already queued). It can be
+/// called from the top halves, or from the tests.
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+///
+/// # Returns
+/// Ok if it's queued (now or before), Err if the line is not threaded or the queue is full.
+pub fn schedule_bottom(irq: u8) -> Result<(), ()> {
+    if !is_threaded(irq) {
+        return Err(());
+    }
+
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+
+        let result = if PENDING[irq as usize] {
+            Ok(())
+        } else if crate::proc::workqueue::queue_work(run_bottom, irq as usize).is_ok() {
+            PENDING[irq as usize] = true;
+            
+            #[cfg(feature = "latency-stats")]
+            crate::debug::latency::deferred(irq as usize);
+            Ok(())
+        } else {
+            DROPPED += 1;
+            Err(())
+        };
+
+        interrupts::restore(were_enabled);
+        result
+    }
+}
+
+/// A simple getter for the number of interrupts on a line which were not raised by the device.
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+///
+/// # Returns
+/// The number of times the top half returned NotMine.
+pub fn unhandled(irq: u8) -> usize {
+    unsafe { UNHANDLED.get(irq as usize).copied().unwrap_or(0) }
+}
+
+/// A simple getter for the number of bottom halves which could not be queued.
+///
+/// # Returns
+/// The number of dropped bottom halves.
+pub fn dropped() -> usize {
+    unsafe { DROPPED }
+}
+
+/// The work function which runs the bottom half of a line. The pending flag is cleared before it
+/// runs, so anything which arrives while it's running queues it again.
+///
+/// # Parameters
+/// `irq` : The IRQ line (passed as the work argument).
+fn run_bottom(irq: usize) {
+    #[cfg(feature = "latency-stats")]
+    crate::debug::latency::bottom_started(irq);
+    
+    let bottom = unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        PENDING[irq] = false;
+        let bottom = LINES[irq].map(|threaded| threaded.bottom);
+        interrupts::restore(were_enabled);
+        bottom
+    };
+
+    // The line might have been freed after it was queued.
+    if let Some(bottom) = bottom {
+        bottom();
+    }
+}
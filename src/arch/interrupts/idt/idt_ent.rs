@@ -88,6 +88,12 @@ impl IDTEnt {
     /// A method which clears the present bit of this idt entry.
     pub unsafe fn set_absent(&mut self) { self.attr_type.clear_bit(7); }
     
+    /// A method which checks the present bit of this idt entry.
+    pub fn is_present(&self) -> bool { 
+        let attr_type = self.attr_type;
+        attr_type.is_set(7) 
+    }
+    
     /// A function which sets the type of this entry as an interrupt. Which means that when the 
     /// isr is called, the interrupts will be disabled by the system.
     pub unsafe fn set_interrupt_type(&mut self) { 
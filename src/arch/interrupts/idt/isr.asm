@@ -14,8 +14,13 @@ section .text
 ; The main rust handler which will be called (it then calls all other handlers).
 %define RUST_MAIN_HANDLER main_handler
 
+; The rust hook which is called right before returning (it does the context
+; switches which were deferred while a nested handler was running).
+%define RUST_EXIT_HANDLER exit_handler
+
 ; Make sure the default rust handlers are accessible here.
 extern RUST_MAIN_HANDLER
+extern RUST_EXIT_HANDLER
 
 ;  The main isr handling  :;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;
 
@@ -75,6 +80,8 @@ extern RUST_MAIN_HANDLER
         mov rsi, rsp                ; Context struct pointer as second arg.
         cld                         ; Clear the direction flag (sysv64 ABI).
         call RUST_MAIN_HANDLER      ; Call the high level handler.
+        mov rdi, rsp                ; Context struct pointer as first arg.
+        call RUST_EXIT_HANDLER      ; Do a deferred switch (if it's outermost).
         load_context                ; Load back the gp registers.
         pop rax                     ; Pop the error number.
         iretq                       ; Return from the interrupt.
@@ -99,6 +106,8 @@ extern RUST_MAIN_HANDLER
         mov rsi, rsp                ; Context struct pointer as second arg.
         cld                         ; Clear the direction flag (sysv64 ABI).
         call RUST_MAIN_HANDLER      ; Call the high level handler.
+        mov rdi, rsp                ; Context struct pointer as first arg.
+        call RUST_EXIT_HANDLER      ; Do a deferred switch (if it's outermost).
         load_context                ; Load back the gp registers.
         pop rax                     ; Pop the error number.
         iretq                       ; Return from the interrupt.
@@ -0,0 +1,131 @@
+//! A sub-module which drives the I/O APIC just enough to deliver an ISA interrupt as a non-maskable
+//! interrupt. The regular IRQs are still delivered by the PIC (the other I/O APIC entries are left
+//! masked). It's used by the watchdog to get a periodic NMI from the PIT, which arrives even while
+//! the interrupts are disabled (see proc::watchdog).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::mem::addr::PhysAddr;
+use crate::mem::region::Region;
+
+/// The physical address of the I/O APIC (the default one, since the MADT is not parsed yet).
+const IOAPIC_BASE: usize = 0xFEC0_0000;
+
+/// The offsets of the register select and the data window (the registers are accessed indirectly).
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+/// The register which holds the version and the number of redirection entries.
+const REG_VERSION: u32 = 0x01;
+
+/// The first register of the redirection table (each entry takes two registers).
+const REG_REDIRECTION: u32 = 0x10;
+
+/// The delivery mode bits of a redirection entry for a non-maskable interrupt.
+const DELIVERY_NMI: u32 = 0b100 << 8;
+
+/// The bit which masks a redirection entry.
+const ENTRY_MASKED: u32 = 1 << 16;
+
+/// The pin which the PIT (ISA IRQ0) is connected to (the usual interrupt source override).
+pub const PIT_PIN: u8 = 2;
+
+/// True once the registers are mapped (and the I/O APIC answered).
+static mut PRESENT: bool = false;
+
+/// A function which maps the registers of the I/O APIC, and makes sure it's there.
+///
+/// # Returns
+/// Ok if the I/O APIC can be used, Err if there is no local APIC or I/O APIC.
+pub unsafe fn init() -> Result<(), ()> {
+    if PRESENT {
+        return Ok(());
+    }
+
+    if !crate::arch::proc::cpu::has_apic() {
+        return Err(());
+    }
+
+    // The registers are identity mapped (the frame is not RAM, so it's reserved first, and it's
+    // never allocated).
+    let registers = Region::new_sized(IOAPIC_BASE, crate::mem::vmm::PAGE_SIZE);
+    let _ = crate::mem::mmio::reserve_mmio(registers);
+    crate::mem::vmm::identity_map(PhysAddr::new(IOAPIC_BASE), false, true, true)?;
+
+    // A missing device reads as all ones.
+    if read(REG_VERSION) == 0xFFFF_FFFF {
+        return Err(());
+    }
+
+    PRESENT = true;
+    oxid_log!("Found an I/O APIC with {} pins.", num_pins());
+    Ok(())
+}
+
+/// A function which finds the number of pins (redirection entries) of the I/O APIC.
+///
+/// # Returns
+/// The number of pins (0 if it's not initialized).
+pub fn num_pins() -> usize {
+    if unsafe { !PRESENT } {
+        return 0;
+    }
+
+    unsafe { ((read(REG_VERSION) >> 16) & 0xFF) as usize + 1 }
+}
+
+/// A function which routes a pin to the local APIC of a CPU as a non-maskable interrupt.
+///
+/// # Parameters
+/// `pin` : The pin of the I/O APIC.
+/// `apic_id` : The ID of the local APIC which receives it.
+///
+/// # Returns
+/// Ok if it was routed, Err if the I/O APIC is not initialized or the pin is invalid.
+pub unsafe fn route_nmi(pin: u8, apic_id: u8) -> Result<(), ()> {
+    if pin as usize >= num_pins() {
+        return Err(());
+    }
+
+    // Set the destination first, and unmask it last (edge triggered, physical destination).
+    let reg = REG_REDIRECTION + pin as u32 * 2;
+    write(reg, ENTRY_MASKED);
+    write(reg + 1, (apic_id as u32) << 24);
+    write(reg, DELIVERY_NMI);
+    Ok(())
+}
+
+/// A function which masks a pin (so it doesn't deliver anything).
+///
+/// # Parameters
+/// `pin` : The pin of the I/O APIC.
+pub unsafe fn mask(pin: u8) {
+    if (pin as usize) < num_pins() {
+        write(REG_REDIRECTION + pin as u32 * 2, ENTRY_MASKED);
+    }
+}
+
+/// A helper which reads a register of the I/O APIC.
+///
+/// # Parameters
+/// `reg` : The index of the register.
+///
+/// # Returns
+/// The value of the register.
+unsafe fn read(reg: u32) -> u32 {
+    core::ptr::write_volatile((IOAPIC_BASE + IOREGSEL) as *mut u32, reg);
+    core::ptr::read_volatile((IOAPIC_BASE + IOWIN) as *const u32)
+}
+
+/// A helper which writes a register of the I/O APIC.
+///
+/// # Parameters
+/// `reg` : The index of the register.
+/// `value` : The value which is written.
+unsafe fn write(reg: u32, value: u32) {
+    core::ptr::write_volatile((IOAPIC_BASE + IOREGSEL) as *mut u32, reg);
+    core::ptr::write_volatile((IOAPIC_BASE + IOWIN) as *mut u32, value);
+}
@@ -8,6 +8,8 @@
 
 global enable
 global disable
+global are_enabled
+global raise_interrupt
 
 ; A simple wrapper for the STI instruction.
 enable:
@@ -18,3 +20,34 @@ enable:
 disable:
     cli				; Simply call it and return.
     ret
+
+; A function which checks the interrupt flag (bit 9 of RFLAGS). Returns 1 if
+; the interrupts are enabled, and 0 otherwise.
+are_enabled:
+    pushfq			; Push the flags and pop them in rax.
+    pop rax
+    shr rax, 9			; Only keep the interrupt flag.
+    and rax, 1
+    ret
+
+; A function which raises a software interrupt (the INT instruction only takes
+; an immediate, so it jumps to a stub in a table which has one for each vector).
+; The first argument (dil) is the vector. Each stub is 4 bytes, and returns to
+; the caller once the interrupt is handled.
+raise_interrupt:
+    movzx rax, dil		; Find the address of the stub for the vector.
+    shl rax, 2
+    lea rcx, [rel int_stubs]
+    add rax, rcx
+    jmp rax			; The stub returns to our caller.
+
+; The table of the stubs (generated for all the 256 vectors).
+align 4
+int_stubs:
+%assign vector 0
+%rep 256
+    align 4
+    int vector
+    ret
+%assign vector vector + 1
+%endrep
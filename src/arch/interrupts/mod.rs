@@ -7,6 +7,8 @@
 pub mod idt;
 pub mod handlers;
 pub mod pic;
+pub mod ioapic;
+pub mod vectors;
 
 pub const IRQ_OFFSET: u8 = 32;       // The offset for hardware interrupts (set by PIC or APIC).
 
@@ -18,6 +20,44 @@ extern "sysv64" {
     
     /// A function which disables interrupts by using the CLI instruction.
     pub fn disable();
+    
+    /// A function which checks if the interrupts are currently enabled (the IF flag).
+    ///
+    /// # Returns
+    /// True if interrupts are enabled, False otherwise.
+    pub fn are_enabled() -> bool;
+    
+    /// A function which raises a software interrupt (the INT instruction), and returns once it's
+    /// handled. It's only used by the unit tests (to nest interrupts).
+    ///
+    /// # Parameters
+    /// `vector` : The interrupt vector (0-255).
+    #[cfg(feature = "unit-test")]
+    pub fn raise_interrupt(vector: u8);
+}
+
+/// A function which disables interrupts, and returns if they were enabled before. It is used with
+/// restore() to create critical sections which also work when interrupts are already disabled 
+/// (such as in interrupt handlers).
+///
+/// # Returns
+/// True if the interrupts were enabled before the call, False otherwise.
+#[inline]
+pub unsafe fn save_and_disable() -> bool {
+    let were_enabled = are_enabled();
+    disable();
+    were_enabled
+}
+
+/// A function which restores the interrupt state which was returned by save_and_disable().
+///
+/// # Parameters
+/// `were_enabled` : The value returned from save_and_disable().
+#[inline]
+pub unsafe fn restore(were_enabled: bool) {
+    if were_enabled {
+        enable();
+    }
 }
 
 /// A function which initializes all the interrupt handling code. Including the IDT, and the 
@@ -36,6 +76,9 @@ pub unsafe fn init() {
 
     // Initialize the PIC.
     pic::init(IRQ_OFFSET);
+    
+    // Hide the vectors which are allocated at runtime (until a driver allocates them).
+    vectors::init();
 
     // Then enable interrupts.
     enable();
@@ -8,6 +8,8 @@
 
 global out_b
 global in_b
+global out_w
+global in_w
 
 ; A wrapper for the out instruction which writes a byte. The first argument is
 ; a 16-bit value representing the io port, the second is a byte long value.
@@ -23,3 +25,18 @@ in_b:
     mov rdx, rdi        ; Store the port number in rdi.
     in al, dx           ; Call in and store the value in rax.
     ret                 ; Since the return value is in al anyways, we can ret.
+
+; A wrapper for the out instruction which writes a word (16 bits). The first
+; argument is a 16-bit value representing the io port, the second is the word.
+out_w:
+    mov rdx, rdi        ; Store the port number in rdx.
+    mov rax, rsi        ; Store the value in rax.
+    out dx, ax          ; Call and return.
+    ret
+
+; A wrapper for the in instruction which reads a word (16 bits) from an IO
+; port. It accepts a 16-bit io port as a parameter, and returns the word.
+in_w:
+    mov rdx, rdi        ; Store the port number in rdx.
+    in ax, dx           ; Call in and store the value in rax.
+    ret                 ; Since the return value is in ax anyways, we can ret.
@@ -21,6 +21,10 @@ const NUM_IRQS: u8 = 0x8;                    // The number of IRQs for each PIC.
 const MAX_IRQS: u8 = 0x10;                   // Total number of IRQs supperted.
 const EOI: u8 = 0x20;                        // End of interrupt flag.                  
 const DISABLE: u8 = 0xFF;                    // Disable flag.
+const READ_ISR: u8 = 0x0B;                   // OCW3 which selects the in-service register.
+
+/// True if the PICs are initialized and not disabled (they are the active interrupt controller).
+static mut IS_ACTIVE: bool = false;
 
 /// A group of signature definitions to allow calling assembly code from rust.
 /// it utilizes the System V AMD64 calling conventions to call the assembly code.
@@ -40,6 +44,22 @@ extern "sysv64" {
     /// # Returns
     /// The byte which was read from the port.
     pub fn in_b(io_port: u16) -> u8;
+    
+    /// A function which writes a word (16 bits) to a given IO port (output port).
+    /// 
+    /// # Parameters
+    /// `io_port` : The port we're writing to (based on x86 specifications).
+    /// `value` : The word we're writing to that port.
+    pub fn out_w(io_port: u16, value: u16);
+    
+    /// A function which reads a word (16 bits) from a given IO port (input port).
+    /// 
+    /// # Parameters
+    /// `io_port` : The port we're reading from (based on x86 specifications).
+    ///
+    /// # Returns
+    /// The word which was read from the port.
+    pub fn in_w(io_port: u16) -> u16;
 }
 
 /// A function which initializes the programmable interrupt controllers and remaps their default
@@ -70,6 +90,8 @@ pub unsafe fn init(first_int_num: u8) {
     for irq_num in 0..MAX_IRQS {
          disable_irq(irq_num);
     }
+    
+    IS_ACTIVE = true;
 }
 
 /// A function which sends an end of interrupt command to the PIC chipsets based on a given IRQ.
@@ -83,7 +105,7 @@ pub unsafe fn end_of_interrupt(irq_num: u8) {
     assert!(irq_num < MAX_IRQS, "Invalid IRQ number passed. Out of range.");                
 
     // Check if it's an interrupt from secondary pic, in that case send EOI to it.
-    if irq_num > NUM_IRQS {
+    if irq_num >= NUM_IRQS {
         out_b(SECONDARY_PIC_CMD, EOI);
     }
     
@@ -91,6 +113,18 @@ pub unsafe fn end_of_interrupt(irq_num: u8) {
     out_b(PRIMARY_PIC_CMD, EOI);
 }
 
+/// A function which reads the in-service registers of both PICs (the IRQs which were delivered but
+/// did not get an EOI yet).
+///
+/// # Returns
+/// A bit for every IRQ (the secondary PIC's IRQs are in the high byte).
+pub unsafe fn in_service() -> u16 {
+    out_b(PRIMARY_PIC_CMD, READ_ISR);
+    out_b(SECONDARY_PIC_CMD, READ_ISR);
+    
+    (in_b(PRIMARY_PIC_CMD) as u16) | ((in_b(SECONDARY_PIC_CMD) as u16) << 8)
+}
+
 /// A function which clears the mask for sepcific IRQ number thus it enables it. It follows the 
 /// same approach to clear the mask as the C code which can be fonud at
 /// https://wiki.osdev.org/PIC#Programming_the_PIC_chips
@@ -140,4 +174,13 @@ pub unsafe fn disable_irq(irq_num: u8) {
 pub unsafe fn disable() {
     out_b(SECONDARY_PIC_DATA, DISABLE);
     out_b(PRIMARY_PIC_DATA, DISABLE);
+    IS_ACTIVE = false;
+}
+
+/// A function which checks if the PICs are the active interrupt controller.
+///
+/// # Returns
+/// True if they were initialized and not disabled, False otherwise.
+pub fn is_active() -> bool {
+    unsafe { IS_ACTIVE }
 }
@@ -0,0 +1,190 @@
+//! A sub-module which allocates the interrupt vectors at runtime (for the drivers which need a 
+//! software vector, such as a syscall gate or a secondary timer). The vectors below 48 are reserved
+//! for the exceptions and the legacy IRQs. Allocating a vector also registers it's handler and 
+//! marks the gate present, and freeing it unregisters the handler and marks the gate absent.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use super::handlers::{self, InterruptHandler};
+use super::idt;
+use crate::proc::mutex::Mutex;
+
+/// The first vector which can be allocated (the ones before it are reserved).
+pub const FIRST_DYNAMIC_VECTOR: u8 = 48;
+
+/// The first vector of the system class (the ones before it are for the devices).
+pub const FIRST_SYSTEM_VECTOR: u8 = 224;
+
+/// An enum which represents the range a vector is allocated from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VectorClass {
+    Device,                     // The device interrupts (48 - 223).
+    System,                     // The system vectors such as syscalls and IPIs (224 - 255).
+}
+
+impl VectorClass {
+    /// A method which returns the range of vectors in this class.
+    ///
+    /// # Returns
+    /// The first and the last vector (inclusive).
+    pub fn range(&self) -> (u8, u8) {
+        match self {
+            VectorClass::Device => (FIRST_DYNAMIC_VECTOR, FIRST_SYSTEM_VECTOR - 1),
+            VectorClass::System => (FIRST_SYSTEM_VECTOR, 255),
+        }
+    }
+}
+
+/// The owners of the allocated vectors (None if it's free).
+static mut OWNERS: [Option<&'static str>; idt::NUM_IDT_ENTRIES] = [None; idt::NUM_IDT_ENTRIES];
+
+/// A mutex which protects the allocation table.
+static mut VECTORS_MUTEX: Mutex = Mutex::new();
+
+/// A function which marks all the dynamic vectors absent (since none of them are allocated yet).
+pub unsafe fn init() {
+    for vector in FIRST_DYNAMIC_VECTOR..=255 {
+        idt::IDT[vector as usize].set_absent();
+    }
+}
+
+/// A function which allocates a free vector from a given class, and registers it's handler.
+///
+/// # Parameters
+/// `class` : The range which the vector is allocated from.
+/// `owner` : The name of the driver which owns it (shown in irqstat).
+/// `handler` : The handler which is called for the vector.
+///
+/// # Returns
+/// The allocated vector, or Err if all the vectors in the class are in use.
+pub fn alloc_vector(class: VectorClass, owner: &'static str, handler: InterruptHandler) 
+    -> Result<u8, ()> {
+    let (first, last) = class.range();
+    
+    unsafe {
+        // Reserve the lowest free vector.
+        VECTORS_MUTEX.lock();
+        let found = (first..=last).find(|vector| OWNERS[*vector as usize].is_none());
+        if let Some(vector) = found {
+            OWNERS[vector as usize] = Some(owner);
+        }
+        VECTORS_MUTEX.unlock();
+        
+        // Then register it (outside the lock, since the handlers have their own).
+        let vector = found.ok_or(())?;
+        handlers::register_int(vector, handler);
+        idt::IDT[vector as usize].set_present();
+        
+        oxid_log!("Allocated interrupt vector {} for {}.", vector, owner);
+        Ok(vector)
+    }
+}
+
+/// A function which frees an allocated vector. It's handler is unregistered, and the gate is 
+/// marked absent.
+///
+/// # Parameters
+/// `vector` : The vector which we're freeing.
+///
+/// # Returns
+/// Ok if it was freed, Err if it was not allocated.
+pub fn free_vector(vector: u8) -> Result<(), ()> {
+    unsafe {
+        if vector < FIRST_DYNAMIC_VECTOR || owner(vector).is_none() {
+            return Err(());
+        }
+        
+        // Remove the gate first, so the vector is not reused while it's still registered.
+        handlers::unregister(vector);
+        idt::IDT[vector as usize].set_absent();
+        
+        VECTORS_MUTEX.lock();
+        OWNERS[vector as usize] = None;
+        VECTORS_MUTEX.unlock();
+        
+        Ok(())
+    }
+}
+
+/// A function which finds the owner of a vector.
+///
+/// # Parameters
+/// `vector` : The vector which we're checking.
+///
+/// # Returns
+/// Some(owner) if it's allocated, None otherwise.
+pub fn owner(vector: u8) -> Option<&'static str> {
+    unsafe { OWNERS[vector as usize] }
+}
+
+/// A function which counts the free vectors of a class.
+///
+/// # Parameters
+/// `class` : The class which we're checking.
+///
+/// # Returns
+/// The number of vectors which can still be allocated.
+pub fn num_free(class: VectorClass) -> usize {
+    let (first, last) = class.range();
+    (first..=last).filter(|vector| owner(*vector).is_none()).count()
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::vec::Vec;
+    use crate::arch::interrupts::handlers::{self, context};
+    use crate::arch::interrupts::idt;
+    use super::{alloc_vector, free_vector, VectorClass};
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_exhaustion();
+    }
+    
+    /// A handler which is never called.
+    ///
+    /// # Parameters
+    /// `_info` : The context before the interrupt.
+    unsafe fn dummy_handler(_info: *const context::Context) {}
+    
+    /// Allocate all the system vectors, free one, and reallocate it (none are handed out twice).
+    fn test_exhaustion() {
+        let free_before = super::num_free(VectorClass::System);
+        let mut allocated: Vec<u8> = Vec::new();
+        while let Ok(vector) = alloc_vector(VectorClass::System, "vector_test", dummy_handler) {
+            assert!(vector >= super::FIRST_SYSTEM_VECTOR);
+            assert!(!allocated.contains(&vector));
+            assert!(handlers::is_registered(vector));
+            assert!(unsafe { idt::IDT[vector as usize].is_present() });
+            allocated.push(vector);
+        }
+        
+        assert_eq!(allocated.len(), free_before);
+        assert_eq!(super::num_free(VectorClass::System), 0);
+        
+        // Free one, and make sure it's the one which is allocated again.
+        let freed = allocated.remove(allocated.len() / 2);
+        assert_eq!(free_vector(freed), Ok(()));
+        assert_eq!(free_vector(freed), Err(()));
+        assert!(!handlers::is_registered(freed));
+        assert!(unsafe { !idt::IDT[freed as usize].is_present() });
+        assert_eq!(alloc_vector(VectorClass::System, "vector_test", dummy_handler), Ok(freed));
+        allocated.push(freed);
+        
+        // The reserved vectors can't be freed.
+        assert_eq!(free_vector(14), Err(()));
+        
+        for vector in allocated {
+            assert_eq!(free_vector(vector), Ok(()));
+        }
+        assert_eq!(super::num_free(VectorClass::System), free_before);
+    }
+}
@@ -0,0 +1,681 @@
+//! A sub-module which provides a basic ATA PIO driver (LBA28, one sector at a time). Every wait on
+//! the status register is bounded (the IDENTIFY command and the sector transfers have separate
+//! timeouts), the error register is decoded into an AtaError, and the transient errors (bad CRC or
+//! an uncorrectable read) are retried a few times. A floating bus (a status of 0xFF) or a status of
+//! zero means there is no drive, so the probe returns right away. The ports are accessed through
+//! the PortIo trait, so the driver can be tested without the hardware.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::boxed::Box;
+use crate::arch::interrupts::pic;
+use crate::io::block::{self, BlockDevice, BlockError, SECTOR_SIZE};
+use crate::olibc::bounded::BoundedString;
+
+/// The offsets of the registers from the IO base of a channel.
+const REG_DATA: u16 = 0;
+const REG_ERROR: u16 = 1;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_STATUS: u16 = 7;
+const REG_COMMAND: u16 = 7;
+
+/// The bits of the status register.
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+/// The status which is read when nothing drives the bus (there is no controller or drive).
+const FLOATING_BUS: u8 = 0xFF;
+
+/// The bits of the error register.
+const ERROR_AMNF: u8 = 1 << 0;
+const ERROR_TK0NF: u8 = 1 << 1;
+const ERROR_ABRT: u8 = 1 << 2;
+const ERROR_MCR: u8 = 1 << 3;
+const ERROR_IDNF: u8 = 1 << 4;
+const ERROR_MC: u8 = 1 << 5;
+const ERROR_UNC: u8 = 1 << 6;
+const ERROR_ICRC: u8 = 1 << 7;
+
+/// The commands which are used.
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_CACHE_FLUSH: u8 = 0xE7;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+/// The values of the drive register (the slave bit is added for the second drive).
+const DRIVE_IDENTIFY: u8 = 0xA0;
+const DRIVE_LBA: u8 = 0xE0;
+const DRIVE_SLAVE: u8 = 1 << 4;
+
+/// The number of words which are transferred for a sector (and the IDENTIFY data).
+const SECTOR_WORDS: usize = SECTOR_SIZE / 2;
+
+/// The largest address which can be used with LBA28.
+const LBA28_MAX: u64 = 1 << 28;
+
+/// The time between the status polls (in microseconds).
+const POLL_US: usize = 10;
+
+/// The time to wait after selecting a drive (the status is not valid for 400ns).
+const SELECT_DELAY_US: usize = 1;
+
+/// The number of attempts for the errors which can be retried.
+pub const MAX_ATTEMPTS: usize = 3;
+
+/// An enum which represents the reason an ATA operation failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AtaError {
+    NoDevice,                   // There is no drive (floating bus or a status of zero).
+    NotAta,                     // The drive is not an ATA drive (such as ATAPI or SATA).
+    Timeout,                    // The drive did not become ready in time.
+    DeviceFault,                // The drive reported a fault (DF).
+    BadCrc,                     // An interface CRC error occurred (ICRC).
+    Uncorrectable,              // The data could not be read (UNC).
+    IdNotFound,                 // The sector was not found (IDNF).
+    Aborted,                    // The command was aborted (ABRT).
+    MediaChanged,               // The media was changed (MC).
+    MediaChangeRequested,       // A media change was requested (MCR).
+    TrackZeroNotFound,          // Track 0 was not found (TK0NF).
+    AddressMarkNotFound,        // The address mark was not found (AMNF).
+    Unknown(u8),                // The error register did not have a known bit set.
+}
+
+impl AtaError {
+    /// A function which decodes the error register (the most specific error is chosen if more
+    /// than one bit is set).
+    ///
+    /// # Parameters
+    /// `error` : The value of the error register.
+    ///
+    /// # Returns
+    /// The corresponding error.
+    pub fn decode(error: u8) -> AtaError {
+        if error & ERROR_ICRC != 0 { return AtaError::BadCrc; }
+        if error & ERROR_UNC != 0 { return AtaError::Uncorrectable; }
+        if error & ERROR_IDNF != 0 { return AtaError::IdNotFound; }
+        if error & ERROR_ABRT != 0 { return AtaError::Aborted; }
+        if error & ERROR_MC != 0 { return AtaError::MediaChanged; }
+        if error & ERROR_MCR != 0 { return AtaError::MediaChangeRequested; }
+        if error & ERROR_TK0NF != 0 { return AtaError::TrackZeroNotFound; }
+        if error & ERROR_AMNF != 0 { return AtaError::AddressMarkNotFound; }
+
+        AtaError::Unknown(error)
+    }
+
+    /// A method which checks if the operation should be retried after this error.
+    ///
+    /// # Returns
+    /// True if it's a transient error, False otherwise.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, AtaError::BadCrc | AtaError::Uncorrectable)
+    }
+}
+
+/// The interface used to access the IO ports (and to wait), so the driver can be tested.
+pub trait PortIo {
+    /// Reads a byte from a port.
+    fn in_b(&mut self, port: u16) -> u8;
+
+    /// Writes a byte to a port.
+    fn out_b(&mut self, port: u16, value: u8);
+
+    /// Reads a word from a port.
+    fn in_w(&mut self, port: u16) -> u16;
+
+    /// Writes a word to a port.
+    fn out_w(&mut self, port: u16, value: u16);
+
+    /// Waits for a given number of microseconds.
+    fn delay_us(&mut self, us: usize);
+}
+
+/// Allow passing the ports by reference (so they can be inspected after the driver is done).
+impl<P: PortIo> PortIo for &mut P {
+    fn in_b(&mut self, port: u16) -> u8 { (**self).in_b(port) }
+    fn out_b(&mut self, port: u16, value: u8) { (**self).out_b(port, value) }
+    fn in_w(&mut self, port: u16) -> u16 { (**self).in_w(port) }
+    fn out_w(&mut self, port: u16, value: u16) { (**self).out_w(port, value) }
+    fn delay_us(&mut self, us: usize) { (**self).delay_us(us) }
+}
+
+/// The ports of the hardware.
+pub struct HwPorts;
+
+impl PortIo for HwPorts {
+    fn in_b(&mut self, port: u16) -> u8 {
+        unsafe { pic::in_b(port) }
+    }
+
+    fn out_b(&mut self, port: u16, value: u8) {
+        unsafe { pic::out_b(port, value) }
+    }
+
+    fn in_w(&mut self, port: u16) -> u16 {
+        unsafe { pic::in_w(port) }
+    }
+
+    fn out_w(&mut self, port: u16, value: u16) {
+        unsafe { pic::out_w(port, value) }
+    }
+
+    fn delay_us(&mut self, us: usize) {
+        crate::time::delay_us(us);
+    }
+}
+
+/// An ATA channel (the ports of it's registers).
+#[derive(Copy, Clone)]
+pub struct Channel {
+    pub name: &'static str,     // The name of the channel (for the logs).
+    pub io_base: u16,           // The IO port of the first register.
+}
+
+/// The standard channels.
+pub const PRIMARY: Channel = Channel { name: "primary", io_base: 0x1F0 };
+pub const SECONDARY: Channel = Channel { name: "secondary", io_base: 0x170 };
+
+/// The timeouts of the operations (in microseconds).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Timeouts {
+    pub identify_us: usize,     // The time the IDENTIFY command can take.
+    pub transfer_us: usize,     // The time a sector transfer can take.
+}
+
+impl Timeouts {
+    /// The default timeouts (5 seconds for IDENTIFY, and 1 second per sector).
+    pub const DEFAULT: Timeouts = Timeouts {
+        identify_us: 5_000_000,
+        transfer_us: 1_000_000,
+    };
+}
+
+/// The maximum number of bytes shown for the model name.
+const MODEL_MAX: usize = 40;
+
+/// An ATA drive which was found by the probe.
+pub struct AtaDrive<P: PortIo> {
+    ports: P,                               // The ports which are used to access it.
+    channel: Channel,                       // The channel it's on.
+    slave: bool,                            // True if it's the second drive on the channel.
+    timeouts: Timeouts,                     // The timeouts of the operations.
+    num_sectors: u64,                       // The number of sectors (LBA28).
+    model: BoundedString<MODEL_MAX>,        // The model name (from the IDENTIFY data).
+    name: BoundedString<8>,                 // The name of the block device.
+}
+
+impl<P: PortIo> AtaDrive<P> {
+    /// A function which checks if a drive exists, and identifies it.
+    ///
+    /// # Parameters
+    /// `ports` : The ports which are used to access it.
+    /// `channel` : The channel it's on.
+    /// `slave` : True for the second drive on the channel, False for the first.
+    /// `timeouts` : The timeouts of the operations.
+    ///
+    /// # Returns
+    /// The drive if it was identified, Err with the reason otherwise.
+    pub fn probe(ports: P, channel: Channel, slave: bool, timeouts: Timeouts)
+        -> Result<Self, AtaError> {
+        let mut drive = AtaDrive {
+            ports, channel, slave, timeouts,
+            num_sectors: 0,
+            model: BoundedString::new(),
+            name: BoundedString::new(),
+        };
+
+        // Nothing drives the bus if there is no drive.
+        drive.select(DRIVE_IDENTIFY);
+        if drive.status() == FLOATING_BUS {
+            return Err(AtaError::NoDevice);
+        }
+
+        for reg in REG_SECTOR_COUNT..=REG_LBA_HIGH {
+            drive.out_reg(reg, 0);
+        }
+        drive.out_reg(REG_COMMAND, CMD_IDENTIFY);
+
+        // A status of zero means the drive doesn't exist.
+        let status = drive.status();
+        if status == 0 || status == FLOATING_BUS {
+            return Err(AtaError::NoDevice);
+        }
+
+        // The ATAPI and SATA drives set the LBA registers (and abort the command).
+        drive.wait_not_busy(timeouts.identify_us)?;
+        if drive.in_reg(REG_LBA_MID) != 0 || drive.in_reg(REG_LBA_HIGH) != 0 {
+            return Err(AtaError::NotAta);
+        }
+        drive.wait_drq(timeouts.identify_us)?;
+
+        // Read the identify data.
+        let mut data: [u16; SECTOR_WORDS] = [0; SECTOR_WORDS];
+        for word in data.iter_mut() {
+            *word = drive.ports.in_w(drive.channel.io_base + REG_DATA);
+        }
+        drive.num_sectors = (data[60] as u64 | (data[61] as u64) << 16).min(LBA28_MAX);
+
+        // The model name is stored as big endian words (padded with spaces).
+        let mut model: BoundedString<MODEL_MAX> = BoundedString::new();
+        for word in &data[27..47] {
+            for byte in &word.to_be_bytes() {
+                model.push(if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '?' });
+            }
+        }
+        let _ = drive.model.push_str(model.as_str().trim());
+
+        Ok(drive)
+    }
+
+    /// A simple getter for the model name of the drive.
+    ///
+    /// # Returns
+    /// The model name (from the IDENTIFY data).
+    pub fn model(&self) -> &str {
+        self.model.as_str()
+    }
+
+    /// A method which sets the name of the block device.
+    ///
+    /// # Parameters
+    /// `name` : The new name (such as "ata0").
+    pub fn set_name(&mut self, name: &str) {
+        self.name.clear();
+        let _ = self.name.push_str(name);
+    }
+
+    /// A helper which sends a command for a single sector.
+    ///
+    /// # Parameters
+    /// `lba` : The logical block address of the sector.
+    /// `command` : The command which we're sending.
+    ///
+    /// # Returns
+    /// Ok if the drive is ready for the data, Err with the reason otherwise.
+    fn start_transfer(&mut self, lba: u64, command: u8) -> Result<(), AtaError> {
+        self.wait_not_busy(self.timeouts.transfer_us)?;
+
+        self.select(DRIVE_LBA | ((lba >> 24) as u8 & 0x0F));
+        self.out_reg(REG_SECTOR_COUNT, 1);
+        self.out_reg(REG_LBA_LOW, lba as u8);
+        self.out_reg(REG_LBA_MID, (lba >> 8) as u8);
+        self.out_reg(REG_LBA_HIGH, (lba >> 16) as u8);
+        self.out_reg(REG_COMMAND, command);
+
+        self.wait_drq(self.timeouts.transfer_us).map(|_| ())
+    }
+
+    /// A helper which selects the drive (with the given drive register bits), and waits for it.
+    ///
+    /// # Parameters
+    /// `bits` : The bits of the drive register (the slave bit is added if needed).
+    fn select(&mut self, bits: u8) {
+        let bits = if self.slave { bits | DRIVE_SLAVE } else { bits };
+        self.out_reg(REG_DRIVE, bits);
+        self.ports.delay_us(SELECT_DELAY_US);
+    }
+
+    /// A helper which waits until the drive is not busy.
+    ///
+    /// # Parameters
+    /// `timeout_us` : The maximum time to wait.
+    ///
+    /// # Returns
+    /// Ok(status) if it's ready, Err if there is no drive or it timed out.
+    fn wait_not_busy(&mut self, timeout_us: usize) -> Result<u8, AtaError> {
+        self.poll(timeout_us, |status| status & STATUS_BSY == 0)
+    }
+
+    /// A helper which waits until the drive is ready to transfer the data (or reports an error).
+    ///
+    /// # Parameters
+    /// `timeout_us` : The maximum time to wait.
+    ///
+    /// # Returns
+    /// Ok(status) if it's ready, Err with the decoded error (or a timeout) otherwise.
+    fn wait_drq(&mut self, timeout_us: usize) -> Result<u8, AtaError> {
+        let status = self.poll(timeout_us,
+            |status| status & STATUS_BSY == 0 && status & (STATUS_DRQ | STATUS_ERR | STATUS_DF) != 0)?;
+
+        if status & STATUS_ERR != 0 {
+            return Err(AtaError::decode(self.in_reg(REG_ERROR)));
+        }
+        if status & STATUS_DF != 0 {
+            return Err(AtaError::DeviceFault);
+        }
+
+        Ok(status)
+    }
+
+    /// A helper which polls the status register until a condition is met (or the time is up).
+    ///
+    /// # Parameters
+    /// `timeout_us` : The maximum time to wait.
+    /// `done` : The condition (it's called with the status).
+    ///
+    /// # Returns
+    /// Ok(status) if the condition was met, Err if there is no drive or it timed out.
+    fn poll(&mut self, timeout_us: usize, done: impl Fn(u8) -> bool) -> Result<u8, AtaError> {
+        let mut waited_us = 0;
+        loop {
+            let status = self.status();
+            if status == FLOATING_BUS {
+                return Err(AtaError::NoDevice);
+            }
+            if done(status) {
+                return Ok(status);
+            }
+
+            if waited_us >= timeout_us {
+                return Err(AtaError::Timeout);
+            }
+            self.ports.delay_us(POLL_US);
+            waited_us += POLL_US;
+        }
+    }
+
+    /// A helper which reads the status register.
+    #[inline]
+    fn status(&mut self) -> u8 {
+        self.in_reg(REG_STATUS)
+    }
+
+    /// A helper which reads a register of the channel.
+    #[inline]
+    fn in_reg(&mut self, reg: u16) -> u8 {
+        self.ports.in_b(self.channel.io_base + reg)
+    }
+
+    /// A helper which writes a register of the channel.
+    #[inline]
+    fn out_reg(&mut self, reg: u16, value: u8) {
+        self.ports.out_b(self.channel.io_base + reg, value)
+    }
+}
+
+impl<P: PortIo> BlockDevice for AtaDrive<P> {
+    fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn num_sectors(&self) -> u64 {
+        self.num_sectors
+    }
+
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), BlockError> {
+        if lba >= self.num_sectors {
+            return Err(BlockError::OutOfRange);
+        }
+
+        // Keep the address and the buffer for the retries.
+        let mut sector: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+        let mut attempt = 1;
+        loop {
+            let result = self.start_transfer(lba, CMD_READ_SECTORS).map(|_| {
+                for bytes in sector.chunks_exact_mut(2) {
+                    bytes.copy_from_slice(&self.ports.in_w(self.channel.io_base + REG_DATA)
+                        .to_le_bytes());
+                }
+            });
+
+            match result {
+                Err(error) if error.is_retryable() && attempt < MAX_ATTEMPTS => {
+                    oxid_warn!("ATA {}: {:?} reading sector {} (attempt {} of {}), retrying.",
+                        self.name.as_str(), error, lba, attempt, MAX_ATTEMPTS);
+                    attempt += 1;
+                },
+                Err(error) => return Err(BlockError::Ata(error)),
+                Ok(()) => break,
+            }
+        }
+
+        buf.copy_from_slice(&sector);
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), BlockError> {
+        if lba >= self.num_sectors {
+            return Err(BlockError::OutOfRange);
+        }
+
+        let mut attempt = 1;
+        loop {
+            let result = self.start_transfer(lba, CMD_WRITE_SECTORS).and_then(|_| {
+                for bytes in buf.chunks_exact(2) {
+                    self.ports.out_w(self.channel.io_base + REG_DATA,
+                        u16::from_le_bytes([bytes[0], bytes[1]]));
+                }
+
+                // Make sure it's on the disk.
+                self.out_reg(REG_COMMAND, CMD_CACHE_FLUSH);
+                self.wait_not_busy(self.timeouts.transfer_us).map(|_| ())
+            });
+
+            match result {
+                Err(error) if error.is_retryable() && attempt < MAX_ATTEMPTS => {
+                    oxid_warn!("ATA {}: {:?} writing sector {} (attempt {} of {}), retrying.",
+                        self.name.as_str(), error, lba, attempt, MAX_ATTEMPTS);
+                    attempt += 1;
+                },
+                Err(error) => return Err(BlockError::Ata(error)),
+                Ok(()) => return Ok(()),
+            }
+        }
+    }
+}
+
+/// A function which probes the drives on the standard channels, logs the results, and registers
+/// the drives which were found as block devices. It should be called after the delay timer is
+/// initialized (see time::init).
+pub fn init() {
+    let mut num_found = 0;
+
+    for channel in &[PRIMARY, SECONDARY] {
+        for &slave in &[false, true] {
+            let position = if slave { "slave" } else { "master" };
+            match AtaDrive::probe(HwPorts, *channel, slave, Timeouts::DEFAULT) {
+                Ok(mut drive) => {
+                    drive.set_name(&alloc::format!("ata{}", num_found));
+                    oxid_log!("ATA {} {}: {} ({} sectors) as {}.", channel.name, position,
+                        drive.model(), drive.num_sectors, drive.name.as_str());
+                    block::register(Box::new(drive));
+                    num_found += 1;
+                },
+                Err(error) => oxid_log!("ATA {} {}: {:?}.", channel.name, position, error),
+            }
+        }
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::vec::Vec;
+    use crate::io::block::{BlockDevice, BlockError, SECTOR_SIZE};
+    use super::*;
+
+    /// Short timeouts for the tests (the mocked delays don't actually wait).
+    const TEST_TIMEOUTS: Timeouts = Timeouts { identify_us: 1000, transfer_us: 500 };
+
+    /// A mocked port backend. The status reads follow a script (the last value repeats), and the
+    /// error register, the commands, and the time which was waited are recorded.
+    struct MockPorts {
+        statuses: Vec<u8>,          // The values of the status reads (in order).
+        error: u8,                  // The value of the error register.
+        lba_mid_high: (u8, u8),     // The values of the LBA registers (after IDENTIFY).
+        data: Vec<u16>,             // The words which are read from the data register.
+        commands: Vec<u8>,          // The commands which were written.
+        written: Vec<u16>,          // The words which were written to the data register.
+        waited_us: usize,           // The total time which was waited.
+    }
+
+    impl MockPorts {
+        fn new(statuses: &[u8]) -> Self {
+            MockPorts {
+                statuses: statuses.to_vec(),
+                error: 0,
+                lba_mid_high: (0, 0),
+                data: Vec::new(),
+                commands: Vec::new(),
+                written: Vec::new(),
+                waited_us: 0,
+            }
+        }
+    }
+
+    impl PortIo for MockPorts {
+        fn in_b(&mut self, port: u16) -> u8 {
+            match port - PRIMARY.io_base {
+                REG_STATUS if self.statuses.len() > 1 => self.statuses.remove(0),
+                REG_STATUS => self.statuses[0],
+                REG_ERROR => self.error,
+                REG_LBA_MID => self.lba_mid_high.0,
+                REG_LBA_HIGH => self.lba_mid_high.1,
+                _ => 0,
+            }
+        }
+
+        fn out_b(&mut self, port: u16, value: u8) {
+            if port - PRIMARY.io_base == REG_COMMAND {
+                self.commands.push(value);
+            }
+        }
+
+        fn in_w(&mut self, _port: u16) -> u16 {
+            if self.data.is_empty() { 0 } else { self.data.remove(0) }
+        }
+
+        fn out_w(&mut self, _port: u16, value: u16) {
+            self.written.push(value);
+        }
+
+        fn delay_us(&mut self, us: usize) {
+            self.waited_us += us;
+        }
+    }
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_decode();
+        test_no_device();
+        test_identify_timeout();
+        test_not_ata();
+        test_read_retry();
+        test_no_retry();
+    }
+
+    /// A helper which creates a drive with 100 sectors through a scripted probe.
+    ///
+    /// # Returns
+    /// The drive (the mock can be scripted again through it's ports).
+    fn probed_drive() -> AtaDrive<MockPorts> {
+        let mut ports = MockPorts::new(&[0x50, 0x50, 0x58]);
+        let mut identify: [u16; SECTOR_WORDS] = [0; SECTOR_WORDS];
+        identify[27] = u16::from_be_bytes(*b"QE");
+        identify[28] = u16::from_be_bytes(*b"MU");
+        identify[60] = 100;
+        ports.data = identify.to_vec();
+
+        let drive = AtaDrive::probe(ports, PRIMARY, false, TEST_TIMEOUTS).unwrap();
+        assert_eq!(drive.model(), "QEMU");
+        assert_eq!(drive.num_sectors(), 100);
+
+        drive
+    }
+
+    /// Make sure the error register is decoded, and only the transient errors are retried.
+    fn test_decode() {
+        assert_eq!(AtaError::decode(ERROR_ABRT), AtaError::Aborted);
+        assert_eq!(AtaError::decode(ERROR_UNC), AtaError::Uncorrectable);
+        assert_eq!(AtaError::decode(ERROR_IDNF | ERROR_ABRT), AtaError::IdNotFound);
+        assert_eq!(AtaError::decode(ERROR_ICRC | ERROR_ABRT), AtaError::BadCrc);
+        assert_eq!(AtaError::decode(ERROR_AMNF), AtaError::AddressMarkNotFound);
+        assert_eq!(AtaError::decode(0), AtaError::Unknown(0));
+
+        assert!(AtaError::Uncorrectable.is_retryable());
+        assert!(AtaError::BadCrc.is_retryable());
+        assert!(!AtaError::Aborted.is_retryable());
+        assert!(!AtaError::Timeout.is_retryable());
+    }
+
+    /// Make sure a floating bus and a status of zero are detected right away.
+    fn test_no_device() {
+        let result = AtaDrive::probe(MockPorts::new(&[0xFF]), PRIMARY, false, TEST_TIMEOUTS);
+        assert_eq!(result.err(), Some(AtaError::NoDevice));
+
+        let result = AtaDrive::probe(MockPorts::new(&[0x50, 0x00]), PRIMARY, true, TEST_TIMEOUTS);
+        assert_eq!(result.err(), Some(AtaError::NoDevice));
+    }
+
+    /// Make sure a drive which stays busy times out (after the IDENTIFY timeout).
+    fn test_identify_timeout() {
+        let mut ports = MockPorts::new(&[0x50, STATUS_BSY]);
+        let result = AtaDrive::probe(&mut ports, PRIMARY, false, TEST_TIMEOUTS);
+        assert_eq!(result.err(), Some(AtaError::Timeout));
+        assert!(ports.waited_us >= TEST_TIMEOUTS.identify_us);
+        assert!(ports.waited_us <= TEST_TIMEOUTS.identify_us + POLL_US + SELECT_DELAY_US);
+    }
+
+    /// Make sure the ATAPI drives are detected by their signature.
+    fn test_not_ata() {
+        let mut ports = MockPorts::new(&[0x50, 0x51, 0x51]);
+        ports.lba_mid_high = (0x14, 0xEB);
+        let result = AtaDrive::probe(ports, PRIMARY, false, TEST_TIMEOUTS);
+        assert_eq!(result.err(), Some(AtaError::NotAta));
+    }
+
+    /// Make sure a read which fails once (uncorrectable) is retried and succeeds.
+    fn test_read_retry() {
+        let mut drive = probed_drive();
+        drive.ports.statuses = alloc::vec![0x50, STATUS_ERR | 0x40, 0x50, 0x58];
+        drive.ports.error = ERROR_UNC;
+        drive.ports.commands.clear();
+        drive.ports.data = (0..SECTOR_WORDS as u16).collect();
+
+        let mut buf: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+        assert_eq!(drive.read_sector(7, &mut buf), Ok(()));
+        assert_eq!(drive.ports.commands, [CMD_READ_SECTORS, CMD_READ_SECTORS]);
+        assert_eq!(&buf[..4], &[0, 0, 1, 0]);
+        assert_eq!(&buf[SECTOR_SIZE - 2..], &[(SECTOR_WORDS - 1) as u8, 0]);
+
+        // It gives up after MAX_ATTEMPTS if it keeps failing.
+        drive.ports.statuses = alloc::vec![STATUS_ERR | 0x40];
+        drive.ports.commands.clear();
+        assert_eq!(drive.read_sector(7, &mut buf), Err(BlockError::Ata(AtaError::Uncorrectable)));
+        assert_eq!(drive.ports.commands.len(), MAX_ATTEMPTS);
+
+        assert_eq!(drive.read_sector(100, &mut buf), Err(BlockError::OutOfRange));
+    }
+
+    /// Make sure the other errors (and timeouts) are not retried.
+    fn test_no_retry() {
+        let mut drive = probed_drive();
+        drive.ports.statuses = alloc::vec![0x50, STATUS_ERR | 0x40];
+        drive.ports.error = ERROR_ABRT;
+        drive.ports.commands.clear();
+
+        let mut buf: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+        assert_eq!(drive.read_sector(0, &mut buf), Err(BlockError::Ata(AtaError::Aborted)));
+        assert_eq!(drive.ports.commands, [CMD_READ_SECTORS]);
+
+        // A transfer which never finishes times out (after the transfer timeout).
+        drive.ports.statuses = alloc::vec![0x50, STATUS_BSY];
+        drive.ports.commands.clear();
+        drive.ports.waited_us = 0;
+        assert_eq!(drive.write_sector(0, &buf), Err(BlockError::Ata(AtaError::Timeout)));
+        assert_eq!(drive.ports.commands, [CMD_WRITE_SECTORS]);
+        assert!(drive.ports.waited_us >= TEST_TIMEOUTS.transfer_us);
+    }
+}
@@ -0,0 +1,123 @@
+//! A sub-module which provides access to the CMOS (the battery backed RAM of the RTC). It reads and
+//! writes it's registers through the index (0x70) and data (0x71) ports. NMIs are disabled while
+//! selecting a register (as recommended), and the interrupts are disabled during the access so the
+//! index and data writes are never separated.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::arch::interrupts::{self, pic};
+
+// The port which selects the CMOS register.
+const INDEX_PORT: u16 = 0x70;
+
+// The port which reads or writes the selected register.
+const DATA_PORT: u16 = 0x71;
+
+// The bit in the index port which disables the NMIs.
+const NMI_DISABLE: u8 = 1 << 7;
+
+/// The RTC registers which hold the current time (in BCD unless the binary mode is set).
+const RTC_SECONDS_REG: u8 = 0x00;
+const RTC_MINUTES_REG: u8 = 0x02;
+const RTC_HOURS_REG: u8 = 0x04;
+
+/// The RTC status registers (A has the update flag, B has the format of the time).
+const RTC_STATUS_A_REG: u8 = 0x0A;
+const RTC_STATUS_B_REG: u8 = 0x0B;
+
+// The bit in status A which is set while the RTC is updating the time.
+const RTC_UPDATING: u8 = 1 << 7;
+
+// The bits in status B which are set if the time is in binary (not BCD), and in the 24 hour format.
+const RTC_BINARY: u8 = 1 << 2;
+const RTC_24_HOUR: u8 = 1 << 1;
+
+// The bit in the hours register which is set for PM (in the 12 hour format).
+const RTC_PM: u8 = 1 << 7;
+
+/// The shutdown status register (the reason for the last reset, as set before it).
+pub const SHUTDOWN_STATUS_REG: u8 = 0x0F;
+
+/// A register which is not used by the BIOS (SeaBIOS/QEMU), so the kernel can keep state in it.
+pub const SCRATCH_REG: u8 = 0x6F;
+
+/// The first register of the range which is reserved for the crash record (right before SCRATCH_REG).
+pub const CRASH_REGS_START: u8 = 0x50;
+
+/// The number of registers which are reserved for the crash record.
+pub const CRASH_REGS_LEN: u8 = SCRATCH_REG - CRASH_REGS_START;
+
+/// A function which reads a CMOS register.
+///
+/// # Parameters
+/// `reg` : The number of the register (0 to 127).
+///
+/// # Returns
+/// The value of the register.
+pub fn read(reg: u8) -> u8 {
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        pic::out_b(INDEX_PORT, NMI_DISABLE | (reg & 0x7F));
+        let value = pic::in_b(DATA_PORT);
+        interrupts::restore(were_enabled);
+
+        value
+    }
+}
+
+/// A function which writes a CMOS register.
+///
+/// # Parameters
+/// `reg` : The number of the register (0 to 127).
+/// `value` : The value which we're writing.
+pub fn write(reg: u8, value: u8) {
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        pic::out_b(INDEX_PORT, NMI_DISABLE | (reg & 0x7F));
+        pic::out_b(DATA_PORT, value);
+        interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which reads the time of day from the RTC. It waits for an update to finish, and 
+/// reads the time until it's the same twice (so it's never read in the middle of an update).
+///
+/// # Returns
+/// The number of seconds since midnight.
+pub fn rtc_seconds_of_day() -> u32 {
+    let read_time = || {
+        while read(RTC_STATUS_A_REG) & RTC_UPDATING != 0 {
+            core::hint::spin_loop();
+        }
+        (read(RTC_SECONDS_REG), read(RTC_MINUTES_REG), read(RTC_HOURS_REG))
+    };
+    
+    let mut time = read_time();
+    loop {
+        let again = read_time();
+        if again == time {
+            break;
+        }
+        time = again;
+    }
+    
+    // Convert it to binary and the 24 hour format if needed.
+    let status_b = read(RTC_STATUS_B_REG);
+    let (seconds, minutes, hours) = time;
+    let is_pm = hours & RTC_PM != 0;
+    let decode = |value: u8| if status_b & RTC_BINARY != 0 { 
+        value as u32 
+    } else { 
+        ((value >> 4) * 10 + (value & 0x0F)) as u32 
+    };
+    
+    let mut hours = decode(hours & !RTC_PM);
+    if status_b & RTC_24_HOUR == 0 {
+        hours = (hours % 12) + if is_pm { 12 } else { 0 };
+    }
+    
+    hours * 3600 + decode(minutes) * 60 + decode(seconds)
+}
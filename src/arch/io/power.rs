@@ -0,0 +1,50 @@
+//! A sub-module which resets or powers off the machine. Powering off without ACPI relies on the 
+//! ports which are used by the common emulators (QEMU, Bochs, and VirtualBox).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::arch::interrupts::pic;
+
+/// The ports (and values) which power off the common emulators.
+const POWEROFF_PORTS: [(u16, u16); 3] = [
+    (0x604, 0x2000),        // QEMU (with ACPI).
+    (0xB004, 0x2000),       // Bochs and older versions of QEMU.
+    (0x4004, 0x3400),       // VirtualBox.
+];
+
+/// A function which reboots the machine by pulsing the CPU reset line (through the PS2 controller).
+/// If that doesn't work, the CPU is halted.
+pub fn reboot() -> ! {
+    unsafe { crate::arch::interrupts::disable(); }
+    
+    if crate::arch::io::ps2_keyboard::reset_cpu().is_ok() {
+        // Give the controller some time to reset the CPU.
+        crate::time::delay_us(100000);
+    }
+    
+    oxid_err!("Could not reboot, halting the CPU instead.");
+    halt_forever()
+}
+
+/// A function which powers off the machine. If that doesn't work, the CPU is halted (so it's safe
+/// to turn it off manually).
+pub fn poweroff() -> ! {
+    unsafe {
+        crate::arch::interrupts::disable();
+        for (port, value) in POWEROFF_PORTS.iter() {
+            pic::out_w(*port, *value);
+        }
+    }
+    
+    oxid_warn!("Could not power off, it's now safe to turn off the machine.");
+    halt_forever()
+}
+
+/// A helper which halts the CPU forever (with the interrupts disabled).
+fn halt_forever() -> ! {
+    crate::proc::watchdog::disarm();
+    loop {
+        unsafe { crate::arch::proc::halt(); }
+    }
+}
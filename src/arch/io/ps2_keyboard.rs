@@ -1,12 +1,14 @@
-/// A sub-module which provides a basic driver for a PS2 keyboard. It registers an interrupt handler
-/// for it, enables the IRQ, and gets a keycode when a key is pressed. It then calls the 
-/// architecture independent code, and sends an end of interrupt to the PIC.
+/// A sub-module which provides a basic driver for a PS2 keyboard. It registers a threaded handler
+/// for it and enables the IRQ. The top half only reads the scancode and queues it, and the bottom
+/// half (in the work queue) translates the queued scancodes and calls the architecture independent
+/// code in order.
 /// TODO: Check if ps2 keyboards are supported, and use APIC instead of PIC.
 ///
 /// `Author` : Ardalan Ahanchi
 /// `Date` : Feb 2021
 
-use crate::arch::interrupts::{handlers, pic};
+use crate::arch::interrupts::{self, handlers, pic};
+use crate::arch::interrupts::handlers::threaded::{self, IrqAck};
 use crate::io::keyboard;
 
 /// The IRQ number for the PS2 keyboard in PIC (set initially by the system).
@@ -18,35 +20,278 @@ const INT_NUM: u8 = IRQ_NUM + crate::arch::interrupts::IRQ_OFFSET;
 // The port for the keyboard (to read keys from).
 const KEYBOARD_IO_PORT: u16 = 0x60;
 
+// The status port of the PS2 controller.
+const STATUS_PORT: u16 = 0x64;
+
+// The bit in the status register which is set while the controller's input buffer is full.
+const INPUT_FULL: u8 = 1 << 1;
+
+// The command which sets the keyboard LEDs (followed by a byte with the LED bits).
+const SET_LEDS_CMD: u8 = 0xED;
+
+// The controller command which pulses the CPU reset line.
+const RESET_CPU_CMD: u8 = 0xFE;
+
+// The maximum number of times we check the status before giving up (10us apart).
+const MAX_POLLS: usize = 1000;
+
+// The scancode of the escape key (pressed).
+const ESC_PRESSED: u8 = 0x01;
+
+// The scancodes of the control keys (the right one is prefixed), and the C key (pressed).
+const CTRL_PRESSED: u8 = 0x1D;
+const CTRL_RELEASED: u8 = 0x9D;
+const C_PRESSED: u8 = 0x2E;
+
+/// True while a control key is held (tracked by the top half, for Ctrl+C).
+static mut CTRL_HELD: bool = false;
+
+/// The maximum number of scancodes which can wait for the bottom half (the rest are dropped).
+pub const SCANCODE_QUEUE_SIZE: usize = 64;
+
+/// The ring buffer of the scancodes which were read by the top half (in the order they arrived).
+static mut SCANCODES: [u8; SCANCODE_QUEUE_SIZE] = [0; SCANCODE_QUEUE_SIZE];
+
+/// The index of the oldest scancode in the ring buffer.
+static mut SCANCODES_HEAD: usize = 0;
+
+/// The number of scancodes in the ring buffer.
+static mut SCANCODES_COUNT: usize = 0;
+
+/// The number of scancodes which were dropped since the ring buffer was full.
+static mut SCANCODES_DROPPED: usize = 0;
+
+/// The bits for each of the keyboard LEDs.
+pub const LED_SCROLL_LOCK: u8 = 1 << 0;
+pub const LED_NUM_LOCK: u8 = 1 << 1;
+pub const LED_CAPS_LOCK: u8 = 1 << 2;
+
 /// A function which initializes the PS2 keyboard driver, it registers the handler for the keyboard,
 /// and enables the irq line for it.
 pub fn init() {
     oxid_log!("Initializing the PS2 keyboard");
 
-    // Register the handler as an interrupt handler with the correct interrupt number.
-    handlers::register_int(INT_NUM, handle);
+    // Register the top and bottom halves for the keyboard's line.
+    if threaded::request_irq_threaded(IRQ_NUM, top_half, bottom_half).is_err() {
+        oxid_err!("Could not register the keyboard handler (vector {} is in use).", INT_NUM);
+        return;
+    }
     
     // Enable the irq for this interrupt.
     unsafe { pic::enable_irq(IRQ_NUM); }
 }
 
-/// An interrupt handler for the keyboard interrupts. It gets the key-code, calls the high-level 
-/// architecture independent code with the key code, and sends an end of interrupt to the PIC.
+/// The top half of the keyboard interrupts. It reads the key-code from the controller and queues it
+/// for the bottom half (the EOI is sent by the interrupt dispatch). Escape also asks the foreground
+/// program to exit here, and Ctrl+C cancels it's operations (see proc::cancel), since it's running
+/// in the same thread as the bottom half.
+///
+/// # Parameters
+/// `_info` : The context before the interrupt happended (registers, error code, etc.).
+///
+/// # Returns
+/// Defer, so the bottom half translates the queued key-codes.
+fn top_half(_info: &handlers::context::Context) -> IrqAck {
+    // Get the keycode recieved from port 0x60.
+    let key_code: u8 = unsafe { pic::in_b(KEYBOARD_IO_PORT) };
+    
+    if key_code == ESC_PRESSED && crate::proc::exec::in_foreground() {
+        let worker = crate::proc::workqueue::worker_pid();
+        if let Some(worker) = worker.and_then(crate::proc::scheduler::handle_of) {
+            crate::proc::scheduler::request_termination(worker);
+        }
+    }
+    
+    unsafe {
+        match key_code {
+            CTRL_PRESSED => CTRL_HELD = true,
+            CTRL_RELEASED => CTRL_HELD = false,
+            C_PRESSED if CTRL_HELD && crate::proc::exec::in_foreground() => {
+                let worker = crate::proc::workqueue::worker_pid();
+                if let Some(worker) = worker.and_then(crate::proc::scheduler::handle_of) {
+                    crate::proc::scheduler::cancel(worker);
+                }
+            },
+            _ => (),
+        }
+    }
+    
+    queue_scancode(key_code);
+    IrqAck::Defer
+}
+
+/// The bottom half of the keyboard interrupts. It translates the queued key-codes and calls the
+/// high-level architecture independent code with each event (in the order they arrived). The
+/// events are handled with the interrupts disabled (like they were in the interrupt).
+fn bottom_half() {
+    while let Some(key_code) = pop_scancode() {
+        unsafe {
+            let were_enabled = interrupts::save_and_disable();
+            
+            // Translate the key code and get an event.
+            let kb_event: keyboard::Event = keyboard::ps2::set_1::translate(key_code);
+            
+            // Call the event handler of the keyboard with the event.
+            keyboard::handle_event(&kb_event);
+            
+            interrupts::restore(were_enabled);
+        }
+    }
+}
+
+/// A function which adds a scancode at the end of the queue (it's dropped if the queue is full).
+/// It's called by the top half, and by the tests to simulate the controller.
+///
+/// # Parameters
+/// `key_code` : The scancode which we're queueing.
+pub fn queue_scancode(key_code: u8) {
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        
+        if SCANCODES_COUNT == SCANCODE_QUEUE_SIZE {
+            SCANCODES_DROPPED += 1;
+        } else {
+            SCANCODES[(SCANCODES_HEAD + SCANCODES_COUNT) % SCANCODE_QUEUE_SIZE] = key_code;
+            SCANCODES_COUNT += 1;
+        }
+        
+        interrupts::restore(were_enabled);
+    }
+}
+
+/// A helper which removes the oldest scancode from the queue.
+///
+/// # Returns
+/// The scancode, or None if the queue is empty.
+fn pop_scancode() -> Option<u8> {
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        
+        let key_code = if SCANCODES_COUNT == 0 {
+            None
+        } else {
+            let key_code = SCANCODES[SCANCODES_HEAD];
+            SCANCODES_HEAD = (SCANCODES_HEAD + 1) % SCANCODE_QUEUE_SIZE;
+            SCANCODES_COUNT -= 1;
+            Some(key_code)
+        };
+        
+        interrupts::restore(were_enabled);
+        key_code
+    }
+}
+
+/// A simple getter for the number of scancodes which were dropped (the queue was full).
+///
+/// # Returns
+/// The number of dropped scancodes.
+pub fn dropped_scancodes() -> usize {
+    unsafe { SCANCODES_DROPPED }
+}
+
+/// A function which sets the keyboard LEDs. It polls the controller before every write, so it
+/// should not be called from an interrupt handler (queue it in the work queue instead). The
+/// keyboard's acknowledgements are received by the handler and ignored.
+///
+/// # Parameters
+/// `leds` : The bits of the LEDs which should be on (the LED_ constants).
+///
+/// # Returns
+/// Ok if the LEDs were set, Err if the controller never became ready.
+pub fn set_leds(leds: u8) -> Result<(), ()> {
+    unsafe {
+        write_data(SET_LEDS_CMD)?;
+        write_data(leds & (LED_SCROLL_LOCK | LED_NUM_LOCK | LED_CAPS_LOCK))
+    }
+}
+
+/// A function which asks the PS2 controller to pulse the CPU reset line (which reboots the system).
+///
+/// # Returns
+/// Err if the controller never became ready (if it returns at all).
+pub fn reset_cpu() -> Result<(), ()> {
+    unsafe { write_port(STATUS_PORT, RESET_CPU_CMD) }
+}
+
+/// A helper which waits until the controller's input buffer is empty, and writes a byte to the
+/// keyboard.
 ///
 /// # Parameters
-/// `info` : The context before the interrupt happended (registers, error code, etc.). 
-pub fn handle(_info: *const handlers::context::Context) {
-    unsafe { 
-        // Get the keycode recieved from port 0x60.
-        let key_code: u8 = pic::in_b(KEYBOARD_IO_PORT);
+/// `data` : The byte which we're writing.
+///
+/// # Returns
+/// Ok if it was written, Err if the controller never became ready.
+unsafe fn write_data(data: u8) -> Result<(), ()> {
+    write_port(KEYBOARD_IO_PORT, data)
+}
+
+/// A helper which waits until the controller's input buffer is empty, and writes a byte to a port
+/// (the keyboard, or the controller's command port).
+///
+/// # Parameters
+/// `port` : The port which we're writing to.
+/// `data` : The byte which we're writing.
+///
+/// # Returns
+/// Ok if it was written, Err if the controller never became ready.
+unsafe fn write_port(port: u16, data: u8) -> Result<(), ()> {
+    for _ in 0..MAX_POLLS {
+        if pic::in_b(STATUS_PORT) & INPUT_FULL == 0 {
+            pic::out_b(port, data);
+            return Ok(());
+        }
+
+        crate::time::delay_us(10);
+    }
+
+    oxid_warn!("The PS2 controller is not ready, ignoring the write.");
+    Err(())
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::vec::Vec;
+    use crate::arch::interrupts::handlers::threaded;
+    use crate::io::keyboard::discipline;
+    use crate::proc::process::InputMode;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_split_order();
+    }
+
+    /// Queue the scancodes of a few key presses (as the top half would), and make sure the bottom 
+    /// half delivers them in order.
+    fn test_split_order() {
+        assert!(threaded::is_threaded(super::IRQ_NUM));
+        
+        crate::proc::set_input_mode(InputMode::Raw);
+        discipline::grab(true);
+        
+        // Press and release a, b, and c.
+        for key_code in [0x1E, 0x9E, 0x30, 0xB0, 0x2E, 0xAE].iter() {
+            super::queue_scancode(*key_code);
+        }
+        assert_eq!(threaded::schedule_bottom(super::IRQ_NUM), Ok(()));
         
-        // Translate the key code and get an event.
-        let kb_event: keyboard::Event = keyboard::ps2::set_1::translate(key_code);
+        // Wait (for up to a second) for the kworker to run the bottom half.
+        let mut bytes = Vec::new();
+        let mut buf: [u8; 8] = [0; 8];
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        while bytes.len() < 3 && crate::time::ticks() < deadline {
+            let count = discipline::read(&mut buf);
+            bytes.extend_from_slice(&buf[..count]);
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
         
-        // Call the event handler of the keyboard with the event.
-        keyboard::handle_event(&kb_event);
+        discipline::grab(false);
+        crate::proc::set_input_mode(InputMode::Cooked);
         
-        // Send eoi to the PIC so it can continue.
-        pic::end_of_interrupt(IRQ_NUM); 
+        assert_eq!(bytes, b"abc");
     }
 }
@@ -0,0 +1,190 @@
+//! A sub-module which provides a basic driver for the first serial port (COM1, a 16550 UART). The
+//! output is polled (every byte waits for the transmitter to be empty, with a bounded wait), and
+//! the received bytes raise IRQ 4. The top half only reads the bytes, and the ENQ control byte
+//! (see debug::statsdump) defers a statistics dump to the bottom half. The port is probed with the
+//! scratch register first, so nothing is written when there is no UART.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::fmt;
+use crate::arch::interrupts::{handlers, pic};
+use crate::arch::interrupts::handlers::threaded::{self, IrqAck};
+
+/// The IRQ number for COM1 in PIC (set initially by the system).
+pub const IRQ_NUM: u8 = 4;
+
+// The IO base of COM1.
+const COM1_BASE: u16 = 0x3F8;
+
+/// The offsets of the registers from the IO base (the divisor latch replaces the first two while
+/// the DLAB bit is set).
+const REG_DATA: u16 = 0;
+const REG_INT_ENABLE: u16 = 1;
+const REG_FIFO_CTRL: u16 = 2;
+const REG_LINE_CTRL: u16 = 3;
+const REG_MODEM_CTRL: u16 = 4;
+const REG_LINE_STATUS: u16 = 5;
+const REG_SCRATCH: u16 = 7;
+
+// The divisor for 38400 baud (from the 115200 base clock).
+const BAUD_DIVISOR: u16 = 3;
+
+/// The values written to the control registers.
+const LINE_DLAB: u8 = 1 << 7;
+const LINE_8N1: u8 = 0x03;
+const FIFO_ENABLE_CLEAR: u8 = 0xC7;
+const MODEM_DTR_RTS_OUT2: u8 = 0x0B;
+const INT_RX_AVAILABLE: u8 = 1 << 0;
+
+/// The bits of the line status register.
+const STATUS_DATA_READY: u8 = 1 << 0;
+const STATUS_TX_EMPTY: u8 = 1 << 5;
+
+// The value which is written to the scratch register to find out if there is a UART.
+const PROBE_VALUE: u8 = 0xAE;
+
+// The maximum number of times we check the status before dropping a byte.
+const MAX_POLLS: usize = 100_000;
+
+/// True if the UART was found and initialized.
+static mut PRESENT: bool = false;
+
+/// True if the ENQ control byte was received (and the bottom half didn't handle it yet).
+static mut ENQ_PENDING: bool = false;
+
+/// The number of bytes which were dropped since the transmitter never became empty.
+static mut DROPPED: usize = 0;
+
+/// A function which initializes COM1 (38400 baud, 8N1, with the FIFOs), registers the handler for
+/// the received bytes, and enables the irq line for it.
+pub fn init() {
+    unsafe {
+        // Make sure there is a UART (the scratch register reads back what was written).
+        pic::out_b(COM1_BASE + REG_SCRATCH, PROBE_VALUE);
+        if pic::in_b(COM1_BASE + REG_SCRATCH) != PROBE_VALUE {
+            oxid_log!("There is no serial port at 0x{:x}.", COM1_BASE);
+            return;
+        }
+
+        pic::out_b(COM1_BASE + REG_INT_ENABLE, 0);
+        pic::out_b(COM1_BASE + REG_LINE_CTRL, LINE_DLAB);
+        pic::out_b(COM1_BASE + REG_DATA, (BAUD_DIVISOR & 0xFF) as u8);
+        pic::out_b(COM1_BASE + REG_INT_ENABLE, (BAUD_DIVISOR >> 8) as u8);
+        pic::out_b(COM1_BASE + REG_LINE_CTRL, LINE_8N1);
+        pic::out_b(COM1_BASE + REG_FIFO_CTRL, FIFO_ENABLE_CLEAR);
+        pic::out_b(COM1_BASE + REG_MODEM_CTRL, MODEM_DTR_RTS_OUT2);
+        PRESENT = true;
+
+        // Register the top and bottom halves for the received bytes.
+        if threaded::request_irq_threaded(IRQ_NUM, top_half, bottom_half).is_err() {
+            oxid_err!("Could not register the serial port handler (IRQ {} is in use).", IRQ_NUM);
+            return;
+        }
+
+        pic::out_b(COM1_BASE + REG_INT_ENABLE, INT_RX_AVAILABLE);
+        pic::enable_irq(IRQ_NUM);
+    }
+
+    // Keep the kernel log on the host when the system is shut down.
+    let _ = crate::power::on_shutdown(crate::debug::klog::flush_to_serial);
+
+    oxid_log!("Initialized the serial port at 0x{:x}.", COM1_BASE);
+}
+
+/// A simple getter for if the serial port was found.
+///
+/// # Returns
+/// True if it can be written to, False otherwise.
+pub fn is_present() -> bool {
+    unsafe { PRESENT }
+}
+
+/// A simple getter for the number of bytes which were dropped (the transmitter was never empty).
+///
+/// # Returns
+/// The number of dropped bytes.
+pub fn dropped() -> usize {
+    unsafe { DROPPED }
+}
+
+/// The top half of the serial interrupts. It reads every received byte (so the UART stops raising
+/// the interrupt), and defers to the bottom half if the ENQ control byte was one of them.
+///
+/// # Parameters
+/// `_info` : The context before the interrupt happended (registers, error code, etc.).
+///
+/// # Returns
+/// Defer if a dump was requested, Handled otherwise.
+fn top_half(_info: &handlers::context::Context) -> IrqAck {
+    let mut requested = false;
+    unsafe {
+        while pic::in_b(COM1_BASE + REG_LINE_STATUS) & STATUS_DATA_READY != 0 {
+            requested |= pic::in_b(COM1_BASE + REG_DATA) == crate::debug::statsdump::TRIGGER_BYTE;
+        }
+
+        ENQ_PENDING |= requested;
+    }
+
+    if requested { IrqAck::Defer } else { IrqAck::Handled }
+}
+
+/// The bottom half of the serial interrupts. It writes a statistics dump to the serial port (the
+/// dump can take a while, so it's not done in the interrupt).
+fn bottom_half() {
+    let pending = unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let pending = ENQ_PENDING;
+        ENQ_PENDING = false;
+        crate::arch::interrupts::restore(were_enabled);
+        pending
+    };
+
+    if pending {
+        let _ = crate::debug::statsdump::dump_to_serial();
+    }
+}
+
+/// A function which writes a byte to the serial port. It waits for the transmitter to be empty
+/// (the byte is dropped if it never is, or if there is no serial port).
+///
+/// # Parameters
+/// `byte` : The byte which we're writing.
+///
+/// # Returns
+/// Ok if it was written, Err if it was dropped.
+pub fn write_byte(byte: u8) -> Result<(), ()> {
+    unsafe {
+        if !PRESENT {
+            return Err(());
+        }
+
+        for _ in 0..MAX_POLLS {
+            if pic::in_b(COM1_BASE + REG_LINE_STATUS) & STATUS_TX_EMPTY != 0 {
+                pic::out_b(COM1_BASE + REG_DATA, byte);
+                return Ok(());
+            }
+
+            core::hint::spin_loop();
+        }
+
+        DROPPED += 1;
+        Err(())
+    }
+}
+
+/// A writer which writes the formatted text to the serial port (as it is, the newlines are not
+/// translated).
+pub struct SerialWriter;
+
+impl fmt::Write for SerialWriter {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for byte in string.bytes() {
+            write_byte(byte).map_err(|_| fmt::Error)?;
+        }
+
+        Ok(())
+    }
+}
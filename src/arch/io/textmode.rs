@@ -138,8 +138,8 @@ impl Driver for TextMode {
     /// The color enum which is used for the background.
     #[inline]
     unsafe fn get_bg(&mut self, row: usize, col: usize) -> Color {    
-        // Get the specified color byte from the given cell (and shift it by a byte).
-        let color_byte: u8 = *((VGA_BUFFER + ((row * self.cols + col) * 2) + 1) as *mut u8) >> 8;
+        // Get the specified color byte from the given cell (the background is the high nibble).
+        let color_byte: u8 = *((VGA_BUFFER + ((row * self.cols + col) * 2) + 1) as *mut u8) >> 4;
         
         // Calculate the required Color enum and return it.
         Color::from(color_byte)
@@ -6,6 +6,32 @@
 
 pub mod page_tables;
 pub mod tlb;
+pub mod probe;
+
+// Wrappers for the assembly functions.
+extern "sysv64" {
+    /// A function which returns the number of bytes which were identity mapped (starting at 0x0) 
+    /// by the bootstrap code in arch/boot/boot.asm.
+    ///
+    /// # Returns
+    /// The size of the identity mapped area (in bytes).
+    pub fn get_boot_id_mapped_size() -> usize;
+    
+    /// A function which returns the address of the first page table (PT) which was used by the
+    /// bootstrap code to identity map the memory. The rest of them follow it, and each one maps the
+    /// next 2MB (starting at 0x0).
+    ///
+    /// # Returns
+    /// The physical address of the first boot PT.
+    pub fn get_boot_pts_start() -> usize;
+    
+    /// A function which returns the address after the last page table which was used by the
+    /// bootstrap code.
+    ///
+    /// # Returns
+    /// The physical address after the last boot PT.
+    pub fn get_boot_pts_end() -> usize;
+}
 
 // Unit Tests **************************************************************************************
 
@@ -17,5 +43,6 @@ pub mod test {
     /// sub module. 
     pub fn run() {
         super::page_tables::test::run();
+        super::tlb::test::run();
     }
 }
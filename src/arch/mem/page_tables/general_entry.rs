@@ -120,6 +120,39 @@ macro_rules! impl_general_entry {
                 self.0.is_set(5)
             }
             
+            /// A method which checks if the entry is writable. It checks the bit 1 as specified by 
+            /// the architecture.
+            ///
+            /// # Returns
+            /// true if it's R/W, false if it's read-only.
+            #[inline]
+            pub fn is_writable(&self) -> bool {
+                use crate::mem::bitwise::BitWise;
+                self.0.is_set(1)
+            }
+            
+            /// A method which checks if the entry is user accessible. It checks the bit 2 as 
+            /// specified by the architecture.
+            ///
+            /// # Returns
+            /// true if user programs can access it, false if it's kernel mode only.
+            #[inline]
+            pub fn is_user(&self) -> bool {
+                use crate::mem::bitwise::BitWise;
+                self.0.is_set(2)
+            }
+            
+            /// A method which checks if the no execute bit is set. It checks the bit 63 as 
+            /// specified by the architecture.
+            ///
+            /// # Returns
+            /// true if no code can be executed here, false otherwise.
+            #[inline]
+            pub fn is_no_execute(&self) -> bool {
+                use crate::mem::bitwise::BitWise;
+                self.0.is_set(63)
+            }
+            
             /// A method which gets the address which is pointed to by this entry.
             ///
             /// # Returns
@@ -181,9 +214,12 @@ macro_rules! impl_make_table_if_not_present {
                     use crate::mem::frame_alloc::{alloc, FrameAllocResult};
                     let addr = match alloc() {
                         // If successful, store the address.
-                        FrameAllocResult::Ok(addr) => addr,
-                        // If failed, return err since we were unsuccessful.
-                        _ => return Err(()),
+                        FrameAllocResult::Ok(addr) => addr.as_usize(),
+                        // If failed, log why and return err since we were unsuccessful.
+                        result => {
+                            oxid_err!("Could not allocate a frame for a new table ({:?}).", result);
+                            return Err(());
+                        },
                     };
                     
                     // Actually inintialize the table and set it's values.
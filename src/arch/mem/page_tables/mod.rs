@@ -22,6 +22,8 @@ mod pd;             // Page directory
 mod pt;             // Page table
 
 use pml_4::PML4;
+use crate::mem::addr::{PhysAddr, VirtAddr};
+use crate::mem::region::Region;
 use pdp::PDP;
 use pd::PD;
 use pt::PT;
@@ -29,24 +31,116 @@ use pt::PT;
 // Calculate the total number of entries for all tables (by default it is 512).
 pub const NUM_ENTRIES: usize = crate::mem::frame_alloc::FRAME_SIZE / core::mem::size_of::<usize>();  
 
-// Calculate the start and end of virtual memory region for the page tables (based on self ent).
-pub const PAGE_TABLES_VM_START: usize = PT_START_ADDR;
-pub const PAGE_TABLES_VM_END: usize = PML4_START_ADDR | 0xFFFF;
+/// The size of the pages which are mapped directly in the PDP (1GB).
+pub const HUGE_PAGE_SIZE: usize = 0x40000000;
 
-// The index for the self-ref entry (page tables addresses).
-const SELF_ENTRY_IDX: usize = 511;          
+// The size of the regular pages (mapped in the PT).
+const PAGE_SIZE: usize = crate::mem::frame_alloc::FRAME_SIZE;
 
-// Calculate the sign extension bits (canonical) based on the given self_entry index.
-const SIGN_EXTEND: usize = match SELF_ENTRY_IDX > (NUM_ENTRIES / 2) {
-    true => 0xFFFF << 48,
-    false => 0,
-};
+// The scratch pages which are used to fill in new tables while splitting a huge page. They're in
+// the unused part of the higher half (PML4 index 510).
+const SCRATCH_PD_ADDR: usize = 0xFFFF_FF00_0000_0000;
+const SCRATCH_PT_ADDR: usize = SCRATCH_PD_ADDR + PAGE_SIZE;
 
-// Calculate the starting address of each table (using the self-referenced entries).
-const PT_START_ADDR: usize = SIGN_EXTEND | (SELF_ENTRY_IDX << 39);      // Start addr of PTs.
-const PD_START_ADDR: usize = PT_START_ADDR | (SELF_ENTRY_IDX << 30);    // Start addr of PDs.
-const PDP_START_ADDR: usize = PD_START_ADDR | (SELF_ENTRY_IDX << 21);   // Start addr of PDPs.
-const PML4_START_ADDR: usize = PDP_START_ADDR | (SELF_ENTRY_IDX << 12); // Start addr of PML4.
+// The page which is used to access any frame temporarily (see PageTables::with_frame), and if it's
+// currently in use (it can't be nested).
+const FRAME_WINDOW_ADDR: usize = SCRATCH_PT_ADDR + PAGE_SIZE;
+static mut FRAME_WINDOW_USED: bool = false;
+
+/// The number of bits in the virtual addresses (4-level paging). It's the single source of truth
+/// for the canonical addresses, and arch::init refuses to boot if the hardware doesn't agree.
+pub const VIRT_ADDR_WIDTH: u32 = 48;
+
+// The preferred index for the self-ref entry (page tables addresses). If the boot page tables 
+// already use it, a free entry in the higher half is chosen instead (see choose_self_entry).
+const DEFAULT_SELF_ENTRY_IDX: usize = 511;
+
+// The index of the scratch pages in the PML4 (it's never chosen for the self-ref entry).
+const SCRATCH_ENTRY_IDX: usize = (SCRATCH_PD_ADDR >> 39) & 0x1FF;
+
+/// The window which the loaded tables are accessed through (set by setup_kernel_pagetable).
+static mut WINDOW: SelfRefWindow = SelfRefWindow::new(DEFAULT_SELF_ENTRY_IDX);
+
+/// A structure which holds the addresses of the tables which are accessed through a self-ref 
+/// entry. They're calculated from the index of the entry (every level goes through it once more).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SelfRefWindow {
+    pub idx: usize,             // The index of the self-ref entry in the PML4.
+    pub pt_start: usize,        // Start addr of PTs.
+    pub pd_start: usize,        // Start addr of PDs.
+    pub pdp_start: usize,       // Start addr of PDPs.
+    pub pml4_start: usize,      // Start addr of PML4.
+}
+
+impl SelfRefWindow {
+    /// A constructor which calculates the window for a given self-ref entry index.
+    ///
+    /// # Parameters
+    /// `idx` : The index of the self-ref entry in the PML4.
+    ///
+    /// # Returns
+    /// The addresses of the tables.
+    pub const fn new(idx: usize) -> Self {
+        // Calculate the sign extension bits (canonical) based on the given index.
+        let sign_extend = if idx >= NUM_ENTRIES / 2 { !0 << VIRT_ADDR_WIDTH } else { 0 };
+        let pt_start = sign_extend | (idx << 39);
+        let pd_start = pt_start | (idx << 30);
+        let pdp_start = pd_start | (idx << 21);
+        
+        SelfRefWindow {
+            idx,
+            pt_start,
+            pd_start,
+            pdp_start,
+            pml4_start: pdp_start | (idx << 12),
+        }
+    }
+    
+    /// A method which calculates the virtual memory region which is covered by the window (all the
+    /// tables are in the range of the PML4 entry).
+    ///
+    /// # Returns
+    /// The region (it ends one byte early, so it doesn't overflow for the last entry).
+    pub fn region(&self) -> Region {
+        Region::new(self.pt_start, self.pt_start | ((1 << 39) - 1))
+    }
+}
+
+/// A structure which represents the mapping of a regular page (as it's stored in it's PT entry).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PageMapping {
+    pub frame_addr: usize,      // The address of the frame which the page is mapped to.
+    pub is_writable: bool,      // True if R/W, False if it's read-only.
+    pub is_user: bool,          // True if it's user accessible, False otherwise.
+    pub is_no_exec: bool,       // True if not executable, False otherwise.
+}
+
+/// A simple getter for the window which the loaded tables are accessed through.
+///
+/// # Returns
+/// A copy of the window (of the default index before the kernel page table is set up).
+pub fn window() -> SelfRefWindow {
+    unsafe { WINDOW }
+}
+
+/// A function which chooses the index of the self-ref entry in a PML4. The default index is used
+/// if it's empty (or it already refers to the PML4). Otherwise, the highest empty entry in the 
+/// higher half is used, so the entry of a real mapping is never replaced.
+///
+/// # Parameters
+/// `pml4` : The PML4 table (accessible at it's virtual address).
+/// `pml4_addr` : The physical address of the PML4.
+///
+/// # Returns
+/// Some(idx), or None if every entry in the higher half is used.
+fn choose_self_entry(pml4: &PML4, pml4_addr: usize) -> Option<usize> {
+    let is_free = |idx: usize| !pml4[idx].is_present() || pml4[idx].get_addr() == pml4_addr;
+    if is_free(DEFAULT_SELF_ENTRY_IDX) {
+        return Some(DEFAULT_SELF_ENTRY_IDX);
+    }
+    
+    (NUM_ENTRIES / 2..NUM_ENTRIES).rev().find(|idx| *idx != SCRATCH_ENTRY_IDX && is_free(*idx))
+}
 
 /// Create a public struct which represents the 4 level paging from the outside world. It implements
 /// the page table trait which allows it to be more modular and makes it possible to utilize other 
@@ -67,8 +161,25 @@ impl PageTables {
         }
     }
     
+    /// A constructor which refers to an existing page table (which doesn't have to be loaded). It
+    /// can only be used to translate the addresses (see virt_to_phys_in), or to load it.
+    ///
+    /// # Parameters
+    /// `pml4_addr` : The physical address of the PML4 (such as the address in CR3).
+    pub const fn at(pml4_addr: PhysAddr) -> Self {
+        PageTables {
+            table_addr: Some(pml4_addr.as_usize()),
+        }
+    }
+    
+    /// A constructor which refers to the page table which is currently loaded (in CR3).
+    pub fn current() -> Self {
+        unsafe { PageTables::at(PhysAddr::new(crate::arch::registers::get_cr3() & !0xFFF)) }
+    }
+    
     /// A method which sets up the kernel page table. It gets the address of the current page table,
-    /// sets the table address, sets the self-reference entry, 
+    /// sets the table address, and sets the self-reference entry (in an entry which is not used by
+    /// the boot page tables). The window of the tables is calculated from the chosen entry.
     pub fn setup_kernel_pagetable(&mut self) {
         unsafe {
             // Get the currently stored address and store it.
@@ -76,16 +187,29 @@ impl PageTables {
             self.table_addr = Some(pml4_addr);
             oxid_log!("Initializing the kernel page table. PML4 is at 0x{:x}.", pml4_addr);
             
-            // Get the table from the address, and set the self-reference entry.
+            // Get the table from the address (it's identity mapped), and choose the entry.
             let mut table = PML4::at(pml4_addr);
-            table[SELF_ENTRY_IDX].set_addr(pml4_addr);
-            table[SELF_ENTRY_IDX].set_present(true);
-            table[SELF_ENTRY_IDX].set_writable(true);
+            let idx = match choose_self_entry(&table, pml4_addr) {
+                Some(idx) => idx,
+                None => panic!("No free PML4 entry for the page tables self-reference."),
+            };
+            if idx != DEFAULT_SELF_ENTRY_IDX {
+                oxid_warn!("PML4 entry {} is already used, the self-reference was moved.", 
+                    DEFAULT_SELF_ENTRY_IDX);
+            }
+            
+            // Set the self-reference entry, and use it's window from now on.
+            table[idx].set_addr(pml4_addr);
+            table[idx].set_present(true);
+            table[idx].set_writable(true);
+            WINDOW = SelfRefWindow::new(idx);
+            oxid_log!("The page tables self-reference is in PML4 entry {} (at 0x{:x}).", idx, 
+                WINDOW.pt_start);
         }
     }
     
     /// A function which checks if a given address is in canonical form. This has to be the case 
-    /// in this architecture (this assumes the 48-bit address width).
+    /// in this architecture (it's either in the user half or the kernel half of the address space).
     ///
     /// # Parameters
     /// `addr` : The address which we're checking.
@@ -94,9 +218,24 @@ impl PageTables {
     /// true if the address is valid and in canonical form, false otherwise.
     #[inline(always)]
     pub fn is_canonical(addr: usize) -> bool {
-        // More details can be found at:
-        // https://stackoverflow.com/questions/25852367/x86-64-canonical-address
-        addr <= 0x00007FFFFFFFFFFF || addr >= 0xFFFF800000000000 
+        addr <= crate::mem::MAX_USER_ADDR || addr >= crate::mem::MIN_KERNEL_ADDR
+    }
+    
+    /// A function which checks if a given address is in canonical form for a given address width 
+    /// (the bits above the width must be copies of the highest bit). More details can be found at:
+    /// https://stackoverflow.com/questions/25852367/x86-64-canonical-address
+    ///
+    /// # Parameters
+    /// `addr` : The address which we're checking.
+    /// `width` : The number of bits in the virtual addresses (such as 48 or 57).
+    ///
+    /// # Returns
+    /// true if the address is in canonical form, false otherwise.
+    #[inline(always)]
+    pub const fn is_canonical_for(addr: usize, width: u32) -> bool {
+        // Sign extend the address from the width, and make sure nothing changed.
+        let shift = usize::BITS - width;
+        (((addr << shift) as isize) >> shift) as usize == addr
     }
     
     /// A function which maps a given page address (starting address) to a given frame address. It 
@@ -111,15 +250,13 @@ impl PageTables {
     ///
     /// # Returns
     /// Ok if everything went as expected, Err otherwise.
-    pub unsafe fn map(page_addr: usize, frame_addr: usize, is_user: bool, 
+    pub unsafe fn map(page_addr: VirtAddr, frame_addr: PhysAddr, is_user: bool, 
         is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
         
-        //oxid_log!("Mapping page 0x{:x} to frame 0x{:x}", page_addr, frame_addr);
+        oxid_dbg!(Vmm, "Mapping page 0x{:x} to frame 0x{:x}", page_addr, frame_addr);
         
-        // Check if the passed page address is in canonical form. If not, return err.
-        if ! PageTables::is_canonical(page_addr) {
-            return Err(());
-        }
+        // The page address is always in canonical form (VirtAddr checks it).
+        let (page_addr, frame_addr) = (page_addr.as_usize(), frame_addr.as_usize());
                            
         // Get the indexes within each table.
         let pml4_idx = PML4::get_idx(page_addr);
@@ -128,17 +265,22 @@ impl PageTables {
         let pt_idx = PT::get_idx(page_addr);
         
         // Calculate each table's addressess.
-        let pml4_addr = PML4_START_ADDR;
-        let pdp_addr = PDP_START_ADDR | (pml4_idx << 12);
-        let pd_addr = PD_START_ADDR | (pml4_idx << 21) | (pdp_idx << 12);
-        let pt_addr = PT_START_ADDR | (pml4_idx << 30) | (pdp_idx << 21) | (pd_idx << 12);
+        let window = window();
+        let pml4_addr = window.pml4_start;
+        let pdp_addr = window.pdp_start | (pml4_idx << 12);
+        let pd_addr = window.pd_start | (pml4_idx << 21) | (pdp_idx << 12);
+        let pt_addr = window.pt_start | (pml4_idx << 30) | (pdp_idx << 21) | (pd_idx << 12);
             
         // Get the pml4 from the self-reference entry, and create a PDP if not present in PML4.
         let mut pml4 = PML4::at(pml4_addr);
         pml4[pml4_idx].make_table_if_not_present(pdp_addr, is_user, is_writable, is_no_exec)?;
         
-        // Get the pdp from the self-reference entry, and create a PD if not present in PDP.
+        // Get the pdp from the self-reference entry. If the page is a part of a huge page, split it
+        // first (so the PD exists). Then create a PD if not present in PDP.
         let mut pdp = PDP::at(pdp_addr);
+        if pdp[pdp_idx].is_present() && pdp[pdp_idx].is_huge() {
+            PageTables::split_huge(page_addr)?;
+        }
         pdp[pdp_idx].make_table_if_not_present(pd_addr, is_user, is_writable, is_no_exec)?;
         
         // Get the pd from the self-reference entry, and create a PT if not present in PDP.
@@ -158,6 +300,237 @@ impl PageTables {
         Ok(())
     }
     
+    /// A function which maps a 1GB page directly in the PDP. It only works if the CPU supports it, 
+    /// and the addresses are 1GB aligned. It will not replace an existing mapping (or PD), so the
+    /// whole 1GB range has to be unused.
+    ///
+    /// # Parameters
+    /// `page_addr` : The starting address of the huge page (1GB aligned).
+    /// `frame_addr` : The starting address of the physical memory (1GB aligned).
+    /// `is_user` : True if the permissions are user accessible, False otherwise.
+    /// `is_writable` : True if R/W, False if it's read-only.
+    /// `is_no_exec` : True if not executable, False otherwise.
+    ///
+    /// # Returns
+    /// Ok if it was mapped, Err if not supported, not aligned, or the range is already in use.
+    pub unsafe fn map_1g(page_addr: VirtAddr, frame_addr: PhysAddr, is_user: bool, 
+        is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
+        
+        oxid_dbg!(Vmm, "Mapping huge page 0x{:x} to frame 0x{:x}", page_addr, frame_addr);
+        
+        // Check the addresses, and make sure the CPU supports huge pages.
+        if ! page_addr.is_aligned(HUGE_PAGE_SIZE) || ! frame_addr.is_aligned(HUGE_PAGE_SIZE) 
+            || ! crate::arch::proc::cpu::has_1g_pages() {
+            return Err(());
+        }
+        let (page_addr, frame_addr) = (page_addr.as_usize(), frame_addr.as_usize());
+        
+        // Get the indexes and the address of the PDP.
+        let pml4_idx = PML4::get_idx(page_addr);
+        let pdp_idx = PDP::get_idx(page_addr);
+        let pdp_addr = window().pdp_start | (pml4_idx << 12);
+        
+        // Create a PDP if not present in PML4.
+        let mut pml4 = PML4::at(window().pml4_start);
+        pml4[pml4_idx].make_table_if_not_present(pdp_addr, is_user, is_writable, is_no_exec)?;
+        
+        // Make sure we're not replacing anything, and set the entry (with the page size bit).
+        let mut pdp = PDP::at(pdp_addr);
+        if pdp[pdp_idx].is_present() {
+            return Err(());
+        }
+        
+        pdp[pdp_idx] = pdp::PDPEntry::new();
+        pdp[pdp_idx].set_present(true);
+        pdp[pdp_idx].set_huge(true);
+        pdp[pdp_idx].set_addr(frame_addr);
+        pdp[pdp_idx].set_user(is_user);
+        pdp[pdp_idx].set_writable(is_writable);
+        pdp[pdp_idx].set_no_execute(is_no_exec);
+        
+        Ok(())
+    }
+    
+    /// A function which unmaps a whole 1GB page which was mapped by map_1g.
+    ///
+    /// # Parameters
+    /// `page_addr` : The starting address of the huge page (1GB aligned).
+    ///
+    /// # Returns
+    /// Ok if the huge page was unmapped, Err if the address is not the start of a huge page.
+    pub unsafe fn unmap_1g(page_addr: VirtAddr) -> Result<(), ()> {
+        if ! page_addr.is_aligned(HUGE_PAGE_SIZE) {
+            return Err(());
+        }
+        
+        // Reset the entry, and invalidate it (a single invalidation covers the whole page).
+        let entry_ptr = PageTables::get_huge_entry_ptr(page_addr.as_usize()).ok_or(())?;
+        (*entry_ptr) = pdp::PDPEntry::new();
+        super::tlb::invalidate_page(page_addr.as_usize())
+    }
+    
+    /// A function which splits the 1GB page which includes the given address into regular pages 
+    /// with the same frames and permissions, so a part of it can be unmapped or remapped. It needs
+    /// a new PD and 512 new PTs (about 2MB). The new tables are filled through the scratch pages
+    /// before they're linked in, so the range stays mapped during the whole split.
+    ///
+    /// # Parameters
+    /// `page_addr` : Any address within the huge page.
+    ///
+    /// # Returns
+    /// Ok if it was split, Err if it's not a huge page or we ran out of frames.
+    pub unsafe fn split_1g(page_addr: VirtAddr) -> Result<(), ()> {
+        PageTables::split_huge(page_addr.as_usize())
+    }
+    
+    /// The internal implementation of split_1g (which takes a raw address).
+    ///
+    /// # Parameters
+    /// `page_addr` : Any address within the huge page (canonical).
+    ///
+    /// # Returns
+    /// Ok if it was split, Err if it's not a huge page or we ran out of frames.
+    unsafe fn split_huge(page_addr: usize) -> Result<(), ()> {
+        oxid_dbg!(Vmm, "Splitting the huge page which includes 0x{:x}", page_addr);
+        
+        // Get the huge page's entry, and keep it's frame and permissions.
+        let entry_ptr = PageTables::get_huge_entry_ptr(page_addr).ok_or(())?;
+        let huge = *entry_ptr;
+        let frame_base = huge.get_addr() & !(HUGE_PAGE_SIZE - 1);
+        
+        // Allocate the new PD, and make it accessible using the scratch page.
+        let pd_frame = PageTables::alloc_table_frame()?;
+        if PageTables::map_scratch(SCRATCH_PD_ADDR, pd_frame).is_err() {
+            crate::mem::frame_alloc::dealloc(pd_frame);
+            return Err(());
+        }
+        let mut pd = PD::new(SCRATCH_PD_ADDR);
+        
+        // Create a PT for every entry of the PD, which maps the same frames as the huge page.
+        for pd_idx in 0..NUM_ENTRIES {
+            let pt_frame = match PageTables::alloc_table_frame() {
+                Ok(pt_frame) => pt_frame,
+                Err(()) => {
+                    PageTables::abort_split(&pd, pd_frame);
+                    return Err(());
+                },
+            };
+            
+            if PageTables::map_scratch(SCRATCH_PT_ADDR, pt_frame).is_err() {
+                crate::mem::frame_alloc::dealloc(pt_frame);
+                PageTables::abort_split(&pd, pd_frame);
+                return Err(());
+            }
+            
+            let mut pt = PT::at(SCRATCH_PT_ADDR);
+            for pt_idx in 0..NUM_ENTRIES {
+                pt[pt_idx] = pt::PTEntry::new();
+                pt[pt_idx].set_present(true);
+                pt[pt_idx].set_addr(frame_base | (pd_idx << 21) | (pt_idx << 12));
+                pt[pt_idx].set_user(huge.is_user());
+                pt[pt_idx].set_writable(huge.is_writable());
+                pt[pt_idx].set_no_execute(huge.is_no_execute());
+            }
+            
+            pd[pd_idx].set_present(true);
+            pd[pd_idx].set_addr(pt_frame.as_usize());
+            pd[pd_idx].set_user(huge.is_user());
+            pd[pd_idx].set_writable(huge.is_writable());
+            pd[pd_idx].set_no_execute(huge.is_no_execute());
+        }
+        
+        // We don't need the scratch pages anymore.
+        PageTables::unmap_scratch(SCRATCH_PT_ADDR)?;
+        PageTables::unmap_scratch(SCRATCH_PD_ADDR)?;
+        
+        // Replace the huge page with the PD, and flush the whole TLB (since every page changed).
+        let mut entry = pdp::PDPEntry::new();
+        entry.set_present(true);
+        entry.set_addr(pd_frame.as_usize());
+        entry.set_user(huge.is_user());
+        entry.set_writable(huge.is_writable());
+        entry.set_no_execute(huge.is_no_execute());
+        (*entry_ptr) = entry;
+        super::tlb::flush_all();
+        
+        Ok(())
+    }
+    
+    /// A function which finds the mapping which includes the given address.
+    ///
+    /// # Parameters
+    /// `page_addr` : The address which we're looking up.
+    ///
+    /// # Returns
+    /// Ok((frame_addr, page_size)) for the page which includes it, Err if it's not mapped.
+    pub fn query(page_addr: VirtAddr) -> Result<(PhysAddr, usize), ()> {
+        PageTables::query_raw(page_addr.as_usize())
+            .map(|(frame_addr, page_size)| (PhysAddr::new(frame_addr), page_size))
+    }
+    
+    /// A function which reads the PT entry of a regular page (the huge pages don't have one).
+    ///
+    /// # Parameters
+    /// `page_addr` : The address which we're looking up.
+    ///
+    /// # Returns
+    /// Ok(mapping) of the page which includes it, Err if it's not mapped by a regular page.
+    pub fn mapping(page_addr: VirtAddr) -> Result<PageMapping, ()> {
+        unsafe {
+            let entry = *PageTables::get_pt_entry_ptr(page_addr.as_usize())?;
+            Ok(PageMapping {
+                frame_addr: entry.get_addr(),
+                is_writable: entry.is_writable(),
+                is_user: entry.is_user(),
+                is_no_exec: entry.is_no_execute(),
+            })
+        }
+    }
+    
+    /// A function which changes the permission of a mapped page (it keeps the frame), and 
+    /// invalidates it in the TLB. A huge page is split first, so only the given page is changed.
+    /// Only the PT entry is changed, so a read-only table above it still makes it read-only.
+    ///
+    /// # Parameters
+    /// `page_addr` : The address of the page which we're changing.
+    /// `is_writable` : True if R/W, False if it's read-only.
+    ///
+    /// # Returns
+    /// Ok if it was changed, Err if invalid address or non-existant page.
+    pub unsafe fn protect(page_addr: VirtAddr, is_writable: bool) -> Result<(), ()> {
+        oxid_dbg!(Vmm, "Protecting page 0x{:x} (writable={})", page_addr, is_writable);
+        
+        let page_addr = page_addr.align_down(PAGE_SIZE).as_usize();
+        if PageTables::get_huge_entry_ptr(page_addr).is_some() {
+            PageTables::split_huge(page_addr)?;
+        }
+        
+        let entry_ptr = PageTables::get_pt_entry_ptr(page_addr)?;
+        (*entry_ptr).set_writable(is_writable);
+        super::tlb::invalidate_page(page_addr)
+    }
+    
+    /// The internal implementation of query (which takes a raw address).
+    ///
+    /// # Parameters
+    /// `page_addr` : The address which we're looking up.
+    ///
+    /// # Returns
+    /// Ok((frame_addr, page_size)) for the page which includes it, Err if it's not mapped.
+    fn query_raw(page_addr: usize) -> Result<(usize, usize), ()> {
+        unsafe {
+            // Check for a huge page first (since it doesn't have the lower levels).
+            if let Some(entry_ptr) = PageTables::get_huge_entry_ptr(page_addr) {
+                return Ok(((*entry_ptr).get_addr() & !(HUGE_PAGE_SIZE - 1), HUGE_PAGE_SIZE));
+            }
+            
+            match PageTables::get_pt_entry_ptr(page_addr) {
+                Ok(entry_ptr) => Ok(((*entry_ptr).get_addr(), PAGE_SIZE)),
+                Err(()) => Err(())
+            }
+        }
+    }
+    
     /// A function which unmaps a given page address. It resets the lowest level page table entry 
     /// for this specific page and "frees" it for future use. It does not remove the page table 
     /// however since it is likely that it will be re-used and it doesn't take that much space.
@@ -167,13 +540,32 @@ impl PageTables {
     ///
     /// # Returns
     /// Ok if the given page was unmapped, Err if invalid address or non-existant page.
-    pub unsafe fn unmap(page_addr: usize) -> Result<(), ()> { 
+    pub unsafe fn unmap(page_addr: VirtAddr) -> Result<(), ()> { 
+        // Reset the entry, and invalidate the whole page in the TLB.
+        PageTables::unmap_no_invalidate(page_addr)?;
+        super::tlb::invalidate_page(page_addr.align_down(PAGE_SIZE).as_usize())
+    }
+    
+    /// A version of unmap which does not invalidate the page in the TLB. It is used when unmapping
+    /// large ranges, where the caller flushes the whole TLB afterwards (see tlb::flush_if_needed).
+    ///
+    /// # Parameters
+    /// `page_addr` : The address of the page which we're unmapping.
+    ///
+    /// # Returns
+    /// Ok if the given page was unmapped, Err if invalid address or non-existant page.
+    pub unsafe fn unmap_no_invalidate(page_addr: VirtAddr) -> Result<(), ()> { 
+        // If the page is a part of a huge page, split it so only this page is unmapped.
+        let page_addr = page_addr.as_usize();
+        if PageTables::get_huge_entry_ptr(page_addr).is_some() {
+            PageTables::split_huge(page_addr)?;
+        }
+        
         // Get a pointer to the page table entry and check for errors. If anty error occured, 
         // simply return Err, otherwise reset the entry to a new (unpresent) one, and return ok.
         match PageTables::get_pt_entry_ptr(page_addr) {
             Ok(entry_ptr) => { 
                 (*entry_ptr) = pt::PTEntry::new();      // Reset entry to a new one. 
-                super::tlb::invalidate(page_addr);      // Invalidate it in TLB.
                 Ok(()) 
             },
             
@@ -181,6 +573,54 @@ impl PageTables {
         }
     }
     
+    /// A function which removes a page table (last level) which maps a 2MB region from it's PD, if
+    /// none of it's pages are mapped. The table's frame is not deallocated (the caller can give it
+    /// back to the frame allocator).
+    ///
+    /// # Parameters
+    /// `page_addr` : The start of the region which is mapped by the table (2MB aligned).
+    /// `pt_frame` : The frame of the table (it's only removed if the PD still points to it).
+    ///
+    /// # Returns
+    /// Ok if the table was removed, Err if it's not aligned, the PD points to another table (or
+    /// none), or any of it's pages are still mapped.
+    pub unsafe fn unlink_empty_pt(page_addr: VirtAddr, pt_frame: PhysAddr) -> Result<(), ()> {
+        if ! page_addr.is_aligned(NUM_ENTRIES * PAGE_SIZE) {
+            return Err(());
+        }
+        
+        // Get the indexes, and the addresses of the tables (through the self reference).
+        let page_addr = page_addr.as_usize();
+        let pml4_idx = PML4::get_idx(page_addr);
+        let pdp_idx = PDP::get_idx(page_addr);
+        let pd_idx = PD::get_idx(page_addr);
+        let window = window();
+        let pd_addr = window.pd_start | (pml4_idx << 21) | (pdp_idx << 12);
+        let pt_addr = window.pt_start | (pml4_idx << 30) | (pdp_idx << 21) | (pd_idx << 12);
+        
+        // Make sure the PD exists (and the region is not a part of a huge page).
+        let pml4 = PML4::at(window.pml4_start);
+        let pdp = PDP::at(window.pdp_start | (pml4_idx << 12));
+        if ! pml4[pml4_idx].is_present() || ! pdp[pdp_idx].is_present() || pdp[pdp_idx].is_huge() {
+            return Err(());
+        }
+        
+        // Make sure it's the given table, and none of it's pages are mapped.
+        let mut pd = PD::at(pd_addr);
+        if ! pd[pd_idx].is_present() || pd[pd_idx].get_addr() != pt_frame.as_usize() {
+            return Err(());
+        }
+        
+        let pt = PT::at(pt_addr);
+        if (0..NUM_ENTRIES).any(|pt_idx| pt[pt_idx].is_present()) {
+            return Err(());
+        }
+        
+        // Remove it, and invalidate the table's page in the self reference window.
+        pd[pd_idx] = pd::PDEntry::new();
+        super::tlb::invalidate_page(pt_addr)
+    }
+    
     /// A function which translates a given virtual address to it's corresponding physical address 
     /// based on the currently stored page table. It will return an Err if the page table is not 
     /// set-up or if the address is not currently mapped.
@@ -190,8 +630,25 @@ impl PageTables {
     ///
     /// # Returns
     /// Ok(frame_addr) if everything went as expected, Err otherwise.
-    pub fn virt_to_phys(page_addr: usize) -> Result<usize, ()> {
+    pub fn virt_to_phys(page_addr: VirtAddr) -> Result<PhysAddr, ()> {
+        PageTables::virt_to_phys_raw(page_addr.as_usize()).map(PhysAddr::new)
+    }
+    
+    /// The internal implementation of virt_to_phys (which takes a raw address).
+    ///
+    /// # Parameters
+    /// `page_addr` : The address of the page which we're translating.
+    ///
+    /// # Returns
+    /// Ok(frame_addr) if everything went as expected, Err otherwise.
+    fn virt_to_phys_raw(page_addr: usize) -> Result<usize, ()> {
         unsafe {
+            // Huge pages have a 30-bit offset (instead of 12).
+            if let Some(entry_ptr) = PageTables::get_huge_entry_ptr(page_addr) {
+                return Ok(((*entry_ptr).get_addr() & !(HUGE_PAGE_SIZE - 1)) 
+                    | (page_addr & (HUGE_PAGE_SIZE - 1)));
+            }
+            
             // Get a pointer to the page table entry and check for errors. If anty error occured, 
             // simply return Err, otherwise (addr is present) save the offset and return it.
             match PageTables::get_pt_entry_ptr(page_addr) {
@@ -201,6 +658,148 @@ impl PageTables {
         }
     }
     
+    /// A method which translates a given virtual address to it's physical address using this page 
+    /// table, even if it's not the loaded one (such as a new address space). The tables are read 
+    /// through their frames (see with_frame) instead of the self-reference entry, so it's slower 
+    /// than virt_to_phys.
+    ///
+    /// # Parameters
+    /// `page_addr` : The address which we're translating.
+    ///
+    /// # Returns
+    /// Ok(frame_addr) if it's mapped in this page table, Err otherwise (or if the window is busy).
+    pub fn virt_to_phys_in(&self, page_addr: VirtAddr) -> Result<PhysAddr, ()> {
+        let page_addr = page_addr.as_usize();
+        let pml4_frame = PhysAddr::new(self.table_addr.ok_or(())?);
+        
+        unsafe {
+            // Follow the physical addresses from the PML4 down, and stop at a non-present entry.
+            let pml4_entry = PageTables::with_frame(pml4_frame, |frame| 
+                PML4::at(frame.as_ptr() as usize)[PML4::get_idx(page_addr)])?;
+            if ! pml4_entry.is_present() {
+                return Err(());
+            }
+            
+            let pdp_entry = PageTables::with_frame(PhysAddr::new(pml4_entry.get_addr()), |frame| 
+                PDP::at(frame.as_ptr() as usize)[PDP::get_idx(page_addr)])?;
+            if ! pdp_entry.is_present() {
+                return Err(());
+            }
+            
+            // Huge pages have a 30-bit offset (instead of 12).
+            if pdp_entry.is_huge() {
+                return Ok(PhysAddr::new((pdp_entry.get_addr() & !(HUGE_PAGE_SIZE - 1)) 
+                    | (page_addr & (HUGE_PAGE_SIZE - 1))));
+            }
+            
+            let pd_entry = PageTables::with_frame(PhysAddr::new(pdp_entry.get_addr()), |frame| 
+                PD::at(frame.as_ptr() as usize)[PD::get_idx(page_addr)])?;
+            if ! pd_entry.is_present() {
+                return Err(());
+            }
+            
+            let pt_entry = PageTables::with_frame(PhysAddr::new(pd_entry.get_addr()), |frame| 
+                PT::at(frame.as_ptr() as usize)[PT::get_idx(page_addr)])?;
+            if ! pt_entry.is_present() {
+                return Err(());
+            }
+            
+            Ok(PhysAddr::new(pt_entry.get_addr() | (page_addr & 0xFFF)))
+        }
+    }
+    
+    /// A function which makes any frame accessible for a closure. The frame is mapped at a reserved
+    /// page (the frame window) in the loaded page table, and it's unmapped after the closure. The
+    /// interrupts are disabled while it's mapped, and it can't be nested (it returns an Err). 
+    ///
+    /// # Parameters
+    /// `frame_addr` : The address of the frame (it's aligned down to the frame).
+    /// `func` : The closure which is called with the contents of the frame.
+    ///
+    /// # Returns
+    /// Ok(result) of the closure, Err if the window is in use or it could not be mapped.
+    pub unsafe fn with_frame<R>(frame_addr: PhysAddr, func: impl FnOnce(&[u8; PAGE_SIZE]) -> R) 
+        -> Result<R, ()> {
+        // Claim the window (the interrupts are disabled, so nothing else can claim it in between).
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if FRAME_WINDOW_USED {
+            crate::arch::interrupts::restore(were_enabled);
+            return Err(());
+        }
+        FRAME_WINDOW_USED = true;
+        
+        let result = match PageTables::map_scratch(FRAME_WINDOW_ADDR, 
+            frame_addr.align_down(PAGE_SIZE)) {
+            Ok(()) => {
+                let result = func(&*(FRAME_WINDOW_ADDR as *const [u8; PAGE_SIZE]));
+                PageTables::unmap_scratch(FRAME_WINDOW_ADDR).map(|()| result)
+            },
+            Err(()) => Err(()),
+        };
+        
+        FRAME_WINDOW_USED = false;
+        crate::arch::interrupts::restore(were_enabled);
+        result
+    }
+    
+    /// A function which walks the lower half of the currently loaded page table, and calls the 
+    /// given closures for every table and every mapped page which it finds. The self-reference 
+    /// entry is skipped. It is slow, so it should only be used for debugging and auditing.
+    ///
+    /// # Parameters
+    /// `on_table` : Called with the physical address of every PDP, PD, and PT which is present.
+    /// `on_page` : Called with the page address and frame address of every present page.
+    pub unsafe fn walk(on_table: &mut dyn FnMut(usize), on_page: &mut dyn FnMut(usize, usize)) {
+        // Go through the lower half of the PML4 (the higher half is not used for regular pages).
+        let window = window();
+        let pml4 = PML4::at(window.pml4_start);
+        for pml4_idx in 0..(NUM_ENTRIES / 2) {
+            if pml4_idx == window.idx || ! pml4[pml4_idx].is_present() {
+                continue;
+            }
+            on_table(pml4[pml4_idx].get_addr());
+            
+            // Go through the present entries of the PDP.
+            let pdp = PDP::at(window.pdp_start | (pml4_idx << 12));
+            for pdp_idx in 0..NUM_ENTRIES {
+                if ! pdp[pdp_idx].is_present() {
+                    continue;
+                }
+                
+                // Report every regular page which is covered by a huge page.
+                if pdp[pdp_idx].is_huge() {
+                    let page_base = (pml4_idx << 39) | (pdp_idx << 30);
+                    let frame_base = pdp[pdp_idx].get_addr() & !(HUGE_PAGE_SIZE - 1);
+                    for offset in (0..HUGE_PAGE_SIZE).step_by(PAGE_SIZE) {
+                        on_page(page_base + offset, frame_base + offset);
+                    }
+                    continue;
+                }
+                on_table(pdp[pdp_idx].get_addr());
+                
+                // Go through the present entries of the PD.
+                let pd = PD::at(window.pd_start | (pml4_idx << 21) | (pdp_idx << 12));
+                for pd_idx in 0..NUM_ENTRIES {
+                    if ! pd[pd_idx].is_present() {
+                        continue;
+                    }
+                    on_table(pd[pd_idx].get_addr());
+                    
+                    // Finally, report every present page in the PT.
+                    let pt = PT::at(window.pt_start | (pml4_idx << 30) | (pdp_idx << 21) 
+                        | (pd_idx << 12));
+                    for pt_idx in 0..NUM_ENTRIES {
+                        if pt[pt_idx].is_present() {
+                            let page_addr = (pml4_idx << 39) | (pdp_idx << 30) | (pd_idx << 21) 
+                                | (pt_idx << 12);
+                            on_page(page_addr, pt[pt_idx].get_addr());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
     /// A method which loads this page table into the system (using the CR3 register). It keeps 
     /// the properties that were previously stored in the CR3 register.
     pub unsafe fn load(&self) {
@@ -244,10 +843,11 @@ impl PageTables {
         let pt_idx = PT::get_idx(page_addr);
         
         // Calculate each table's addressess.
-        let pml4_addr = PML4_START_ADDR;
-        let pdp_addr = PDP_START_ADDR | (pml4_idx << 12);
-        let pd_addr = PD_START_ADDR | (pml4_idx << 21) | (pdp_idx << 12);
-        let pt_addr = PT_START_ADDR | (pml4_idx << 30) | (pdp_idx << 21) | (pd_idx << 12);
+        let window = window();
+        let pml4_addr = window.pml4_start;
+        let pdp_addr = window.pdp_start | (pml4_idx << 12);
+        let pd_addr = window.pd_start | (pml4_idx << 21) | (pdp_idx << 12);
+        let pt_addr = window.pt_start | (pml4_idx << 30) | (pdp_idx << 21) | (pd_idx << 12);
         
         // Get the pml4 table using the self reference entry, and check if entry is present.
         let pml4 = PML4::at(pml4_addr);
@@ -255,9 +855,10 @@ impl PageTables {
             return Err(());
         }
         
-        // Get the pdp table using the self reference entry, and check if entry is present.
+        // Get the pdp table using the self reference entry, and check if entry is present (huge 
+        // pages don't have a PT entry).
         let pdp = PDP::at(pdp_addr);
-        if ! pdp[pdp_idx].is_present() {
+        if ! pdp[pdp_idx].is_present() || pdp[pdp_idx].is_huge() {
             return Err(());
         }
         
@@ -276,6 +877,90 @@ impl PageTables {
         // If we get here, the address is present. So get a pointer to it and return it.
         Ok(&mut pt[pt_idx] as *mut pt::PTEntry)
     }
+    
+    /// An internal function which tries to get a pointer to the PDP entry of a huge page which 
+    /// includes the given address.
+    ///
+    /// # Parameters
+    /// `page_addr` : Any address within the huge page.
+    ///
+    /// # Returns
+    /// Some(entry_ptr) if the address is mapped by a huge page, None otherwise.
+    unsafe fn get_huge_entry_ptr(page_addr: usize) -> Option<*mut pdp::PDPEntry> {
+        if ! PageTables::is_canonical(page_addr) {
+            return None;
+        }
+        
+        // Get the indexes, and make sure the PDP exists.
+        let pml4_idx = PML4::get_idx(page_addr);
+        let pdp_idx = PDP::get_idx(page_addr);
+        let pml4 = PML4::at(window().pml4_start);
+        if ! pml4[pml4_idx].is_present() {
+            return None;
+        }
+        
+        // Check if the entry maps a huge page.
+        let mut pdp = PDP::at(window().pdp_start | (pml4_idx << 12));
+        if ! pdp[pdp_idx].is_present() || ! pdp[pdp_idx].is_huge() {
+            return None;
+        }
+        
+        Some(&mut pdp[pdp_idx] as *mut pdp::PDPEntry)
+    }
+    
+    /// An internal helper which allocates a frame for a new table.
+    ///
+    /// # Returns
+    /// Ok(frame_addr) if it was allocated, Err otherwise.
+    unsafe fn alloc_table_frame() -> Result<PhysAddr, ()> {
+        use crate::mem::frame_alloc::{alloc, FrameAllocResult};
+        match alloc() {
+            FrameAllocResult::Ok(addr) => Ok(addr),
+            _ => Err(()),
+        }
+    }
+    
+    /// An internal helper which maps a scratch page to a given frame (replacing the old mapping).
+    ///
+    /// # Parameters
+    /// `scratch_addr` : The address of the scratch page.
+    /// `frame_addr` : The frame which should be accessible through the scratch page.
+    ///
+    /// # Returns
+    /// Ok if it was mapped, Err otherwise.
+    unsafe fn map_scratch(scratch_addr: usize, frame_addr: PhysAddr) -> Result<(), ()> {
+        PageTables::map(VirtAddr::new(scratch_addr)?, frame_addr, false, true, true)?;
+        super::tlb::invalidate_page(scratch_addr)
+    }
+    
+    /// An internal helper which unmaps a scratch page (the frame is not deallocated).
+    ///
+    /// # Parameters
+    /// `scratch_addr` : The address of the scratch page.
+    ///
+    /// # Returns
+    /// Ok if it was unmapped, Err otherwise.
+    unsafe fn unmap_scratch(scratch_addr: usize) -> Result<(), ()> {
+        PageTables::unmap(VirtAddr::new(scratch_addr)?)
+    }
+    
+    /// An internal helper which gives back the frames of a split which could not be completed.
+    ///
+    /// # Parameters
+    /// `pd` : The new PD (accessible using the scratch page).
+    /// `pd_frame` : The frame of the new PD.
+    unsafe fn abort_split(pd: &PD, pd_frame: PhysAddr) {
+        // Free every PT which was already created, and then the PD itself.
+        for pd_idx in 0..NUM_ENTRIES {
+            if pd[pd_idx].is_present() {
+                crate::mem::frame_alloc::dealloc(PhysAddr::new(pd[pd_idx].get_addr()));
+            }
+        }
+        
+        let _ = PageTables::unmap_scratch(SCRATCH_PT_ADDR);
+        let _ = PageTables::unmap_scratch(SCRATCH_PD_ADDR);
+        crate::mem::frame_alloc::dealloc(pd_frame);
+    }
 }
 
 
@@ -296,5 +981,195 @@ pub mod test {
         assert_eq!(core::mem::size_of::<super::pdp::PDPEntry>(), 8);
         assert_eq!(core::mem::size_of::<super::pd::PDEntry>(), 8);
         assert_eq!(core::mem::size_of::<super::pt::PTEntry>(), 8);
+        
+        test_canonical();
+        test_virt_to_phys_in();
+        test_choose_self_entry();
+        test_relocated_window();
+    }
+    
+    /// Simulate the used entries in a scratch PML4, and make sure the self-reference is only put in
+    /// an empty entry (or the one which already refers to the PML4).
+    fn test_choose_self_entry() {
+        use super::{choose_self_entry, PML4, NUM_ENTRIES};
+        use super::{DEFAULT_SELF_ENTRY_IDX, SCRATCH_ENTRY_IDX};
+        
+        let scratch = crate::mem::test::scratch(super::PAGE_SIZE);
+        let mut pml4 = PML4::new(scratch.addr);
+        let (pml4_addr, other_addr) = (0x1234000, 0x5678000);
+        assert_eq!(choose_self_entry(&pml4, pml4_addr), Some(DEFAULT_SELF_ENTRY_IDX));
+        
+        // A previous self-reference is kept.
+        pml4[DEFAULT_SELF_ENTRY_IDX].set_addr(pml4_addr);
+        pml4[DEFAULT_SELF_ENTRY_IDX].set_present(true);
+        assert_eq!(choose_self_entry(&pml4, pml4_addr), Some(DEFAULT_SELF_ENTRY_IDX));
+        
+        // A real mapping in the default entry moves it to the highest free one (not the scratch).
+        pml4[DEFAULT_SELF_ENTRY_IDX].set_addr(other_addr);
+        assert_eq!(SCRATCH_ENTRY_IDX, DEFAULT_SELF_ENTRY_IDX - 1);
+        assert_eq!(choose_self_entry(&pml4, pml4_addr), Some(DEFAULT_SELF_ENTRY_IDX - 2));
+        
+        // Nothing is chosen if the whole higher half is used.
+        for idx in NUM_ENTRIES / 2..NUM_ENTRIES {
+            pml4[idx].set_addr(other_addr);
+            pml4[idx].set_present(true);
+        }
+        assert_eq!(choose_self_entry(&pml4, pml4_addr), None);
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Add a second self-reference to the loaded PML4, switch to it's window, and make sure a page
+    /// can be unmapped, mapped, and translated through it.
+    fn test_relocated_window() {
+        use super::{PageTables, SelfRefWindow, PML4, NUM_ENTRIES, SCRATCH_ENTRY_IDX, PAGE_SIZE};
+        use crate::mem::addr::VirtAddr;
+        
+        unsafe {
+            let original = super::window();
+            let pml4_addr = PageTables::current().table_addr.unwrap();
+            let mut pml4 = PML4::at(original.pml4_start);
+            let idx = (NUM_ENTRIES / 2..NUM_ENTRIES).rev()
+                .find(|idx| *idx != SCRATCH_ENTRY_IDX && !pml4[*idx].is_present()).unwrap();
+            
+            // A page (with a known value) which is remapped.
+            let ptr = crate::mem::dyn_alloc::kmalloc(PAGE_SIZE, false, true, true) as *mut usize;
+            *ptr = 0xC0FFEE;
+            let page = VirtAddr::from_ptr(ptr as *const u8);
+            let frame = PageTables::virt_to_phys(page).unwrap();
+            
+            // Switch to the new window (the interrupts are disabled, so nothing else maps pages).
+            let were_enabled = crate::arch::interrupts::save_and_disable();
+            pml4[idx].set_addr(pml4_addr);
+            pml4[idx].set_present(true);
+            pml4[idx].set_writable(true);
+            super::WINDOW = SelfRefWindow::new(idx);
+            
+            let moved = super::window();
+            assert_ne!(moved.pt_start, original.pt_start);
+            assert!(moved.region().includes(moved.pml4_start));
+            assert!(!moved.region().includes(original.pml4_start));
+            assert_eq!(PML4::at(moved.pml4_start)[idx].get_addr(), pml4_addr);
+            
+            assert_eq!(PageTables::unmap(page), Ok(()));
+            assert!(PageTables::virt_to_phys(page).is_err());
+            assert_eq!(PageTables::map(page, frame, false, true, true), Ok(()));
+            assert_eq!(PageTables::virt_to_phys(page), Ok(frame));
+            assert_eq!(PageTables::current().virt_to_phys_in(page), Ok(frame));
+            assert_eq!(*ptr, 0xC0FFEE);
+            
+            // Switch back, and remove the second self-reference.
+            super::WINDOW = original;
+            pml4[idx] = super::pml_4::PML4Entry::new();
+            super::super::tlb::flush_all();
+            crate::arch::interrupts::restore(were_enabled);
+            
+            assert_eq!(PageTables::virt_to_phys(page), Ok(frame));
+            crate::mem::dyn_alloc::kfree(ptr as *mut u8);
+        }
+    }
+    
+    /// Build a second address space (which is never loaded) with a regular page and a huge page,
+    /// and make sure it's translations are found by following the physical addresses.
+    fn test_virt_to_phys_in() {
+        use super::{PageTables, FRAME_WINDOW_ADDR, HUGE_PAGE_SIZE, PAGE_SIZE};
+        use super::{PML4, PDP, PD, PT, pdp::PDPEntry};
+        use crate::mem::addr::{PhysAddr, VirtAddr};
+        
+        // A helper which allocates a table, and zeroes it through the window.
+        let new_table = || unsafe {
+            let frame = PageTables::alloc_table_frame().unwrap();
+            PageTables::map_scratch(FRAME_WINDOW_ADDR, frame).unwrap();
+            PT::new(FRAME_WINDOW_ADDR);
+            PageTables::unmap_scratch(FRAME_WINDOW_ADDR).unwrap();
+            frame
+        };
+        
+        // A helper which sets an entry in a table (these bits are the same in every level).
+        let set_entry = |table: PhysAddr, idx: usize, addr: PhysAddr, huge: bool| unsafe {
+            PageTables::map_scratch(FRAME_WINDOW_ADDR, table).unwrap();
+            let mut entry = PDPEntry::new();
+            entry.set_present(true);
+            entry.set_writable(true);
+            entry.set_huge(huge);
+            entry.set_addr(addr.as_usize());
+            PDP::at(FRAME_WINDOW_ADDR)[idx] = entry;
+            PageTables::unmap_scratch(FRAME_WINDOW_ADDR).unwrap();
+        };
+        
+        let page = VirtAddr::new(0x0000_7000_0020_3000).unwrap();
+        let huge = VirtAddr::new(0x0000_7000_8000_0000).unwrap();
+        let (page_raw, huge_raw) = (page.as_usize(), huge.as_usize());
+        let tables = [new_table(), new_table(), new_table(), new_table()];
+        let data = new_table();
+        
+        // PML4 -> PDP -> PD -> PT -> data, and a huge page in the same PDP.
+        set_entry(tables[0], PML4::get_idx(page_raw), tables[1], false);
+        set_entry(tables[1], PDP::get_idx(page_raw), tables[2], false);
+        set_entry(tables[2], PD::get_idx(page_raw), tables[3], false);
+        set_entry(tables[3], PT::get_idx(page_raw), data, false);
+        set_entry(tables[1], PDP::get_idx(huge_raw), PhysAddr::new(HUGE_PAGE_SIZE * 2), true);
+        
+        // The pages are only mapped in the second address space.
+        let space = PageTables::at(tables[0]);
+        assert_eq!(space.virt_to_phys_in(page + 0x123), Ok(data + 0x123));
+        assert_eq!(space.virt_to_phys_in(huge + 0x12345), 
+            Ok(PhysAddr::new(HUGE_PAGE_SIZE * 2 + 0x12345)));
+        assert!(space.virt_to_phys_in(page + PAGE_SIZE).is_err());
+        assert!(space.virt_to_phys_in(VirtAddr::new(0x1000).unwrap()).is_err());
+        assert!(PageTables::virt_to_phys(page).is_err());
+        
+        // The loaded page table gives the same results as the self-reference entry.
+        static MARKER: usize = 0xC0FFEE;
+        let marker = VirtAddr::from_ptr(&MARKER);
+        let frame = PageTables::virt_to_phys(marker).unwrap();
+        assert_eq!(PageTables::current().virt_to_phys_in(marker), Ok(frame));
+        assert!(PageTables::new().virt_to_phys_in(marker).is_err());
+        
+        unsafe {
+            // The frame window reads the frame itself, and it can't be nested.
+            let offset = frame.as_usize() % PAGE_SIZE;
+            let read = PageTables::with_frame(frame, |bytes| 
+                usize::from_ne_bytes(*(bytes[offset..].as_ptr() as *const [u8; 8])));
+            assert_eq!(read, Ok(0xC0FFEE));
+            assert_eq!(PageTables::with_frame(frame, |_| PageTables::with_frame(frame, |_| ())), 
+                Ok(Err(())));
+            assert!(PageTables::virt_to_phys(VirtAddr::new(FRAME_WINDOW_ADDR).unwrap()).is_err());
+            
+            for table in tables.iter().chain(core::iter::once(&data)) {
+                crate::mem::frame_alloc::dealloc(*table);
+            }
+        }
+    }
+    
+    /// Check the canonical addresses at the exact boundaries for 48-bit and (simulated) 57-bit 
+    /// widths.
+    fn test_canonical() {
+        use super::PageTables;
+        
+        // The 48-bit boundaries.
+        assert!(PageTables::is_canonical_for(0x0000_7FFF_FFFF_FFFF, 48));
+        assert!(!PageTables::is_canonical_for(0x0000_8000_0000_0000, 48));
+        assert!(!PageTables::is_canonical_for(0xFFFF_7FFF_FFFF_FFFF, 48));
+        assert!(PageTables::is_canonical_for(0xFFFF_8000_0000_0000, 48));
+        
+        // The 57-bit boundaries (which are not canonical with 48 bits).
+        assert!(PageTables::is_canonical_for(0x00FF_FFFF_FFFF_FFFF, 57));
+        assert!(!PageTables::is_canonical_for(0x0100_0000_0000_0000, 57));
+        assert!(!PageTables::is_canonical_for(0xFEFF_FFFF_FFFF_FFFF, 57));
+        assert!(PageTables::is_canonical_for(0xFF00_0000_0000_0000, 57));
+        assert!(!PageTables::is_canonical_for(0x00FF_FFFF_FFFF_FFFF, 48));
+        
+        // Both ends of the address space are canonical for every width.
+        for width in [48, 57].iter() {
+            assert!(PageTables::is_canonical_for(0, *width));
+            assert!(PageTables::is_canonical_for(!0, *width));
+        }
+        
+        // The default width is used by is_canonical, and the user/kernel split.
+        assert!(PageTables::is_canonical(crate::mem::MAX_USER_ADDR));
+        assert!(!PageTables::is_canonical(crate::mem::MAX_USER_ADDR + 1));
+        assert!(!PageTables::is_canonical(crate::mem::MIN_KERNEL_ADDR - 1));
+        assert!(PageTables::is_canonical(crate::mem::MIN_KERNEL_ADDR));
     }
 }
@@ -7,6 +7,7 @@
 #![allow(dead_code)]                        // To allow partial usage.
 
 use super::PD;
+use crate::mem::bitwise::BitWise;           // To allow bitwise operations.
 
 /// Define an entry for PDP and implement all the general functions for it using the defined macro
 /// in general_entry.
@@ -19,7 +20,7 @@ use super::PD;
 /// [4] - PCD - Page-level cache disable - 0 makes the table cacheable, 1 is not.
 /// [5] - A - Accessed - 1 if the page was used.
 /// [6] - IGN - Ignored.
-/// [7] - 0 - Always 0.
+/// [7] - PS - Page size - 0 points to a PD, 1 maps a 1GB page directly (if supported).
 /// [8] - IGN - Ignored.
 /// [9,11] - AVL - Available to use.
 /// [12,51] - ADR - Address - The physical address of the frame (the bits 0-11 are 0).
@@ -31,6 +32,25 @@ pub struct PDPEntry(usize);
 impl_general_entry!(PDPEntry);
 impl_make_table_if_not_present!(PDPEntry, PD);
 
+/// Implement additional functions which are applicable to this type of entry specifically.
+impl PDPEntry {
+    /// A method which determines if this entry maps a 1GB page (instead of pointing to a PD).
+    ///
+    /// # Returns
+    /// true if the page size bit is set, false otherwise.
+    pub fn is_huge(&self) -> bool {
+        self.0.is_set(7)
+    }
+    
+    /// A method which sets the page size bit, which makes this entry map a 1GB page directly.
+    ///
+    /// # Parameters
+    /// `is_huge` : True if it maps a 1GB page, false if it points to a PD.
+    pub fn set_huge(&mut self, is_huge: bool) {
+        self.0.write_bit(7, is_huge)
+    }
+}
+
 /// Define the PDP table with a virtual address and a certain number of entries.
 pub struct PDP {
     virt_addr: usize,
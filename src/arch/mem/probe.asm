@@ -0,0 +1,24 @@
+; A routine which writes to memory, and recovers if the write causes a page
+; fault (used to check the page protections without panicking).
+;
+; Author: Ardalan Ahanchi
+; Date: Mar 2021
+
+; The calling of these functions and the calling conventions are System V AMD64.
+
+global probe_write
+global probe_write_insn
+global probe_write_fixup
+
+; A routine which writes the byte in sil to the address in rdi. It returns 0
+; in rax if the write succeeded. If it faults, the page fault handler resumes
+; at probe_write_fixup (instead of the faulting instruction) which returns 1.
+probe_write:
+    xor rax, rax
+probe_write_insn:
+    mov byte [rdi], sil             ; The only instruction which can fault.
+    ret
+
+probe_write_fixup:
+    mov rax, 1
+    ret
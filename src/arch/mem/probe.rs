@@ -0,0 +1,75 @@
+//! A sub-module which allows probing the memory (writing to an address which might fault). It is
+//! used to check that the page protections are actually enforced. If the probe faults, the page 
+//! fault handler resumes after it (see fixup) instead of treating it as a real fault.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::arch::interrupts::handlers::context::Context;
+
+extern "sysv64" {
+    /// A function which writes a byte to a given address.
+    ///
+    /// # Parameters
+    /// `addr` : The address which we're writing to.
+    /// `value` : The value which is written.
+    ///
+    /// # Returns
+    /// 0 if the write succeeded, 1 if it faulted.
+    fn probe_write(addr: usize, value: u8) -> usize;
+    
+    /// The label of the instruction which can fault (not a real function).
+    fn probe_write_insn();
+    
+    /// The label where the execution is resumed after a fault (not a real function).
+    fn probe_write_fixup();
+}
+
+/// The number of faults which were caught while probing.
+static mut CAUGHT_FAULTS: usize = 0;
+
+/// A function which tries to write a byte to a given address.
+///
+/// # Parameters
+/// `addr` : The address which we're writing to.
+/// `value` : The value which is written.
+///
+/// # Returns
+/// Ok if it was written, Err if the write caused a page fault.
+pub unsafe fn try_write(addr: usize, value: u8) -> Result<(), ()> {
+    match probe_write(addr, value) {
+        0 => Ok(()),
+        _ => Err(()),
+    }
+}
+
+/// A function which is called by the page fault handler before the fault is handled. If the fault 
+/// was caused by a probe, it's reported and the execution is resumed at the fixup code.
+///
+/// # Parameters
+/// `info` : The context before the page fault happened.
+///
+/// # Returns
+/// True if the fault was caused by a probe (and it's handled), False otherwise.
+pub unsafe fn fixup(info: *mut Context) -> bool {
+    if (*info).rip != probe_write_insn as usize {
+        return false;
+    }
+    
+    CAUGHT_FAULTS += 1;
+    oxid_warn!("Caught a page fault while probing address 0x{:x}.", 
+        crate::arch::registers::get_cr2());
+    
+    (*info).rip = probe_write_fixup as usize;
+    true
+}
+
+/// A simple getter for the number of faults which were caught while probing.
+///
+/// # Returns
+/// The number of caught faults.
+pub fn caught_faults() -> usize {
+    unsafe { CAUGHT_FAULTS }
+}
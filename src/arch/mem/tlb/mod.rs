@@ -1,20 +1,135 @@
-//! A sub-module which provides TLB management. In rust, it is just a wrapper to allow access to 
-//! the assembly functions.
+//! A sub-module which provides TLB management. The assembly functions are wrapped so the addresses
+//! are validated before invlpg is executed (a non-canonical address can cause a #GP), and so large 
+//! ranges are handled by a full flush instead of thousands of single page invalidations.
 //! 
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Feb 2021.
 
 #![allow(dead_code)]
 
+use super::page_tables::PageTables;
+
+/// The alignment of the page addresses which can be invalidated (4K pages).
+const PAGE_ALIGNMENT: usize = 0x1000;
+
+/// The number of pages after which a full flush is cheaper than invalidating them one by one.
+pub const FLUSH_THRESHOLD: usize = 32;
+
+/// True if the next invalidation should be skipped (so the tests can leave a stale entry).
+#[cfg(feature = "unit-test")]
+static mut SKIP_NEXT: bool = false;
+
 extern "sysv64" {
     /// A function which flushes every entry in TLB. It simply sets the CR3 register to what it was 
     /// initially stored in it.
-    pub fn flush();
+    fn flush();
 
     /// A function which invalidates a specific address in TLB. It can be used in some situations 
     /// to avoid flushing every entry (much more efficient).
     ///
     /// # Parameters
     /// `page_addr` : The address of the page which we're invalidating in the TLB.
-    pub fn invalidate(page_addr: usize);    
+    fn invalidate(page_addr: usize);    
+}
+
+/// A function which flushes every entry in the TLB (except the global pages).
+pub unsafe fn flush_all() {
+    flush();
+}
+
+/// A function which invalidates a single page in the TLB. The address is checked before the 
+/// invlpg instruction is executed.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're invalidating in the TLB.
+///
+/// # Returns
+/// Ok if it was invalidated, Err if the address is not canonical or not page aligned.
+pub unsafe fn invalidate_page(page_addr: usize) -> Result<(), ()> {
+    if ! PageTables::is_canonical(page_addr) || page_addr % PAGE_ALIGNMENT != 0 {
+        return Err(());
+    }
+    
+    #[cfg(feature = "unit-test")]
+    if core::mem::replace(&mut SKIP_NEXT, false) {
+        return Ok(());
+    }
+    
+    invalidate(page_addr);
+    Ok(())
+}
+
+/// A function which makes the next invalidate_page do nothing (it still succeeds), so the tests 
+/// can check that a missing invalidation is detected (see debug::tlb_check).
+#[cfg(feature = "unit-test")]
+pub fn skip_next_invalidation() {
+    unsafe { SKIP_NEXT = true; }
+}
+
+/// A function which checks if a number of pages is large enough to be handled by a full flush.
+///
+/// # Parameters
+/// `num_pages` : The number of pages which were changed.
+///
+/// # Returns
+/// True if a full flush should be used, False if the pages should be invalidated one by one.
+#[inline]
+pub fn needs_flush(num_pages: usize) -> bool {
+    num_pages > FLUSH_THRESHOLD
+}
+
+/// A function which flushes the whole TLB if too many pages were changed to invalidate them one 
+/// by one. Callers which change ranges should skip the single page invalidations if it flushes.
+///
+/// # Parameters
+/// `pages_invalidated` : The number of pages which were changed.
+///
+/// # Returns
+/// True if the TLB was flushed, False otherwise.
+pub unsafe fn flush_if_needed(pages_invalidated: usize) -> bool {
+    if needs_flush(pages_invalidated) {
+        flush();
+        return true;
+    }
+    
+    false
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_invalidate_validation();
+        test_flush_threshold();
+    }
+    
+    /// Make sure non-canonical and unaligned addresses are rejected.
+    fn test_invalidate_validation() {
+        unsafe {
+            assert_eq!(super::invalidate_page(0x0000_8000_0000_0000), Err(()));
+            assert_eq!(super::invalidate_page(0xFFFF_7FFF_FFFF_F000), Err(()));
+            assert_eq!(super::invalidate_page(0x1001), Err(()));
+            
+            // Both halves of the canonical address space are accepted.
+            assert_eq!(super::invalidate_page(0x1000), Ok(()));
+            assert_eq!(super::invalidate_page(0xFFFF_8000_0000_0000), Ok(()));
+        }
+    }
+    
+    /// Check the threshold used to choose between single invalidations and a full flush.
+    fn test_flush_threshold() {
+        assert!(!super::needs_flush(1));
+        assert!(!super::needs_flush(super::FLUSH_THRESHOLD));
+        assert!(super::needs_flush(super::FLUSH_THRESHOLD + 1));
+        
+        unsafe {
+            assert!(!super::flush_if_needed(super::FLUSH_THRESHOLD));
+            assert!(super::flush_if_needed(super::FLUSH_THRESHOLD * 100));
+        }
+    }
 }
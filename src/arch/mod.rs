@@ -9,28 +9,154 @@ pub mod proc;
 pub mod interrupts;
 pub mod mem;
 pub mod registers;
+pub mod time;
+
+use registers::control::{Cr0Flags, Cr4Flags};
 
 /// A function which is called by the kernel main to intitialize the architecture specific code.
 /// It might call other modules to initialize themselves if needed.
 pub unsafe fn init() {
     oxid_log!("Initializing the architecture dependent code (x86_64).");
     
+    // Detect the CPU features, and make sure the paging matches the address width.
+    proc::cpu::init();
+    check_addr_width();
+    
+    // Enable the CPU features which the bootstrap code left alone.
+    enable_cpu_features();
+    
     // Initialize the PS2 keyboard.
     io::ps2_keyboard::init();
     
+    // Initialize the serial port (used for the statistics dumps).
+    io::serial::init();
+    
     // Initialize the processing code (TSS, etc.)
     proc::process::init();
 }
 
+/// A function which makes sure the hardware agrees with the virtual address width which the page
+/// tables are built for. It refuses to boot on a mismatch (rather than corrupting the mappings).
+pub unsafe fn check_addr_width() {
+    use mem::page_tables::VIRT_ADDR_WIDTH;
+    
+    // The active width depends on the paging mode (5-level paging uses 57 bits).
+    let active_width = if Cr4Flags::read().contains(Cr4Flags::LA57) { 57 } else { 48 };
+    let max_width = proc::cpu::features().linear_addr_width as u32;
+    
+    if active_width != VIRT_ADDR_WIDTH || max_width < VIRT_ADDR_WIDTH {
+        panic!("The address width ({} bits active, {} supported) does not match the page tables \
+            ({} bits).", active_width, max_width, VIRT_ADDR_WIDTH);
+    }
+}
+
+/// A function which enables the CPU features in the control registers (CR0 and CR4). Write 
+/// protection is always enabled (so the read-only pages are enforced in the kernel), and the rest
+/// are only enabled if cpuid reports them. Every decision is logged.
+pub unsafe fn enable_cpu_features() {
+    let mut cr0 = Cr0Flags::read();
+    let mut cr4 = Cr4Flags::read();
+    
+    // Enforce the read-only pages in ring 0 as well.
+    cr0.insert(Cr0Flags::WP);
+    oxid_log!("Enabling write protection in the kernel (CR0.WP).");
+    
+    // Keep the global pages in the TLB when CR3 changes.
+    if proc::cpu::has_pge() {
+        cr4.insert(Cr4Flags::PGE);
+        oxid_log!("Enabling global pages (CR4.PGE).");
+    } else {
+        oxid_warn!("Global pages are not supported by the CPU.");
+    }
+    
+    // Allow the SSE instructions (without emulating the FPU).
+    if proc::cpu::has_sse() {
+        cr0.remove(Cr0Flags::EM);
+        cr0.insert(Cr0Flags::MP);
+        cr4.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT);
+        oxid_log!("Enabling SSE (CR4.OSFXSR, CR4.OSXMMEXCPT, CR0.MP).");
+    } else {
+        oxid_warn!("SSE is not supported by the CPU.");
+    }
+    
+    // Prevent the kernel from executing or accessing the user pages.
+    if proc::cpu::has_smep() {
+        cr4.insert(Cr4Flags::SMEP);
+        oxid_log!("Enabling supervisor mode execution prevention (CR4.SMEP).");
+    } else {
+        oxid_log!("SMEP is not supported by the CPU, leaving it disabled.");
+    }
+    
+    if proc::cpu::has_smap() {
+        cr4.insert(Cr4Flags::SMAP);
+        oxid_log!("Enabling supervisor mode access prevention (CR4.SMAP).");
+    } else {
+        oxid_log!("SMAP is not supported by the CPU, leaving it disabled.");
+    }
+    
+    Cr0Flags::write(cr0);
+    Cr4Flags::write(cr4);
+}
+
 // Unit Tests **************************************************************************************
 
 /// The main test function which will call all the unit tests. This code will only be compiled
 /// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
 #[cfg(feature = "unit-test")]
 pub mod test {
+    use super::registers::control::{Cr0Flags, Cr4Flags};
+    
     /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
     /// sub module. 
     pub fn run() {
         super::mem::test::run();
+        super::time::test::run();
+        super::interrupts::vectors::test::run();
+        super::io::ata::test::run();
+        super::io::ps2_keyboard::test::run();
+        test_cpu_features();
+        test_write_protect();
+    }
+    
+    /// Make sure the features were enabled by init (the optional ones only if they are supported).
+    fn test_cpu_features() {
+        unsafe {
+            let cr0 = Cr0Flags::read();
+            let cr4 = Cr4Flags::read();
+            
+            assert!(cr0.contains(Cr0Flags::WP | Cr0Flags::PG));
+            assert!(cr4.contains(Cr4Flags::PAE));
+            assert_eq!(cr4.contains(Cr4Flags::PGE), super::proc::cpu::has_pge());
+            assert_eq!(cr4.contains(Cr4Flags::OSFXSR), super::proc::cpu::has_sse());
+            assert_eq!(cr4.contains(Cr4Flags::SMEP), super::proc::cpu::has_smep());
+            assert!(!cr4.contains(Cr4Flags::LA57));
+            assert!(super::proc::cpu::features().linear_addr_width as u32 
+                >= super::mem::page_tables::VIRT_ADDR_WIDTH);
+        }
+    }
+    
+    /// Make sure a kernel write to a read-only page faults (and the fault is caught by the probe).
+    fn test_write_protect() {
+        use super::mem::probe;
+        
+        // An unused address in the higher half.
+        const PAGE: usize = 0xFFFF_FE00_0000_0000;
+        let page = crate::mem::addr::VirtAddr::new(PAGE).unwrap();
+        
+        unsafe {
+            // A writable page can be written.
+            assert!(crate::mem::vmm::map(page, false, true, true).is_ok());
+            assert_eq!(probe::try_write(PAGE, 0xAB), Ok(()));
+            assert_eq!(*(PAGE as *const u8), 0xAB);
+            assert!(crate::mem::vmm::unmap(page).is_ok());
+            
+            // A read-only page can be read, but the write faults.
+            let caught = probe::caught_faults();
+            assert!(crate::mem::vmm::map(page, false, false, true).is_ok());
+            let _ = core::ptr::read_volatile(PAGE as *const u8);
+            assert_eq!(probe::try_write(PAGE, 0xCD), Err(()));
+            assert_eq!(probe::caught_faults(), caught + 1);
+            assert!(crate::mem::vmm::unmap(page).is_ok());
+        }
     }
 }
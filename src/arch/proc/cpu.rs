@@ -0,0 +1,253 @@
+//! A sub-module which identifies the CPU using the cpuid instruction (the vendor and brand strings,
+//! and the supported features).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use crate::olibc::bounded::BoundedString;
+
+/// The cpuid leaf which holds the basic feature bits.
+const FEATURES_LEAF: u32 = 0x1;
+
+/// The cpuid leaf which holds the structured extended feature bits.
+const EXT_STRUCT_FEATURES_LEAF: u32 = 0x7;
+
+/// The bit in EDX of the basic features which indicates that there is a local APIC.
+const APIC_BIT: u32 = 1 << 9;
+
+/// The bit in EDX of the basic features which indicates the support for global pages (pge).
+const PGE_BIT: u32 = 1 << 13;
+
+/// The bit in EDX of the basic features which indicates the support for fxsave and fxrstor.
+const FXSR_BIT: u32 = 1 << 24;
+
+/// The bit in EDX of the basic features which indicates the support for SSE.
+const SSE_BIT: u32 = 1 << 25;
+
+/// The bit in EBX of the structured extended features which indicates the support for SMEP.
+const SMEP_BIT: u32 = 1 << 7;
+
+/// The bit in EBX of the structured extended features which indicates the support for SMAP.
+const SMAP_BIT: u32 = 1 << 20;
+
+/// The extended cpuid leaf which returns the highest supported extended leaf.
+const EXT_MAX_LEAF: u32 = 0x80000000;
+
+/// The first of the three extended leaves which hold the brand string.
+const EXT_BRAND_LEAF: u32 = 0x80000002;
+
+/// The extended cpuid leaf which holds the extended feature bits.
+const EXT_FEATURES_LEAF: u32 = 0x80000001;
+
+/// The bit in EDX of the extended features which indicates the support for 1GB pages (pdpe1gb).
+const PDPE1GB_BIT: u32 = 1 << 26;
+
+/// The extended cpuid leaf which holds the physical and linear address widths.
+const EXT_ADDR_SIZES_LEAF: u32 = 0x80000008;
+
+/// The address widths which are assumed if the CPU doesn't report them.
+const DEFAULT_PHYS_ADDR_WIDTH: u8 = 36;
+const DEFAULT_LINEAR_ADDR_WIDTH: u8 = 48;
+
+/// A structure which holds the CPU properties which are detected once (during arch::init).
+#[derive(Copy, Clone, Debug)]
+pub struct CpuFeatures {
+    pub phys_addr_width: u8,            // The number of physical address bits.
+    pub linear_addr_width: u8,          // The maximum number of linear (virtual) address bits.
+}
+
+/// The features which were detected by init.
+static mut FEATURES: CpuFeatures = CpuFeatures {
+    phys_addr_width: DEFAULT_PHYS_ADDR_WIDTH,
+    linear_addr_width: DEFAULT_LINEAR_ADDR_WIDTH,
+};
+
+/// A function which detects the CPU features, and stores them (so they can be read with features).
+pub fn init() {
+    unsafe { FEATURES = detect(); }
+    oxid_log!("CPU address widths: {} bits physical, {} bits linear.", 
+        features().phys_addr_width, features().linear_addr_width);
+}
+
+/// A simple getter for the features which were detected by init.
+///
+/// # Returns
+/// The CPU features (the defaults if init wasn't called).
+pub fn features() -> CpuFeatures {
+    unsafe { FEATURES }
+}
+
+/// A function which detects the CPU features with cpuid.
+///
+/// # Returns
+/// The detected features (the address widths are the defaults if they're not reported).
+pub fn detect() -> CpuFeatures {
+    // Make sure the address sizes leaf is supported.
+    #[allow(unused_unsafe)]
+    if unsafe { __cpuid(EXT_MAX_LEAF) }.eax < EXT_ADDR_SIZES_LEAF {
+        return CpuFeatures {
+            phys_addr_width: DEFAULT_PHYS_ADDR_WIDTH,
+            linear_addr_width: DEFAULT_LINEAR_ADDR_WIDTH,
+        };
+    }
+    
+    // The physical width is in bits 0-7 of EAX, and the linear width is in bits 8-15.
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid(EXT_ADDR_SIZES_LEAF) };
+    CpuFeatures {
+        phys_addr_width: (result.eax & 0xFF) as u8,
+        linear_addr_width: ((result.eax >> 8) & 0xFF) as u8,
+    }
+}
+
+/// A function which reads the vendor string of the CPU (such as "GenuineIntel").
+///
+/// # Returns
+/// The 12 character vendor string.
+pub fn vendor() -> BoundedString<12> {
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid(0) };
+
+    // The vendor is stored in EBX, EDX, and ECX (in that order).
+    let mut vendor = BoundedString::new();
+    for reg in [result.ebx, result.edx, result.ecx].iter() {
+        push_bytes(&mut vendor, &reg.to_le_bytes());
+    }
+
+    vendor
+}
+
+/// A function which reads the brand string of the CPU (such as "Intel(R) Core(TM) ...").
+///
+/// # Returns
+/// Some(brand) if it's supported by the CPU, None otherwise.
+pub fn brand() -> Option<BoundedString<48>> {
+    // Make sure the brand leaves are supported.
+    #[allow(unused_unsafe)]
+    if unsafe { __cpuid(EXT_MAX_LEAF) }.eax < EXT_BRAND_LEAF + 2 {
+        return None;
+    }
+
+    // Each of the three leaves holds 16 characters in EAX, EBX, ECX, and EDX.
+    let mut brand: BoundedString<48> = BoundedString::new();
+    for leaf in EXT_BRAND_LEAF..(EXT_BRAND_LEAF + 3) {
+        #[allow(unused_unsafe)]
+        let result = unsafe { __cpuid(leaf) };
+        for reg in [result.eax, result.ebx, result.ecx, result.edx].iter() {
+            push_bytes(&mut brand, &reg.to_le_bytes());
+        }
+    }
+
+    // It's padded with spaces (and terminated with a null), so trim it.
+    let mut trimmed = BoundedString::new();
+    trimmed.push_str(brand.as_str().trim());
+    Some(trimmed)
+}
+
+/// A function which checks if the CPU supports mapping 1GB pages in the PDP (the pdpe1gb bit).
+///
+/// # Returns
+/// True if 1GB pages are supported, False otherwise.
+pub fn has_1g_pages() -> bool {
+    // Make sure the extended features leaf is supported.
+    #[allow(unused_unsafe)]
+    if unsafe { __cpuid(EXT_MAX_LEAF) }.eax < EXT_FEATURES_LEAF {
+        return false;
+    }
+
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid(EXT_FEATURES_LEAF) };
+    result.edx & PDPE1GB_BIT != 0
+}
+
+/// A function which checks if the CPU supports global pages (which stay in the TLB when CR3 changes).
+///
+/// # Returns
+/// True if global pages are supported, False otherwise.
+pub fn has_pge() -> bool {
+    features_edx() & PGE_BIT != 0
+}
+
+/// A function which checks if the CPU has a local APIC (so it can receive the interrupts which are
+/// routed by an I/O APIC).
+///
+/// # Returns
+/// True if there is a local APIC, False otherwise.
+pub fn has_apic() -> bool {
+    features_edx() & APIC_BIT != 0
+}
+
+/// A function which reads the ID of the local APIC of the running CPU (the initial ID, in bits
+/// 24-31 of EBX of the basic features).
+///
+/// # Returns
+/// The local APIC ID.
+pub fn apic_id() -> u8 {
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid(FEATURES_LEAF) };
+    (result.ebx >> 24) as u8
+}
+
+/// A function which checks if the CPU supports SSE (and the fxsave and fxrstor instructions).
+///
+/// # Returns
+/// True if SSE is supported, False otherwise.
+pub fn has_sse() -> bool {
+    features_edx() & (SSE_BIT | FXSR_BIT) == SSE_BIT | FXSR_BIT
+}
+
+/// A function which checks if the CPU supports supervisor mode execution prevention.
+///
+/// # Returns
+/// True if SMEP is supported, False otherwise.
+pub fn has_smep() -> bool {
+    ext_struct_features_ebx() & SMEP_BIT != 0
+}
+
+/// A function which checks if the CPU supports supervisor mode access prevention.
+///
+/// # Returns
+/// True if SMAP is supported, False otherwise.
+pub fn has_smap() -> bool {
+    ext_struct_features_ebx() & SMAP_BIT != 0
+}
+
+/// A helper which reads the basic feature bits in EDX.
+///
+/// # Returns
+/// The value of EDX for the basic features leaf.
+fn features_edx() -> u32 {
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid(FEATURES_LEAF) };
+    result.edx
+}
+
+/// A helper which reads the structured extended feature bits in EBX (the first sub-leaf).
+///
+/// # Returns
+/// The value of EBX for the structured extended features leaf (0 if it's not supported).
+fn ext_struct_features_ebx() -> u32 {
+    // Make sure the leaf is supported (the highest basic leaf is in EAX of leaf 0).
+    #[allow(unused_unsafe)]
+    if unsafe { __cpuid(0) }.eax < EXT_STRUCT_FEATURES_LEAF {
+        return 0;
+    }
+
+    #[allow(unused_unsafe)]
+    let result = unsafe { __cpuid_count(EXT_STRUCT_FEATURES_LEAF, 0) };
+    result.ebx
+}
+
+/// A helper which adds the printable ascii characters of a byte array to a string.
+///
+/// # Parameters
+/// `string` : The string which we're adding to.
+/// `bytes` : The bytes (ascii characters) which we're adding.
+fn push_bytes<const N: usize>(string: &mut BoundedString<N>, bytes: &[u8]) {
+    for byte in bytes.iter().filter(|byte| byte.is_ascii_graphic() || **byte == b' ') {
+        string.push(*byte as char);
+    }
+}
@@ -6,6 +6,7 @@
 ; The calling of these functions and the calling conventions are System V AMD64.
 global pause
 global halt
+global wait_for_interrupt
 
 ; A wrapper for the pause instruction which is used for busy waiting (makes it
 ; slightly more efficient accross cores).
@@ -17,3 +18,8 @@ pause:
 halt:
     hlt
     jmp halt
+
+; A wrapper for the hlt instruction which returns after the next interrupt.
+wait_for_interrupt:
+    hlt
+    ret
@@ -7,6 +7,7 @@
 
 pub mod sync;
 pub mod process;
+pub mod cpu;
 
 /// A group of signature definitions to allow calling assembly code from rust.
 /// it utilizes the System V AMD64 calling conventions to call the assembly code.
@@ -17,4 +18,7 @@ extern "sysv64" {
     
     /// A wrapper for the hlt instruction which simply puts the CPU in low power mode.
     pub fn halt();
+    
+    /// A wrapper for the hlt instruction which returns after the next interrupt is handled.
+    pub fn wait_for_interrupt();
 }
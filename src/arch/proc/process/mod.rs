@@ -10,6 +10,20 @@ pub mod scheduling;
 
 static mut CURR_TSS: tss::TSS = tss::TSS::new();
 
+/// The index of the interrupt stack which the double fault handler runs on.
+pub const DOUBLE_FAULT_IST: u8 = 1;
+
+/// The size of the double fault handler's stack (it's large enough to panic on).
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+
+/// The stack which the double fault handler runs on (the process stacks might have overflowed).
+static mut DOUBLE_FAULT_STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+
 pub unsafe fn init() {
+    // The stacks grow down, so the IST points to the end (aligned to 16 bytes).
+    let stack_top = (DOUBLE_FAULT_STACK.as_ptr() as usize + DOUBLE_FAULT_STACK_SIZE) & !0xF;
+    CURR_TSS.ist_1 = stack_top;
     CURR_TSS.load(0);
+    
+    crate::arch::interrupts::idt::IDT[0x8].set_ist(DOUBLE_FAULT_IST);
 }
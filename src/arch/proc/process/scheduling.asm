@@ -0,0 +1,17 @@
+; A basic stub which is used as the return address of the processes, so the
+; exit code which their main function returned can be passed on.
+;
+; Author: Ardalan Ahanchi
+; Date: March 2021
+
+; The calling of these functions and the calling conventions are System V AMD64.
+
+global program_return
+
+; A sub-routine which is returned to (never called) when the main function of a
+; process returns. The exit code is in rax, and the exit point was stored right
+; above this stub's address by init_context (see scheduling.rs), so it's passed
+; as the first argument and returned to.
+program_return:
+    mov rdi, rax                ; The exit code is the first argument.
+    ret                         ; Return to the exit point.
@@ -8,7 +8,7 @@
 
 use crate::arch::interrupts::{handlers, pic};
 use crate::arch::interrupts::handlers::context::Context;
-use crate::proc::process::Args;
+use crate::proc::process::{Args, MainFn};
 
 /// The IRQ number for the PIT timer in PIC (set initially by the system).
 const IRQ_NUM: u8 = 0;
@@ -16,6 +16,19 @@ const IRQ_NUM: u8 = 0;
 // The interrupt number based on the IRQ offset.
 const INT_NUM: u8 = IRQ_NUM + crate::arch::interrupts::IRQ_OFFSET;
 
+/// The interrupt flag in RFLAGS.
+const RFLAGS_IF: usize = 1 << 9;
+
+/// The bit in RFLAGS which is always set.
+const RFLAGS_RESERVED: usize = 1 << 1;
+
+// The signature definitions to allow calling assembly code from rust (in scheduling.asm).
+extern "sysv64" {
+    /// The return address of the processes (it's never called directly). It moves the returned 
+    /// exit code to the first argument, and returns to the exit point which is above it.
+    fn program_return();
+}
+
 /// A function which initializes the PS2 keyboard driver, it registers the handler for the keyboard,
 /// and enables the irq line for it.
 pub fn init() {
@@ -29,23 +42,30 @@ pub fn init() {
 }
 
 /// The low level scheduler which is directly called by the interrupt handler. It simply calls the 
-/// high-level scheduler to perform the task. And sends an EOI to the PIC.
+/// high-level scheduler to perform the task (the EOI is sent by the interrupt dispatch).
 ///
 /// # Parameters
 /// `context` : The passed context from the interrupt handling code.
 #[inline]
 unsafe fn schedule_process(context: *const Context) {
+    #[cfg(feature = "latency-stats")]
+    crate::debug::latency::switch_started();
+    
+    // Count the tick for the time keeping code, and the process which was running. The tick is also
+    // a scheduling point for the watchdog.
+    crate::proc::watchdog::tick();
+    crate::time::tick();
+    crate::proc::scheduler::account_tick();
+    
     // Call the high level handler with the context casted to a generic pointer.
     crate::proc::scheduler::schedule(context as *mut u8);
-    
-    // Send the EOI signal to the PIC.
-    pic::end_of_interrupt(IRQ_NUM);  
 }
 
 /// A function which initializes a context to point to a given function (starting_point). It  
 /// basically sets it's stack and starting point of the given function. Additionally, it sets the 
 /// exit point of the function (return instruction), and passes argc and argv to the function based
-/// on the sysv64 ABI.
+/// on the sysv64 ABI. The function returns to program_return, which passes it's return value (the
+/// exit code) to the exit point.
 ///
 /// # Parameters
 /// `starting_point` : The function pointer which will be executed by this context.
@@ -53,14 +73,18 @@ unsafe fn schedule_process(context: *const Context) {
 /// `stack_start` : The starting addrss (high_addr) of the stack for this context.
 /// `context_ptr` : The pointer to the context that we're initializing.
 /// `args` : Pointer to the arguments.
-pub unsafe fn init_context(starting_point: extern "sysv64" fn(*const Args), exit_point: fn()
+pub unsafe fn init_context(starting_point: MainFn, exit_point: extern "sysv64" fn(usize)
     , stack_start: *mut u8, context_ptr: *mut u8, args: *const Args) {
     // Store a cast version for readability.
     let context = context_ptr as *mut Context;
     
-    // Store the value of exit function's address, and then set the rsp to after it.
-    let new_stack_start = (stack_start as usize) - core::mem::size_of::<usize>();
-    *(new_stack_start as *mut usize) = exit_point as *const u8 as usize;
+    // Store the exit point (which program_return returns to) and program_return (which the 
+    // function returns to), and then set the rsp to after them. The slot above them keeps both
+    // entries aligned like a call would (16 bytes before the return address is pushed).
+    let word = core::mem::size_of::<usize>();
+    let new_stack_start = (stack_start as usize) - word * 3;
+    *((new_stack_start + word) as *mut usize) = exit_point as *const u8 as usize;
+    *(new_stack_start as *mut usize) = program_return as *const u8 as usize;
     (*context).orig_rsp = new_stack_start;
     
     // Set the intitial rip, and CS values to start at the correct instruction.
@@ -69,6 +93,9 @@ pub unsafe fn init_context(starting_point: extern "sysv64" fn(*const Args), exit
     
     // Set the RDI to args (first parameter based on sysv64 ABI).
     (*context).rdi = args as usize;
+    
+    // The programs always start with the interrupts enabled (or they could never be preempted).
+    (*context).rflags = RFLAGS_IF | RFLAGS_RESERVED;
 }
 
 /// A function which sets a new context in the destination. It basically copies everything 
@@ -81,11 +108,46 @@ pub unsafe fn set_context(dst: *mut u8, new: *mut u8) {
     // Store the rflags from the previous context.
     let prev_eflags = (*(dst as *mut Context)).rflags;
     
+    // Switching in a nested handler would resume the handler it interrupted on another process.
+    debug_assert_eq!(handlers::int_depth(), 1, "A context switch happened in a nested interrupt.");
+    
+    // A process which is entered with the interrupts disabled can't be preempted (and only the
+    // watchdog could take the CPU back from it).
+    debug_assert!(prev_eflags & RFLAGS_IF != 0, 
+        "A process is entered with the interrupts disabled.");
+    debug_assert!((*(new as *mut Context)).rflags & RFLAGS_IF != 0, 
+        "A process was saved with the interrupts disabled.");
+    
     // Override the context at the destination.
     crate::olibc::memcpy::memcpy(dst, new, context_size());
     
     // Restore it's rflags.
     (*(dst as *mut Context)).rflags = prev_eflags;
+    
+    #[cfg(feature = "latency-stats")]
+    crate::debug::latency::switch_done();
+}
+
+/// A function which resets an interrupted context (on the interrupt stack), so it continues in a
+/// given function on an empty stack, with the interrupts enabled. It's used to take the CPU back
+/// from a process which can't be preempted (see proc::watchdog).
+///
+/// # Parameters
+/// `context_ptr` : The interrupted context.
+/// `entry` : The function which is run instead (it should never return).
+/// `stack_start` : The starting address (high_addr) of the stack which it runs on.
+pub unsafe fn redirect_context(context_ptr: *mut u8, entry: extern "sysv64" fn(), 
+    stack_start: *mut u8) {
+    let context = context_ptr as *mut Context;
+    
+    // Leave an empty return address (like a call), so the stack is aligned as the function expects.
+    let new_stack_start = (stack_start as usize & !0xF) - core::mem::size_of::<usize>();
+    *(new_stack_start as *mut usize) = 0;
+    
+    (*context).orig_rsp = new_stack_start;
+    (*context).rip = entry as *const u8 as usize;
+    (*context).cs = crate::arch::registers::get_cs() as usize;
+    (*context).rflags |= RFLAGS_IF;
 }
 
 /// A constant accessor for the size of the contexts. Used for implementing architecture independent
@@ -0,0 +1,130 @@
+//! A sub-module which provides typed flags for the control registers (CR0 and CR4), so the CPU 
+//! features can be inspected and changed without using the raw bit numbers. The bits are explained
+//! at: https://wiki.osdev.org/CPU_Registers_x86-64#Control_Registers
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+/// A macro which implements the common methods of the control register flags. The first parameter 
+/// is the type, and the other two are the getter and setter of the register.
+macro_rules! impl_control_flags {
+    ($flags_type:ident, $getter_name:ident, $setter_name:ident) => {
+        impl $flags_type {
+            /// A function which reads the current value of the register.
+            ///
+            /// # Returns
+            /// The flags which are currently set.
+            pub unsafe fn read() -> $flags_type {
+                $flags_type(super::$getter_name())
+            }
+            
+            /// A function which writes the given flags to the register (all the other bits are 
+            /// cleared, so it should be based on what read returned).
+            ///
+            /// # Parameters
+            /// `flags` : The new value of the register.
+            pub unsafe fn write(flags: $flags_type) {
+                super::$setter_name(flags.0);
+            }
+            
+            /// A simple getter for the raw value of the register.
+            ///
+            /// # Returns
+            /// The bits of the flags.
+            #[inline]
+            pub fn bits(&self) -> usize {
+                self.0
+            }
+            
+            /// A method which checks if all the given flags are set.
+            ///
+            /// # Parameters
+            /// `other` : The flags which we're checking.
+            ///
+            /// # Returns
+            /// True if all of them are set, False otherwise.
+            #[inline]
+            pub fn contains(&self, other: $flags_type) -> bool {
+                self.0 & other.0 == other.0
+            }
+            
+            /// A method which sets the given flags.
+            ///
+            /// # Parameters
+            /// `other` : The flags which we're setting.
+            #[inline]
+            pub fn insert(&mut self, other: $flags_type) {
+                self.0 |= other.0;
+            }
+            
+            /// A method which clears the given flags.
+            ///
+            /// # Parameters
+            /// `other` : The flags which we're clearing.
+            #[inline]
+            pub fn remove(&mut self, other: $flags_type) {
+                self.0 &= !other.0;
+            }
+        }
+        
+        impl core::ops::BitOr for $flags_type {
+            type Output = $flags_type;
+            
+            fn bitor(self, other: $flags_type) -> $flags_type {
+                $flags_type(self.0 | other.0)
+            }
+        }
+    }
+}
+
+/// The flags of the CR0 register.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cr0Flags(usize);
+
+impl Cr0Flags {
+    /// Monitor co-processor (the wait instruction checks TS).
+    pub const MP: Cr0Flags = Cr0Flags(1 << 1);
+    
+    /// Emulation (the x87 and SSE instructions cause an exception when it's set).
+    pub const EM: Cr0Flags = Cr0Flags(1 << 2);
+    
+    /// Task switched (the FPU context is saved lazily).
+    pub const TS: Cr0Flags = Cr0Flags(1 << 3);
+    
+    /// Write protect (the read-only pages are also enforced in ring 0).
+    pub const WP: Cr0Flags = Cr0Flags(1 << 16);
+    
+    /// Paging is enabled (set by the bootstrap code).
+    pub const PG: Cr0Flags = Cr0Flags(1 << 31);
+}
+
+impl_control_flags!(Cr0Flags, get_cr0, set_cr0);
+
+/// The flags of the CR4 register.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cr4Flags(usize);
+
+impl Cr4Flags {
+    /// Physical address extension (set by the bootstrap code).
+    pub const PAE: Cr4Flags = Cr4Flags(1 << 5);
+    
+    /// Page global enable (the global pages are kept in the TLB when CR3 changes).
+    pub const PGE: Cr4Flags = Cr4Flags(1 << 7);
+    
+    /// The OS supports fxsave and fxrstor (enables the SSE instructions).
+    pub const OSFXSR: Cr4Flags = Cr4Flags(1 << 9);
+    
+    /// The OS handles the unmasked SIMD floating point exceptions.
+    pub const OSXMMEXCPT: Cr4Flags = Cr4Flags(1 << 10);
+    
+    /// 57-bit linear addresses (5-level paging is active).
+    pub const LA57: Cr4Flags = Cr4Flags(1 << 12);
+    
+    /// Supervisor mode execution prevention (the kernel can't execute user pages).
+    pub const SMEP: Cr4Flags = Cr4Flags(1 << 20);
+    
+    /// Supervisor mode access prevention (the kernel can't access user pages).
+    pub const SMAP: Cr4Flags = Cr4Flags(1 << 21);
+}
+
+impl_control_flags!(Cr4Flags, get_cr4, set_cr4);
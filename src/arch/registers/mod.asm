@@ -56,8 +56,10 @@ impl_accessors r14
 impl_accessors r15
 
 ; Implement accessors for control registers.
+impl_accessors cr0
 impl_accessors cr2
 impl_accessors cr3
+impl_accessors cr4
 
 ; Implement getters for the segment registers.
 impl_getter cs
@@ -10,6 +10,8 @@
 #[macro_use] 
 mod wrapper_macros;                     // A module to allow creating wrappers with one line.
 
+pub mod control;                        // Typed flags for the control registers (CR0 and CR4).
+
 
 // Define the registers here.
 
@@ -32,8 +34,10 @@ wrap_accessors!(get_r14, set_r14, usize);
 wrap_accessors!(get_r15, set_r15, usize);
 
 // Wrap the accessors for control registers (64 bits).
+wrap_accessors!(get_cr0, set_cr0, usize);
 wrap_accessors!(get_cr2, set_cr2, usize);
 wrap_accessors!(get_cr3, set_cr3, usize);
+wrap_accessors!(get_cr4, set_cr4, usize);
 
 // Wrap the getter for segment selectors (16 bits long).
 wrap_getter!(get_cs, u16);
@@ -0,0 +1,21 @@
+//! A module which includes the architecture dependent time sources. These are used by the high
+//! level time module to implement delays.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+pub mod pit;
+pub mod tsc;
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::pit::test::run();
+    }
+}
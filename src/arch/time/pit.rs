@@ -0,0 +1,146 @@
+//! A sub-module which uses the channel 2 of the programmable interval timer (8253/8254 PIT) for 
+//! short delays. Channel 2 is normally connected to the PC speaker, but it's output can be polled
+//! through port 0x61, so it can be used without interrupts (and before anything is calibrated).
+//! More details can be found at: https://wiki.osdev.org/Programmable_Interval_Timer
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::arch::interrupts::pic::{in_b, out_b};
+
+/// The frequency of the PIT's input clock (in Hz).
+pub const PIT_FREQ: usize = 1193182;
+
+const CHANNEL_2_DATA: u16 = 0x42;            // The data port for channel 2.
+const COMMAND: u16 = 0x43;                   // The mode/command port.
+const SPEAKER_CTRL: u16 = 0x61;              // The port for the gate and the output of channel 2.
+
+const GATE_BIT: u8 = 0b00000001;             // Gate input of channel 2 (counting enabled).
+const SPEAKER_BIT: u8 = 0b00000010;          // Connects the channel 2 output to the speaker.
+const OUT_BIT: u8 = 0b00100000;              // The output of channel 2 (set at terminal count).
+
+const CMD_ONE_SHOT: u8 = 0b10110000;         // Channel 2, lobyte/hibyte, mode 0, binary.
+const CMD_LATCH: u8 = 0b10000000;            // Latch the count of channel 2.
+
+/// The largest count which fits in the 16-bit counter.
+const MAX_COUNT: usize = 0xFFFF;
+
+/// The number of polls after which the PIT is considered missing (much longer than MAX_COUNT).
+const MAX_POLLS: usize = 0x1000000;
+
+/// A function which converts a number of microseconds to the number of PIT ticks (rounded up).
+///
+/// # Parameters
+/// `us` : The number of microseconds.
+///
+/// # Returns
+/// The number of PIT ticks which take at least that long.
+#[inline]
+pub fn us_to_ticks(us: usize) -> usize {
+    (us * PIT_FREQ + 999_999) / 1_000_000
+}
+
+/// A function which converts a number of PIT ticks to microseconds (rounded down).
+///
+/// # Parameters
+/// `ticks` : The number of PIT ticks.
+///
+/// # Returns
+/// The number of microseconds which have passed.
+#[inline]
+pub fn ticks_to_us(ticks: usize) -> usize {
+    ticks * 1_000_000 / PIT_FREQ
+}
+
+/// A function which starts a one-shot countdown on channel 2 with the speaker disconnected. The
+/// output bit will be set once the count reaches zero.
+///
+/// # Parameters
+/// `count` : The number of ticks (should be at most MAX_COUNT).
+pub unsafe fn start(count: u16) {
+    // Disable the gate (and the speaker) while programming the counter.
+    let ctrl = in_b(SPEAKER_CTRL) & !(SPEAKER_BIT | GATE_BIT);
+    out_b(SPEAKER_CTRL, ctrl);
+    
+    // Set the mode, and write the count (low byte first).
+    out_b(COMMAND, CMD_ONE_SHOT);
+    out_b(CHANNEL_2_DATA, (count & 0xFF) as u8);
+    out_b(CHANNEL_2_DATA, (count >> 8) as u8);
+    
+    // Raise the gate to start counting.
+    out_b(SPEAKER_CTRL, ctrl | GATE_BIT);
+}
+
+/// A function which reads the current count of channel 2 (started by start()).
+///
+/// # Returns
+/// The number of ticks which are left in the countdown.
+pub unsafe fn read_count() -> u16 {
+    // Latch the count, and read it (low byte first).
+    out_b(COMMAND, CMD_LATCH);
+    let low = in_b(CHANNEL_2_DATA) as u16;
+    let high = in_b(CHANNEL_2_DATA) as u16;
+    
+    (high << 8) | low
+}
+
+/// A function which polls the output of channel 2 until the countdown is finished.
+///
+/// # Returns
+/// Ok if the countdown finished, Err if it never did (no PIT).
+pub unsafe fn wait() -> Result<(), ()> {
+    for _ in 0..MAX_POLLS {
+        if in_b(SPEAKER_CTRL) & OUT_BIT != 0 {
+            return Ok(());
+        }
+    }
+    
+    Err(())
+}
+
+/// A function which busy waits for a number of microseconds by polling channel 2. Longer delays 
+/// are split into multiple countdowns since the counter is only 16 bits.
+///
+/// # Parameters
+/// `us` : The number of microseconds to wait.
+///
+/// # Returns
+/// Ok if the delay was performed, Err if the PIT did not respond.
+pub unsafe fn delay_us(us: usize) -> Result<(), ()> {
+    let mut ticks_left = us_to_ticks(us);
+    
+    // Do the countdowns one chunk at a time.
+    while ticks_left > 0 {
+        let count = core::cmp::min(ticks_left, MAX_COUNT);
+        start(count as u16);
+        wait()?;
+        ticks_left -= count;
+    }
+    
+    Ok(())
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_conversions();
+    }
+    
+    /// Check the conversion between microseconds and ticks.
+    fn test_conversions() {
+        assert_eq!(super::us_to_ticks(0), 0);
+        assert_eq!(super::us_to_ticks(1), 2);
+        assert_eq!(super::us_to_ticks(1000), 1194);
+        assert_eq!(super::us_to_ticks(1_000_000), super::PIT_FREQ);
+        assert_eq!(super::ticks_to_us(super::PIT_FREQ), 1_000_000);
+        assert_eq!(super::ticks_to_us(1193), 999);
+    }
+}
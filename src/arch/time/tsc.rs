@@ -0,0 +1,29 @@
+//! A sub-module which provides access to the time stamp counter (TSC). It is a 64-bit counter 
+//! which is incremented at a fixed rate on modern CPUs, so once it's calibrated against a known
+//! clock (the PIT), it can be used for precise short delays.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// The bit in the EDX register (CPUID leaf 1) which is set if the TSC is supported.
+const CPUID_TSC_BIT: u32 = 1 << 4;
+
+/// A function which checks if the CPU supports the time stamp counter.
+///
+/// # Returns
+/// True if the rdtsc instruction can be used, False otherwise.
+pub fn is_supported() -> bool {
+    #[allow(unused_unsafe)]
+    unsafe { core::arch::x86_64::__cpuid(1).edx & CPUID_TSC_BIT != 0 }
+}
+
+/// A function which reads the current value of the time stamp counter.
+///
+/// # Returns
+/// The number of cycles counted by the TSC.
+#[inline(always)]
+pub fn read() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
@@ -3,6 +3,16 @@
 //! logging, warnining, and error messages. The allows various levels of messages to be printed 
 //! to the writer (with appropriate colors and header messages).
 //!
+//! The regular output (print and println) of a process is throttled, so a program which prints as
+//! fast as it can (such as yes) doesn't keep the CPU from everything else. Each process has an 
+//! output credit which is refilled on every tick, and once it's used up, the process gives up it's
+//! time slice until it's refilled. If it can't block (the interrupts are disabled), the output is
+//! dropped and counted instead. The log messages are never throttled, and the copies in the kernel
+//! log are already capped for each process (see debug::klog).
+//!
+//! The driver of the console can be replaced while it's running (see reinit), for example when the
+//! screen mode is changed. The writer is kept, and the old screen is replayed on the new one.
+//!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Jan 2021
 
@@ -14,8 +24,10 @@
 #![allow(unused_macros)]
 #![macro_use]
 
+use core::fmt;
 use crate::arch::io::textmode::TextMode;
 use crate::io::textmode::{driver::Driver, writer::Writer, color::Color};
+use crate::io::textmode::buffer::BufferDriver;
 
 pub const BG_COLOR: Color = Color::Black;               // The background color used.
 pub const TEXT_COLOR: Color = Color::Gray;              // The default text color.
@@ -23,25 +35,113 @@ pub const LOG_COLOR: Color = Color::Green;              // The color for the log
 pub const WARN_COLOR: Color = Color::Yellow;            // The color for the warning messages.
 pub const ERR_COLOR: Color = Color::Red;                // The color for the error messages.
 
+/// The number of bytes a process can print on every timer tick (about two screens), once it used 
+/// up it's burst. A faster producer is throttled to this rate.
+pub const OUTPUT_BYTES_PER_TICK: usize = 4000;
+
+/// The most output credit a process can save up (so the programs which print a lot at once, such 
+/// as help, are not throttled).
+pub const OUTPUT_BURST: usize = 16384;
+
 /// A static console which we can use to write globally.
-// pub static mut CONSOLE: Option<Writer<TextMode>> = None;
-pub static mut CONSOLE: Option<Writer<TextMode>> = None;
+pub static mut CONSOLE: Option<Writer<ConsoleDriver>> = None;
+
+/// An enum which represents the drivers which the console can use (so the driver can be replaced
+/// without changing the type of the console).
+pub enum ConsoleDriver {
+    Text(TextMode),                                     // The text mode screen of the architecture.
+    Buffer(BufferDriver),                               // A buffer in memory (of any size).
+}
+
+/// The number of times a process had to wait for it's output credit.
+static mut THROTTLED: usize = 0;
+
+/// The number of writes which were dropped, since the process was out of credit and could not wait.
+static mut DROPPED: usize = 0;
 
 /// A function which initializes the console which is statically available. It allows global writes
 /// to the console. It initializes the driver which is used, and a writer, and stores it in CONSOLE.
 pub unsafe fn init() {
     let tm_driver_x86: TextMode = TextMode::new_default();              // Initialize the driver.
-    let tm_writer: Writer<TextMode> = Writer::new(tm_driver_x86);       // Initialize the writer.
+    let tm_writer = Writer::new(ConsoleDriver::Text(tm_driver_x86));    // Initialize the writer.
     CONSOLE = Some(tm_writer);                                          // Store the global console.
 }
 
+/// Forward all the calls to the driver which is being used.
+impl Driver for ConsoleDriver {
+    fn new(rows: usize, cols: usize) -> Self {
+        ConsoleDriver::Text(TextMode::new(rows, cols))
+    }
+
+    fn new_default() -> Self {
+        ConsoleDriver::Text(TextMode::new_default())
+    }
+
+    fn get_rows(&self) -> usize {
+        match self {
+            ConsoleDriver::Text(driver) => driver.get_rows(),
+            ConsoleDriver::Buffer(driver) => driver.get_rows(),
+        }
+    }
+
+    fn get_cols(&self) -> usize {
+        match self {
+            ConsoleDriver::Text(driver) => driver.get_cols(),
+            ConsoleDriver::Buffer(driver) => driver.get_cols(),
+        }
+    }
+
+    unsafe fn set_cell(&mut self, character: u8, fg: Color, bg: Color, row: usize, col: usize) {
+        match self {
+            ConsoleDriver::Text(driver) => driver.set_cell(character, fg, bg, row, col),
+            ConsoleDriver::Buffer(driver) => driver.set_cell(character, fg, bg, row, col),
+        }
+    }
+
+    unsafe fn clear_cell(&mut self, row: usize, col: usize) {
+        match self {
+            ConsoleDriver::Text(driver) => driver.clear_cell(row, col),
+            ConsoleDriver::Buffer(driver) => driver.clear_cell(row, col),
+        }
+    }
+
+    unsafe fn copy_cell(&mut self, src_row: usize, src_col: usize, dst_row: usize, dst_col: usize) {
+        match self {
+            ConsoleDriver::Text(driver) => driver.copy_cell(src_row, src_col, dst_row, dst_col),
+            ConsoleDriver::Buffer(driver) => driver.copy_cell(src_row, src_col, dst_row, dst_col),
+        }
+    }
+
+    unsafe fn get_char(&mut self, row: usize, col: usize) -> u8 {
+        match self {
+            ConsoleDriver::Text(driver) => driver.get_char(row, col),
+            ConsoleDriver::Buffer(driver) => driver.get_char(row, col),
+        }
+    }
+
+    unsafe fn get_fg(&mut self, row: usize, col: usize) -> Color {
+        match self {
+            ConsoleDriver::Text(driver) => driver.get_fg(row, col),
+            ConsoleDriver::Buffer(driver) => driver.get_fg(row, col),
+        }
+    }
+
+    unsafe fn get_bg(&mut self, row: usize, col: usize) -> Color {
+        match self {
+            ConsoleDriver::Text(driver) => driver.get_bg(row, col),
+            ConsoleDriver::Buffer(driver) => driver.get_bg(row, col),
+        }
+    }
+}
+
 /// A macro which performs a regular print without needing a newline. It can accepts all kinds of 
 /// inputs (as long as they are tt's). The pattern matching was inspired rom the rust std library's
 /// implementation of print! macro. Which can be found at:
 /// https://doc.rust-lang.org/src/std/macros.rs.html#92-97
 macro_rules! oxid_print {
     ($($arg:tt)*) => ({
-        oxid_print_colored_nl!(crate::console::TEXT_COLOR, crate::console::BG_COLOR, false, $($arg)*);
+        #[allow(unused_unsafe)]                              // The arguments might be unsafe.
+        unsafe { crate::console::print_output(format_args!($($arg)*), false); }
     });
 }
 
@@ -54,7 +154,8 @@ macro_rules! oxid_println {
     () => (oxid_print!("\n"));
 
     ($($arg:tt)*) => ({
-        oxid_print_colored_nl!(crate::console::TEXT_COLOR, crate::console::BG_COLOR, true, $($arg)*);
+        #[allow(unused_unsafe)]                              // The arguments might be unsafe.
+        unsafe { crate::console::print_output(format_args!($($arg)*), true); }
     })
 }
 
@@ -64,9 +165,9 @@ macro_rules! oxid_println {
 /// Which can be found at: https://doc.rust-lang.org/src/std/macros.rs.html#92-97
 macro_rules! oxid_log {
     ($($arg:tt)*) => ({
-        // Print the log header (without a newline), and then print the message.
-        oxid_print_colored_nl!(crate::console::LOG_COLOR, crate::console::BG_COLOR, false, "Oxid: Log: ");
-        oxid_print_colored_nl!(crate::console::LOG_COLOR, crate::console::BG_COLOR, true, $($arg)*);
+        // Print the log header and the message together (so the line is never split).
+        oxid_print_colored_nl!(crate::console::LOG_COLOR, crate::console::BG_COLOR, true, 
+            "Oxid: Log: {}", format_args!($($arg)*));
     });
 }
 
@@ -76,9 +177,9 @@ macro_rules! oxid_log {
 /// Which can be found at: https://doc.rust-lang.org/src/std/macros.rs.html#92-97
 macro_rules! oxid_warn {
     ($($arg:tt)*) => ({
-        // Print the warn header (without a newline), and then print the message.
-        oxid_print_colored_nl!(crate::console::WARN_COLOR, crate::console::BG_COLOR, false, "Oxid: Warn: ");
-        oxid_print_colored_nl!(crate::console::WARN_COLOR, crate::console::BG_COLOR, true, $($arg)*);
+        // Print the warn header and the message together (so the line is never split).
+        oxid_print_colored_nl!(crate::console::WARN_COLOR, crate::console::BG_COLOR, true, 
+            "Oxid: Warn: {}", format_args!($($arg)*));
     });
 }
 
@@ -88,15 +189,36 @@ macro_rules! oxid_warn {
 /// Which can be found at: https://doc.rust-lang.org/src/std/macros.rs.html#92-97
 macro_rules! oxid_err {
     ($($arg:tt)*) => ({
-        // Print the err header (without a newline), and then print the message.
-        oxid_print_colored_nl!(crate::console::ERR_COLOR, crate::console::BG_COLOR, false, "Oxid: Err: ");
-        oxid_print_colored_nl!(crate::console::ERR_COLOR, crate::console::BG_COLOR, true, $($arg)*);
+        // Print the err header and the message together (so the line is never split).
+        oxid_print_colored_nl!(crate::console::ERR_COLOR, crate::console::BG_COLOR, true, 
+            "Oxid: Err: {}", format_args!($($arg)*));
+    });
+}
+
+/// A macro which logs a trace message for a given subsystem (a variant of debug::trace::Subsystem).
+/// It is only compiled in when the trace feature is enabled, and it only logs if the subsystem 
+/// was turned on at runtime (from the command line or the trace program). The messages are not 
+/// printed, they're kept in the kernel log at the trace level (see `trace show`). The header 
+/// includes the subsystem's name so the messages can be told apart.
+macro_rules! oxid_dbg {
+    ($subsys:ident, $($arg:tt)*) => ({
+        #[cfg(feature = "trace")]
+        {
+            // Only log if the subsystem is currently being traced.
+            let subsys = crate::debug::trace::Subsystem::$subsys;
+            if crate::debug::trace::is_enabled(subsys) {
+                crate::debug::klog::trace(format_args!("Oxid: Dbg: {}: {}", subsys.name(), 
+                    format_args!($($arg)*)));
+            }
+        }
     });
 }
 
 /// The main backbone behind all the implemented macros for formatted printing in oxid os. It allows
 /// colored printing (specified foreground and backgroun colors), and allows adding a newline at the
 /// end of the printing if requested. It is used to merge all the sensitive code into one macro.
+/// The colors are passed with the write (the writer's current colors are not changed), and the 
+/// writer is locked for the whole write, so the text and it's color are never mixed up.
 ///
 /// # Required Parameters
 /// `fg` : The color for the foreground (should be of type Color).
@@ -109,16 +231,8 @@ macro_rules! oxid_print_colored_nl {
         unsafe {  
             match &mut crate::console::CONSOLE {             // Check if the console is initialized.
                 Some(w) => {
-                    #[allow(unused_imports)]
-                    use core::fmt::Write;
-                    
-                    // Set the color to the passed text colors (in case something changed it).
-                    w.set_colors($fg, $bg);
-                    w.write_fmt(format_args!($($arg)*)).unwrap();           // Write fmt to console.
-                    
-                    if $add_nl {                              // If a newline was requested, add it.
-                        w.print("\n");
-                    }
+                    // Write fmt to console (with a newline if it was requested).
+                    w.print_fmt_colored(format_args!($($arg)*), $fg, $bg, $add_nl);
                 },
 
                 None => panic!("Console not initialized"),
@@ -127,3 +241,212 @@ macro_rules! oxid_print_colored_nl {
     })
 }
 
+/// The function which prints the regular output (used by oxid_print and oxid_println) in the 
+/// default colors. The output is charged to the running process, and it waits for it's credit to
+/// be refilled if it's used up (see the module's description).
+///
+/// # Parameters
+/// `args` : The formatted arguments (created by format_args!).
+/// `add_nl` : If true, a newline is added at the end.
+pub fn print_output(args: fmt::Arguments, add_nl: bool) {
+    if !crate::proc::scheduler::has_output_credit() {
+        if unsafe { !crate::arch::interrupts::are_enabled() } {
+            unsafe { DROPPED += 1; }
+            return;
+        }
+        
+        unsafe {
+            let were_enabled = crate::arch::interrupts::save_and_disable();
+            THROTTLED += 1;
+            crate::arch::interrupts::restore(were_enabled);
+        }
+        
+        while !crate::proc::scheduler::has_output_credit() {
+            crate::proc::scheduler::yield_now();
+        }
+    }
+    
+    let written = match unsafe { &mut CONSOLE } {
+        Some(writer) => writer.print_fmt_colored(args, TEXT_COLOR, BG_COLOR, add_nl),
+        None => panic!("Console not initialized"),
+    };
+    crate::proc::scheduler::charge_output(written);
+}
+
+/// A simple getter for the number of times a process had to wait for it's output credit.
+///
+/// # Returns
+/// The number of throttled writes.
+pub fn throttled() -> usize {
+    unsafe { THROTTLED }
+}
+
+/// A simple getter for the number of writes which were dropped (they could not wait for credit).
+///
+/// # Returns
+/// The number of dropped writes.
+pub fn dropped() -> usize {
+    unsafe { DROPPED }
+}
+
+/// A function which replaces the driver of the console (for example, after the screen mode was 
+/// changed). The write which is in progress is finished first (the writer is locked), and the rows
+/// of the old screen are replayed on the new one, so nothing which was on the screen is lost. The
+/// users of the console read it's size on every use, so nothing else has to be updated.
+///
+/// # Parameters
+/// `driver` : The driver which is used from now on.
+///
+/// # Returns
+/// Some(old_driver), or None if the console was not initialized (then it's initialized with it).
+pub unsafe fn reinit(driver: ConsoleDriver) -> Option<ConsoleDriver> {
+    match &mut CONSOLE {
+        Some(writer) => {
+            let old_driver = writer.replace_driver(driver);
+            let (rows, cols) = writer.get_size();
+            oxid_log!("Switched the console to a {}x{} screen.", cols, rows);
+            Some(old_driver)
+        },
+        
+        None => {
+            CONSOLE = Some(Writer::new(driver));
+            None
+        },
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::io::textmode::color::Color;
+    use crate::proc::process::Args;
+
+    /// The number of lines printed by each of the test processes.
+    const LINES: usize = 8;
+
+    /// The characters and colors used by each of the test processes (unlikely to be on the screen).
+    const FIRST: (&str, Color) = ("\x01\x01\x01\x01\x01\x01\x01\x01", Color::LightRed);
+    const SECOND: (&str, Color) = ("\x02\x02\x02\x02\x02\x02\x02\x02", Color::LightBlue);
+
+    /// The number of test processes which are done printing.
+    static mut DONE: usize = 0;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_concurrent_colors();
+        test_reinit();
+    }
+
+    /// The first test process, which prints it's lines in it's color.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn print_first(_args: *const Args) -> usize {
+        for _ in 0..LINES {
+            oxid_print_colored_nl!(FIRST.1, super::BG_COLOR, true, "{}", FIRST.0);
+        }
+        unsafe { DONE += 1; }
+        0
+    }
+
+    /// The second test process, which prints it's lines in it's color (with plain text between).
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn print_second(_args: *const Args) -> usize {
+        for _ in 0..LINES {
+            oxid_print_colored_nl!(SECOND.1, super::BG_COLOR, true, "{}", SECOND.0);
+            oxid_println!("{}", "plain");
+        }
+        unsafe { DONE += 1; }
+        0
+    }
+
+    /// Print from two processes in different colors at the same time, and make sure every cell on
+    /// the screen has the color of the text which was written there.
+    fn test_concurrent_colors() {
+        unsafe {
+            DONE = 0;
+
+            let mut args = Args::new();
+            crate::proc::scheduler::spawn(print_first, &mut args as *mut Args, "color_a");
+            crate::proc::scheduler::spawn(print_second, &mut args as *mut Args, "color_b");
+
+            // Wait (for up to a second) for both of them to finish.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+            while DONE < 2 && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert_eq!(DONE, 2);
+
+            // Go through the whole screen and check the colors of the test characters.
+            let console = super::CONSOLE.as_mut().unwrap();
+            let (rows, cols) = console.get_size();
+            for row in 0..rows {
+                for col in 0..cols {
+                    match console.get_cell(row, col) {
+                        (0x01, fg, _) => assert!(fg == FIRST.1),
+                        (0x02, fg, _) => assert!(fg == SECOND.1),
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+    
+    /// A helper which counts the cells on the console which have a character in a color.
+    ///
+    /// # Parameters
+    /// `character` : The character to look for.
+    /// `color` : The foreground color it should have.
+    ///
+    /// # Returns
+    /// The number of cells which match.
+    fn count_cells(character: u8, color: Color) -> usize {
+        let console = unsafe { super::CONSOLE.as_mut().unwrap() };
+        let (rows, cols) = console.get_size();
+        (0..rows).flat_map(|row| (0..cols).map(move |col| (row, col)))
+            .filter(|(row, col)| { 
+                let (found, fg, _) = console.get_cell(*row, *col);
+                found == character && fg == color
+            }).count()
+    }
+
+    /// Switch the console to a smaller buffer in memory and back, and make sure the screen is
+    /// replayed both times (in it's colors), and the new output stays in the smaller bounds.
+    fn test_reinit() {
+        use crate::io::textmode::{buffer::BufferDriver, driver::Driver};
+        use super::ConsoleDriver;
+
+        const MARKER: &str = "\x03\x03\x03\x03";
+        const WIDE: usize = 100;
+
+        unsafe {
+            oxid_print_colored_nl!(Color::LightPurple, super::BG_COLOR, true, "{}", MARKER);
+            let old = super::reinit(ConsoleDriver::Buffer(BufferDriver::new(12, 40))).unwrap();
+            assert_eq!(super::CONSOLE.as_mut().unwrap().get_size(), (12, 40));
+            assert_eq!(count_cells(0x03, Color::LightPurple), MARKER.len());
+
+            // A line which is wider than the buffer wraps (it would panic if it was out of bounds).
+            let mut wide = crate::olibc::bounded::BoundedString::<WIDE>::new();
+            for _ in 0..WIDE {
+                wide.push('\x04');
+            }
+            oxid_print_colored_nl!(Color::LightGreen, super::BG_COLOR, true, "{}", wide.as_str());
+            assert_eq!(count_cells(0x04, Color::LightGreen), WIDE);
+
+            // Switch back, the buffer's content is replayed on the screen.
+            match super::reinit(old) {
+                Some(ConsoleDriver::Buffer(buffer)) => assert_eq!(buffer.get_rows(), 12),
+                _ => panic!("The buffer driver was not returned."),
+            }
+            assert_eq!(super::CONSOLE.as_mut().unwrap().get_size(), (25, 80));
+            assert_eq!(count_cells(0x03, Color::LightPurple), MARKER.len());
+            assert_eq!(count_cells(0x04, Color::LightGreen), WIDE);
+        }
+    }
+}
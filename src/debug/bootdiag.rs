@@ -0,0 +1,226 @@
+//! A sub-module which collects the boot diagnostics: the boot device which the boot loader loaded
+//! the kernel from, the reason for the last reset (from the CMOS shutdown status and the BIOS warm
+//! boot flag), and if the previous shutdown was clean. A marker is kept in a CMOS scratch register
+//! which is set while the kernel is running, and replaced when it shuts down (or reboots) cleanly,
+//! so the next boot can tell if the previous one crashed. The boot self-tests (such as measuring
+//! the delay timer) are only run if `bootdiag` is passed on the command line, since they slow the
+//! boot down.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::arch::io::cmos;
+use crate::multiboot2::MultibootInfo;
+use crate::multiboot2::boot_dev::BootDev;
+
+/// The marker which is written to the scratch register while the kernel is running.
+const MARKER_RUNNING: u8 = 0xB7;
+
+/// The marker which is written to the scratch register on a clean shutdown or reboot.
+const MARKER_CLEAN: u8 = 0x5C;
+
+/// The address of the BIOS warm boot flag (in the BIOS data area).
+const WARM_BOOT_FLAG_ADDR: usize = 0x472;
+
+/// The value of the warm boot flag when the memory test was skipped (a warm reset).
+const WARM_BOOT_MAGIC: u16 = 0x1234;
+
+/// An enum which represents what is known about the previous shutdown.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PrevShutdown {
+    Clean,                    // The previous boot shut down (or rebooted) cleanly.
+    Crashed,                  // The previous boot was still running when it was reset.
+    Unknown,                  // There was no marker (first boot, or the CMOS was cleared).
+}
+
+/// The diagnostics which are collected during boot.
+#[derive(Copy, Clone)]
+pub struct BootDiag {
+    pub boot_dev: Option<BootDev>,       // The boot device (if the boot loader passed it).
+    pub shutdown_status: u8,             // The raw CMOS shutdown status byte.
+    pub warm_boot: bool,                 // True if the BIOS warm boot flag was set.
+    pub prev_shutdown: PrevShutdown,     // The state of the previous shutdown.
+    pub self_tests: bool,                // True if the boot self-tests were requested.
+}
+
+/// The diagnostics which were collected (None until init is called).
+static mut BOOT_DIAG: Option<BootDiag> = None;
+
+/// A function which collects the boot diagnostics, logs them, and marks the kernel as running (so
+/// the next boot can detect a crash). It should be called after parsing the multiboot information.
+///
+/// # Parameters
+/// `mb_info` : The multiboot information reference (already parsed).
+pub fn init(mb_info: &MultibootInfo) {
+    let diag = BootDiag {
+        boot_dev: mb_info.boot_dev_tag,
+        shutdown_status: cmos::read(cmos::SHUTDOWN_STATUS_REG),
+        warm_boot: unsafe { *(WARM_BOOT_FLAG_ADDR as *const u16) } == WARM_BOOT_MAGIC,
+        prev_shutdown: decode_marker(cmos::read(cmos::SCRATCH_REG)),
+        self_tests: mb_info.boot_cmd_tag.as_ref().map_or(false, |cmd| cmd.has_flag("bootdiag")),
+    };
+
+    // Mark the kernel as running (until it's shut down in an orderly way).
+    cmos::write(cmos::SCRATCH_REG, MARKER_RUNNING);
+    let _ = crate::power::on_shutdown(mark_clean_shutdown);
+
+    // Log the findings.
+    match diag.boot_dev {
+        Some(dev) => oxid_log!("Booted from {}.", describe_boot_dev(&dev)),
+        None => oxid_log!("The boot device was not passed by the boot loader."),
+    }
+    oxid_log!("Reset: {} ({}), previous shutdown: {}.", shutdown_status_name(diag.shutdown_status),
+        if diag.warm_boot { "warm" } else { "cold" }, prev_shutdown_name(diag.prev_shutdown));
+
+    oxid_log!("Identity mapped by boot: 0x{:x} bytes, by the kernel: 0x0-0x{:x}.", 
+        crate::mem::vmm::boot_id_mapped_size(), crate::mem::vmm::id_map_end());
+
+    if diag.prev_shutdown == PrevShutdown::Crashed {
+        oxid_warn!("The previous boot did not shut down cleanly (it crashed or was reset).");
+    }
+
+    unsafe { BOOT_DIAG = Some(diag); }
+}
+
+/// A simple getter for the diagnostics which were collected during boot.
+///
+/// # Returns
+/// Some(diagnostics) if init was called, None otherwise.
+pub fn get() -> Option<BootDiag> {
+    unsafe { BOOT_DIAG }
+}
+
+/// A function which runs the boot self-tests if they were requested (with the bootdiag option). It
+/// should be called once the timer ticks are running (after the scheduler is initialized).
+pub fn run_self_tests() {
+    if !get().map_or(false, |diag| diag.self_tests) {
+        return;
+    }
+
+    // Measure the delay timer against the timer ticks.
+    unsafe { crate::time::self_test(); }
+}
+
+/// A function which marks the current boot as cleanly shut down. It should be called right before
+/// shutting down or rebooting.
+pub fn mark_clean_shutdown() {
+    cmos::write(cmos::SCRATCH_REG, MARKER_CLEAN);
+}
+
+/// A function which converts the value of the scratch register to the previous shutdown state.
+///
+/// # Parameters
+/// `marker` : The value which was read from the scratch register.
+///
+/// # Returns
+/// The state of the previous shutdown.
+pub fn decode_marker(marker: u8) -> PrevShutdown {
+    match marker {
+        MARKER_CLEAN => PrevShutdown::Clean,
+        MARKER_RUNNING => PrevShutdown::Crashed,
+        _ => PrevShutdown::Unknown,
+    }
+}
+
+/// A function which returns a name for the previous shutdown state.
+///
+/// # Parameters
+/// `prev` : The state of the previous shutdown.
+///
+/// # Returns
+/// A short description of it.
+pub fn prev_shutdown_name(prev: PrevShutdown) -> &'static str {
+    match prev {
+        PrevShutdown::Clean => "clean",
+        PrevShutdown::Crashed => "crashed",
+        PrevShutdown::Unknown => "unknown",
+    }
+}
+
+/// A function which decodes the CMOS shutdown status byte (as defined by the IBM PC/AT BIOS).
+///
+/// # Parameters
+/// `status` : The value of the shutdown status register.
+///
+/// # Returns
+/// A short description of the reason for the reset.
+pub fn shutdown_status_name(status: u8) -> &'static str {
+    match status {
+        0x00 => "power on or soft reset",
+        0x01 => "memory size pass",
+        0x02 => "memory test pass",
+        0x03 => "memory test fail",
+        0x04 => "boot loader request",
+        0x05 => "jump with EOI",
+        0x06 | 0x07 | 0x08 => "protected mode test",
+        0x09 => "block move",
+        0x0A => "jump without EOI",
+        0x0B => "iret without EOI",
+        0x0C => "retf without EOI",
+        _ => "unknown",
+    }
+}
+
+/// A function which describes a boot device (the BIOS drive number and the partitions).
+///
+/// # Parameters
+/// `dev` : The boot device tag.
+///
+/// # Returns
+/// A string such as "drive 0x80, partition 0".
+pub fn describe_boot_dev(dev: &BootDev) -> alloc::string::String {
+    let mut desc = alloc::format!("drive 0x{:x}", dev.drive());
+
+    if let Some(partition) = dev.partition() {
+        desc.push_str(&alloc::format!(", partition {}", partition));
+    }
+    if let Some(sub_partition) = dev.sub_partition() {
+        desc.push_str(&alloc::format!(", sub-partition {}", sub_partition));
+    }
+
+    desc
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::arch::io::cmos;
+    use crate::multiboot2::boot_dev::BootDev;
+    use super::PrevShutdown;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_boot_dev();
+        test_marker_round_trip();
+    }
+
+    /// Check the field extraction from a synthetic boot device tag.
+    fn test_boot_dev() {
+        // The tag type, size, drive, partition, and no sub-partition.
+        let tag: [u32; 5] = [5, 20, 0x80, 1, 0xFFFFFFFF];
+        let dev = unsafe { BootDev::new(tag.as_ptr() as usize) };
+
+        assert_eq!(dev.drive(), 0x80);
+        assert_eq!(dev.partition(), Some(1));
+        assert_eq!(dev.sub_partition(), None);
+        assert_eq!(super::describe_boot_dev(&dev), "drive 0x80, partition 1");
+    }
+
+    /// Write the clean shutdown marker, read it back, and restore the running marker.
+    fn test_marker_round_trip() {
+        let prev = cmos::read(cmos::SCRATCH_REG);
+
+        super::mark_clean_shutdown();
+        assert_eq!(super::decode_marker(cmos::read(cmos::SCRATCH_REG)), PrevShutdown::Clean);
+
+        cmos::write(cmos::SCRATCH_REG, super::MARKER_RUNNING);
+        assert_eq!(super::decode_marker(cmos::read(cmos::SCRATCH_REG)), PrevShutdown::Crashed);
+        assert_eq!(super::decode_marker(0), PrevShutdown::Unknown);
+
+        cmos::write(cmos::SCRATCH_REG, prev);
+    }
+}
@@ -0,0 +1,532 @@
+//! A sub-module which keeps a record of the last fatal panic across a reboot. On panic, the message,
+//! the instruction pointer, and the top of the backtrace are serialized into a compact record which
+//! is written to a "crash sector" (if a writable block device registered one), or to a reserved
+//! range of the CMOS registers (which only fits the instruction pointer and a truncated message).
+//! On the next boot, check reads the record, reports it, and clears it. The write path doesn't
+//! allocate or enable the interrupts, so it's safe to use from the panic handler.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use crate::arch::io::cmos;
+use crate::olibc::bounded::BoundedString;
+
+/// The size of the crash sector (in bytes).
+pub const SECTOR_SIZE: usize = 512;
+
+/// The number of return addresses which are kept from the backtrace.
+pub const MAX_FRAMES: usize = 8;
+
+/// The magic value at the start of a crash sector ("OXCR").
+const SECTOR_MAGIC: u32 = 0x5243_584F;
+
+/// The layout of the crash sector (magic, rip, number of frames, message length, frames, message).
+const SECTOR_RIP_OFFSET: usize = 4;
+const SECTOR_NUM_FRAMES_OFFSET: usize = 12;
+const SECTOR_MSG_LEN_OFFSET: usize = 13;
+const SECTOR_FRAMES_OFFSET: usize = 15;
+const SECTOR_MSG_OFFSET: usize = SECTOR_FRAMES_OFFSET + MAX_FRAMES * 8;
+
+/// The maximum length of the message (in bytes), which is limited by the crash sector.
+pub const MSG_MAX: usize = SECTOR_SIZE - SECTOR_MSG_OFFSET;
+
+/// The magic value in the first reserved CMOS register when it holds a record.
+const CMOS_MAGIC: u8 = 0xC7;
+
+/// The layout of the CMOS record (magic, message length, rip, message).
+const CMOS_MSG_LEN_OFFSET: u8 = 1;
+const CMOS_RIP_OFFSET: u8 = 2;
+const CMOS_MSG_OFFSET: u8 = 10;
+
+/// The maximum length of the message (in bytes) which fits in the CMOS.
+pub const CMOS_MSG_MAX: usize = (cmos::CRASH_REGS_LEN - CMOS_MSG_OFFSET) as usize;
+
+/// The maximum distance between the frames which are followed (the stacks are smaller than this).
+const MAX_FRAME_DISTANCE: usize = 0x10_0000;
+
+/// A record of a panic (which is stored inline, so it doesn't need the heap).
+#[derive(Copy, Clone)]
+pub struct CrashRecord {
+    pub rip: u64,                              // The address where the panic was called from.
+    pub frames: [u64; MAX_FRAMES],             // The return addresses of the callers.
+    pub num_frames: usize,                     // The number of valid frames.
+    pub message: BoundedString<MSG_MAX>,       // The panic message (it might be truncated).
+}
+
+/// The functions which access the crash sector of a writable block device (or a ramdisk).
+#[derive(Copy, Clone)]
+pub struct CrashSector {
+    pub read: fn(&mut [u8; SECTOR_SIZE]) -> bool,     // Reads the sector, returns true on success.
+    pub write: fn(&[u8; SECTOR_SIZE]) -> bool,        // Writes the sector, returns true on success.
+}
+
+/// The crash sector which was registered (None if the CMOS should be used).
+static mut SECTOR: Option<CrashSector> = None;
+
+/// The sector which is used by the tests. It also records the panics of recovered processes.
+#[cfg(feature = "unit-test")]
+static mut TEST_SECTOR: Option<CrashSector> = None;
+
+/// Set once a record was written, so a panic during the write doesn't overwrite it.
+static mut RECORDED: bool = false;
+
+/// The record which was found by check (the crash of the previous boot).
+static mut PREVIOUS: Option<CrashRecord> = None;
+
+impl CrashRecord {
+    /// A constructor which creates an empty record.
+    ///
+    /// # Returns
+    /// A record without any frames or message.
+    pub const fn new() -> Self {
+        CrashRecord {
+            rip: 0,
+            frames: [0; MAX_FRAMES],
+            num_frames: 0,
+            message: BoundedString::new(),
+        }
+    }
+
+    /// A method which returns the valid frames of the backtrace.
+    ///
+    /// # Returns
+    /// The return addresses (the innermost first).
+    pub fn frames(&self) -> &[u64] {
+        &self.frames[..self.num_frames]
+    }
+}
+
+/// A function which registers the crash sector of a block device. The next panic records it there
+/// (instead of the CMOS), and check reads it on the next boot.
+///
+/// # Parameters
+/// `sector` : The functions which access the sector.
+pub fn register_sector(sector: CrashSector) {
+    unsafe { SECTOR = Some(sector); }
+}
+
+/// A function which removes the crash sector (the CMOS is used again).
+pub fn unregister_sector() {
+    unsafe { SECTOR = None; }
+}
+
+/// A function which is called by the panic handler. It records fatal panics (once), and when the
+/// tests set a hook, the recovered ones as well. It doesn't allocate or enable the interrupts.
+///
+/// # Parameters
+/// `info` : The information about the panic.
+/// `fatal` : True if the system is going to halt, False if only the process is removed.
+pub fn on_panic(info: &PanicInfo, fatal: bool) {
+    unsafe {
+        #[cfg(feature = "unit-test")]
+        if let Some(sector) = TEST_SECTOR {
+            write_sector(&sector, &capture(info));
+            return;
+        }
+
+        if !fatal || RECORDED {
+            return;
+        }
+        RECORDED = true;
+
+        let record = capture(info);
+        match SECTOR {
+            Some(sector) => if !write_sector(&sector, &record) { write_cmos(&record); },
+            None => write_cmos(&record),
+        }
+    }
+}
+
+/// A function which checks if the previous boot left a crash record. If it did, it's reported in
+/// the boot log and cleared (so it's only reported once).
+///
+/// # Returns
+/// Some(record) if the previous boot crashed, None otherwise.
+pub fn check() -> Option<CrashRecord> {
+    // The crash sector (if there is one) can hold more, so it's preferred.
+    let mut found = None;
+    if let Some(sector) = current_sector() {
+        let mut buffer: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+        if (sector.read)(&mut buffer) {
+            found = decode_sector(&buffer);
+            if found.is_some() {
+                (sector.write)(&[0; SECTOR_SIZE]);
+            }
+        }
+    }
+
+    if let Some(record) = read_cmos() {
+        clear_cmos();
+        found = found.or(Some(record));
+    }
+
+    if let Some(record) = found.as_ref() {
+        oxid_warn!("previous boot crashed: {} (rip=0x{:x})", record.message.as_str(), record.rip);
+        for frame in record.frames() {
+            oxid_log!("    at 0x{:x}", frame);
+        }
+    }
+
+    unsafe { PREVIOUS = found; }
+    found
+}
+
+/// A simple getter for the crash record which was found during boot.
+///
+/// # Returns
+/// Some(record) if the previous boot crashed, None otherwise.
+pub fn previous() -> Option<CrashRecord> {
+    unsafe { PREVIOUS }
+}
+
+/// A function which returns the sector which should be used (the test sector has priority).
+///
+/// # Returns
+/// Some(sector) if there is one, None if only the CMOS should be used.
+fn current_sector() -> Option<CrashSector> {
+    #[cfg(feature = "unit-test")]
+    if let Some(sector) = unsafe { TEST_SECTOR } {
+        return Some(sector);
+    }
+
+    unsafe { SECTOR }
+}
+
+/// A function which captures the record of a panic (the message and the backtrace).
+///
+/// # Parameters
+/// `info` : The information about the panic.
+///
+/// # Returns
+/// The record (the message is truncated if it doesn't fit).
+fn capture(info: &PanicInfo) -> CrashRecord {
+    let mut record = CrashRecord::new();
+    let _ = write!(record.message, "{}", info);
+
+    // Walk the frame pointers, the first return address is where the panic was called from.
+    let mut addrs: [u64; MAX_FRAMES + 1] = [0; MAX_FRAMES + 1];
+    let count = backtrace(&mut addrs);
+    if count > 0 {
+        record.rip = addrs[0];
+        record.num_frames = count - 1;
+        record.frames[..count - 1].copy_from_slice(&addrs[1..count]);
+    }
+
+    record
+}
+
+/// A function which walks the frame pointers (rbp) and stores the return addresses. It stops when
+/// a frame pointer doesn't look valid, since a panic might have corrupted the stack.
+///
+/// # Parameters
+/// `addrs` : The array where the return addresses are stored.
+///
+/// # Returns
+/// The number of addresses which were stored.
+fn backtrace(addrs: &mut [u64]) -> usize {
+    let mut frame = unsafe { crate::arch::registers::get_rbp() };
+    let mut count = 0;
+
+    while count < addrs.len() {
+        // Each frame holds the previous rbp, followed by the return address.
+        if frame == 0 || frame % core::mem::size_of::<usize>() != 0 {
+            break;
+        }
+
+        let (next, ret_addr) = unsafe { (*(frame as *const usize), *((frame + 8) as *const usize)) };
+        if ret_addr == 0 {
+            break;
+        }
+        addrs[count] = ret_addr as u64;
+        count += 1;
+
+        // The stack grows down, so the callers must be at higher addresses.
+        if next <= frame || next - frame > MAX_FRAME_DISTANCE {
+            break;
+        }
+        frame = next;
+    }
+
+    count
+}
+
+/// A function which serializes a record, and writes it to a crash sector.
+///
+/// # Parameters
+/// `sector` : The sector which we're writing to.
+/// `record` : The record which we're writing.
+///
+/// # Returns
+/// True if it was written, False otherwise.
+fn write_sector(sector: &CrashSector, record: &CrashRecord) -> bool {
+    (sector.write)(&encode_sector(record))
+}
+
+/// A function which serializes a record in the crash sector format.
+///
+/// # Parameters
+/// `record` : The record which we're serializing.
+///
+/// # Returns
+/// The contents of the sector.
+pub fn encode_sector(record: &CrashRecord) -> [u8; SECTOR_SIZE] {
+    let mut buffer: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+    let message = record.message.as_str().as_bytes();
+
+    buffer[..SECTOR_RIP_OFFSET].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+    buffer[SECTOR_RIP_OFFSET..SECTOR_NUM_FRAMES_OFFSET].copy_from_slice(&record.rip.to_le_bytes());
+    buffer[SECTOR_NUM_FRAMES_OFFSET] = record.num_frames as u8;
+    buffer[SECTOR_MSG_LEN_OFFSET..SECTOR_FRAMES_OFFSET]
+        .copy_from_slice(&(message.len() as u16).to_le_bytes());
+
+    for (index, frame) in record.frames().iter().enumerate() {
+        let offset = SECTOR_FRAMES_OFFSET + index * 8;
+        buffer[offset..offset + 8].copy_from_slice(&frame.to_le_bytes());
+    }
+
+    buffer[SECTOR_MSG_OFFSET..SECTOR_MSG_OFFSET + message.len()].copy_from_slice(message);
+    buffer
+}
+
+/// A function which deserializes a record from the crash sector format.
+///
+/// # Parameters
+/// `buffer` : The contents of the sector.
+///
+/// # Returns
+/// Some(record) if the sector holds a valid record, None otherwise.
+pub fn decode_sector(buffer: &[u8; SECTOR_SIZE]) -> Option<CrashRecord> {
+    let mut word: [u8; 8] = [0; 8];
+    let mut record = CrashRecord::new();
+
+    word[..4].copy_from_slice(&buffer[..SECTOR_RIP_OFFSET]);
+    if u32::from_le_bytes([word[0], word[1], word[2], word[3]]) != SECTOR_MAGIC {
+        return None;
+    }
+
+    word.copy_from_slice(&buffer[SECTOR_RIP_OFFSET..SECTOR_NUM_FRAMES_OFFSET]);
+    record.rip = u64::from_le_bytes(word);
+
+    record.num_frames = buffer[SECTOR_NUM_FRAMES_OFFSET] as usize;
+    let msg_len = u16::from_le_bytes([buffer[SECTOR_MSG_LEN_OFFSET],
+        buffer[SECTOR_MSG_LEN_OFFSET + 1]]) as usize;
+    if record.num_frames > MAX_FRAMES || msg_len > MSG_MAX {
+        return None;
+    }
+
+    for index in 0..record.num_frames {
+        let offset = SECTOR_FRAMES_OFFSET + index * 8;
+        word.copy_from_slice(&buffer[offset..offset + 8]);
+        record.frames[index] = u64::from_le_bytes(word);
+    }
+
+    push_message(&mut record, &buffer[SECTOR_MSG_OFFSET..SECTOR_MSG_OFFSET + msg_len]);
+    Some(record)
+}
+
+/// A function which writes a record to the reserved CMOS registers (the frames are not kept, and
+/// the message is truncated to CMOS_MSG_MAX bytes).
+///
+/// # Parameters
+/// `record` : The record which we're writing.
+pub fn write_cmos(record: &CrashRecord) {
+    // Only keep whole characters of the message.
+    let message = record.message.as_str();
+    let msg_len = crate::debug::klog::floor_char_boundary(message, CMOS_MSG_MAX);
+
+    for (index, byte) in record.rip.to_le_bytes().iter().enumerate() {
+        cmos::write(cmos::CRASH_REGS_START + CMOS_RIP_OFFSET + index as u8, *byte);
+    }
+    for (index, byte) in message.as_bytes()[..msg_len].iter().enumerate() {
+        cmos::write(cmos::CRASH_REGS_START + CMOS_MSG_OFFSET + index as u8, *byte);
+    }
+    cmos::write(cmos::CRASH_REGS_START + CMOS_MSG_LEN_OFFSET, msg_len as u8);
+
+    // Write the magic last, so a partial record is never considered valid.
+    cmos::write(cmos::CRASH_REGS_START, CMOS_MAGIC);
+}
+
+/// A function which reads a record from the reserved CMOS registers.
+///
+/// # Returns
+/// Some(record) if the CMOS holds a valid record, None otherwise.
+pub fn read_cmos() -> Option<CrashRecord> {
+    if cmos::read(cmos::CRASH_REGS_START) != CMOS_MAGIC {
+        return None;
+    }
+
+    let msg_len = cmos::read(cmos::CRASH_REGS_START + CMOS_MSG_LEN_OFFSET) as usize;
+    if msg_len > CMOS_MSG_MAX {
+        return None;
+    }
+
+    let mut record = CrashRecord::new();
+    let mut word: [u8; 8] = [0; 8];
+    for (index, byte) in word.iter_mut().enumerate() {
+        *byte = cmos::read(cmos::CRASH_REGS_START + CMOS_RIP_OFFSET + index as u8);
+    }
+    record.rip = u64::from_le_bytes(word);
+
+    let mut message: [u8; CMOS_MSG_MAX] = [0; CMOS_MSG_MAX];
+    for (index, byte) in message[..msg_len].iter_mut().enumerate() {
+        *byte = cmos::read(cmos::CRASH_REGS_START + CMOS_MSG_OFFSET + index as u8);
+    }
+    push_message(&mut record, &message[..msg_len]);
+
+    Some(record)
+}
+
+/// A function which clears the record in the reserved CMOS registers.
+pub fn clear_cmos() {
+    for reg in cmos::CRASH_REGS_START..(cmos::CRASH_REGS_START + cmos::CRASH_REGS_LEN) {
+        cmos::write(reg, 0);
+    }
+}
+
+/// A helper which adds the valid UTF-8 prefix of the stored bytes to the message of a record.
+///
+/// # Parameters
+/// `record` : The record which we're adding to.
+/// `bytes` : The stored bytes of the message.
+fn push_message(record: &mut CrashRecord, bytes: &[u8]) {
+    let valid = match core::str::from_utf8(bytes) {
+        Ok(string) => string,
+        Err(error) => unsafe { core::str::from_utf8_unchecked(&bytes[..error.valid_up_to()]) },
+    };
+
+    let _ = record.message.push_str(valid);
+}
+
+/// A function which resets the state of the module as if the system was rebooted (used by the
+/// tests, the stored records are not touched).
+#[cfg(feature = "unit-test")]
+fn reset() {
+    unsafe {
+        RECORDED = false;
+        PREVIOUS = None;
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{CrashRecord, CrashSector, SECTOR_SIZE};
+    use crate::arch::io::cmos;
+    use crate::proc::process::{Args, SpawnFlags};
+    use crate::proc::scheduler;
+
+    /// The ramdisk which holds the crash sector in the tests.
+    static mut RAMDISK: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_sector_round_trip();
+        test_cmos_round_trip();
+        test_panic_recovered();
+    }
+
+    /// Reads the crash sector from the ramdisk.
+    fn ramdisk_read(buffer: &mut [u8; SECTOR_SIZE]) -> bool {
+        unsafe { buffer.copy_from_slice(&RAMDISK); }
+        true
+    }
+
+    /// Writes the crash sector to the ramdisk.
+    fn ramdisk_write(buffer: &[u8; SECTOR_SIZE]) -> bool {
+        unsafe { RAMDISK.copy_from_slice(buffer); }
+        true
+    }
+
+    /// A process which panics with a known message.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn panicking_process(_args: *const Args) -> usize {
+        panic!("crashlog test");
+    }
+
+    /// Serialize a record in the sector format, and make sure it's decoded the same way.
+    fn test_sector_round_trip() {
+        let mut record = CrashRecord::new();
+        record.rip = 0xFFFF_8000_0010_2030;
+        record.frames[..3].copy_from_slice(&[1, 2, 3]);
+        record.num_frames = 3;
+        let _ = record.message.push_str("sector round trip");
+
+        let decoded = super::decode_sector(&super::encode_sector(&record)).unwrap();
+        assert_eq!(decoded.rip, record.rip);
+        assert_eq!(decoded.frames(), &[1, 2, 3]);
+        assert_eq!(decoded.message.as_str(), "sector round trip");
+
+        assert!(super::decode_sector(&[0; SECTOR_SIZE]).is_none());
+    }
+
+    /// Write a record to the CMOS, make sure the message is truncated, and that it's cleared.
+    fn test_cmos_round_trip() {
+        // Keep the original values of the registers.
+        let mut saved: [u8; cmos::CRASH_REGS_LEN as usize] = [0; cmos::CRASH_REGS_LEN as usize];
+        for (index, value) in saved.iter_mut().enumerate() {
+            *value = cmos::read(cmos::CRASH_REGS_START + index as u8);
+        }
+
+        let mut record = CrashRecord::new();
+        record.rip = 0x1234_5678;
+        let _ = record.message.push_str("a message which is too long to fit in the CMOS");
+        super::write_cmos(&record);
+
+        let read = super::read_cmos().unwrap();
+        assert_eq!(read.rip, 0x1234_5678);
+        assert_eq!(read.message.as_str(), &record.message.as_str()[..super::CMOS_MSG_MAX]);
+
+        super::clear_cmos();
+        assert!(super::read_cmos().is_none());
+
+        for (index, value) in saved.iter().enumerate() {
+            cmos::write(cmos::CRASH_REGS_START + index as u8, *value);
+        }
+    }
+
+    /// Force a panic with the test hook (which writes to the ramdisk), reset the module as if the
+    /// system rebooted, and make sure the record is recovered and cleared.
+    fn test_panic_recovered() {
+        unsafe {
+            let boot_record = super::PREVIOUS;
+            RAMDISK = [0; SECTOR_SIZE];
+            super::TEST_SECTOR = Some(CrashSector { read: ramdisk_read, write: ramdisk_write });
+
+            let mut args = Args::new();
+            let handle = scheduler::spawn_with_flags(panicking_process, &mut args as *mut Args,
+                "crashlog_test", SpawnFlags::NONE);
+
+            // Wait (for up to 2 seconds) for the scheduler to remove it.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while scheduler::find_handle(handle, |_| ()).is_some() 
+                && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert!(scheduler::find_handle(handle, |_| ()).is_none());
+
+            // "Reboot", and check for the record.
+            super::reset();
+            let record = super::check().unwrap();
+            assert!(record.message.as_str().contains("crashlog test"));
+            assert!(record.rip != 0);
+            assert!(super::previous().is_some());
+
+            // It should be cleared after it's reported.
+            assert!(super::decode_sector(&RAMDISK).is_none());
+            super::reset();
+            assert!(super::check().is_none());
+
+            super::TEST_SECTOR = None;
+            super::reset();
+            super::PREVIOUS = boot_record;
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! A sub-module which formats memory as a classic hex dump (`addr: hex bytes  |ascii|`). It only 
+//! uses stack buffers, so it's safe to use from the debugging tools which can't allocate. The 
+//! lines are aligned to the number of bytes per line, so unaligned dumps start with blanks.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::{self, Write};
+
+/// The number of bytes per line which is used by the tools.
+pub const DEFAULT_BYTES_PER_LINE: usize = 16;
+
+/// The maximum number of bytes per line (the size of the ascii buffer).
+pub const MAX_BYTES_PER_LINE: usize = 32;
+
+/// A writer which prints to the console (so the dumps can be printed without a buffer).
+pub struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        oxid_print!("{}", string);
+        Ok(())
+    }
+}
+
+/// A function which writes a hex dump of the given bytes.
+///
+/// # Parameters
+/// `w` : The writer which the dump is written to.
+/// `base_addr` : The address of the first byte (which is printed at the start of the lines).
+/// `bytes` : The bytes which we're dumping.
+/// `bytes_per_line` : The number of bytes on each line (it's limited to MAX_BYTES_PER_LINE).
+///
+/// # Returns
+/// The result of the writes.
+pub fn hexdump_to<W: Write>(w: &mut W, base_addr: usize, bytes: &[u8], bytes_per_line: usize) 
+    -> fmt::Result {
+    
+    dump_lines(w, base_addr, bytes.len(), bytes_per_line, |addr| Some(bytes[addr - base_addr]))
+}
+
+/// A function which writes a hex dump of the memory at a given address. Each page is validated 
+/// (with vmm::query) before it's read, and the unmapped bytes are printed as `..` instead of 
+/// faulting.
+///
+/// # Parameters
+/// `w` : The writer which the dump is written to.
+/// `addr` : The address of the first byte.
+/// `len` : The number of bytes which we're dumping.
+/// `bytes_per_line` : The number of bytes on each line (it's limited to MAX_BYTES_PER_LINE).
+///
+/// # Returns
+/// The result of the writes.
+pub fn hexdump_mapped_to<W: Write>(w: &mut W, addr: usize, len: usize, bytes_per_line: usize) 
+    -> fmt::Result {
+    
+    // Keep the last mapping which was found (so each page is only queried once).
+    let mapped = core::cell::Cell::new((0, 0));
+    dump_lines(w, addr, len, bytes_per_line, |byte_addr| {
+        let (start, end) = mapped.get();
+        if byte_addr < start || byte_addr >= end {
+            // The non-canonical addresses are never mapped.
+            let page = crate::mem::addr::VirtAddr::new(byte_addr);
+            match page.and_then(crate::mem::vmm::query) {
+                Ok((_, page_size)) => {
+                    let page_start = crate::mem::align::align_lower(byte_addr, page_size);
+                    mapped.set((page_start, page_start + page_size));
+                },
+                Err(_) => return None,
+            }
+        }
+        
+        Some(unsafe { *(byte_addr as *const u8) })
+    })
+}
+
+/// A function which prints a hex dump of the memory at a given address to the console (the 
+/// unmapped bytes are printed as `..`).
+///
+/// # Parameters
+/// `addr` : The address of the first byte.
+/// `len` : The number of bytes which we're dumping.
+pub fn hexdump_mapped(addr: usize, len: usize) {
+    let _ = hexdump_mapped_to(&mut Console, addr, len, DEFAULT_BYTES_PER_LINE);
+}
+
+/// A function which writes the lines of a dump. The bytes are read with a closure, which returns
+/// None if a byte can't be read.
+///
+/// # Parameters
+/// `w` : The writer which the dump is written to.
+/// `addr` : The address of the first byte.
+/// `len` : The number of bytes which we're dumping.
+/// `bytes_per_line` : The number of bytes on each line (it's limited to MAX_BYTES_PER_LINE).
+/// `byte_at` : Returns the byte at an address (or None if it's not readable).
+///
+/// # Returns
+/// The result of the writes.
+fn dump_lines<W: Write, F: Fn(usize) -> Option<u8>>(w: &mut W, addr: usize, len: usize, 
+    bytes_per_line: usize, byte_at: F) -> fmt::Result {
+    
+    let bytes_per_line = core::cmp::min(core::cmp::max(bytes_per_line, 1), MAX_BYTES_PER_LINE);
+    let end = addr + len;
+    let mut line_addr = addr - addr % bytes_per_line;
+    
+    while line_addr < end {
+        // The ascii column is kept until the hex is written.
+        let mut ascii: [u8; MAX_BYTES_PER_LINE] = [b' '; MAX_BYTES_PER_LINE];
+        let mut ascii_len = 0;
+        
+        write!(w, "{:016x}:", line_addr)?;
+        for byte_addr in line_addr..(line_addr + bytes_per_line) {
+            // The bytes outside of the range are left blank.
+            if byte_addr < addr || byte_addr >= end {
+                w.write_str("   ")?;
+                if byte_addr < addr { ascii_len += 1; }
+                continue;
+            }
+            
+            match byte_at(byte_addr) {
+                Some(byte) => {
+                    write!(w, " {:02x}", byte)?;
+                    ascii[ascii_len] = if byte.is_ascii_graphic() || byte == b' ' { byte } else { b'.' };
+                },
+                // The unreadable bytes are left blank in the ascii column.
+                None => w.write_str(" ..")?,
+            }
+            ascii_len += 1;
+        }
+        
+        // Only printable ascii is stored in the buffer, so it's always valid.
+        let ascii = unsafe { core::str::from_utf8_unchecked(&ascii[..ascii_len]) };
+        write!(w, "  |{}|\n", ascii)?;
+        
+        line_addr += bytes_per_line;
+    }
+    
+    Ok(())
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    
+    /// The bytes which are dumped by the tests ("Oxid OS" followed by some non printable bytes).
+    const BYTES: [u8; 10] = [b'O', b'x', b'i', b'd', b' ', b'O', b'S', 0x00, 0x7F, 0xFF];
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module.
+    pub fn run() {
+        test_aligned();
+        test_unaligned();
+        test_short();
+        test_gap();
+    }
+    
+    /// Dump the bytes at an aligned address (over two lines).
+    fn test_aligned() {
+        let mut out = String::new();
+        super::hexdump_to(&mut out, 0x1000, &BYTES, 8).unwrap();
+        assert_eq!(out, 
+            "0000000000001000: 4f 78 69 64 20 4f 53 00  |Oxid OS.|\n\
+             0000000000001008: 7f ff                    |..|\n");
+    }
+    
+    /// Dump the bytes at an unaligned address (the first line starts with blanks).
+    fn test_unaligned() {
+        let mut out = String::new();
+        super::hexdump_to(&mut out, 0x1005, &BYTES[..5], 8).unwrap();
+        assert_eq!(out, 
+            "0000000000001000:                4f 78 69  |     Oxi|\n\
+             0000000000001008: 64 20                    |d |\n");
+    }
+    
+    /// Dump less than a line (and nothing at all).
+    fn test_short() {
+        let mut out = String::new();
+        super::hexdump_to(&mut out, 0x20, &BYTES[..2], 16).unwrap();
+        assert_eq!(out, "0000000000000020: 4f 78                                            |Ox|\n");
+        
+        out.clear();
+        super::hexdump_to(&mut out, 0x20, &[], 16).unwrap();
+        assert_eq!(out, "");
+    }
+    
+    /// Dump a range with a gap which can't be read.
+    fn test_gap() {
+        let mut out = String::new();
+        super::dump_lines(&mut out, 0x0, 6, 4, |addr| if addr >= 2 && addr < 5 { None } 
+            else { Some(BYTES[addr]) }).unwrap();
+        assert_eq!(out, 
+            "0000000000000000: 4f 78 .. ..  |Ox  |\n\
+             0000000000000004: .. 4f        | O|\n");
+    }
+}
@@ -0,0 +1,411 @@
+//! A sub-module which provides the kernel log ring. It keeps the most recent messages in a fixed 
+//! size ring of bytes (the oldest entries are dropped when it's full), so they can be inspected 
+//! after they scrolled away. Every entry starts with a small header (the PID, the level, and the 
+//! length), so the messages can be filtered. The output of the processes which were spawned with 
+//! the LOG_OUTPUT flag is copied here (see tee), and so are the oxid_dbg! trace messages (which are
+//! not printed, see trace).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::olibc::bounded::BoundedString;
+
+/// The size of the ring in bytes (including the headers).
+pub const RING_SIZE: usize = 8192;
+
+/// The maximum number of bytes in a single entry (longer messages are cut).
+pub const MAX_ENTRY: usize = 255;
+
+/// The maximum number of bytes which are logged for a single process (so a chatty process can't
+/// evict everything else).
+pub const PROC_LOG_LIMIT: usize = 1024;
+
+/// The size of the header of each entry: the PID (4 bytes), the level, and the length (1 byte each).
+const HEADER_SIZE: usize = 6;
+
+/// An enum which represents the level of a log entry.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Level {
+    Output = 0,                     // The regular output of a process.
+    Trace = 1,                      // A trace message (below the log messages).
+    Log = 2,                        // A log message.
+    Warn = 3,                       // A warning message.
+    Err = 4,                        // An error message.
+}
+
+impl Level {
+    /// A function which converts the level stored in a header back to the enum.
+    ///
+    /// # Parameters
+    /// `value` : The stored value.
+    ///
+    /// # Returns
+    /// The level (Output if the value is not valid).
+    fn from_u8(value: u8) -> Level {
+        match value {
+            1 => Level::Trace,
+            2 => Level::Log,
+            3 => Level::Warn,
+            4 => Level::Err,
+            _ => Level::Output,
+        }
+    }
+}
+
+/// A struct which holds the ring of bytes. The entries are stored back to back, starting from the
+/// oldest one (at start), and they wrap around the end of the buffer.
+struct Ring {
+    buffer: [u8; RING_SIZE],        // The stored entries.
+    start: usize,                   // The index of the oldest entry.
+    len: usize,                     // The number of bytes which are used.
+}
+
+/// The kernel log ring (it's only accessed with the interrupts disabled).
+static mut RING: Ring = Ring { buffer: [0; RING_SIZE], start: 0, len: 0 };
+
+impl Ring {
+    /// A method which reads a byte based on it's offset from the oldest entry.
+    ///
+    /// # Parameters
+    /// `offset` : The offset from the start of the ring.
+    ///
+    /// # Returns
+    /// The stored byte.
+    #[inline]
+    fn byte(&self, offset: usize) -> u8 {
+        self.buffer[(self.start + offset) % RING_SIZE]
+    }
+    
+    /// A method which adds a byte to the end of the ring (there should be space for it).
+    ///
+    /// # Parameters
+    /// `value` : The byte which we're adding.
+    #[inline]
+    fn push_byte(&mut self, value: u8) {
+        self.buffer[(self.start + self.len) % RING_SIZE] = value;
+        self.len += 1;
+    }
+    
+    /// A method which removes the oldest entry from the ring.
+    fn drop_oldest(&mut self) {
+        let entry_size = HEADER_SIZE + self.byte(HEADER_SIZE - 1) as usize;
+        self.start = (self.start + entry_size) % RING_SIZE;
+        self.len -= entry_size;
+    }
+    
+    /// A method which adds an entry to the ring, and drops the oldest ones if there's no space.
+    ///
+    /// # Parameters
+    /// `pid` : The process which the entry belongs to.
+    /// `level` : The level of the entry.
+    /// `text` : The text of the entry (at most MAX_ENTRY bytes).
+    fn write(&mut self, pid: usize, level: Level, text: &[u8]) {
+        while RING_SIZE - self.len < HEADER_SIZE + text.len() {
+            self.drop_oldest();
+        }
+        
+        for byte in (pid as u32).to_le_bytes().iter() {
+            self.push_byte(*byte);
+        }
+        self.push_byte(level as u8);
+        self.push_byte(text.len() as u8);
+        
+        for byte in text {
+            self.push_byte(*byte);
+        }
+    }
+}
+
+/// A function which adds an entry to the kernel log. Texts longer than MAX_ENTRY are cut.
+///
+/// # Parameters
+/// `pid` : The process which the entry belongs to.
+/// `level` : The level of the entry.
+/// `text` : The text of the entry.
+pub fn write(pid: usize, level: Level, text: &str) {
+    let text = &text[..floor_char_boundary(text, MAX_ENTRY)];
+    
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        RING.write(pid, level, text.as_bytes());
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which copies the output of the current process to the kernel log, if it was spawned
+/// with the LOG_OUTPUT flag. Each process can only log up to PROC_LOG_LIMIT bytes. The output which
+/// is printed with the interrupts disabled (from the interrupt handlers, and the foreground 
+/// programs) does not belong to the current process, so it's not logged. It should be called
+/// before the output is printed (since unlocking the writer enables the interrupts).
+///
+/// # Parameters
+/// `args` : The formatted arguments which were printed.
+/// `add_nl` : True if a newline was printed after them.
+pub fn tee(args: fmt::Arguments, add_nl: bool) {
+    if !unsafe { crate::arch::interrupts::are_enabled() } 
+        || !crate::proc::scheduler::current_logs_output() {
+        return;
+    }
+    
+    // Format it without allocating (it's cut if it's too long).
+    let mut text: BoundedString<MAX_ENTRY> = BoundedString::new();
+    let _ = fmt::write(&mut text, args);
+    if add_nl {
+        text.push('\n');
+    }
+    
+    // Only log what's left of the process' budget.
+    if let Some((pid, granted)) = crate::proc::scheduler::take_log_budget(text.len()) {
+        let end = floor_char_boundary(text.as_str(), granted);
+        if end > 0 {
+            write(pid, Level::Output, &text.as_str()[..end]);
+        }
+    }
+}
+
+/// A function which adds a trace message (from oxid_dbg!) to the kernel log. It's charged to the
+/// running process (or PID 0 before the scheduler starts), but it doesn't use it's log budget. It
+/// doesn't allocate, so it can be used in the interrupt handlers and the allocators.
+///
+/// # Parameters
+/// `args` : The formatted message (without a newline).
+pub fn trace(args: fmt::Arguments) {
+    let mut text: BoundedString<MAX_ENTRY> = BoundedString::new();
+    let _ = fmt::write(&mut text, args);
+    text.push('\n');
+    
+    let pid = crate::proc::scheduler::current_pid().unwrap_or(0);
+    write(pid, Level::Trace, text.as_str());
+}
+
+/// A function which copies the contents of the ring (oldest entry first) to a given buffer.
+///
+/// # Parameters
+/// `buf` : The buffer which the entries are copied to (it should be at least RING_SIZE bytes).
+///
+/// # Returns
+/// The number of bytes which were copied.
+pub fn snapshot(buf: &mut [u8]) -> usize {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        
+        let len = RING.len.min(buf.len());
+        for (offset, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = RING.byte(offset);
+        }
+        
+        crate::arch::interrupts::restore(were_enabled);
+        len
+    }
+}
+
+/// A function which goes through the entries of a snapshot.
+///
+/// # Parameters
+/// `snapshot` : The bytes which were copied by snapshot().
+/// `func` : Called with the PID, the level, and the text of every entry.
+pub fn for_each_entry(snapshot: &[u8], func: &mut dyn FnMut(usize, Level, &str)) {
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= snapshot.len() {
+        // Read the header.
+        let mut pid_bytes: [u8; 4] = [0; 4];
+        pid_bytes.copy_from_slice(&snapshot[offset..(offset + 4)]);
+        let level = Level::from_u8(snapshot[offset + 4]);
+        let len = snapshot[offset + 5] as usize;
+        
+        // Then the text (which is always cut at a character boundary).
+        let text_start = offset + HEADER_SIZE;
+        let text_end = (text_start + len).min(snapshot.len());
+        let text = core::str::from_utf8(&snapshot[text_start..text_end]).unwrap_or("");
+        func(u32::from_le_bytes(pid_bytes) as usize, level, text);
+        
+        offset = text_start + len;
+    }
+}
+
+/// A function which finds all the entries of a given process.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+/// `out` : The string which the texts of the entries are added to.
+pub fn read_pid(pid: usize, out: &mut String) {
+    // Copy the ring first, since we can't allocate with the interrupts disabled.
+    let mut buf: Vec<u8> = alloc::vec![0; RING_SIZE];
+    let len = snapshot(&mut buf);
+    
+    for_each_entry(&buf[..len], &mut |entry_pid, _, text| {
+        if entry_pid == pid {
+            out.push_str(text);
+        }
+    });
+}
+
+/// A function which finds all the entries of a given level (for example, the trace messages).
+///
+/// # Parameters
+/// `level` : The level of the entries.
+/// `out` : The string which the texts of the entries are added to.
+pub fn read_level(level: Level, out: &mut String) {
+    // Copy the ring first, since we can't allocate with the interrupts disabled.
+    let mut buf: Vec<u8> = alloc::vec![0; RING_SIZE];
+    let len = snapshot(&mut buf);
+    
+    for_each_entry(&buf[..len], &mut |_, entry_level, text| {
+        if entry_level == level {
+            out.push_str(text);
+        }
+    });
+}
+
+/// A function which writes all the entries to the serial port (oldest entry first), so the host 
+/// keeps the log after the system is shut down. It's registered as a shutdown hook by the serial 
+/// driver (early, so it runs after the other hooks).
+pub fn flush_to_serial() {
+    use core::fmt::Write;
+    
+    // Copy the ring first, since we can't allocate with the interrupts disabled.
+    let mut buf: Vec<u8> = alloc::vec![0; RING_SIZE];
+    let len = snapshot(&mut buf);
+    
+    let mut out = crate::arch::io::serial::SerialWriter;
+    for_each_entry(&buf[..len], &mut |pid, level, text| {
+        let _ = write!(out, "[{} {:?}] {}", pid, level, text);
+        if !text.ends_with('\n') {
+            let _ = out.write_str("\n");
+        }
+    });
+}
+
+/// A helper which finds the largest character boundary which is not after a given index.
+///
+/// # Parameters
+/// `text` : The text which we're cutting.
+/// `max` : The maximum index.
+///
+/// # Returns
+/// The index of the boundary (the length of the text if it's shorter).
+pub fn floor_char_boundary(text: &str, max: usize) -> usize {
+    if max >= text.len() {
+        return text.len();
+    }
+    
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    
+    end
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use crate::proc::process::{Args, SpawnFlags};
+    use crate::proc::scheduler;
+    use super::{Level, PROC_LOG_LIMIT, RING_SIZE};
+    
+    /// A PID which is never used by a real process.
+    const TEST_PID: usize = 0xFFFF_FFF0;
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_logged_output();
+        test_eviction();
+        test_trace_level();
+        test_char_boundary();
+    }
+    
+    /// A process which prints a short line, and then a lot more than it's log budget.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn chatty_process(_args: *const Args) -> usize {
+        oxid_println!("klog test start");
+        for line in 0..64 {
+            oxid_println!("klog test line {:02} which is long enough to fill the budget", line);
+        }
+        0
+    }
+    
+    /// Spawn a process which logs it's output, and make sure it's lines are logged (up to the cap).
+    fn test_logged_output() {
+        unsafe {
+            let mut args = Args::new();
+            let handle = scheduler::spawn_with_flags(chatty_process, &mut args as *mut Args,
+                "klog_test", SpawnFlags::LOG_OUTPUT);
+            
+            // Wait (for up to 2 seconds) for it to exit.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while scheduler::find_handle(handle, |_| ()).is_some() 
+                && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            
+            let mut output = String::new();
+            super::read_pid(handle.pid(), &mut output);
+            assert!(output.starts_with("klog test start\n"));
+            assert!(output.contains("klog test line 00 which"));
+            assert_eq!(output.len(), PROC_LOG_LIMIT);
+            assert!(!output.contains("klog test line 63"));
+        }
+    }
+    
+    /// Fill the ring, and make sure the oldest entries are dropped while the rest stay intact.
+    fn test_eviction() {
+        for idx in 0..(RING_SIZE / 16) {
+            super::write(TEST_PID, Level::Warn, &alloc::format!("eviction {:04}", idx));
+        }
+        
+        let mut buf = alloc::vec![0u8; RING_SIZE];
+        let len = super::snapshot(&mut buf);
+        let mut count = 0;
+        let mut first = String::new();
+        super::for_each_entry(&buf[..len], &mut |pid, level, text| {
+            if pid == TEST_PID {
+                assert_eq!(level, Level::Warn);
+                assert!(text.starts_with("eviction "));
+                if count == 0 {
+                    first.push_str(text);
+                }
+                count += 1;
+            }
+        });
+        
+        // Each entry is 19 bytes, so only the newest ones fit.
+        assert_eq!(count, RING_SIZE / 19);
+        assert_ne!(first, "eviction 0000");
+        
+        let mut last = String::new();
+        super::read_pid(TEST_PID, &mut last);
+        assert!(last.ends_with(&alloc::format!("eviction {:04}", RING_SIZE / 16 - 1)));
+    }
+    
+    /// Add a trace message, and make sure it's only found at the trace level (with a newline).
+    fn test_trace_level() {
+        super::trace(format_args!("klog trace test {}", 42));
+        
+        let mut traces = String::new();
+        super::read_level(Level::Trace, &mut traces);
+        assert!(traces.ends_with("klog trace test 42\n"));
+        
+        let mut logs = String::new();
+        super::read_level(Level::Log, &mut logs);
+        assert!(!logs.contains("klog trace test"));
+    }
+    
+    /// Make sure the long entries are cut at a character boundary.
+    fn test_char_boundary() {
+        assert_eq!(super::floor_char_boundary("abc", 10), 3);
+        assert_eq!(super::floor_char_boundary("abc", 2), 2);
+        assert_eq!(super::floor_char_boundary("aé", 2), 1);
+    }
+}
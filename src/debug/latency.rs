@@ -0,0 +1,258 @@
+//! A sub-module which collects latency histograms (in TSC cycles). The context switch latency is
+//! measured from the timer interrupt to the end of set_context, and the deferral latency from a
+//! device's top half to the start of it's bottom half. The samples are only recorded when the
+//! latency-stats feature is enabled (so the default build doesn't read the TSC for them).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::arch::time::tsc;
+
+/// The number of buckets in a histogram (bucket i holds the values below 2^i, and above the
+/// previous bucket). The last bucket holds everything which is larger.
+pub const NUM_BUCKETS: usize = 40;
+
+/// A structure which represents a histogram with log2 buckets.
+#[derive(Copy, Clone, Debug)]
+pub struct Histogram {
+    buckets: [u64; NUM_BUCKETS],               // The number of samples in each bucket.
+    count: u64,                                // The total number of samples.
+    max: u64,                                  // The largest sample.
+}
+
+impl Histogram {
+    /// The main constructor which creates an empty histogram.
+    ///
+    /// # Returns
+    /// The created histogram.
+    pub const fn new() -> Self {
+        Histogram { buckets: [0; NUM_BUCKETS], count: 0, max: 0 }
+    }
+
+    /// A function which finds the bucket of a value (0 for 0, i for 2^(i-1) to 2^i - 1).
+    ///
+    /// # Parameters
+    /// `value` : The sample.
+    ///
+    /// # Returns
+    /// The index of the bucket.
+    pub fn bucket_of(value: u64) -> usize {
+        core::cmp::min((64 - value.leading_zeros()) as usize, NUM_BUCKETS - 1)
+    }
+
+    /// A function which returns the largest value which falls in a bucket.
+    ///
+    /// # Parameters
+    /// `bucket` : The index of the bucket.
+    ///
+    /// # Returns
+    /// The upper bound (inclusive) of the bucket.
+    pub fn bucket_limit(bucket: usize) -> u64 {
+        if bucket == NUM_BUCKETS - 1 { u64::MAX } else { (1u64 << bucket) - 1 }
+    }
+
+    /// A method which adds a sample.
+    ///
+    /// # Parameters
+    /// `value` : The sample (in cycles).
+    #[inline]
+    pub fn record(&mut self, value: u64) {
+        self.buckets[Histogram::bucket_of(value)] += 1;
+        self.count += 1;
+        self.max = core::cmp::max(self.max, value);
+    }
+
+    /// A method which estimates a percentile. It returns the upper bound of the bucket which
+    /// contains it (but never more than the largest sample).
+    ///
+    /// # Parameters
+    /// `percent` : The percentile (1 to 100).
+    ///
+    /// # Returns
+    /// The estimated value, or None if there are no samples.
+    pub fn percentile(&self, percent: u64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        // The rank of the sample (rounded up, so p50 of 2 samples is the first one).
+        let rank = core::cmp::max((self.count * percent + 99) / 100, 1);
+        let mut seen: u64 = 0;
+        for (bucket, bucket_count) in self.buckets.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                return Some(core::cmp::min(Histogram::bucket_limit(bucket), self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+
+    /// A simple getter for the samples in each bucket.
+    ///
+    /// # Returns
+    /// The bucket counts.
+    pub fn buckets(&self) -> &[u64; NUM_BUCKETS] {
+        &self.buckets
+    }
+
+    /// A simple getter for the number of samples.
+    ///
+    /// # Returns
+    /// The number of samples.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// A simple getter for the largest sample.
+    ///
+    /// # Returns
+    /// The largest sample (0 if there are none).
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+}
+
+/// The context switch latency (from the timer interrupt to the end of set_context).
+static mut SWITCH: Histogram = Histogram::new();
+
+/// The deferral latency (from a top half to the start of it's bottom half).
+static mut DEFERRAL: Histogram = Histogram::new();
+
+/// The TSC when the last timer interrupt started.
+static mut SWITCH_START: u64 = 0;
+
+/// The TSC when the bottom half of each IRQ line was queued.
+static mut DEFERRED_AT: [u64; 16] = [0; 16];
+
+/// A function which is called at the start of the timer interrupt.
+#[inline(always)]
+pub fn switch_started() {
+    unsafe { SWITCH_START = tsc::read(); }
+}
+
+/// A function which is called by set_context once the next process is loaded. It's called with the
+/// interrupts disabled (in the timer interrupt).
+#[inline(always)]
+pub fn switch_done() {
+    unsafe { SWITCH.record(tsc::read().wrapping_sub(SWITCH_START)); }
+}
+
+/// A function which is called when a top half queues it's bottom half.
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+#[inline(always)]
+pub fn deferred(irq: usize) {
+    unsafe { DEFERRED_AT[irq % 16] = tsc::read(); }
+}
+
+/// A function which is called when a bottom half starts.
+///
+/// # Parameters
+/// `irq` : The IRQ line (0-15).
+#[inline(always)]
+pub fn bottom_started(irq: usize) {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        DEFERRAL.record(tsc::read().wrapping_sub(DEFERRED_AT[irq % 16]));
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which copies the histograms (with the interrupts disabled, so they are consistent).
+///
+/// # Returns
+/// A tuple of the (switch, deferral) histograms.
+pub fn snapshot() -> (Histogram, Histogram) {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let copies = (SWITCH, DEFERRAL);
+        crate::arch::interrupts::restore(were_enabled);
+
+        copies
+    }
+}
+
+/// A function which clears both of the histograms.
+pub fn reset() {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        SWITCH = Histogram::new();
+        DEFERRAL = Histogram::new();
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which checks if the latencies are collected in this build.
+///
+/// # Returns
+/// True if the latency-stats feature is enabled, False otherwise.
+pub fn is_enabled() -> bool {
+    cfg!(feature = "latency-stats")
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{Histogram, NUM_BUCKETS};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_bucketing();
+        test_percentiles();
+    }
+
+    /// Make sure the values land in the correct log2 buckets.
+    fn test_bucketing() {
+        assert_eq!(Histogram::bucket_of(0), 0);
+        assert_eq!(Histogram::bucket_of(1), 1);
+        assert_eq!(Histogram::bucket_of(2), 2);
+        assert_eq!(Histogram::bucket_of(3), 2);
+        assert_eq!(Histogram::bucket_of(4), 3);
+        assert_eq!(Histogram::bucket_of(1023), 10);
+        assert_eq!(Histogram::bucket_of(1024), 11);
+        assert_eq!(Histogram::bucket_of(u64::MAX), NUM_BUCKETS - 1);
+        assert_eq!(Histogram::bucket_limit(10), 1023);
+
+        let mut histogram = Histogram::new();
+        for value in [5, 6, 7, 100, 0].iter() {
+            histogram.record(*value);
+        }
+
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.max(), 100);
+        assert_eq!(histogram.buckets()[0], 1);
+        assert_eq!(histogram.buckets()[3], 3);
+        assert_eq!(histogram.buckets()[7], 1);
+    }
+
+    /// Feed a known distribution, and check the percentile estimates.
+    fn test_percentiles() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(50), None);
+
+        // 98 fast samples (bucket 4), one slower (bucket 8), and one outlier (bucket 12).
+        for _ in 0..98 {
+            histogram.record(10);
+        }
+        histogram.record(200);
+        histogram.record(3000);
+
+        assert_eq!(histogram.percentile(50), Some(15));
+        assert_eq!(histogram.percentile(98), Some(15));
+        assert_eq!(histogram.percentile(99), Some(255));
+        assert_eq!(histogram.percentile(100), Some(3000));
+
+        // A single sample is never estimated above itself.
+        let mut single = Histogram::new();
+        single.record(9);
+        assert_eq!(single.percentile(50), Some(9));
+    }
+}
@@ -7,3 +7,33 @@
 #![allow(dead_code)]            // So we can choose to call it or not.
 
 pub mod memview;
+pub mod trace;
+pub mod bootdiag;
+pub mod klog;
+pub mod crashlog;
+pub mod hexdump;
+pub mod latency;
+pub mod tlb_check;
+pub mod sweeper;
+pub mod statsdump;
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::trace::test::run();
+        super::bootdiag::test::run();
+        super::klog::test::run();
+        super::crashlog::test::run();
+        super::hexdump::test::run();
+        super::latency::test::run();
+        super::tlb_check::test::run();
+        super::sweeper::test::run();
+        super::statsdump::test::run();
+    }
+}
@@ -0,0 +1,292 @@
+//! A sub-module which dumps the kernel statistics in a machine readable format (for the CI and the
+//! soak rigs, which can't parse the colored text of the terminal). The statistics come from the
+//! providers which are registered with a name, and each of them writes it's values with `field`.
+//! A dump has a line per value (`provider.key=value`), bracketed by the BEGIN and END markers, so
+//! the host tooling can find it in the serial output. The newlines and the backslashes in the
+//! values are escaped (as `\n` and `\\`), so a value never spans lines. Nothing is allocated, each
+//! line is built in a buffer on the stack.
+//!
+//! A dump is written to the serial port by the `statsdump` command, or when the ENQ control byte
+//! is received on the serial port.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::fmt::{self, Write};
+use crate::olibc::bounded::{BoundedString, BoundedVec};
+use crate::proc::mutex::Mutex;
+
+/// The maximum number of providers which can be registered.
+pub const MAX_PROVIDERS: usize = 16;
+
+/// The maximum length of a line (without the provider's name), the rest of it is dropped.
+pub const LINE_MAX: usize = 192;
+
+/// The control byte which asks for a dump when it's received on the serial port (ENQ).
+pub const TRIGGER_BYTE: u8 = 0x05;
+
+/// The markers at the start of the first line, and the last line of a dump.
+pub const BEGIN_MARKER: &str = "BEGIN STATSDUMP";
+pub const END_MARKER: &str = "END STATSDUMP";
+
+/// The type of the providers (they write their values with `field`).
+pub type Provider = fn(&mut dyn Write);
+
+/// The registered providers (with their names), in the order they were registered.
+static mut PROVIDERS: BoundedVec<(&'static str, Provider), MAX_PROVIDERS> = BoundedVec::new();
+
+/// The number of dumps which were written (used as the sequence number of the next one).
+static mut DUMPS: usize = 0;
+
+/// Makes sure the dumps are not interleaved (on the serial port).
+static mut DUMP_MUTEX: Mutex = Mutex::new();
+
+/// A function which registers the built-in providers (the heap and frames, the interrupts, the
+/// scheduler, and the latency histograms).
+pub fn init() {
+    let _ = register("mem", crate::demo::fragstat::stats);
+    let _ = register("irq", crate::demo::irqstat::stats);
+    let _ = register("sched", sched_stats);
+    let _ = register("latency", crate::demo::latstat::stats);
+}
+
+/// A function which registers a provider. It's called with a writer on every dump, and the lines
+/// it writes are prefixed with it's name.
+///
+/// # Parameters
+/// `name` : The name of the provider (it should not have dots or spaces).
+/// `provider` : The function which writes the values.
+///
+/// # Returns
+/// Ok if it was registered, Err if the name is used or there is no space left.
+pub fn register(name: &'static str, provider: Provider) -> Result<(), ()> {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let result = if PROVIDERS.as_slice().iter().any(|(used, _)| *used == name) {
+            Err(())
+        } else {
+            PROVIDERS.try_push((name, provider)).map_err(|_| ())
+        };
+        crate::arch::interrupts::restore(were_enabled);
+
+        result
+    }
+}
+
+/// A function which writes a single value (as a `key=value` line). The newlines and the
+/// backslashes in the value are escaped. It's used by the providers.
+///
+/// # Parameters
+/// `out` : The writer which was passed to the provider.
+/// `key` : The name of the value (it should not have dots, spaces or an equal sign).
+/// `value` : The value.
+pub fn field(out: &mut dyn Write, key: &str, value: impl fmt::Display) {
+    let _ = write!(out, "{}=", key);
+    let _ = write!(Escaped(out), "{}", value);
+    let _ = out.write_char('\n');
+}
+
+/// A function which writes a dump of every provider to a given writer.
+///
+/// # Parameters
+/// `out` : The writer which the dump is written to.
+///
+/// # Returns
+/// The number of lines which were written (without the markers).
+pub fn dump(out: &mut dyn Write) -> usize {
+    // Copy the providers, so they can register more while running.
+    let providers = unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let providers = PROVIDERS;
+        crate::arch::interrupts::restore(were_enabled);
+        providers
+    };
+
+    unsafe {
+        DUMP_MUTEX.lock();
+        DUMPS += 1;
+        let seq = DUMPS;
+
+        let _ = writeln!(out, "{} seq={} ticks={}", BEGIN_MARKER, seq, crate::time::ticks());
+        let (mut lines, mut truncated) = (0, 0);
+        for (name, provider) in providers.as_slice() {
+            let mut writer = LineWriter { out: &mut *out, prefix: name, line: BoundedString::new(),
+                lines: 0, truncated: 0, cut: false };
+            provider(&mut writer);
+            writer.flush();
+
+            lines += writer.lines;
+            truncated += writer.truncated;
+        }
+        let _ = writeln!(out, "{} seq={} lines={} truncated={}", END_MARKER, seq, lines,
+            truncated);
+
+        DUMP_MUTEX.unlock();
+        lines
+    }
+}
+
+/// A function which writes a dump to the serial port.
+///
+/// # Returns
+/// Ok with the number of lines, or Err if there is no serial port.
+pub fn dump_to_serial() -> Result<usize, ()> {
+    if !crate::arch::io::serial::is_present() {
+        return Err(());
+    }
+
+    Ok(dump(&mut crate::arch::io::serial::SerialWriter))
+}
+
+/// The provider of the scheduler's counters (and the number of processes).
+///
+/// # Parameters
+/// `out` : The writer which the values are written to.
+fn sched_stats(out: &mut dyn Write) {
+    let counters = crate::proc::scheduler::counters();
+    field(out, "switches", counters.switches);
+    field(out, "reaped", counters.reaped);
+    field(out, "lock_overruns", counters.lock_overruns);
+    field(out, "deferred", counters.deferred);
+
+    let mut processes: usize = 0;
+    crate::proc::scheduler::for_each(&mut |_| processes += 1);
+    field(out, "processes", processes);
+}
+
+/// A writer which escapes the newlines and the backslashes (used for the values).
+struct Escaped<'a>(&'a mut dyn Write);
+
+impl Write for Escaped<'_> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for character in string.chars() {
+            match character {
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\\' => self.0.write_str("\\\\")?,
+                _ => self.0.write_char(character)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A writer which collects the output of a provider a line at a time (in a buffer on the stack),
+/// and writes each line with the provider's name in front of it.
+struct LineWriter<'a> {
+    out: &'a mut dyn Write,                 // The writer of the dump.
+    prefix: &'a str,                        // The name of the provider.
+    line: BoundedString<LINE_MAX>,          // The current line (without the prefix).
+    lines: usize,                           // The number of lines which were written.
+    truncated: usize,                       // The number of lines which were too long.
+    cut: bool,                              // True if the current line is too long.
+}
+
+impl LineWriter<'_> {
+    /// A method which writes the current line (if it's not empty), and starts a new one.
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            let _ = writeln!(self.out, "{}.{}", self.prefix, self.line.as_str());
+            self.lines += 1;
+            self.truncated += self.cut as usize;
+        }
+
+        self.line.clear();
+        self.cut = false;
+    }
+}
+
+impl Write for LineWriter<'_> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for character in string.chars() {
+            if character == '\n' {
+                self.flush();
+            } else if self.line.try_push(character).is_err() {
+                self.cut = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use core::fmt::Write;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_framing();
+        test_builtin();
+    }
+
+    /// A provider with a few plain values.
+    ///
+    /// # Parameters
+    /// `out` : The writer which the values are written to.
+    fn plain_provider(out: &mut dyn Write) {
+        super::field(out, "count", 42);
+        super::field(out, "name", "oxid");
+    }
+
+    /// A provider with values which need escaping (and a line which is too long).
+    ///
+    /// # Parameters
+    /// `out` : The writer which the values are written to.
+    fn messy_provider(out: &mut dyn Write) {
+        super::field(out, "note", "two\nlines");
+        super::field(out, "path", "a\\b");
+        super::field(out, "long", "x".repeat(super::LINE_MAX));
+    }
+
+    /// Dump the mock providers, and make sure the markers, the prefixes and the escaping are right.
+    fn test_framing() {
+        unsafe {
+            let saved = super::PROVIDERS;
+            super::PROVIDERS.clear();
+            assert_eq!(super::register("plain", plain_provider), Ok(()));
+            assert_eq!(super::register("messy", messy_provider), Ok(()));
+            assert_eq!(super::register("plain", messy_provider), Err(()));
+
+            let mut output = String::new();
+            assert_eq!(super::dump(&mut output), 5);
+            super::PROVIDERS = saved;
+
+            let lines: alloc::vec::Vec<&str> = output.lines().collect();
+            assert_eq!(lines.len(), 7);
+            assert!(lines[0].starts_with(super::BEGIN_MARKER));
+            assert_eq!(lines[1], "plain.count=42");
+            assert_eq!(lines[2], "plain.name=oxid");
+            assert_eq!(lines[3], "messy.note=two\\nlines");
+            assert_eq!(lines[4], "messy.path=a\\\\b");
+            assert_eq!(lines[5].len(), "messy.".len() + super::LINE_MAX);
+            assert!(lines[6].starts_with(super::END_MARKER));
+            assert!(lines[6].ends_with("lines=5 truncated=1"));
+
+            // The sequence number is the same in both markers.
+            let seq = lines[0].split(' ').nth(2).unwrap();
+            assert!(seq.starts_with("seq=") && lines[6].contains(seq));
+        }
+    }
+
+    /// Dump the built-in providers, and make sure each of them wrote something.
+    fn test_builtin() {
+        let mut output = String::new();
+        assert!(super::dump(&mut output) > 0);
+
+        for prefix in ["mem.", "irq.", "sched.", "latency."].iter() {
+            assert!(output.lines().any(|line| line.starts_with(prefix)));
+        }
+        assert!(output.lines().all(|line| line.contains('=') || line.starts_with("BEGIN")
+            || line.starts_with("END")));
+    }
+}
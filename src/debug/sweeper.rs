@@ -0,0 +1,572 @@
+//! A sub-module which runs the integrity checks in the background (they're usually only run when
+//! someone asks, so a regression can go unnoticed for a long time). A kernel thread (ksweeper)
+//! wakes up every period and sweeps through the selected checks: the canaries of the heap nodes,
+//! the heap lists, the stacks of the processes, and the frame audit. A sweep is made of small steps
+//! (a few node slots, a single stack, or a whole list), and each slice only runs steps until it's
+//! budget is used, so the rest of the sweep is continued on the next wakeup (shortly after). The
+//! locks are only held for a single step. The thread is only started when the kernel is built with
+//! one of the debug features, but a sweep can always be run with `sweep now`.
+//!
+//! Every finding is reported with an error and a snapshot of the kernel state (the heap, the frames
+//! and the processes), and the kernel panics instead with the strict-debug feature. The rest of a
+//! check is skipped after a finding (it's checked again on the next sweep).
+//!
+//! A whole sweep (`sweep now`) polls the running process's token between the steps (see
+//! proc::cancel), so it can be interrupted with Ctrl+C. The next sweep starts over.
+//!
+//! The heap lists and the frame audit are checked in a single step, so their time is not bounded by
+//! the budget. The audit walks every page table (and logs a summary), so it's not selected by
+//! default.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::mem::audit::AuditReport;
+use crate::mem::dyn_alloc::{self, HeapCorruption};
+use crate::proc::cancel::{CancelToken, Cancelled};
+use crate::proc::mutex::Mutex;
+use crate::proc::process::{Args, SpawnFlags};
+use crate::proc::scheduler;
+
+/// The default time between the sweeps (in milliseconds).
+pub const DEFAULT_PERIOD_MS: usize = 5000;
+
+/// The default time which a slice can run for (in milliseconds).
+pub const DEFAULT_BUDGET_MS: usize = 2;
+
+/// The time between the slices of a sweep which didn't fit in the budget (in milliseconds).
+pub const SLICE_GAP_MS: usize = 10;
+
+/// The number of node slots which have their canaries checked in each step.
+pub const CANARY_STEP: usize = 64;
+
+/// The checks which are selected by default (all of them, except the frame audit).
+pub const DEFAULT_CHECKS: u8 = Check::Canaries.bit() | Check::Lists.bit() | Check::Stacks.bit();
+
+/// An enum which represents the checks which can be run by the sweeper.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Check {
+    Canaries,                   // The canaries of the heap nodes.
+    Lists,                      // The order, merging and counts of the heap lists.
+    Stacks,                     // The high-water mark of every process stack.
+    Frames,                     // The heap, the frame bitmap and the page tables (mem::audit).
+}
+
+impl Check {
+    /// All the checks (in the order they're run in a sweep).
+    pub const ALL: [Check; 4] = [Check::Canaries, Check::Lists, Check::Stacks, Check::Frames];
+
+    /// A method which returns the name of a check (as used by the sweep command).
+    ///
+    /// # Returns
+    /// The name of the check.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Canaries => "canaries",
+            Check::Lists => "lists",
+            Check::Stacks => "stacks",
+            Check::Frames => "frames",
+        }
+    }
+
+    /// A method which finds a check from it's name.
+    ///
+    /// # Parameters
+    /// `name` : The name of the check.
+    ///
+    /// # Returns
+    /// Some(check) if it's a known name, None otherwise.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Check::ALL.iter().copied().find(|check| check.name() == name)
+    }
+
+    /// A helper which returns the bit of the check in SweepConfig::checks.
+    const fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// An enum which represents a problem which was found by a sweep.
+#[derive(Copy, Clone, Debug)]
+pub enum Finding {
+    Heap(HeapCorruption),       // A corrupted heap node or list.
+    Stack(usize, usize),        // The PID of a process which used all of it's stack, and the usage.
+    Frames(AuditReport),        // The report of an audit which was not clean.
+}
+
+/// An enum which represents the reason a whole sweep was not completed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SweepError {
+    Busy,                       // The sweeper is already running.
+    Cancelled,                  // It was cancelled (the next sweep starts over).
+}
+
+/// A structure which holds the settings of the sweeper.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SweepConfig {
+    pub period_ms: usize,       // The time between the sweeps.
+    pub budget_ms: usize,       // The time each slice can run for.
+    pub checks: u8,             // A bit for each selected check (see Check::ALL).
+    pub strict: bool,           // True if the kernel panics on the first finding.
+}
+
+impl SweepConfig {
+    /// A method which checks if a check is selected.
+    ///
+    /// # Parameters
+    /// `check` : The check.
+    ///
+    /// # Returns
+    /// True if it's run by the sweeps, False otherwise.
+    pub fn has(&self, check: Check) -> bool {
+        self.checks & check.bit() != 0
+    }
+
+    /// A method which selects (or deselects) a check.
+    ///
+    /// # Parameters
+    /// `check` : The check.
+    /// `selected` : True if it should be run by the sweeps, False otherwise.
+    pub fn set(&mut self, check: Check, selected: bool) {
+        if selected {
+            self.checks |= check.bit();
+        } else {
+            self.checks &= !check.bit();
+        }
+    }
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        SweepConfig {
+            period_ms: DEFAULT_PERIOD_MS,
+            budget_ms: DEFAULT_BUDGET_MS,
+            checks: DEFAULT_CHECKS,
+            strict: cfg!(feature = "strict-debug"),
+        }
+    }
+}
+
+/// A structure which holds the statistics of a sweeper.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SweepStats {
+    pub sweeps: usize,              // The number of sweeps which were completed.
+    pub slices: usize,              // The number of slices which were run.
+    pub findings: usize,            // The number of problems which were found.
+    pub last: Option<Finding>,      // The last problem which was found.
+}
+
+/// A structure which represents the progress of the sweeps (it's advanced a step at a time).
+pub struct Sweeper {
+    check_idx: usize,               // The index of the current check in Check::ALL.
+    cursor: usize,                  // Where the current check continues from (a slot or a PID).
+    stats: SweepStats,              // The statistics of the sweeps.
+}
+
+impl Sweeper {
+    /// A constructor which creates a sweeper at the start of a sweep.
+    ///
+    /// # Returns
+    /// The newly created sweeper.
+    pub const fn new() -> Self {
+        Sweeper {
+            check_idx: 0,
+            cursor: 0,
+            stats: SweepStats { sweeps: 0, slices: 0, findings: 0, last: None },
+        }
+    }
+
+    /// A method which runs the steps of the current sweep until it's done, or the budget is used.
+    ///
+    /// # Parameters
+    /// `config` : The settings (the selected checks and the budget).
+    ///
+    /// # Returns
+    /// True if the sweep was completed, False if it should be continued later.
+    pub fn slice(&mut self, config: &SweepConfig) -> bool {
+        let start = crate::time::ticks();
+        let budget = crate::time::ms_to_ticks(config.budget_ms);
+        self.stats.slices += 1;
+
+        loop {
+            if self.step(config) {
+                return true;
+            }
+            if crate::time::ticks() - start >= budget {
+                return false;
+            }
+        }
+    }
+
+    /// A method which runs a whole sweep from the start (ignoring the budget). The token is polled
+    /// between the steps.
+    ///
+    /// # Parameters
+    /// `config` : The settings (the selected checks).
+    /// `token` : The token which stops the sweep once it's cancelled.
+    ///
+    /// # Returns
+    /// Ok with the number of problems which were found, or Err(Cancelled) if it was cancelled.
+    pub fn sweep(&mut self, config: &SweepConfig, token: &CancelToken) -> Result<usize, Cancelled> {
+        let findings = self.stats.findings;
+        self.check_idx = 0;
+        self.cursor = 0;
+
+        loop {
+            if let Err(cancelled) = token.check() {
+                self.check_idx = 0;
+                self.cursor = 0;
+                return Err(cancelled);
+            }
+            if self.step(config) {
+                return Ok(self.stats.findings - findings);
+            }
+        }
+    }
+
+    /// A simple getter for the statistics of the sweeper.
+    ///
+    /// # Returns
+    /// A copy of the statistics.
+    pub fn stats(&self) -> SweepStats {
+        self.stats
+    }
+
+    /// A helper which runs a single step of the current check, and moves to the next check once
+    /// it's done.
+    ///
+    /// # Parameters
+    /// `config` : The settings (the selected checks).
+    ///
+    /// # Returns
+    /// True if it was the last step of the sweep, False otherwise.
+    fn step(&mut self, config: &SweepConfig) -> bool {
+        let check = Check::ALL[self.check_idx];
+        let next = if config.has(check) {
+            match run_step(check, self.cursor) {
+                Ok(next) => next,
+                Err(finding) => {
+                    self.report(check, finding, config);
+                    None
+                },
+            }
+        } else {
+            None
+        };
+
+        if let Some(cursor) = next {
+            self.cursor = cursor;
+            return false;
+        }
+
+        self.check_idx += 1;
+        self.cursor = 0;
+        if self.check_idx < Check::ALL.len() {
+            return false;
+        }
+
+        self.check_idx = 0;
+        self.stats.sweeps += 1;
+        true
+    }
+
+    /// A helper which records a finding, and reports it with a snapshot of the kernel state.
+    ///
+    /// # Parameters
+    /// `check` : The check which found it.
+    /// `finding` : The problem which was found.
+    /// `config` : The settings (it panics if it's strict).
+    fn report(&mut self, check: Check, finding: Finding, config: &SweepConfig) {
+        self.stats.findings += 1;
+        self.stats.last = Some(finding);
+
+        oxid_err!("Sweep: The {} check failed: {:?}", check.name(), finding);
+        log_snapshot();
+
+        if config.strict {
+            panic!("The {} check failed (strict-debug): {:?}", check.name(), finding);
+        }
+    }
+}
+
+/// The settings of the kernel's sweeper (only changed with the interrupts disabled).
+static mut CONFIG: SweepConfig = SweepConfig {
+    period_ms: DEFAULT_PERIOD_MS,
+    budget_ms: DEFAULT_BUDGET_MS,
+    checks: DEFAULT_CHECKS,
+    strict: cfg!(feature = "strict-debug"),
+};
+
+/// The kernel's sweeper (used by the kernel thread, and by `sweep now`).
+static mut SWEEPER: Sweeper = Sweeper::new();
+
+/// A mutex which is held while the kernel's sweeper is running (it's only tried, never spun on).
+static mut SWEEPER_MUTEX: Mutex = Mutex::new();
+
+/// The PID of the ksweeper thread (None if it was not started).
+static mut SWEEPER_PID: Option<usize> = None;
+
+/// A function which starts the ksweeper kernel thread (which can't be killed). It should be called
+/// after the scheduler is initialized, and only when the kernel is built with a debug feature.
+pub fn start() {
+    unsafe {
+        // The arguments are copied by the scheduler, so they can live on the stack.
+        let mut args = Args::new();
+        SWEEPER_PID = Some(scheduler::spawn_with_flags(ksweeper, &mut args as *mut Args,
+            "ksweeper", SpawnFlags::KERNEL_SERVICE | SpawnFlags::NO_KILL).pid());
+    }
+
+    oxid_log!("Started the integrity sweeper (period={}ms, budget={}ms).", config().period_ms,
+        config().budget_ms);
+}
+
+/// The main function of the ksweeper kernel thread. It runs a slice of the sweep, and then sleeps
+/// until the next slice (or the next sweep, if it was completed).
+///
+/// # Parameters
+/// `_args` : The arguments passed (not used).
+pub extern "sysv64" fn ksweeper(_args: *const Args) -> usize {
+    loop {
+        let config = config();
+        let done = with_sweeper(|sweeper| sweeper.slice(&config)).unwrap_or(false);
+
+        crate::time::sleep_ms(if done { config.period_ms } else { SLICE_GAP_MS });
+    }
+}
+
+/// A function which runs a whole sweep with the kernel's sweeper right away (the sweep which was
+/// in progress is started over). It's stopped once the running process's token is cancelled.
+///
+/// # Returns
+/// Ok(findings) with the number of problems which were found, Err(Busy) if a sweep is already
+/// running, or Err(Cancelled) if it was cancelled.
+pub fn sweep_now() -> Result<usize, SweepError> {
+    let config = config();
+    let token = crate::proc::cancel::current();
+    match with_sweeper(|sweeper| sweeper.sweep(&config, token)) {
+        Ok(result) => result.map_err(|_| SweepError::Cancelled),
+        Err(()) => Err(SweepError::Busy),
+    }
+}
+
+/// A simple getter for the settings of the kernel's sweeper.
+///
+/// # Returns
+/// A copy of the settings.
+pub fn config() -> SweepConfig {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let config = CONFIG;
+        crate::arch::interrupts::restore(were_enabled);
+
+        config
+    }
+}
+
+/// A function which changes the settings of the kernel's sweeper (they're used from the next
+/// slice).
+///
+/// # Parameters
+/// `config` : The new settings.
+pub fn set_config(config: &SweepConfig) {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        CONFIG = *config;
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which returns the statistics of the kernel's sweeper.
+///
+/// # Returns
+/// Some(stats), or None if it's running (so they're changing).
+pub fn stats() -> Option<SweepStats> {
+    with_sweeper(|sweeper| sweeper.stats()).ok()
+}
+
+/// A simple getter for the PID of the ksweeper thread.
+///
+/// # Returns
+/// Some(pid) if it was started, None otherwise.
+pub fn sweeper_pid() -> Option<usize> {
+    unsafe { SWEEPER_PID }
+}
+
+/// A function which logs a snapshot of the kernel state (the heap, the frames and the processes),
+/// so a finding can be matched to what was running.
+pub fn log_snapshot() {
+    dyn_alloc::log_stats();
+    oxid_log!("Frames: {} of {} used.", crate::mem::frame_alloc::used_count(),
+        crate::mem::frame_alloc::total_count());
+
+    // Copy the processes first (nothing is printed while the list is locked).
+    let mut procs: Vec<(usize, String, u64)> = Vec::new();
+    scheduler::for_each(&mut |pcb| procs.push((pcb.pid, pcb.name.clone(), pcb.cpu_ticks)));
+    for (pid, name, cpu_ticks) in procs.iter() {
+        oxid_log!("Process PID={} ({}): {} ticks.", pid, name, cpu_ticks);
+    }
+}
+
+/// A helper which runs a function on the kernel's sweeper, if it's not already in use.
+///
+/// # Parameters
+/// `func` : The function which is run.
+///
+/// # Returns
+/// Ok(result) of the function, or Err if the sweeper is in use.
+fn with_sweeper<T>(func: impl FnOnce(&mut Sweeper) -> T) -> Result<T, ()> {
+    unsafe {
+        if !SWEEPER_MUTEX.try_lock() {
+            return Err(());
+        }
+
+        let result = func(&mut SWEEPER);
+        SWEEPER_MUTEX.release();
+        Ok(result)
+    }
+}
+
+/// A helper which runs a single step of a check.
+///
+/// # Parameters
+/// `check` : The check.
+/// `cursor` : Where the check continues from (0 at the start).
+///
+/// # Returns
+/// Ok(Some(cursor)) if the check should be continued from the cursor, Ok(None) if it's done, or
+/// Err(finding) if a problem was found.
+fn run_step(check: Check, cursor: usize) -> Result<Option<usize>, Finding> {
+    match check {
+        Check::Canaries => dyn_alloc::check_canaries(cursor, CANARY_STEP).map_err(Finding::Heap),
+        Check::Lists => dyn_alloc::check_integrity().map(|_| None).map_err(Finding::Heap),
+        Check::Stacks => {
+            // Find the process with the lowest PID from the cursor.
+            let mut next_pid: Option<usize> = None;
+            scheduler::for_each(&mut |pcb| {
+                if pcb.pid >= cursor && next_pid.map_or(true, |pid| pcb.pid < pid) {
+                    next_pid = Some(pcb.pid);
+                }
+            });
+
+            let pid = match next_pid {
+                Some(pid) => pid,
+                None => return Ok(None),
+            };
+            match scheduler::stack_usage(pid) {
+                Some((used, size)) if used >= size => Err(Finding::Stack(pid, used)),
+                _ => Ok(Some(pid + 1)),
+            }
+        },
+        Check::Frames => {
+            let report = unsafe { crate::mem::audit::audit() };
+            if report.is_clean() { Ok(None) } else { Err(Finding::Frames(report)) }
+        },
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{Check, Finding, SweepConfig, Sweeper};
+    use crate::mem::dyn_alloc::HeapCorruption;
+    use crate::proc::cancel::{CancelToken, Cancelled};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_config();
+        test_slices();
+        test_canary_found();
+        test_cancelled();
+    }
+
+    /// Make sure the checks can be found by name, and selected one at a time.
+    fn test_config() {
+        let mut config = SweepConfig::default();
+        assert!(config.has(Check::Canaries) && !config.has(Check::Frames));
+
+        for check in Check::ALL.iter() {
+            assert_eq!(Check::from_name(check.name()), Some(*check));
+        }
+        assert_eq!(Check::from_name("bogus"), None);
+
+        config.set(Check::Frames, true);
+        config.set(Check::Lists, false);
+        assert!(config.has(Check::Frames) && !config.has(Check::Lists));
+    }
+
+    /// Run a sweep with an empty budget (so every slice is a single step), and make sure it's
+    /// spread over a few slices, and the heap is clean.
+    fn test_slices() {
+        let config = SweepConfig { budget_ms: 0, strict: false, ..SweepConfig::default() };
+        let mut sweeper = Sweeper::new();
+
+        let mut slices: usize = 1;
+        while !sweeper.slice(&config) {
+            slices += 1;
+        }
+
+        // The free and used nodes, the lists, and at least the IDLE process's stack.
+        let stats = sweeper.stats();
+        assert!(slices >= 4);
+        assert_eq!((stats.sweeps, stats.slices, stats.findings), (1, slices, 0));
+        assert_eq!(sweeper.sweep(&config, &CancelToken::new()), Ok(0));
+        assert_eq!(sweeper.stats().sweeps, 2);
+    }
+
+    /// Overwrite the canary of a kernel heap allocation, and make sure it's reported within two
+    /// periods (the kernel's sweeper is held, so only the private one can find it).
+    fn test_canary_found() {
+        let config = SweepConfig { strict: false, ..SweepConfig::default() };
+        let mut sweeper = Sweeper::new();
+
+        unsafe {
+            while !super::SWEEPER_MUTEX.try_lock() {
+                crate::time::sleep_ms(super::SLICE_GAP_MS);
+            }
+
+            let ptr = crate::mem::dyn_alloc::kmalloc(crate::mem::vmm::PAGE_SIZE * 2, false,
+                true, true);
+            assert!(crate::mem::dyn_alloc::test::flip_canary(ptr));
+
+            for _ in 0..2 {
+                while !sweeper.slice(&config) {}
+                if sweeper.stats().findings > 0 {
+                    break;
+                }
+            }
+
+            assert!(crate::mem::dyn_alloc::test::flip_canary(ptr));
+            crate::mem::dyn_alloc::kfree(ptr);
+            super::SWEEPER_MUTEX.release();
+        }
+
+        let stats = sweeper.stats();
+        assert!(stats.findings > 0 && stats.sweeps <= 2);
+        assert!(matches!(stats.last, Some(Finding::Heap(HeapCorruption::Canary("used", _)))));
+        assert_eq!(sweeper.sweep(&config, &CancelToken::new()), Ok(0));
+    }
+
+    /// Cancel a sweep with a deadline which already passed, and make sure it's not counted (and
+    /// the next one starts over and completes).
+    fn test_cancelled() {
+        let config = SweepConfig { strict: false, ..SweepConfig::default() };
+        let mut sweeper = Sweeper::new();
+        let token = CancelToken::new();
+
+        token.set_deadline(Some(crate::time::ticks()));
+        assert_eq!(sweeper.sweep(&config, &token), Err(Cancelled));
+        assert_eq!((sweeper.stats().sweeps, sweeper.check_idx, sweeper.cursor), (0, 0, 0));
+
+        token.reset();
+        assert_eq!(sweeper.sweep(&config, &token), Ok(0));
+        assert_eq!(sweeper.stats().sweeps, 1);
+    }
+}
@@ -0,0 +1,358 @@
+//! A sub-module which checks the page tables against a shadow copy of the mappings, to catch the
+//! bugs where an entry is changed without the TLB being updated (they usually work in QEMU, but
+//! not on real hardware). Every change which is made by the vmm is recorded in the shadow (the
+//! frame and the permissions of the page), along with the code which made it. The checker reads
+//! the live entries of a random sample of the recorded pages (through the self-ref window), and
+//! reports the ones which don't match, since they were changed behind the vmm's back. Separately,
+//! every permission downgrade is probed with a write, which should fault if the page was
+//! invalidated. The vmm only calls it with the tlb-check feature, and the responsible call sites
+//! are printed when the vmm is traced.
+//!
+//! The shadow has a fixed size (it's updated while the heap maps it's pages), so the pages which
+//! don't fit are not checked. The huge pages are not recorded.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::panic::Location;
+use crate::arch::mem::page_tables::PageMapping;
+use crate::mem::addr::VirtAddr;
+use crate::mem::vmm::PAGE_SIZE;
+
+/// The number of entries in the shadow (a power of two).
+pub const SHADOW_SIZE: usize = 2048;
+
+/// The number of pages which are checked after each change (and in each periodic check).
+pub const SAMPLE_SIZE: usize = 8;
+
+/// The time between the periodic checks (in milliseconds).
+pub const CHECK_MS: usize = 1000;
+
+/// A structure which represents a page in the shadow.
+#[derive(Copy, Clone)]
+struct ShadowEntry {
+    page: usize,                                // The address of the page.
+    mapping: PageMapping,                       // The mapping it should have.
+    site: &'static Location<'static>,           // The code which made the last change.
+}
+
+/// A structure which holds the statistics of the checker.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TlbCheckStats {
+    pub tracked: usize,             // The number of pages in the shadow.
+    pub dropped: usize,             // The number of changes which didn't fit in the shadow.
+    pub mismatches: usize,          // The number of entries which didn't match the shadow.
+    pub stale: usize,               // The number of downgrades which were not enforced.
+}
+
+/// The recorded pages (an open addressing hash table, with linear probing).
+static mut SHADOW: [Option<ShadowEntry>; SHADOW_SIZE] = [None; SHADOW_SIZE];
+
+/// The statistics of the checker.
+static mut STATS: TlbCheckStats = TlbCheckStats { tracked: 0, dropped: 0, mismatches: 0, stale: 0 };
+
+/// The state of the generator which chooses the sampled entries (xorshift).
+static mut SAMPLE_STATE: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// A function which records the current mapping of a page (after it was changed by the vmm), and
+/// then checks a sample of the shadow. A page which is not mapped is removed instead.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which was changed.
+#[track_caller]
+pub fn track(page_addr: VirtAddr) {
+    let page = page_addr.align_down(PAGE_SIZE).as_usize();
+    let mapping = match crate::mem::vmm::mapping(page_addr) {
+        Ok(mapping) => mapping,
+        Err(()) => return forget(page_addr),
+    };
+
+    let site = Location::caller();
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        match find_slot(page) {
+            Some(slot) => {
+                if SHADOW[slot].is_none() {
+                    STATS.tracked += 1;
+                }
+                SHADOW[slot] = Some(ShadowEntry { page, mapping, site });
+            },
+            None => STATS.dropped += 1,
+        }
+        crate::arch::interrupts::restore(were_enabled);
+    }
+
+    check_sample(SAMPLE_SIZE);
+}
+
+/// A function which removes a page from the shadow (after it was unmapped by the vmm), and then
+/// checks a sample of the shadow.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which was unmapped.
+pub fn forget(page_addr: VirtAddr) {
+    let page = page_addr.align_down(PAGE_SIZE).as_usize();
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if let Some(slot) = find_slot(page) {
+            if SHADOW[slot].is_some() {
+                remove_slot(slot);
+                STATS.tracked -= 1;
+            }
+        }
+        crate::arch::interrupts::restore(were_enabled);
+    }
+
+    check_sample(SAMPLE_SIZE);
+}
+
+/// A function which compares the live entries of a random sample of the shadow with the recorded
+/// mappings, and reports the ones which don't match.
+///
+/// # Parameters
+/// `count` : The number of recorded pages which are checked.
+///
+/// # Returns
+/// The number of pages which didn't match.
+pub fn check_sample(count: usize) -> usize {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+
+        // Check the recorded pages which follow a random slot.
+        let start = next_sample() as usize % SHADOW_SIZE;
+        let (mut checked, mut found) = (0, 0);
+        for offset in 0..SHADOW_SIZE {
+            if checked == count {
+                break;
+            }
+
+            if let Some(entry) = SHADOW[(start + offset) % SHADOW_SIZE] {
+                checked += 1;
+                found += check_entry(&entry) as usize;
+            }
+        }
+
+        crate::arch::interrupts::restore(were_enabled);
+        found
+    }
+}
+
+/// A function which compares the live entries of every page in the shadow with the recorded
+/// mappings, and reports the ones which don't match.
+///
+/// # Returns
+/// The number of pages which didn't match.
+pub fn check_all() -> usize {
+    check_sample(SHADOW_SIZE)
+}
+
+/// A function which makes sure a page which was made read-only can't be written anymore. The
+/// current value is written back, so nothing is changed if the write goes through (which means
+/// the TLB still has the old writable entry).
+///
+/// # Parameters
+/// `page_addr` : The address of the page which was made read-only.
+///
+/// # Returns
+/// True if the write faulted (it's enforced), False otherwise.
+#[track_caller]
+pub fn verify_read_only(page_addr: VirtAddr) -> bool {
+    let addr = page_addr.align_down(PAGE_SIZE).as_usize();
+    unsafe {
+        let value = core::ptr::read_volatile(addr as *const u8);
+        if crate::arch::mem::probe::try_write(addr, value).is_err() {
+            return true;
+        }
+
+        STATS.stale += 1;
+    }
+
+    oxid_warn!("TLB check: page 0x{:x} is still writable after it was made read-only.", addr);
+    oxid_dbg!(Vmm, "TLB check: page 0x{:x} was made read-only at {}.", addr, Location::caller());
+    false
+}
+
+/// A function which starts the periodic checks (every CHECK_MS). It should be called after the
+/// work queue is initialized.
+pub fn start() {
+    if crate::proc::workqueue::queue_work_delayed(CHECK_MS, periodic_check, 0).is_err() {
+        oxid_warn!("Could not start the periodic TLB checks.");
+    }
+}
+
+/// A simple getter for the statistics of the checker.
+///
+/// # Returns
+/// A copy of the statistics.
+pub fn stats() -> TlbCheckStats {
+    unsafe { STATS }
+}
+
+/// A function which checks a sample of the shadow, and queues the next check. It's run by the
+/// work queue.
+///
+/// # Parameters
+/// `_arg` : The argument passed by the work queue (not used).
+fn periodic_check(_arg: usize) {
+    check_sample(SAMPLE_SIZE);
+    let _ = crate::proc::workqueue::queue_work_delayed(CHECK_MS, periodic_check, 0);
+}
+
+/// A helper which compares the live entry of a recorded page with it's mapping, and reports it if
+/// it doesn't match.
+///
+/// # Parameters
+/// `entry` : The recorded page.
+///
+/// # Returns
+/// True if it didn't match, False otherwise.
+unsafe fn check_entry(entry: &ShadowEntry) -> bool {
+    let live = VirtAddr::new(entry.page).ok().and_then(|page| crate::mem::vmm::mapping(page).ok());
+    if live == Some(entry.mapping) {
+        return false;
+    }
+
+    STATS.mismatches += 1;
+    match live {
+        Some(live) => oxid_warn!("TLB check: page 0x{:x} is mapped to 0x{:x} (writable={}), \
+            expected 0x{:x} (writable={}).", entry.page, live.frame_addr, live.is_writable,
+            entry.mapping.frame_addr, entry.mapping.is_writable),
+        None => oxid_warn!("TLB check: page 0x{:x} is not mapped, expected 0x{:x}.", entry.page,
+            entry.mapping.frame_addr),
+    }
+    oxid_dbg!(Vmm, "TLB check: page 0x{:x} was last changed at {}.", entry.page, entry.site);
+    true
+}
+
+/// A helper which finds the slot of a page in the shadow (the interrupts should be disabled).
+///
+/// # Parameters
+/// `page` : The address of the page.
+///
+/// # Returns
+/// Some(slot) which holds the page (or the empty slot where it belongs), None if it's full.
+unsafe fn find_slot(page: usize) -> Option<usize> {
+    let start = hash(page);
+    for offset in 0..SHADOW_SIZE {
+        let slot = (start + offset) % SHADOW_SIZE;
+        match SHADOW[slot] {
+            Some(entry) if entry.page != page => continue,
+            _ => return Some(slot),
+        }
+    }
+
+    None
+}
+
+/// A helper which empties a slot, and moves the entries after it back (so none of them are cut
+/// off from their hash by the empty slot). The interrupts should be disabled.
+///
+/// # Parameters
+/// `slot` : The slot which is emptied.
+unsafe fn remove_slot(mut slot: usize) {
+    SHADOW[slot] = None;
+    let mut next = (slot + 1) % SHADOW_SIZE;
+    while let Some(entry) = SHADOW[next] {
+        // Move it to the empty slot, unless it's hash is between the empty slot and it.
+        let home = hash(entry.page);
+        let distance = |from: usize, to: usize| (to + SHADOW_SIZE - from) % SHADOW_SIZE;
+        if distance(home, next) >= distance(slot, next) {
+            SHADOW[slot] = Some(entry);
+            SHADOW[next] = None;
+            slot = next;
+        }
+        next = (next + 1) % SHADOW_SIZE;
+    }
+}
+
+/// A helper which finds the preferred slot of a page (fibonacci hashing of the page number).
+///
+/// # Parameters
+/// `page` : The address of the page.
+///
+/// # Returns
+/// The slot which is tried first.
+fn hash(page: usize) -> usize {
+    let bits = SHADOW_SIZE.trailing_zeros();
+    ((page / PAGE_SIZE).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (usize::BITS - bits)) as usize
+}
+
+/// A helper which generates the next random number for the samples (xorshift64).
+///
+/// # Returns
+/// The random number.
+unsafe fn next_sample() -> u64 {
+    SAMPLE_STATE ^= SAMPLE_STATE << 13;
+    SAMPLE_STATE ^= SAMPLE_STATE >> 7;
+    SAMPLE_STATE ^= SAMPLE_STATE << 17;
+    SAMPLE_STATE
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::mem::addr::VirtAddr;
+
+    /// An unused address in the higher half (it's not used by the other tests at the same time).
+    const PAGE: usize = 0xFFFF_FE00_0010_0000;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_shadow();
+        test_stale_downgrade();
+    }
+
+    /// Record a page, and make sure a change which is made behind the vmm's back is reported (and
+    /// the unmapped pages are removed from the shadow).
+    fn test_shadow() {
+        let page = VirtAddr::new(PAGE).unwrap();
+
+        unsafe {
+            let before = super::stats();
+            assert!(crate::mem::vmm::map(page, false, true, true).is_ok());
+            super::track(page);
+            assert_eq!(super::stats().tracked, before.tracked + 1);
+            assert_eq!(super::check_all(), 0);
+
+            // Change the entry without recording it (a missing update).
+            assert!(crate::arch::mem::page_tables::PageTables::protect(page, false).is_ok());
+            assert_eq!(super::check_all(), 1);
+            assert_eq!(super::stats().mismatches, before.mismatches + 1);
+
+            // Once it's recorded it matches again, and it's removed once it's unmapped.
+            super::track(page);
+            assert_eq!(super::check_all(), 0);
+            assert!(crate::mem::vmm::unmap(page).is_ok());
+            super::forget(page);
+            assert_eq!(super::stats().tracked, before.tracked);
+        }
+    }
+
+    /// Skip the invalidation of a downgrade (after the writable entry is cached), and make sure
+    /// the write still goes through and is flagged. It's enforced once the page is invalidated.
+    fn test_stale_downgrade() {
+        let page = VirtAddr::new(PAGE).unwrap();
+
+        unsafe {
+            let before = super::stats();
+            assert!(crate::mem::vmm::map(page, false, true, true).is_ok());
+            core::ptr::write_volatile(PAGE as *mut u8, 0x5A);
+
+            crate::arch::mem::tlb::skip_next_invalidation();
+            assert!(crate::mem::vmm::protect(page, false).is_ok());
+            assert!(!super::verify_read_only(page));
+            assert_eq!(super::stats().stale, before.stale + 1);
+            assert_eq!(core::ptr::read_volatile(PAGE as *const u8), 0x5A);
+
+            assert!(crate::arch::mem::tlb::invalidate_page(PAGE).is_ok());
+            assert!(super::verify_read_only(page));
+            assert!(crate::mem::vmm::unmap(page).is_ok());
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! A sub-module which provides the runtime switches for the oxid_dbg! trace macro. Every trace
+//! message belongs to a subsystem, and each subsystem can be turned on or off at runtime (from the
+//! kernel command line with `trace=heap,vmm`, or from the terminal with the trace program). The
+//! trace messages are only compiled in when the trace feature is enabled.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::multiboot2::MultibootInfo;        // To read the trace option from the command line.
+
+/// An enum which represents the subsystems which can be traced separately.
+#[repr(usize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Subsystem {
+    Heap,                                    // The kernel heap (dyn_alloc).
+    Frames,                                  // The physical frame allocator.
+    Vmm,                                     // The virtual memory manager and page tables.
+    Sched,                                   // The scheduler.
+    Irq,                                     // The interrupt handling code.
+    Kbd,                                     // The keyboard driver.
+}
+
+/// A list of all the subsystems (used for parsing and printing).
+pub const ALL_SUBSYSTEMS: [Subsystem; 6] = [Subsystem::Heap, Subsystem::Frames, Subsystem::Vmm,
+    Subsystem::Sched, Subsystem::Irq, Subsystem::Kbd];
+
+/// The bitmask of the subsystems which are currently traced (everything is off by default).
+static mut TRACE_MASK: usize = 0;
+
+impl Subsystem {
+    /// A method which returns the bit which represents this subsystem in the trace mask.
+    ///
+    /// # Returns
+    /// The mask with only this subsystem's bit set.
+    #[inline]
+    pub fn bit(&self) -> usize {
+        1 << (*self as usize)
+    }
+
+    /// A method which returns the name used for this subsystem (in the command line and messages).
+    ///
+    /// # Returns
+    /// The lowercase name of the subsystem.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsystem::Heap => "heap",
+            Subsystem::Frames => "frames",
+            Subsystem::Vmm => "vmm",
+            Subsystem::Sched => "sched",
+            Subsystem::Irq => "irq",
+            Subsystem::Kbd => "kbd",
+        }
+    }
+
+    /// A function which finds a subsystem based on it's name.
+    ///
+    /// # Parameters
+    /// `name` : The name of the subsystem (as returned by name()).
+    ///
+    /// # Returns
+    /// Some(subsystem) if the name is valid, None otherwise.
+    pub fn from_name(name: &str) -> Option<Subsystem> {
+        ALL_SUBSYSTEMS.iter().find(|subsys| subsys.name() == name).copied()
+    }
+}
+
+/// A function which checks if a given subsystem is currently being traced.
+///
+/// # Parameters
+/// `subsys` : The subsystem which we're checking.
+///
+/// # Returns
+/// True if the trace messages should be printed, False otherwise.
+#[inline]
+pub fn is_enabled(subsys: Subsystem) -> bool {
+    unsafe { TRACE_MASK & subsys.bit() != 0 }
+}
+
+/// A function which turns on the tracing for a given subsystem.
+///
+/// # Parameters
+/// `subsys` : The subsystem which we're tracing.
+pub fn enable(subsys: Subsystem) {
+    unsafe { TRACE_MASK |= subsys.bit(); }
+}
+
+/// A function which turns off the tracing for a given subsystem.
+///
+/// # Parameters
+/// `subsys` : The subsystem which we're not tracing anymore.
+pub fn disable(subsys: Subsystem) {
+    unsafe { TRACE_MASK &= !subsys.bit(); }
+}
+
+/// A simple getter for the current trace mask.
+///
+/// # Returns
+/// The bitmask of the traced subsystems.
+pub fn get_mask() -> usize {
+    unsafe { TRACE_MASK }
+}
+
+/// A simple setter for the trace mask (replaces all the previous switches).
+///
+/// # Parameters
+/// `mask` : The new bitmask of the traced subsystems.
+pub fn set_mask(mask: usize) {
+    unsafe { TRACE_MASK = mask; }
+}
+
+/// A function which parses a comma separated list of subsystem names (or "all") into a mask.
+///
+/// # Parameters
+/// `list` : The list of names such as "heap,vmm".
+///
+/// # Returns
+/// Ok(mask) if every name was valid, Err otherwise.
+pub fn parse_list(list: &str) -> Result<usize, ()> {
+    let mut mask: usize = 0;
+
+    // Go through every name and add it's bit to the mask.
+    for name in list.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+        if name == "all" {
+            mask |= ALL_SUBSYSTEMS.iter().fold(0, |acc, subsys| acc | subsys.bit());
+        } else {
+            mask |= Subsystem::from_name(name).ok_or(())?.bit();
+        }
+    }
+
+    Ok(mask)
+}
+
+/// A function which initializes the trace switches from the `trace=` kernel command line option.
+///
+/// # Parameters
+/// `mb_info` : The multiboot information reference (already parsed).
+pub fn init(mb_info: &MultibootInfo) {
+    // Get the option from the command line (if it was passed).
+    let list = match mb_info.boot_cmd_tag.as_ref().and_then(|cmd| cmd.get("trace")) {
+        Some(list) => list,
+        None => return,
+    };
+
+    // Parse it and set the mask.
+    match parse_list(list) {
+        Ok(mask) => set_mask(mask),
+        Err(()) => oxid_warn!("Invalid trace option passed \"{}\". Ignoring it.", list),
+    }
+
+    // Let the user know if tracing was requested but it's not compiled in.
+    if !crate::features::has("trace") {
+        oxid_warn!("Tracing was requested, but the trace feature is not enabled.");
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::Subsystem;
+    use crate::multiboot2::boot_cmd::BootCmd;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        // Store the mask to restore it after the tests.
+        let prev_mask = super::get_mask();
+
+        test_toggle();
+        test_parse_list();
+        test_cmdline();
+
+        super::set_mask(prev_mask);
+    }
+
+    /// Turn the subsystems on and off and check that they don't affect each other.
+    fn test_toggle() {
+        super::set_mask(0);
+
+        super::enable(Subsystem::Heap);
+        super::enable(Subsystem::Kbd);
+        assert!(super::is_enabled(Subsystem::Heap));
+        assert!(super::is_enabled(Subsystem::Kbd));
+        assert!(!super::is_enabled(Subsystem::Vmm));
+
+        super::disable(Subsystem::Heap);
+        assert!(!super::is_enabled(Subsystem::Heap));
+        assert!(super::is_enabled(Subsystem::Kbd));
+    }
+
+    /// Check the parsing of the subsystem lists.
+    fn test_parse_list() {
+        assert_eq!(super::parse_list("heap,vmm"),
+            Ok(Subsystem::Heap.bit() | Subsystem::Vmm.bit()));
+        assert_eq!(super::parse_list(""), Ok(0));
+        assert_eq!(super::parse_list("all"), Ok(0b111111));
+        assert_eq!(super::parse_list("heap,bogus"), Err(()));
+    }
+
+    /// Check that the trace option is read from the command line.
+    fn test_cmdline() {
+        let cmd = BootCmd::from_str("quiet trace=frames,sched");
+        assert!(cmd.has_flag("quiet"));
+        assert_eq!(cmd.get("trace"), Some("frames,sched"));
+        assert_eq!(cmd.get("heap"), None);
+
+        super::set_mask(super::parse_list(cmd.get("trace").unwrap()).unwrap());
+        assert!(super::is_enabled(Subsystem::Frames));
+        assert!(super::is_enabled(Subsystem::Sched));
+        assert!(!super::is_enabled(Subsystem::Heap));
+    }
+}
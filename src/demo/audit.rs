@@ -0,0 +1,29 @@
+//! A basic program which audits the kernel memory (heap, frames, and page tables) and prints any
+//! inconsistencies that it finds. For debugging purposes.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    unsafe {
+        oxid_println!();
+        
+        // Run the audit, and let the user know if everything was consistent.
+        if crate::mem::audit::audit().is_clean() {
+            oxid_println!("No inconsistencies found.");
+            0
+        } else {
+            oxid_err!("The memory state is inconsistent.");
+            1
+        }
+    }
+}
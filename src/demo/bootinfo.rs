@@ -0,0 +1,40 @@
+//! A basic program which prints the boot diagnostics: the boot device, the reason for the last
+//! reset, and if the previous shutdown was clean.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+use crate::debug::bootdiag;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    oxid_println!();
+
+    // Make sure the diagnostics were collected.
+    let diag = match bootdiag::get() {
+        Some(diag) => diag,
+        None => {
+            oxid_err!("The boot diagnostics were not collected.");
+            return 1;
+        }
+    };
+
+    match diag.boot_dev {
+        Some(dev) => oxid_println!("Boot device: {}", bootdiag::describe_boot_dev(&dev)),
+        None => oxid_println!("Boot device: unknown"),
+    }
+    oxid_println!("Reset reason: {} (0x{:x})", bootdiag::shutdown_status_name(diag.shutdown_status),
+        diag.shutdown_status);
+    oxid_println!("Reset type: {}", if diag.warm_boot { "warm" } else { "cold" });
+    oxid_println!("Previous shutdown: {}", bootdiag::prev_shutdown_name(diag.prev_shutdown));
+    oxid_println!("Identity mapped: 0x{:x} bytes by boot, 0x0-0x{:x} by the kernel", 
+        crate::mem::vmm::boot_id_mapped_size(), crate::mem::vmm::id_map_end());
+    0
+}
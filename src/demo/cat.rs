@@ -0,0 +1,151 @@
+//! A basic program which prints files from the initrd (`cat <path>...`). The bytes which can't be
+//! printed are shown as '.'. The files are printed a chunk at a time, and the running process's
+//! token is polled between the chunks (see proc::cancel), so a large file can be interrupted with
+//! Ctrl+C.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::Write;
+use crate::olibc::bounded::BoundedString;
+use crate::proc::cancel::{CancelToken, Cancelled, INTERRUPTED_STATUS};
+use crate::proc::process::Args;
+
+/// The number of bytes which are printed between the polls of the token.
+pub const CHUNK_SIZE: usize = 512;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed, or INTERRUPTED_STATUS if it was interrupted).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("cat: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    if full_args.len() < 2 {
+        oxid_err!("Usage: cat <path>...");
+        return 1;
+    }
+
+    let mut code = 0;
+    for arg in &full_args[1..] {
+        let data = match crate::fs::path::resolve(arg.trim()).ok()
+            .and_then(|path| crate::fs::initrd::read(&path)) {
+            Some(data) => data,
+            None => {
+                oxid_err!("cat: {}: No such file.", arg.trim());
+                code = 1;
+                continue;
+            },
+        };
+
+        let token = crate::proc::cancel::current();
+        if copy(data, &mut crate::debug::hexdump::Console, token).is_err() {
+            return INTERRUPTED_STATUS as usize;
+        }
+    }
+    code
+}
+
+/// A function which writes the contents of a file a chunk at a time, and polls a token before each
+/// chunk.
+///
+/// # Parameters
+/// `data` : The contents of the file.
+/// `out` : The writer which it's written to.
+/// `token` : The token which is polled.
+///
+/// # Returns
+/// Ok with the number of bytes which were written, or Err(Cancelled) if it was cancelled.
+pub fn copy(data: &[u8], out: &mut dyn Write, token: &CancelToken) -> Result<usize, Cancelled> {
+    let mut written: usize = 0;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        token.check()?;
+
+        let mut text: BoundedString<CHUNK_SIZE> = BoundedString::new();
+        for byte in chunk.iter().copied() {
+            let printable = byte == b'\n' || byte == b'\t' || (0x20..0x7F).contains(&byte);
+            text.push(if printable { byte as char } else { '.' });
+        }
+
+        let _ = out.write_str(text.as_str());
+        written += chunk.len();
+    }
+
+    Ok(written)
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use core::fmt::{self, Write};
+    use crate::proc::cancel::{CancelToken, Cancelled};
+    use super::CHUNK_SIZE;
+
+    /// The contents of the large test file (a few chunks).
+    static LARGE: [u8; CHUNK_SIZE * 4] = [b'x'; CHUNK_SIZE * 4];
+
+    /// A writer which cancels a token once it was written to a few times (like Ctrl+C in the
+    /// middle of the output).
+    struct CancelAfter<'a> {
+        token: &'a CancelToken,     // The token which is cancelled.
+        cancel_at: Option<usize>,   // The number of writes before it's cancelled (None if never).
+        writes: usize,              // The number of writes so far.
+        output: String,             // What was written.
+    }
+
+    impl Write for CancelAfter<'_> {
+        fn write_str(&mut self, string: &str) -> fmt::Result {
+            self.output.push_str(string);
+            self.writes += 1;
+            if Some(self.writes) == self.cancel_at {
+                self.token.cancel();
+            }
+            Ok(())
+        }
+    }
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_copy();
+        test_interrupted();
+    }
+
+    /// Copy a file with bytes which can't be printed, and make sure they're replaced.
+    fn test_copy() {
+        let token = CancelToken::new();
+        let mut output = String::new();
+        assert_eq!(super::copy(b"a\tb\x01c\n\xFF", &mut output, &token), Ok(7));
+        assert_eq!(output, "a\tb.c\n.");
+    }
+
+    /// Cancel the token after the first chunk of a large file, and make sure nothing is written
+    /// after it.
+    fn test_interrupted() {
+        let token = CancelToken::new();
+        let mut writer = CancelAfter { token: &token, cancel_at: Some(1), writes: 0,
+            output: String::new() };
+        assert_eq!(super::copy(&LARGE, &mut writer, &token), Err(Cancelled));
+        assert_eq!(writer.output.len(), CHUNK_SIZE);
+
+        token.reset();
+        let mut writer = CancelAfter { token: &token, cancel_at: None, writes: 0,
+            output: String::new() };
+        assert_eq!(super::copy(&LARGE, &mut writer, &token), Ok(LARGE.len()));
+        assert_eq!(writer.output.len(), LARGE.len());
+    }
+}
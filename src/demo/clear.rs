@@ -10,9 +10,13 @@ use crate::proc::process::Args;
 ///
 /// # Parameters
 /// `args` : The list of arguments.
-pub extern "sysv64" fn main(_args: *const Args) {
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
     unsafe {
         // Simlpy clear the terminal.
         crate::console::CONSOLE.as_mut().expect("Console not initialized").clear();
     }  
+    0
 }
@@ -10,16 +10,24 @@ use crate::proc::process::Args;
 ///
 /// # Parameters
 /// `args` : The list of arguments.
-pub extern "sysv64" fn main(args: *const Args) {
-    unsafe {
-        let full_args = (*args).get_args();
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("echo: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
     
-        // Add a new line and print all arguments expect the command.
-        oxid_println!("");
-        if full_args.len() > 1 {
-            for txt in &full_args[1..] {
-                oxid_print!("{} ", txt);
-            }
+    // Add a new line and print all arguments expect the command.
+    oxid_println!("");
+    if full_args.len() > 1 {
+        for txt in &full_args[1..] {
+            oxid_print!("{} ", txt);
         }
-    }  
+    }
+    0
 }
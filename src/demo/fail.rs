@@ -0,0 +1,29 @@
+//! A basic program which does nothing and fails (like the original false). It returns 1, or the
+//! exit code which was passed (`false <code>`), so the exit status can be tested from the terminal.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+
+/// The exit code when no code was passed.
+const DEFAULT_EXIT_CODE: usize = 1;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("fail: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    full_args.get(1).and_then(|code| code.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_EXIT_CODE)
+}
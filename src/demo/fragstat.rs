@@ -0,0 +1,69 @@
+//! A basic program which shows the fragmentation of the kernel heap (the current statistics, and
+//! the ratios which were sampled recently). The same statistics (with the frame counts) are written
+//! to the machine readable dumps by `stats` (see debug::statsdump).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::Write;
+use crate::debug::statsdump::field;
+use crate::mem::{dyn_alloc, frame_alloc};
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    oxid_println!();
+    let stats = match dyn_alloc::stats() {
+        Some(stats) => stats,
+        None => {
+            oxid_err!("The heap statistics are not available.");
+            return 1;
+        }
+    };
+    
+    oxid_println!("Allocations: {} ({} of {} bytes used, {} failed)", stats.num_allocs, 
+        stats.used_bytes, stats.total_bytes, stats.failed_allocs);
+    oxid_println!("Free: {} bytes in {} blocks (mean={}, max={})", stats.free_bytes, 
+        stats.free_blocks, stats.mean_free(), stats.max_free);
+    oxid_println!("Slabs: {} objects in {} pages", stats.slab_objects, stats.slab_pages);
+    oxid_println!("Fragmentation: {}.{}%", stats.fragmentation_permille() / 10, 
+        stats.fragmentation_permille() % 10);
+    
+    // Print the samples as percentages (the oldest first).
+    let history = dyn_alloc::frag_history();
+    oxid_print!("History (every {}s):", dyn_alloc::FRAG_SAMPLE_MS / 1000);
+    for sample in history.as_slice() {
+        oxid_print!(" {}", sample / 10);
+    }
+    0
+}
+
+/// The statistics provider of the memory (the heap, and the frames) for debug::statsdump.
+///
+/// # Parameters
+/// `out` : The writer which the values are written to.
+pub fn stats(out: &mut dyn Write) {
+    field(out, "frames_total", frame_alloc::total_count());
+    field(out, "frames_used", frame_alloc::used_count());
+    field(out, "heap_allocs", dyn_alloc::num_allocs());
+
+    // The heap statistics are not available while the heap is locked.
+    if let Some(stats) = dyn_alloc::stats() {
+        field(out, "heap_total_bytes", stats.total_bytes);
+        field(out, "heap_used_bytes", stats.used_bytes);
+        field(out, "heap_resident_bytes", stats.resident_bytes);
+        field(out, "heap_free_bytes", stats.free_bytes);
+        field(out, "heap_free_blocks", stats.free_blocks);
+        field(out, "heap_max_free", stats.max_free);
+        field(out, "heap_failed_allocs", stats.failed_allocs);
+        field(out, "slab_pages", stats.slab_pages);
+        field(out, "slab_objects", stats.slab_objects);
+        field(out, "frag_permille", stats.fragmentation_permille());
+    }
+}
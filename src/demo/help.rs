@@ -0,0 +1,34 @@
+//! A basic program which lists all the available programs.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    oxid_println!();
+    oxid_println!("Available programs (add & to run in the background):");
+    
+    // Print one program per line (they are already sorted), with how many times it was started.
+    for name in crate::demo::names() {
+        match crate::demo::instances(name) {
+            0 => oxid_println!("  {}", name),
+            count => oxid_println!("  {} ({} started)", name, count),
+        }
+    }
+    
+    oxid_println!("Built-in commands: cd <path>, pwd, ulimit [heap <bytes> | children <count>]");
+    oxid_println!("The exit code of the last command is $? (the prompt is red if it failed).");
+    oxid_println!("Run top in the background (top &), and press q to quit it.");
+    oxid_println!("View a file in the background (view <path> &), and press q to quit it.");
+    oxid_println!("Prefix a background program with log (log <program> &) to see it's output with \
+        plog <pid>.");
+    0
+}
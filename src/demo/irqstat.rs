@@ -0,0 +1,93 @@
+//! A basic program which shows the interrupt vectors which were allocated at runtime (with their
+//! owners), the number of free vectors in each class, how long each IRQ line kept the interrupts
+//! masked, and how much work was dropped because the queues were full. The same counters are 
+//! written to the machine readable dumps by `stats` (see debug::statsdump).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::Write;
+use crate::arch::interrupts::handlers::{self, threaded};
+use crate::debug::statsdump::field;
+use crate::arch::interrupts::vectors::{self, VectorClass};
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    oxid_println!();
+    oxid_println!("{:<8}{}", "VECTOR", "OWNER");
+    
+    for vector in vectors::FIRST_DYNAMIC_VECTOR..=255 {
+        if let Some(owner) = vectors::owner(vector) {
+            oxid_println!("{:<8}{}", vector, owner);
+        }
+    }
+    
+    let (entered, eois) = handlers::irq_counts();
+    oxid_println!("IRQs: entered={}, eois={}", entered, eois);
+    
+    // The masked windows of the lines which fired (in microseconds if the TSC is calibrated).
+    oxid_println!("{:<6}{:<10}{:<10}{:<14}{:<14}", "IRQ", "TYPE", "COUNT", "AVG MASKED", 
+        "MAX MASKED");
+    for irq in 0..16 {
+        let stats = handlers::masked_stats(irq);
+        if stats.count == 0 {
+            continue;
+        }
+        
+        let kind = if threaded::is_threaded(irq) { "threaded" } else { "plain" };
+        oxid_println!("{:<6}{:<10}{:<10}{:<14}{:<14}", irq, kind, stats.count, 
+            format_cycles(stats.mean_cycles()), format_cycles(stats.max_cycles));
+    }
+    
+    oxid_println!("Dropped: bottom halves={}, scancodes={}", threaded::dropped(), 
+        crate::arch::io::ps2_keyboard::dropped_scancodes());
+    
+    oxid_print!("Free vectors: device={}, system={}", vectors::num_free(VectorClass::Device),
+        vectors::num_free(VectorClass::System));
+    0
+}
+
+/// The statistics provider of the interrupts for debug::statsdump (the masked windows are in TSC
+/// cycles).
+///
+/// # Parameters
+/// `out` : The writer which the values are written to.
+pub fn stats(out: &mut dyn Write) {
+    let (entered, eois) = handlers::irq_counts();
+    field(out, "entered", entered);
+    field(out, "eois", eois);
+    field(out, "bottom_dropped", threaded::dropped());
+    field(out, "scancodes_dropped", crate::arch::io::ps2_keyboard::dropped_scancodes());
+    field(out, "free_device_vectors", vectors::num_free(VectorClass::Device));
+    field(out, "free_system_vectors", vectors::num_free(VectorClass::System));
+
+    for irq in 0..16 {
+        let stats = handlers::masked_stats(irq);
+        if stats.count > 0 {
+            let _ = writeln!(out, "irq{}_count={}", irq, stats.count);
+            let _ = writeln!(out, "irq{}_mean_masked_cycles={}", irq, stats.mean_cycles());
+            let _ = writeln!(out, "irq{}_max_masked_cycles={}", irq, stats.max_cycles);
+        }
+    }
+}
+
+/// A helper which formats a number of TSC cycles (as microseconds if the TSC is calibrated).
+///
+/// # Parameters
+/// `cycles` : The number of TSC cycles.
+///
+/// # Returns
+/// The formatted string.
+fn format_cycles(cycles: u64) -> alloc::string::String {
+    match crate::time::cycles_to_us(cycles) {
+        Some(us) => alloc::format!("{}us", us),
+        None => alloc::format!("{}cyc", cycles),
+    }
+}
@@ -0,0 +1,58 @@
+//! A basic program which kills a process by it's PID. It is used as `kill <pid>`. The kernel
+//! services (such as the kworker) can't be killed.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+use crate::proc::scheduler::{self, KillResult};
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    // Get the list of arguments.
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("kill: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    // Parse the PID.
+    let pid = match full_args.get(1).and_then(|pid| pid.trim().parse::<usize>().ok()) {
+        Some(pid) => pid,
+        None => {
+            oxid_err!("Usage: kill <pid>");
+            return 1;
+        }
+    };
+
+    // Find the process which has it right now (it's generation is checked when it's killed).
+    let handle = match scheduler::handle_of(pid) {
+        Some(handle) => handle,
+        None => {
+            oxid_err!("No process with PID={}.", pid);
+            return 1;
+        }
+    };
+
+    // Kill it, and let the user know if it failed.
+    match scheduler::kill_pid(handle) {
+        KillResult::Killed => 0,
+        KillResult::NotFound => {
+            oxid_err!("No process with PID={}.", pid);
+            1
+        },
+        KillResult::NotKillable => {
+            oxid_err!("Process PID={} is a kernel service and can't be killed.", pid);
+            1
+        },
+    }
+}
@@ -0,0 +1,101 @@
+//! A basic program which shows the latency histograms (the context switch latency, and the latency
+//! of the bottom halves). It is used as `latstat`, or `latstat reset` to clear them. The latencies
+//! are only collected when the kernel is built with the latency-stats feature. The same histograms
+//! are written to the machine readable dumps by `stats` (see debug::statsdump).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::Write;
+use crate::debug::latency::{self, Histogram};
+use crate::debug::statsdump::field;
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("latstat: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+    
+    if !crate::features::require("The latency collection", "latency-stats") {
+        return 1;
+    }
+    
+    match full_args.get(1).map(|arg| arg.trim()) {
+        None => {
+            let (switch, deferral) = latency::snapshot();
+            print_histogram("Context switch", &switch);
+            print_histogram("Bottom half", &deferral);
+        },
+        Some("reset") => {
+            latency::reset();
+            oxid_print!("The latency histograms were cleared.");
+        },
+        Some(_) => {
+            oxid_err!("Usage: latstat [reset]");
+            return 1;
+        },
+    }
+    0
+}
+
+/// A helper which prints a histogram (the summary, and the buckets which have samples).
+///
+/// # Parameters
+/// `name` : The name of the histogram.
+/// `histogram` : The histogram which is printed.
+fn print_histogram(name: &str, histogram: &Histogram) {
+    oxid_println!("{}: {} samples, p50={} p99={} max={}", name, histogram.count(), 
+        format_cycles(histogram.percentile(50).unwrap_or(0)), 
+        format_cycles(histogram.percentile(99).unwrap_or(0)), format_cycles(histogram.max()));
+    
+    for (bucket, count) in histogram.buckets().iter().enumerate().filter(|(_, count)| **count > 0) {
+        oxid_println!("  <= {:<14}{}", Histogram::bucket_limit(bucket), count);
+    }
+}
+
+/// The statistics provider of the latency histograms for debug::statsdump (in TSC cycles). Only
+/// `enabled=0` is written when they're not collected.
+///
+/// # Parameters
+/// `out` : The writer which the values are written to.
+pub fn stats(out: &mut dyn Write) {
+    let enabled = crate::features::has("latency-stats");
+    field(out, "enabled", enabled as usize);
+    if !enabled {
+        return;
+    }
+
+    let (switch, deferral) = latency::snapshot();
+    for (name, histogram) in [("switch", &switch), ("bottom_half", &deferral)].iter() {
+        let _ = writeln!(out, "{}_count={}", name, histogram.count());
+        let _ = writeln!(out, "{}_p50_cycles={}", name, histogram.percentile(50).unwrap_or(0));
+        let _ = writeln!(out, "{}_p99_cycles={}", name, histogram.percentile(99).unwrap_or(0));
+        let _ = writeln!(out, "{}_max_cycles={}", name, histogram.max());
+    }
+}
+
+/// A helper which formats a number of TSC cycles (with microseconds if the TSC is calibrated).
+///
+/// # Parameters
+/// `cycles` : The number of TSC cycles.
+///
+/// # Returns
+/// The formatted string.
+fn format_cycles(cycles: u64) -> alloc::string::String {
+    match crate::time::cycles_to_us(cycles) {
+        Some(us) => alloc::format!("{}cyc ({}us)", cycles, us),
+        None => alloc::format!("{}cyc", cycles),
+    }
+}
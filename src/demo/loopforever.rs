@@ -1,4 +1,5 @@
-//! A basic program which goes into an infinite loop printing for ever (until interrupted).
+//! A basic program which goes into an infinite loop printing for ever (until interrupted, or asked
+//! to exit).
 //! For demonstration purposes.
 //!
 //! `Author` : Ardalan Ahanchi
@@ -10,21 +11,30 @@ use crate::proc::process::Args;
 ///
 /// # Parameters
 /// `args` : The list of arguments.
-pub extern "sysv64" fn main(args: *const Args) {
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
 
-    unsafe {
-        // Get the list of arguments.
-        let full_args = (*args).get_args();
-        
-        if full_args.len() > 1 {
-            oxid_warn!("Launching loop.");
-        
-            // Print it forever.
-            loop {
-                oxid_print!("{}", full_args[1]);
-            }
-        } else {
-            oxid_err!("Please pass in an argument.");
+    // Get the list of arguments.
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("loopforever: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    
+    if full_args.len() > 1 {
+        oxid_warn!("Launching loop.");
+    
+        // Print it forever (or until we're asked to exit).
+        while !crate::proc::scheduler::termination_requested() {
+            oxid_print!("{}", full_args[1]);
         }
-    }  
+        0
+    } else {
+        oxid_err!("Please pass in an argument.");
+        1
+    }
 }
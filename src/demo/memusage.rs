@@ -0,0 +1,31 @@
+//! A basic program which shows the memory which is used by the kernel heap for each owner (the
+//! allocations are tagged with set_tag). Running it before and after a program shows the owner of
+//! anything which was not freed.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::mem::dyn_alloc;
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    oxid_println!();
+    oxid_println!("{:<10}{:>10}{:>12}", "Owner", "Allocs", "Bytes");
+    
+    let (mut allocs, mut bytes) = (0, 0);
+    for usage in dyn_alloc::usage_by_tag().iter() {
+        oxid_println!("{:<10}{:>10}{:>12}", usage.tag.name(), usage.allocs, usage.bytes);
+        allocs += usage.allocs;
+        bytes += usage.bytes;
+    }
+    
+    oxid_print!("{:<10}{:>10}{:>12}", "total", allocs, bytes);
+    0
+}
@@ -1,4 +1,7 @@
-//! A module which includes some sample programs for demonstration and testing purposes.
+//! A module which includes some sample programs for demonstration and testing purposes. The same
+//! main function is used by every instance of a program (for example, `loop a & loop b`), so the 
+//! main functions must be re-entrant. Their state should be kept in stack locals (or the passed 
+//! arguments), never in mutable statics.
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : March 2021
@@ -6,27 +9,88 @@
 // Define the programs here.
 pub mod clear;
 pub mod echo;
+pub mod fail;
 pub mod poke;
 pub mod loopforever;
+pub mod trace;
+pub mod audit;
+pub mod ps;
+pub mod kill;
+pub mod bootinfo;
+pub mod sysinfo;
+pub mod help;
+pub mod top;
+pub mod plog;
+pub mod irqstat;
+pub mod fragstat;
+pub mod memusage;
+pub mod shutdown;
+pub mod reboot;
+pub mod latstat;
+pub mod yes;
+pub mod selftest;
+pub mod pmap;
+pub mod view;
+pub mod sweep;
+pub mod statsdump;
+pub mod cat;
 
 use alloc::collections::btree_map::BTreeMap;
-
-// The type for the main functions (defined by the scheduler).
-type MainFn = extern "sysv64" fn(*const crate::proc::process::Args);
+use alloc::vec::Vec;
+use alloc::string::String;
+use crate::proc::process::MainFn;
 
 /// A map which holds the mapping between program names, and their main functions.
 static mut PROGRAMS: Option<BTreeMap<&str, MainFn>> = None;
 
+/// A map which holds the number of instances which were spawned for each program.
+static mut INSTANCES: Option<BTreeMap<&str, usize>> = None;
+
 /// A function which initializes all the user programs into the programs tree. 
 pub unsafe fn init() {
     // Initialize the programs.
     PROGRAMS = Some(BTreeMap::new());
+    INSTANCES = Some(BTreeMap::new());
     
     // Add the programs here with their names.
     PROGRAMS.as_mut().unwrap().insert("clear", clear::main);
     PROGRAMS.as_mut().unwrap().insert("echo", echo::main);
+    PROGRAMS.as_mut().unwrap().insert("false", fail::main);
     PROGRAMS.as_mut().unwrap().insert("poke", poke::main);
     PROGRAMS.as_mut().unwrap().insert("loop", loopforever::main);
+    PROGRAMS.as_mut().unwrap().insert("trace", trace::main);
+    PROGRAMS.as_mut().unwrap().insert("audit", audit::main);
+    PROGRAMS.as_mut().unwrap().insert("ps", ps::main);
+    PROGRAMS.as_mut().unwrap().insert("kill", kill::main);
+    PROGRAMS.as_mut().unwrap().insert("bootinfo", bootinfo::main);
+    PROGRAMS.as_mut().unwrap().insert("sysinfo", sysinfo::main);
+    PROGRAMS.as_mut().unwrap().insert("help", help::main);
+    PROGRAMS.as_mut().unwrap().insert("top", top::main);
+    PROGRAMS.as_mut().unwrap().insert("plog", plog::main);
+    PROGRAMS.as_mut().unwrap().insert("irqstat", irqstat::main);
+    PROGRAMS.as_mut().unwrap().insert("fragstat", fragstat::main);
+    PROGRAMS.as_mut().unwrap().insert("memusage", memusage::main);
+    PROGRAMS.as_mut().unwrap().insert("shutdown", shutdown::main);
+    PROGRAMS.as_mut().unwrap().insert("reboot", reboot::main);
+    PROGRAMS.as_mut().unwrap().insert("latstat", latstat::main);
+    PROGRAMS.as_mut().unwrap().insert("yes", yes::main);
+    PROGRAMS.as_mut().unwrap().insert("selftest", selftest::main);
+    PROGRAMS.as_mut().unwrap().insert("pmap", pmap::main);
+    PROGRAMS.as_mut().unwrap().insert("view", view::main);
+    PROGRAMS.as_mut().unwrap().insert("sweep", sweep::main);
+    PROGRAMS.as_mut().unwrap().insert("statsdump", statsdump::main);
+    PROGRAMS.as_mut().unwrap().insert("cat", cat::main);
+}
+
+/// A function which registers a program (after init), so the tests can run their own programs
+/// from the terminal.
+///
+/// # Parameters
+/// `name` : The name of the program.
+/// `main` : The main function of the program.
+#[cfg(feature = "unit-test")]
+pub fn register(name: &'static str, main: MainFn) {
+    unsafe { PROGRAMS.as_mut().expect("Programs not initialized").insert(name, main); }
 }
 
 /// A function which returns the main function pointer to a given program with a specific name.
@@ -53,3 +117,100 @@ pub fn get_main(name: &str) -> Option<MainFn> {
         }
     }
 }
+
+/// A function which returns the names of all the registered programs (sorted).
+///
+/// # Returns
+/// A vector with the names of the programs (empty if not initialized).
+pub fn names() -> Vec<&'static str> {
+    unsafe {
+        match &PROGRAMS {
+            Some(tree) => tree.keys().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A function which creates a unique name for a new instance of a program (such as `loop[2]`), so
+/// the processes which run the same program can be told apart.
+///
+/// # Parameters
+/// `name` : The name of the program registered in programs::init
+///
+/// # Returns
+/// Some with the instance name if the program exists, None otherwise.
+pub fn instance_name(name: &str) -> Option<String> {
+    unsafe {
+        // Use the key from the programs (since it lives forever).
+        let (key, _) = PROGRAMS.as_ref()?.get_key_value(name)?;
+        let count = INSTANCES.as_mut()?.entry(key).or_insert(0);
+        *count += 1;
+        
+        Some(alloc::format!("{}[{}]", name, count))
+    }
+}
+
+/// A simple getter for the number of instances which were spawned for a program.
+///
+/// # Parameters
+/// `name` : The name of the program registered in programs::init
+///
+/// # Returns
+/// The number of instances (0 if it was never spawned).
+pub fn instances(name: &str) -> usize {
+    unsafe {
+        INSTANCES.as_ref().and_then(|instances| instances.get(name)).copied().unwrap_or(0)
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::sysinfo::test::run();
+        super::top::test::run();
+        super::yes::test::run();
+        super::selftest::test::run();
+        super::view::test::run();
+        super::sweep::test::run();
+        super::statsdump::test::run();
+        super::cat::test::run();
+        test_concurrent_instances();
+    }
+    
+    /// Run the same program twice in the background, and make sure both instances get unique names
+    /// and print their own output.
+    fn test_concurrent_instances() {
+        use alloc::string::String;
+        
+        let before = super::instances("echo");
+        let first = alloc::format!("(echo[{}])", before + 1);
+        let second = alloc::format!("(echo[{}])", before + 2);
+        
+        crate::io::term::start_capture();
+        crate::io::term::type_line("echo first & echo second\n");
+        
+        // Wait (for up to a second) for both of them to exit.
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        loop {
+            let mut running = false;
+            crate::proc::scheduler::for_each(&mut |pcb| running |= pcb.name.starts_with("echo["));
+            if !running || crate::time::ticks() >= deadline {
+                break;
+            }
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+        
+        let mut output = String::new();
+        crate::io::term::capture_output(&mut output);
+        
+        assert_eq!(super::instances("echo"), before + 2);
+        assert!(output.contains(&first) && output.contains(&second));
+        assert!(output.contains("\nfirst ") && output.contains("\nsecond "));
+    }
+}
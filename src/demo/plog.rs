@@ -0,0 +1,47 @@
+//! A basic program which prints the output of a process from the kernel log. It is used as 
+//! `plog <pid>`, and only the processes which were started as `log <program> &` are logged.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use alloc::string::String;
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    // Get the list of arguments.
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("plog: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    // Parse the PID.
+    let pid = match full_args.get(1).and_then(|pid| pid.trim().parse::<usize>().ok()) {
+        Some(pid) => pid,
+        None => {
+            oxid_err!("Usage: plog <pid>");
+            return 1;
+        }
+    };
+
+    // Print whatever is still in the ring.
+    let mut output = String::new();
+    crate::debug::klog::read_pid(pid, &mut output);
+    if output.is_empty() {
+        oxid_err!("No logged output for PID={}.", pid);
+        1
+    } else {
+        oxid_print!("{}", output);
+        0
+    }
+}
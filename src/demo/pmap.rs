@@ -0,0 +1,73 @@
+//! A basic program which translates a virtual address to it's physical address. It uses the loaded
+//! page table, or any other one with `--table <cr3>` (the physical address of it's PML4). The 
+//! addresses can be in hex (with 0x) or decimal. For debugging purposes.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use alloc::vec::Vec;
+use crate::proc::process::Args;
+use crate::mem::addr::{PhysAddr, VirtAddr};
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("pmap: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    let words: Vec<&str> = full_args.iter().skip(1).map(|arg| arg.trim()).collect();
+    oxid_println!();
+    
+    // Get the table (if it was passed) and the address.
+    let (table, addr) = match words.as_slice() {
+        [addr] => (Some(None), parse_num(addr)),
+        ["--table", table, addr] => (parse_num(table).map(Some), parse_num(addr)),
+        _ => {
+            oxid_err!("Usage: pmap [--table <cr3>] <address>");
+            return 1;
+        },
+    };
+    
+    let (table, page_addr) = match (table, addr.and_then(|addr| VirtAddr::new(addr).ok())) {
+        (Some(table), Some(page_addr)) => (table, page_addr),
+        _ => {
+            oxid_err!("Invalid address passed. Please check input.");
+            return 1;
+        },
+    };
+    
+    // Translate it in the given table (the flags in the lower bits of CR3 are ignored).
+    let result = match table {
+        Some(table) => crate::mem::vmm::virt_to_phys_in(PhysAddr::new(table & !0xFFF), page_addr),
+        None => crate::mem::vmm::virt_to_phys(page_addr),
+    };
+    
+    match result {
+        Ok(frame_addr) => oxid_println!("0x{:x} -> 0x{:x}", page_addr, frame_addr),
+        Err(()) => oxid_println!("0x{:x} is not mapped.", page_addr),
+    }
+    0
+}
+
+/// A helper which parses a number in hex (with 0x) or decimal.
+///
+/// # Parameters
+/// `text` : The text which we're parsing.
+///
+/// # Returns
+/// Some(number) if it's valid, None otherwise.
+fn parse_num(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -1,30 +1,48 @@
-//! A basic program which checks the memory contents of a passed location.
-//! For demonstration purposes.
+//! A basic program which checks the memory contents of a passed location (the unmapped pages are
+//! shown as `..`). For demonstration purposes.
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Mar 2021
 
 use crate::proc::process::Args;
 
+/// The number of bytes which are printed.
+const DUMP_LEN: usize = 128;
+
 /// The main function as specified by the system requirements.
 ///
 /// # Parameters
 /// `args` : The list of arguments.
-pub extern "sysv64" fn main(args: *const Args) {
-    unsafe {
-        // Get the list of arguments.
-        let full_args = (*args).get_args();
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    // Get the list of arguments.
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("poke: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    
+    // Make sure the address was passed.
+    if full_args.len() < 2 {
+        oxid_err!("Usage: poke <address>");
+        return 1;
+    }
     
-        // Parse the first argument and check the results.
-        match full_args[1].trim().parse() {
-            Ok(addr) => {
-                oxid_println!();
-                crate::debug::memview::hex_dump(addr, 15);
-            },
-            
-            Err(_error) => {
-                oxid_err!("Invalid address passed. Please check input.")
-            }
+    // Parse the first argument and check the results.
+    match full_args[1].trim().parse() {
+        Ok(addr) => {
+            oxid_println!();
+            crate::debug::hexdump::hexdump_mapped(addr, DUMP_LEN);
+            0
+        },
+        
+        Err(_error) => {
+            oxid_err!("Invalid address passed. Please check input.");
+            1
         }
-    }  
+    }
 }
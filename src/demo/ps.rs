@@ -0,0 +1,67 @@
+//! A basic program which lists the processes in the scheduler, with their status, flags (K for the
+//! processes which can't be killed, L for the ones which copy their output to the kernel log, and !
+//! for the ones which were caught by the watchdog), and stack usage.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::olibc::bounded::{BoundedVec, BoundedString};
+use crate::proc::process::{Args, PCB, ProcessStatus, SpawnFlags};
+use crate::proc::scheduler;
+
+/// The maximum number of processes which are listed.
+const MAX_LISTED: usize = 64;
+
+/// The maximum number of bytes printed for each process name.
+const MAX_NAME: usize = 15;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    // Get the PIDs first, since we can't print or allocate while going through the processes.
+    let mut pids: BoundedVec<usize, MAX_LISTED> = BoundedVec::new();
+    scheduler::for_each(&mut |pcb: &PCB| pids.push(pcb.pid));
+
+    oxid_println!();
+    oxid_println!("{:<6}{:<16}{:<10}{:<7}{}", "PID", "NAME", "STATUS", "FLAGS", "STACK");
+
+    // Print a line for every process which still exists.
+    for pid in pids.as_slice() {
+        let info = scheduler::find(*pid, |pcb: &PCB| {
+            let mut name: BoundedString<MAX_NAME> = BoundedString::new();
+            let _ = name.push_str(&pcb.name);
+
+            let status = match pcb.status {
+                ProcessStatus::Started => "started",
+                ProcessStatus::Exited => "exited",
+            };
+
+            // Mark the kernel services which can't be killed, and the logged processes.
+            let mut flags: BoundedString<4> = BoundedString::new();
+            if pcb.flags.contains(SpawnFlags::NO_KILL) {
+                flags.push('K');
+            }
+            if pcb.flags.contains(SpawnFlags::LOG_OUTPUT) {
+                flags.push('L');
+            }
+            if pcb.stuck {
+                flags.push('!');
+            }
+
+            (name, status, flags)
+        });
+
+        // The stack is scanned for the high-water mark (and a warning is printed if needed).
+        if let (Some((name, status, flags)), Some((used, total))) = (info,
+            scheduler::stack_usage(*pid)) {
+            oxid_println!("{:<6}{:<16}{:<10}{:<7}{}/{}", pid, name.as_str(), status, flags.as_str(), used,
+                total);
+        }
+    }
+    0
+}
@@ -0,0 +1,20 @@
+//! A basic program which reboots the system in an orderly way (the processes are stopped, and the
+//! subsystems are shut down first).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    oxid_println!();
+    crate::power::orderly_shutdown(true);
+    0
+}
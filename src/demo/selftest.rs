@@ -0,0 +1,173 @@
+//! A program which runs an end-to-end test of the process features together (the bugs between
+//! them are not found by their own unit tests). It checks the sleep against the timer, spawns a
+//! child (another instance of itself, `selftest child`), asks it to exit, and checks the code it
+//! exited with (the child only exits with CHILD_EXIT_CODE if it saw the request). The processes
+//! and the heap allocations are then compared to the ones before the test, so nothing is leaked.
+//! A line is printed for each step (PASS or FAIL), and it returns a non-zero code if any of them
+//! failed, so it can stop the boot commands (see io::rc).
+//!
+//! There are no pipes or signals yet, so the termination request stands in for the interrupt
+//! signal, and the child reports back through it's exit code.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+use crate::proc::exec::{self, ExecFlags, ExecResult};
+use crate::proc::scheduler;
+
+/// The exit code of the child once it saw the termination request.
+const CHILD_EXIT_CODE: usize = 42;
+
+/// The exit code of the child if it was never asked to exit.
+const CHILD_TIMEOUT_CODE: usize = 1;
+
+/// The exit code when the arguments are invalid.
+const USAGE_EXIT_CODE: usize = 2;
+
+/// The time which is slept (and measured with the timer).
+const SLEEP_MS: usize = 100;
+
+/// The time between the checks of the child (for the termination request).
+const CHILD_POLL_MS: usize = 10;
+
+/// The longest time the child runs, or the parent waits for it.
+const TIMEOUT_MS: usize = 2000;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("selftest: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    match full_args.get(1).copied() {
+        None => run_parent(),
+        Some("child") => run_child(),
+        Some(_) => {
+            oxid_err!("Usage: selftest");
+            USAGE_EXIT_CODE
+        },
+    }
+}
+
+/// A function which runs the steps of the test.
+///
+/// # Returns
+/// The exit code (non-zero if any of the steps failed).
+fn run_parent() -> usize {
+    let (procs_before, allocs_before) = (num_processes(), crate::mem::dyn_alloc::num_allocs());
+    let mut failures: usize = 0;
+
+    // The sleep should take about as long as the timer says.
+    let start = crate::time::ticks();
+    crate::time::sleep_ms(SLEEP_MS);
+    let (elapsed, expected) = (crate::time::ticks() - start, crate::time::ms_to_ticks(SLEEP_MS));
+    report("sleep", elapsed >= expected && elapsed <= expected * 2 + 1, &mut failures);
+
+    // Spawn the child, and make sure it's still running after a while.
+    let child = match exec::exec("selftest child", ExecFlags::BACKGROUND) {
+        Ok(ExecResult::Spawned(pid)) => scheduler::handle_of(pid),
+        _ => None,
+    };
+    crate::time::sleep_ms(SLEEP_MS);
+    let running = child.map_or(false, |child| scheduler::find_handle(child, |_| ()).is_some());
+    report("spawn", running, &mut failures);
+
+    // Ask it to exit, and wait for it to be removed (it reports with it's exit code).
+    if let Some(child) = child {
+        report("interrupt", scheduler::request_termination(child), &mut failures);
+
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(TIMEOUT_MS);
+        while scheduler::find_handle(child, |_| ()).is_some() && crate::time::ticks() < deadline {
+            crate::time::sleep_ms(CHILD_POLL_MS);
+        }
+        report("exit code", scheduler::find_handle(child, |_| ()).is_none()
+            && scheduler::last_failure() == Some((child.pid(), CHILD_EXIT_CODE as i32)), 
+            &mut failures);
+    }
+
+    // Nothing should be left behind (such as the child's PCB or stack).
+    report("cleanup", num_processes() == procs_before
+        && crate::mem::dyn_alloc::num_allocs() == allocs_before, &mut failures);
+
+    if failures > 0 {
+        oxid_err!("selftest: {} step(s) failed.", failures);
+        return 1;
+    }
+    0
+}
+
+/// A function which runs the child, it waits for the termination request (for up to TIMEOUT_MS).
+///
+/// # Returns
+/// The exit code (CHILD_EXIT_CODE if it saw the request).
+fn run_child() -> usize {
+    let deadline = crate::time::ticks() + crate::time::ms_to_ticks(TIMEOUT_MS);
+    while crate::time::ticks() < deadline {
+        if scheduler::termination_requested() {
+            return CHILD_EXIT_CODE;
+        }
+        crate::time::sleep_ms(CHILD_POLL_MS);
+    }
+
+    CHILD_TIMEOUT_CODE
+}
+
+/// A helper which prints the result of a step, and counts the failures.
+///
+/// # Parameters
+/// `step` : The name of the step.
+/// `passed` : True if it passed, False otherwise.
+/// `failures` : The number of failed steps (incremented if it failed).
+fn report(step: &str, passed: bool, failures: &mut usize) {
+    if passed {
+        oxid_println!("selftest: PASS {}", step);
+    } else {
+        *failures += 1;
+        oxid_println!("selftest: FAIL {}", step);
+    }
+}
+
+/// A helper which counts the processes in the scheduler.
+///
+/// # Returns
+/// The number of processes (including the IDLE process).
+fn num_processes() -> usize {
+    let mut count: usize = 0;
+    scheduler::for_each(&mut |_| count += 1);
+    count
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::proc::exec::{exec, ExecFlags, ExecResult};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_selftest();
+    }
+
+    /// Run the self test inline (every step should pass), and make sure the exit code of an inline
+    /// program is returned by exec.
+    fn test_selftest() {
+        assert_eq!(exec("selftest", ExecFlags::NONE), Ok(ExecResult::Exited(0)));
+        assert_eq!(exec("selftest bogus", ExecFlags::NONE),
+            Ok(ExecResult::Exited(super::USAGE_EXIT_CODE as i32)));
+    }
+}
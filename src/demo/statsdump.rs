@@ -0,0 +1,82 @@
+//! A basic program which writes a machine readable statistics dump (see debug::statsdump). It's
+//! used as `statsdump` to write it to the serial port (for the host tooling), or `statsdump show`
+//! to print it on the terminal.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use alloc::string::String;
+use crate::debug::statsdump;
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("statsdump: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    match full_args.get(1).map(|arg| arg.trim()) {
+        None => match statsdump::dump_to_serial() {
+            Ok(lines) => oxid_print!("statsdump: {} lines were written to the serial port.", lines),
+            Err(()) => {
+                oxid_err!("statsdump: There is no serial port (use `statsdump show`).");
+                return 1;
+            },
+        },
+        Some("show") => {
+            // Build the dump first, so it's not interleaved with the terminal's output.
+            let mut output = String::new();
+            statsdump::dump(&mut output);
+            oxid_print!("{}", output);
+        },
+        Some(_) => {
+            oxid_err!("Usage: statsdump [show]");
+            return 1;
+        },
+    }
+    0
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use crate::debug::statsdump;
+    use crate::proc::exec::{exec, ExecFlags, ExecResult};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_show();
+    }
+
+    /// Show a dump on the terminal, and make sure it's framed (and the usage is printed for the
+    /// unknown arguments).
+    fn test_show() {
+        let mut output = String::new();
+
+        crate::io::term::start_capture();
+        assert_eq!(exec("statsdump show", ExecFlags::NONE), Ok(ExecResult::Exited(0)));
+        assert_eq!(exec("statsdump later", ExecFlags::NONE), Ok(ExecResult::Exited(1)));
+        crate::io::term::capture_output(&mut output);
+
+        assert!(output.contains(statsdump::BEGIN_MARKER));
+        assert!(output.contains(statsdump::END_MARKER));
+        assert!(output.contains("sched.processes="));
+        assert!(output.contains("Usage: statsdump"));
+    }
+}
@@ -0,0 +1,165 @@
+//! A basic program which controls the integrity sweeper (see debug::sweeper). It's used as `sweep`
+//! to show the statistics, `sweep now` to run a whole sweep right away, or `sweep config` to show
+//! (or change) the period, the budget, and the selected checks. The checks are passed as a comma
+//! separated list (for example, `sweep config checks canaries,lists`).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::debug::sweeper::{self, Check, SweepConfig, SweepError};
+use crate::proc::cancel::INTERRUPTED_STATUS;
+use crate::proc::process::Args;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed, or anything was found).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("sweep: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    let full_args: alloc::vec::Vec<&str> = full_args.iter().map(|arg| arg.trim()).collect();
+    oxid_println!();
+
+    match full_args.get(1).copied() {
+        None => show_stats(),
+        Some("now") => match sweeper::sweep_now() {
+            Ok(0) => oxid_println!("sweep: No problems were found."),
+            Ok(findings) => {
+                oxid_err!("sweep: {} problem(s) were found (see the log).", findings);
+                return 1;
+            },
+            Err(SweepError::Busy) => {
+                oxid_err!("sweep: A sweep is already running, try again.");
+                return 1;
+            },
+            Err(SweepError::Cancelled) => return INTERRUPTED_STATUS as usize,
+        },
+        Some("config") if full_args.len() == 2 => show_config(&sweeper::config()),
+        Some("config") if full_args.len() == 4 => {
+            let mut config = sweeper::config();
+            if configure(&mut config, full_args[2], full_args[3]).is_err() {
+                print_usage();
+                return 1;
+            }
+            sweeper::set_config(&config);
+            show_config(&config);
+        },
+        Some(_) => {
+            print_usage();
+            return 1;
+        },
+    }
+    0
+}
+
+/// A helper which changes a single setting.
+///
+/// # Parameters
+/// `config` : The settings which are changed.
+/// `name` : The name of the setting (period, budget or checks).
+/// `value` : The new value (milliseconds, or a comma separated list of checks).
+///
+/// # Returns
+/// Ok if it was changed, Err if the name or the value is not valid.
+fn configure(config: &mut SweepConfig, name: &str, value: &str) -> Result<(), ()> {
+    match name {
+        "period" => config.period_ms = value.parse::<usize>().ok().filter(|ms| *ms > 0).ok_or(())?,
+        "budget" => config.budget_ms = value.parse::<usize>().map_err(|_| ())?,
+        "checks" => {
+            let mut selected = SweepConfig { checks: 0, ..*config };
+            for name in value.split(',') {
+                selected.set(Check::from_name(name).ok_or(())?, true);
+            }
+            *config = selected;
+        },
+        _ => return Err(()),
+    }
+    Ok(())
+}
+
+/// A helper which prints the settings of the sweeper.
+///
+/// # Parameters
+/// `config` : The settings.
+fn show_config(config: &SweepConfig) {
+    oxid_print!("period={}ms budget={}ms strict={} checks=", config.period_ms, config.budget_ms,
+        config.strict);
+    let mut first = true;
+    for check in Check::ALL.iter().filter(|check| config.has(**check)) {
+        oxid_print!("{}{}", if first { "" } else { "," }, check.name());
+        first = false;
+    }
+    oxid_println!();
+}
+
+/// A helper which prints the statistics of the sweeper (and if it's running in the background).
+fn show_stats() {
+    match sweeper::sweeper_pid() {
+        Some(pid) => oxid_println!("The sweeper is running (PID={}).", pid),
+        None => oxid_println!("The sweeper is not running (it's started with the debug features)."),
+    }
+
+    match sweeper::stats() {
+        Some(stats) => oxid_println!("{} sweeps ({} slices), {} problems found.", stats.sweeps,
+            stats.slices, stats.findings),
+        None => oxid_println!("A sweep is running, the statistics are not available."),
+    }
+}
+
+/// A helper which prints the usage of the program.
+fn print_usage() {
+    oxid_err!("Usage: sweep [now | config [period <ms> | budget <ms> | checks <check,...>]]");
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use crate::debug::sweeper;
+    use crate::proc::exec::{exec, ExecFlags, ExecResult};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_commands();
+    }
+
+    /// Run a sweep, and change the settings (the invalid ones should be rejected, and the original
+    /// ones are restored at the end).
+    fn test_commands() {
+        let original = sweeper::config();
+        let mut output = String::new();
+
+        crate::io::term::start_capture();
+        assert_eq!(exec("sweep now", ExecFlags::NONE), Ok(ExecResult::Exited(0)));
+        assert_eq!(exec("sweep config period 250", ExecFlags::NONE), Ok(ExecResult::Exited(0)));
+        assert_eq!(exec("sweep config checks lists,frames", ExecFlags::NONE),
+            Ok(ExecResult::Exited(0)));
+        assert_eq!(exec("sweep config checks lists,bogus", ExecFlags::NONE),
+            Ok(ExecResult::Exited(1)));
+        assert_eq!(exec("sweep config period 0", ExecFlags::NONE), Ok(ExecResult::Exited(1)));
+        assert_eq!(exec("sweep later", ExecFlags::NONE), Ok(ExecResult::Exited(1)));
+        crate::io::term::capture_output(&mut output);
+
+        let config = sweeper::config();
+        assert_eq!(config.period_ms, 250);
+        assert!(config.has(sweeper::Check::Frames) && !config.has(sweeper::Check::Canaries));
+        assert!(output.contains("sweep: No problems were found."));
+        assert!(output.contains("period=250ms budget=2ms"));
+        assert!(output.contains("checks=lists,frames"));
+        assert!(output.contains("Usage: sweep"));
+
+        sweeper::set_config(&original);
+    }
+}
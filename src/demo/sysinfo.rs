@@ -0,0 +1,107 @@
+//! A basic program which prints a summary of the system: the kernel version and build, the CPU,
+//! the memory, the uptime and timers, the interrupt controller, the processes, and the file 
+//! systems. Every line is read from the stats functions of the corresponding module.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use core::fmt::Write;
+use alloc::string::String;
+use crate::proc::process::Args;
+use crate::mem::{dyn_alloc, frame_alloc};
+use crate::arch::{interrupts::pic, proc::cpu};
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    // Build the report first, so nothing is printed while gathering the stats.
+    let mut output = String::new();
+    report(&mut output);
+
+    oxid_println!();
+    oxid_print!("{}", output);
+    0
+}
+
+/// A function which writes the system summary to a given writer (one line per section).
+///
+/// # Parameters
+/// `out` : The writer which the summary is written to.
+///
+/// # Returns
+/// Ok if everything was written, Err if the writer failed.
+pub fn report(out: &mut dyn Write) -> core::fmt::Result {
+    // The kernel version, and how it was built.
+    writeln!(out, "Kernel: {} v{} ({}, built {}, features: {})", crate::version::NAME,
+        crate::version::VERSION, crate::version::GIT_HASH, crate::version::BUILD_DATE,
+        crate::features::list())?;
+
+    // The CPU identification.
+    match cpu::brand() {
+        Some(brand) => writeln!(out, "CPU: {} ({})", brand.as_str(), cpu::vendor().as_str())?,
+        None => writeln!(out, "CPU: {}", cpu::vendor().as_str())?,
+    }
+
+    // The physical memory (from the frame allocator), and the heap.
+    let total = frame_alloc::total_count();
+    let used = frame_alloc::used_count();
+    writeln!(out, "Memory: {} KiB total, {} KiB free, {} heap allocations",
+        total * frame_alloc::FRAME_SIZE / 1024,
+        total.saturating_sub(used) * frame_alloc::FRAME_SIZE / 1024, dyn_alloc::num_allocs())?;
+
+    // The uptime (from the timer ticks), and the timer sources.
+    let time = crate::time::state();
+    let uptime_ms = time.uptime_ms();
+    writeln!(out, "Uptime: {}.{:03}s", uptime_ms / 1000, uptime_ms % 1000)?;
+    writeln!(out, "Timer: PIT ({}us ticks), delays: {:?}", time.tick_us, crate::time::backend())?;
+
+    // The interrupt controller which is in use (the APIC is not supported yet).
+    writeln!(out, "Interrupts: {}", if pic::is_active() { "8259 PIC" } else { "none" })?;
+
+    // The number of processes (counted while the process list is locked).
+    let mut processes: usize = 0;
+    crate::proc::scheduler::for_each(&mut |_| processes += 1);
+    writeln!(out, "Processes: {}", processes)?;
+    let counters = crate::proc::scheduler::counters();
+    writeln!(out, "Scheduler: {} switches, {} reaped, {} lock overruns, {} deferred", 
+        counters.switches, counters.reaped, counters.lock_overruns, counters.deferred)?;
+
+    // There is no file system support yet, so nothing can be mounted.
+    writeln!(out, "Filesystems: none mounted")
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_report_sections();
+    }
+
+    /// Write the report to a string, and make sure every section is there.
+    fn test_report_sections() {
+        let mut output = String::new();
+        assert!(super::report(&mut output).is_ok());
+
+        for header in ["Kernel:", "CPU:", "Memory:", "Uptime:", "Timer:", "Interrupts:",
+            "Processes:", "Scheduler:", "Filesystems:"].iter() {
+            assert!(output.lines().any(|line| line.starts_with(header)));
+        }
+
+        // The stats should agree with each other (at least the idle process is running).
+        assert!(output.contains(crate::version::VERSION));
+        assert!(!output.contains("Processes: 0"));
+        assert!(output.contains("Interrupts: 8259 PIC"));
+    }
+}
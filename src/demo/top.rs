@@ -0,0 +1,226 @@
+//! A program which shows the processes sorted by their recent CPU usage, and refreshes the screen
+//! every second until q is pressed. It has to run in the background (`top &`), since the 
+//! foreground programs run from the keyboard's bottom half with the interrupts disabled (so they 
+//! can't sleep or read the keyboard).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::olibc::bounded::{BoundedVec, BoundedString};
+use crate::proc::process::{Args, PCB};
+use crate::proc::scheduler;
+use crate::io::keyboard;
+
+/// The maximum number of processes which are sampled.
+const MAX_LISTED: usize = 64;
+
+/// The maximum number of bytes shown for each process name.
+const MAX_NAME: usize = 15;
+
+/// The time between the refreshes.
+const REFRESH_MS: usize = 1000;
+
+/// The row where the process table starts (after the header lines).
+const TABLE_ROW: usize = 3;
+
+/// A struct which holds the information of a single process in a sample.
+#[derive(Copy, Clone)]
+struct Sample {
+    pid: usize,                         // The process ID.
+    name: BoundedString<MAX_NAME>,      // The (possibly cut) name of the process.
+    cpu_ticks: u64,                     // The total number of ticks it was running for.
+    recent: u64,                        // The number of ticks since the previous sample.
+}
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `_args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(_args: *const Args) -> usize {
+    // The ticks don't advance in the foreground (the interrupts are disabled).
+    if !unsafe { crate::arch::interrupts::are_enabled() } {
+        oxid_println!();
+        oxid_err!("top has to run in the background (top &).");
+        return 1;
+    }
+    
+    let console = unsafe { crate::console::CONSOLE.as_mut().expect("Console not initialized") };
+    let (rows, cols) = console.get_size();
+    
+    // Take the keyboard in the raw mode, so q is read right away (and doesn't end up in the terminal).
+    crate::proc::set_input_mode(crate::proc::InputMode::Raw);
+    keyboard::grab(true);
+    console.clear();
+    
+    let mut prev: BoundedVec<Sample, MAX_LISTED> = BoundedVec::new();
+    let mut prev_rows = 0;
+    loop {
+        let curr = sample(&prev);
+        
+        // Print the header and the table (padded, so the previous contents are overwritten).
+        let free_frames = crate::mem::frame_alloc::total_count() 
+            - crate::mem::frame_alloc::used_count();
+        let header = alloc::format!("Uptime: {}s  Memory: {}K free of {}K  Processes: {}",
+            crate::time::uptime_ms() / 1000,
+            free_frames * crate::mem::frame_alloc::FRAME_SIZE / 1024,
+            crate::mem::frame_alloc::total_count() * crate::mem::frame_alloc::FRAME_SIZE / 1024,
+            curr.len());
+        print_row(0, cols, &header);
+        print_row(1, cols, "Press q to quit.");
+        print_row(2, cols, &alloc::format!("{:<6}{:<16}{:<8}{}", "PID", "NAME", "CPU", "TOTAL"));
+        
+        let shown = curr.len().min(rows - TABLE_ROW);
+        for (idx, proc_sample) in curr.as_slice()[..shown].iter().enumerate() {
+            print_row(TABLE_ROW + idx, cols, &alloc::format!("{:<6}{:<16}{:<8}{}", 
+                proc_sample.pid, proc_sample.name.as_str(), proc_sample.recent, 
+                proc_sample.cpu_ticks));
+        }
+        
+        // Clear the rows of the processes which are gone.
+        for row in shown..prev_rows {
+            print_row(TABLE_ROW + row, cols, "");
+        }
+        prev_rows = shown;
+        prev = curr;
+        
+        // Wait for the next refresh (and stop if q was pressed, or we're asked to exit).
+        crate::time::sleep_ms(REFRESH_MS);
+        if quit_requested() || crate::proc::scheduler::termination_requested() {
+            break;
+        }
+    }
+    
+    // Give the screen and the keyboard back to the terminal.
+    keyboard::grab(false);
+    console.clear();
+    crate::io::term::print_prompt();
+    0
+}
+
+/// A function which samples the processes, and calculates their recent ticks based on the previous
+/// sample. The processes which are new (not in the previous sample) use their total ticks.
+///
+/// # Parameters
+/// `prev` : The previous sample (empty for the first one).
+///
+/// # Returns
+/// The new sample, sorted by the recent ticks (highest first).
+fn sample(prev: &BoundedVec<Sample, MAX_LISTED>) -> BoundedVec<Sample, MAX_LISTED> {
+    // Copy what we need, since we can't print or allocate while going through the processes.
+    let mut curr: BoundedVec<Sample, MAX_LISTED> = BoundedVec::new();
+    scheduler::for_each(&mut |pcb: &PCB| {
+        let mut name: BoundedString<MAX_NAME> = BoundedString::new();
+        let _ = name.push_str(&pcb.name);
+        let _ = curr.try_push(Sample { pid: pcb.pid, name, cpu_ticks: pcb.cpu_ticks, recent: 0 });
+    });
+    
+    for proc_sample in curr.as_mut_slice() {
+        let before = prev.as_slice().iter().find(|prev_sample| prev_sample.pid == proc_sample.pid)
+            .map_or(0, |prev_sample| prev_sample.cpu_ticks);
+        proc_sample.recent = proc_sample.cpu_ticks.saturating_sub(before);
+    }
+    
+    curr.as_mut_slice().sort_unstable_by(|a, b| b.recent.cmp(&a.recent));
+    curr
+}
+
+/// A function which prints a line at the start of a given row, padded to the width of the screen.
+///
+/// # Parameters
+/// `row` : The row which we're printing.
+/// `cols` : The number of columns on the screen.
+/// `line` : The text which is printed.
+fn print_row(row: usize, cols: usize, line: &str) {
+    let padded = alloc::format!("{:<1$}", line, cols);
+    unsafe { 
+        crate::console::CONSOLE.as_mut().expect("Console not initialized").print_at(row, 0, &padded);
+    }
+}
+
+/// A function which reads every typed key, and checks if q was one of them.
+///
+/// # Returns
+/// True if q (or Q) was typed, False otherwise.
+fn quit_requested() -> bool {
+    let mut quit = false;
+    let mut buf: [u8; 16] = [0; 16];
+    loop {
+        let count = keyboard::read(&mut buf);
+        if count == 0 {
+            break;
+        }
+        quit |= buf[..count].iter().any(|byte| *byte == b'q' || *byte == b'Q');
+    }
+    
+    quit
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use crate::io::keyboard::{self, Event, Key};
+    use crate::proc::pid::ProcHandle;
+    use crate::proc::process::PCB;
+    use crate::proc::scheduler;
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_refresh_and_quit();
+    }
+    
+    /// A helper which finds a process based on the start of it's name.
+    ///
+    /// # Parameters
+    /// `prefix` : The start of the process name (such as "top[").
+    ///
+    /// # Returns
+    /// Some(handle) if it's running, None otherwise.
+    fn find_proc(prefix: &str) -> Option<ProcHandle> {
+        let mut found = None;
+        scheduler::for_each(&mut |pcb: &PCB| {
+            if pcb.name.starts_with(prefix) {
+                found = Some(pcb.handle());
+            }
+        });
+        
+        found
+    }
+    
+    /// Run top through a few refreshes while a process it displayed exits, and then quit it.
+    fn test_refresh_and_quit() {
+        crate::io::term::start_capture();
+        crate::io::term::type_line("loop & top &\n");
+        
+        // Let it refresh twice, then kill the loop (which top already displayed).
+        crate::time::sleep_ms(super::REFRESH_MS + super::REFRESH_MS / 2);
+        let loop_proc = find_proc("loop[").expect("The loop process is not running.");
+        let _ = scheduler::kill_pid(loop_proc);
+        
+        // Let it refresh without it, and then press q.
+        crate::time::sleep_ms(super::REFRESH_MS);
+        keyboard::inject(Event::new(Key::Ch('q'), true));
+        keyboard::inject(Event::new(Key::Ch('q'), false));
+        
+        // Wait (for up to a few refreshes) for it to exit.
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(3 * super::REFRESH_MS);
+        while find_proc("top[").is_some() && crate::time::ticks() < deadline {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+        
+        let mut output = String::new();
+        crate::io::term::capture_output(&mut output);
+        
+        assert!(find_proc("top[").is_none());
+        assert!(find_proc("loop[").is_none());
+        assert!(output.matches("Uptime: ").count() >= 3);
+        assert!(output.contains("loop["));
+    }
+}
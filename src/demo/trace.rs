@@ -0,0 +1,75 @@
+//! A basic program which turns the trace messages of the kernel subsystems on and off. It is used
+//! as `trace on|off <subsystem>`, and without arguments it prints the current switches. The trace
+//! messages are kept in the kernel log, and `trace show` prints the ones which are still there.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+use crate::debug::trace::{self, Subsystem};
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    // Get the list of arguments.
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("trace: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    // The switches don't do anything if the messages are not compiled in.
+    if !crate::features::require("Tracing", "trace") {
+        return 1;
+    }
+
+    // If there are no arguments, just print the status of every subsystem.
+    if full_args.len() < 2 {
+        for subsys in trace::ALL_SUBSYSTEMS.iter() {
+            oxid_println!("{}: {}", subsys.name(),
+                if trace::is_enabled(*subsys) { "on" } else { "off" });
+        }
+        return 0;
+    }
+
+    // Print the trace messages which are still in the kernel log.
+    if full_args[1].trim() == "show" {
+        let mut traces = alloc::string::String::new();
+        crate::debug::klog::read_level(crate::debug::klog::Level::Trace, &mut traces);
+        oxid_print!("{}", traces);
+        return 0;
+    }
+
+    // Make sure the subsystem was passed, and parse it.
+    if full_args.len() < 3 {
+        oxid_err!("Usage: trace on|off <subsystem> | trace show");
+        return 1;
+    }
+
+    let subsys = match Subsystem::from_name(full_args[2].trim()) {
+        Some(subsys) => subsys,
+        None => {
+            oxid_err!("Unknown subsystem \"{}\".", full_args[2].trim());
+            return 1;
+        }
+    };
+
+    // Turn it on or off based on the first argument.
+    match full_args[1].trim() {
+        "on" => trace::enable(subsys),
+        "off" => trace::disable(subsys),
+        _ => {
+            oxid_err!("Usage: trace on|off <subsystem> | trace show");
+            return 1;
+        },
+    }
+    0
+}
@@ -0,0 +1,565 @@
+//! A program which shows a file from the initrd one screen at a time (`view <path>`). The arrows
+//! (or j and k) scroll by a line, the page keys (or space and b) scroll by a screen, g and G jump
+//! to the start and the end, `/text` finds the next line which has the text (n finds the one
+//! after it), and q quits. The matches are highlighted, and the bytes which can't be printed are
+//! shown as '.'. The screen and the input mode are saved before it starts, and they're restored
+//! once it quits. Like top, it has to run in the background (`view <path> &`), since the
+//! foreground programs can't read the keyboard.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::olibc::bounded::BoundedVec;
+use crate::io::keyboard;
+use crate::io::textmode::color::Color;
+use crate::proc::process::{Args, InputMode};
+
+/// The largest file which can be viewed (1MB).
+const MAX_FILE_SIZE: usize = 0x100000;
+
+/// The time between the reads of the keyboard.
+const POLL_MS: usize = 20;
+
+/// The number of columns in a tab stop.
+const TAB_WIDTH: usize = 4;
+
+/// The colors of the matches.
+const MATCH_FG: Color = Color::Black;
+const MATCH_BG: Color = Color::Yellow;
+
+/// The colors of the status line (the last row).
+const STATUS_FG: Color = Color::Black;
+const STATUS_BG: Color = Color::Gray;
+
+/// An enum which represents the input of the viewer (the keys which are delivered as sequences
+/// are decoded, and the rest are passed as their bytes).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Input {
+    Up,                         // Up arrow ("\x1b[A").
+    Down,                       // Down arrow ("\x1b[B").
+    PageUp,                     // Page up ("\x1b[5~").
+    PageDown,                   // Page down ("\x1b[6~").
+    Byte(u8),                   // Any other byte (escape is '\x1b').
+}
+
+/// A structure which decodes the raw input bytes. The sequences can be split between the reads, so
+/// the start of a sequence is kept until the rest of it arrives.
+struct InputParser {
+    pending: BoundedVec<u8, 4>,             // The start of the sequence which is being decoded.
+}
+
+impl InputParser {
+    /// A constructor which creates a parser without any pending bytes.
+    const fn new() -> Self {
+        InputParser { pending: BoundedVec::new() }
+    }
+
+    /// A method which decodes the next byte of the input.
+    ///
+    /// # Parameters
+    /// `byte` : The byte which was read.
+    /// `handle` : The closure which is called with every decoded input.
+    fn feed(&mut self, byte: u8, handle: &mut dyn FnMut(Input)) {
+        if self.pending.is_empty() {
+            match byte {
+                b'\x1b' => self.pending.push(byte),
+                _ => handle(Input::Byte(byte)),
+            }
+            return;
+        }
+
+        // A sequence which doesn't match is dropped (except for an escape on it's own).
+        self.pending.push(byte);
+        let decoded = match self.pending.as_slice() {
+            [b'\x1b', b'['] | [b'\x1b', b'[', b'5'] | [b'\x1b', b'[', b'6'] => return,
+            [b'\x1b', b'[', b'A'] => Some(Input::Up),
+            [b'\x1b', b'[', b'B'] => Some(Input::Down),
+            [b'\x1b', b'[', b'5', b'~'] => Some(Input::PageUp),
+            [b'\x1b', b'[', b'6', b'~'] => Some(Input::PageDown),
+            [b'\x1b', other] => {
+                handle(Input::Byte(b'\x1b'));
+                Some(Input::Byte(*other))
+            },
+            _ => None,
+        };
+
+        self.pending.clear();
+        if let Some(input) = decoded {
+            handle(input);
+        }
+    }
+
+    /// A method which is called once there is nothing else to read, so an escape on it's own is
+    /// not kept waiting for the rest of a sequence.
+    ///
+    /// # Parameters
+    /// `handle` : The closure which is called with every decoded input.
+    fn flush(&mut self, handle: &mut dyn FnMut(Input)) {
+        if self.pending.as_slice() == [b'\x1b'] {
+            handle(Input::Byte(b'\x1b'));
+        }
+        self.pending.clear();
+    }
+}
+
+/// A structure which holds the state of the viewer (the lines of the file, and which ones are
+/// shown). It doesn't touch the screen, so it can be driven without it.
+struct Viewer<'a> {
+    name: &'a str,                          // The name which is shown in the status line.
+    data: &'a [u8],                         // The contents of the file.
+    lines: Vec<(usize, usize)>,             // The start and the end of every line in the data.
+    top: usize,                             // The first line which is shown.
+    rows: usize,                            // The number of lines which are shown.
+    cols: usize,                            // The number of columns in each line.
+    pattern: String,                        // The text which was searched for last.
+    found: Option<usize>,                   // The line where the pattern was found last.
+    search: Option<String>,                 // The text which is being typed after '/'.
+    message: Option<&'static str>,          // A message which replaces the status once.
+}
+
+impl<'a> Viewer<'a> {
+    /// A constructor which splits the data to it's lines, and shows the first ones.
+    ///
+    /// # Parameters
+    /// `name` : The name which is shown in the status line.
+    /// `data` : The contents of the file.
+    /// `rows` : The number of lines which are shown.
+    /// `cols` : The number of columns in each line.
+    fn new(name: &'a str, data: &'a [u8], rows: usize, cols: usize) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (idx, byte) in data.iter().enumerate() {
+            if *byte == b'\n' {
+                lines.push((start, idx));
+                start = idx + 1;
+            }
+        }
+
+        // The last line might not end with a newline.
+        if start < data.len() {
+            lines.push((start, data.len()));
+        }
+
+        Viewer { name, data, lines, top: 0, rows, cols, pattern: String::new(), found: None,
+            search: None, message: None }
+    }
+
+    /// A method which handles a single input.
+    ///
+    /// # Parameters
+    /// `input` : The decoded input.
+    ///
+    /// # Returns
+    /// True if it should keep running, False if it should quit.
+    fn handle(&mut self, input: Input) -> bool {
+        // While the search is being typed, the keys edit it.
+        if let Some(search) = self.search.as_mut() {
+            match input {
+                Input::Byte(b'\n') => {
+                    self.pattern = self.search.take().unwrap_or_default();
+                    self.find_from(self.top);
+                },
+                Input::Byte(b'\x08') => { search.pop(); },
+                Input::Byte(b'\x1b') => self.search = None,
+                Input::Byte(byte) if byte.is_ascii_graphic() || byte == b' ' => {
+                    search.push(byte as char);
+                },
+                _ => (),
+            }
+            return true;
+        }
+
+        self.message = None;
+        let last_top = self.lines.len().saturating_sub(self.rows);
+        match input {
+            Input::Up | Input::Byte(b'k') => self.top = self.top.saturating_sub(1),
+            Input::Down | Input::Byte(b'j') | Input::Byte(b'\n') => {
+                self.top = (self.top + 1).min(last_top);
+            },
+            Input::PageUp | Input::Byte(b'b') => self.top = self.top.saturating_sub(self.rows),
+            Input::PageDown | Input::Byte(b' ') => self.top = (self.top + self.rows).min(last_top),
+            Input::Byte(b'g') => self.top = 0,
+            Input::Byte(b'G') => self.top = last_top,
+            Input::Byte(b'/') => self.search = Some(String::new()),
+            Input::Byte(b'n') => self.find_from(self.found.map_or(self.top, |line| line + 1)),
+            Input::Byte(b'q') | Input::Byte(b'Q') => return false,
+            _ => (),
+        }
+
+        true
+    }
+
+    /// A method which finds the next line which has the pattern, and scrolls to it.
+    ///
+    /// # Parameters
+    /// `from` : The first line which is checked.
+    fn find_from(&mut self, from: usize) {
+        let found = if self.pattern.is_empty() { None } else {
+            (from..self.lines.len()).find(|idx| self.render_line(*idx).contains(&self.pattern))
+        };
+
+        match found {
+            Some(line) => {
+                self.found = Some(line);
+                self.top = line.min(self.lines.len().saturating_sub(self.rows));
+            },
+            None => self.message = Some("Pattern not found"),
+        }
+    }
+
+    /// A method which renders a line as it's shown. The tabs are expanded, the bytes which can't be
+    /// printed are replaced with '.', and it's cut to the number of columns.
+    ///
+    /// # Parameters
+    /// `idx` : The index of the line.
+    ///
+    /// # Returns
+    /// The rendered line.
+    fn render_line(&self, idx: usize) -> String {
+        let (start, end) = self.lines[idx];
+        let mut line = String::new();
+        for (offset, byte) in self.data[start..end].iter().enumerate() {
+            match *byte {
+                b'\t' => {
+                    let spaces = TAB_WIDTH - line.len() % TAB_WIDTH;
+                    line.extend(core::iter::repeat(' ').take(spaces));
+                },
+                b'\r' if start + offset == end - 1 => (),
+                byte if byte == b' ' || byte.is_ascii_graphic() => line.push(byte as char),
+                _ => line.push('.'),
+            }
+        }
+
+        line.truncate(self.cols);
+        line
+    }
+
+    /// A method which renders the lines which are shown.
+    ///
+    /// # Returns
+    /// A vector with a rendered line for every row which has a line.
+    fn window(&self) -> Vec<String> {
+        let end = (self.top + self.rows).min(self.lines.len());
+        (self.top..end).map(|idx| self.render_line(idx)).collect()
+    }
+
+    /// A method which creates the status line (the search which is being typed, the message, or
+    /// the position in the file).
+    ///
+    /// # Returns
+    /// The status line.
+    fn status(&self) -> String {
+        if let Some(search) = self.search.as_ref() {
+            return alloc::format!("/{}", search);
+        }
+
+        if let Some(message) = self.message {
+            return String::from(message);
+        }
+
+        let last = (self.top + self.rows).min(self.lines.len());
+        alloc::format!("{}  lines {}-{} of {}  (q: quit, /: search)", self.name,
+            (self.top + 1).min(last), last, self.lines.len())
+    }
+
+    /// A method which draws the lines and the status line (with the matches highlighted).
+    fn draw(&self) {
+        let console = unsafe { crate::console::CONSOLE.as_mut().expect("Console not initialized") };
+        let window = self.window();
+        for row in 0..self.rows {
+            let line = window.get(row).map_or("", |line| line.as_str());
+            console.print_at(row, 0, &alloc::format!("{:<1$}", line, self.cols));
+
+            if !self.pattern.is_empty() {
+                console.set_colors(MATCH_FG, MATCH_BG);
+                for (col, text) in line.match_indices(self.pattern.as_str()) {
+                    console.print_at(row, col, text);
+                }
+                console.reset_colors();
+            }
+        }
+
+        console.set_colors(STATUS_FG, STATUS_BG);
+        console.print_at(self.rows, 0, &alloc::format!("{:<1$}", self.status(), self.cols));
+        console.reset_colors();
+    }
+}
+
+/// A structure which holds the state of the terminal before the viewer started (the screen and the
+/// input mode of the process).
+struct TermState {
+    mode: InputMode,                        // The input mode of the process.
+    cells: Vec<(u8, Color, Color)>,         // The character and the colors of every cell.
+    cols: usize,                            // The number of columns on the screen.
+}
+
+impl TermState {
+    /// A constructor which saves the current state of the terminal.
+    fn save() -> Self {
+        let console = unsafe { crate::console::CONSOLE.as_mut().expect("Console not initialized") };
+        let (rows, cols) = console.get_size();
+        let mode = crate::proc::scheduler::current_pid()
+            .and_then(crate::proc::scheduler::input_mode).unwrap_or(InputMode::Cooked);
+
+        let mut cells = Vec::with_capacity(rows * cols);
+        for row in 0..rows {
+            for col in 0..cols {
+                cells.push(console.get_cell(row, col));
+            }
+        }
+
+        TermState { mode, cells, cols }
+    }
+
+    /// A method which gives the keyboard back to the terminal, and restores the saved state. The
+    /// screen is written back in runs of the cells which have the same colors (the characters 
+    /// which are not ASCII are written as '.', since they can't be passed as a single byte).
+    fn restore(&self) {
+        keyboard::grab(false);
+        crate::proc::set_input_mode(self.mode);
+
+        let console = unsafe { crate::console::CONSOLE.as_mut().expect("Console not initialized") };
+        for (row, cells) in self.cells.chunks(self.cols).enumerate() {
+            let mut col = 0;
+            while col < cells.len() {
+                let (_, fg, bg) = cells[col];
+                let run: String = cells[col..].iter()
+                    .take_while(|cell| cell.1 == fg && cell.2 == bg)
+                    .map(|cell| if cell.0.is_ascii() { cell.0 as char } else { '.' }).collect();
+
+                console.set_colors(fg, bg);
+                console.print_at(row, col, &run);
+                col += run.len();
+            }
+        }
+        console.reset_colors();
+    }
+}
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("view: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+    oxid_println!();
+
+    let path = match full_args.get(1).map(|path| crate::fs::path::resolve(path)) {
+        Some(Ok(path)) => path,
+        Some(Err(())) => {
+            oxid_err!("view: Invalid path.");
+            return 1;
+        },
+        None => {
+            oxid_err!("Usage: view <path>");
+            return 1;
+        },
+    };
+
+    let data = match crate::fs::initrd::read(&path) {
+        Some(data) => data,
+        None => {
+            oxid_err!("view: {}: No such file.", path);
+            return 1;
+        },
+    };
+
+    if data.len() > MAX_FILE_SIZE {
+        oxid_err!("view: {} is too large ({} bytes, the limit is {} bytes).", path, data.len(),
+            MAX_FILE_SIZE);
+        return 1;
+    }
+
+    // The keyboard can't be read in the foreground (the interrupts are disabled).
+    if !unsafe { crate::arch::interrupts::are_enabled() } {
+        oxid_err!("view has to run in the background (view <path> &).");
+        return 1;
+    }
+
+    // Save the terminal, and take the keyboard in the raw mode (so every key is read right away).
+    let state = TermState::save();
+    crate::proc::set_input_mode(InputMode::Raw);
+    keyboard::grab(true);
+
+    let (rows, cols) = unsafe {
+        crate::console::CONSOLE.as_ref().expect("Console not initialized").get_size()
+    };
+    let mut viewer = Viewer::new(&path, data, rows - 1, cols);
+    let mut parser = InputParser::new();
+    let mut running = true;
+    viewer.draw();
+
+    while running && !crate::proc::scheduler::termination_requested() {
+        let mut buf: [u8; 16] = [0; 16];
+        let count = keyboard::read(&mut buf);
+
+        let mut handle = |input: Input| running &= viewer.handle(input);
+        for byte in buf[..count].iter() {
+            parser.feed(*byte, &mut handle);
+        }
+        if count == 0 {
+            parser.flush(&mut handle);
+            crate::time::sleep_ms(POLL_MS);
+            continue;
+        }
+
+        if running {
+            viewer.draw();
+        }
+    }
+
+    state.restore();
+    0
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use crate::io::keyboard::{self, Event, Key};
+    use crate::proc::exec::{exec, ExecFlags, ExecResult};
+    use super::{Viewer, InputParser};
+
+    /// The path of the test file in the initrd.
+    const TEST_PATH: &str = "/tmp/view_test";
+
+    /// The number of lines in the test file.
+    const TEST_LINES: usize = 60;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        let data = test_text();
+        test_navigation(data);
+        test_program(data);
+    }
+
+    /// A helper which creates the test file (60 numbered lines, with a binary one and a tab). It's
+    /// leaked, since the initrd only holds the static data.
+    ///
+    /// # Returns
+    /// The contents of the file.
+    fn test_text() -> &'static [u8] {
+        let mut text = String::new();
+        for line in 1..=TEST_LINES {
+            match line {
+                3 => text.push_str("bin\x01\x7fary\tend\n"),
+                _ => text.push_str(&alloc::format!("line {}\n", line)),
+            }
+        }
+
+        alloc::boxed::Box::leak(text.into_boxed_str()).as_bytes()
+    }
+
+    /// A helper which feeds the bytes of a key script to a viewer (like the raw input).
+    ///
+    /// # Parameters
+    /// `viewer` : The viewer which handles the keys.
+    /// `script` : The bytes which were typed.
+    ///
+    /// # Returns
+    /// True if it's still running, False if it quit.
+    fn press(viewer: &mut Viewer, script: &[u8]) -> bool {
+        let mut parser = InputParser::new();
+        let mut running = true;
+        let mut handle = |input: super::Input| running &= viewer.handle(input);
+        for byte in script.iter() {
+            parser.feed(*byte, &mut handle);
+        }
+        parser.flush(&mut handle);
+
+        running
+    }
+
+    /// Drive a 10 row viewer with a key script, and check the lines which are shown after each key.
+    ///
+    /// # Parameters
+    /// `data` : The contents of the test file.
+    fn test_navigation(data: &[u8]) {
+        let mut viewer = Viewer::new("test", data, 10, 40);
+        let first_line = |viewer: &Viewer| viewer.window()[0].clone();
+        assert_eq!(viewer.window().len(), 10);
+        assert_eq!(first_line(&viewer), "line 1");
+        assert_eq!(viewer.window()[2], "bin..ary    end");
+        assert_eq!(viewer.status(), "test  lines 1-10 of 60  (q: quit, /: search)");
+
+        // The page keys, the arrows, and the jumps.
+        let steps: [(&[u8], &str); 8] = [(b"\x1b[6~", "line 11"), (b"\x1b[B", "line 12"),
+            (b"\x1b[A", "line 11"), (b"\x1b[5~", "line 1"), (b"\x1b[A", "line 1"),
+            (b"G", "line 51"), (b"\x1b[6~", "line 51"), (b"g", "line 1")];
+        for (script, expected) in steps.iter() {
+            assert!(press(&mut viewer, script));
+            assert_eq!(first_line(&viewer), *expected);
+        }
+
+        // Search forward (the status shows the search while it's typed).
+        assert!(press(&mut viewer, b"/line 4"));
+        assert_eq!(viewer.status(), "/line 4");
+        assert!(press(&mut viewer, b"5\n"));
+        assert_eq!(first_line(&viewer), "line 45");
+        assert!(press(&mut viewer, b"/line 5\n"));
+        assert_eq!(first_line(&viewer), "line 50");
+        assert!(press(&mut viewer, b"n"));
+        assert_eq!(first_line(&viewer), "line 51");
+        assert_eq!(viewer.found, Some(50));
+        assert!(press(&mut viewer, b"/missing\n"));
+        assert_eq!(viewer.status(), "Pattern not found");
+
+        // An escape cancels the search, and q quits.
+        assert!(press(&mut viewer, b"/abc\x1b"));
+        assert!(viewer.search.is_none());
+        assert!(!press(&mut viewer, b"q"));
+    }
+
+    /// Run the program in the background, page down with the keyboard, and quit. Make sure the page
+    /// was shown, and the keyboard was given back. The errors are checked in the foreground.
+    ///
+    /// # Parameters
+    /// `data` : The contents of the test file.
+    fn test_program(data: &'static [u8]) {
+        assert_eq!(crate::fs::initrd::add(TEST_PATH, data), Ok(()));
+        assert_eq!(exec("view /tmp/view_missing", ExecFlags::NONE), Ok(ExecResult::Exited(1)));
+
+        crate::io::term::start_capture();
+        crate::io::term::type_line("view /tmp/view_test &\n");
+        crate::time::sleep_ms(super::POLL_MS * 5);
+
+        for key in [Key::PageDown, Key::Ch('q')].iter() {
+            keyboard::inject(Event::new(*key, true));
+            keyboard::inject(Event::new(*key, false));
+            crate::time::sleep_ms(super::POLL_MS * 5);
+        }
+
+        // Wait (for up to a second) for it to exit.
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        while keyboard::discipline::owner().is_some() && crate::time::ticks() < deadline {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+
+        let mut output = String::new();
+        crate::io::term::capture_output(&mut output);
+
+        let (rows, _) = unsafe {
+            crate::console::CONSOLE.as_ref().expect("Console not initialized").get_size()
+        };
+        assert!(keyboard::discipline::owner().is_none());
+        assert!(output.contains("\nline 1\n"));
+        assert!(output.contains(&alloc::format!("\nline {}\n", rows)));
+        assert!(output.contains("/tmp/view_test  lines 1-"));
+        assert_eq!(crate::fs::initrd::remove(TEST_PATH), Ok(()));
+    }
+}
@@ -0,0 +1,120 @@
+//! A program which prints a line (`y` by default, or it's arguments) as fast as it can, until it's
+//! killed. It's used to stress the console: the output is throttled (see console::print_output),
+//! so the terminal should stay responsive while it's running in the background (`yes &`).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::proc::process::Args;
+use crate::olibc::bounded::BoundedString;
+
+/// The maximum number of bytes in the printed line.
+const MAX_LINE: usize = 128;
+
+/// The main function as specified by the system requirements.
+///
+/// # Parameters
+/// `args` : The list of arguments.
+///
+/// # Returns
+/// The exit code (non-zero if it failed).
+pub extern "sysv64" fn main(args: *const Args) -> usize {
+    let full_args: alloc::vec::Vec<&str> = match Args::parse(args) {
+        Ok(view) => view.argv().collect(),
+        Err(error) => {
+            oxid_err!("yes: Invalid arguments ({:?}).", error);
+            return 1;
+        },
+    };
+
+    // Join the arguments (like the original yes), the line is cut if it's too long.
+    let mut line: BoundedString<MAX_LINE> = BoundedString::new();
+    if full_args.len() > 1 {
+        for (idx, arg) in full_args[1..].iter().enumerate() {
+            if idx > 0 {
+                line.push(' ');
+            }
+            let _ = line.push_str(arg);
+        }
+    } else {
+        line.push('y');
+    }
+
+    // Print it forever (or until we're asked to exit).
+    while !crate::proc::scheduler::termination_requested() {
+        oxid_println!("{}", line.as_str());
+    }
+    0
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::proc::exec::{exec, ExecFlags, ExecResult};
+    use crate::proc::scheduler;
+
+    /// The number of keys which are typed while it's running.
+    const KEYS: usize = 5;
+
+    /// The most ticks the terminal can take to echo a key while it's running.
+    const MAX_ECHO_TICKS: u64 = 4;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_echo_latency();
+    }
+
+    /// Run yes in the background, and type a few keys through the keyboard's bottom half (like the
+    /// interrupt would). The producer should be throttled, and the terminal should echo every key
+    /// within a few ticks.
+    fn test_echo_latency() {
+        use crate::arch::io::ps2_keyboard;
+        use crate::arch::interrupts::handlers::threaded;
+
+        let throttled = crate::console::throttled();
+        let handle = match exec("yes backpressure test", ExecFlags::BACKGROUND) {
+            Ok(ExecResult::Spawned(pid)) => scheduler::handle_of(pid).unwrap(),
+            other => panic!("Unexpected exec result {:?}", other),
+        };
+
+        // Let it use up it's burst first.
+        let start = crate::time::ticks();
+        while crate::time::ticks() < start + 2 {
+            scheduler::yield_now();
+        }
+
+        // Press and release a, and wait for the terminal to add it to the line.
+        let mut max_latency = 0;
+        for _ in 0..KEYS {
+            let typed = crate::io::term::input_len();
+            let pressed_at = crate::time::ticks();
+            ps2_keyboard::queue_scancode(0x1E);
+            ps2_keyboard::queue_scancode(0x9E);
+            assert_eq!(threaded::schedule_bottom(ps2_keyboard::IRQ_NUM), Ok(()));
+
+            let deadline = pressed_at + crate::time::ms_to_ticks(2000);
+            while crate::io::term::input_len() == typed && crate::time::ticks() < deadline {
+                scheduler::yield_now();
+            }
+            max_latency = max_latency.max(crate::time::ticks() - pressed_at);
+        }
+
+        // Stop it, and remove what was typed.
+        scheduler::kill_pid(handle);
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+        while scheduler::find_handle(handle, |_| ()).is_some() && crate::time::ticks() < deadline {
+            scheduler::yield_now();
+        }
+        for _ in 0..KEYS {
+            crate::io::term::type_line("\x08");
+        }
+
+        assert!(scheduler::find_handle(handle, |_| ()).is_none());
+        assert!(crate::console::throttled() > throttled);
+        assert!(max_latency <= MAX_ECHO_TICKS, "The echo took {} ticks.", max_latency);
+    }
+}
@@ -0,0 +1,141 @@
+//! A module which lets the kernel check (at runtime) which cargo features it was built with. The
+//! commands which depend on a feature check it first, so they can tell the user it's missing
+//! instead of silently doing nothing. The list is generated from the cfg checks by a macro, so
+//! every feature in Cargo.toml should be added to the features! invocation below.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// A macro which defines the list of known features (and the enabled ones) from the feature names.
+/// The names are passed to cfg!, so they are always in sync with the checks.
+macro_rules! features {
+    ($($name:tt),* $(,)?) => {
+        /// Every known feature, and if it's enabled in this build.
+        const ALL: &[(&str, bool)] = &[$(($name, cfg!(feature = $name))),*];
+
+        /// The features which are enabled in this build.
+        static ENABLED: &[&str] = &[$(#[cfg(feature = $name)] $name),*];
+    };
+}
+
+features!("show-page-faults", "trace", "latency-stats", "heap-debug", "tlb-check",
+    "strict-debug", "unit-test");
+
+/// A structure which displays the enabled features as a comma separated list ("none" if there are
+/// none). It doesn't need the heap, so it can be printed at any time.
+pub struct FeatureList;
+
+impl core::fmt::Display for FeatureList {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if ENABLED.is_empty() {
+            return write!(f, "none");
+        }
+
+        for (idx, name) in ENABLED.iter().enumerate() {
+            write!(f, "{}{}", if idx == 0 { "" } else { "," }, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A function which returns the features that the kernel was built with.
+///
+/// # Returns
+/// The names of the enabled features (in the same order as Cargo.toml).
+pub fn enabled() -> &'static [&'static str] {
+    ENABLED
+}
+
+/// A function which checks if the kernel was built with a given feature.
+///
+/// # Parameters
+/// `name` : The name of the feature (as in Cargo.toml).
+///
+/// # Returns
+/// True if it's enabled, False if it's not (or it's not a known feature).
+pub fn has(name: &str) -> bool {
+    ALL.iter().any(|&(feature, enabled)| feature == name && enabled)
+}
+
+/// A function which checks if a feature is known (listed in Cargo.toml).
+///
+/// # Parameters
+/// `name` : The name of the feature.
+///
+/// # Returns
+/// True if it's a known feature, False otherwise.
+pub fn is_known(name: &str) -> bool {
+    ALL.iter().any(|&(feature, _)| feature == name)
+}
+
+/// A function which returns a printable list of the enabled features.
+///
+/// # Returns
+/// The list, which can be used with the formatting macros.
+pub fn list() -> FeatureList {
+    FeatureList
+}
+
+/// A function which is called by the commands that need a feature. It prints an error if the
+/// kernel was built without it.
+///
+/// # Parameters
+/// `what` : The name of what needs the feature (such as the command).
+/// `name` : The name of the feature.
+///
+/// # Returns
+/// True if the feature is enabled (the command can continue), False otherwise.
+pub fn require(what: &str, name: &str) -> bool {
+    debug_assert!(is_known(name), "Unknown feature \"{}\".", name);
+
+    if !has(name) {
+        oxid_err!("{} is not compiled in (enable feature {}).", what, name);
+    }
+    has(name)
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::{String, ToString};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_flags();
+        test_guard();
+    }
+
+    /// Make sure the flags agree with the build (the test build never has heap-debug).
+    fn test_flags() {
+        assert!(super::has("unit-test"));
+        assert!(super::enabled().contains(&"unit-test"));
+        assert!(!super::has("heap-debug") && !super::enabled().contains(&"heap-debug"));
+        assert!(!super::has("bogus") && !super::is_known("bogus"));
+        assert_eq!(super::has("trace"), cfg!(feature = "trace"));
+
+        let list = super::list().to_string();
+        assert!(list.split(',').eq(super::enabled().iter().copied()));
+    }
+
+    /// Make sure a missing feature is reported by the guard, and by a command which needs it.
+    fn test_guard() {
+        use crate::proc::exec::{exec, ExecFlags};
+
+        let mut output = String::new();
+        crate::io::term::start_capture();
+        assert!(!super::require("Heap debugging", "heap-debug"));
+        assert!(super::require("The tests", "unit-test"));
+        exec("latstat", ExecFlags::NONE).unwrap();
+        crate::io::term::capture_output(&mut output);
+
+        assert!(output.contains("Heap debugging is not compiled in (enable feature heap-debug)."));
+        assert!(!output.contains("The tests is not compiled in"));
+        assert!(output.contains("(enable feature latency-stats)") || super::has("latency-stats"));
+    }
+}
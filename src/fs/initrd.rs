@@ -0,0 +1,118 @@
+//! A sub-module which provides a minimal read-only initial ramdisk. Every boot module which is 
+//! passed with an absolute path as it's string (for example `module2 /boot/rc /etc/rc` in GRUB) is
+//! registered as a file with that path. There are no directories, the files are only looked up by
+//! their normalized paths.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::multiboot2::MultibootInfo;
+
+/// The files in the ramdisk (their normalized paths, and contents).
+static mut FILES: Vec<(String, &'static [u8])> = Vec::new();
+
+/// A function which registers the boot modules as the files of the ramdisk. It should be called 
+/// after the heap is initialized.
+///
+/// # Parameters
+/// `mb_info` : The multiboot information reference (already parsed).
+pub fn init(mb_info: &MultibootInfo) {
+    for module in mb_info.module_tags.as_slice() {
+        // The path is the first word of the module's string.
+        let path = module.cmd().split_whitespace().next().unwrap_or("");
+        if !super::path::is_absolute(path) {
+            oxid_warn!("Ignoring the boot module \"{}\" (not an absolute path).", module.cmd());
+            continue;
+        }
+        
+        if add(path, unsafe { module.as_slice() }).is_ok() {
+            oxid_log!("Added {} to the initrd ({} bytes).", path, module.end - module.start);
+        }
+    }
+}
+
+/// A function which adds a file to the ramdisk (it replaces the file if it already exists).
+///
+/// # Parameters
+/// `path` : The absolute path of the file.
+/// `data` : The contents of the file.
+///
+/// # Returns
+/// Ok if it was added, Err if the path is not valid.
+pub fn add(path: &str, data: &'static [u8]) -> Result<(), ()> {
+    if !super::path::is_absolute(path) {
+        return Err(());
+    }
+    let path = super::path::normalize(path)?;
+    
+    unsafe {
+        match FILES.iter_mut().find(|(name, _)| *name == path) {
+            Some(file) => file.1 = data,
+            None => FILES.push((path, data)),
+        }
+    }
+    
+    Ok(())
+}
+
+/// A function which removes a file from the ramdisk.
+///
+/// # Parameters
+/// `path` : The absolute path of the file.
+///
+/// # Returns
+/// Ok if it was removed, Err if it does not exist.
+pub fn remove(path: &str) -> Result<(), ()> {
+    let path = super::path::normalize(path)?;
+    
+    unsafe {
+        let index = FILES.iter().position(|(name, _)| *name == path).ok_or(())?;
+        FILES.remove(index);
+    }
+    
+    Ok(())
+}
+
+/// A function which finds a file in the ramdisk.
+///
+/// # Parameters
+/// `path` : The absolute path of the file.
+///
+/// # Returns
+/// Some(contents) if it exists, None otherwise.
+pub fn read(path: &str) -> Option<&'static [u8]> {
+    let path = super::path::normalize(path).ok()?;
+    
+    unsafe { FILES.iter().find(|(name, _)| *name == path).map(|(_, data)| *data) }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_add_read();
+    }
+    
+    /// Add a file, read it through a path which is not normalized, replace it, and remove it.
+    fn test_add_read() {
+        assert_eq!(super::add("/tmp//initrd_test", b"first"), Ok(()));
+        assert_eq!(super::read("/tmp/./initrd_test"), Some(&b"first"[..]));
+        
+        assert_eq!(super::add("/tmp/initrd_test/", b"second"), Ok(()));
+        assert_eq!(super::read("/tmp/initrd_test"), Some(&b"second"[..]));
+        
+        assert_eq!(super::add("relative", b"rejected"), Err(()));
+        assert_eq!(super::remove("/tmp/initrd_test"), Ok(()));
+        assert_eq!(super::read("/tmp/initrd_test"), None);
+        assert_eq!(super::remove("/tmp/initrd_test"), Err(()));
+    }
+}
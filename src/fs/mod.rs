@@ -0,0 +1,23 @@
+//! A module which provides the file system related code. There are no file systems yet, so it 
+//! only includes the utilities which are shared by everything that handles paths, and a read-only
+//! ramdisk which holds the boot modules.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+pub mod path;
+pub mod initrd;
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::path::test::run();
+        super::initrd::test::run();
+    }
+}
@@ -0,0 +1,252 @@
+//! A sub-module which provides the path manipulation utilities: normalizing (removing empty 
+//! components, `.` and `..`), joining a relative path to a base directory, and splitting a path to
+//! it's directory and name. Paths are separated by `/`, and absolute paths start with it.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The separator between the components of a path.
+pub const SEPARATOR: char = '/';
+
+/// The root directory.
+pub const ROOT: &str = "/";
+
+/// The maximum length of a single component (in bytes).
+pub const MAX_COMPONENT_LEN: usize = 255;
+
+/// A function which checks if a path is absolute (starts at the root).
+///
+/// # Parameters
+/// `path` : The path which we're checking.
+///
+/// # Returns
+/// True if it's absolute, False if it's relative.
+#[inline]
+pub fn is_absolute(path: &str) -> bool {
+    path.starts_with(SEPARATOR)
+}
+
+/// A function which normalizes a path. It removes the empty components (`//` and trailing 
+/// slashes) and the `.` components, and collapses the `..` components with the one before them.
+/// The leading `..` components of relative paths are kept, since they depend on the base.
+///
+/// # Parameters
+/// `path` : The path which we're normalizing.
+///
+/// # Returns
+/// Ok(normalized) if it's valid, Err if a component is too long or it escapes the root.
+pub fn normalize(path: &str) -> Result<String, ()> {
+    let absolute = is_absolute(path);
+    let mut components: Vec<&str> = Vec::new();
+    
+    // Go through the non-empty components.
+    for component in path.split(SEPARATOR).filter(|component| !component.is_empty()) {
+        if component.len() > MAX_COMPONENT_LEN {
+            return Err(());
+        }
+        
+        match component {
+            "." => (),
+            
+            // Remove the previous component (unless it's also a leading ..).
+            ".." => match components.last() {
+                Some(&last) if last != ".." => { components.pop(); },
+                _ if absolute => return Err(()),
+                _ => components.push(component),
+            },
+            
+            _ => components.push(component),
+        }
+    }
+    
+    // Put them back together.
+    let mut normalized = String::new();
+    if absolute {
+        normalized.push(SEPARATOR);
+    }
+    normalized.push_str(&components.join("/"));
+    
+    // An empty relative path is the current directory.
+    if normalized.is_empty() {
+        normalized.push('.');
+    }
+    
+    Ok(normalized)
+}
+
+/// A function which joins a path to a base directory. If the path is absolute, the base is 
+/// ignored. The result is normalized.
+///
+/// # Parameters
+/// `base` : The directory which relative paths start from (such as the current directory).
+/// `path` : The path which we're joining.
+///
+/// # Returns
+/// Ok(joined) if it's valid, Err if a component is too long or it escapes the root.
+pub fn join(base: &str, path: &str) -> Result<String, ()> {
+    if is_absolute(path) {
+        return normalize(path);
+    }
+    
+    let mut joined = String::from(base);
+    joined.push(SEPARATOR);
+    joined.push_str(path);
+    normalize(&joined)
+}
+
+/// A function which splits a path to it's directory and it's name (the last component). The 
+/// trailing slashes are ignored. It does not normalize the path.
+///
+/// # Parameters
+/// `path` : The path which we're splitting.
+///
+/// # Returns
+/// A tuple with the directory ("." if there is none) and the name ("" for the root).
+pub fn split(path: &str) -> (&str, &str) {
+    // Ignore the trailing slashes (but keep the root).
+    let trimmed = path.trim_end_matches(SEPARATOR);
+    if trimmed.is_empty() {
+        return if is_absolute(path) { (ROOT, "") } else { (".", "") };
+    }
+    
+    match trimmed.rfind(SEPARATOR) {
+        Some(idx) => {
+            // Ignore the repeated slashes before the name as well.
+            let dir = trimmed[..idx].trim_end_matches(SEPARATOR);
+            (if dir.is_empty() { ROOT } else { dir }, &trimmed[idx + 1..])
+        },
+        None => (".", trimmed),
+    }
+}
+
+/// A function which returns the directory of a path (see split).
+///
+/// # Parameters
+/// `path` : The path.
+///
+/// # Returns
+/// The directory which includes the last component.
+#[inline]
+pub fn dirname(path: &str) -> &str {
+    split(path).0
+}
+
+/// A function which returns the name of the last component of a path (see split).
+///
+/// # Parameters
+/// `path` : The path.
+///
+/// # Returns
+/// The last component.
+#[inline]
+pub fn basename(path: &str) -> &str {
+    split(path).1
+}
+
+/// A function which resolves a path against the current directory of the running process.
+///
+/// # Parameters
+/// `path` : The path which we're resolving (absolute or relative).
+///
+/// # Returns
+/// Ok(absolute) if it's valid, Err if a component is too long or it escapes the root.
+pub fn resolve(path: &str) -> Result<String, ()> {
+    join(&crate::proc::scheduler::current_cwd(), path)
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use super::{normalize, join, split, dirname, basename};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_normalize();
+        test_dot_dot();
+        test_component_length();
+        test_join();
+        test_split();
+    }
+
+    /// A helper which normalizes a path and unwraps it.
+    ///
+    /// # Parameters
+    /// `path` : The path which we're normalizing.
+    ///
+    /// # Returns
+    /// The normalized path.
+    fn norm(path: &str) -> String {
+        normalize(path).unwrap()
+    }
+
+    /// Check the removal of empty components, trailing slashes, and dots.
+    fn test_normalize() {
+        assert_eq!(norm("/"), "/");
+        assert_eq!(norm("//"), "/");
+        assert_eq!(norm("/usr//bin/"), "/usr/bin");
+        assert_eq!(norm("/./usr/./bin/."), "/usr/bin");
+        assert_eq!(norm("usr/bin/"), "usr/bin");
+        assert_eq!(norm(""), ".");
+        assert_eq!(norm("./"), ".");
+    }
+
+    /// Check the collapsing of the .. components (and escaping the root).
+    fn test_dot_dot() {
+        assert_eq!(norm("/usr/bin/.."), "/usr");
+        assert_eq!(norm("/usr/../bin"), "/bin");
+        assert_eq!(norm("/usr/.."), "/");
+        assert_eq!(normalize("/.."), Err(()));
+        assert_eq!(normalize("/usr/../.."), Err(()));
+        
+        // The leading .. of relative paths are kept.
+        assert_eq!(norm("../a"), "../a");
+        assert_eq!(norm("a/../../b"), "../b");
+        assert_eq!(norm("a/.."), ".");
+    }
+
+    /// Make sure components longer than the limit are rejected.
+    fn test_component_length() {
+        let mut path = String::from("/");
+        for _ in 0..super::MAX_COMPONENT_LEN {
+            path.push('a');
+        }
+        assert!(normalize(&path).is_ok());
+        
+        path.push('a');
+        assert_eq!(normalize(&path), Err(()));
+    }
+
+    /// Check joining relative and absolute paths to a base.
+    fn test_join() {
+        assert_eq!(join("/home", "docs").unwrap(), "/home/docs");
+        assert_eq!(join("/home/", "./docs/").unwrap(), "/home/docs");
+        assert_eq!(join("/home", "..").unwrap(), "/");
+        assert_eq!(join("/home", "/etc").unwrap(), "/etc");
+        assert_eq!(join("/", "").unwrap(), "/");
+        assert_eq!(join("/home", "../.."), Err(()));
+    }
+
+    /// Check splitting the paths to their directories and names.
+    fn test_split() {
+        assert_eq!(split("/usr/bin/ls"), ("/usr/bin", "ls"));
+        assert_eq!(split("/usr/bin/"), ("/usr", "bin"));
+        assert_eq!(split("/usr"), ("/", "usr"));
+        assert_eq!(split("//usr"), ("/", "usr"));
+        assert_eq!(split("/"), ("/", ""));
+        assert_eq!(split("ls"), (".", "ls"));
+        assert_eq!(split("a//b"), ("a", "b"));
+        assert_eq!(split(""), (".", ""));
+        assert_eq!(dirname("/a/b"), "/a");
+        assert_eq!(basename("/a/b"), "b");
+    }
+}
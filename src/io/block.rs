@@ -0,0 +1,192 @@
+//! A sub-module which defines the interface of the block devices (the devices which are read and
+//! written in fixed size sectors, such as disks), and keeps a list of the devices which were found
+//! by the drivers. Reading many sectors at once (read_sectors) polls a cancellation token between
+//! the sectors (see proc::cancel), so a long scan can be interrupted.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::arch::io::ata::AtaError;
+use crate::proc::cancel::CancelToken;
+
+/// The size of a sector (in bytes).
+pub const SECTOR_SIZE: usize = 512;
+
+/// An enum which represents the reason a block device operation failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlockError {
+    OutOfRange,                 // The sector is past the end of the device.
+    Ata(AtaError),              // The ATA drive reported an error (or it timed out).
+    Cancelled,                  // The token was cancelled before all the sectors were read.
+}
+
+/// The interface which every block device implements.
+pub trait BlockDevice {
+    /// A method which returns a short name for the device (such as "ata0").
+    ///
+    /// # Returns
+    /// The name of the device.
+    fn name(&self) -> &str;
+
+    /// A method which returns the number of sectors in the device.
+    ///
+    /// # Returns
+    /// The number of sectors (each one is SECTOR_SIZE bytes).
+    fn num_sectors(&self) -> u64;
+
+    /// A method which reads a sector from the device.
+    ///
+    /// # Parameters
+    /// `lba` : The logical block address of the sector.
+    /// `buf` : The buffer which the sector is read into.
+    ///
+    /// # Returns
+    /// Ok if it was read, Err with the reason otherwise.
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), BlockError>;
+
+    /// A method which writes a sector to the device.
+    ///
+    /// # Parameters
+    /// `lba` : The logical block address of the sector.
+    /// `buf` : The contents of the sector.
+    ///
+    /// # Returns
+    /// Ok if it was written, Err with the reason otherwise.
+    fn write_sector(&mut self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), BlockError>;
+
+    /// A method which reads consecutive sectors from the device. The token is polled before each
+    /// sector, and the sectors which were already read are kept in the buffer if it's cancelled.
+    ///
+    /// # Parameters
+    /// `lba` : The logical block address of the first sector.
+    /// `buf` : The buffer which the sectors are read into (a multiple of SECTOR_SIZE bytes).
+    /// `token` : The token which stops the read once it's cancelled.
+    ///
+    /// # Returns
+    /// Ok if they were all read, Err with the reason otherwise.
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8], token: &CancelToken)
+        -> Result<(), BlockError> {
+        let mut sector = [0u8; SECTOR_SIZE];
+        for (idx, chunk) in buf.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            token.check().map_err(|_| BlockError::Cancelled)?;
+            self.read_sector(lba + idx as u64, &mut sector)?;
+            chunk.copy_from_slice(&sector);
+        }
+        Ok(())
+    }
+}
+
+/// The block devices which were registered by the drivers.
+static mut DEVICES: Vec<Box<dyn BlockDevice>> = Vec::new();
+
+/// A function which adds a block device to the list of devices.
+///
+/// # Parameters
+/// `device` : The device which was found by a driver.
+pub fn register(device: Box<dyn BlockDevice>) {
+    unsafe { DEVICES.push(device); }
+}
+
+/// A simple getter for the number of registered block devices.
+///
+/// # Returns
+/// The number of devices.
+pub fn count() -> usize {
+    unsafe { DEVICES.len() }
+}
+
+/// A function which calls a given function with a registered block device.
+///
+/// # Parameters
+/// `index` : The index of the device (in the order they were registered).
+/// `func` : The function which is called with the device.
+///
+/// # Returns
+/// Some with the function's result if the device exists, None otherwise.
+pub fn with_device<R>(index: usize, func: impl FnOnce(&mut dyn BlockDevice) -> R) -> Option<R> {
+    unsafe { DEVICES.get_mut(index).map(|device| func(device.as_mut())) }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::proc::cancel::CancelToken;
+    use super::{BlockDevice, BlockError, SECTOR_SIZE};
+
+    /// A fake device where every sector is filled with it's own LBA. It cancels a token once a
+    /// given sector is read (like Ctrl+C in the middle of a scan).
+    struct FakeDevice<'a> {
+        token: &'a CancelToken,     // The token which is cancelled.
+        cancel_at: Option<u64>,     // The sector which it's cancelled at (None if never).
+        reads: usize,               // The number of sectors which were read.
+    }
+
+    impl BlockDevice for FakeDevice<'_> {
+        fn name(&self) -> &str {
+            "fake0"
+        }
+
+        fn num_sectors(&self) -> u64 {
+            16
+        }
+
+        fn read_sector(&mut self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), BlockError> {
+            if lba >= self.num_sectors() {
+                return Err(BlockError::OutOfRange);
+            }
+
+            buf.iter_mut().for_each(|byte| *byte = lba as u8);
+            self.reads += 1;
+            if self.cancel_at == Some(lba) {
+                self.token.cancel();
+            }
+            Ok(())
+        }
+
+        fn write_sector(&mut self, _lba: u64, _buf: &[u8; SECTOR_SIZE]) -> Result<(), BlockError> {
+            Ok(())
+        }
+    }
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_read_sectors();
+        test_cancelled();
+    }
+
+    /// Read a few sectors at once, and make sure they're in order (and the end is checked).
+    fn test_read_sectors() {
+        let token = CancelToken::new();
+        let mut device = FakeDevice { token: &token, cancel_at: None, reads: 0 };
+        let mut buf = [0u8; SECTOR_SIZE * 4];
+
+        assert_eq!(device.read_sectors(2, &mut buf, &token), Ok(()));
+        assert!(buf.chunks(SECTOR_SIZE).enumerate()
+            .all(|(idx, chunk)| chunk.iter().all(|&byte| byte == idx as u8 + 2)));
+        assert_eq!(device.read_sectors(14, &mut buf, &token), Err(BlockError::OutOfRange));
+    }
+
+    /// Cancel the token in the middle of a read, and make sure it stops at the next sector.
+    fn test_cancelled() {
+        let token = CancelToken::new();
+        let mut device = FakeDevice { token: &token, cancel_at: Some(5), reads: 0 };
+        let mut buf = [0u8; SECTOR_SIZE * 8];
+
+        assert_eq!(device.read_sectors(3, &mut buf, &token), Err(BlockError::Cancelled));
+        assert_eq!(device.reads, 3);
+        assert_eq!(buf[SECTOR_SIZE * 3], 0);
+
+        token.reset();
+        device.cancel_at = None;
+        assert_eq!(device.read_sectors(3, &mut buf, &token), Ok(()));
+        assert_eq!(device.reads, 11);
+    }
+}
@@ -0,0 +1,306 @@
+//! A sub-module which handles the system key chords before the keys are delivered (so they work in
+//! the raw mode, and while the terminal is busy). Ctrl+Alt+Del reboots the system (after a short
+//! grace period), and Ctrl+Alt+F1 to F4 switch the virtual terminal. The chords only fire once per
+//! press (the repeats and the release of the key are swallowed). Pressing Escape three times within
+//! two seconds force-kills the program which grabbed the keyboard, as a last resort.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use super::{Event, Key};
+use crate::time::timeout::{AtomicFlag, Timeout, TimeoutTarget};
+
+/// The number of virtual terminals which can be switched to (Ctrl+Alt+F1 to F4).
+pub const NUM_VTS: u8 = 4;
+
+/// The number of Escape presses which force-kill the program.
+const ESC_PRESSES: usize = 3;
+
+/// The time which all of the Escape presses should happen in, starting from the first one (in 
+/// milliseconds).
+const ESC_WINDOW_MS: usize = 2000;
+
+/// An enum which represents the actions triggered by the chords.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChordAction {
+    Reboot,                     // Ctrl+Alt+Del.
+    SwitchVt(u8),               // Ctrl+Alt+F1 to F4 (the terminal number).
+    ForceKill,                  // Escape pressed three times.
+}
+
+/// The key which completed the last chord (it's swallowed until it's released).
+static mut CHORD_KEY: Option<Key> = None;
+
+/// The number of Escape presses since the first one (in the window).
+static mut ESC_COUNT: usize = 0;
+
+/// The timeout of the window (it's armed on the first press).
+static mut ESC_TIMEOUT: Option<Timeout> = None;
+
+/// Set when the window is over (the presses are counted from zero after it).
+static ESC_EXPIRED: AtomicFlag = AtomicFlag::new();
+
+/// True while the Escape key is held (so the repeats are not counted).
+static mut ESC_HELD: bool = false;
+
+/// The virtual terminal which is active.
+static mut ACTIVE_VT: u8 = 1;
+
+/// The function which replaces the actions in the tests.
+#[cfg(feature = "unit-test")]
+static mut TEST_ACTION: Option<fn(ChordAction)> = None;
+
+/// A function which checks an event for the system chords, and triggers their actions. It's called
+/// with the interrupts disabled (after the modifiers were updated), so it never blocks.
+///
+/// # Parameters
+/// `event` : The keyboard event.
+/// `ctrl` : True if a control key is held.
+/// `alt` : True if an alt key is held.
+///
+/// # Returns
+/// True if the event was consumed (it should not be delivered), False otherwise.
+pub fn filter(event: &Event, ctrl: bool, alt: bool) -> bool {
+    unsafe {
+        // The key of the last chord is swallowed until it's released (the repeats don't fire).
+        if let Some(chord_key) = CHORD_KEY {
+            if chord_key == event.key {
+                if !event.pressed {
+                    CHORD_KEY = None;
+                }
+                return true;
+            }
+        }
+
+        if event.key == Key::Esc {
+            return count_escape(event.pressed);
+        }
+
+        if !event.pressed || !(ctrl && alt) {
+            return false;
+        }
+
+        let action = match event.key {
+            Key::Delete => ChordAction::Reboot,
+            Key::F(number) if number >= 1 && number <= NUM_VTS => ChordAction::SwitchVt(number),
+            _ => return false,
+        };
+
+        CHORD_KEY = Some(event.key);
+        trigger(action);
+        true
+    }
+}
+
+/// A simple getter for the virtual terminal which is active.
+///
+/// # Returns
+/// The number of the terminal (1 to NUM_VTS).
+pub fn active_vt() -> u8 {
+    unsafe { ACTIVE_VT }
+}
+
+/// A helper which counts the Escape presses, and force-kills the program on the last one.
+///
+/// # Parameters
+/// `pressed` : True if Escape was pressed, False if it was released.
+///
+/// # Returns
+/// True if the press triggered the force-kill (so it's consumed), False otherwise.
+unsafe fn count_escape(pressed: bool) -> bool {
+    if !pressed {
+        ESC_HELD = false;
+        return false;
+    }
+
+    if ESC_HELD {
+        return false;
+    }
+    ESC_HELD = true;
+
+    // The first press (or the first one after the window is over) starts a new window. If the 
+    // timeout can't be armed, the presses are counted without a window.
+    if ESC_EXPIRED.take() {
+        ESC_COUNT = 0;
+    }
+    if ESC_COUNT == 0 {
+        ESC_TIMEOUT = Timeout::new(ESC_WINDOW_MS, TimeoutTarget::SetFlag(&ESC_EXPIRED)).ok();
+    }
+    ESC_COUNT += 1;
+
+    if ESC_COUNT == ESC_PRESSES {
+        ESC_COUNT = 0;
+        if let Some(timeout) = ESC_TIMEOUT.take() {
+            timeout.cancel();
+        }
+        trigger(ChordAction::ForceKill);
+        return true;
+    }
+
+    false
+}
+
+/// A helper which runs the action of a chord.
+///
+/// # Parameters
+/// `action` : The action which was triggered.
+unsafe fn trigger(action: ChordAction) {
+    #[cfg(feature = "unit-test")]
+    if let Some(test_action) = TEST_ACTION {
+        test_action(action);
+        return;
+    }
+
+    match action {
+        ChordAction::Reboot => {
+            oxid_warn!("Ctrl+Alt+Del pressed, rebooting in {}ms.", crate::power::SHUTDOWN_GRACE_MS);
+            if crate::power::request_shutdown(true).is_err() {
+                oxid_err!("Could not queue the reboot.");
+            }
+        },
+
+        // There is only a single screen so far, so only the active terminal is recorded.
+        ChordAction::SwitchVt(number) => {
+            ACTIVE_VT = number;
+            oxid_log!("Switched to the virtual terminal {}.", number);
+        },
+
+        ChordAction::ForceKill => match super::discipline::owner() {
+            Some(pid) => {
+                oxid_warn!("Escape pressed {} times, force-killing PID={}.", ESC_PRESSES, pid);
+                if let Some(owner) = crate::proc::scheduler::handle_of(pid) {
+                    crate::proc::scheduler::kill_pid(owner);
+                }
+            },
+            None => oxid_err!("No process to kill."),
+        },
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::ChordAction;
+    use crate::io::keyboard::{inject, Event, Key};
+    use crate::olibc::bounded::BoundedVec;
+
+    /// The actions which were triggered.
+    static mut ACTIONS: BoundedVec<ChordAction, 8> = BoundedVec::new();
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        unsafe { super::TEST_ACTION = Some(record); }
+
+        test_reboot_chord();
+        test_vt_chord();
+        test_triple_escape();
+
+        unsafe { super::TEST_ACTION = None; }
+    }
+
+    /// Records the triggered actions (instead of running them).
+    ///
+    /// # Parameters
+    /// `action` : The action which was triggered.
+    fn record(action: ChordAction) {
+        unsafe { let _ = ACTIONS.try_push(action); }
+    }
+
+    /// A helper which presses (or releases) a key.
+    ///
+    /// # Parameters
+    /// `key` : The key.
+    /// `pressed` : True to press it, False to release it.
+    fn key(key: Key, pressed: bool) {
+        inject(Event::new(key, pressed));
+    }
+
+    /// Hold Ctrl+Alt, press Del (with a few repeats) twice, and make sure it's requested once per
+    /// press.
+    fn test_reboot_chord() {
+        unsafe {
+            ACTIONS.clear();
+            key(Key::LCtrl, true);
+            key(Key::LAlt, true);
+
+            // The repeats of a held key don't fire again.
+            for _ in 0..3 {
+                key(Key::Delete, true);
+            }
+            assert_eq!(ACTIONS.as_slice(), &[ChordAction::Reboot]);
+
+            key(Key::Delete, false);
+            key(Key::Delete, true);
+            key(Key::Delete, false);
+            assert_eq!(ACTIONS.as_slice(), &[ChordAction::Reboot, ChordAction::Reboot]);
+
+            key(Key::LAlt, false);
+            key(Key::LCtrl, false);
+
+            // Without the modifiers, it's just a key.
+            key(Key::Delete, true);
+            key(Key::Delete, false);
+            assert_eq!(ACTIONS.len(), 2);
+        }
+    }
+
+    /// Switch the virtual terminal, and make sure the function keys outside the range (or without
+    /// the modifiers) don't.
+    fn test_vt_chord() {
+        unsafe {
+            ACTIONS.clear();
+            key(Key::LCtrl, true);
+            key(Key::LAlt, true);
+            key(Key::F(2), true);
+            key(Key::F(2), true);
+            key(Key::F(2), false);
+            key(Key::F(9), true);
+            key(Key::F(9), false);
+            key(Key::LAlt, false);
+            key(Key::LCtrl, false);
+
+            key(Key::F(3), true);
+            key(Key::F(3), false);
+
+            assert_eq!(ACTIONS.as_slice(), &[ChordAction::SwitchVt(2)]);
+        }
+    }
+
+    /// Press Escape three times (with a repeat in between, which should not count), and make sure
+    /// it force-kills once.
+    fn test_triple_escape() {
+        unsafe {
+            ACTIONS.clear();
+            super::ESC_COUNT = 0;
+            
+            // Grab the keyboard in the raw mode (the single presses are delivered to the program).
+            crate::proc::set_input_mode(crate::proc::InputMode::Raw);
+            crate::io::keyboard::grab(true);
+
+            key(Key::Esc, true);
+            key(Key::Esc, true);
+            key(Key::Esc, false);
+            key(Key::Esc, true);
+            key(Key::Esc, false);
+            assert!(ACTIONS.is_empty());
+
+            key(Key::Esc, true);
+            key(Key::Esc, false);
+            assert_eq!(ACTIONS.as_slice(), &[ChordAction::ForceKill]);
+            
+            // The first two were delivered, and the last one was consumed.
+            let mut buf: [u8; 4] = [0; 4];
+            assert_eq!(crate::io::keyboard::read(&mut buf), 2);
+            assert_eq!(&buf[..2], b"\x1b\x1b");
+            
+            crate::io::keyboard::grab(false);
+            crate::proc::set_input_mode(crate::proc::InputMode::Cooked);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! A sub-module which implements the line discipline for the programs which read the keyboard. A 
+//! program grabs the keyboard, and then reads it's input based on it's input mode. In the cooked
+//! mode (the default), the characters are echoed and can be edited with backspace, and the line is
+//! delivered when enter is pressed. In the raw mode, every key is delivered right away (enter as
+//! '\n', backspace as '\x08', escape as '\x1b', and the arrows and the page keys as the VT100 
+//! sequences such as "\x1b[A") without being echoed. In the cooked mode, 
+//! escape goes to the terminal (which kills the program). Pressing it three times always kills the
+//! program (see chords).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::olibc::bounded::BoundedVec;
+use crate::proc::process::InputMode;
+use super::Key;
+
+/// The maximum number of bytes which are queued for read (the rest are dropped).
+const INPUT_QUEUE_SIZE: usize = 256;
+
+/// The maximum number of characters in a cooked line.
+const LINE_SIZE: usize = 128;
+
+/// The bytes which are ready to be read by the program (filled from the interrupt).
+static mut INPUT_QUEUE: BoundedVec<u8, INPUT_QUEUE_SIZE> = BoundedVec::new();
+
+/// The line which is being edited in the cooked mode.
+static mut LINE: BoundedVec<char, LINE_SIZE> = BoundedVec::new();
+
+/// The PID of the process which grabbed the keyboard (None if the terminal has it).
+static mut OWNER: Option<usize> = None;
+
+/// A function which grabs (or releases) the keyboard for the running process. While it's grabbed, 
+/// the typed keys are delivered to it (based on it's input mode) instead of the terminal.
+///
+/// # Parameters
+/// `grabbed` : True to grab the keyboard, False to give it back to the terminal.
+pub fn grab(grabbed: bool) {
+    let owner = if grabbed { crate::proc::scheduler::current_pid() } else { None };
+    
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        OWNER = owner;
+        INPUT_QUEUE.clear();
+        LINE.clear();
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A simple getter for the process which grabbed the keyboard.
+///
+/// # Returns
+/// The PID of the owner, or None if the terminal has the keyboard.
+pub fn owner() -> Option<usize> {
+    unsafe { OWNER }
+}
+
+/// A function which reads the input of the process which grabbed the keyboard. It does not block,
+/// and in the cooked mode it only returns complete lines (including the '\n').
+///
+/// # Parameters
+/// `buf` : The buffer which the bytes are copied to.
+///
+/// # Returns
+/// The number of bytes which were read (0 if nothing is ready).
+pub fn read(buf: &mut [u8]) -> usize {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        
+        // Copy the oldest bytes, and move the rest to the front.
+        let count = buf.len().min(INPUT_QUEUE.len());
+        buf[..count].copy_from_slice(&INPUT_QUEUE.as_slice()[..count]);
+        INPUT_QUEUE.as_mut_slice().rotate_left(count);
+        for _ in 0..count {
+            INPUT_QUEUE.pop();
+        }
+        
+        crate::arch::interrupts::restore(were_enabled);
+        count
+    }
+}
+
+/// A function which delivers a key to the process which grabbed the keyboard. It's called from the
+/// keyboard interrupt, so it never allocates.
+///
+/// # Parameters
+/// `key` : The key which was pressed.
+///
+/// # Returns
+/// True if it was consumed, False if it should be sent to the terminal.
+pub fn route(key: &Key) -> bool {
+    unsafe {
+        let owner = match OWNER {
+            Some(owner) => owner,
+            None => return false,
+        };
+        
+        // If the owner is gone, give the keyboard back to the terminal.
+        let mode = match crate::proc::scheduler::input_mode(owner) {
+            Some(mode) => mode,
+            None => {
+                OWNER = None;
+                return false;
+            },
+        };
+        
+        match mode {
+            InputMode::Raw => match key {
+                Key::Ch(character) => { queue_char(*character); },
+                Key::Enter => { queue_char('\n'); },
+                Key::Backspace => { queue_char('\x08'); },
+                Key::Esc => { queue_char('\x1b'); },
+                Key::Up => { queue_seq("\x1b[A"); },
+                Key::Down => { queue_seq("\x1b[B"); },
+                Key::PageUp => { queue_seq("\x1b[5~"); },
+                Key::PageDown => { queue_seq("\x1b[6~"); },
+                _ => (),
+            },
+            
+            InputMode::Cooked => match key {
+                Key::Ch(character) => {
+                    if LINE.try_push(*character).is_ok() {
+                        oxid_print!("{}", character);
+                    }
+                },
+                Key::Backspace => {
+                    if LINE.pop().is_some() {
+                        crate::console::CONSOLE.as_mut()
+                            .expect("Console not initialized").clear_last_cell();
+                    }
+                },
+                Key::Enter => {
+                    oxid_println!("");
+                    deliver_line();
+                },
+                
+                // Escape goes to the terminal (so the program can be killed).
+                Key::Esc => return false,
+                _ => (),
+            },
+        }
+        
+        true
+    }
+}
+
+/// A helper which queues the bytes of a character (it's dropped if it doesn't fit).
+///
+/// # Parameters
+/// `character` : The character which we're queueing.
+///
+/// # Returns
+/// True if it was queued, False otherwise.
+unsafe fn queue_char(character: char) -> bool {
+    let mut encoded: [u8; 4] = [0; 4];
+    let encoded = character.encode_utf8(&mut encoded).as_bytes();
+    if INPUT_QUEUE.len() + encoded.len() > INPUT_QUEUE_SIZE {
+        return false;
+    }
+    
+    for byte in encoded {
+        INPUT_QUEUE.push(*byte);
+    }
+    
+    true
+}
+
+/// A helper which queues an escape sequence as a whole (it's dropped if it doesn't fit, so a reader
+/// never sees a partial sequence).
+///
+/// # Parameters
+/// `seq` : The sequence which we're queueing (ASCII).
+///
+/// # Returns
+/// True if it was queued, False otherwise.
+unsafe fn queue_seq(seq: &str) -> bool {
+    if INPUT_QUEUE.len() + seq.len() > INPUT_QUEUE_SIZE {
+        return false;
+    }
+    
+    for byte in seq.bytes() {
+        INPUT_QUEUE.push(byte);
+    }
+    
+    true
+}
+
+/// A helper which moves the cooked line (and a '\n') to the input queue. If the queue can't fit 
+/// the whole line, it's dropped (so a reader never sees a partial line).
+unsafe fn deliver_line() {
+    let line_len: usize = LINE.as_slice().iter().map(|character| character.len_utf8()).sum();
+    if INPUT_QUEUE.len() + line_len + 1 <= INPUT_QUEUE_SIZE {
+        for character in LINE.as_slice() {
+            queue_char(*character);
+        }
+        queue_char('\n');
+    }
+    
+    LINE.clear();
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use crate::proc::process::InputMode;
+    use crate::io::keyboard::{self, Event, Key};
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_cooked();
+        test_raw();
+    }
+    
+    /// A helper which types a line with the keyboard grabbed, and returns what was rendered.
+    ///
+    /// # Parameters
+    /// `line` : The characters which are typed.
+    ///
+    /// # Returns
+    /// The captured output of the terminal.
+    fn type_grabbed(line: &str) -> String {
+        let mut output = String::new();
+        crate::io::term::start_capture();
+        crate::io::term::type_line(line);
+        crate::io::term::capture_output(&mut output);
+        
+        output
+    }
+    
+    /// A helper which reads everything that is ready.
+    ///
+    /// # Returns
+    /// The bytes which were read.
+    fn read_all() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut buf: [u8; 8] = [0; 8];
+        loop {
+            let count = super::read(&mut buf);
+            if count == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..count]);
+        }
+        
+        bytes
+    }
+    
+    /// Make sure the cooked mode echoes, handles backspace, and only delivers complete lines.
+    fn test_cooked() {
+        crate::proc::set_input_mode(InputMode::Cooked);
+        super::grab(true);
+        
+        let output = type_grabbed("ab\x08c");
+        assert_eq!(output, "ac");
+        assert!(read_all().is_empty());
+        
+        assert_eq!(type_grabbed("\nxyz\n"), "\nxyz\n");
+        assert_eq!(read_all(), b"ac\nxyz\n");
+        
+        super::grab(false);
+    }
+    
+    /// Make sure the raw mode delivers every key right away, without echoing them.
+    fn test_raw() {
+        crate::proc::set_input_mode(InputMode::Raw);
+        super::grab(true);
+        
+        assert_eq!(type_grabbed("q"), "");
+        assert_eq!(read_all(), b"q");
+        
+        assert_eq!(type_grabbed("ab\x08\n"), "");
+        assert_eq!(read_all(), b"ab\x08\n");
+        
+        // The navigation keys are delivered as their sequences.
+        for key in [Key::Up, Key::PageDown].iter() {
+            keyboard::inject(Event::new(*key, true));
+            keyboard::inject(Event::new(*key, false));
+        }
+        assert_eq!(read_all(), b"\x1b[A\x1b[6~");
+        
+        super::grab(false);
+        crate::proc::set_input_mode(InputMode::Cooked);
+        
+        // Once it's released, the keys go back to the terminal.
+        assert!(type_grabbed("\n").ends_with("Oxid > "));
+        assert!(read_all().is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+//! A sub-module which defines the keyboard layouts. A layout translates the characters of the keys
+//! based on the modifiers: the symbols only depend on Shift, the letters depend on Shift XOR 
+//! CapsLock, and everything else ignores both.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+/// A structure which represents a keyboard layout (the shifted version of each symbol key).
+pub struct Layout {
+    pub name: &'static str,                     // The name of the layout.
+    pub shifted: &'static [(char, char)],       // The symbols, and their shifted versions.
+}
+
+/// The US QWERTY layout (the one used by the PS2 set 1 table).
+pub const US_QWERTY: Layout = Layout {
+    name: "us",
+    shifted: &[('1', '!'), ('2', '@'), ('3', '#'), ('4', '$'), ('5', '%'), ('6', '^'), 
+        ('7', '&'), ('8', '*'), ('9', '('), ('0', ')'), ('-', '_'), ('=', '+'), ('`', '~'), 
+        ('[', '{'), (']', '}'), ('\\', '|'), (';', ':'), ('\'', '"'), (',', '<'), ('.', '>'), 
+        ('/', '?')],
+};
+
+impl Layout {
+    /// A method which translates the character of a key based on the modifiers.
+    ///
+    /// # Parameters
+    /// `character` : The character of the key (unshifted, and lowercase).
+    /// `shift` : True if a shift key is held.
+    /// `caps` : True if caps lock is on.
+    ///
+    /// # Returns
+    /// The translated character.
+    pub fn translate(&self, character: char, shift: bool, caps: bool) -> char {
+        // Letters are inverted by caps lock.
+        if character.is_ascii_alphabetic() {
+            return if shift != caps { character.to_ascii_uppercase() } else { character };
+        }
+        
+        // Symbols only depend on shift (the rest are not modified).
+        if shift {
+            if let Some((_, shifted)) = self.shifted.iter().find(|(key, _)| *key == character) {
+                return *shifted;
+            }
+        }
+        
+        character
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::US_QWERTY;
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module.
+    pub fn run() {
+        test_modifier_combinations();
+        test_through_keyboard();
+    }
+    
+    /// Translate a letter, a digit, a punctuation key, and a non symbol key with every combination 
+    /// of the modifiers (shift, caps).
+    fn test_modifier_combinations() {
+        let cases: [(char, [char; 4]); 4] = [
+            // Key, then (none, shift, caps, shift + caps).
+            ('a', ['a', 'A', 'A', 'a']),
+            ('1', ['1', '!', '1', '!']),
+            (';', [';', ':', ';', ':']),
+            (' ', [' ', ' ', ' ', ' ']),
+        ];
+        
+        for (key, expected) in cases.iter() {
+            assert_eq!(US_QWERTY.translate(*key, false, false), expected[0]);
+            assert_eq!(US_QWERTY.translate(*key, true, false), expected[1]);
+            assert_eq!(US_QWERTY.translate(*key, false, true), expected[2]);
+            assert_eq!(US_QWERTY.translate(*key, true, true), expected[3]);
+        }
+    }
+    
+    /// Make sure the keyboard translates the characters with the layout (and its modifier state).
+    fn test_through_keyboard() {
+        use crate::io::keyboard::{self, SHIFT_PRESSED, IS_CAPS};
+        
+        unsafe {
+            let saved = (SHIFT_PRESSED, IS_CAPS);
+            
+            SHIFT_PRESSED = true;
+            IS_CAPS = true;
+            assert_eq!(keyboard::process_character('q'), 'q');
+            assert_eq!(keyboard::process_character('/'), '?');
+            
+            SHIFT_PRESSED = false;
+            assert_eq!(keyboard::process_character('q'), 'Q');
+            assert_eq!(keyboard::process_character('/'), '/');
+            
+            SHIFT_PRESSED = saved.0;
+            IS_CAPS = saved.1;
+        }
+    }
+}
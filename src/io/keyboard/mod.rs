@@ -7,14 +7,24 @@
 #![allow(dead_code)]
 
 pub mod ps2;
+pub mod discipline;
+pub mod layout;
+pub mod chords;
+
+pub use discipline::{grab, read};
+
+/// The layout which is used to translate the characters.
+static mut LAYOUT: &layout::Layout = &layout::US_QWERTY;
 
 static mut SHIFT_PRESSED: bool = false;
+static mut CTRL_PRESSED: bool = false;
+static mut ALT_PRESSED: bool = false;
 static mut IS_CAPS: bool = false;
 static mut IS_NUM_LOCK: bool = false;
 
 /// An enum which represents a key. It can be of any of the following types. It is used for 
 /// translation of the key codes and proper handling of them.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Key {
     Ch(char),       // Represent a character.
     F(u8),          // Function keys (F1-F12).
@@ -30,6 +40,11 @@ pub enum Key {
     ScrlLock,       // Scroll lock.
     Enter,          // Enter key.
     Backspace,      // Backspace key.
+    Delete,         // Delete key (the keypad's '.' while num lock is on).
+    Up,             // Up arrow.
+    Down,           // Down arrow.
+    PageUp,         // Page up.
+    PageDown,       // Page down.
     Null,           // No key.
 }
 
@@ -39,24 +54,67 @@ pub struct Event {
     pressed: bool,          // True if key was pressed, False if released.
 }
 
+impl Event {
+    /// A constructor which creates an event for a given key.
+    ///
+    /// # Parameters
+    /// `key` : The key which was pressed or released.
+    /// `pressed` : True if key was pressed, False if released.
+    pub fn new(key: Key, pressed: bool) -> Self {
+        Event { key, pressed }
+    }
+}
+
 /// A function which is called by the keyboard drivers with a given event. It will try to handle
-/// the event gracefully and handles the upper/lower case modifiers.
+/// the event gracefully and handles the upper/lower case modifiers. The terminal allocates while 
+/// it handles the keys (and runs the commands), so it should be called from the bottom half of the
+/// drivers (never in the interrupt handler).
 ///
 /// # Parameters
 /// `event` : The keyboard event which occured (key and pressed information).
 pub fn handle_event(event: &Event) {
+    debug_assert!(!crate::arch::interrupts::handlers::in_irq(), 
+        "A keyboard event was handled in an interrupt handler.");
+    
+    // Track the modifiers which are used by the system chords.
+    match event.key {
+        Key::LCtrl | Key::RCtrl => unsafe { CTRL_PRESSED = event.pressed },
+        Key::LAlt | Key::RAlt => unsafe { ALT_PRESSED = event.pressed },
+        _ => (),
+    }
+    
+    // The system chords are never delivered to the programs.
+    if unsafe { chords::filter(event, CTRL_PRESSED, ALT_PRESSED) } {
+        return;
+    }
+    
     // Check if the key was pressed.
     if event.pressed {
         // For now, just print the event if it's a character.
         match event.key {
+            // Ctrl+C interrupts the program which has the keyboard (or drops the line).
+            Key::Ch('c') | Key::Ch('C') if unsafe { CTRL_PRESSED } => crate::io::term::interrupt(),
+            
             // If it's a character, process and send it.
             Key::Ch(character) => send_key(Key::Ch(process_character(character))),
             
             // If it's shift is pressed simply set the variable.
             Key::LShift | Key::RShift => unsafe { SHIFT_PRESSED = true },
             
-            // Toggle the caps.
-            Key::CapsLock => unsafe { IS_CAPS = !IS_CAPS },
+            // Toggle the caps, and update the LEDs later (it polls the controller).
+            Key::CapsLock => unsafe {
+                IS_CAPS = !IS_CAPS;
+                let _ = crate::proc::workqueue::queue_work(update_leds, 0);
+            },
+            
+            // Toggle the num lock, and update the LEDs as well.
+            Key::NumLock => unsafe {
+                IS_NUM_LOCK = !IS_NUM_LOCK;
+                let _ = crate::proc::workqueue::queue_work(update_leds, 0);
+            },
+            
+            // The keypad's delete is a '.' while num lock is on.
+            Key::Delete if unsafe { IS_NUM_LOCK } => send_key(Key::Ch('.')),
             
             // Otherwise, just send the key as it.
             _ => send_key(event.key),
@@ -73,48 +131,68 @@ pub fn handle_event(event: &Event) {
     }
 }
 
-
-/// A function which hanled a given character and processes it if necessary (for example, if it is 
-/// supposed to be caps, or the modifier keys are pressed).
+/// A function which feeds an event through the same path as the keyboard interrupt handler (with 
+/// the interrupts disabled). It allows the tests to simulate key presses.
 ///
 /// # Parameters
-/// `character` : The character from the key (ASCII).
-#[inline]
-fn process_character(character: char) -> char {
+/// `event` : The keyboard event which we're simulating.
+#[cfg(feature = "unit-test")]
+pub fn inject(event: Event) {
     unsafe {
-        // To modify based on the modifiers.
-        let mut transformed_char = character;
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        handle_event(&event);
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which sets the keyboard LEDs based on the current lock states. It is run from the
+/// work queue, since setting the LEDs has to wait for the keyboard controller.
+///
+/// # Parameters
+/// `_arg` : The argument passed by the work queue (not used).
+fn update_leds(_arg: usize) {
+    use crate::arch::io::ps2_keyboard::{self, LED_CAPS_LOCK, LED_NUM_LOCK};
     
-        // Check if shift is currently pressed, and print it accordingly.
-        if SHIFT_PRESSED {
-            transformed_char = match character {
-                // If any of the modified characters, translate them.
-                '1' => '!', '2' => '@', '3' => '#', '4' => '$', '5' => '%', '6' => '^', 
-                '7' => '&', '8' => '*', '9' => '(', '0' => ')', '-' => '_', '=' => '+',
-                '`' => '~', '[' => '{', ']' => '}', '\\' => '|', ';' => ':', '\'' => '"',
-                ',' => '<', '.' => '>', '/' => '?',
-                
-                // Otherwise, just translate it to uppercase.
-                _ => if IS_CAPS {
-                        character
-                    } else {
-                        character.to_ascii_uppercase()
-                    }
-            };
-        } else if IS_CAPS {
-            transformed_char = character.to_ascii_uppercase()
-        }
+    unsafe {
+        let mut leds: u8 = 0;
+        if IS_CAPS { leds |= LED_CAPS_LOCK; }
+        if IS_NUM_LOCK { leds |= LED_NUM_LOCK; }
         
-        // Return the transformed character.
-        transformed_char
+        let _ = ps2_keyboard::set_leds(leds);
     }
 }
 
+/// A function which sets the layout which is used to translate the characters.
+///
+/// # Parameters
+/// `new_layout` : The layout which should be used.
+pub fn set_layout(new_layout: &'static layout::Layout) {
+    unsafe { LAYOUT = new_layout; }
+}
+
+/// A function which handles a given character and processes it based on the layout and the 
+/// modifiers (shift and caps lock).
+///
+/// # Parameters
+/// `character` : The character from the key (ASCII).
+///
+/// # Returns
+/// The translated character.
+#[inline]
+fn process_character(character: char) -> char {
+    unsafe { LAYOUT.translate(character, SHIFT_PRESSED, IS_CAPS) }
+}
+
 
 /// A function which sends a given key press to the appropriate place. This will be replaced in the
 /// future to send it to some sort of a file (or somewhere else that does that).
 #[inline]
 fn send_key(to_send: Key) {
-    // Just send it to the terminal for now.
+    // If a program grabbed the keyboard, deliver it based on the program's input mode.
+    if discipline::route(&to_send) {
+        return;
+    }
+    
+    // Otherwise, just send it to the terminal.
     crate::io::term::key_press(&to_send);
 }
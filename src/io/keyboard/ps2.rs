@@ -19,7 +19,8 @@ pub mod set_1 {
 
     /// A translation table for the PS2 set 1 (US QWERTY) scan codes. This table is ordered and can
     /// be directly indexed into from idx 0 to 88 which includes all the key presses. For now, it 
-    /// does not support extended or multimedia keys. More details about these codes can be
+    /// does not support the multimedia keys, and the extended keys are the same as their base keys
+    /// (except for the ones in EXTENDED_KEYS). More details about these codes can be
     /// found at: https://wiki.osdev.org/PS2_Keyboard
     const SCAN_CODES: [Key; 0x59] = [Null, Esc, Ch('1'), Ch('2'), Ch('3'),
         Ch('4'), Ch('5'), Ch('6'), Ch('7'), Ch('8'), Ch('9'), Ch('0'), Ch('-'),
@@ -31,7 +32,17 @@ pub mod set_1 {
         Ch('/'), RShift, Ch('*'), LAlt, Ch(' '), CapsLock, F(1), F(2), 
         F(3), F(4), F(5), F(6), F(7), F(8), F(9), F(10), NumLock, ScrlLock, 
         Ch('7'), Ch('8'), Ch('9'), Ch('-'), Ch('4'), Ch('5'), Ch('6'), Ch('+'), 
-        Ch('1'), Ch('2'), Ch('3'), Ch('0'), Ch('.'), Null, Null, Null, F(11), F(12)];
+        Ch('1'), Ch('2'), Ch('3'), Ch('0'), Delete, Null, Null, Null, F(11), F(12)];
+    
+    /// The prefix which is sent before the codes of the extended keys.
+    const EXTENDED_PREFIX: u8 = 0xE0;
+    
+    /// The extended keys which are not the same as their base keys (the keypad's arrows).
+    const EXTENDED_KEYS: [(u8, Key); 4] = [(0x48, Up), (0x50, Down), (0x49, PageUp), 
+        (0x51, PageDown)];
+    
+    /// True if the previous code was the extended prefix.
+    static mut EXTENDED: bool = false;
         
     /// A function which can translate a given key_code to a key structure. It is typically used by
     /// the PS2 driver to get a Key and call an event in the Keyboard code.
@@ -42,6 +53,18 @@ pub mod set_1 {
     /// # Returns
     /// A keyboard event to be handled by the event handler.
     pub fn translate(key_code: u8) -> Event {
+        // Remember the prefix, and check if the code is one of the extended keys.
+        let extended = unsafe { core::mem::replace(&mut EXTENDED, key_code == EXTENDED_PREFIX) };
+        if extended {
+            let found = EXTENDED_KEYS.iter().find(|(code, _)| *code == key_code & 0x7F);
+            if let Some((_, key)) = found {
+                return Event {
+                    key: *key,
+                    pressed: key_code & 0x80 == 0,
+                };
+            }
+        }
+        
         // The following conditions are based on the table defined for the set 1. More information
         // can be found at https://wiki.osdev.org/PS2_Keyboard.
         if key_code < 0x59 {
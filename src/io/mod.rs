@@ -7,3 +7,23 @@
 pub mod textmode;
 pub mod keyboard;
 pub mod term;
+pub mod rc;
+pub mod block;
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::term::test::run();
+        super::keyboard::discipline::test::run();
+        super::keyboard::layout::test::run();
+        super::keyboard::chords::test::run();
+        super::rc::test::run();
+        super::block::test::run();
+    }
+}
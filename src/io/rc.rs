@@ -0,0 +1,173 @@
+//! A sub-module which runs the boot commands before the prompt is handed to the user. They are read
+//! from the `init=<cmd;cmd;...>` kernel command line option (which takes the rest of the command 
+//! line), and the /etc/rc file in the initrd (one command per line, `#` starts a comment). Each 
+//! command is printed and then executed, and the first failure stops the rest of them (unless the
+//! command starts with `-`). Commands which end with `&` are spawned in the background.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+use crate::multiboot2::boot_cmd::BootCmd;
+use crate::proc::exec::{exec, ExecFlags, ExecResult};
+
+/// The path of the boot script in the initrd.
+pub const RC_PATH: &str = "/etc/rc";
+
+/// The kernel command line option which holds the boot commands.
+pub const INIT_OPTION: &str = "init";
+
+/// The prefix of the commands whose failure is ignored.
+const IGNORE_PREFIX: char = '-';
+
+/// The suffix of the commands which are spawned in the background.
+const BACKGROUND_SUFFIX: char = '&';
+
+/// The prefix which is printed before each command.
+const RC_PROMPT: &str = "rc > ";
+
+/// A function which runs the boot commands (from the command line first, and then the boot 
+/// script), and prints the terminal prompt once they are done.
+///
+/// # Parameters
+/// `boot_cmd` : The kernel command line (if it was passed).
+pub fn run(boot_cmd: Option<&BootCmd>) {
+    let mut commands: Vec<&str> = Vec::new();
+    
+    if let Some(list) = boot_cmd.and_then(|cmd| cmd.get_rest(INIT_OPTION)) {
+        commands.extend(list.split(';'));
+    }
+    
+    if let Some(script) = crate::fs::initrd::read(RC_PATH) {
+        match core::str::from_utf8(script) {
+            Ok(script) => commands.extend(parse_script(script)),
+            Err(_) => oxid_warn!("Ignoring {} (it's not valid UTF-8).", RC_PATH),
+        }
+    }
+    
+    run_commands(&commands);
+    
+    // Hand the prompt to the user.
+    crate::io::term::print_prompt();
+}
+
+/// A function which finds the commands in a boot script. The comments and the empty lines are 
+/// removed.
+///
+/// # Parameters
+/// `script` : The contents of the script.
+///
+/// # Returns
+/// An iterator over the commands (trimmed).
+pub fn parse_script<'a>(script: &'a str) -> impl Iterator<Item = &'a str> {
+    script.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+}
+
+/// A function which runs a list of commands in order, and stops at the first one which fails 
+/// (unless it's marked as ignorable).
+///
+/// # Parameters
+/// `commands` : The commands which we're running.
+///
+/// # Returns
+/// The number of commands which were run (including the failed one).
+pub fn run_commands(commands: &[&str]) -> usize {
+    let mut num_run = 0;
+    
+    for command in commands.iter().map(|command| command.trim()).filter(|cmd| !cmd.is_empty()) {
+        num_run += 1;
+        
+        // Check for the prefix and the suffix.
+        let (ignorable, cmdline) = match command.strip_prefix(IGNORE_PREFIX) {
+            Some(rest) => (true, rest),
+            None => (false, command),
+        };
+        let (flags, cmdline) = match cmdline.strip_suffix(BACKGROUND_SUFFIX) {
+            Some(rest) => (ExecFlags::BACKGROUND, rest),
+            None => (ExecFlags::NONE, cmdline),
+        };
+        
+        // Print it (as if it was typed), and run it.
+        oxid_print!("{}{}", RC_PROMPT, command);
+        let succeeded = match exec(cmdline, flags) {
+            Ok(ExecResult::Spawned(_)) | Ok(ExecResult::Exited(0)) => true,
+            Ok(ExecResult::Exited(code)) => {
+                oxid_println!("");
+                oxid_err!("The command exited with code {}.", code);
+                false
+            },
+            Err(error) => {
+                let program = cmdline.split_whitespace().next().unwrap_or("");
+                crate::io::term::report_exec_error(program, error);
+                false
+            },
+        };
+        oxid_println!("");
+        
+        if !succeeded && !ignorable {
+            oxid_err!("Stopping the boot commands (\"{}\" failed).", command);
+            oxid_println!("");
+            break;
+        }
+    }
+    
+    num_run
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use crate::multiboot2::boot_cmd::BootCmd;
+    
+    /// The boot script which is placed in the test initrd.
+    const TEST_RC: &[u8] = b"# The test boot script.\n\necho boot-ok   # a comment\n-bogus_ignored\n\
+        bogus_failing\necho never-run\n";
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_parse_script();
+        test_boot_script();
+    }
+    
+    /// Make sure the comments and the empty lines are removed.
+    fn test_parse_script() {
+        let script = core::str::from_utf8(TEST_RC).unwrap();
+        let commands: Vec<&str> = super::parse_script(script).collect();
+        assert_eq!(commands, ["echo boot-ok", "-bogus_ignored", "bogus_failing", "echo never-run"]);
+        
+        let cmd = BootCmd::from_str("quiet init=echo a; loopforever &");
+        assert_eq!(cmd.get_rest("init"), Some("echo a; loopforever &"));
+        assert_eq!(cmd.get_rest("quiet"), None);
+    }
+    
+    /// Run the boot commands from the command line and the test initrd, and make sure the output 
+    /// shows the echo, the failure, and the prompt (and nothing after the failure is run).
+    fn test_boot_script() {
+        assert_eq!(crate::fs::initrd::add(super::RC_PATH, TEST_RC), Ok(()));
+        let cmd = BootCmd::from_str("init=echo cmdline-ok");
+        
+        let mut output = String::new();
+        crate::io::term::start_capture();
+        super::run(Some(&cmd));
+        crate::io::term::capture_output(&mut output);
+        assert_eq!(crate::fs::initrd::remove(super::RC_PATH), Ok(()));
+        
+        assert!(output.contains("rc > echo cmdline-ok\ncmdline-ok "));
+        assert!(output.contains("rc > echo boot-ok\nboot-ok "));
+        assert!(output.contains("Could not find the bogus_ignored command."));
+        assert!(output.contains("Could not find the bogus_failing command."));
+        assert!(output.contains("Stopping the boot commands (\"bogus_failing\" failed)."));
+        assert!(!output.contains("never-run"));
+        assert!(output.ends_with("Oxid > "));
+    }
+}
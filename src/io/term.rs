@@ -10,22 +10,47 @@ use alloc::vec::Vec;                        // For managing strings.
 use alloc::string::String;
 use crate::io::keyboard::Key;               // For finding what key was pressed.
 use crate::io::textmode::color::Color;      // For setting color (mainly for prompt).
-use crate::proc::process::Args;
+use crate::proc::exec::{exec_with_limits, ExecError, ExecFlags, ExecResult};
+use crate::proc::process::ResourceLimits;
+use crate::proc::cancel::INTERRUPTED_STATUS;
+use crate::olibc::bounded::BoundedVec;      // For the input buffer (no allocations in the ISR).
 
-/// The buffer used for the terminal (will be cleared when user presses enter).
-static mut TERM_BUFFER: Vec<char> = Vec::new();
+/// The maximum number of characters in a single command line.
+const TERM_BUFFER_SIZE: usize = 1024;
+
+/// The buffer used for the terminal (will be cleared when user presses enter). It is filled from 
+/// the keyboard interrupt, so it should never allocate.
+static mut TERM_BUFFER: BoundedVec<char, TERM_BUFFER_SIZE> = BoundedVec::new();
 
 /// The color of the prompt which will be printed on every line.
 pub const PROMPT_COLOR: Color = Color::Cyan;
 
+/// The color of the prompt after a command failed (it exited with a non-zero code).
+pub const PROMPT_FAILED_COLOR: Color = Color::Red;
+
+/// The exit status of a command which could not be found (like the other shells).
+pub const NOT_FOUND_STATUS: i32 = 127;
+
+/// The exit status of a command which could not be executed (or a built-in which failed).
+pub const FAILED_STATUS: i32 = 1;
+
 /// Hold the last parsed command and it's arguments.
 pub static mut LAST_CMD_ARGS: Vec<&str> = Vec::new();
 
+/// The current directory of the terminal (the background programs start in it).
+static mut CWD: String = String::new();
+
+/// The resource limits of the background programs (they can be changed with ulimit).
+static mut LIMITS: ResourceLimits = ResourceLimits::DEFAULT;
+
+/// The exit status of the last foreground command (it's expanded from $? in the command lines).
+static mut LAST_STATUS: i32 = 0;
+
 /// A function which initializes the terminal buffer. It should be called after the kernel heap
-/// is already set up and working.
+/// is already set up and working. The prompt is printed once the boot commands are done (see rc).
 pub fn init() {
-    // Print a terminal prompt.
-    print_prompt();
+    // Start at the root directory.
+    unsafe { CWD = String::from(crate::fs::path::ROOT); }
 }
 
 /// A function which recieves a keypress from the keyboard driver, and acts accordingly.
@@ -38,11 +63,10 @@ pub fn key_press(pressed: &Key) {
         match pressed {
             // If it's just a character, add it to the buffer, and update the terminal.
             Key::Ch(character) => {
-                // Add the character to the buffer.
-                TERM_BUFFER.push(*character);
-                
-                // Then just print the line for now.
-                oxid_print!("{}", character);
+                // Add the character to the buffer, and print it if there was space for it.
+                if TERM_BUFFER.try_push(*character).is_ok() {
+                    oxid_print!("{}", character);
+                }
             },
             
             // If it's enter, process the buffer, clear it and go to the next line.
@@ -63,22 +87,44 @@ pub fn key_press(pressed: &Key) {
                 }
             },
             
-            // If it's escape, terminate the currently running program.
-            Key::Esc => {
-                crate::proc::scheduler::kill();
-            }
+            // If it's escape, terminate the program which grabbed the keyboard (the keys are 
+            // handled in the kworker thread, so the current process is never the program).
+            Key::Esc => match crate::io::keyboard::discipline::owner() {
+                Some(pid) => match crate::proc::scheduler::handle_of(pid) {
+                    Some(owner) => { crate::proc::scheduler::kill_pid(owner); },
+                    None => oxid_err!("No process to kill."),
+                },
+                None => oxid_err!("No process to kill."),
+            },
             
             _ => {},
         }
     }
 }
 
-/// A function which processes the current buffer, and performs the appropriate tasks. For now some 
-/// commands are hard coded, but in the future, it will call exec.
+/// A function which handles Ctrl+C. If a program grabbed the keyboard, it's operations are
+/// cancelled (see proc::cancel), otherwise the current line is dropped. The foreground programs are
+/// cancelled by the keyboard's top half instead (they run in the same thread as the terminal).
+pub fn interrupt() {
+    let owner = crate::io::keyboard::discipline::owner();
+    match owner.and_then(crate::proc::scheduler::handle_of) {
+        Some(owner) => { crate::proc::scheduler::cancel(owner); },
+        None => unsafe {
+            TERM_BUFFER.clear();
+            oxid_println!("^C");
+            print_prompt();
+        },
+    }
+}
+
+/// A function which processes the current buffer, and performs the appropriate tasks. The built-in
+/// commands are run here, and everything else is passed to exec. The exit status of each command 
+/// is recorded (the background commands succeed once they're spawned), and $? is replaced with the
+/// status of the previous one.
 fn process_buffer() {
     unsafe {
         // Turn the commands into a string.
-        let cmd_args_str: String = (&TERM_BUFFER).into_iter().collect();
+        let cmd_args_str: String = TERM_BUFFER.as_slice().iter().collect();
         
         // Get the list of & seperated items.
         let whole_cmds: Vec<&str> = cmd_args_str.split("&").collect();
@@ -90,46 +136,348 @@ fn process_buffer() {
             if cmd_arg.len() < 1 {
                 continue;
             }
+            
+            // Replace the status of the previous command.
+            let expanded = expand_status(cmd_arg);
+            let cmd_arg = expanded.as_str();
+            
+            // Background commands which start with log also copy their output to the kernel log.
+            let mut flags = if run_in_bg { ExecFlags::BACKGROUND } else { ExecFlags::NONE };
+            let cmd_arg = match cmd_arg.trim().strip_prefix("log ") {
+                Some(rest) if run_in_bg => {
+                    flags = flags | ExecFlags::LOG_OUTPUT;
+                    rest
+                },
+                _ => cmd_arg,
+            };
         
             // Split it by space.
-            let cmds: Vec<&str> = cmd_arg.trim().split(" ").collect();
+            let cmds: Vec<&str> = cmd_arg.split_whitespace().collect();
+            if cmds.is_empty() {
+                continue;
+            }
+            
+            // Check for the built-in commands first.
+            if let Some(status) = run_builtin(&cmds) {
+                LAST_STATUS = status;
+                continue;
+            }
         
-            // Check if the program exists, if it does, check if we're supposed to run in background.
-            match crate::demo::get_main(cmds[0]) {
-                Some(program_main) => {
-                    // Allocate some memory for the arguments.
-                    let args_ptr = crate::mem::dyn_alloc::kmalloc(core::mem::size_of::<Args>()
-                        , false, true, false) as *mut Args;
-                
-                    // Set the arguments based on the passed data.
-                    (*args_ptr).set_args(&cmd_arg.trim());
-                    
-                    // Check if we're supposed to run in background.
-                    if run_in_bg {
-                        // Spawn a new process.
-                        crate::proc::scheduler::spawn(program_main, args_ptr, cmds[0]);
-                    } else {
-                        // Just run the program.
-                        program_main(args_ptr);
-                        
-                        // Then deallocate the allocated memory.
-                        crate::mem::dyn_alloc::kfree(args_ptr as *mut u8);
-                    }
+            // Execute it, and let the user know if it failed.
+            LAST_STATUS = match exec_with_limits(cmd_arg, flags, Some(LIMITS)) {
+                // The background programs start in the current directory.
+                Ok(ExecResult::Spawned(pid)) => {
+                    let _ = crate::proc::scheduler::set_cwd(pid, CWD.clone());
+                    0
+                },
+                Ok(ExecResult::Exited(0)) => 0,
+                Ok(ExecResult::Exited(INTERRUPTED_STATUS)) => {
+                    oxid_println!("");
+                    oxid_err!("{}: interrupted", cmds[0]);
+                    INTERRUPTED_STATUS
                 },
-                
-                None => {
+                Ok(ExecResult::Exited(code)) => {
                     oxid_println!("");
-                    oxid_err!("Could not find the {} command.", cmds[0]);
+                    oxid_err!("{} exited with code {}.", cmds[0], code);
+                    code
+                },
+                Err(error) => {
+                    report_exec_error(cmds[0], error);
+                    if error == ExecError::NotFound { NOT_FOUND_STATUS } else { FAILED_STATUS }
+                },
+            };
+        }
+        
+    }
+}
+
+/// A function which lets the user know why a command could not be executed.
+///
+/// # Parameters
+/// `program` : The name of the program.
+/// `error` : The reason it was not executed.
+pub fn report_exec_error(program: &str, error: ExecError) {
+    match error {
+        ExecError::Empty => (),
+        ExecError::NotFound => {
+            oxid_println!("");
+            oxid_err!("Could not find the {} command.", program);
+        },
+        ExecError::TooLong => {
+            oxid_println!("");
+            oxid_err!("The command is too long (at most {} characters).", 
+                crate::proc::process::ARGS_MAX);
+        },
+        ExecError::TooManyArgs => {
+            oxid_println!("");
+            oxid_err!("Too many arguments (at most {}).", crate::proc::exec::MAX_ARGS);
+        },
+        ExecError::TooManyChildren => {
+            oxid_println!("");
+            oxid_err!("Could not spawn the {} command (too many processes).", program);
+        },
+        ExecError::BadArgs => {
+            oxid_println!("");
+            oxid_err!("Could not spawn the {} command (invalid arguments).", program);
+        },
+    }
+}
+
+/// A function which runs the commands which are built into the terminal (since they change it's 
+/// state): `cd <path>` changes the current directory, and `pwd` prints it. There are no file 
+/// systems yet, so the directories are not checked for existence. `ulimit` shows the limits of the
+/// background programs, and `ulimit heap <bytes>` or `ulimit children <count>` changes them.
+///
+/// # Parameters
+/// `cmds` : The command and it's arguments.
+///
+/// # Returns
+/// Some(status) if it was a built-in command (and it was run), None otherwise.
+fn run_builtin(cmds: &[&str]) -> Option<i32> {
+    unsafe {
+        match cmds[0] {
+            "cd" => {
+                // Without a path, go back to the root.
+                let path = cmds.get(1).copied().unwrap_or(crate::fs::path::ROOT);
+                match crate::fs::path::join(&CWD, path) {
+                    Ok(cwd) => CWD = cwd,
+                    Err(()) => {
+                        oxid_println!("");
+                        oxid_err!("Invalid path \"{}\".", path);
+                        return Some(FAILED_STATUS);
+                    },
                 }
-            }
+            },
+            
+            "ulimit" => {
+                oxid_println!("");
+                let value = cmds.get(2).and_then(|value| value.parse::<usize>().ok());
+                match (cmds.get(1).copied(), value) {
+                    (None, _) => oxid_print!("heap={} bytes, children={}", 
+                        LIMITS.max_heap_bytes, LIMITS.max_children),
+                    (Some("heap"), Some(bytes)) => LIMITS.max_heap_bytes = bytes,
+                    (Some("children"), Some(count)) => LIMITS.max_children = count,
+                    _ => {
+                        oxid_err!("Usage: ulimit [heap <bytes> | children <count>]");
+                        return Some(FAILED_STATUS);
+                    },
+                }
+            },
+            
+            "pwd" => {
+                oxid_println!("");
+                oxid_print!("{}", CWD);
+            },
+            
+            _ => return None,
+        }
+        
+        Some(0)
+    }
+}
+
+/// A function which replaces $? in a command with the exit status of the last foreground command.
+///
+/// # Parameters
+/// `cmd` : The command and it's arguments.
+///
+/// # Returns
+/// The command with the status in place of $?.
+fn expand_status(cmd: &str) -> String {
+    cmd.replace("$?", &alloc::format!("{}", last_status()))
+}
+
+/// A simple getter for the exit status of the last foreground command (or built-in command).
+///
+/// # Returns
+/// The exit status (non-zero if it failed).
+pub fn last_status() -> i32 {
+    unsafe { LAST_STATUS }
+}
+
+/// A function which finds the color of the prompt, it's PROMPT_FAILED_COLOR if the last command
+/// failed (so it can be noticed), and PROMPT_COLOR otherwise.
+///
+/// # Returns
+/// The color which the prompt is printed in.
+pub fn prompt_color() -> Color {
+    if last_status() != 0 { PROMPT_FAILED_COLOR } else { PROMPT_COLOR }
+}
+
+/// A simple getter for the current directory of the terminal.
+///
+/// # Returns
+/// A copy of the current directory.
+pub fn cwd() -> String {
+    unsafe { CWD.clone() }
+}
+
+/// A simple getter for the number of characters which were typed in the current line.
+///
+/// # Returns
+/// The number of characters in the buffer.
+pub fn input_len() -> usize {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let len = TERM_BUFFER.len();
+        crate::arch::interrupts::restore(were_enabled);
+        len
+    }
+}
+
+/// A function which starts capturing what the terminal renders (see capture_output).
+#[cfg(feature = "unit-test")]
+pub fn start_capture() {
+    unsafe { crate::console::CONSOLE.as_mut().expect("Console not initialized").start_capture(); }
+}
+
+/// A function which stops capturing, and adds what the terminal rendered since start_capture was
+/// called to a given buffer (backspaces remove the characters, as they do on the screen).
+///
+/// # Parameters
+/// `buf` : The buffer which the captured output is added to.
+#[cfg(feature = "unit-test")]
+pub fn capture_output(buf: &mut String) {
+    unsafe {
+        if let Some(capture) = crate::console::CONSOLE.as_mut()
+            .expect("Console not initialized").stop_capture() {
+            buf.push_str(capture.as_str());
         }
+    }
+}
+
+/// A function which types a given line through the keyboard event handler, as if the user typed 
+/// it. Newlines are sent as enter, and '\x08' as backspace.
+///
+/// # Parameters
+/// `line` : The characters which are typed.
+#[cfg(feature = "unit-test")]
+pub fn type_line(line: &str) {
+    use crate::io::keyboard::{self, Event};
+    
+    for character in line.chars() {
+        let key = match character {
+            '\n' => Key::Enter,
+            '\x08' => Key::Backspace,
+            _ => Key::Ch(character),
+        };
         
+        keyboard::inject(Event::new(key, true));
+        keyboard::inject(Event::new(key, false));
     }
 }
 
 /// A simple function which prints the terminal prompt. Preferably, it does it in a different color.
 #[inline]
-fn print_prompt() {
+pub fn print_prompt() {
     // Call the appropriate macro, don't 
-    oxid_print_colored_nl!(crate::io::term::PROMPT_COLOR, crate::console::BG_COLOR, false, "Oxid > ");
+    oxid_print_colored_nl!(prompt_color(), crate::console::BG_COLOR, false, "Oxid > ");
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_run_command();
+        test_backspace();
+        test_unknown_command();
+        test_help();
+        test_cd_pwd();
+        test_exit_status();
+    }
+    
+    /// A helper which types a line in the terminal and returns what was rendered.
+    ///
+    /// # Parameters
+    /// `line` : The characters which are typed.
+    ///
+    /// # Returns
+    /// The captured output of the terminal.
+    fn run_line(line: &str) -> String {
+        let mut output = String::new();
+        super::start_capture();
+        super::type_line(line);
+        super::capture_output(&mut output);
+        
+        output
+    }
+    
+    /// Run a command, and make sure it's echoed, executed, and followed by a new prompt.
+    fn test_run_command() {
+        let output = run_line("echo hi there\n");
+        assert!(output.starts_with("echo hi there\n"));
+        assert!(output.contains("\nhi there "));
+        assert!(output.ends_with("Oxid > "));
+        assert!(unsafe { super::TERM_BUFFER.is_empty() });
+    }
+    
+    /// Make sure the backspace removes characters from both the screen and the buffer.
+    fn test_backspace() {
+        let output = run_line("ecx\x08ho fixed\x08\x08\x08\x08\x08ok\n");
+        assert!(output.starts_with("echo ok\n"));
+        assert!(output.contains("\nok "));
+        
+        // Backspace on an empty line does nothing.
+        let output = run_line("\x08\x08\n");
+        assert_eq!(output, "\nOxid > ");
+    }
+    
+    /// Make sure unknown commands are reported.
+    fn test_unknown_command() {
+        let output = run_line("bogus\n");
+        assert!(output.contains("Could not find the bogus command."));
+    }
+    
+    /// Run the help command, and make sure it lists the basic programs.
+    fn test_help() {
+        let output = run_line("help\n");
+        assert!(output.lines().any(|line| line.trim() == "echo"));
+        assert!(output.lines().any(|line| line.trim() == "clear"));
+    }
+    
+    /// Change the directory with relative and absolute paths, and make sure pwd prints it.
+    fn test_cd_pwd() {
+        run_line("cd /usr//local/\n");
+        run_line("cd ./bin/../lib\n");
+        assert_eq!(super::cwd(), "/usr/local/lib");
+        assert!(run_line("pwd\n").contains("\n/usr/local/lib\n"));
+        
+        // Escaping the root is rejected (and the directory is not changed).
+        assert!(run_line("cd ../../../..\n").contains("Invalid path"));
+        assert_eq!(super::cwd(), "/usr/local/lib");
+        
+        // Without a path, it goes back to the root.
+        run_line("cd\n");
+        assert_eq!(super::cwd(), "/");
+    }
+    
+    /// Run a program which returns 3, and make sure it's status is expanded from $? (and the prompt
+    /// is colored while it's non-zero). The built-in commands and the missing ones set it too.
+    fn test_exit_status() {
+        use super::{last_status, prompt_color, PROMPT_COLOR, PROMPT_FAILED_COLOR};
+        
+        assert!(run_line("false 3\n").contains("false exited with code 3."));
+        assert_eq!(last_status(), 3);
+        assert!(prompt_color() == PROMPT_FAILED_COLOR);
+        
+        // The status of echo itself is 0.
+        assert!(run_line("echo $?\n").contains("\n3 "));
+        assert_eq!(last_status(), 0);
+        assert!(prompt_color() == PROMPT_COLOR);
+        assert!(run_line("echo $?\n").contains("\n0 "));
+        
+        run_line("bogus\n");
+        assert_eq!(last_status(), super::NOT_FOUND_STATUS);
+        run_line("cd ../..\n");
+        assert_eq!(last_status(), super::FAILED_STATUS);
+        run_line("pwd\n");
+        assert_eq!(last_status(), 0);
+    }
 }
@@ -0,0 +1,152 @@
+//! A text mode driver which keeps it's cells in memory instead of a hardware buffer. It can have
+//! any number of rows and columns, so it can hold the console while there is no text mode screen
+//! (for example, as the surface which is later drawn on a framebuffer). The cells are stored in
+//! the same format as the VGA buffer.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+use alloc::vec;
+use crate::io::textmode::{driver::Driver, color::Color};
+
+const DEFAULT_ROWS: usize = 25;         // Default number of rows in the buffer.
+const DEFAULT_COLS: usize = 80;         // Default number of column in the buffer.
+
+/// A structure which represents a text mode buffer in memory (it needs the kernel heap).
+pub struct BufferDriver {
+    rows: usize,                        // The number of rows in the buffer.
+    cols: usize,                        // The number of columns in the buffer.
+    cells: Vec<u16>,                    // The cells (the character, and the colors above it).
+}
+
+impl Driver for BufferDriver {
+    /// Primary constructor which creates an empty buffer with a given row and column.
+    ///
+    /// # Parameters
+    /// `rows` : The number of rows in the buffer.
+    /// `cols` : The number of columns in the buffer.
+    ///
+    /// # Returns
+    /// The newly created buffer.
+    fn new(rows: usize, cols: usize) -> Self {
+        BufferDriver {
+            rows,
+            cols,
+            cells: vec![0; rows * cols],
+        }
+    }
+
+    /// Default constructor which creates an empty 80x25 buffer.
+    ///
+    /// # Returns
+    /// The newly created buffer.
+    fn new_default() -> Self {
+        BufferDriver::new(DEFAULT_ROWS, DEFAULT_COLS)
+    }
+
+    /// A public getter for the number of rows in the buffer.
+    ///
+    /// # Returns
+    /// The number of rows in this object.
+    fn get_rows(&self) -> usize {
+        self.rows
+    }
+
+    /// A public getter for the number of cols in the buffer.
+    ///
+    /// # Returns
+    /// The number of cols in this object.
+    fn get_cols(&self) -> usize {
+        self.cols
+    }
+
+    /// A method which sets a character in a cell at a row and column.
+    ///
+    /// # Parameters
+    /// `character` : The ASCII byte which we want to set.
+    /// `fg` : The foreground color (text).
+    /// `bg` : The background color (behind the text).
+    /// `row` : The row which we want to set.
+    /// `col` : The column which we want to set.
+    unsafe fn set_cell(&mut self, character: u8, fg: Color, bg: Color, row: usize, col: usize) {
+        let idx = self.index(row, col);
+        self.cells[idx] = ((bg as u16) << 12) | ((fg as u16) << 8) | (character as u16);
+    }
+
+    /// A method which clears a given cell at a row and column (sets it to empty space).
+    ///
+    /// # Parameters
+    /// `row` : The row which we want to clear.
+    /// `col` : The column which we want to clear.
+    unsafe fn clear_cell(&mut self, row: usize, col: usize) {
+        self.set_cell(0, Color::Black, Color::Black, row, col);
+    }
+
+    /// A method which copies the full cell data from the src to the dst cell.
+    ///
+    /// # Parameters
+    /// `src_row` : The row for the cell which we want to copy from.
+    /// `src_col` : The column for the cell which we want to copy from.
+    /// `dst_row` : The row for the cell which we want to copy to.
+    /// `dst_col` : The column for the cell which we want to copy to.
+    unsafe fn copy_cell(&mut self, src_row: usize, src_col: usize, dst_row: usize, dst_col: usize) {
+        let (src, dst) = (self.index(src_row, src_col), self.index(dst_row, dst_col));
+        self.cells[dst] = self.cells[src];
+    }
+
+    /// A method which returns a character in a cell at a row and column.
+    ///
+    /// # Parameters
+    /// `row` : The row for the cell which we want to get.
+    /// `col` : The column for the cell which we want to get.
+    ///
+    /// # Returns
+    /// The 8_bit character value (ASCII) stored in the cell which was requested.
+    unsafe fn get_char(&mut self, row: usize, col: usize) -> u8 {
+        self.cells[self.index(row, col)] as u8
+    }
+
+    /// A method which returns the color of foreground at a given row and column.
+    ///
+    /// # Parameters
+    /// `row` : The row for the cell which we want to get.
+    /// `col` : The column for the cell which we want to get.
+    ///
+    /// # Returns
+    /// The color enum which is used for the foreground.
+    unsafe fn get_fg(&mut self, row: usize, col: usize) -> Color {
+        Color::from((self.cells[self.index(row, col)] >> 8) as u8)
+    }
+
+    /// A method which returns the color of background at a given row and column.
+    ///
+    /// # Parameters
+    /// `row` : The row for the cell which we want to get.
+    /// `col` : The column for the cell which we want to get.
+    ///
+    /// # Returns
+    /// The color enum which is used for the background.
+    unsafe fn get_bg(&mut self, row: usize, col: usize) -> Color {
+        Color::from((self.cells[self.index(row, col)] >> 12) as u8)
+    }
+}
+
+impl BufferDriver {
+    /// A helper which finds the index of a cell (it panics if it's outside of the buffer, instead
+    /// of writing past it like the hardware buffer would).
+    ///
+    /// # Parameters
+    /// `row` : The row of the cell.
+    /// `col` : The column of the cell.
+    ///
+    /// # Returns
+    /// The index of the cell in the cells vector.
+    #[inline]
+    fn index(&self, row: usize, col: usize) -> usize {
+        assert!(row < self.rows && col < self.cols, "The cell {},{} is out of bounds.", row, col);
+        row * self.cols + col
+    }
+}
@@ -8,7 +8,8 @@
 
 use crate::io::textmode::color::Color;
 
-/// A group of functions which are needed for a text mode driver to be implemented.
+/// A group of functions which are needed for a text mode driver to be implemented. The 
+/// constructors are only available for the sized types, so the trait stays object safe.
 pub trait Driver {
     /// Primary constructor which creates a vga buffer with a given row and column.
     /// it simply sets those values and returns it.
@@ -19,14 +20,14 @@ pub trait Driver {
     ///
     /// # Returns
     /// The newly created vga buffer.
-    fn new(rows: usize, cols: usize) -> Self;
+    fn new(rows: usize, cols: usize) -> Self where Self: Sized;
 
     /// Default constructor which creates a default vga buffer with the default number of rows.
     /// and columns for the architecture.
     ///
     /// # Returns
     /// The newly created vga buffer.
-    fn new_default() -> Self;
+    fn new_default() -> Self where Self: Sized;
 
     /// A public getter for the number of rows in the buffer.
     ///
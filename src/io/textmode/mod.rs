@@ -1,6 +1,7 @@
 //! A module which implements the basic VESA textmode driver (abstracted, and independent of 
 //! hardware). It also defines a basic driver which can be implemented based on the architecture
-//! to allow access to it. The code here should be re-usable within various VESA modes. 
+//! to allow access to it. The code here should be re-usable within various VESA modes. A driver 
+//! which keeps the cells in memory is also included (see buffer).
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Jan 2021
@@ -8,3 +9,4 @@
 pub mod driver;
 pub mod color;
 pub mod writer;
+pub mod buffer;
@@ -11,9 +11,16 @@ use crate::io::textmode::{driver::Driver, color::Color};        // To allow acce
 use crate::proc::mutex::Mutex;                            // To allow synchronization.
 use core::fmt;
 
+#[cfg(feature = "unit-test")]
+use crate::olibc::bounded::BoundedString;                 // To capture the output in tests.
+
 const DEFAULT_BG_COLOR: Color = Color::Black;                   // Default background color.
 const DEFAULT_FG_COLOR: Color = Color::Green;                   // Default foreground color (Text).
 
+/// The maximum number of bytes which can be captured (the rest is dropped).
+#[cfg(feature = "unit-test")]
+pub const CAPTURE_SIZE: usize = 4096;
+
 /// A struct which abstracts over different types of writers, and allows writing of a group of 
 /// characters to the textmode driver.
 pub struct Writer<T: Driver> {
@@ -23,6 +30,9 @@ pub struct Writer<T: Driver> {
     curr_fg: Color,             // The current foreground color.
     curr_bg: Color,             // The current background color.
     mutex: Mutex,               // To allow safe access.
+    
+    #[cfg(feature = "unit-test")]
+    capture: Option<BoundedString<CAPTURE_SIZE>>,   // The captured output (if capturing).
 }
 
 impl<T: Driver> Writer<T> {
@@ -43,6 +53,9 @@ impl<T: Driver> Writer<T> {
             curr_fg: DEFAULT_FG_COLOR,
             curr_bg: DEFAULT_BG_COLOR,
             mutex: Mutex::new(),
+            
+            #[cfg(feature = "unit-test")]
+            capture: None,
          };  
         
         new_writer.clear();                                             // Clear the terminal.
@@ -51,6 +64,7 @@ impl<T: Driver> Writer<T> {
     
     /// A function which writes a string to the buffer. It can also write it in configurable
     /// background and foreground colors. It can also print new lines and shift down when needed.
+    /// The colors are only used for this string (the current colors are not changed).
     ///
     /// # Parameters
     /// `string` : The actual string we want to print.
@@ -58,35 +72,50 @@ impl<T: Driver> Writer<T> {
     /// `bg` : The background color for the printed string.
     pub fn print_colored(&mut self, string: &str, fg: Color, bg: Color) {
         // Lock the mutex to allow safe modification access.
-        //self.mutex.lock();
+        self.mutex.lock();
     
-        // Go through every byte in the string.
-        for character in string.bytes() {
-            // If we have a newline character, go to the next line.
-            if character == b'\n' {
-                self.new_line();
-            // Otherwise, set the cell at the current cursor position.    
-            } else {
-                unsafe {
-                    // Set the cell at the current cursor row and column. 
-                    self.vga_driver.set_cell(character, fg, bg, self.cursor_row, self.cursor_col);
-                    
-                    // Increase the column.
-                    self.cursor_col += 1;
-                    
-                    // If the cursor is out of the screen, add a new line.
-                    if self.cursor_col >= self.vga_driver.get_cols() {
-                        self.new_line();
-                    }
-                }   
-            }    
+        self.write_colored(string, fg, bg);
+        
+        // Unlock the mutex since we're done with the modifications.
+        self.mutex.unlock();
+    }
+    
+    /// A function which writes formatted text to the buffer in the given colors. The mutex is held
+    /// for the whole write (including the newline), so the output of different processes is never
+    /// mixed up in content or color.
+    ///
+    /// # Parameters
+    /// `args` : The formatted arguments (created by format_args!).
+    /// `fg` : The foreground color for the printed text.
+    /// `bg` : The background color for the printed text.
+    /// `add_nl` : If true, a newline is added at the end.
+    ///
+    /// # Returns
+    /// The number of bytes which were written (including the newline).
+    pub fn print_fmt_colored(&mut self, args: fmt::Arguments, fg: Color, bg: Color, add_nl: bool) 
+        -> usize {
+        // Copy it to the kernel log if needed (before the mutex changes the interrupt state).
+        crate::debug::klog::tee(args, add_nl);
+        
+        // Lock the mutex to allow safe modification access.
+        self.mutex.lock();
+        
+        // Write it through an adapter which uses the passed colors.
+        let mut adapter = ColoredWriter { writer: self, fg, bg, written: 0 };
+        let _ = fmt::write(&mut adapter, args);
+        let mut written = adapter.written;
+        if add_nl {
+            self.write_colored("\n", fg, bg);
+            written += 1;
         }
         
         // Unlock the mutex since we're done with the modifications.
         self.mutex.unlock();
+        
+        written
     }
     
-    /// A wrapper for the print_colored function which prints in the default colors. It only accepts
+    /// A wrapper for the print_colored function which prints in the current colors. It only accepts
     /// the string which we want to print.
     ///
     /// # Parameters
@@ -97,7 +126,8 @@ impl<T: Driver> Writer<T> {
     }
     
     /// A function which sets the current color which will be used by the print method. All the
-    /// future writes will be with the given colors.
+    /// future writes with print will be with the given colors. It should only be used for 
+    /// deliberate persistent changes, since the formatted printing macros pass their own colors.
     ///
     /// # Parameters
     /// `fg` : The foreground color we want to set.
@@ -105,7 +135,7 @@ impl<T: Driver> Writer<T> {
     #[inline]
     pub fn set_colors(&mut self, fg: Color, bg: Color) {
         // Lock the mutex to allow safe modification access.
-        //self.mutex.lock();
+        self.mutex.lock();
     
         self.curr_fg = fg;
         self.curr_bg = bg;
@@ -114,6 +144,134 @@ impl<T: Driver> Writer<T> {
         self.mutex.unlock();
     }
     
+    /// A function which unlocks the writer even if it's currently locked. It should only be used
+    /// when the holder can never unlock it (for example, by the panic handler).
+    pub unsafe fn force_unlock(&mut self) {
+        self.mutex.unlock();
+    }
+    
+    /// A function which returns the character and the colors of a cell on the screen.
+    ///
+    /// # Parameters
+    /// `row` : The row of the cell.
+    /// `col` : The column of the cell.
+    ///
+    /// # Returns
+    /// A tuple with the character, the foreground color, and the background color.
+    pub fn get_cell(&mut self, row: usize, col: usize) -> (u8, Color, Color) {
+        unsafe {
+            (self.vga_driver.get_char(row, col), self.vga_driver.get_fg(row, col),
+                self.vga_driver.get_bg(row, col))
+        }
+    }
+    
+    /// A simple getter for the size of the screen.
+    ///
+    /// # Returns
+    /// A tuple which represents the number of rows and columns (ordered).
+    pub fn get_size(&self) -> (usize, usize) {
+        (self.vga_driver.get_rows(), self.vga_driver.get_cols())
+    }
+    
+    /// A function which starts capturing everything which is written (in addition to showing it). 
+    /// The capture is a fixed size buffer, since it's filled while the writer is locked.
+    #[cfg(feature = "unit-test")]
+    pub fn start_capture(&mut self) {
+        self.mutex.lock();
+        self.capture = Some(BoundedString::new());
+        self.mutex.unlock();
+    }
+    
+    /// A function which stops capturing and returns what was written since it was started.
+    ///
+    /// # Returns
+    /// Some(output) if it was capturing, None otherwise.
+    #[cfg(feature = "unit-test")]
+    pub fn stop_capture(&mut self) -> Option<BoundedString<CAPTURE_SIZE>> {
+        self.mutex.lock();
+        let capture = self.capture.take();
+        self.mutex.unlock();
+        
+        capture
+    }
+    
+    /// A function which writes a string at a given position in the current colors, without moving
+    /// the cursor. It's cut at the end of the row (it never wraps or scrolls). Each positioned 
+    /// write is captured as a separate line (without the trailing spaces).
+    ///
+    /// # Parameters
+    /// `row` : The row where the string is written.
+    /// `col` : The column where the string starts.
+    /// `string` : The string which we're writing (newlines are not supported).
+    pub fn print_at(&mut self, row: usize, col: usize, string: &str) {
+        self.mutex.lock();
+        
+        #[cfg(feature = "unit-test")]
+        if let Some(capture) = self.capture.as_mut() {
+            let _ = capture.push_str(string.trim_end());
+            let _ = capture.push_str("\n");
+        }
+        
+        // Write every byte which fits in the row.
+        if row < self.vga_driver.get_rows() {
+            let max_len = self.vga_driver.get_cols().saturating_sub(col);
+            for (idx, character) in string.bytes().take(max_len).enumerate() {
+                unsafe {
+                    self.vga_driver.set_cell(character, self.curr_fg, self.curr_bg, row, col + idx);
+                }
+            }
+        }
+        
+        self.mutex.unlock();
+    }
+    
+    /// A function which replaces the driver (for example, when the screen mode is changed). The
+    /// writer is locked, so the write which is in progress is finished first. The new surface is 
+    /// cleared, and the rows of the old one (up to the cursor) are written to it again in their 
+    /// colors, so they wrap and scroll within the new size.
+    ///
+    /// # Parameters
+    /// `new_driver` : The driver which is used from now on.
+    ///
+    /// # Returns
+    /// The driver which was replaced.
+    pub fn replace_driver(&mut self, new_driver: T) -> T {
+        self.mutex.lock();
+        
+        let mut old_driver = core::mem::replace(&mut self.vga_driver, new_driver);
+        let (last_row, last_col) = (self.cursor_row, self.cursor_col);
+        for row in 0..self.vga_driver.get_rows() {
+            self.clear_line(row);
+        }
+        self.cursor_row = 0;
+        self.cursor_col = 0;
+        
+        for row in 0..=last_row {
+            // The cursor's row is only written up to the cursor (the rest is trimmed).
+            let mut len = if row == last_row { last_col } else { old_driver.get_cols() };
+            while len > 0 && matches!(unsafe { old_driver.get_char(row, len - 1) }, 0 | b' ') {
+                len -= 1;
+            }
+            
+            for col in 0..len {
+                unsafe {
+                    let character = old_driver.get_char(row, col);
+                    let (fg, bg) = (old_driver.get_fg(row, col), old_driver.get_bg(row, col));
+                    self.put_char(character, fg, bg);
+                }
+            }
+            
+            // A full row already moved to the next line (when it wrapped).
+            if row != last_row && (len == 0 || self.cursor_col != 0) {
+                self.new_line();
+            }
+        }
+        
+        self.mutex.unlock();
+        
+        old_driver
+    }
+    
     /// A function which resets the colors to their default color mode.
     #[inline]
     pub fn reset_colors(&mut self) {
@@ -124,7 +282,7 @@ impl<T: Driver> Writer<T> {
     /// to write on. It should be called when initializing the screen.
     pub fn clear(&mut self) {
         // Lock the mutex to allow safe modification access.
-        //self.mutex.lock();
+        self.mutex.lock();
     
         // Go through every single row.
         let mut row : usize = 0;
@@ -144,7 +302,7 @@ impl<T: Driver> Writer<T> {
     /// A method which clears the last line in the console screen (current cursor row).
     pub fn clear_last_line(&mut self) {
         // Lock the mutex to allow safe modification access.
-        //self.mutex.lock();
+        self.mutex.lock();
     
         // Simply clear the current line.
         self.clear_line(self.cursor_row);
@@ -159,7 +317,7 @@ impl<T: Driver> Writer<T> {
     /// A method which clears the last cell in the console screen (current cursor row, col).
     pub fn clear_last_cell(&mut self) {
         // Lock the mutex to allow safe modification access.
-        //self.mutex.lock();
+        self.mutex.lock();
         
         // Get the row and column of previous cell.
         let (prev_row, prev_col) = self.prev_cell();
@@ -167,6 +325,12 @@ impl<T: Driver> Writer<T> {
         // Simply clear the previous cell.
         unsafe { self.vga_driver.clear_cell(prev_row, prev_col) };
         
+        // Remove it from the captured output as well.
+        #[cfg(feature = "unit-test")]
+        if let Some(capture) = self.capture.as_mut() {
+            capture.pop();
+        }
+        
         // Set the current row and column.
         self.cursor_row = prev_row;
         self.cursor_col = prev_col;
@@ -175,6 +339,55 @@ impl<T: Driver> Writer<T> {
         self.mutex.unlock();
     }
     
+    /// A method which writes a string in the given colors. It does not lock the mutex, so it should
+    /// only be called while holding it.
+    ///
+    /// # Parameters
+    /// `string` : The actual string we want to print.
+    /// `fg` : The foreground color for the printed string.
+    /// `bg` : The background color for the printed string.
+    fn write_colored(&mut self, string: &str, fg: Color, bg: Color) {
+        // Keep a copy of it if the output is being captured.
+        #[cfg(feature = "unit-test")]
+        if let Some(capture) = self.capture.as_mut() {
+            let _ = capture.push_str(string);
+        }
+        
+        // Go through every byte in the string.
+        for character in string.bytes() {
+            // If we have a newline character, go to the next line.
+            if character == b'\n' {
+                self.new_line();
+            // Otherwise, set the cell at the current cursor position.    
+            } else {
+                self.put_char(character, fg, bg);
+            }    
+        }
+    }
+    
+    /// A method which sets the cell at the cursor, and moves the cursor forward (to the next line
+    /// if it's out of the screen). It should only be called while holding the mutex.
+    ///
+    /// # Parameters
+    /// `character` : The ASCII byte which we're writing.
+    /// `fg` : The foreground color for the character.
+    /// `bg` : The background color for the character.
+    #[inline]
+    fn put_char(&mut self, character: u8, fg: Color, bg: Color) {
+        unsafe {
+            // Set the cell at the current cursor row and column. 
+            self.vga_driver.set_cell(character, fg, bg, self.cursor_row, self.cursor_col);
+        }
+        
+        // Increase the column.
+        self.cursor_col += 1;
+        
+        // If the cursor is out of the screen, add a new line.
+        if self.cursor_col >= self.vga_driver.get_cols() {
+            self.new_line();
+        }
+    }
+    
     /// A method which clears a full line (row) in the console. It needs a row number (less than 
     /// the total number of rows) to clear it.
     ///
@@ -267,3 +480,20 @@ impl<T: Driver>  fmt::Write for Writer<T> {
     }
 }
 
+
+/// An adapter which allows formatted writes in specific colors while the writer is locked. It is
+/// used by print_fmt_colored, so the colors of the writer are never changed.
+struct ColoredWriter<'a, T: Driver> {
+    writer: &'a mut Writer<T>,  // The writer which is locked.
+    fg: Color,                  // The foreground color for the text.
+    bg: Color,                  // The background color for the text.
+    written: usize,             // The number of bytes which were written.
+}
+
+impl<'a, T: Driver> fmt::Write for ColoredWriter<'a, T> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.writer.write_colored(string, self.fg, self.bg);
+        self.written += string.len();
+        Ok(())
+    }
+}
@@ -17,6 +17,11 @@ mod debug;
 mod mem;
 mod panic;
 mod demo;
+mod time;
+mod version;
+mod features;
+mod fs;
+mod power;
 
 extern crate alloc;
 
@@ -25,30 +30,75 @@ pub unsafe extern "sysv64" fn kernel_main(multiboot_info: usize) {
     // Initialize the console so we can write to it globally.
     console::init();
     oxid_log!("Initialized the console.");
+    oxid_log!("{} v{} ({}), features: {}", version::NAME, version::VERSION, version::GIT_HASH, 
+        features::list());
     
     // Parse the multiboot information.
     let mb_info = multiboot2::MultibootInfo::parse(multiboot_info).unwrap();
     oxid_log!("Parsed the multiboot2 information header.");
     
+    // Turn on the trace messages which were requested in the command line.
+    debug::trace::init(&mb_info);
+    
     // Initialize the interrupt handling code.
     arch::interrupts::init();
     
     // Initialize the memory code.
     mem::init(&mb_info);
+    
+    // Log the boot device and the reason for the last reset (needs the heap).
+    debug::bootdiag::init(&mb_info);
+    
+    // Report the panic of the previous boot (if it left a crash record).
+    debug::crashlog::check();
 
     // Initialize the rest of what needs to be initialized on the hardware side.
     arch::init();
     
+    // Choose and calibrate the timer used for short delays.
+    time::init();
+    
+    // Find the disks (the waits on them need the delay timer).
+    arch::io::ata::init();
+    
     // Initialize the scheduling code and run the scheduler.
     arch::proc::process::scheduling::init();
     proc::scheduler::init();
     
+    // Run the boot self-tests which need the timer ticks (only with the bootdiag option).
+    debug::bootdiag::run_self_tests();
+    
+    // Start the kernel thread which runs the deferred work.
+    proc::workqueue::init();
+    
+    // Start catching the processes which keep the interrupts disabled (reports on the workqueue).
+    proc::watchdog::init();
+    
+    // Register the statistics which are dumped for the host tooling (on the serial port).
+    debug::statsdump::init();
+    
+    // Start sampling the heap fragmentation (it runs on the work queue).
+    mem::dyn_alloc::start_frag_sampling();
+    
+    // Start the periodic checks of the page tables against the vmm's mappings.
+    #[cfg(feature = "tlb-check")]
+    debug::tlb_check::start();
+    
+    // Start checking the kernel's data structures in the background (with any debug feature).
+    #[cfg(any(feature = "heap-debug", feature = "tlb-check", feature = "latency-stats", 
+        feature = "strict-debug"))]
+    debug::sweeper::start();
+    
     // Initialize the interactive terminal.
     io::term::init();
     
     // Initialize the demonstration programs.
     demo::init();
     
+    // Add the boot modules to the initrd, and run the boot commands (then show the prompt).
+    fs::initrd::init(&mb_info);
+    io::rc::run(mb_info.boot_cmd_tag.as_ref());
+    
     // Run the unit tests if the unit-test feature is set.
     #[cfg(feature = "unit-test")]
     test::run();
@@ -69,5 +119,15 @@ pub mod test {
     pub fn run() {
         super::mem::test::run();
         super::arch::test::run();
+        super::debug::test::run();
+        super::time::test::run();
+        super::olibc::test::run();
+        super::fs::test::run();
+        super::proc::test::run();
+        super::console::test::run();
+        super::io::test::run();
+        super::demo::test::run();
+        super::power::test::run();
+        super::features::test::run();
     }
 }
@@ -0,0 +1,282 @@
+//! A sub-module which defines the types of the physical and virtual addresses. Both of them used to
+//! be a bare usize, so a virtual address could be passed to the frame allocator (or a physical one
+//! to unmap) without any errors. The conversions between them are explicit, and the virtual
+//! addresses are always canonical. For example, freeing the frame of a heap pointer does not
+//! compile anymore (it has to be translated with to_phys first):
+//!
+//! ```compile_fail
+//! use oxid_os::mem::{addr::VirtAddr, frame_alloc};
+//! let ptr = unsafe { oxid_os::mem::dyn_alloc::kmalloc(64, false, true, true) };
+//! frame_alloc::dealloc(VirtAddr::from_ptr(ptr));
+//! ```
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::fmt;
+use core::ops::Add;
+use crate::arch::mem::page_tables::PageTables;
+use crate::mem::align::{align_higher, align_lower};
+
+/// A structure which represents a physical address (such as the address of a frame).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct PhysAddr(usize);
+
+/// A structure which represents a canonical virtual address (such as the address of a page).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct VirtAddr(usize);
+
+impl PhysAddr {
+    /// The main constructor which wraps a physical address.
+    ///
+    /// # Parameters
+    /// `addr` : The physical address.
+    ///
+    /// # Returns
+    /// The created address.
+    pub const fn new(addr: usize) -> Self {
+        PhysAddr(addr)
+    }
+
+    /// A simple getter for the raw address.
+    ///
+    /// # Returns
+    /// The address as a usize.
+    pub const fn as_usize(&self) -> usize {
+        self.0
+    }
+
+    /// A method which finds the virtual address which the same address is identity mapped to.
+    ///
+    /// # Returns
+    /// Ok(virt_addr), or Err if the address can't be a canonical virtual address.
+    pub fn to_identity(&self) -> Result<VirtAddr, ()> {
+        VirtAddr::new(self.0)
+    }
+
+    /// A method which rounds the address down to an alignment.
+    ///
+    /// # Parameters
+    /// `alignment` : The alignment (a power of two).
+    ///
+    /// # Returns
+    /// The aligned address.
+    pub fn align_down(&self, alignment: usize) -> Self {
+        PhysAddr(align_lower(self.0, alignment))
+    }
+
+    /// A method which rounds the address up to an alignment.
+    ///
+    /// # Parameters
+    /// `alignment` : The alignment (a power of two).
+    ///
+    /// # Returns
+    /// The aligned address.
+    pub fn align_up(&self, alignment: usize) -> Self {
+        PhysAddr(align_higher(self.0, alignment))
+    }
+
+    /// A method which checks if the address is aligned.
+    ///
+    /// # Parameters
+    /// `alignment` : The alignment (a power of two).
+    ///
+    /// # Returns
+    /// True if it's aligned, False otherwise.
+    pub fn is_aligned(&self, alignment: usize) -> bool {
+        self.0 % alignment == 0
+    }
+}
+
+impl VirtAddr {
+    /// The main constructor which checks if the address is canonical.
+    ///
+    /// # Parameters
+    /// `addr` : The virtual address.
+    ///
+    /// # Returns
+    /// Ok(virt_addr), or Err if the address is not canonical.
+    pub fn new(addr: usize) -> Result<Self, ()> {
+        if PageTables::is_canonical(addr) {
+            Ok(VirtAddr(addr))
+        } else {
+            Err(())
+        }
+    }
+
+    /// A constructor which creates the address of a pointer (the pointers are always canonical).
+    ///
+    /// # Parameters
+    /// `ptr` : The pointer.
+    ///
+    /// # Returns
+    /// The address of the pointer.
+    pub fn from_ptr<T>(ptr: *const T) -> Self {
+        debug_assert!(PageTables::is_canonical(ptr as usize));
+        VirtAddr(ptr as usize)
+    }
+
+    /// A simple getter for the raw address.
+    ///
+    /// # Returns
+    /// The address as a usize.
+    pub const fn as_usize(&self) -> usize {
+        self.0
+    }
+
+    /// A method which converts the address to a pointer.
+    ///
+    /// # Returns
+    /// The pointer.
+    pub const fn as_ptr<T>(&self) -> *const T {
+        self.0 as *const T
+    }
+
+    /// A method which converts the address to a mutable pointer.
+    ///
+    /// # Returns
+    /// The pointer.
+    pub const fn as_mut_ptr<T>(&self) -> *mut T {
+        self.0 as *mut T
+    }
+
+    /// A method which translates the address using the currently loaded page table.
+    ///
+    /// # Returns
+    /// Ok(phys_addr), or Err if it's not mapped.
+    pub fn to_phys(&self) -> Result<PhysAddr, ()> {
+        crate::mem::vmm::virt_to_phys(*self)
+    }
+
+    /// A method which rounds the address down to an alignment.
+    ///
+    /// # Parameters
+    /// `alignment` : The alignment (a power of two).
+    ///
+    /// # Returns
+    /// The aligned address.
+    pub fn align_down(&self, alignment: usize) -> Self {
+        VirtAddr(align_lower(self.0, alignment))
+    }
+
+    /// A method which rounds the address up to an alignment.
+    ///
+    /// # Parameters
+    /// `alignment` : The alignment (a power of two).
+    ///
+    /// # Returns
+    /// Ok(aligned_addr), or Err if it's not canonical anymore.
+    pub fn align_up(&self, alignment: usize) -> Result<Self, ()> {
+        VirtAddr::new(align_higher(self.0, alignment))
+    }
+
+    /// A method which checks if the address is aligned.
+    ///
+    /// # Parameters
+    /// `alignment` : The alignment (a power of two).
+    ///
+    /// # Returns
+    /// True if it's aligned, False otherwise.
+    pub fn is_aligned(&self, alignment: usize) -> bool {
+        self.0 % alignment == 0
+    }
+}
+
+impl Add<usize> for PhysAddr {
+    type Output = PhysAddr;
+
+    fn add(self, offset: usize) -> PhysAddr {
+        PhysAddr(self.0 + offset)
+    }
+}
+
+impl Add<usize> for VirtAddr {
+    type Output = VirtAddr;
+
+    /// The offsets are only added within a mapping, so the result should still be canonical.
+    fn add(self, offset: usize) -> VirtAddr {
+        debug_assert!(PageTables::is_canonical(self.0 + offset));
+        VirtAddr(self.0 + offset)
+    }
+}
+
+impl fmt::LowerHex for PhysAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl fmt::LowerHex for VirtAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{PhysAddr, VirtAddr};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_canonical();
+        test_alignment();
+        test_conversions();
+    }
+
+    /// Make sure only the canonical virtual addresses can be created.
+    fn test_canonical() {
+        assert!(VirtAddr::new(0).is_ok());
+        assert!(VirtAddr::new(crate::mem::MAX_USER_ADDR).is_ok());
+        assert!(VirtAddr::new(crate::mem::MAX_USER_ADDR + 1).is_err());
+        assert!(VirtAddr::new(crate::mem::MIN_KERNEL_ADDR - 1).is_err());
+        assert!(VirtAddr::new(crate::mem::MIN_KERNEL_ADDR).is_ok());
+
+        // Aligning up past the end of the lower half is not canonical.
+        let last_page = VirtAddr::new(crate::mem::MAX_USER_ADDR & !0xFFF).unwrap();
+        assert!((last_page + 1).align_up(0x1000).is_err());
+    }
+
+    /// Check the alignment helpers of both types.
+    fn test_alignment() {
+        let phys = PhysAddr::new(0x12345);
+        assert_eq!(phys.align_down(0x1000), PhysAddr::new(0x12000));
+        assert_eq!(phys.align_up(0x1000), PhysAddr::new(0x13000));
+        assert!(!phys.is_aligned(0x1000) && phys.align_down(0x1000).is_aligned(0x1000));
+
+        let virt = VirtAddr::new(0xFFFF_8000_0000_0123).unwrap();
+        assert_eq!(virt.align_down(0x1000).as_usize(), 0xFFFF_8000_0000_0000);
+        assert_eq!(virt.align_up(0x1000), VirtAddr::new(0xFFFF_8000_0000_1000));
+        assert_eq!(virt + 0x10, VirtAddr::new(0xFFFF_8000_0000_0133).unwrap());
+    }
+
+    /// Translate the addresses of the identity mapped and the heap memory.
+    fn test_conversions() {
+        // The kernel is identity mapped.
+        static MARKER: usize = 0;
+        let marker = VirtAddr::from_ptr(&MARKER as *const usize);
+        assert_eq!(marker.to_phys(), Ok(PhysAddr::new(marker.as_usize())));
+        assert_eq!(PhysAddr::new(marker.as_usize()).to_identity(), Ok(marker));
+        assert_eq!(marker.as_ptr::<usize>(), &MARKER as *const usize);
+
+        // The heap memory is mapped to a frame which is in use.
+        unsafe {
+            let ptr = crate::mem::dyn_alloc::kmalloc_zeroed(64, false, true, true);
+            let frame = VirtAddr::from_ptr(ptr).to_phys().unwrap();
+            assert_eq!(frame.as_usize() % 0x1000, ptr as usize % 0x1000);
+            assert_eq!(crate::mem::frame_alloc::is_used(frame), Ok(true));
+            crate::mem::dyn_alloc::kfree(ptr);
+        }
+
+        // A physical address above the canonical range has no identity mapping.
+        assert!(PhysAddr::new(crate::mem::MAX_USER_ADDR + 1).to_identity().is_err());
+    }
+}
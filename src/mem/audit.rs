@@ -0,0 +1,258 @@
+//! A sub-module which cross-checks the three sources of truth for the kernel memory: the heap's
+//! used list, the frame allocator's bitmap, and the live page tables. It is used to find bugs such
+//! as heap regions which were unmapped early, frames which were freed while still mapped, pages in
+//! the heap arena which don't belong to any allocation, and frames which are marked used but are
+//! not referenced by any mapping (leaked). The lazy heap regions are only mapped once they're
+//! touched, so their unmapped pages are counted separately (they're not an issue). It also makes
+//! sure the early allocation region doesn't overlap the heap.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::vec::Vec;
+use crate::mem::{dyn_alloc, early_alloc, frame_alloc, map, mmio, vmm};
+use crate::mem::dyn_alloc::AllocTag;
+use crate::mem::region::Region;
+use crate::mem::addr::{PhysAddr, VirtAddr};
+
+/// The maximum number of findings which are printed for each category (to avoid flooding).
+const MAX_PRINTED: usize = 8;
+
+/// A structure which holds the results of an audit.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AuditReport {
+    pub used_regions: usize,               // Number of regions in the heap's used list.
+    pub mapped_pages: usize,               // Number of mapped pages in the lower half.
+    pub unmapped_pages: usize,             // Pages of used heap regions which are not mapped.
+    pub lazy_pages: usize,                 // Pages of lazy heap regions which were not touched.
+    pub free_frames: usize,                // Mapped pages whose frame is marked free in the bitmap.
+    pub stray_pages: usize,                // Mapped pages in the heap arena outside the used list.
+    pub orphaned_frames: usize,            // Frames marked used, but not referenced by a mapping.
+    pub early_overlaps: usize,             // Heap regions which overlap the early region.
+}
+
+impl AuditReport {
+    /// A method which checks if the audit found any inconsistencies.
+    ///
+    /// # Returns
+    /// True if no issues were found, False otherwise.
+    pub fn is_clean(&self) -> bool {
+        self.unmapped_pages == 0 && self.free_frames == 0 && self.stray_pages == 0
+            && self.orphaned_frames == 0 && self.early_overlaps == 0
+    }
+}
+
+/// A structure which holds a copy of a used region of the kernel heap.
+#[derive(Copy, Clone, Debug)]
+struct UsedRegion {
+    region: Region,                        // The usable part of the region (without guard pages).
+    lazy: bool,                            // True if it's mapped when it's touched.
+    tag: AllocTag,                         // The owner of the allocation.
+}
+
+/// A helper which copies the used regions of the kernel heap, sorted by their address. The copy
+/// is allocated before the heap is locked, so it's retried if the heap grew in the meantime.
+///
+/// # Returns
+/// The used regions.
+unsafe fn used_regions() -> Vec<UsedRegion> {
+    loop {
+        let mut count: usize = 0;
+        dyn_alloc::for_each_used(&mut |_: &Region, _: bool, _: AllocTag| count += 1);
+
+        // Leave some room for the allocation of the copy itself.
+        let mut regions: Vec<UsedRegion> = Vec::with_capacity(count + 4);
+        let mut complete = true;
+        dyn_alloc::for_each_used(&mut |region: &Region, lazy: bool, tag: AllocTag| {
+            if regions.len() < regions.capacity() {
+                regions.push(UsedRegion { region: *region, lazy, tag });
+            } else {
+                complete = false;
+            }
+        });
+
+        if complete {
+            regions.sort_unstable_by_key(|used| used.region.addr);
+            return regions;
+        }
+    }
+}
+
+/// A helper which finds the used region which includes a given address.
+///
+/// # Parameters
+/// `regions` : The used regions (sorted by their address).
+/// `addr` : The address which we're looking for.
+///
+/// # Returns
+/// Some(used_region) if it's owned by one, None otherwise.
+fn find_owner(regions: &[UsedRegion], addr: usize) -> Option<&UsedRegion> {
+    // The owner can only be the last region which starts at (or before) the address.
+    let idx = match regions.binary_search_by_key(&addr, |used| used.region.addr) {
+        Ok(idx) => idx,
+        Err(0) => return None,
+        Err(idx) => idx - 1,
+    };
+
+    Some(&regions[idx]).filter(|used| used.region.includes(addr))
+}
+
+/// A function which audits the kernel memory state and prints every inconsistency it finds (up to
+/// a limit per category) with the addresses involved (and the tag of the allocation which owns
+/// them), followed by a summary. It is slow, since it walks the page tables, so it should only be
+/// used for debugging.
+///
+/// # Returns
+/// The report with the number of issues found in each category.
+pub unsafe fn audit() -> AuditReport {
+    let mut report = AuditReport::default();
+
+    // Copy the used regions once, so the pages can be checked against them without the heap lock.
+    let regions = used_regions();
+    report.used_regions = regions.len();
+
+    // Make sure every page of every used heap region is mapped (unless it's lazy).
+    for used in regions.iter() {
+        let mut page_addr = used.region.addr;
+        while page_addr < used.region.end_addr() {
+            let mapped = VirtAddr::new(page_addr).and_then(vmm::virt_to_phys).is_ok();
+            if !mapped && used.lazy {
+                report.lazy_pages += 1;
+            } else if !mapped {
+                if report.unmapped_pages < MAX_PRINTED {
+                    oxid_warn!("Audit: Used heap page 0x{:x} (region at 0x{:x}, tag {}) is not \
+                        mapped.", page_addr, used.region.addr, used.tag.name());
+                }
+                report.unmapped_pages += 1;
+            }
+
+            page_addr += vmm::PAGE_SIZE;
+        }
+    }
+
+    // Count the frames which are referenced by the page tables (tables and pages separately).
+    let mut table_frames: usize = 0;
+    let mut page_frames: usize = 0;
+
+    // Walk the page tables, and check every mapped page against the bitmap and the heap.
+    vmm::walk(
+        &mut |table_addr: usize| {
+            if frame_alloc::is_used(PhysAddr::new(table_addr)) == Ok(true) {
+                table_frames += 1;
+            }
+        },
+        &mut |page_addr: usize, frame_addr: usize| {
+            report.mapped_pages += 1;
+
+            // Check the frame's state in the bitmap (the kernel identity maps all of the memory
+            // before the heap, so the free frames in it are not an issue).
+            let identity = page_addr == frame_addr && page_addr < vmm::id_map_end();
+            match frame_alloc::is_used(PhysAddr::new(frame_addr)) {
+                Ok(true) => page_frames += 1,
+                Ok(false) if identity => (),
+                Ok(false) => {
+                    if report.free_frames < MAX_PRINTED {
+                        let tag = find_owner(&regions, page_addr).map_or("none", |used| 
+                            used.tag.name());
+                        oxid_warn!("Audit: Page 0x{:x} (tag {}) is mapped to free frame 0x{:x}.",
+                            page_addr, tag, frame_addr);
+                    }
+                    report.free_frames += 1;
+                },
+                Err(()) => (),
+            }
+
+            // If the page is in the heap arena, it should belong to a used region (the pages of the
+            // chained metadata regions are reserved at it's end, outside of the arena).
+            if dyn_alloc::in_arena(page_addr) && find_owner(&regions, page_addr).is_none() {
+                if report.stray_pages < MAX_PRINTED {
+                    oxid_warn!("Audit: Heap page 0x{:x} (frame 0x{:x}) has no owner.",
+                        page_addr, frame_addr);
+                }
+                report.stray_pages += 1;
+            }
+        }
+    );
+
+    // The reserved MMIO frames which are not identity mapped are also accounted for.
+    let mut referenced_frames = table_frames + page_frames;
+    for region in mmio::reserved_regions().iter().flatten() {
+        let mut frame_addr = PhysAddr::new(region.addr);
+        while frame_addr.as_usize() < region.end_addr() {
+            let identity = frame_addr.to_identity().and_then(vmm::virt_to_phys);
+            if frame_alloc::is_used(frame_addr) == Ok(true) && identity.is_err() {
+                referenced_frames += 1;
+            }
+
+            frame_addr = frame_addr + frame_alloc::FRAME_SIZE;
+        }
+    }
+
+    // Whatever is used, but not referenced, is orphaned.
+    report.orphaned_frames = frame_alloc::used_count().saturating_sub(referenced_frames);
+    if report.orphaned_frames > 0 {
+        oxid_warn!("Audit: {} frames are marked used, but are not referenced by any mapping.",
+            report.orphaned_frames);
+    }
+
+    // The early region is identity mapped, so it should be out of the heap's metadata and arena.
+    if let Some(early) = early_alloc::region() {
+        let layout = map::layout();
+        for region in [layout.metadata_region(), layout.heap_region()].iter() {
+            if early.addr < region.end_addr() && region.addr < early.end_addr() {
+                oxid_warn!("Audit: The early region 0x{:x}-0x{:x} overlaps the heap at 0x{:x}.",
+                    early.addr, early.end_addr(), region.addr);
+                report.early_overlaps += 1;
+            }
+        }
+    }
+
+    // Print the summary.
+    oxid_log!("Audit: {} used regions, {} mapped pages, {} unmapped ({} lazy), {} free frames \
+        mapped, {} stray, {} orphaned, {} early overlaps.", report.used_regions, 
+        report.mapped_pages, report.unmapped_pages, report.lazy_pages, report.free_frames, 
+        report.stray_pages, report.orphaned_frames, report.early_overlaps);
+
+    report
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::mem::dyn_alloc::{kmalloc, kfree};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_audit_after_allocs();
+    }
+
+    /// Allocate and free some memory, and make sure the audit is clean in both cases.
+    fn test_audit_after_allocs() {
+        unsafe {
+            // Make a few allocations with different sizes.
+            let small = kmalloc(64, false, true, true);
+            let large = kmalloc(vmm_pages(5), false, true, true);
+
+            let report = super::audit();
+            assert!(report.is_clean());
+            assert!(report.used_regions >= 2);
+            assert!(report.lazy_pages >= 5);
+
+            // Free them, and make sure nothing was left behind.
+            kfree(small);
+            kfree(large);
+            assert!(super::audit().is_clean());
+        }
+    }
+
+    /// A helper which calculates the size of a given number of pages.
+    fn vmm_pages(count: usize) -> usize {
+        count * crate::mem::vmm::PAGE_SIZE
+    }
+}
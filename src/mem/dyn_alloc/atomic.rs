@@ -0,0 +1,121 @@
+//! A sub-module which implements a small cache of zeroed pages for the allocations which are made
+//! in the interrupt handlers (see kmalloc_atomic). The heap should not be used in them, since they
+//! can't wait for it's lock. The pages are reserved in the kernel image (so they're always mapped),
+//! and they're handed out without taking the heap's lock. The allocations fail right away when the
+//! cache is empty (they never wait for a page to be freed). A page is zeroed again when it's freed,
+//! so the cache only has zeroed pages.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::mem::vmm::PAGE_SIZE;
+use super::DeallocError;
+
+/// The number of pages in the cache (at most 32, since they're tracked in a single word).
+pub const NUM_PAGES: usize = 8;
+
+/// The memory of the cache.
+#[repr(C, align(4096))]
+struct Pages([[u8; PAGE_SIZE]; NUM_PAGES]);
+
+/// The pages which are handed out.
+static mut PAGES: Pages = Pages([[0; PAGE_SIZE]; NUM_PAGES]);
+
+/// A bit for each page (set if it's allocated).
+static mut USED: u32 = 0;
+
+/// The number of allocations which could not be served (too large, or the cache was empty).
+static mut FAILED: usize = 0;
+
+/// A function which allocates a zeroed page from the cache. It can be called with the interrupts
+/// enabled or disabled (they're restored to their previous state).
+///
+/// # Parameters
+/// `size` : The number of bytes which are needed (at most a page).
+///
+/// # Returns
+/// The address of the page, or null if the size is larger than a page or the cache is empty.
+pub unsafe fn alloc(size: usize) -> *mut u8 {
+    if size > PAGE_SIZE {
+        FAILED += 1;
+        return core::ptr::null_mut();
+    }
+
+    let were_enabled = crate::arch::interrupts::save_and_disable();
+    let page = (!USED).trailing_zeros() as usize;
+    let ptr = if page < NUM_PAGES {
+        USED |= 1 << page;
+        PAGES.0[page].as_mut_ptr()
+    } else {
+        FAILED += 1;
+        core::ptr::null_mut()
+    };
+    crate::arch::interrupts::restore(were_enabled);
+
+    ptr
+}
+
+/// A function which zeroes a page, and gives it back to the cache.
+///
+/// # Parameters
+/// `ptr` : The address of the page (returned by alloc).
+///
+/// # Returns
+/// Ok if it was freed, NotAllocated if the page is free (or it's not in the cache), or Unaligned
+/// if it points inside a page.
+pub unsafe fn free(ptr: *mut u8) -> Result<(), DeallocError> {
+    if !contains(ptr) {
+        return Err(DeallocError::NotAllocated);
+    }
+
+    let offset = ptr as usize - PAGES.0.as_ptr() as usize;
+    if offset % PAGE_SIZE != 0 {
+        return Err(DeallocError::Unaligned);
+    }
+
+    let mask = 1 << (offset / PAGE_SIZE);
+    if USED & mask == 0 {
+        return Err(DeallocError::NotAllocated);
+    }
+
+    // It's still owned by the caller, so it can be zeroed before it's marked as free.
+    crate::olibc::memset::memset(ptr, 0, PAGE_SIZE);
+
+    let were_enabled = crate::arch::interrupts::save_and_disable();
+    USED &= !mask;
+    crate::arch::interrupts::restore(were_enabled);
+
+    Ok(())
+}
+
+/// A function which checks if an address is in the cache.
+///
+/// # Parameters
+/// `ptr` : The address to look up.
+///
+/// # Returns
+/// True if it's in the cache, False otherwise.
+pub fn contains(ptr: *const u8) -> bool {
+    unsafe {
+        let start = PAGES.0.as_ptr() as usize;
+        (ptr as usize) >= start && (ptr as usize) < start + PAGE_SIZE * NUM_PAGES
+    }
+}
+
+/// A simple getter for the number of pages which can still be allocated.
+///
+/// # Returns
+/// The number of free pages.
+pub fn num_free() -> usize {
+    unsafe { NUM_PAGES - USED.count_ones() as usize }
+}
+
+/// A simple getter for the number of allocations which could not be served.
+///
+/// # Returns
+/// The number of failed allocations.
+pub fn failures() -> usize {
+    unsafe { FAILED }
+}
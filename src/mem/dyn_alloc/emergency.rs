@@ -0,0 +1,129 @@
+//! A sub-module which implements a small emergency pool for the allocations which are made in the
+//! interrupt handlers while the heap is locked (by the code which was interrupted). Spinning on the
+//! lock would deadlock in that case, so the allocations are served from a few fixed size blocks
+//! which are reserved in the kernel image instead. The blocks are found by their address when
+//! they're freed (or reallocated), so they can be given back from anywhere.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::alloc::Layout;
+use super::DeallocError;
+
+/// The size (and the largest alignment) of each block in the pool.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The number of blocks in the pool (at most 32, since they're tracked in a single word).
+pub const NUM_BLOCKS: usize = 16;
+
+/// The memory of the pool (page aligned, so every block is aligned to it's size).
+#[repr(C, align(4096))]
+struct Pool([[u8; BLOCK_SIZE]; NUM_BLOCKS]);
+
+/// The blocks which are handed out.
+static mut POOL: Pool = Pool([[0; BLOCK_SIZE]; NUM_BLOCKS]);
+
+/// A bit for each block (set if it's allocated).
+static mut USED: u32 = 0;
+
+/// The number of allocations which could not be served (too large, or the pool was full).
+static mut FAILED: usize = 0;
+
+/// The number of frees which could not be deferred (the memory was leaked).
+static mut LEAKED: usize = 0;
+
+/// A function which allocates a block from the pool. It can be called with the interrupts enabled
+/// or disabled (they're restored to their previous state).
+///
+/// # Parameters
+/// `layout` : The size and alignment that is requested for the returned ptr.
+///
+/// # Returns
+/// The address of the block, or null if it's too large for a block or the pool is full.
+pub unsafe fn alloc(layout: &Layout) -> *mut u8 {
+    if layout.size() > BLOCK_SIZE || layout.align() > BLOCK_SIZE {
+        FAILED += 1;
+        return core::ptr::null_mut();
+    }
+
+    let were_enabled = crate::arch::interrupts::save_and_disable();
+    let block = (!USED).trailing_zeros() as usize;
+    let ptr = if block < NUM_BLOCKS {
+        USED |= 1 << block;
+        POOL.0[block].as_mut_ptr()
+    } else {
+        FAILED += 1;
+        core::ptr::null_mut()
+    };
+    crate::arch::interrupts::restore(were_enabled);
+
+    ptr
+}
+
+/// A function which gives a block back to the pool.
+///
+/// # Parameters
+/// `ptr` : The address of the block (returned by alloc).
+///
+/// # Returns
+/// Ok if it was freed, NotAllocated if the block is free (or it's not in the pool), or Unaligned
+/// if it points inside a block.
+pub unsafe fn free(ptr: *mut u8) -> Result<(), DeallocError> {
+    if !contains(ptr) {
+        return Err(DeallocError::NotAllocated);
+    }
+
+    let offset = ptr as usize - POOL.0.as_ptr() as usize;
+    if offset % BLOCK_SIZE != 0 {
+        return Err(DeallocError::Unaligned);
+    }
+
+    let were_enabled = crate::arch::interrupts::save_and_disable();
+    let mask = 1 << (offset / BLOCK_SIZE);
+    let result = if USED & mask != 0 {
+        USED &= !mask;
+        Ok(())
+    } else {
+        Err(DeallocError::NotAllocated)
+    };
+    crate::arch::interrupts::restore(were_enabled);
+
+    result
+}
+
+/// A function which checks if an address is in the pool.
+///
+/// # Parameters
+/// `ptr` : The address to look up.
+///
+/// # Returns
+/// True if it's in the pool, False otherwise.
+pub fn contains(ptr: *const u8) -> bool {
+    unsafe {
+        let start = POOL.0.as_ptr() as usize;
+        (ptr as usize) >= start && (ptr as usize) < start + BLOCK_SIZE * NUM_BLOCKS
+    }
+}
+
+/// A function which records a free which could not be deferred (so the memory was leaked).
+pub fn note_leak() {
+    unsafe { LEAKED += 1; }
+}
+
+/// A simple getter for the number of blocks which are allocated.
+///
+/// # Returns
+/// The number of used blocks.
+pub fn num_used() -> usize {
+    unsafe { USED.count_ones() as usize }
+}
+
+/// A simple getter for the failures of the pool.
+///
+/// # Returns
+/// A tuple of (failed allocations, leaked frees).
+pub fn failures() -> (usize, usize) {
+    unsafe { (FAILED, LEAKED) }
+}
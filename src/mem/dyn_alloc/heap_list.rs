@@ -1,7 +1,7 @@
 //! A sub-module which represents the linked list structure which is used internally for this 
 //! allocator. It utilizes the heap_node_alloc to allocate and manage nodes. It additionally
-//! implements an iterator for safe(er) access to the elements.
-//! TODO: Optimizations, make the allocation more efficient, use a dual linked list maybe.
+//! implements an iterator for safe(er) access to the elements. The list is doubly linked, so a
+//! node can be removed without traversing the list again.
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Mar 2021
@@ -11,12 +11,46 @@
 use crate::mem::region::Region;                // To represent memory regions.
 use super::heap_node::HeapNode;                // To represent nodes.
 use super::heap_node_alloc::HeapNodeAlloc;     // To allocate nodes.
+use super::heap_node_alloc::GrowFn;            // To grow the node memory.
+use super::heap_node_alloc::NodeFreeError;     // To report the nodes which can't be freed.
+
+/// The most regions which are printed by dump (so the console is not flooded).
+pub const DUMP_MAX_NODES: usize = 16;
+
+/// An enum which represents the reason an operation on a list failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HeapListError {
+    NoNodes,                                   // A node could not be allocated (it's full).
+    NotLinked,                                 // The node is not linked in this list.
+    Node(NodeFreeError),                       // A node could not be freed.
+}
+
+impl From<NodeFreeError> for HeapListError {
+    /// A function which wraps the reason a node could not be freed.
+    ///
+    /// # Parameters
+    /// `err` : The reason from the node allocator.
+    fn from(err: NodeFreeError) -> Self {
+        HeapListError::Node(err)
+    }
+}
 
 /// A structure which represents a basic allocator for HeapNodes. This will be used in the
 /// implementation of the linked list to hold the allocators.
 pub struct HeapList {
     node_alloc: HeapNodeAlloc,                 // To allocate new nodes to use.
     head: Option<*mut HeapNode>,               // The first node in the list.
+    name: &'static str,                        // The identity of the list (such as "free").
+}
+
+/// A structure which holds a summary of the regions in a list.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeapListSummary {
+    pub num_nodes: usize,                      // The number of nodes in the list.
+    pub total_bytes: usize,                    // The sum of the region sizes.
+    pub min_size: usize,                       // The size of the smallest region (0 if empty).
+    pub max_size: usize,                       // The size of the largest region.
+    pub median_size: usize,                    // The size of the middle region (the upper one).
 }
 
 impl HeapList {
@@ -24,11 +58,14 @@ impl HeapList {
     ///
     /// # Parameters
     /// `metadata_region` : The start addr and size of the memory which will be used to store nodes.
-    pub fn new(metadata_region: &Region) -> Self {
+    /// `grow` : The function which acquires more memory for the nodes (None to never grow).
+    /// `name` : The identity of the list (which is included in the dumps).
+    pub fn new(metadata_region: &Region, grow: Option<GrowFn>, name: &'static str) -> Self {
         // Initialize an allocator and set the head to none for now.
         HeapList {
-            node_alloc: unsafe { HeapNodeAlloc::new(metadata_region) },
-            head: None
+            node_alloc: unsafe { HeapNodeAlloc::new(metadata_region, grow) },
+            head: None,
+            name,
         }
     }
     
@@ -38,13 +75,19 @@ impl HeapList {
     ///
     /// # Parameters
     /// `region` : The new memory region which we want to allocate.
-    /// `merge` : If we want to merge the region with previous ones if continous.
+    /// `merge` : If we want to merge the region with it's neighbours if continous (only the 
+    /// neighbours are checked, so the rest of the list should already be merged).
     ///
     /// # Returns
-    /// Ok(new_node_ptr) if everything was successful, Err() if allocation failed.
-    pub unsafe fn add(&mut self, region: &Region, merge: bool) -> Result<*mut HeapNode, ()> {
+    /// Ok(node_ptr) which holds the region (the previous node if it was merged into it), NoNodes 
+    /// if the allocation failed, or the reason a merged node could not be freed.
+    pub unsafe fn add(&mut self, region: &Region, merge: bool) 
+        -> Result<*mut HeapNode, HeapListError> {
+        // The empty regions should be filtered by the callers (they would never be used).
+        debug_assert!(!region.is_empty(), "Adding an empty region at 0x{:x}.", region.addr);
+        
         // First allocate a new node, if not successful, return the Err.
-        let mut new_node_ptr = self.node_alloc.alloc()?;
+        let mut new_node_ptr = self.node_alloc.alloc().map_err(|()| HeapListError::NoNodes)?;
         
         // Assign the region to the node.
         (*new_node_ptr).region = region.clone();
@@ -67,64 +110,88 @@ impl HeapList {
             },
         }
         
-        // If merging was requested, merge all the possible nodes.
+        // Link the new node and it's neighbours back to each other.
+        (*new_node_ptr).prev = prev_node;
+        if let Some(next) = (*new_node_ptr).next {
+            (*next).prev = Some(new_node_ptr);
+        }
+        
+        // If merging was requested, merge it with it's neighbours.
         if merge {
-            self.merge();
+            new_node_ptr = self.merge_at(new_node_ptr)?;
         }
     
         // If we get here, the region was successfully added to a new node.
         Ok(new_node_ptr)
     }
     
+    /// A method which merges a node with it's previous and next nodes (if they're continuous). It
+    /// coalesces at most twice, so it doesn't depend on the length of the list.
+    ///
+    /// # Parameters
+    /// `node` : The node which was just inserted.
+    ///
+    /// # Returns
+    /// Ok(node_ptr) which holds the merged region, or the reason a merged node could not be freed.
+    pub unsafe fn merge_at(&mut self, node: *mut HeapNode) -> Result<*mut HeapNode, HeapListError> {
+        // Take the next region into this node.
+        if let Some(next) = (*node).next {
+            if (*node).region.end_addr() == (*next).region.addr {
+                (*node).region.size += self.remove(next)?.size;
+            }
+        }
+        
+        // Give this region to the previous node.
+        if let Some(prev) = (*node).prev {
+            if (*prev).region.end_addr() == (*node).region.addr {
+                (*prev).region.size += self.remove(node)?.size;
+                return Ok(prev);
+            }
+        }
+        
+        Ok(node)
+    }
+    
     /// A method which removes a given node (with a pointer) from the list. It then returns the 
-    /// region which was included in the node.
+    /// region which was included in the node. It uses the links of the node, so it doesn't
+    /// traverse the list.
     ///
     /// # Parameters
     /// `node`: The pointer to the node we're deleting.
     ///
     /// # Returns 
-    /// Ok(node_mem_region) if successful, Err() otherwise.
-    pub unsafe fn remove(&mut self, node: *mut HeapNode) -> Result<Region, ()> {
-        // Traverse the list from the head, to find where the given node is.
-        let prev_node_ptr: Option<*mut HeapNode> = self.traverse((*node).region.addr);
+    /// Ok(node_mem_region) if successful, NotLinked if it's not in this list, or the reason the 
+    /// node could not be freed.
+    pub unsafe fn remove(&mut self, node: *mut HeapNode) -> Result<Region, HeapListError> {
+        let prev_node = (*node).prev;
+        let next_node = (*node).next;
         
-        // To store the results of the removal.
-        let mut to_return: Result<Region, ()> = Err(());
+        // Make sure the node is actually linked in this list (it's previous node, or the head if
+        // it doesn't have one, should point to it).
+        let linked = match prev_node {
+            Some(prev) => (*prev).next == Some(node),
+            None => self.head == Some(node),
+        };
         
-        // Check the value of the traversed node.
-        match prev_node_ptr {
-            // If there is currently a previous node, it means that we're not at the head.
-            Some(prev_node) => {
-                // In this case, check the next pointer.
-                match (*prev_node).next {
-                    // If there is a node, make sure it is the same as this node.
-                    Some(curr_node) => {
-                        // If it is, adjust the next pointers, and deallocate the node.
-                        if curr_node == node {
-                            (*prev_node).next = (*curr_node).next;
-                            to_return = Ok((*curr_node).region.clone());
-                            self.node_alloc.free(node)?;
-                        }
-                    },
-                    
-                    // Otherwise don't do anything (the error will remain).
-                    None => (),
-                }
-            },
-            
-            // If there are not previous nodes, it means that we're at the head.
-            None => {
-                // Make sure the head is the same as our current node.
-                if self.head == Some(node) {
-                    // If it is, adjust the pointers, and deallocate the node.
-                    to_return = Ok((*node).region);
-                    self.head = (*node).next;
-                    self.node_alloc.free(node)?;
-                }
-            }
+        if !linked {
+            return Err(HeapListError::NotLinked);
         }
         
-        to_return
+        // Unlink the node from both of it's neighbours.
+        match prev_node {
+            Some(prev) => (*prev).next = next_node,
+            None => self.head = next_node,
+        }
+        
+        if let Some(next) = next_node {
+            (*next).prev = prev_node;
+        }
+        
+        // Deallocate the node, and return it's region.
+        let region = (*node).region;
+        self.node_alloc.free(node)?;
+        
+        Ok(region)
     }
     
     /// A method which starts at the beginning of the list, and traverses it until it reaches a 
@@ -156,8 +223,13 @@ impl HeapList {
     }
     
     /// A method which starts at the beginning, and iterates over the list and merges every two 
-    /// cells that can be merged (based on their region). 
-    pub unsafe fn merge(&mut self) {
+    /// cells that can be merged (based on their region). It walks the whole list, so add only
+    /// merges around the new node (see merge_at).
+    ///
+    /// # Returns
+    /// Ok if everything was merged, or the reason a merged node could not be freed (it's region is
+    /// merged, but the node is leaked and the rest of the list is not merged).
+    pub unsafe fn merge(&mut self) -> Result<(), HeapListError> {
         // To keep track of the last node (start at None since head's previous node is none).
         let mut prev_node: Option<*mut HeapNode> = None;
         
@@ -169,12 +241,15 @@ impl HeapList {
                 Some(prev) => {
                     // If it is continous, merge them.
                     if (*prev).region.end_addr() == (*node).region.addr {
-                        // Adjust the size and next values.
+                        // Adjust the size and the links.
                         (*prev).region.size += (*node).region.size;
                         (*prev).next = (*node).next;
+                        if let Some(next) = (*node).next {
+                            (*next).prev = Some(prev);
+                        }
                         
                         // Free the previous current node, and DO NOT update the prev_node.
-                        self.node_alloc.free(node).expect("Couldn't free it!");
+                        self.node_alloc.free(node)?;
                     // If it's not continous, just update prev_node.
                     } else {
                         prev_node = Some(node);
@@ -185,6 +260,91 @@ impl HeapList {
                 None => prev_node = Some(node),
             }
         }
+        
+        // A merge bug would leave continuous regions behind (and fragment the heap for no reason).
+        debug_assert!(self.is_merged(), "The heap list has continuous regions after merging.");
+        debug_assert!(self.is_linked(), "The heap list has broken previous links after merging.");
+        Ok(())
+    }
+    
+    /// A method which checks that no two nodes in the list have continuous regions (so everything
+    /// which could be merged was merged).
+    ///
+    /// # Returns
+    /// True if the list is fully merged, False otherwise.
+    pub unsafe fn is_merged(&self) -> bool {
+        let mut prev_end: Option<usize> = None;
+        for node in self.into_iter() {
+            if prev_end == Some((*node).region.addr) {
+                return false;
+            }
+            prev_end = Some((*node).region.end_addr());
+        }
+        
+        true
+    }
+    
+    /// A method which finds the node with a region which starts at a given address. It can be used
+    /// to look up the node which follows a region (by passing the region's end address).
+    ///
+    /// # Parameters
+    /// `addr` : The start address of the region which we're looking for.
+    ///
+    /// # Returns
+    /// Some(node_ptr) if there is a node starting at addr, None otherwise.
+    pub unsafe fn find_starting_at(&self, addr: usize) -> Option<*mut HeapNode> {
+        // The list is sorted, so stop once we're past the address.
+        self.into_iter().take_while(|node_ptr| (**node_ptr).region.addr <= addr)
+            .find(|node_ptr| (**node_ptr).region.addr == addr)
+    }
+    
+    /// A method which checks that the previous pointer of every node points to the node before it
+    /// (and that the head doesn't have one).
+    ///
+    /// # Returns
+    /// True if the links are consistent, False otherwise.
+    pub unsafe fn is_linked(&self) -> bool {
+        let mut prev_node: Option<*mut HeapNode> = None;
+        for node in self.into_iter() {
+            if (*node).prev != prev_node {
+                return false;
+            }
+            prev_node = Some(node);
+        }
+        
+        true
+    }
+    
+    /// A simple getter for the number of nodes which were allocated for this list (it should match
+    /// the number of nodes which are reached from the head).
+    ///
+    /// # Returns
+    /// The number of allocated nodes.
+    pub fn num_nodes(&self) -> usize {
+        self.node_alloc.len()
+    }
+    
+    /// A simple getter for the number of node slots which were handed out for this list (see
+    /// check_canaries).
+    ///
+    /// # Returns
+    /// The number of slots (used or free).
+    pub fn num_slots(&self) -> usize {
+        self.node_alloc.num_slots()
+    }
+    
+    /// A method which checks the canaries of the nodes in a range of slots (see 
+    /// HeapNodeAlloc::check_canaries).
+    ///
+    /// # Parameters
+    /// `start` : The index of the first slot which is checked.
+    /// `count` : The maximum number of slots which are checked.
+    ///
+    /// # Returns
+    /// Ok(Some(next)) with the slot to continue from, Ok(None) if the last slot was checked, or 
+    /// Err(index) of the first node whose canary was overwritten.
+    pub fn check_canaries(&self, start: usize, count: usize) -> Result<Option<usize>, usize> {
+        self.node_alloc.check_canaries(start, count)
     }
     
     /// To get an iterator over HeapList. It simply stores the head in the iterator.
@@ -194,13 +354,66 @@ impl HeapList {
         }
     }
     
-    /// A function which iterates over this heap list, and dumps it to the console.
-    pub fn dump(&self) {
-        oxid_println!("Dumping the HeapList");
-        // Get an iterator, dereferece the ptr, and dump it using debug mode.
+    /// A method which summarizes the regions in the list. It doesn't allocate (so it can be used 
+    /// while the heap is locked), so the median is found by counting the smaller regions for each
+    /// one (it's quadratic, so it should only be used for debugging).
+    ///
+    /// # Returns
+    /// The summary of the list.
+    pub fn summary(&self) -> HeapListSummary {
+        let mut summary = HeapListSummary::default();
         for node in self.into_iter() {
-            oxid_println!("Node: {:?}", unsafe{*node});
+            let size = unsafe { (*node).region.size };
+            summary.min_size = if summary.num_nodes == 0 { size } 
+                else { core::cmp::min(summary.min_size, size) };
+            summary.max_size = core::cmp::max(summary.max_size, size);
+            summary.total_bytes += size;
+            summary.num_nodes += 1;
         }
+        
+        // The median is the size which has half of the regions below it (and the rest above it).
+        let sizes = || self.into_iter().map(|node| unsafe { (*node).region.size });
+        let middle = summary.num_nodes / 2;
+        summary.median_size = sizes().find(|size| {
+            let below = sizes().filter(|other| other < size).count();
+            below <= middle && middle < below + sizes().filter(|other| other == size).count()
+        }).unwrap_or(0);
+        
+        summary
+    }
+    
+    /// A method which writes a compact summary of the list (it's identity, the number of nodes, 
+    /// the total bytes, and the region sizes), followed by up to max_nodes regions.
+    ///
+    /// # Parameters
+    /// `w` : The writer which the dump is written to.
+    /// `max_nodes` : The most regions which are written (the rest are only counted).
+    ///
+    /// # Returns
+    /// The result of the writes.
+    pub fn dump_to(&self, w: &mut dyn core::fmt::Write, max_nodes: usize) -> core::fmt::Result {
+        let summary = self.summary();
+        writeln!(w, "HeapList ({}): {} nodes, {} bytes, size min={} max={} median={}", self.name,
+            summary.num_nodes, summary.total_bytes, summary.min_size, summary.max_size, 
+            summary.median_size)?;
+        
+        for node in self.into_iter().take(max_nodes) {
+            let node = unsafe { *node };
+            writeln!(w, "  0x{:x}-0x{:x} ({} bytes){}{}", node.region.addr, node.region.end_addr(),
+                node.region.size, if node.lazy { " lazy" } else { "" }, 
+                if node.guarded { " guarded" } else { "" })?;
+        }
+        
+        if summary.num_nodes > max_nodes {
+            writeln!(w, "  ... {} more", summary.num_nodes - max_nodes)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// A method which dumps this heap list to the console (with up to DUMP_MAX_NODES regions).
+    pub fn dump(&self) {
+        let _ = self.dump_to(&mut crate::debug::hexdump::Console, DUMP_MAX_NODES);
     }
 }
 
@@ -239,22 +452,95 @@ impl Iterator for HeapListIter {
 pub mod test {
     use crate::mem::dyn_alloc::heap_node::HeapNode;
     use crate::mem::region::Region;
+    use super::HeapList;
 
     /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
     /// sub module. 
     pub fn run() {
+        test_add_remove();
+        test_remove_positions();
+        test_dump();
+        test_merge_at();
+    }
+    
+    /// Add three adjacent regions in every order, and make sure they always end up in a single 
+    /// node. The regions which are not adjacent stay in separate nodes.
+    fn test_merge_at() {
+        const ORDERS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], 
+            [2, 1, 0]];
+        
+        unsafe {
+            const SIZE: usize = core::mem::size_of::<HeapNode>() * 10;
+            let scratch = crate::mem::test::scratch(SIZE);
+            let mut addrs: [usize; 8] = [0; 8];
+            let regions = [Region::new(10, 20), Region::new(20, 30), Region::new(30, 40)];
+            
+            for order in ORDERS.iter() {
+                let mut list = HeapList::new(&Region::new_sized(scratch.addr, SIZE), None, "test");
+                let mut last = core::ptr::null_mut();
+                for idx in order.iter() {
+                    last = list.add(&regions[*idx], true).unwrap();
+                }
+                
+                // The returned node holds the merged region.
+                assert_eq!(addrs_of(&list, &mut addrs), 1);
+                assert_eq!(((*last).region.addr, (*last).region.size), (10, 30));
+                assert_eq!(list.num_nodes(), 1);
+                assert!(list.is_merged() && list.is_linked());
+            }
+            
+            // With a gap between them, they're not merged.
+            let mut list = HeapList::new(&Region::new_sized(scratch.addr, SIZE), None, "test");
+            list.add(&Region::new(30, 40), true).unwrap();
+            list.add(&Region::new(10, 20), true).unwrap();
+            assert_eq!(addrs_of(&list, &mut addrs), 2);
+            assert_eq!(&addrs[..2], &[10, 30]);
+            assert!(list.is_merged() && list.is_linked());
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Dump a list to a buffer, and make sure the summary is correct and the regions are bounded.
+    fn test_dump() {
+        use crate::olibc::bounded::BoundedString;
+        use super::HeapListSummary;
+        
         unsafe {
-            // Create a region at the end of the kernel.
+            const SIZE: usize = core::mem::size_of::<HeapNode>() * 10;
+            let scratch = crate::mem::test::scratch(SIZE);
+            let mut list = HeapList::new(&Region::new_sized(scratch.addr, SIZE), None, "free");
+            assert_eq!(list.summary(), HeapListSummary::default());
+            
+            // Add the regions with the sizes 0x30, 0x10, 0x20, 0x10 (with gaps).
+            list.add(&Region::new_sized(0x1000, 0x30), true).unwrap();
+            list.add(&Region::new_sized(0x2000, 0x10), true).unwrap();
+            list.add(&Region::new_sized(0x3000, 0x20), true).unwrap();
+            list.add(&Region::new_sized(0x4000, 0x10), true).unwrap();
+            assert_eq!(list.summary(), HeapListSummary { num_nodes: 4, total_bytes: 0x70, 
+                min_size: 0x10, max_size: 0x30, median_size: 0x20 });
+            
+            // Only the first two regions are written.
+            let mut out: BoundedString<256> = BoundedString::new();
+            assert!(list.dump_to(&mut out, 2).is_ok());
+            assert_eq!(out.as_str(), "HeapList (free): 4 nodes, 112 bytes, size min=16 max=48 \
+                median=32\n  0x1000-0x1030 (48 bytes)\n  0x2000-0x2010 (16 bytes)\n  ... 2 more\n");
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Add and remove a few regions (with merging), and check the number of nodes left.
+    fn test_add_remove() {
+        unsafe {
+            // Get a private scratch region (so the kernel heap's metadata is never touched).
             const SIZE: usize = core::mem::size_of::<HeapNode>() * 20;
-            let start_addr = crate::mem::map::KERNEL_END_ADDR;
+            let scratch = crate::mem::test::scratch(SIZE);
+            let start_addr = scratch.addr;
             let kreg = Region::new_sized(start_addr, SIZE);
         
-            // Save the previously written data at address to restore it later.
-            let prev_data: [u8; SIZE] = [0; SIZE];
-            crate::olibc::memcpy::memcpy(prev_data.as_ptr() as *mut u8, start_addr as *const u8, SIZE);
-            
             // Create a new heaplist.
-            let mut list = super::HeapList::new(&kreg);
+            let mut list = super::HeapList::new(&kreg, None, "test");
             
             // Create some test regions.
             let reg_1 = Region::new(1, 3);
@@ -280,9 +566,78 @@ pub mod test {
             
             // Check if the count is correct.
             assert_eq!(count, 3);
+            assert!(list.is_linked());
+            
+            // Give the scratch region back.
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// A helper which collects the start addresses of the regions in the list (in order).
+    ///
+    /// # Parameters
+    /// `list` : The list to go through.
+    /// `addrs` : The array to fill.
+    ///
+    /// # Returns
+    /// The number of nodes in the list.
+    unsafe fn addrs_of(list: &HeapList, addrs: &mut [usize; 8]) -> usize {
+        let mut count: usize = 0;
+        for node in list.into_iter() {
+            addrs[count] = (*node).region.addr;
+            count += 1;
+        }
+        
+        count
+    }
+    
+    /// Remove the middle, tail, and head nodes (in that order), and check the order and the
+    /// previous links after each removal.
+    fn test_remove_positions() {
+        unsafe {
+            const SIZE: usize = core::mem::size_of::<HeapNode>() * 10;
+            let scratch = crate::mem::test::scratch(SIZE);
+            let mut list = HeapList::new(&Region::new_sized(scratch.addr, SIZE), None, "test");
+            let mut addrs: [usize; 8] = [0; 8];
+            
+            // Add them out of order (with gaps so nothing merges).
+            let node_3 = list.add(&Region::new(30, 35), true).unwrap();
+            let node_1 = list.add(&Region::new(10, 15), true).unwrap();
+            let node_4 = list.add(&Region::new(40, 45), true).unwrap();
+            let node_2 = list.add(&Region::new(20, 25), true).unwrap();
+            assert_eq!(addrs_of(&list, &mut addrs), 4);
+            assert_eq!(&addrs[..4], &[10, 20, 30, 40]);
+            assert!(list.is_linked());
+            assert_eq!((*node_1).prev, None);
+            assert_eq!((*node_3).prev, Some(node_2));
+            
+            // Remove from the middle.
+            assert_eq!(list.remove(node_2).unwrap().addr, 20);
+            assert_eq!(addrs_of(&list, &mut addrs), 3);
+            assert_eq!(&addrs[..3], &[10, 30, 40]);
+            assert_eq!((*node_3).prev, Some(node_1));
+            assert!(list.is_linked());
+            
+            // Remove the tail.
+            assert_eq!(list.remove(node_4).unwrap().addr, 40);
+            assert_eq!(addrs_of(&list, &mut addrs), 2);
+            assert_eq!((*node_3).next, None);
+            assert!(list.is_linked());
+            
+            // Remove the head.
+            assert_eq!(list.remove(node_1).unwrap().addr, 10);
+            assert_eq!(addrs_of(&list, &mut addrs), 1);
+            assert_eq!((*node_3).prev, None);
+            assert!(list.is_linked());
+            
+            // Merging should keep the links of the following nodes.
+            list.add(&Region::new(35, 40), true).unwrap();
+            list.add(&Region::new(50, 55), true).unwrap();
+            assert_eq!(addrs_of(&list, &mut addrs), 2);
+            assert_eq!(&addrs[..2], &[30, 50]);
+            assert!(list.is_linked());
             
-            // Restore the data from the backup.
-            crate::olibc::memcpy::memcpy(start_addr as *mut u8, prev_data.as_ptr(), SIZE);
+            crate::mem::test::free_scratch(&scratch);
         }
     }
 }
@@ -6,10 +6,55 @@
 
 use crate::mem::region::Region;                    // To represent memory regions.
 
+/// The canary which is written at the start of every allocated node. The nodes are packed together
+/// in the metadata regions, so a write which runs past one node overwrites the canary of the next.
+pub const NODE_CANARY: usize = 0x0A1D_5AFE_C0DE_CAFE;
+
 /// A structure which represents a node for managing memory.
 #[derive(Copy, Clone, Debug, Default)]
 pub struct HeapNode {
+    pub canary: usize,                             // NODE_CANARY while the node is allocated.
     pub region: Region,                            // The region that this node represents.
     pub next: Option<*mut HeapNode>,               // Pointer to the next node.
+    pub prev: Option<*mut HeapNode>,               // Pointer to the previous node.
     pub list_idx: usize,                           // Store index to allow fast frees.
+    pub lazy: bool,                                // True if it's mapped on the first access.
+    pub no_exec: bool,                             // The permission of the lazy pages.
+    pub guarded: bool,                             // True if it has a guard page at both ends.
+    pub req_size: usize,                           // The size which was requested (not rounded).
+    pub req_align: usize,                          // The alignment which was requested.
+    pub tag: super::AllocTag,                      // The owner of the allocation.
+}
+
+impl HeapNode {
+    /// A method which checks the canary of the node.
+    ///
+    /// # Returns
+    /// True if it's intact, False if it was overwritten.
+    pub fn canary_ok(&self) -> bool {
+        self.canary == NODE_CANARY
+    }
+    
+    /// A method which finds the part of the region which is returned to the caller (it's the whole
+    /// region, unless it has guard pages which are never mapped).
+    ///
+    /// # Returns
+    /// The usable region.
+    pub fn usable(&self) -> Region {
+        if self.guarded {
+            Region::new(self.region.addr + crate::mem::vmm::PAGE_SIZE, 
+                self.region.end_addr() - crate::mem::vmm::PAGE_SIZE)
+        } else {
+            self.region
+        }
+    }
+    
+    /// A method which returns the layout that was requested for this allocation (before it was
+    /// rounded up to pages).
+    ///
+    /// # Returns
+    /// The requested layout (only valid for the used regions).
+    pub fn requested(&self) -> core::alloc::Layout {
+        unsafe { core::alloc::Layout::from_size_align_unchecked(self.req_size, self.req_align) }
+    }
 }
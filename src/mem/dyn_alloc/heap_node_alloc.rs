@@ -1,15 +1,42 @@
 //! A sub-module which represents an allocator for each of the heap nodes. It allows allocation and 
-//! freeing of the nodes. It is used to implement the linked list for the heap.
+//! freeing of the nodes. It is used to implement the linked list for the heap. When the first
+//! region is full, more page sized regions can be chained to it (they're acquired from the source
+//! which was passed to the constructor). Each chained region starts with a header which links it
+//! to the next one, and the chained regions are kept once they're added (they're reused).
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Mar 2021
 
-use super::heap_node::HeapNode;                // To represent nodes.
+use super::heap_node::{HeapNode, NODE_CANARY}; // To represent nodes.
 use crate::mem::region::Region;                // For initialization.
 
 /// The size of the nodes when wrapped in an option (which is the type stored).
 const OPT_NODE_SIZE: usize = core::mem::size_of::<Option<HeapNode>>();
 
+/// The offset of the first node in a chained region (the header only has usizes, so the nodes 
+/// right after it are aligned).
+const CHAIN_OFFSET: usize = core::mem::size_of::<ChainHeader>();
+
+/// The type of the function which acquires another region for the nodes (once the others are
+/// full). It returns Err if there is no more memory for the metadata.
+pub type GrowFn = unsafe fn() -> Result<Region, ()>;
+
+/// An enum which represents the reason a node could not be freed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NodeFreeError {
+    OutOfRange,                 // It's not in any of the regions.
+    Misaligned,                 // It's in a region, but not at the start of a node.
+    AlreadyFree,                // The node is not allocated (a double free).
+    IndexMismatch,              // The index in the node doesn't match it's slot (corrupted).
+}
+
+/// A structure which represents the header at the start of every chained region.
+#[repr(C)]
+struct ChainHeader {
+    next: usize,                               // The next chained region (0 if it's the last).
+    max_count: usize,                          // The number of nodes which fit in this region.
+}
+
 /// A structure which represents a basic allocator for HeapNodes. This will be used in the
 /// implementation of the linked list to hold the allocators.
 #[derive(Debug)]
@@ -18,6 +45,12 @@ pub struct HeapNodeAlloc {
     curr_count: usize,                         // The number of elements in the list (currently).
     used_count: usize,                         // Number of used (not None) elements in the list.
     max_count: usize,                          // Most elements supprted in this list.
+    first_count: usize,                        // The number of elements in the first region.
+    first_chain: usize,                        // The first chained region (0 if there's none).
+    last_chain: usize,                         // The last chained region (0 if there's none).
+    num_regions: usize,                        // The number of regions (including the first).
+    grow: Option<GrowFn>,                      // To acquire more regions (None if it's fixed).
+    growing: bool,                             // To guard against a recursive grow.
 }
 
 impl HeapNodeAlloc {
@@ -25,12 +58,19 @@ impl HeapNodeAlloc {
     ///
     /// # Parameters
     /// `mem_region` : The start addr and size of the memory which will be used by this list.
-    pub unsafe fn new(mem_region: &Region) -> Self {
+    /// `grow` : The function which acquires more regions when it's full (None to never grow).
+    pub unsafe fn new(mem_region: &Region, grow: Option<GrowFn>) -> Self {
         HeapNodeAlloc {
             start_addr: mem_region.addr,
             curr_count: 0,
             used_count: 0,
             max_count: mem_region.size / OPT_NODE_SIZE,
+            first_count: mem_region.size / OPT_NODE_SIZE,
+            first_chain: 0,
+            last_chain: 0,
+            num_regions: 1,
+            grow,
+            growing: false,
         }
     }
     
@@ -39,9 +79,9 @@ impl HeapNodeAlloc {
     /// # Returns
     /// Ok(ptr_to_node) if successful, Err if the list is full or other error occured.
     pub unsafe fn alloc(&mut self) -> Result<*mut HeapNode, ()> {
-        // Check if the allocator is full.
+        // Check if the allocator is full, and chain another region if it is.
         if self.used_count == self.max_count {
-            return Err(());
+            self.chain_region()?;
         }
         
         // Hold an index for the element which we want to add.
@@ -53,8 +93,7 @@ impl HeapNodeAlloc {
             idx = self.curr_count;
         
             // Clear out the memory for the new section.
-            crate::olibc::memset::memset(
-                (self.start_addr + idx * OPT_NODE_SIZE) as *mut u8, 0, OPT_NODE_SIZE);
+            crate::olibc::memset::memset(self.slot_addr(idx) as *mut u8, 0, OPT_NODE_SIZE);
             
             // Increase the count.
             self.curr_count += 1;
@@ -64,7 +103,7 @@ impl HeapNodeAlloc {
             // Find the first cell which is None, and set the idx to it's idx.
             for i in 0..self.curr_count {
                 // If we found an empty one, store it's index and break the loop.
-                if self[i].is_none() {
+                if self.get(i).map_or(false, |slot| slot.is_none()) {
                     idx = i;
                     break;
                 }
@@ -74,39 +113,38 @@ impl HeapNodeAlloc {
         // Create a default node (everything set to 0), and set it's index for the future.
         let mut new_node = HeapNode::default();
         new_node.list_idx = idx;
-        
-        // Create a new default node (Everything is 0), and set it to the correct idx.
-        self[new_node.list_idx] = Some(new_node);
+        new_node.canary = NODE_CANARY;
         
         // Increase the number of used nodes.
         self.used_count += 1;
         
+        // Create a new default node (Everything is 0), and set it to the correct idx.
+        let slot = self.get_mut(idx).expect("The new node is out of range.");
+        *slot = Some(new_node);
+        
         // Get a reference to the node directly, and return a pointer to it.
-        Ok(self[new_node.list_idx].as_mut().expect("Fatal error in Node alloc") as *mut HeapNode)
+        Ok(slot.as_mut().expect("Fatal error in Node alloc") as *mut HeapNode)
     }
     
     /// The main deallocator for HeapNodes. It relies on the list_idx field of the node. It sets 
-    /// the correct index to None and "frees" it.
+    /// the correct index to None and "frees" it. The node can be in any of the regions.
     ///
     /// # Returns
-    /// Ok if it was successfully freed, Err if the ptr was not valid.
-    pub fn free(&mut self, node_ptr: *mut HeapNode) -> Result<(), ()> {
-        // To check the address constraints.
-        let node_addr: usize = node_ptr as usize;
+    /// Ok if it was successfully freed, or the reason if the ptr was not valid.
+    pub fn free(&mut self, node_ptr: *mut HeapNode) -> Result<(), NodeFreeError> {
+        // Check if the pointer is out of all the regions, or if the address is not aligned 
+        // correctly (right when HeapNodes should start).
+        let idx = self.index_of(node_ptr as usize)?;
         
-        // Check if the pointer is out of the acceptable address range. Or if the address is not 
-        // aligned correctly (right when HeapNodes should start).
-        if (node_addr < self.start_addr) 
-            || (node_addr > self.start_addr + (self.curr_count * OPT_NODE_SIZE)) 
-            || ((node_addr - self.start_addr) % OPT_NODE_SIZE != 0) {
-            return Err(())
-        }
+        // The slots after the current count were never used (or they were freed).
+        let slot = self.get_mut(idx).ok_or(NodeFreeError::AlreadyFree)?;
         
-        // Get the index of the node we're freeing.
-        let idx: usize = unsafe { (*node_ptr).list_idx };
-        
-        // Set the node at the idx to None.
-        self[idx] = None;
+        // The index of the node should match where it is (and it should not be freed already).
+        match slot {
+            None => return Err(NodeFreeError::AlreadyFree),
+            Some(node) if node.list_idx != idx => return Err(NodeFreeError::IndexMismatch),
+            Some(_) => *slot = None,
+        }
         
         // Decrease the number of used elements.
         self.used_count -= 1;
@@ -120,6 +158,40 @@ impl HeapNodeAlloc {
         Ok(())
     }
     
+    /// A method which returns the slot at an index (the slots are None once they're freed).
+    ///
+    /// # Parameters
+    /// `index` : The index of the slot.
+    ///
+    /// # Returns
+    /// Some(slot) if it's one of the current slots, None if it's out of range.
+    pub fn get(&self, index: usize) -> Option<&Option<HeapNode>> {
+        if index >= self.curr_count {
+            return None;
+        }
+        
+        // Calculate the address of the wanted entry, and return a reference to it.
+        let addr = self.slot_addr(index);
+        unsafe { Some(&*(addr as *const Option<HeapNode>)) }
+    }
+    
+    /// A method which returns the mutable slot at an index (the slots are None once they're freed).
+    ///
+    /// # Parameters
+    /// `index` : The index of the slot.
+    ///
+    /// # Returns
+    /// Some(slot) if it's one of the current slots, None if it's out of range.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Option<HeapNode>> {
+        if index >= self.curr_count {
+            return None;
+        }
+        
+        // Calculate the address of the wanted entry, and return a reference to it.
+        let addr = self.slot_addr(index);
+        unsafe { Some(&mut *(addr as *mut Option<HeapNode>)) }
+    }
+    
     /// A method to get the current number of used nodes.
     ///
     /// # Returns
@@ -128,64 +200,255 @@ impl HeapNodeAlloc {
     pub fn len(&self) -> usize {
         self.used_count
     }
-}
-
-/// Implement the index trait to be able to access elements directly.
-impl core::ops::Index<usize> for HeapNodeAlloc {
-    type Output = Option<HeapNode>;
     
-    /// A function which is an accessor for the array enclosed.
-    #[inline(never)]
-    fn index(&self, index: usize) -> &Option<HeapNode> {
-        // Make sure the index is within range.
-        assert!(index < self.curr_count);
+    /// A method to get the number of slots which were handed out so far (used or free). The slots
+    /// are reused, so it never goes down.
+    ///
+    /// # Returns
+    /// The number of slots.
+    pub fn num_slots(&self) -> usize {
+        self.curr_count
+    }
+    
+    /// A method which checks the canaries of the allocated nodes in a range of slots. It only
+    /// checks a few of them, so a large allocator can be checked a part at a time.
+    ///
+    /// # Parameters
+    /// `start` : The index of the first slot which is checked.
+    /// `count` : The maximum number of slots which are checked.
+    ///
+    /// # Returns
+    /// Ok(Some(next)) with the slot to continue from, Ok(None) if the last slot was checked, or 
+    /// Err(index) of the first node whose canary was overwritten.
+    pub fn check_canaries(&self, start: usize, count: usize) -> Result<Option<usize>, usize> {
+        let end = start.saturating_add(count).min(self.curr_count);
+        for idx in start..end {
+            if let Some(Some(node)) = self.get(idx) {
+                if !node.canary_ok() {
+                    return Err(idx);
+                }
+            }
+        }
+        
+        Ok(if end < self.curr_count { Some(end) } else { None })
+    }
     
-        // Calculate the address of the wanted entry, and return a reference to it.
-        let addr = self.start_addr + (OPT_NODE_SIZE * index);
-        unsafe { &*(addr as *const Option<HeapNode>) }
+    /// A method to get the number of regions which hold the nodes.
+    ///
+    /// # Returns
+    /// The number of regions (the first one, and the chained ones).
+    #[allow(dead_code)]
+    pub fn num_regions(&self) -> usize {
+        self.num_regions
     }
-}
-
-/// Implement the mutable index trait to be able to modify elements directly.
-impl core::ops::IndexMut<usize> for HeapNodeAlloc {
-    /// A function which is a mutable accessor for the array enclosed.
-    #[inline(never)]
-    fn index_mut(&mut self, index: usize) -> &mut Option<HeapNode> {
-        // Make sure the index is within range.
-        assert!(index < self.curr_count);
     
-        // Calculate the address of the wanted entry, and return a reference to it.
-        let addr = self.start_addr + (OPT_NODE_SIZE * index);
-        unsafe { &mut *(addr as *mut Option<HeapNode>) }
+    /// A helper which acquires another region from the grow function, and chains it after the 
+    /// last one (so the indices keep going from where they ended).
+    ///
+    /// # Returns
+    /// Ok if a region was added, Err if it can't grow (or it's already growing).
+    unsafe fn chain_region(&mut self) -> Result<(), ()> {
+        let grow = match self.grow {
+            Some(grow) if !self.growing => grow,
+            _ => return Err(()),
+        };
+        
+        // The function might allocate from the heap which is using this allocator.
+        self.growing = true;
+        let region = grow();
+        self.growing = false;
+        
+        let region = region?;
+        if region.size < CHAIN_OFFSET + OPT_NODE_SIZE {
+            return Err(());
+        }
+        
+        // Set up the header, and link it after the last region.
+        let header = region.addr as *mut ChainHeader;
+        (*header).next = 0;
+        (*header).max_count = (region.size - CHAIN_OFFSET) / OPT_NODE_SIZE;
+        
+        if self.last_chain == 0 {
+            self.first_chain = region.addr;
+        } else {
+            (*(self.last_chain as *mut ChainHeader)).next = region.addr;
+        }
+        
+        self.last_chain = region.addr;
+        self.max_count += (*header).max_count;
+        self.num_regions += 1;
+        
+        Ok(())
+    }
+    
+    /// A helper which finds the address of the node at an index (in any of the regions).
+    ///
+    /// # Parameters
+    /// `index` : The index of the node (it should be less than max_count).
+    ///
+    /// # Returns
+    /// The address of the node.
+    fn slot_addr(&self, index: usize) -> usize {
+        if index < self.first_count {
+            return self.start_addr + OPT_NODE_SIZE * index;
+        }
+        
+        // Go through the chained regions until the one which has it.
+        let mut index = index - self.first_count;
+        let mut chain = self.first_chain;
+        while chain != 0 {
+            let header = unsafe { &*(chain as *const ChainHeader) };
+            if index < header.max_count {
+                return chain + CHAIN_OFFSET + OPT_NODE_SIZE * index;
+            }
+            
+            index -= header.max_count;
+            chain = header.next;
+        }
+        
+        panic!("The node index is not in any of the regions.");
+    }
+    
+    /// A helper which finds the index of a node from it's address.
+    ///
+    /// # Parameters
+    /// `addr` : The address of the node.
+    ///
+    /// # Returns
+    /// Ok(index) if it's the start of a node in any of the regions, OutOfRange if it's not in any
+    /// of them, or Misaligned if it's not the start of a node.
+    fn index_of(&self, addr: usize) -> Result<usize, NodeFreeError> {
+        // Check the first region, and then all the chained ones.
+        let mut base = self.start_addr;
+        let mut count = self.first_count;
+        let mut first_idx: usize = 0;
+        let mut chain = self.first_chain;
+        
+        loop {
+            if addr >= base && addr < base + count * OPT_NODE_SIZE {
+                return if (addr - base) % OPT_NODE_SIZE == 0 {
+                    Ok(first_idx + (addr - base) / OPT_NODE_SIZE)
+                } else {
+                    Err(NodeFreeError::Misaligned)
+                };
+            }
+            
+            if chain == 0 {
+                return Err(NodeFreeError::OutOfRange);
+            }
+            
+            let header = unsafe { &*(chain as *const ChainHeader) };
+            first_idx += count;
+            base = chain + CHAIN_OFFSET;
+            count = header.max_count;
+            chain = header.next;
+        }
     }
 }
 
-
 // Unit Tests **************************************************************************************
 
 /// The main test function which will call all the unit tests. This code will only be compiled
 /// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
 #[cfg(feature = "unit-test")]
 pub mod test {
-    use crate::mem::dyn_alloc::heap_node_alloc::HeapNodeAlloc;
+    use crate::mem::dyn_alloc::heap_node_alloc::{HeapNodeAlloc, NodeFreeError};
     use crate::mem::dyn_alloc::heap_node::HeapNode;
     use crate::mem::region::Region;
+    use crate::mem::vmm::PAGE_SIZE;
+
+    /// The next page which is given to the chained allocator, and the end of it's pages.
+    static mut NEXT_PAGE: usize = 0;
+    static mut PAGES_END: usize = 0;
 
     /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
     /// sub module. 
     pub fn run() {
+        test_reuse();
+        test_chained();
+        test_free_errors();
+        test_canaries();
+    }
+    
+    /// Overwrite the canary of a node, and make sure it's found from any range which includes
+    /// it (and the free slots are skipped).
+    fn test_canaries() {
+        const NODES: usize = 4;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(NODES * super::OPT_NODE_SIZE);
+            let reg = Region::new_sized(scratch.addr, NODES * super::OPT_NODE_SIZE);
+            let mut alloc = HeapNodeAlloc::new(&reg, None);
+            let nodes = [alloc.alloc().unwrap(), alloc.alloc().unwrap(), alloc.alloc().unwrap()];
+            assert!((*nodes[0]).canary_ok());
+            assert_eq!(alloc.num_slots(), 3);
+            assert_eq!(alloc.check_canaries(0, 2), Ok(Some(2)));
+            assert_eq!(alloc.check_canaries(2, 2), Ok(None));
+            
+            (*nodes[2]).canary = 0;
+            assert_eq!(alloc.check_canaries(0, 2), Ok(Some(2)));
+            assert_eq!(alloc.check_canaries(1, 2), Err(2));
+            assert_eq!(alloc.check_canaries(0, usize::MAX), Err(2));
+            
+            // The free slots are never checked (their memory is reused).
+            (*nodes[2]).canary = super::NODE_CANARY;
+            alloc.free(nodes[1]).unwrap();
+            assert_eq!(alloc.check_canaries(0, usize::MAX), Ok(None));
+            
+            alloc.free(nodes[0]).unwrap();
+            alloc.free(nodes[2]).unwrap();
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Make sure the pointer right after the last slot is out of range, and a node can't be freed
+    /// twice (in the middle, or at the end). The slots are checked with get as well.
+    fn test_free_errors() {
+        const NODES: usize = 3;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(NODES * super::OPT_NODE_SIZE);
+            let reg = Region::new_sized(scratch.addr, NODES * super::OPT_NODE_SIZE);
+            let mut alloc = HeapNodeAlloc::new(&reg, None);
+            let nodes = [alloc.alloc().unwrap(), alloc.alloc().unwrap(), alloc.alloc().unwrap()];
+            
+            let past_end = reg.end_addr() as *mut HeapNode;
+            assert_eq!(alloc.free(past_end), Err(NodeFreeError::OutOfRange));
+            assert_eq!(alloc.free((reg.addr - super::OPT_NODE_SIZE) as *mut HeapNode), 
+                Err(NodeFreeError::OutOfRange));
+            assert_eq!(alloc.free((nodes[1] as usize + 8) as *mut HeapNode), 
+                Err(NodeFreeError::Misaligned));
+            
+            // A node which points to another slot is corrupted.
+            (*nodes[1]).list_idx = 2;
+            assert_eq!(alloc.free(nodes[1]), Err(NodeFreeError::IndexMismatch));
+            (*nodes[1]).list_idx = 1;
+            
+            assert_eq!(alloc.free(nodes[1]), Ok(()));
+            assert_eq!(alloc.free(nodes[1]), Err(NodeFreeError::AlreadyFree));
+            assert!(alloc.get(1).unwrap().is_none());
+            assert_eq!(alloc.free(nodes[2]), Ok(()));
+            assert_eq!(alloc.free(nodes[2]), Err(NodeFreeError::AlreadyFree));
+            assert!(alloc.get(2).is_none());
+            assert!(alloc.get(0).unwrap().is_some());
+            
+            assert_eq!(alloc.free(nodes[0]), Ok(()));
+            assert_eq!(alloc.len(), 0);
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Allocate and free a few nodes, and make sure the freed slots are reused.
+    fn test_reuse() {
         unsafe {
-            // Create a region at the end of the kernel.
+            // Get a private scratch region (so the kernel heap's metadata is never touched).
             const SIZE: usize = core::mem::size_of::<HeapNode>() * 3;
-            let start_addr = crate::mem::map::KERNEL_END_ADDR;
+            let scratch = crate::mem::test::scratch(SIZE);
+            let start_addr = scratch.addr;
             let reg = Region::new_sized(start_addr, SIZE);
         
-            // Save the previously written data at address to restore it later.
-            let prev_data: [u8; SIZE] = [0; SIZE];
-            crate::olibc::memcpy::memcpy(prev_data.as_ptr() as *mut u8, start_addr as *const u8, SIZE);
-            
             // First create an allocator.
-            let mut alloc = HeapNodeAlloc::new(&reg);
+            let mut alloc = HeapNodeAlloc::new(&reg, None);
             
             // Allocate some nodes and check if their addresses are correct.
             let node_1 = alloc.alloc().expect("Could not allocate node 1.");
@@ -212,8 +475,86 @@ pub mod test {
             // Make sure the table length is correct.
             assert_eq!(alloc.len(), 0);
             
-            // Restore the data from the backup.
-            crate::olibc::memcpy::memcpy(start_addr as *mut u8, prev_data.as_ptr(), SIZE);
+            // Give the scratch region back.
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// A grow function which hands out the pages of a scratch region (one at a time).
+    ///
+    /// # Returns
+    /// Ok(page_region), or Err if all the pages were given out.
+    unsafe fn grow_scratch() -> Result<Region, ()> {
+        if NEXT_PAGE >= PAGES_END {
+            return Err(());
+        }
+        
+        let page = Region::new_sized(NEXT_PAGE, PAGE_SIZE);
+        NEXT_PAGE += PAGE_SIZE;
+        Ok(page)
+    }
+    
+    /// Allocate enough nodes to spill into a chained region, make sure it stops when the grow
+    /// function runs out, and free them all (from every region).
+    fn test_chained() {
+        const FIRST_NODES: usize = 3;
+        const MAX_NODES: usize = 256;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(FIRST_NODES * super::OPT_NODE_SIZE);
+            let pages = crate::mem::test::scratch(PAGE_SIZE * 2);
+            NEXT_PAGE = pages.addr;
+            PAGES_END = pages.end_addr();
+            
+            // Without a grow function, it's full after the first region.
+            let reg = Region::new_sized(scratch.addr, FIRST_NODES * super::OPT_NODE_SIZE);
+            let mut fixed = HeapNodeAlloc::new(&reg, None);
+            let mut nodes: [*mut HeapNode; MAX_NODES] = [core::ptr::null_mut(); MAX_NODES];
+            for idx in 0..FIRST_NODES {
+                nodes[idx] = fixed.alloc().expect("Could not allocate a node.");
+            }
+            assert!(fixed.alloc().is_err());
+            for idx in 0..FIRST_NODES {
+                fixed.free(nodes[idx]).expect("Could not free a node.");
+            }
+            
+            // With one, it spills into the pages (until they run out).
+            let mut alloc = HeapNodeAlloc::new(&reg, Some(grow_scratch));
+            let mut count: usize = 0;
+            while count < MAX_NODES {
+                match alloc.alloc() {
+                    Ok(node) => nodes[count] = node,
+                    Err(()) => break,
+                }
+                count += 1;
+            }
+            
+            let per_page = (PAGE_SIZE - super::CHAIN_OFFSET) / super::OPT_NODE_SIZE;
+            assert!(count < MAX_NODES);
+            assert_eq!(count, FIRST_NODES + per_page * 2);
+            assert_eq!(alloc.num_regions(), 3);
+            assert!(pages.includes(nodes[FIRST_NODES] as usize));
+            assert!(pages.includes(nodes[count - 1] as usize));
+            
+            // The pointers which are not nodes are rejected (the header, and the middle of a node).
+            assert!(alloc.free(pages.addr as *mut HeapNode).is_err());
+            assert!(alloc.free((nodes[FIRST_NODES] as usize + 1) as *mut HeapNode).is_err());
+            
+            // Free every other node first (so the slots in the middle are reused), then the rest.
+            for idx in (0..count).step_by(2) {
+                alloc.free(nodes[idx]).expect("Could not free a node.");
+            }
+            assert_eq!(alloc.alloc().expect("Could not reuse a node."), nodes[0]);
+            alloc.free(nodes[0]).expect("Could not free a node.");
+            assert!(alloc.free(nodes[2]).is_err());
+            for idx in (1..count).step_by(2) {
+                alloc.free(nodes[idx]).expect("Could not free a node.");
+            }
+            
+            assert_eq!(alloc.len(), 0);
+            
+            crate::mem::test::free_scratch(&pages);
+            crate::mem::test::free_scratch(&scratch);
         }
     }
 }
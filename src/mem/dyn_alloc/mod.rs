@@ -1,5 +1,20 @@
 //! A sub-module which implements a dynamic memory allocator for the kernel. It uses linked lists 
 //! to manage the a usable memory region. It keeps a list of free and used memory regions to do so.
+//! The small allocations from the rust types are served by a slab layer (see slab), which carves
+//! pages from the heap into smaller objects.
+//!
+//! The heap can be used from the interrupt handlers. It's critical sections restore the interrupts
+//! to their previous state (instead of enabling them), and the interrupted code always holds the
+//! locks with the interrupts disabled. The only way a handler finds the heap locked is if it was
+//! raised in a critical section (such as an exception, or a software interrupt). In that case, the
+//! rust allocations are served from an emergency pool (see emergency), and the frees are deferred
+//! until the heap is used outside of the handler. The kmalloc functions always wait for the lock.
+//!
+//! The hardware interrupt handlers should not use the heap at all (a walk of the lists adds to the
+//! time their line is masked, and mapping the pages can fault). The allocations and the frees made
+//! in them are counted, and they panic with the caller's location with the heap-debug feature.
+//! They can use kmalloc_atomic instead, which hands out zeroed pages from a small cache (see
+//! atomic) without taking the heap's lock, and kfree_deferred to free the heap memory.
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Feb 2021
@@ -9,67 +24,416 @@
 mod heap_node;
 mod heap_list;
 mod heap_node_alloc;
+mod slab;
+pub mod emergency;
+pub mod atomic;
 
 extern crate alloc;
 
 use crate::mem::region::Region;
+use crate::mem::addr::{PhysAddr, VirtAddr};
 use heap_list::HeapList;
 use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
 use crate::proc::mutex::Mutex;
+use crate::olibc::bounded::BoundedVec;
+
+/// The number of fragmentation samples which are kept.
+pub const FRAG_HISTORY_SIZE: usize = 64;
+
+/// The time between the fragmentation samples (in milliseconds).
+pub const FRAG_SAMPLE_MS: usize = 5000;
+
+/// The fragmentation ratios which were sampled (in permille, the oldest first).
+static mut FRAG_HISTORY: BoundedVec<u16, FRAG_HISTORY_SIZE> = BoundedVec::new();
+
+/// The most frees which can be deferred (made in a handler while the heap is locked).
+pub const MAX_DEFERRED: usize = 32;
+
+/// The number of times the heap was used in a hardware interrupt handler.
+static mut ISR_USES: usize = 0;
+
+/// True if a use of the heap in an interrupt handler was already reported (only the first one 
+/// panics, so the panic handler can still use the heap).
+static mut ISR_REPORTED: bool = false;
+
+/// The location of the code which first used the heap in an interrupt handler.
+static mut ISR_CALLER: Option<&'static core::panic::Location<'static>> = None;
+
+/// The PID of a process which acts as if it's running in an interrupt handler (for the tests).
+#[cfg(feature = "unit-test")]
+static mut FAKE_ISR_PID: Option<usize> = None;
+
+/// The most regions which can make up the arena of a heap (including the ones added after boot).
+pub const MAX_HEAP_REGIONS: usize = 16;
+
+/// The most memory which is reserved (at the end of the kernel heap's arena) for the metadata 
+/// regions which are chained once the metadata region is full.
+pub const MAX_META_SPILL_SIZE: usize = 0x400000;
+
+/// The reserved range for the chained metadata regions, and the next page which is given out.
+static mut META_SPILL_START: usize = 0;
+static mut META_SPILL_NEXT: usize = 0;
+static mut META_SPILL_END: usize = 0;
 
 /// The static global allocator which will be used for kernel memory allocations. This is declared
 /// global allocator so we can use the rust types. For more information, please look at:
 /// https://doc.rust-lang.org/core/alloc/trait.GlobalAlloc.html
 #[global_allocator]
-static mut HEAP_ALLOC: HeapAlloc = HeapAlloc::new();
+static mut HEAP_ALLOC: GlobalHeap = GlobalHeap::new(HeapAlloc::new());
+
+/// A structure which holds the functions which a heap uses to map and unmap it's memory (and to
+/// get more memory for it's metadata). It allows creating heaps which don't touch the real page 
+/// tables (for example, in the unit tests).
+#[derive(Copy, Clone)]
+pub struct HeapMapper {
+    pub map: unsafe fn(VirtAddr, usize, bool, bool, bool) -> Result<(), ()>, // Like vmm::map_range.
+    pub unmap: unsafe fn(VirtAddr, usize) -> Result<(), ()>,                 // Like vmm::unmap_range.
+    pub grow_meta: unsafe fn() -> Result<Region, ()>,                        // A mapped page.
+    pub translate: fn(VirtAddr) -> Result<PhysAddr, ()>,                     // Like virt_to_phys.
+}
+
+/// The mapper used by the kernel heap (which uses the virtual memory manager). The lazy regions
+/// might have pages which were never mapped, so those are skipped when unmapping.
+pub const VMM_MAPPER: HeapMapper = HeapMapper {
+    map: crate::mem::vmm::map_range,
+    unmap: crate::mem::vmm::unmap_present_range,
+    grow_meta: grow_metadata,
+    translate: crate::mem::vmm::virt_to_phys,
+};
+
+/// An enum which represents the reason a pointer could not be freed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeallocError {
+    NotAllocated,               // It's not in any allocation (a double free, or a wild pointer).
+    Unaligned,                  // It's inside an allocation, but it's not it's start.
+}
+
+/// An enum which represents the reason a region could not be added to a heap.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AddRegionError {
+    NotInitialized,             // The heap was not initialized yet.
+    Unaligned,                  // It's empty, or it's not page aligned.
+    Overlap,                    // It overlaps the heap (or the memory reserved for it).
+    TooMany,                    // The heap already has MAX_HEAP_REGIONS regions.
+}
+
+/// An enum which represents how a free region is chosen for an allocation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FitStrategy {
+    FirstFit,                   // The first region which fits (the fastest, it stops early).
+    BestFit,                    // The smallest region which fits (it keeps the large blocks).
+}
+
+impl FitStrategy {
+    /// A function which parses a strategy from it's name (as passed on the command line).
+    ///
+    /// # Parameters
+    /// `name` : The name of the strategy ("first" or "best").
+    ///
+    /// # Returns
+    /// Some(strategy) if the name is valid, None otherwise.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "first" => Some(FitStrategy::FirstFit),
+            "best" => Some(FitStrategy::BestFit),
+            _ => None,
+        }
+    }
+}
+
+/// An enum which represents the owner of an allocation, so the used memory (and the leaks) can be
+/// attributed. The allocations are untagged unless they're tagged with set_tag.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AllocTag {
+    Unknown,                        // Not tagged (such as the rust types).
+    Slab,                           // The pages which are carved into small objects.
+    Pcb,                            // The process control blocks.
+    Stack,                          // The process stacks.
+    Context,                        // The saved contexts of the processes.
+    Args,                           // The arguments which are passed to the programs.
+}
+
+/// The number of allocation tags.
+pub const NUM_TAGS: usize = 6;
+
+impl AllocTag {
+    /// Every tag (in the order of their values).
+    pub const ALL: [AllocTag; NUM_TAGS] = [AllocTag::Unknown, AllocTag::Slab, AllocTag::Pcb,
+        AllocTag::Stack, AllocTag::Context, AllocTag::Args];
+    
+    /// A getter for the name of the tag (for printing).
+    ///
+    /// # Returns
+    /// The name of the tag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AllocTag::Unknown => "unknown",
+            AllocTag::Slab => "slab",
+            AllocTag::Pcb => "pcb",
+            AllocTag::Stack => "stack",
+            AllocTag::Context => "context",
+            AllocTag::Args => "args",
+        }
+    }
+}
+
+impl Default for AllocTag {
+    fn default() -> Self {
+        AllocTag::Unknown
+    }
+}
+
+/// A structure which holds the memory which is used by the allocations of a tag.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TagUsage {
+    pub tag: AllocTag,              // The owner of the allocations.
+    pub allocs: usize,              // The number of allocations.
+    pub bytes: usize,               // The bytes in their regions (rounded up to pages).
+}
+
+/// The statistics of a heap (the free block figures show how fragmented it is).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeapStats {
+    pub total_bytes: usize,         // The size of the heap arena.
+    pub num_allocs: usize,          // The number of allocations which were not freed yet.
+    pub used_bytes: usize,          // The bytes in the used regions (rounded up to pages).
+    pub free_bytes: usize,          // The bytes in the free regions.
+    pub free_blocks: usize,         // The number of free regions.
+    pub max_free: usize,            // The size of the largest free region.
+    pub failed_allocs: usize,       // The number of allocations which did not fit.
+    pub slab_pages: usize,          // The number of pages carved into small objects.
+    pub slab_objects: usize,        // The number of small objects which were not freed yet.
+    pub resident_bytes: usize,      // The bytes in the used regions which are backed by frames.
+}
 
-/// The mutex for the heap allocator to keep allocations memory safe.
-static mut HEAP_ALLOC_MUTEX: Mutex = Mutex::new();
+impl HeapStats {
+    /// A method which calculates the mean size of the free regions.
+    ///
+    /// # Returns
+    /// The mean size in bytes (0 if there are no free regions).
+    pub fn mean_free(&self) -> usize {
+        if self.free_blocks == 0 { 0 } else { self.free_bytes / self.free_blocks }
+    }
+    
+    /// A method which calculates the fragmentation ratio (1 - largest_free / total_free). It's 0
+    /// when all the free memory is in one region, and it approaches 1 as it's split up.
+    ///
+    /// # Returns
+    /// The ratio in permille (0 to 1000).
+    pub fn fragmentation_permille(&self) -> usize {
+        if self.free_bytes == 0 { 0 } else { 1000 - self.max_free * 1000 / self.free_bytes }
+    }
+}
+
+/// The figures which were collected while checking the integrity of a heap.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeapCheckReport {
+    pub free_nodes: usize,          // The number of nodes in the free list.
+    pub used_nodes: usize,          // The number of nodes in the used list.
+    pub free_bytes: usize,          // The bytes in the free regions.
+    pub used_bytes: usize,          // The bytes in the used regions.
+}
+
+/// An enum which represents the first violation which was found while checking a heap. The lists
+/// are named by the first field ("free" or "used").
+#[derive(Copy, Clone, Debug)]
+pub enum HeapCorruption {
+    EmptyRegion(&'static str, Region),          // A node has a region without any bytes.
+    Unordered(&'static str, Region, Region),    // Out of order or overlapping (or a cycle).
+    Unmerged(Region, Region),                   // Two continuous free regions were not merged.
+    Overlap(Region, Region),                    // A free region (first) overlaps a used one.
+    CountMismatch(&'static str, usize, usize),  // The nodes reached, and the nodes allocated.
+    Canary(&'static str, usize),                // The canary of the node in a slot was overwritten.
+}
 
 /// A structure which represents the heap allocator for oxid os. It utilizes two linked lists to 
 /// hold the blocks and manage them.
-struct HeapAlloc {
+pub struct HeapAlloc {
     free_list: Option<heap_list::HeapList>,         // List of all free regions (merged).
     used_list: Option<heap_list::HeapList>,         // List of all used regions.
     num_allocs: usize,                              // Keep the number of allocations.
+    failed_allocs: usize,                           // The allocations which did not fit.
+    total_bytes: usize,                             // The size of the allocation regions.
+    regions: BoundedVec<Region, MAX_HEAP_REGIONS>,  // The regions which make up the arena.
+    mapper: HeapMapper,                             // To map and unmap the allocated memory.
+    slab: slab::SlabCache,                          // For the small allocations.
+    strategy: FitStrategy,                          // How the free region is chosen.
+    deferred: BoundedVec<usize, MAX_DEFERRED>,      // The frees made in a handler while locked.
+    mutex: Mutex,                                   // To keep allocations memory safe.
 }
 
 impl HeapAlloc {
     /// A constructor which initializes an empty HeapAllocator (the lists aren't initialized).
     pub const fn new() -> Self {
+        HeapAlloc::new_with_mapper(VMM_MAPPER)
+    }
+    
+    /// A constructor which initializes an empty HeapAllocator which uses the given mapper instead
+    /// of the virtual memory manager (the lists aren't initialized).
+    ///
+    /// # Parameters
+    /// `mapper` : The functions which are used to map and unmap the allocated memory.
+    pub const fn new_with_mapper(mapper: HeapMapper) -> Self {
         // Set both lists to None and return.
         HeapAlloc {
             free_list: None,
             used_list: None,
             num_allocs: 0,
+            failed_allocs: 0,
+            total_bytes: 0,
+            regions: BoundedVec::new(),
+            mapper,
+            slab: slab::SlabCache::new(),
+            strategy: FitStrategy::FirstFit,
+            deferred: BoundedVec::new(),
+            mutex: Mutex::new(),
         }
     }
+    
+    /// A simple getter for the strategy which is used to choose the free regions.
+    ///
+    /// # Returns
+    /// The current fit strategy.
+    pub fn strategy(&self) -> FitStrategy {
+        self.strategy
+    }
+    
+    /// A method which changes the strategy which is used to choose the free regions. It only 
+    /// affects the allocations after it, so it can be changed at any time.
+    ///
+    /// # Parameters
+    /// `strategy` : The new fit strategy.
+    pub fn set_strategy(&mut self, strategy: FitStrategy) {
+        let were_enabled = self.mutex.lock_irqsave();
+        self.strategy = strategy;
+        self.mutex.unlock_irqrestore(were_enabled);
+    }
+    
+    /// A method which checks if the heap can't be locked without deadlocking. It's the case when an
+    /// interrupt handler is running, and either lock is held (by the code which it interrupted).
+    ///
+    /// # Returns
+    /// True if the heap should not be locked, False otherwise.
+    fn locked_in_handler(&self) -> bool {
+        crate::arch::interrupts::handlers::int_depth() > 0 
+            && (self.mutex.is_locked() || self.slab.is_locked())
+    }
+    
+    /// A method which frees the pointers which were freed by the handlers while the heap was 
+    /// locked, or by kfree_deferred (it should be called while the heap is not locked). They're
+    /// left for later in the hardware interrupt handlers.
+    unsafe fn free_deferred(&mut self) {
+        if crate::arch::interrupts::handlers::in_irq() {
+            return;
+        }
+        
+        while !self.deferred.is_empty() {
+            let were_enabled = crate::arch::interrupts::save_and_disable();
+            let ptr = self.deferred.pop();
+            crate::arch::interrupts::restore(were_enabled);
+            
+            if let Some(ptr) = ptr {
+                if let Err(err) = self.internal_dealloc(ptr as *mut u8) {
+                    report_dealloc_err(ptr as *mut u8, err);
+                }
+            }
+        }
+    }
+    
+    /// A simple getter for the number of current allocations.
+    ///
+    /// # Returns
+    /// The number of allocations which were not freed yet.
+    pub fn num_allocs(&self) -> usize {
+        self.num_allocs
+    }
+    
+    /// A simple getter for the number of allocations which failed (the heap was exhausted).
+    ///
+    /// # Returns
+    /// The number of failed allocations.
+    pub fn failed_allocs(&self) -> usize {
+        self.failed_allocs
+    }
 
     /// A method which initializes the default allocator based on a given metadata region and
-    /// allocation region. Keep in mind that in both cases, only the virtual address space 
-    /// will be occupied (frames aren't allocated until needed).
+    /// allocation regions. Keep in mind that in both cases, only the virtual address space 
+    /// will be occupied (frames aren't allocated until needed). The allocation regions don't have
+    /// to be continuous, but they should be page aligned and should not overlap.
     ///
     /// # Parameters
     /// `meta_region` : The region which we're using for the metadata of the heap.
-    /// `alloc_region` : The actual region where memory will be allocated at.
-    pub unsafe fn init(&mut self, meta_region: &Region, alloc_region: &Region) {
-        oxid_log!("Initializing the kernel heap.");
-    
+    /// `alloc_regions` : The actual regions where memory will be allocated at.
+    pub unsafe fn init(&mut self, meta_region: &Region, alloc_regions: &[Region]) {
         // Divide the metadata region to two sections for free and used lists.
         let meta_region_free = Region::new_sized(meta_region.addr , meta_region.size / 2);
         let meta_region_used = 
             Region::new_sized(meta_region_free.end_addr(), meta_region_free.size);
         
-        // Initialize the lists with their corresponding regions.
-        self.free_list = Some(HeapList::new(&meta_region_free));
-        self.used_list = Some(HeapList::new(&meta_region_used));
+        // Initialize the lists with their corresponding regions (both can chain more regions).
+        let grow = Some(self.mapper.grow_meta);
+        self.free_list = Some(HeapList::new(&meta_region_free, grow, "free"));
+        self.used_list = Some(HeapList::new(&meta_region_used, grow, "used"));
         
         // Add all the heap memory to the free list.
-        self.free_list.as_mut().expect("List not initialized.").add(alloc_region, true)
-            .expect("Could not add free region to the free list.");
+        self.total_bytes = 0;
+        self.regions.clear();
+        for alloc_region in alloc_regions.iter() {
+            self.add_region(alloc_region).expect("Could not add a region to the heap.");
+        }
+    }
+    
+    /// A method which adds a region to the arena of an initialized heap (for example, the memory 
+    /// which was found after boot). It's added to the free list, so it's only merged with the free
+    /// regions which are continuous with it.
+    ///
+    /// # Parameters
+    /// `region` : The region which is added (it should be page aligned).
+    ///
+    /// # Returns
+    /// Ok if it was added, or the reason if it was not.
+    pub unsafe fn add_region(&mut self, region: &Region) -> Result<(), AddRegionError> {
+        let page_size = crate::mem::vmm::PAGE_SIZE;
+        if region.is_empty() || region.addr % page_size != 0 || region.size % page_size != 0 {
+            return Err(AddRegionError::Unaligned);
+        }
+        
+        let free_list_uw = match self.free_list.as_mut() {
+            Some(free_list) => free_list,
+            None => return Err(AddRegionError::NotInitialized),
+        };
+        
+        let were_enabled = self.mutex.lock_irqsave();
+        let result = if self.regions.as_slice().iter().any(|other| overlaps(region, other)) {
+            Err(AddRegionError::Overlap)
+        } else if self.regions.try_push(*region).is_err() {
+            Err(AddRegionError::TooMany)
+        } else {
+            self.total_bytes += region.size;
+            free_list_uw.add(region, true).expect("Could not add free region to the free list.");
+            Ok(())
+        };
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        result
     }
     
-    /// A function which checks if a given layout can fit in a region (with needed padding). 
+    /// A method which checks if an address is in the arena of the heap. The regions are never 
+    /// removed, so it doesn't lock the heap (it can be called from the page fault handler).
+    ///
+    /// # Parameters
+    /// `addr` : The address which we're checking.
+    ///
+    /// # Returns
+    /// True if it's in one of the regions, False otherwise.
+    pub fn in_arena(&self, addr: usize) -> bool {
+        self.regions.as_slice().iter().any(|region| region.includes(addr))
+    }
+    
+    /// A function which checks if a given layout can fit in a region (with needed padding). The 
+    /// end of the region is exclusive, so a layout which ends exactly at it fits (an exact fit).
     ///
     /// # Parameters
     /// `free_region` : The region which we're checking for allocation.
@@ -82,18 +446,39 @@ impl HeapAlloc {
         // Calculate the aligned start address based on the starting address of free_region.
         let aligned_start = crate::mem::align::align_higher(free_region.addr, layout.align());
         
-        // Check if the aligned start and the the size based on the layout can fit.
-        if aligned_start + layout.size() < free_region.end_addr() {
+        // Check if the aligned start and the the size based on the layout can fit (an empty region
+        // never fits, even an empty layout).
+        let aligned_end = aligned_start.checked_add(layout.size()).ok_or(())?;
+        if !free_region.is_empty() && aligned_end <= free_region.end_addr() {
             Ok(aligned_start - free_region.addr)
         } else {
             Err(())
         }
     }
     
+    /// A function which splits a free region into the allocated part (at it's start), and the part
+    /// after it. The after part is empty if the size fits exactly.
+    ///
+    /// # Parameters
+    /// `free_region` : The free region which the memory is allocated from.
+    /// `size` : The size of the allocation (it should fit in the free region).
+    ///
+    /// # Returns
+    /// A tuple with the allocated region and the region after it.
+    fn split_region(free_region: &Region, size: usize) -> (Region, Region) {
+        let alloc_region = Region::new_sized(free_region.addr, size);
+        let after_region = Region::try_new(alloc_region.end_addr(), free_region.end_addr())
+            .expect("The allocation does not fit in the free region.");
+        
+        (alloc_region, after_region)
+    }
+    
     /// The primary heap allocation code which will allocate a certain amount of memory based on 
     /// the passed layout (size, alignment), and it will map it to the virtual address space using
-    /// the passed permissions. Interally, it will always allocate memory in page_size alignment. 
-    /// However, the returned pointer might have a different alignment based on the layout given).
+    /// the passed permissions (the memory is also zeroed). Interally, it will always allocate 
+    /// memory in page_size alignment (or the layout's alignment if it's larger). The padding 
+    /// before an aligned start is given back to the free list, so the returned pointer is always
+    /// the start of the used region.
     ///
     /// # Parameters
     /// `layout` : The size and alignment that is requested for the returned ptr.
@@ -102,14 +487,102 @@ impl HeapAlloc {
     /// `is_no_exec` : True if not executable, False otherwise.
     ///
     /// # Returns
-    /// The address of the allocated memory.
+    /// The address of the allocated memory, or null if no free region can fit it.
     #[inline]
-    unsafe fn internal_alloc(&mut self, layout: &Layout, is_user: bool
+    #[track_caller]
+    pub unsafe fn internal_alloc(&mut self, layout: &Layout, is_user: bool
         , is_writable: bool, is_no_exec: bool) -> *mut u8 {
+        check_context();
+        let allocated_ptr = self.reserve(layout, false, is_no_exec, false);
+        if !allocated_ptr.is_null() {
+            self.map_zeroed(allocated_ptr, layout, is_user, is_writable, is_no_exec);
+        }
+           
+        allocated_ptr
+    }
+    
+    /// A version of internal_alloc which adds an unmapped guard page right before and right after
+    /// the allocation (they're a part of it's used region, but they're never mapped). An access 
+    /// which runs off either end causes a page fault in a guard page (see find_guard), instead of
+    /// corrupting the neighbouring allocations. The returned pointer is always page aligned.
+    ///
+    /// # Parameters
+    /// `layout` : The size that is requested for the returned ptr (the alignment is a page).
+    /// `is_user` : True if the permissions are user accessible, False otherwise.
+    /// `is_writable` : True if R/W, False if it's read-only.
+    /// `is_no_exec` : True if not executable, False otherwise.
+    ///
+    /// # Returns
+    /// The address of the allocated memory (after the first guard page), or null if no free 
+    /// region can fit it.
+    #[track_caller]
+    pub unsafe fn internal_alloc_guarded(&mut self, layout: &Layout, is_user: bool, 
+        is_writable: bool, is_no_exec: bool) -> *mut u8 {
+        check_context();
+        let layout = Layout::from_size_align_unchecked(layout.size(), crate::mem::vmm::PAGE_SIZE);
+        let allocated_ptr = self.reserve(&layout, false, is_no_exec, true);
+        if !allocated_ptr.is_null() {
+            self.map_zeroed(allocated_ptr, &layout, is_user, is_writable, is_no_exec);
+        }
+        
+        allocated_ptr
+    }
+    
+    /// A helper which maps a reserved allocation with the given permissions, and zeroes it.
+    ///
+    /// # Parameters
+    /// `ptr` : The start of the allocation (returned by reserve).
+    /// `layout` : The size and alignment which was reserved.
+    /// `is_user` : True if the permissions are user accessible, False otherwise.
+    /// `is_writable` : True if R/W, False if it's read-only.
+    /// `is_no_exec` : True if not executable, False otherwise.
+    unsafe fn map_zeroed(&mut self, ptr: *mut u8, layout: &Layout, is_user: bool, 
+        is_writable: bool, is_no_exec: bool) {
+        // Map the memory (in VMM by default) with the correct permissions.
+        let size = crate::mem::align::align_higher(layout.size(), crate::mem::vmm::PAGE_SIZE);
+        (self.mapper.map)(VirtAddr::from_ptr(ptr), size, is_user, is_writable, is_no_exec)
+            .expect("Could not map memory range");
+        
+        // Set the memory to all zeros.
+        crate::olibc::memset::memset(ptr, 0, layout.size());
+    }
+    
+    /// A version of internal_alloc which doesn't map or zero anything. The pages are mapped (as 
+    /// kernel and writable) by the page fault handler when they're first accessed, so a large 
+    /// buffer only takes the frames which are used. The new frames are always scrubbed by the vmm,
+    /// so they still read as zeros.
+    ///
+    /// # Parameters
+    /// `layout` : The size and alignment that is requested for the returned ptr.
+    /// `is_no_exec` : True if not executable, False otherwise.
+    ///
+    /// # Returns
+    /// The address of the allocated memory, or null if no free region can fit it.
+    #[inline]
+    #[track_caller]
+    pub unsafe fn internal_alloc_lazy(&mut self, layout: &Layout, is_no_exec: bool) -> *mut u8 {
+        check_context();
+        self.reserve(layout, true, is_no_exec, false)
+    }
+    
+    /// A helper which takes a region from the free list, and moves it to the used list (without
+    /// mapping it).
+    ///
+    /// # Parameters
+    /// `layout` : The size and alignment that is requested for the returned ptr.
+    /// `lazy` : True if the region is mapped by the page fault handler.
+    /// `is_no_exec` : The permission of the lazy pages.
+    /// `guarded` : True if a guard page is added at both ends of the region.
+    ///
+    /// # Returns
+    /// The start of the usable part of the used region, or null if no free region can fit it.
+    unsafe fn reserve(&mut self, layout: &Layout, lazy: bool, is_no_exec: bool, guarded: bool) 
+        -> *mut u8 {
         // Create a new layout with a page_size aligned size (just to ensure every allocation is 
         // at least one page long to avoid deallocation issues).
+        let guard_size = if guarded { crate::mem::vmm::PAGE_SIZE * 2 } else { 0 };
         let aligned_layout = Layout::from_size_align_unchecked(
-            crate::mem::align::align_higher(layout.size(), crate::mem::vmm::PAGE_SIZE), 
+            crate::mem::align::align_higher(layout.size(), crate::mem::vmm::PAGE_SIZE) + guard_size,
             layout.align());
             
         // Unwrap the lists for future use.
@@ -117,196 +590,2714 @@ impl HeapAlloc {
         let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
         
         // Lock the allocator.
-        HEAP_ALLOC_MUTEX.lock();
+        let were_enabled = self.mutex.lock_irqsave();
+        
+        // Choose the free region (and the offset of the aligned start in it) based on the strategy.
+        // The first fit stops at the first one, and the best fit goes through the whole list for 
+        // the smallest one (the first of them if there are multiple, so the lowest address).
+        let mut fits = free_list_uw.into_iter().filter_map(|node_ptr| 
+            HeapAlloc::can_fit(&(*node_ptr).region, &aligned_layout).ok()
+                .map(|offset| (node_ptr, offset)));
+        let chosen = match self.strategy {
+            FitStrategy::FirstFit => fits.next(),
+            FitStrategy::BestFit => fits.min_by_key(|&(node_ptr, _)| (*node_ptr).region.size),
+        };
         
         // To store the address of allocated memory.
         let mut allocated_ptr: *mut u8 = 0 as *mut u8;
         
-        // Go through the free list.
-        for node_ptr in free_list_uw.into_iter() {
-            // Get the current region.
-            let curr_region = (*node_ptr).region;
+        // If it can fit, do the actual allocation.
+        if let Some((node_ptr, offset)) = chosen {
+            // Remove the free region from the list of free items.
+            let free_region = free_list_uw.remove(node_ptr)
+                .expect("Could not remove region from the HeapList.");
+            
+            // Give the padding before the aligned start back (for the large alignments).
+            let (before_region, aligned_region) = HeapAlloc::split_region(&free_region, offset);
+            if !before_region.is_empty() {
+                free_list_uw.add(&before_region, true)
+                    .expect("Could not add the padding region to the free list.");
+            }
             
-            // Check if it can fit the requested aligned layout based on alignment.
-            match HeapAlloc::can_fit(&curr_region, &aligned_layout) {
+            // Define the regions based on the aligned start address and the free region.
+            let (alloc_region, after_region) = 
+                HeapAlloc::split_region(&aligned_region, aligned_layout.size());
             
-                // If it can fit, do the actual allocation.
-                Ok(offset) => {           
-                    // Remove the free region from the list of free items.
-                    let free_region = free_list_uw.remove(node_ptr)
-                        .expect("Could not remove region from the HeapList.");
-                    
-                    // Define the regions based on the start address and the free region.
-                    let alloc_region = Region::new_sized(free_region.addr, aligned_layout.size());
-                    let after_region = Region::new(alloc_region.end_addr(), free_region.end_addr());
-                    
-                    // Put the after region in the free list if needed.
-                    if after_region.size > 0 {
-                        free_list_uw.add(&after_region, true)
-                            .expect("Could not add the after region to the free list.");
-                    }
-                    
-                    // Put the allocated region into the used list.
-                    used_list_uw.add(&alloc_region, false)
-                        .expect("Could not add the allocated region to the used list.");
-                
-                    // Store the start address of the allocated region as the pointer.
-                    // Add the offset to it to match the layout's alignment.
-                    allocated_ptr = (alloc_region.addr + offset) as *mut u8;
-                    
-                    break;
-                },
-                
-                // If not, don't do anything.
-                Err(()) => (),
+            // Put the after region in the free list if needed (it's empty if exact fit).
+            if !after_region.is_empty() {
+                free_list_uw.add(&after_region, true)
+                    .expect("Could not add the after region to the free list.");
             }
+            
+            // Put the allocated region into the used list.
+            let used_node = used_list_uw.add(&alloc_region, false)
+                .expect("Could not add the allocated region to the used list.");
+            (*used_node).lazy = lazy;
+            (*used_node).no_exec = is_no_exec;
+            (*used_node).guarded = guarded;
+            (*used_node).req_size = layout.size();
+            (*used_node).req_align = layout.align();
+            (*used_node).tag = AllocTag::Unknown;
+        
+            // Store the start address of the allocated region as the pointer (it's already 
+            // aligned based on the layout), or the address after it's guard.
+            allocated_ptr = (*used_node).usable().addr as *mut u8;
+        }
+        
+        // If nothing could fit it, count the failure (nothing is mapped).
+        if allocated_ptr.is_null() {
+            self.failed_allocs += 1;
+            let max_free = free_list_uw.into_iter().map(|node_ptr| (*node_ptr).region.size)
+                .max().unwrap_or(0);
+            self.mutex.unlock_irqrestore(were_enabled);
+            
+            oxid_err!("The heap is exhausted, could not allocate {} bytes (align {}), the largest \
+                free block is {} bytes.", layout.size(), layout.align(), max_free);
+            return core::ptr::null_mut();
         }
         
         self.num_allocs += 1;
         
         // Unlock the mutex since the critical section is over.
-        HEAP_ALLOC_MUTEX.unlock(); 
+        self.mutex.unlock_irqrestore(were_enabled); 
         
-        // Map the memory in VMM with the correct permissions.
-        crate::mem::vmm::map_range(allocated_ptr as usize, aligned_layout.size()
-            , is_user, is_writable, is_no_exec).expect("Could not map memory range");
+        #[cfg(feature = "heap-debug")]
+        self.debug_check();
         
-        // Set the memory to all zeros.
-        crate::olibc::memset::memset(allocated_ptr, 0, layout.size());
-           
         allocated_ptr
     }
     
+    /// A method which allocates a small object from the slab layer. If the class doesn't have any
+    /// free objects, a new page is allocated from the heap and carved into objects (the directory
+    /// page is allocated the same way the first time).
+    ///
+    /// # Parameters
+    /// `layout` : The size and alignment that is requested for the returned ptr.
+    /// `zeroed` : True if the object should be zeroed (they're reused, so they might be dirty).
+    ///
+    /// # Returns
+    /// The address of the allocated object, or null if it's too large for the slabs or a new page
+    /// could not be added.
+    unsafe fn slab_alloc(&mut self, layout: &Layout, zeroed: bool) -> *mut u8 {
+        let class = match slab::class_of(layout) {
+            Some(class) => class,
+            None => return core::ptr::null_mut(),
+        };
+        
+        let object = match self.slab.alloc(class) {
+            Some(object) => object,
+            None => {
+                // The slab pages have the same permissions as the rest of the kernel heap.
+                let page_layout = Layout::from_size_align_unchecked(crate::mem::vmm::PAGE_SIZE, 
+                    crate::mem::vmm::PAGE_SIZE);
+                
+                if !self.slab.has_directory() {
+                    let directory = self.internal_alloc(&page_layout, false, true, false);
+                    if directory.is_null() {
+                        return directory;
+                    }
+                    self.set_tag(directory, AllocTag::Slab);
+                    
+                    // Another allocation might have set it in the meantime.
+                    if self.slab.set_directory(directory).is_err() {
+                        self.internal_dealloc(directory);
+                    }
+                }
+                
+                if self.slab.is_full() {
+                    return core::ptr::null_mut();
+                }
+                
+                let page = self.internal_alloc(&page_layout, false, true, false);
+                if page.is_null() {
+                    return page;
+                }
+                self.set_tag(page, AllocTag::Slab);
+                if self.slab.add_page(page, class).is_err() {
+                    self.internal_dealloc(page);
+                    return core::ptr::null_mut();
+                }
+                
+                match self.slab.alloc(class) {
+                    Some(object) => object,
+                    None => return core::ptr::null_mut(),
+                }
+            }
+        };
+        
+        if zeroed {
+            crate::olibc::memset::memset(object, 0, layout.size());
+        }
+        
+        object
+    }
+    
     /// The primary deallocation method (similar to free in Clib). It finds an allocation, and 
     /// deallocates it from the dynamic memory. Additionally, it will unmap the pages from the 
-    /// page table.
+    /// page table. Nothing is changed if the pointer is not the start of an allocation. The small
+    /// objects are given back to their slab instead.
     ///
     /// # Parameters
     /// `ptr` : The memory address (which we got from alloc), which we're freeing.
+    ///
+    /// # Returns
+    /// Ok if it was freed, NotAllocated if it's not in any allocation (for example, if it was 
+    /// already freed), or Unaligned if it points inside an allocation.
     #[inline]
-    unsafe fn internal_dealloc(&mut self, ptr: *mut u8) {
+    #[track_caller]
+    pub unsafe fn internal_dealloc(&mut self, ptr: *mut u8) -> Result<(), DeallocError> {
+        check_context();
+        if let Some(result) = self.slab.free(ptr) {
+            return result;
+        }
+        
         // Unwrap the lists for future use.
         let free_list_uw = self.free_list.as_mut().expect("Heap alloc free list not valid.");
         let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
         
-        // Align lower the pointer to match the page alignment.
-        let aligned_ptr = crate::mem::align::align_lower(ptr as usize, crate::mem::vmm::PAGE_SIZE);
-        
         // Lock the allocator.
-        HEAP_ALLOC_MUTEX.lock();
+        let were_enabled = self.mutex.lock_irqsave();
+        
+        // If it's not found at all, it was never allocated (or it's already freed).
+        let mut result = Err(DeallocError::NotAllocated);
         
         // Go through the used list.
         for node_ptr in used_list_uw.into_iter() {
-            // Get the current region's address.
-            let curr_addr = (*node_ptr).region.addr;
+            // Get the current region (and the part of it which was returned).
+            let curr_region = (*node_ptr).region;
+            let usable = (*node_ptr).usable();
+            
+            // The allocations are returned at the start of their region (or right after it's 
+            // guard page), so any other address in it was not returned by alloc.
+            if usable.addr != ptr as usize && curr_region.includes(ptr as usize) {
+                result = Err(DeallocError::Unaligned);
+                break;
+            }
             
             // If the adderss is the same as the address pointed to by the ptr.
-            if curr_addr == aligned_ptr {
+            if usable.addr == ptr as usize {
                 // Remove and get the region from the used list.
                 let removed_region = used_list_uw.remove(node_ptr).expect("Count not remove ptr.");
                 
                 // Add it to the free list and merge if needed.
                 free_list_uw.add(&removed_region, true).expect("Could not add ptr to free list.");
                 
-                // Unmap it from the vmm.
-                crate::mem::vmm::unmap_range(removed_region.addr, removed_region.size)
+                // Unmap it (from the vmm by default), the guard pages were never mapped.
+                (self.mapper.unmap)(VirtAddr::from_ptr(usable.addr as *const u8), usable.size)
                     .expect("Could not unmap memory range.");
                 
                 self.num_allocs -= 1;
+                result = Ok(());
                 break;
             }
         }
 
         // Unlock the mutex since the critical section is over.
-        HEAP_ALLOC_MUTEX.unlock();
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        #[cfg(feature = "heap-debug")]
+        self.debug_check();
+        
+        result
     }
-}
-
-// TODO: Add synchronization.
-
-/// A public wrapper for the internal alloc which always sets the alignment to page size, and 
-/// can provide a familiar interface for kernel processes to allocate memory. Compared to the 
-/// global allocator, it also provides a fine-grain control.
-///
-/// # Parameters
-/// `size` : The number of bytes which will be allocated.
-/// `is_user` : True if the permissions are user accessible, False otherwise.
-/// `is_writable` : True if R/W, False if it's read-only.
-/// `is_no_exec` : True if not executable, False otherwise.
-///
-/// # Returns
-/// The address of the allocated memory.
-pub unsafe fn kmalloc(size: usize, is_user: bool, is_writable: bool, 
-    is_no_exec: bool) -> *mut u8 {
-    // Define a new layout, and then call the internal allocator.
-    let layout = Layout::from_size_align_unchecked(size, crate::mem::vmm::PAGE_SIZE);  
-    HEAP_ALLOC.internal_alloc(&layout, is_user, is_writable, is_no_exec)
-}
-
-/// A wrapper for the internal dealloc method. This is only to provide a familiar interface
-/// for kernel developers.
-///
-/// # Parameters
-/// `ptr` : The memory address (which we got from alloc), which we're freeing.
-pub unsafe fn kfree(ptr: *mut u8) {
-    // Call the internal allocator.
-    HEAP_ALLOC.internal_dealloc(ptr)
-}
-
-/// Implement global alloc so we can use rust standard types.
-unsafe impl GlobalAlloc for HeapAlloc {
-    /// The main entry for kernel heap allocation. It uses the standard rust allocation interface, 
-    /// and can allocate memory with a certain size and alignment. This is a wrapper to make
-    /// the internal implementation compatible with the rust's alloc library.
-    ///
-    /// # Parameters
-    /// `layout` : The size and alignment (rust type).
+    
+    /// A method which collects the statistics of the heap. It walks both lists while the heap is 
+    /// locked (without allocating), and translates every used page (to find the resident bytes). 
+    /// It never spins on the lock, and it restores the interrupts to their previous state, so it 
+    /// can be called with the interrupts disabled (for example, from the page fault handler).
     ///
     /// # Returns
-    /// A pointer to the allocated memory region. 
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        // Get a mutable reference to self (odd syntax, not ideal but needed).
-        let mut_self = &mut *(self as *const HeapAlloc as *mut HeapAlloc);
-    
-        // Since this is the kernel heap, set it to kernel mode, writable, and executable.
-        mut_self.internal_alloc(&layout, false, true, false)
+    /// Some(stats), or None if the heap is not initialized or it's locked (by the code which was
+    /// interrupted).
+    pub unsafe fn stats(&mut self) -> Option<HeapStats> {
+        let (free_list, used_list) = match (self.free_list.as_ref(), self.used_list.as_ref()) {
+            (Some(free_list), Some(used_list)) => (free_list, used_list),
+            _ => return None,
+        };
+        
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if !self.mutex.try_lock() {
+            crate::arch::interrupts::restore(were_enabled);
+            return None;
+        }
+        
+        let mut stats = HeapStats::default();
+        stats.total_bytes = self.total_bytes;
+        stats.failed_allocs = self.failed_allocs;
+        stats.slab_pages = self.slab.num_pages();
+        stats.slab_objects = self.slab.num_objects();
+        for node_ptr in free_list.into_iter() {
+            let size = (*node_ptr).region.size;
+            stats.free_bytes += size;
+            stats.free_blocks += 1;
+            stats.max_free = core::cmp::max(stats.max_free, size);
+        }
+        for node_ptr in used_list.into_iter() {
+            stats.used_bytes += (*node_ptr).region.size;
+            stats.resident_bytes += self.resident_in(&(*node_ptr).usable());
+        }
+        stats.num_allocs = self.num_allocs;
+        
+        self.mutex.release();
+        crate::arch::interrupts::restore(were_enabled);
+        
+        Some(stats)
     }
     
-    /// The main deallocation method which is similar to free in Clib. It uses the standard rust 
-    /// deallocation interface. This is a wrapper to make the internal implementation compatible
-    /// with the rust's alloc library.
+    /// A method which walks both lists while the heap is locked, and checks that they are 
+    /// consistent. The regions should be sorted with no empty ones, the free regions should be 
+    /// merged and never overlap the used ones, and every allocated node should be in it's list. A 
+    /// cycle in a list always goes back to a lower address, so it's reported (instead of hanging).
+    /// The first violation is logged.
+    ///
+    /// # Returns
+    /// Ok(report) if the heap is consistent (or not initialized), Err(corruption) with the first
+    /// violation otherwise.
+    pub unsafe fn check_integrity(&mut self) -> Result<HeapCheckReport, HeapCorruption> {
+        let (free_list, used_list) = match (self.free_list.as_ref(), self.used_list.as_ref()) {
+            (Some(free_list), Some(used_list)) => (free_list, used_list),
+            _ => return Ok(HeapCheckReport::default()),
+        };
+        
+        let were_enabled = self.mutex.lock_irqsave();
+        let result = HeapAlloc::check_lists(free_list, used_list);
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        if let Err(corruption) = result {
+            oxid_err!("The heap is corrupted: {:?}", corruption);
+        }
+        
+        result
+    }
+    
+    /// A method which checks the canaries of the nodes in a range of slots. The slots of the free
+    /// list come first, followed by the ones of the used list. It only holds the lock for the
+    /// range, so the whole heap can be checked a part at a time (the nodes can move between the 
+    /// parts, so it's only a sample if the heap is busy).
     ///
     /// # Parameters
-    /// `ptr` : The pointer for the location we're deallocaing.
-    /// `_layout` : The size and alignment of the memory we're deallocating.
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        // Get a mutable reference to self (odd syntax, not ideal but needed).
-        let mut_self = &mut *(self as *const HeapAlloc as *mut HeapAlloc);
+    /// `start` : The index of the first slot which is checked.
+    /// `count` : The maximum number of slots which are checked.
+    ///
+    /// # Returns
+    /// Ok(Some(next)) with the slot to continue from, Ok(None) if the last slot was checked, or 
+    /// Err(Canary) with the first node whose canary was overwritten.
+    pub unsafe fn check_canaries(&mut self, start: usize, count: usize) 
+        -> Result<Option<usize>, HeapCorruption> {
+        let (free_list, used_list) = match (self.free_list.as_ref(), self.used_list.as_ref()) {
+            (Some(free_list), Some(used_list)) => (free_list, used_list),
+            _ => return Ok(None),
+        };
+        
+        let were_enabled = self.mutex.lock_irqsave();
+        let free_slots = free_list.num_slots();
+        let result = if start < free_slots {
+            free_list.check_canaries(start, count)
+                .map(|next| Some(next.unwrap_or(free_slots)))
+                .map_err(|idx| HeapCorruption::Canary("free", idx))
+        } else {
+            used_list.check_canaries(start - free_slots, count)
+                .map(|next| next.map(|next| next + free_slots))
+                .map_err(|idx| HeapCorruption::Canary("used", idx))
+        };
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        result
+    }
+    
+    /// A helper which checks both lists (it should be called while locked).
+    ///
+    /// # Parameters
+    /// `free_list` : The list of the free regions.
+    /// `used_list` : The list of the used regions.
+    ///
+    /// # Returns
+    /// Ok(report) if they are consistent, Err(corruption) with the first violation otherwise.
+    unsafe fn check_lists(free_list: &HeapList, used_list: &HeapList) 
+        -> Result<HeapCheckReport, HeapCorruption> {
+        let (free_nodes, free_bytes) = HeapAlloc::check_list(free_list, "free", true)?;
+        let (used_nodes, used_bytes) = HeapAlloc::check_list(used_list, "used", false)?;
+        
+        // Both are sorted now, so they can be walked together.
+        let mut used_iter = used_list.into_iter().peekable();
+        for free_node in free_list.into_iter() {
+            let free = (*free_node).region;
+            while let Some(used_node) = used_iter.peek() {
+                let used = (**used_node).region;
+                if used.end_addr() <= free.addr {
+                    used_iter.next();
+                    continue;
+                }
+                
+                if used.addr < free.end_addr() {
+                    return Err(HeapCorruption::Overlap(free, used));
+                }
+                break;
+            }
+        }
+        
+        Ok(HeapCheckReport { free_nodes, used_nodes, free_bytes, used_bytes })
+    }
+    
+    /// A helper which checks the regions of a single list (it should be called while locked).
+    ///
+    /// # Parameters
+    /// `list` : The list to go through.
+    /// `name` : The name of the list (for the report).
+    /// `merged` : True if the continuous regions should have been merged.
+    ///
+    /// # Returns
+    /// Ok((num_nodes, num_bytes)) if it's consistent, Err(corruption) otherwise.
+    unsafe fn check_list(list: &HeapList, name: &'static str, merged: bool) 
+        -> Result<(usize, usize), HeapCorruption> {
+        let mut num_nodes: usize = 0;
+        let mut num_bytes: usize = 0;
+        let mut prev: Option<Region> = None;
+        
+        for node_ptr in list.into_iter() {
+            let region = (*node_ptr).region;
+            if !(*node_ptr).canary_ok() {
+                return Err(HeapCorruption::Canary(name, (*node_ptr).list_idx));
+            }
+            if region.is_empty() {
+                return Err(HeapCorruption::EmptyRegion(name, region));
+            }
+            
+            if let Some(prev) = prev {
+                if region.addr < prev.end_addr() {
+                    return Err(HeapCorruption::Unordered(name, prev, region));
+                }
+                if merged && region.addr == prev.end_addr() {
+                    return Err(HeapCorruption::Unmerged(prev, region));
+                }
+            }
+            
+            // A node which points to another list could lead anywhere, so stop once it's too long.
+            num_nodes += 1;
+            num_bytes += region.size;
+            if num_nodes > list.num_nodes() {
+                break;
+            }
+            prev = Some(region);
+        }
+        
+        if num_nodes != list.num_nodes() {
+            return Err(HeapCorruption::CountMismatch(name, num_nodes, list.num_nodes()));
+        }
+        
+        Ok((num_nodes, num_bytes))
+    }
+    
+    /// A helper which checks the heap after every allocation and free (with the heap-debug 
+    /// feature). It panics on the first corruption, so it's caught right after the operation 
+    /// which caused it.
+    #[cfg(feature = "heap-debug")]
+    unsafe fn debug_check(&mut self) {
+        if let Err(corruption) = self.check_integrity() {
+            panic!("The heap was corrupted: {:?}", corruption);
+        }
+    }
+    
+    /// A method which checks if an address is in a lazy region (so the page fault handler should
+    /// map it). Like stats, it never spins on the lock.
+    ///
+    /// # Parameters
+    /// `addr` : The address which caused the fault.
+    ///
+    /// # Returns
+    /// Some(is_no_exec) if it's in a lazy region, None if it's not (or the heap is locked).
+    pub unsafe fn find_lazy(&mut self, addr: usize) -> Option<bool> {
+        let used_list = self.used_list.as_ref()?;
+        
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if !self.mutex.try_lock() {
+            crate::arch::interrupts::restore(were_enabled);
+            return None;
+        }
+        
+        let found = used_list.into_iter()
+            .find(|node_ptr| (**node_ptr).lazy && (**node_ptr).region.includes(addr))
+            .map(|node_ptr| (*node_ptr).no_exec);
+        
+        self.mutex.release();
+        crate::arch::interrupts::restore(were_enabled);
+        
+        found
+    }
+    
+    /// A method which checks if an address is in a guard page of a used region (so it's an access
+    /// which ran off the end of an allocation). Like stats, it never spins on the lock.
+    ///
+    /// # Parameters
+    /// `addr` : The address which caused the fault.
+    ///
+    /// # Returns
+    /// Some(usable_region) of the allocation which owns the guard page, None if it's not in a 
+    /// guard page (or the heap is locked).
+    pub unsafe fn find_guard(&mut self, addr: usize) -> Option<Region> {
+        let used_list = self.used_list.as_ref()?;
+        
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if !self.mutex.try_lock() {
+            crate::arch::interrupts::restore(were_enabled);
+            return None;
+        }
+        
+        let found = used_list.into_iter()
+            .find(|node_ptr| (**node_ptr).guarded && (**node_ptr).region.includes(addr) 
+                && !(**node_ptr).usable().includes(addr))
+            .map(|node_ptr| (*node_ptr).usable());
+        
+        self.mutex.release();
+        crate::arch::interrupts::restore(were_enabled);
+        
+        found
+    }
+    
+    /// A method which finds the used region which holds a given allocation. Since the sizes are 
+    /// rounded up to pages, the region can be larger than the size which was requested. The guard
+    /// pages are not included.
+    ///
+    /// # Parameters
+    /// `ptr` : The memory address (which we got from alloc).
+    ///
+    /// # Returns
+    /// Some(region) if it's a current allocation, None otherwise.
+    pub unsafe fn find_used(&mut self, ptr: *mut u8) -> Option<Region> {
+        let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
+        let aligned_ptr = crate::mem::align::align_lower(ptr as usize, crate::mem::vmm::PAGE_SIZE);
+        
+        // Go through the used list while it's locked.
+        let were_enabled = self.mutex.lock_irqsave();
+        let found = used_list_uw.into_iter().map(|node_ptr| (*node_ptr).usable())
+            .find(|region| region.addr == aligned_ptr);
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        found
+    }
+    
+    /// A method which finds how much of an allocation is backed by frames. The pages are mapped 
+    /// when they're first touched, so a large allocation might only have a few of them.
+    ///
+    /// # Parameters
+    /// `ptr` : The memory address (which we got from alloc).
+    ///
+    /// # Returns
+    /// Ok(bytes) which are resident (a multiple of the page size), or Err if it's not the start of
+    /// a current allocation.
+    pub unsafe fn resident_bytes(&mut self, ptr: *const u8) -> Result<usize, ()> {
+        let used_list_uw = self.used_list.as_ref().ok_or(())?;
+        
+        // Go through the used list while it's locked.
+        let were_enabled = self.mutex.lock_irqsave();
+        let resident = used_list_uw.into_iter().map(|node_ptr| (*node_ptr).usable())
+            .find(|region| region.addr == ptr as usize)
+            .map(|region| self.resident_in(&region));
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        resident.ok_or(())
+    }
+    
+    /// A method which finds how much of all the allocations (including the slab pages) is backed
+    /// by frames.
+    ///
+    /// # Returns
+    /// The bytes which are resident (0 if the heap is not initialized).
+    pub unsafe fn resident_total(&mut self) -> usize {
+        let used_list_uw = match self.used_list.as_ref() {
+            Some(list) => list,
+            None => return 0,
+        };
+        
+        let were_enabled = self.mutex.lock_irqsave();
+        let resident = used_list_uw.into_iter()
+            .map(|node_ptr| self.resident_in(&(*node_ptr).usable())).sum();
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        resident
+    }
+    
+    /// A helper which counts the bytes of a region which are backed by frames (it translates every
+    /// page with the mapper).
+    ///
+    /// # Parameters
+    /// `region` : The page aligned region.
+    ///
+    /// # Returns
+    /// The bytes in the pages which are mapped.
+    fn resident_in(&self, region: &Region) -> usize {
+        (region.addr..region.end_addr()).step_by(crate::mem::vmm::PAGE_SIZE)
+            .filter(|&page_addr| VirtAddr::new(page_addr).and_then(self.mapper.translate).is_ok())
+            .count() * crate::mem::vmm::PAGE_SIZE
+    }
+    
+    /// A method which sets the owner of an allocation (it's counted under the tag until it's
+    /// freed).
+    ///
+    /// # Parameters
+    /// `ptr` : The memory address (which we got from alloc).
+    /// `tag` : The owner of the allocation.
+    ///
+    /// # Returns
+    /// True if it was tagged, False if it's not the start of a current allocation.
+    pub unsafe fn set_tag(&mut self, ptr: *mut u8, tag: AllocTag) -> bool {
+        let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
+        
+        // Go through the used list while it's locked.
+        let were_enabled = self.mutex.lock_irqsave();
+        let found = used_list_uw.into_iter().find(|&node_ptr| 
+            (*node_ptr).usable().addr == ptr as usize);
+        if let Some(node_ptr) = found {
+            (*node_ptr).tag = tag;
+        }
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        found.is_some()
+    }
+    
+    /// A method which sums the used regions of each tag.
+    ///
+    /// # Returns
+    /// The usage of every tag (in the order of AllocTag::ALL).
+    pub unsafe fn usage_by_tag(&mut self) -> [TagUsage; NUM_TAGS] {
+        let mut usage = [TagUsage::default(); NUM_TAGS];
+        for (idx, tag) in AllocTag::ALL.iter().enumerate() {
+            usage[idx].tag = *tag;
+        }
+        
+        let used_list_uw = match self.used_list.as_mut() {
+            Some(used_list) => used_list,
+            None => return usage,
+        };
+        
+        // Go through the used list while it's locked.
+        let were_enabled = self.mutex.lock_irqsave();
+        for node_ptr in used_list_uw.into_iter() {
+            let entry = &mut usage[(*node_ptr).tag as usize];
+            entry.allocs += 1;
+            entry.bytes += (*node_ptr).region.size;
+        }
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        usage
+    }
+    
+    /// A method which finds the layout which was requested for an allocation (the sizes of the 
+    /// regions are rounded up to pages, so they can't be used to validate the layouts).
+    ///
+    /// # Parameters
+    /// `ptr` : The memory address (which we got from alloc).
+    ///
+    /// # Returns
+    /// Some(layout) if it's the start of a current allocation, None otherwise (or a small object).
+    pub unsafe fn requested_layout(&mut self, ptr: *mut u8) -> Option<Layout> {
+        let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
+        
+        // Go through the used list while it's locked.
+        let were_enabled = self.mutex.lock_irqsave();
+        let found = used_list_uw.into_iter().find(|&node_ptr| 
+            (*node_ptr).usable().addr == ptr as usize).map(|node_ptr| (*node_ptr).requested());
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        found
+    }
+    
+    /// A method which shrinks an allocation in place. The whole pages after the new size are given
+    /// back to the free list and unmapped, and the new size is recorded as the requested size. The
+    /// guarded allocations and the small objects are kept as they are, and a size which is larger 
+    /// than the allocation is ignored.
+    ///
+    /// # Parameters
+    /// `ptr` : The memory address (which we got from alloc), which we're shrinking.
+    /// `new_size` : The new size of the allocation (in bytes).
+    ///
+    /// # Returns
+    /// Ok(freed_bytes) which were given back (0 if nothing was), NotAllocated if it's not in any 
+    /// allocation, or Unaligned if it points inside an allocation.
+    pub unsafe fn internal_shrink(&mut self, ptr: *mut u8, new_size: usize) 
+        -> Result<usize, DeallocError> {
+        if self.slab.object_size(ptr).is_some() {
+            return Ok(0);
+        }
+        
+        let free_list_uw = self.free_list.as_mut().expect("Heap alloc free list not valid.");
+        let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
+        
+        let were_enabled = self.mutex.lock_irqsave();
+        
+        // Find the allocation (the pointer should be it's start, like in dealloc).
+        let node_ptr = match used_list_uw.into_iter()
+            .find(|&node_ptr| (*node_ptr).region.includes(ptr as usize)) {
+            Some(node_ptr) if (*node_ptr).usable().addr == ptr as usize => node_ptr,
+            found => {
+                self.mutex.unlock_irqrestore(were_enabled);
+                return Err(match found {
+                    Some(_) => DeallocError::Unaligned,
+                    None => DeallocError::NotAllocated,
+                });
+            },
+        };
+        
+        // Keep at least a page (so it's still an allocation), and ignore the larger sizes.
+        let usable = (*node_ptr).usable();
+        let keep = crate::mem::align::align_higher(new_size, crate::mem::vmm::PAGE_SIZE)
+            .max(crate::mem::vmm::PAGE_SIZE);
+        if new_size > usable.size {
+            self.mutex.unlock_irqrestore(were_enabled);
+            return Ok(0);
+        }
+        (*node_ptr).req_size = new_size;
+        
+        if (*node_ptr).guarded || keep >= usable.size {
+            self.mutex.unlock_irqrestore(were_enabled);
+            return Ok(0);
+        }
+        
+        // Give the tail back, and unmap it before it's unlocked (so it's not handed out while it's
+        // still mapped).
+        let (kept, tail) = HeapAlloc::split_region(&usable, keep);
+        (*node_ptr).region = kept;
+        free_list_uw.add(&tail, true).expect("Could not add the tail to the free list.");
+        (self.mapper.unmap)(VirtAddr::from_ptr(tail.addr as *const u8), tail.size)
+            .expect("Could not unmap memory range.");
+        
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        #[cfg(feature = "heap-debug")]
+        self.debug_check();
+        
+        Ok(tail.size)
+    }
+    
+    /// A method which tries to extend a used region in place, by taking the start of the free 
+    /// region which immediately follows it. The extension is mapped with the kernel heap's 
+    /// permissions (and zeroed).
+    ///
+    /// # Parameters
+    /// `region` : The used region which we're extending.
+    /// `new_end` : The address which the region should at least reach.
+    ///
+    /// # Returns
+    /// True if it was extended, False if the following free region is missing or too small.
+    unsafe fn try_extend(&mut self, region: &Region, new_end: usize) -> bool {
+        let free_list_uw = self.free_list.as_mut().expect("Heap alloc free list not valid.");
+        let used_list_uw = self.used_list.as_mut().expect("Heap alloc used list not valid.");
+        let extra = crate::mem::align::align_higher(new_end, crate::mem::vmm::PAGE_SIZE) 
+            - region.end_addr();
+        
+        let were_enabled = self.mutex.lock_irqsave();
+        
+        // Find the free region right after it (and make sure it's large enough).
+        let free_node = match free_list_uw.find_starting_at(region.end_addr()) {
+            Some(node_ptr) if (*node_ptr).region.size >= extra => node_ptr,
+            _ => {
+                self.mutex.unlock_irqrestore(were_enabled);
+                return false;
+            }
+        };
+        // The guarded regions don't start at their allocation, so they're never extended.
+        let used_node = match used_list_uw.find_starting_at(region.addr) {
+            Some(node_ptr) if !(*node_ptr).guarded => node_ptr,
+            _ => {
+                self.mutex.unlock_irqrestore(were_enabled);
+                return false;
+            }
+        };
         
-        // Simply call the internal implementation of dealloc.
-        mut_self.internal_dealloc(ptr);
+        // Take the start of the free region, and give the rest back.
+        let free_region = free_list_uw.remove(free_node)
+            .expect("Could not remove region from the HeapList.");
+        let (extension, after_region) = HeapAlloc::split_region(&free_region, extra);
+        if !after_region.is_empty() {
+            free_list_uw.add(&after_region, true)
+                .expect("Could not add the after region to the free list.");
+        }
+        (*used_node).region.size += extra;
+        (*used_node).req_size = new_end - region.addr;
+        
+        self.mutex.unlock_irqrestore(were_enabled);
+        
+        // Map the extension (in VMM by default), and zero it.
+        (self.mapper.map)(VirtAddr::from_ptr(extension.addr as *const u8), extension.size, false, 
+            true, false)
+            .expect("Could not map memory range");
+        crate::olibc::memset::memset(extension.addr as *mut u8, 0, extension.size);
+        
+        true
+    }
+    
+    /// A method which changes the size of an allocation. If the new size still fits in the page 
+    /// rounded region of the allocation, the same pointer is returned (no copies). If the region
+    /// is followed by a large enough free region, it's extended in place. Otherwise, a new region
+    /// is allocated, the contents are copied, and the old region is freed. The small objects are
+    /// kept if the new size still fits in their class.
+    ///
+    /// # Parameters
+    /// `ptr` : The memory address (which we got from alloc), which we're resizing.
+    /// `layout` : The size and alignment which was used for the allocation.
+    /// `new_size` : The new size requested (in bytes).
+    ///
+    /// # Returns
+    /// The address of the resized memory (which might be the same as ptr), or null if a new region
+    /// is needed and the heap is exhausted (the original allocation is kept in that case).
+    pub unsafe fn internal_realloc(&mut self, ptr: *mut u8, layout: &Layout, new_size: usize) 
+        -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        
+        // The small objects are moved to a new object (or region) once they outgrow their class.
+        if let Some(object_size) = self.slab.object_size(ptr) {
+            if new_size <= object_size {
+                return ptr;
+            }
+            
+            let mut new_ptr = self.slab_alloc(&new_layout, false);
+            if new_ptr.is_null() {
+                new_ptr = self.internal_alloc(&new_layout, false, true, false);
+            }
+            if !new_ptr.is_null() {
+                crate::olibc::memcpy::memcpy(new_ptr, ptr, core::cmp::min(layout.size(), new_size));
+                if let Err(err) = self.internal_dealloc(ptr) {
+                    report_dealloc_err(ptr, err);
+                }
+            }
+            
+            return new_ptr;
+        }
+        
+        // If it still fits in the original region, keep the pointer (and give back the whole pages
+        // after the new size). If it can be extended, keep the pointer as well.
+        if let Some(region) = self.find_used(ptr) {
+            if ptr as usize + new_size <= region.end_addr() {
+                if let Err(err) = self.internal_shrink(ptr, new_size) {
+                    report_dealloc_err(ptr, err);
+                }
+                return ptr;
+            }
+            
+            if self.try_extend(&region, ptr as usize + new_size) {
+                return ptr;
+            }
+        }
+        
+        // Otherwise, move it to a new region with the same permissions as the kernel heap (only 
+        // the pages which are copied are mapped).
+        let new_ptr = self.internal_alloc_lazy(&new_layout, false);
+        if new_ptr.is_null() {
+            return new_ptr;
+        }
+        
+        crate::olibc::memcpy::memcpy(new_ptr, ptr, core::cmp::min(layout.size(), new_size));
+        if let Err(err) = self.internal_dealloc(ptr) {
+            report_dealloc_err(ptr, err);
+        }
+        
+        new_ptr
     }
 }
 
-/// A wrapper for the HeapAlloc::init method which initializes the global allocator.
+// TODO: Add synchronization.
+
+/// A public wrapper for the internal alloc which always sets the alignment to page size, and 
+/// can provide a familiar interface for kernel processes to allocate memory. Compared to the 
+/// global allocator, it also provides a fine-grain control. The memory is not zeroed, and the 
+/// kernel writable allocations are mapped lazily (the frames are taken when the pages are first
+/// touched), so it should not be used for the stacks (see kmalloc_zeroed).
 ///
 /// # Parameters
-/// `meta_region` : The region which we're using for the metadata of the heap.
-/// `alloc_region` : The actual region where memory will be allocated at.
-pub unsafe fn init(meta_region: &Region, alloc_region: &Region) {
-    HEAP_ALLOC.init(meta_region, alloc_region)
+/// `size` : The number of bytes which will be allocated.
+/// `is_user` : True if the permissions are user accessible, False otherwise.
+/// `is_writable` : True if R/W, False if it's read-only.
+/// `is_no_exec` : True if not executable, False otherwise.
+///
+/// # Returns
+/// The address of the allocated memory, or null if the heap is exhausted.
+#[track_caller]
+pub unsafe fn kmalloc(size: usize, is_user: bool, is_writable: bool, 
+    is_no_exec: bool) -> *mut u8 {
+    // Define a new layout, and then call the internal allocator.
+    let layout = Layout::from_size_align_unchecked(size, crate::mem::vmm::PAGE_SIZE);  
+    
+    // The page fault handler only maps the kernel writable pages.
+    if !is_user && is_writable {
+        HEAP_ALLOC.internal_alloc_lazy(&layout, is_no_exec)
+    } else {
+        HEAP_ALLOC.internal_alloc(&layout, is_user, is_writable, is_no_exec)
+    }
 }
 
-// Unit Tests **************************************************************************************
+/// A version of kmalloc which maps the whole allocation right away, and zeroes it. It should be 
+/// used if the memory is accessed where a page fault can't be handled (such as a stack), or if 
+/// the contents are expected to be zero.
+///
+/// # Parameters
+/// `size` : The number of bytes which will be allocated.
+/// `is_user` : True if the permissions are user accessible, False otherwise.
+/// `is_writable` : True if R/W, False if it's read-only.
+/// `is_no_exec` : True if not executable, False otherwise.
+///
+/// # Returns
+/// The address of the allocated (and zeroed) memory, or null if the heap is exhausted.
+#[track_caller]
+pub unsafe fn kmalloc_zeroed(size: usize, is_user: bool, is_writable: bool, 
+    is_no_exec: bool) -> *mut u8 {
+    let layout = Layout::from_size_align_unchecked(size, crate::mem::vmm::PAGE_SIZE);  
+    HEAP_ALLOC.internal_alloc(&layout, is_user, is_writable, is_no_exec)
+}
 
-/// The main test function which will call all the unit tests. This code will only be compiled
-/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
-#[cfg(feature = "unit-test")]
-pub mod test {
-    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
-    /// sub module. 
-    pub fn run() {
-        super::heap_node_alloc::test::run();
-        super::heap_list::test::run();
+/// A version of kmalloc which allocates memory with a given alignment (such as a 64KB aligned 
+/// buffer for a device). The alignments smaller than a page are rounded up to a page. It's freed
+/// with kfree (like the other allocations). It's mapped right away and zeroed (like 
+/// kmalloc_zeroed), so the frames can be handed to a device.
+///
+/// # Parameters
+/// `size` : The number of bytes which will be allocated.
+/// `align` : The alignment of the returned address (a power of two).
+/// `is_user` : True if the permissions are user accessible, False otherwise.
+/// `is_writable` : True if R/W, False if it's read-only.
+/// `is_no_exec` : True if not executable, False otherwise.
+///
+/// # Returns
+/// The address of the allocated memory, or null if the heap is exhausted.
+#[track_caller]
+pub unsafe fn kmalloc_aligned(size: usize, align: usize, is_user: bool, is_writable: bool, 
+    is_no_exec: bool) -> *mut u8 {
+    debug_assert!(align.is_power_of_two(), "The alignment 0x{:x} is not a power of two.", align);
+    
+    let layout = Layout::from_size_align_unchecked(size, 
+        core::cmp::max(align, crate::mem::vmm::PAGE_SIZE));
+    HEAP_ALLOC.internal_alloc(&layout, is_user, is_writable, is_no_exec)
+}
+
+/// A version of kmalloc_zeroed which surrounds the allocation with unmapped guard pages (see 
+/// HeapAlloc::internal_alloc_guarded), so running off either end of it causes a page fault. It's
+/// used for the stacks, and it's freed with kfree (like the other allocations).
+///
+/// # Parameters
+/// `size` : The number of bytes which will be allocated.
+/// `is_user` : True if the permissions are user accessible, False otherwise.
+/// `is_writable` : True if R/W, False if it's read-only.
+/// `is_no_exec` : True if not executable, False otherwise.
+///
+/// # Returns
+/// The address of the allocated (and zeroed) memory, or null if the heap is exhausted.
+#[track_caller]
+pub unsafe fn kmalloc_guarded(size: usize, is_user: bool, is_writable: bool, 
+    is_no_exec: bool) -> *mut u8 {
+    let layout = Layout::from_size_align_unchecked(size, crate::mem::vmm::PAGE_SIZE);
+    HEAP_ALLOC.internal_alloc_guarded(&layout, is_user, is_writable, is_no_exec)
+}
+
+/// An enum which represents the reason a tagged allocation failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AllocError {
+    HeapLimitExceeded,          // It would go over the process' max_heap_bytes limit.
+    OutOfMemory,                // The heap is exhausted.
+}
+
+/// A version of kmalloc which charges the allocation to the running process, so it's heap limit is
+/// enforced (see proc::process::ResourceLimits). The sizes are rounded up to pages (like the real
+/// allocations). It should be freed with kfree_tagged by the same process.
+///
+/// # Parameters
+/// `size` : The number of bytes which will be allocated.
+/// `is_user` : True if the permissions are user accessible, False otherwise.
+/// `is_writable` : True if R/W, False if it's read-only.
+/// `is_no_exec` : True if not executable, False otherwise.
+///
+/// # Returns
+/// The address of the allocated memory, or Err if the process' limit would be exceeded (or the 
+/// heap is exhausted).
+pub unsafe fn kmalloc_tagged(size: usize, is_user: bool, is_writable: bool, 
+    is_no_exec: bool) -> Result<*mut u8, AllocError> {
+    let charged = crate::mem::align::align_higher(size, crate::mem::vmm::PAGE_SIZE);
+    crate::proc::scheduler::charge_heap(charged).map_err(|_| AllocError::HeapLimitExceeded)?;
+    
+    let ptr = kmalloc(size, is_user, is_writable, is_no_exec);
+    if ptr.is_null() {
+        crate::proc::scheduler::release_heap(charged);
+        return Err(AllocError::OutOfMemory);
+    }
+    
+    Ok(ptr)
+}
+
+/// A wrapper for the internal shrink method. It gives the whole pages after the new size back to 
+/// the heap (and their frames back to the frame allocator). It should not be used with 
+/// kmalloc_tagged, since the pages which are given back are not released from the process.
+///
+/// # Parameters
+/// `ptr` : The memory address (which we got from kmalloc), which we're shrinking.
+/// `new_size` : The new size of the allocation (in bytes).
+///
+/// # Returns
+/// The number of bytes which were given back (0 if it's invalid, or nothing was given back).
+pub unsafe fn kshrink(ptr: *mut u8, new_size: usize) -> usize {
+    match HEAP_ALLOC.internal_shrink(ptr, new_size) {
+        Ok(freed) => freed,
+        Err(err) => {
+            report_dealloc_err(ptr, err);
+            0
+        },
+    }
+}
+
+/// A version of kfree for the allocations made with kmalloc_tagged. It gives the bytes back to the
+/// running process.
+///
+/// # Parameters
+/// `ptr` : The memory address (which we got from kmalloc_tagged), which we're freeing.
+pub unsafe fn kfree_tagged(ptr: *mut u8) {
+    if let Some(region) = HEAP_ALLOC.find_used(ptr) {
+        kfree(ptr);
+        crate::proc::scheduler::release_heap(region.size);
+    }
+}
+
+/// A wrapper for the internal dealloc method. This is only to provide a familiar interface
+/// for kernel developers.
+///
+/// # Parameters
+/// `ptr` : The memory address (which we got from alloc, or kmalloc_atomic), which we're freeing.
+#[track_caller]
+pub unsafe fn kfree(ptr: *mut u8) {
+    // The pages of the atomic cache never took the heap's lock, so they're given back directly.
+    let result = if atomic::contains(ptr) {
+        atomic::free(ptr)
+    } else {
+        HEAP_ALLOC.internal_dealloc(ptr)
+    };
+    
+    // Report the invalid frees (nothing is freed for them).
+    if let Err(err) = result {
+        report_dealloc_err(ptr, err);
+    }
+}
+
+/// A version of kfree which can be called from the hardware interrupt handlers. Outside of them,
+/// it's the same as kfree. In them, the pointer is freed by the next allocation (or num_allocs) 
+/// outside of the handlers, so the heap is not used in the interrupt. The memory is leaked if too
+/// many frees are waiting (see MAX_DEFERRED).
+///
+/// # Parameters
+/// `ptr` : The memory address (which we got from alloc), which we're freeing.
+#[track_caller]
+pub unsafe fn kfree_deferred(ptr: *mut u8) {
+    if crate::arch::interrupts::handlers::in_irq() || HEAP_ALLOC.locked_in_handler() {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if HEAP_ALLOC.deferred.try_push(ptr as usize).is_err() {
+            emergency::note_leak();
+        }
+        crate::arch::interrupts::restore(were_enabled);
+    } else {
+        kfree(ptr);
+    }
+}
+
+/// A version of kmalloc which can be called from the hardware interrupt handlers. It never takes
+/// the heap's lock, it hands out a zeroed page from the atomic cache instead (see atomic). It fails
+/// right away if the size is larger than a page, or the cache is empty. The page is freed with 
+/// kfree (like the other allocations), and it's always kernel writable and not executable.
+///
+/// # Parameters
+/// `size` : The number of bytes which will be allocated (at most a page).
+///
+/// # Returns
+/// The address of the allocated (and zeroed) memory, or null if it could not be allocated.
+pub unsafe fn kmalloc_atomic(size: usize) -> *mut u8 {
+    atomic::alloc(size)
+}
+
+/// A helper which checks that the heap is not used in a hardware interrupt handler. The uses are
+/// counted, and the first one panics with the caller's location (with the heap-debug feature).
+#[inline]
+#[track_caller]
+fn check_context() {
+    #[cfg(feature = "unit-test")]
+    let in_irq = crate::arch::interrupts::handlers::in_irq() || unsafe { FAKE_ISR_PID.is_some() 
+        && FAKE_ISR_PID == crate::proc::scheduler::current_pid() };
+    #[cfg(not(feature = "unit-test"))]
+    let in_irq = crate::arch::interrupts::handlers::in_irq();
+    
+    if !in_irq {
+        return;
+    }
+    
+    unsafe {
+        ISR_USES += 1;
+        ISR_CALLER = ISR_CALLER.or(Some(core::panic::Location::caller()));
+        if cfg!(feature = "heap-debug") && !ISR_REPORTED {
+            ISR_REPORTED = true;
+            panic!("The heap was used in an interrupt handler (at {}).", 
+                core::panic::Location::caller());
+        }
+    }
+}
+
+/// A simple getter for the number of times the heap was used in a hardware interrupt handler, and
+/// the location of the code which first used it.
+///
+/// # Returns
+/// A tuple of (the number of allocations and frees made in the handlers, the first caller).
+pub fn isr_uses() -> (usize, Option<&'static core::panic::Location<'static>>) {
+    unsafe { (ISR_USES, ISR_CALLER) }
+}
+
+/// A helper which logs a pointer which could not be freed.
+///
+/// # Parameters
+/// `ptr` : The pointer which was passed to free.
+/// `err` : The reason it could not be freed.
+fn report_dealloc_err(ptr: *mut u8, err: DeallocError) {
+    match err {
+        DeallocError::NotAllocated => 
+            oxid_err!("Invalid free of 0x{:x} (double free, or not allocated).", ptr as usize),
+        DeallocError::Unaligned => 
+            oxid_err!("Invalid free of 0x{:x} (inside an allocation).", ptr as usize),
+    }
+}
+
+/// A simple getter for the number of allocations in the kernel heap which were not freed yet.
+///
+/// # Returns
+/// The number of current kernel heap allocations (the deferred frees are done first, unless it's
+/// called from a handler).
+pub fn num_allocs() -> usize {
+    unsafe {
+        if !HEAP_ALLOC.locked_in_handler() {
+            HEAP_ALLOC.free_deferred();
+        }
+        
+        HEAP_ALLOC.num_allocs()
+    }
+}
+
+/// A function which sets the owner of a kernel heap allocation (see HeapAlloc::set_tag).
+///
+/// # Parameters
+/// `ptr` : The memory address (which we got from kmalloc).
+/// `tag` : The owner of the allocation.
+///
+/// # Returns
+/// True if it was tagged, False if it's not the start of a current allocation.
+pub unsafe fn set_tag(ptr: *mut u8, tag: AllocTag) -> bool {
+    HEAP_ALLOC.set_tag(ptr, tag)
+}
+
+/// A function which sums the used memory of the kernel heap for each tag, so the leaks can be 
+/// attributed to their owners.
+///
+/// # Returns
+/// The usage of every tag (in the order of AllocTag::ALL).
+pub fn usage_by_tag() -> [TagUsage; NUM_TAGS] {
+    unsafe { HEAP_ALLOC.usage_by_tag() }
+}
+
+/// A function which collects the statistics of the kernel heap (including the fragmentation). It
+/// can be called with the interrupts disabled (see HeapAlloc::stats).
+///
+/// # Returns
+/// Some(stats), or None if the heap is not initialized or it's locked.
+pub fn stats() -> Option<HeapStats> {
+    unsafe { HEAP_ALLOC.stats() }
+}
+
+/// A function which finds how much of a kernel heap allocation is backed by frames (see
+/// HeapAlloc::resident_bytes).
+///
+/// # Parameters
+/// `ptr` : The memory address (which we got from kmalloc).
+///
+/// # Returns
+/// Ok(bytes) which are resident, or Err if it's not the start of a current allocation.
+pub fn resident_bytes(ptr: *const u8) -> Result<usize, ()> {
+    unsafe { HEAP_ALLOC.resident_bytes(ptr) }
+}
+
+/// A function which finds how much of the kernel heap's allocations is backed by frames (the rest
+/// was never touched).
+///
+/// # Returns
+/// The bytes which are resident.
+pub fn resident_total() -> usize {
+    unsafe { HEAP_ALLOC.resident_total() }
+}
+
+/// A function which changes how the kernel heap chooses the free regions (see 
+/// HeapAlloc::set_strategy).
+///
+/// # Parameters
+/// `strategy` : The new fit strategy.
+pub fn set_strategy(strategy: FitStrategy) {
+    unsafe { HEAP_ALLOC.set_strategy(strategy) }
+}
+
+/// A function which checks the integrity of the kernel heap's lists (see 
+/// HeapAlloc::check_integrity). It can be used to find out where the heap is being corrupted.
+///
+/// # Returns
+/// Ok(report) if the heap is consistent, Err(corruption) with the first violation otherwise.
+pub fn check_integrity() -> Result<HeapCheckReport, HeapCorruption> {
+    unsafe { HEAP_ALLOC.check_integrity() }
+}
+
+/// A function which checks the canaries of the kernel heap's nodes in a range of slots (see 
+/// HeapAlloc::check_canaries).
+///
+/// # Parameters
+/// `start` : The index of the first slot which is checked.
+/// `count` : The maximum number of slots which are checked.
+///
+/// # Returns
+/// Ok(Some(next)) with the slot to continue from, Ok(None) if the last slot was checked, or 
+/// Err(Canary) with the first node whose canary was overwritten.
+pub fn check_canaries(start: usize, count: usize) -> Result<Option<usize>, HeapCorruption> {
+    unsafe { HEAP_ALLOC.check_canaries(start, count) }
+}
+
+/// A function which logs the statistics of the kernel heap (used when an allocation fails).
+pub fn log_stats() {
+    match stats() {
+        Some(stats) => oxid_log!("Heap: {} of {} bytes used by {} allocations, {} bytes free in {} \
+            blocks (largest {}).", stats.used_bytes, stats.total_bytes, stats.num_allocs, 
+            stats.free_bytes, stats.free_blocks, stats.max_free),
+        None => oxid_log!("Heap: the statistics are not available (it's locked)."),
+    }
+}
+
+/// A function which starts sampling the fragmentation of the kernel heap (every FRAG_SAMPLE_MS). It
+/// should be called after the work queue is initialized.
+pub fn start_frag_sampling() {
+    if crate::proc::workqueue::queue_work_delayed(FRAG_SAMPLE_MS, sample_fragmentation, 0).is_err() {
+        oxid_warn!("Could not start sampling the heap fragmentation.");
+    }
+}
+
+/// A function which records the fragmentation ratio of the kernel heap (the oldest sample is 
+/// dropped if the history is full), and queues the next sample. It's run by the work queue.
+///
+/// # Parameters
+/// `_arg` : The argument passed by the work queue (not used).
+fn sample_fragmentation(_arg: usize) {
+    // Skip the sample if the heap is busy (it's retried on the next one).
+    let ratio = match stats() {
+        Some(stats) => stats.fragmentation_permille() as u16,
+        None => {
+            let _ = crate::proc::workqueue::queue_work_delayed(FRAG_SAMPLE_MS, 
+                sample_fragmentation, 0);
+            return;
+        }
+    };
+    
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        if FRAG_HISTORY.is_full() {
+            FRAG_HISTORY.as_mut_slice().rotate_left(1);
+            FRAG_HISTORY.pop();
+        }
+        FRAG_HISTORY.push(ratio);
+        crate::arch::interrupts::restore(were_enabled);
+    }
+    
+    let _ = crate::proc::workqueue::queue_work_delayed(FRAG_SAMPLE_MS, sample_fragmentation, 0);
+}
+
+/// A simple getter for the fragmentation samples of the kernel heap.
+///
+/// # Returns
+/// A copy of the samples (in permille, the oldest first).
+pub fn frag_history() -> BoundedVec<u16, FRAG_HISTORY_SIZE> {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let history = FRAG_HISTORY;
+        crate::arch::interrupts::restore(were_enabled);
+        
+        history
+    }
+}
+
+/// A function which checks if an address is in a lazy region of the kernel heap (see 
+/// HeapAlloc::find_lazy). It's called by the page fault handler.
+///
+/// # Parameters
+/// `addr` : The address which caused the fault.
+///
+/// # Returns
+/// Some(is_no_exec) if the page should be mapped, None otherwise.
+pub unsafe fn find_lazy(addr: usize) -> Option<bool> {
+    HEAP_ALLOC.find_lazy(addr)
+}
+
+/// A function which checks if an address is in a guard page of the kernel heap (see 
+/// HeapAlloc::find_guard). It's called by the page fault handler.
+///
+/// # Parameters
+/// `addr` : The address which caused the fault.
+///
+/// # Returns
+/// Some(usable_region) of the allocation which owns the guard page, None otherwise.
+pub unsafe fn find_guard(addr: usize) -> Option<Region> {
+    HEAP_ALLOC.find_guard(addr)
+}
+
+/// A function which calls a given closure with every region in the used list of the kernel heap. 
+/// The heap is locked while going through the list, so the closure should never allocate memory.
+///
+/// # Parameters
+/// `func` : The closure which is called with every used region (without it's guard pages), True if 
+/// it's lazy, and it's tag.
+pub unsafe fn for_each_used(func: &mut dyn FnMut(&Region, bool, AllocTag)) {
+    // Make sure the heap is initialized first.
+    let used_list = match HEAP_ALLOC.used_list.as_ref() {
+        Some(list) => list,
+        None => return,
+    };
+    
+    // Go through the list while it's locked.
+    let were_enabled = HEAP_ALLOC.mutex.lock_irqsave();
+    for node_ptr in used_list.into_iter() {
+        func(&(*node_ptr).usable(), (*node_ptr).lazy, (*node_ptr).tag);
+    }
+    HEAP_ALLOC.mutex.unlock_irqrestore(were_enabled);
+}
+
+/// A structure which wraps a heap allocator in an UnsafeCell, so it can be changed through the 
+/// shared reference which is passed to the GlobalAlloc methods (the heap is protected by it's own 
+/// mutex). The wrapped heap can be used directly through Deref.
+pub struct GlobalHeap {
+    heap: UnsafeCell<HeapAlloc>,                    // The wrapped heap allocator.
+}
+
+impl GlobalHeap {
+    /// A constructor which wraps a given heap allocator.
+    ///
+    /// # Parameters
+    /// `heap` : The heap allocator which we're wrapping.
+    pub const fn new(heap: HeapAlloc) -> Self {
+        GlobalHeap { heap: UnsafeCell::new(heap) }
+    }
+    
+    /// A method which returns a mutable reference to the wrapped heap from a shared reference. The 
+    /// caller should make sure it's not used by anything else at the same time (the heap methods 
+    /// lock it's mutex before changing the lists).
+    ///
+    /// # Returns
+    /// The wrapped heap allocator.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn heap_mut(&self) -> &mut HeapAlloc {
+        &mut *self.heap.get()
+    }
+}
+
+impl Deref for GlobalHeap {
+    type Target = HeapAlloc;
+    
+    fn deref(&self) -> &HeapAlloc {
+        unsafe { &*self.heap.get() }
+    }
+}
+
+impl DerefMut for GlobalHeap {
+    fn deref_mut(&mut self) -> &mut HeapAlloc {
+        self.heap.get_mut()
+    }
+}
+
+/// Implement global alloc so we can use rust standard types.
+unsafe impl GlobalAlloc for GlobalHeap {
+    /// The main entry for kernel heap allocation. It uses the standard rust allocation interface, 
+    /// and can allocate memory with a certain size and alignment. This is a wrapper to make
+    /// the internal implementation compatible with the rust's alloc library. The small layouts are
+    /// allocated from the slabs (if they can't be, they fall back to the page allocations). The 
+    /// memory is not zeroed, and the pages are mapped when they're first touched.
+    ///
+    /// # Parameters
+    /// `layout` : The size and alignment (rust type).
+    ///
+    /// # Returns
+    /// A pointer to the allocated memory region (null if the heap is exhausted, which calls the
+    /// allocation error handler).
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // Get a mutable reference to the heap (the shared reference is only for the interface).
+        let heap = self.heap_mut();
+        
+        // A handler which interrupted a critical section can't wait for the lock.
+        if heap.locked_in_handler() {
+            return emergency::alloc(&layout);
+        }
+        heap.free_deferred();
+    
+        let object = heap.slab_alloc(&layout, false);
+        if !object.is_null() {
+            return object;
+        }
+        
+        // Since this is the kernel heap, set it to kernel mode, writable, and executable.
+        heap.internal_alloc_lazy(&layout, false)
+    }
+    
+    /// The main deallocation method which is similar to free in Clib. It uses the standard rust 
+    /// deallocation interface. This is a wrapper to make the internal implementation compatible
+    /// with the rust's alloc library.
+    ///
+    /// # Parameters
+    /// `ptr` : The pointer for the location we're deallocaing.
+    /// `layout` : The size and alignment of the memory we're deallocating.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Get a mutable reference to the heap (the shared reference is only for the interface).
+        let heap = self.heap_mut();
+        
+        // The emergency blocks are given back to the pool, and the frees which can't wait for the
+        // lock are deferred (they're leaked if there are too many).
+        let result = if emergency::contains(ptr) {
+            emergency::free(ptr)
+        } else if heap.locked_in_handler() {
+            if heap.deferred.try_push(ptr as usize).is_err() {
+                emergency::note_leak();
+            }
+            Ok(())
+        } else {
+            heap.free_deferred();
+            
+            // The layout should be the one it was allocated (or last reallocated) with. It's only 
+            // checked in the tests, since it walks the used list again.
+            #[cfg(feature = "unit-test")]
+            if let Some(requested) = heap.requested_layout(ptr) {
+                assert_eq!(requested, layout, "Dealloc of 0x{:x} with a different layout.", 
+                    ptr as usize);
+            }
+            let _ = layout;
+            
+            heap.internal_dealloc(ptr)
+        };
+        
+        // An invalid free is a bug in the caller, so it stops the debug builds (and it's only 
+        // reported otherwise).
+        if let Err(err) = result {
+            if cfg!(any(debug_assertions, feature = "unit-test")) {
+                panic!("Invalid dealloc of 0x{:x}: {:?}", ptr as usize, err);
+            }
+            
+            report_dealloc_err(ptr, err);
+        }
+    }
+    
+    /// The allocation method for zeroed memory. The page allocations are mapped right away and 
+    /// zeroed by the internal implementation (so it's the same as kmalloc_zeroed).
+    ///
+    /// # Parameters
+    /// `layout` : The size and alignment (rust type).
+    ///
+    /// # Returns
+    /// A pointer to the allocated (and zeroed) memory region. 
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        // Get a mutable reference to the heap (the shared reference is only for the interface).
+        let heap = self.heap_mut();
+        
+        if heap.locked_in_handler() {
+            let block = emergency::alloc(&layout);
+            if !block.is_null() {
+                crate::olibc::memset::memset(block, 0, layout.size());
+            }
+            return block;
+        }
+        heap.free_deferred();
+        
+        let object = heap.slab_alloc(&layout, true);
+        if !object.is_null() {
+            return object;
+        }
+        
+        heap.internal_alloc(&layout, false, true, false)
+    }
+    
+    /// The reallocation method which is used when growing or shrinking types such as Vec and 
+    /// String. It keeps the same pointer if the new size fits in the page rounded region (or the 
+    /// region can be extended in place), which avoids the allocation and copy done by the default
+    /// implementation.
+    ///
+    /// # Parameters
+    /// `ptr` : The pointer for the location we're resizing.
+    /// `layout` : The size and alignment of the current allocation.
+    /// `new_size` : The new size requested (in bytes).
+    ///
+    /// # Returns
+    /// A pointer to the resized memory region (which might be the same as ptr).
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Get a mutable reference to the heap (the shared reference is only for the interface).
+        let heap = self.heap_mut();
+        
+        // The emergency blocks (and the reallocations which can't wait for the lock) are moved.
+        if emergency::contains(ptr) || heap.locked_in_handler() {
+            let new_ptr = self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+            if !new_ptr.is_null() {
+                let copy_size = core::cmp::min(layout.size(), new_size);
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, copy_size);
+                self.dealloc(ptr, layout);
+            }
+            return new_ptr;
+        }
+        
+        heap.internal_realloc(ptr, &layout, new_size)
+    }
+}
+
+/// A function which gives out a page from the reserved range for the metadata (it's mapped right
+/// away). It's called by the kernel heap when it's metadata region is full, while it's locked.
+///
+/// # Returns
+/// Ok(page_region), or Err if the reserved range is used up.
+unsafe fn grow_metadata() -> Result<Region, ()> {
+    if META_SPILL_NEXT + crate::mem::vmm::PAGE_SIZE > META_SPILL_END {
+        return Err(());
+    }
+    
+    let page = Region::new_sized(META_SPILL_NEXT, crate::mem::vmm::PAGE_SIZE);
+    crate::mem::vmm::map(VirtAddr::new(page.addr)?, false, true, true)?;
+    META_SPILL_NEXT = page.end_addr();
+    
+    oxid_dbg!(Heap, "The heap metadata grew into page 0x{:x}.", page.addr);
+    Ok(page)
+}
+
+/// A simple getter for the range which is reserved for the chained metadata regions (it's at the 
+/// end of the heap arena, but it's never allocated).
+///
+/// # Returns
+/// The reserved range (empty before the heap is initialized).
+pub fn meta_spill_region() -> Region {
+    unsafe { Region::new(META_SPILL_START, META_SPILL_END) }
+}
+
+/// A wrapper for the HeapAlloc::init method which initializes the global allocator. The end of the
+/// last allocation region (a sixteenth of it, up to MAX_META_SPILL_SIZE) is reserved for the 
+/// metadata.
+///
+/// # Parameters
+/// `meta_region` : The region which we're using for the metadata of the heap.
+/// `alloc_regions` : The actual regions where memory will be allocated at (at least one).
+pub unsafe fn init(meta_region: &Region, alloc_regions: &[Region]) {
+    oxid_log!("Initializing the kernel heap.");
+    
+    let last_region = alloc_regions.last().expect("The kernel heap needs a region.");
+    let spill_size = crate::mem::align::align_lower(last_region.size / 16, 
+        crate::mem::vmm::PAGE_SIZE).min(MAX_META_SPILL_SIZE);
+    META_SPILL_START = last_region.end_addr() - spill_size;
+    META_SPILL_NEXT = META_SPILL_START;
+    META_SPILL_END = last_region.end_addr();
+    
+    // Give the heap every region, with the reserved range cut off the last one.
+    let mut regions: BoundedVec<Region, MAX_HEAP_REGIONS> = BoundedVec::new();
+    for region in alloc_regions.iter() {
+        regions.push(*region);
+    }
+    regions.pop();
+    regions.push(Region::new(last_region.addr, META_SPILL_START));
+    
+    HEAP_ALLOC.init(meta_region, regions.as_slice())
+}
+
+/// A wrapper for the HeapAlloc::add_region method which adds a region to the kernel heap after 
+/// it's initialized (such as the memory which was found by a device). It should not overlap the 
+/// heap's metadata, or any other memory which is used by the kernel.
+///
+/// # Parameters
+/// `region` : The region which is added (it should be page aligned).
+///
+/// # Returns
+/// Ok if it was added, or the reason if it was not.
+pub unsafe fn add_region(region: &Region) -> Result<(), AddRegionError> {
+    let reserved = [crate::mem::map::layout().metadata_region(), meta_spill_region()];
+    if reserved.iter().any(|other| overlaps(region, other)) {
+        return Err(AddRegionError::Overlap);
+    }
+    
+    HEAP_ALLOC.add_region(region)?;
+    oxid_log!("Added 0x{:x}-0x{:x} to the kernel heap.", region.addr, region.end_addr());
+    Ok(())
+}
+
+/// A function which checks if an address is in the arena of the kernel heap (see 
+/// HeapAlloc::in_arena). It's called by the page fault handler.
+///
+/// # Parameters
+/// `addr` : The address which we're checking.
+///
+/// # Returns
+/// True if it's in one of the heap's regions, False otherwise.
+pub fn in_arena(addr: usize) -> bool {
+    unsafe { HEAP_ALLOC.in_arena(addr) }
+}
+
+/// A helper which checks if two regions overlap.
+///
+/// # Parameters
+/// `first` : The first region.
+/// `second` : The second region.
+///
+/// # Returns
+/// True if they share any address, False otherwise.
+fn overlaps(first: &Region, second: &Region) -> bool {
+    first.addr < second.end_addr() && second.addr < first.end_addr()
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::heap_node_alloc::test::run();
+        super::heap_list::test::run();
+        test_fresh_heap();
+        test_realloc();
+        test_realloc_in_place();
+        test_large_alignment();
+        test_kmalloc_aligned();
+        test_split_region();
+        test_can_fit();
+        test_fragmentation_stress();
+        test_audit_clean();
+        test_stats_interrupts_disabled();
+        test_invalid_free();
+        test_exhausted();
+        test_slab_bounded();
+        test_slab_invalid_free();
+        test_slab_realloc();
+        test_audit_clean();
+        test_lazy_kmalloc();
+        test_guarded();
+        test_integrity();
+        test_best_fit();
+        test_requested_layout();
+        test_kshrink();
+        test_alloc_in_handler();
+        test_usage_by_tag();
+        test_discontiguous_regions();
+        test_isr_check();
+        test_kmalloc_atomic();
+    }
+    
+    /// A helper which flips the bits of the canary in the node of a kernel heap allocation (so
+    /// flipping it again restores it). It's used to test the checks which should catch it.
+    ///
+    /// # Parameters
+    /// `ptr` : The address of the allocation (it should not be from the slab layer).
+    ///
+    /// # Returns
+    /// True if it was flipped, False if it's not the start of a current allocation.
+    pub unsafe fn flip_canary(ptr: *mut u8) -> bool {
+        let heap = super::HEAP_ALLOC.heap_mut();
+        let used_list = heap.used_list.as_ref().expect("Heap alloc used list not valid.");
+        
+        let were_enabled = heap.mutex.lock_irqsave();
+        let found = used_list.into_iter().find(|&node_ptr| 
+            (*node_ptr).usable().addr == ptr as usize);
+        if let Some(node_ptr) = found {
+            (*node_ptr).canary = !(*node_ptr).canary;
+        }
+        heap.mutex.unlock_irqrestore(were_enabled);
+        
+        found.is_some()
+    }
+    
+    /// Create a private heap from two regions with a hole between them, and make sure they're not 
+    /// merged (nothing is allocated across the hole) until the hole is added as well.
+    fn test_discontiguous_regions() {
+        use core::alloc::Layout;
+        use crate::mem::region::Region;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::AddRegionError;
+        
+        unsafe {
+            // The first 4 pages are the metadata, then 4 pages, a hole of 2 pages, and 6 pages.
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 16);
+            let first = Region::new_sized(scratch.addr + PAGE_SIZE * 4, PAGE_SIZE * 4);
+            let hole = Region::new_sized(first.end_addr(), PAGE_SIZE * 2);
+            let second = Region::new(hole.end_addr(), scratch.end_addr());
+            let mut heap = super::HeapAlloc::new_with_mapper(crate::mem::test::NO_MAPPER);
+            heap.init(&Region::new_sized(scratch.addr, PAGE_SIZE * 4), &[first, second]);
+            
+            let stats = heap.stats().unwrap();
+            assert_eq!((stats.total_bytes, stats.free_blocks), (PAGE_SIZE * 10, 2));
+            assert!(heap.in_arena(first.addr) && heap.in_arena(second.addr));
+            assert!(!heap.in_arena(hole.addr));
+            
+            // Only the second region fits 5 pages, and 7 pages never fit (it's across the hole).
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE * 5, PAGE_SIZE);
+            let ptr = heap.internal_alloc(&layout, false, true, true);
+            assert!(second.includes(ptr as usize));
+            let too_large = Layout::from_size_align_unchecked(PAGE_SIZE * 7, PAGE_SIZE);
+            assert!(heap.internal_alloc(&too_large, false, true, true).is_null());
+            assert!(heap.check_integrity().is_ok());
+            
+            // The overlapping and unaligned regions are refused.
+            assert_eq!(heap.add_region(&Region::new_sized(first.addr + PAGE_SIZE, PAGE_SIZE * 4)),
+                Err(AddRegionError::Overlap));
+            assert_eq!(heap.add_region(&Region::new_sized(hole.addr + 1, PAGE_SIZE)),
+                Err(AddRegionError::Unaligned));
+            
+            // Once the hole is added, it's merged with both sides.
+            assert_eq!(heap.add_region(&hole), Ok(()));
+            heap.internal_dealloc(ptr).unwrap();
+            let stats = heap.stats().unwrap();
+            assert_eq!((stats.total_bytes, stats.free_blocks), (PAGE_SIZE * 12, 1));
+            assert_eq!(stats.free_bytes, PAGE_SIZE * 12);
+            assert!(heap.check_integrity().is_ok());
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// The heap pointer which is freed by the test handler, and what the handler saw.
+    static mut HANDLER_FREE: usize = 0;
+    static mut HANDLER_IN_POOL: bool = false;
+    static mut HANDLER_DEPTH: usize = 0;
+    
+    /// An interrupt handler which grows a vector (like the terminal does), and frees a pointer from
+    /// the heap.
+    ///
+    /// # Parameters
+    /// `_info` : The context of the interrupted code (not used).
+    unsafe fn alloc_handler(_info: *const crate::arch::interrupts::handlers::context::Context) {
+        use alloc::vec::Vec;
+        use alloc::boxed::Box;
+        
+        let mut chars: Vec<char> = Vec::new();
+        for _ in 0..100 {
+            chars.push('x');
+        }
+        
+        HANDLER_IN_POOL = super::emergency::contains(chars.as_ptr() as *const u8) 
+            && chars.iter().all(|c| *c == 'x');
+        HANDLER_DEPTH = super::HEAP_ALLOC.deferred.len();
+        drop(chars);
+        
+        drop(Box::from_raw(HANDLER_FREE as *mut u64));
+        HANDLER_DEPTH = super::HEAP_ALLOC.deferred.len() - HANDLER_DEPTH;
+    }
+    
+    /// Raise an interrupt while the heap is locked, and make sure the handler's allocations are
+    /// served from the emergency pool (instead of deadlocking), and it's free is deferred until the
+    /// heap is used again. The interrupts should stay disabled while the heap is locked.
+    fn test_alloc_in_handler() {
+        use alloc::boxed::Box;
+        use crate::arch::interrupts::{self, vectors};
+        
+        unsafe {
+            let vector = vectors::alloc_vector(vectors::VectorClass::System, "heap_test", 
+                alloc_handler).unwrap();
+            let pool_used = super::emergency::num_used();
+            let objects = super::HEAP_ALLOC.slab.num_objects();
+            HANDLER_FREE = Box::into_raw(Box::new(0x1234_u64)) as usize;
+            
+            // The heap critical sections leave the interrupts disabled if they were disabled.
+            let were_enabled = interrupts::save_and_disable();
+            drop(core::hint::black_box(Box::new(1_u8)));
+            assert!(!interrupts::are_enabled());
+            
+            assert!(super::HEAP_ALLOC.mutex.try_lock());
+            interrupts::raise_interrupt(vector);
+            super::HEAP_ALLOC.mutex.release();
+            interrupts::restore(were_enabled);
+            
+            assert!(HANDLER_IN_POOL);
+            assert_eq!(HANDLER_DEPTH, 1);
+            assert_eq!(super::emergency::num_used(), pool_used);
+            
+            // The next allocation frees the deferred pointer.
+            drop(core::hint::black_box(Box::new(2_u8)));
+            assert!(super::HEAP_ALLOC.deferred.is_empty());
+            assert_eq!(super::HEAP_ALLOC.slab.num_objects(), objects);
+            
+            // Without the lock, the handler uses the heap.
+            HANDLER_FREE = Box::into_raw(Box::new(0x5678_u64)) as usize;
+            interrupts::raise_interrupt(vector);
+            assert!(!HANDLER_IN_POOL);
+            assert_eq!(HANDLER_DEPTH, 0);
+            
+            assert_eq!(vectors::free_vector(vector), Ok(()));
+        }
+    }
+    
+    /// Make sure the requested layouts are recorded (and updated by realloc), and a shrink gives 
+    /// the whole pages back to a private heap.
+    fn test_requested_layout() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::DeallocError;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 16);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let free_before = heap.stats().unwrap().free_bytes;
+            
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE * 4 + 10, PAGE_SIZE);
+            let ptr = heap.internal_alloc(&layout, false, true, true);
+            assert_eq!(heap.requested_layout(ptr), Some(layout));
+            assert_eq!(heap.requested_layout(ptr.add(PAGE_SIZE)), None);
+            
+            // A realloc which fits records the new size (without moving).
+            assert_eq!(heap.internal_realloc(ptr, &layout, PAGE_SIZE * 4 + 20), ptr);
+            assert_eq!(heap.requested_layout(ptr).map(|layout| layout.size()), 
+                Some(PAGE_SIZE * 4 + 20));
+            
+            // Shrink it to 2 pages, the rest is free again (and merged).
+            assert_eq!(heap.internal_shrink(ptr, PAGE_SIZE + 1), Ok(PAGE_SIZE * 3));
+            assert_eq!(heap.find_used(ptr).map(|region| region.size), Some(PAGE_SIZE * 2));
+            assert_eq!(heap.requested_layout(ptr).map(|layout| layout.size()), Some(PAGE_SIZE + 1));
+            let stats = heap.stats().unwrap();
+            assert_eq!((stats.free_bytes, stats.free_blocks), (free_before - PAGE_SIZE * 2, 1));
+            
+            // A larger size (or the same pages) doesn't give anything back.
+            assert_eq!(heap.internal_shrink(ptr, PAGE_SIZE * 3), Ok(0));
+            assert_eq!(heap.internal_shrink(ptr, PAGE_SIZE * 2), Ok(0));
+            assert_eq!(heap.internal_shrink(ptr.add(PAGE_SIZE), 1), Err(DeallocError::Unaligned));
+            assert_eq!(heap.internal_shrink(ptr.add(PAGE_SIZE * 2), 1), 
+                Err(DeallocError::NotAllocated));
+            assert!(heap.check_integrity().is_ok());
+            
+            heap.internal_dealloc(ptr).unwrap();
+            assert_eq!(heap.stats().unwrap().free_bytes, free_before);
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Shrink a 5 page allocation of the kernel heap to 2 pages, and make sure the 3 frames are 
+    /// given back to the frame allocator (and the pages are unmapped).
+    fn test_kshrink() {
+        use crate::mem::frame_alloc;
+        use crate::mem::vmm::PAGE_SIZE;
+        use crate::mem::addr::VirtAddr;
+        
+        unsafe {
+            // Warm up the heap's metadata (so it doesn't take frames in the measurement).
+            super::kfree(super::kmalloc_zeroed(PAGE_SIZE * 5, false, true, true));
+            
+            let ptr = super::kmalloc_zeroed(PAGE_SIZE * 5, false, true, true);
+            let used = frame_alloc::used_count();
+            assert_eq!(super::kshrink(ptr, PAGE_SIZE * 2), PAGE_SIZE * 3);
+            assert_eq!(frame_alloc::used_count(), used - 3);
+            assert!(VirtAddr::from_ptr(ptr.add(PAGE_SIZE)).to_phys().is_ok());
+            assert!(VirtAddr::from_ptr(ptr.add(PAGE_SIZE * 2)).to_phys().is_err());
+            assert_eq!(super::HEAP_ALLOC.find_used(ptr).map(|region| region.size), 
+                Some(PAGE_SIZE * 2));
+            
+            super::kfree(ptr);
+            assert_eq!(frame_alloc::used_count(), used - 5);
+        }
+    }
+    
+    /// Tag an allocation of a private heap, and make sure it's counted under the tag until it's 
+    /// freed (and a reused node doesn't keep the old tag).
+    fn test_usage_by_tag() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::AllocTag;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 16);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let stack = AllocTag::Stack as usize;
+            let before = heap.usage_by_tag();
+            assert_eq!(before[stack].tag, AllocTag::Stack);
+            
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE * 2, PAGE_SIZE);
+            let ptr = heap.internal_alloc(&layout, false, true, false);
+            assert!(heap.set_tag(ptr, AllocTag::Stack));
+            assert!(!heap.set_tag(ptr.add(PAGE_SIZE), AllocTag::Stack));
+            
+            let usage = heap.usage_by_tag();
+            assert_eq!(usage[stack].allocs, before[stack].allocs + 1);
+            assert_eq!(usage[stack].bytes, before[stack].bytes + PAGE_SIZE * 2);
+            
+            // Once it's freed, the next allocation (in the same place) is not tagged.
+            heap.internal_dealloc(ptr).unwrap();
+            assert_eq!(heap.usage_by_tag(), before);
+            let ptr = heap.internal_alloc(&layout, false, true, false);
+            assert_eq!(heap.usage_by_tag()[stack], before[stack]);
+            
+            heap.internal_dealloc(ptr).unwrap();
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Leave a large hole and a tight hole (after it) in a private heap, and make sure the first 
+    /// fit splits the large one, while the best fit uses the tight one.
+    fn test_best_fit() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::FitStrategy;
+        
+        assert_eq!(FitStrategy::from_name("best"), Some(FitStrategy::BestFit));
+        assert_eq!(FitStrategy::from_name("first"), Some(FitStrategy::FirstFit));
+        assert_eq!(FitStrategy::from_name("worst"), None);
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 16);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            assert_eq!(heap.strategy(), FitStrategy::FirstFit);
+            
+            // Allocate [large (2 pages), 1 page, tight (1 page), 1 page], and free large and tight.
+            let page = Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE);
+            let large = heap.internal_alloc(&Layout::from_size_align_unchecked(PAGE_SIZE * 2, 
+                PAGE_SIZE), false, true, true);
+            let first_kept = heap.internal_alloc(&page, false, true, true);
+            let tight = heap.internal_alloc(&page, false, true, true);
+            let second_kept = heap.internal_alloc(&page, false, true, true);
+            assert!(!large.is_null() && !second_kept.is_null());
+            heap.internal_dealloc(large).unwrap();
+            heap.internal_dealloc(tight).unwrap();
+            let free_blocks = heap.stats().unwrap().free_blocks;
+            
+            // The first fit splits the large hole (the number of free blocks is the same).
+            let ptr = heap.internal_alloc(&page, false, true, true);
+            assert_eq!(ptr, large);
+            assert_eq!(heap.stats().unwrap().free_blocks, free_blocks);
+            heap.internal_dealloc(ptr).unwrap();
+            
+            // The best fit fills the tight hole (so there is one less free block).
+            heap.set_strategy(FitStrategy::BestFit);
+            let ptr = heap.internal_alloc(&page, false, true, true);
+            assert_eq!(ptr, tight);
+            assert_eq!(heap.stats().unwrap().free_blocks, free_blocks - 1);
+            
+            // The large hole is still whole for a large allocation.
+            let ptr_large = heap.internal_alloc(&Layout::from_size_align_unchecked(PAGE_SIZE * 2, 
+                PAGE_SIZE), false, true, true);
+            assert_eq!(ptr_large, large);
+            
+            for ptr in [ptr, ptr_large, first_kept, second_kept].iter() {
+                heap.internal_dealloc(*ptr).unwrap();
+            }
+            assert_eq!(heap.num_allocs(), 0);
+            assert!(heap.check_integrity().is_ok());
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Check a private heap, then corrupt it's free list in different ways (restoring it after 
+    /// each one), and make sure every corruption is reported. The kernel heap should be consistent.
+    fn test_integrity() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::HeapCorruption;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 8);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            
+            // Free the middle one of three allocations (so there are two free regions).
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE);
+            let first = heap.internal_alloc(&layout, false, true, true) as usize;
+            let middle = heap.internal_alloc(&layout, false, true, true) as usize;
+            let last = heap.internal_alloc(&layout, false, true, true) as usize;
+            heap.internal_dealloc(middle as *mut u8).unwrap();
+            
+            let report = heap.check_integrity().unwrap();
+            assert_eq!((report.free_nodes, report.used_nodes), (2, 2));
+            assert_eq!(report.used_bytes, PAGE_SIZE * 2);
+            assert_eq!(report.free_bytes + report.used_bytes, heap.stats().unwrap().total_bytes);
+            
+            let free_list = heap.free_list.as_ref().unwrap();
+            let node = free_list.into_iter().next().unwrap();
+            let saved = *node;
+            assert_eq!(saved.region.addr, middle);
+            
+            // An empty region.
+            (*node).region.size = 0;
+            assert!(matches!(heap.check_integrity(), Err(HeapCorruption::EmptyRegion("free", _))));
+            
+            // A free region which overlaps the first allocation.
+            (*node).region = saved.region;
+            (*node).region.addr = first;
+            match heap.check_integrity() {
+                Err(HeapCorruption::Overlap(free, used)) => 
+                    assert_eq!((free.addr, used.addr), (first, first)),
+                other => panic!("Unexpected result {:?}", other),
+            }
+            
+            // A free region which runs into the next one (and the last allocation).
+            (*node).region = saved.region;
+            (*node).region.size = PAGE_SIZE * 2;
+            assert!(matches!(heap.check_integrity(), Err(HeapCorruption::Unmerged(_, _))));
+            
+            // A region after the next one, and a cycle.
+            (*node).region = saved.region;
+            (*node).region.addr = last + PAGE_SIZE * 2;
+            assert!(matches!(heap.check_integrity(), 
+                Err(HeapCorruption::Unordered("free", _, _))));
+            (*node).region = saved.region;
+            (*node).next = Some(node);
+            assert!(matches!(heap.check_integrity(), 
+                Err(HeapCorruption::Unordered("free", _, _))));
+            
+            // A node which was cut off from the list.
+            (*node).next = None;
+            assert!(matches!(heap.check_integrity(), 
+                Err(HeapCorruption::CountMismatch("free", 1, 2))));
+            
+            // A node which was overwritten (it's found by both checks).
+            *node = saved;
+            (*node).canary = 0;
+            assert!(matches!(heap.check_integrity(), Err(HeapCorruption::Canary("free", _))));
+            assert!(matches!(heap.check_canaries(0, usize::MAX), 
+                Err(HeapCorruption::Canary("free", _))));
+            
+            *node = saved;
+            assert_eq!(heap.check_integrity().unwrap(), report);
+            let free_slots = heap.free_list.as_ref().unwrap().num_slots();
+            assert_eq!(heap.check_canaries(0, 1).unwrap(), Some(1));
+            assert_eq!(heap.check_canaries(0, usize::MAX).unwrap(), Some(free_slots));
+            assert_eq!(heap.check_canaries(free_slots, usize::MAX).unwrap(), None);
+            heap.internal_dealloc(first as *mut u8).unwrap();
+            heap.internal_dealloc(last as *mut u8).unwrap();
+            crate::mem::test::free_scratch(&scratch);
+        }
+        
+        assert!(super::check_integrity().is_ok());
+    }
+    
+    /// Allocate a guarded region from a private heap (and from the kernel heap), and make sure the
+    /// guard pages are found (and never mapped).
+    fn test_guarded() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        use crate::mem::addr::VirtAddr;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 16);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let free_before = heap.stats().unwrap().free_bytes;
+            
+            // The allocation starts after the lower guard page.
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE * 2, PAGE_SIZE);
+            let ptr = heap.internal_alloc_guarded(&layout, false, true, true) as usize;
+            assert!(ptr != 0);
+            assert_eq!(heap.stats().unwrap().used_bytes, PAGE_SIZE * 4);
+            assert_eq!(heap.find_used(ptr as *mut u8).map(|region| (region.addr, region.size)), 
+                Some((ptr, PAGE_SIZE * 2)));
+            
+            // Only the pages right before and after it are guard pages.
+            let usable = Some((ptr, PAGE_SIZE * 2));
+            let guard_of = |heap: &mut super::HeapAlloc, addr: usize| 
+                heap.find_guard(addr).map(|region| (region.addr, region.size));
+            assert_eq!(guard_of(&mut heap, ptr - PAGE_SIZE), usable);
+            assert_eq!(guard_of(&mut heap, ptr + PAGE_SIZE * 2 + 8), usable);
+            assert!(heap.find_guard(ptr).is_none());
+            assert!(heap.find_guard(ptr + PAGE_SIZE * 3).is_none());
+            
+            // The guard page is not the start of the allocation.
+            assert_eq!(heap.internal_dealloc((ptr - PAGE_SIZE) as *mut u8), 
+                Err(super::DeallocError::Unaligned));
+            heap.internal_dealloc(ptr as *mut u8).unwrap();
+            assert_eq!(heap.stats().unwrap().free_bytes, free_before);
+            assert!(heap.find_guard(ptr - PAGE_SIZE).is_none());
+            
+            crate::mem::test::free_scratch(&scratch);
+            
+            // In the kernel heap, the allocation is mapped, but it's guard pages are not.
+            let ptr = super::kmalloc_guarded(PAGE_SIZE, false, true, true) as usize;
+            assert!(VirtAddr::new(ptr).unwrap().to_phys().is_ok());
+            assert!(VirtAddr::new(ptr - PAGE_SIZE).unwrap().to_phys().is_err());
+            assert!(VirtAddr::new(ptr + PAGE_SIZE).unwrap().to_phys().is_err());
+            assert!(super::find_guard(ptr + PAGE_SIZE).is_some());
+            super::kfree(ptr as *mut u8);
+        }
+    }
+    
+    /// Allocate a 1MB buffer with the lazy kmalloc, and make sure no frames are taken until it's 
+    /// touched. The frame of a dirty allocation is freed first, so the touched pages should show 
+    /// that the new frames are scrubbed.
+    fn test_lazy_kmalloc() {
+        use crate::mem::frame_alloc;
+        use crate::mem::vmm::PAGE_SIZE;
+        use crate::mem::addr::VirtAddr;
+        
+        const SIZE: usize = 1024 * 1024;
+        const TOUCHED: usize = 4;
+        
+        unsafe {
+            // Leave a pattern in a few frames.
+            let dirty = super::kmalloc_zeroed(TOUCHED * PAGE_SIZE, false, true, true);
+            crate::olibc::memset::memset(dirty, 0xAB, TOUCHED * PAGE_SIZE);
+            super::kfree(dirty);
+            
+            // Warm up the heap's metadata (so it doesn't take frames in the measurement).
+            super::kfree(super::kmalloc(SIZE, false, true, true));
+            
+            let used = frame_alloc::used_count();
+            let buffer = super::kmalloc(SIZE, false, true, true);
+            assert!(!buffer.is_null());
+            assert_eq!(frame_alloc::used_count(), used);
+            assert!(VirtAddr::from_ptr(buffer).to_phys().is_err());
+            assert_eq!(super::resident_bytes(buffer), Ok(0));
+            let resident = super::resident_total();
+            
+            // Touch the first pages (they should read as zeros).
+            for page in 0..TOUCHED {
+                let ptr = buffer.add(page * PAGE_SIZE);
+                assert!((0..PAGE_SIZE).all(|offset| *ptr.add(offset) == 0));
+                *ptr = page as u8 + 1;
+            }
+            assert!(frame_alloc::used_count() >= used + TOUCHED);
+            assert!(VirtAddr::from_ptr(buffer.add(SIZE - PAGE_SIZE)).to_phys().is_err());
+            assert_eq!(super::resident_bytes(buffer), Ok(TOUCHED * PAGE_SIZE));
+            assert_eq!(super::resident_bytes(buffer.add(PAGE_SIZE)), Err(()));
+            assert!(super::resident_total() >= resident + TOUCHED * PAGE_SIZE);
+            let stats = super::stats().unwrap();
+            assert!(stats.resident_bytes >= TOUCHED * PAGE_SIZE);
+            assert!(stats.resident_bytes <= stats.used_bytes);
+            
+            // Only the touched pages are unmapped when it's freed.
+            super::kfree(buffer);
+            assert!(VirtAddr::from_ptr(buffer).to_phys().is_err());
+            assert!(frame_alloc::used_count() < used + TOUCHED);
+            assert_eq!(super::resident_bytes(buffer), Err(()));
+        }
+    }
+    
+    /// A helper which allocates a chain of small objects with the GlobalAlloc interface (each one
+    /// stores the address of the previous one, so nothing else has to hold the pointers).
+    ///
+    /// # Parameters
+    /// `heap` : The heap to allocate from.
+    /// `count` : The number of objects.
+    /// `layout` : The layout of each object.
+    ///
+    /// # Returns
+    /// The last object in the chain.
+    unsafe fn alloc_chain(heap: &super::GlobalHeap, count: usize, layout: core::alloc::Layout) 
+        -> *mut usize {
+        use core::alloc::GlobalAlloc;
+        
+        let mut last: *mut usize = core::ptr::null_mut();
+        for _ in 0..count {
+            let object = heap.alloc(layout) as *mut usize;
+            assert!(!object.is_null());
+            assert_eq!(*object, 0);
+            *object = last as usize;
+            last = object;
+        }
+        
+        last
+    }
+    
+    /// A helper which frees a chain created by alloc_chain.
+    ///
+    /// # Parameters
+    /// `heap` : The heap which the objects are from.
+    /// `last` : The last object in the chain.
+    /// `layout` : The layout of each object.
+    unsafe fn free_chain(heap: &super::GlobalHeap, mut last: *mut usize, 
+        layout: core::alloc::Layout) {
+        use core::alloc::GlobalAlloc;
+        
+        while !last.is_null() {
+            let prev = *last as *mut usize;
+            heap.dealloc(last as *mut u8, layout);
+            last = prev;
+        }
+    }
+    
+    /// Allocate thousands of 32 byte objects from a private heap, and make sure they're packed in
+    /// slab pages (instead of a page each). Freeing and allocating them again reuses the pages.
+    fn test_slab_bounded() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::slab;
+        
+        const NUM_OBJECTS: usize = 2000;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 32);
+            let mut heap = super::GlobalHeap::new(crate::mem::test::fresh_heap(&scratch));
+            let initial = heap.stats().unwrap();
+            let layout = Layout::from_size_align_unchecked(32, 8);
+            let class = slab::class_of(&layout).unwrap();
+            assert_eq!(slab::SIZE_CLASSES[class], 32);
+            
+            // The objects fill whole pages (plus a page for the directory).
+            let per_page = slab::objects_per_page(class);
+            let pages = (NUM_OBJECTS + per_page - 1) / per_page;
+            let last = alloc_chain(&heap, NUM_OBJECTS, layout);
+            let stats = heap.stats().unwrap();
+            assert_eq!((stats.slab_objects, stats.slab_pages), (NUM_OBJECTS, pages));
+            assert_eq!(stats.num_allocs, pages + 1);
+            assert_eq!(stats.used_bytes, (pages + 1) * PAGE_SIZE);
+            
+            // Every object is in a slab page, and they don't overlap.
+            assert_eq!(heap.slab.object_size(last as *mut u8), Some(32));
+            assert_eq!(last as usize % 32, 0);
+            
+            // The pages are kept for the next round (no new pages are taken).
+            free_chain(&heap, last, layout);
+            assert_eq!(heap.stats().unwrap().slab_objects, 0);
+            let last = alloc_chain(&heap, NUM_OBJECTS, layout);
+            assert_eq!(heap.stats().unwrap().num_allocs, pages + 1);
+            free_chain(&heap, last, layout);
+            
+            // The large layouts still take pages.
+            let large = Layout::from_size_align_unchecked(4000, 8);
+            assert!(slab::class_of(&large).is_none());
+            let ptr = core::alloc::GlobalAlloc::alloc(&heap, large);
+            assert!(heap.slab.object_size(ptr).is_none());
+            assert_eq!(heap.find_used(ptr).map(|region| region.addr), Some(ptr as usize));
+            heap.internal_dealloc(ptr).unwrap();
+            
+            assert_eq!(heap.stats().unwrap().free_bytes, initial.free_bytes 
+                - (pages + 1) * PAGE_SIZE);
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Free a small object twice, and free a pointer inside an object (both should be rejected,
+    /// and the object should stay allocated).
+    fn test_slab_invalid_free() {
+        use core::alloc::{GlobalAlloc, Layout};
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::DeallocError;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 8);
+            let mut heap = super::GlobalHeap::new(crate::mem::test::fresh_heap(&scratch));
+            let layout = Layout::from_size_align_unchecked(64, 8);
+            let object = heap.alloc(layout);
+            
+            assert_eq!(heap.internal_dealloc(object.add(8)), Err(DeallocError::Unaligned));
+            assert_eq!(heap.stats().unwrap().slab_objects, 1);
+            
+            assert_eq!(heap.internal_dealloc(object), Ok(()));
+            assert_eq!(heap.internal_dealloc(object), Err(DeallocError::NotAllocated));
+            assert_eq!(heap.stats().unwrap().slab_objects, 0);
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Grow a small object within it's class (it should be kept), then past the largest class (it
+    /// should move to a page, with the contents copied).
+    fn test_slab_realloc() {
+        use core::alloc::{GlobalAlloc, Layout};
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 8);
+            let mut heap = super::GlobalHeap::new(crate::mem::test::fresh_heap(&scratch));
+            let layout = Layout::from_size_align_unchecked(20, 8);
+            let object = heap.alloc(layout);
+            for i in 0..20 {
+                *object.add(i) = i as u8;
+            }
+            
+            assert_eq!(heap.realloc(object, layout, 32), object);
+            
+            let layout = Layout::from_size_align_unchecked(32, 8);
+            let moved = heap.realloc(object, layout, 2000);
+            assert_ne!(moved, object);
+            assert_eq!(heap.find_used(moved).map(|region| region.addr), Some(moved as usize));
+            for i in 0..20 {
+                assert_eq!(*moved.add(i), i as u8);
+            }
+            assert_eq!(heap.stats().unwrap().slab_objects, 0);
+            
+            heap.dealloc(moved, Layout::from_size_align_unchecked(2000, 8));
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// The number of times the exhaustion test heap was mapped, and if address 0 was mapped.
+    static mut MAP_CALLS: usize = 0;
+    static mut MAPPED_ZERO: bool = false;
+    
+    /// A mapper for the exhaustion test which only records the calls (the scratch is mapped).
+    const RECORDING_MAPPER: super::HeapMapper = super::HeapMapper {
+        map: |addr, _, _, _, _| unsafe {
+            MAP_CALLS += 1;
+            MAPPED_ZERO |= addr.as_usize() == 0;
+            Ok(())
+        },
+        unmap: |_, _| Ok(()),
+        grow_meta: || Err(()),
+        translate: crate::mem::vmm::virt_to_phys,
+    };
+    
+    /// Allocate from a small private heap until it's exhausted, and make sure null is returned 
+    /// (and counted) without mapping anything.
+    fn test_exhausted() {
+        use core::alloc::Layout;
+        use crate::mem::region::Region;
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            MAP_CALLS = 0;
+            MAPPED_ZERO = false;
+            
+            // Use a quarter for the metadata (like the other test heaps), and 3 pages for memory.
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 4);
+            let mut heap = super::HeapAlloc::new_with_mapper(RECORDING_MAPPER);
+            heap.init(&Region::new_sized(scratch.addr, PAGE_SIZE), 
+                &[Region::new(scratch.addr + PAGE_SIZE, scratch.end_addr())]);
+            
+            // The second allocation is an exact fit for the rest of the memory (see can_fit).
+            let first = heap.internal_alloc(&Layout::from_size_align_unchecked(PAGE_SIZE, 
+                PAGE_SIZE), false, true, true);
+            let second = heap.internal_alloc(&Layout::from_size_align_unchecked(PAGE_SIZE * 2, 
+                PAGE_SIZE), false, true, true);
+            assert!(!first.is_null() && !second.is_null());
+            assert_eq!(heap.stats().unwrap().free_bytes, 0);
+            assert_eq!(MAP_CALLS, 2);
+            
+            let too_large = Layout::from_size_align_unchecked(1, 1);
+            assert!(heap.internal_alloc(&too_large, false, true, true).is_null());
+            assert_eq!(heap.failed_allocs(), 1);
+            assert_eq!(heap.num_allocs(), 2);
+            assert_eq!(MAP_CALLS, 2);
+            assert!(!MAPPED_ZERO);
+            
+            heap.internal_dealloc(first).unwrap();
+            heap.internal_dealloc(second).unwrap();
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Free a pointer from inside an allocation (it should be rejected and kept), and free the same
+    /// pointer twice (the second one should report that it's not allocated).
+    fn test_invalid_free() {
+        use super::DeallocError;
+        
+        unsafe {
+            let allocs = super::num_allocs();
+            let ptr = super::kmalloc(64, false, true, true);
+            
+            assert_eq!(super::HEAP_ALLOC.internal_dealloc(ptr.add(8)), 
+                Err(DeallocError::Unaligned));
+            assert_eq!(super::num_allocs(), allocs + 1);
+            
+            assert_eq!(super::HEAP_ALLOC.internal_dealloc(ptr), Ok(()));
+            assert_eq!(super::HEAP_ALLOC.internal_dealloc(ptr), Err(DeallocError::NotAllocated));
+            assert_eq!(super::num_allocs(), allocs);
+        }
+    }
+    
+    /// Audit the kernel memory (after the stress tests), and make sure nothing was left behind.
+    fn test_audit_clean() {
+        assert!(unsafe { crate::mem::audit::audit() }.is_clean());
+    }
+    
+    /// Collect the statistics with the interrupts disabled (they should stay disabled), and while
+    /// the heap is locked (it should give up instead of deadlocking).
+    fn test_stats_interrupts_disabled() {
+        use crate::arch::interrupts;
+        
+        unsafe {
+            let were_enabled = interrupts::save_and_disable();
+            let stats = super::stats().unwrap();
+            assert!(!interrupts::are_enabled());
+            assert_eq!(stats.used_bytes + stats.free_bytes, stats.total_bytes);
+            
+            assert!(super::HEAP_ALLOC.mutex.try_lock());
+            assert!(super::stats().is_none());
+            super::HEAP_ALLOC.mutex.release();
+            assert!(!interrupts::are_enabled());
+            
+            interrupts::restore(were_enabled);
+        }
+    }
+    
+    /// Fragment a private heap (free every other allocation), and make sure the statistics and the 
+    /// fragmentation ratio match. The final figures are logged, so the allocator policies can be
+    /// compared.
+    fn test_fragmentation_stress() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        const NUM_ALLOCS: usize = 32;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * NUM_ALLOCS * 2);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let initial = heap.stats().unwrap();
+            assert_eq!((initial.free_blocks, initial.fragmentation_permille()), (1, 0));
+            assert_eq!(initial.total_bytes, initial.free_bytes);
+            
+            // Fill the start of the heap, then free every other allocation.
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE);
+            let mut ptrs: [*mut u8; NUM_ALLOCS] = [core::ptr::null_mut(); NUM_ALLOCS];
+            for ptr in ptrs.iter_mut() {
+                *ptr = heap.internal_alloc(&layout, false, true, true);
+            }
+            for ptr in ptrs.iter().step_by(2) {
+                heap.internal_dealloc(*ptr).unwrap();
+            }
+            
+            // The freed pages are separate blocks (plus the rest of the heap after them).
+            let stats = heap.stats().unwrap();
+            assert_eq!(stats.num_allocs, NUM_ALLOCS / 2);
+            assert_eq!(stats.used_bytes, NUM_ALLOCS / 2 * PAGE_SIZE);
+            assert_eq!(stats.free_blocks, NUM_ALLOCS / 2 + 1);
+            assert_eq!(stats.free_bytes, initial.free_bytes - stats.used_bytes);
+            assert_eq!(stats.max_free, initial.free_bytes - NUM_ALLOCS * PAGE_SIZE);
+            assert_eq!(stats.fragmentation_permille(), 
+                1000 - stats.max_free * 1000 / stats.free_bytes);
+            oxid_log!("Heap fragmentation after the stress test: {} free blocks, mean {} bytes, \
+                max {} bytes, ratio {}/1000.", stats.free_blocks, stats.mean_free(), stats.max_free, 
+                stats.fragmentation_permille());
+            
+            // Freeing the rest merges everything back.
+            for ptr in ptrs.iter().skip(1).step_by(2) {
+                heap.internal_dealloc(*ptr).unwrap();
+            }
+            assert_eq!(heap.stats().unwrap(), initial);
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Split free regions for allocations, including the exact fit (which leaves nothing after).
+    fn test_split_region() {
+        use super::{HeapAlloc, Region};
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        let free_region = Region::new_sized(0x10000, PAGE_SIZE * 4);
+        let (alloc_region, after_region) = HeapAlloc::split_region(&free_region, PAGE_SIZE);
+        assert_eq!((alloc_region.addr, alloc_region.size), (0x10000, PAGE_SIZE));
+        assert_eq!((after_region.addr, after_region.end_addr()), 
+            (0x10000 + PAGE_SIZE, free_region.end_addr()));
+        
+        let (alloc_region, after_region) = HeapAlloc::split_region(&free_region, PAGE_SIZE * 4);
+        assert_eq!(alloc_region.size, free_region.size);
+        assert!(after_region.is_empty());
+        assert_eq!(after_region.addr, free_region.end_addr());
+    }
+    
+    /// Check the boundaries of can_fit (the end of a region is exclusive), and make sure an exact 
+    /// fit is allocated without leaving an empty free region behind.
+    fn test_can_fit() {
+        use core::alloc::Layout;
+        use super::{HeapAlloc, Region};
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        let layout = |size: usize, align: usize| unsafe { 
+            Layout::from_size_align_unchecked(size, align) 
+        };
+        
+        // An exact fit, and one byte too small.
+        let region = Region::new_sized(0x10000, PAGE_SIZE * 2);
+        assert_eq!(HeapAlloc::can_fit(&region, &layout(PAGE_SIZE * 2, PAGE_SIZE)), Ok(0));
+        assert_eq!(HeapAlloc::can_fit(&region, &layout(PAGE_SIZE * 2 + 1, PAGE_SIZE)), Err(()));
+        assert_eq!(HeapAlloc::can_fit(&Region::new_sized(0x10000, PAGE_SIZE * 2 - 1), 
+            &layout(PAGE_SIZE * 2, PAGE_SIZE)), Err(()));
+        
+        // An exact fit after the alignment padding, and one byte too small.
+        let region = Region::new(0x11000, 0x20000 + PAGE_SIZE);
+        assert_eq!(HeapAlloc::can_fit(&region, &layout(PAGE_SIZE, 0x10000)), Ok(0xF000));
+        assert_eq!(HeapAlloc::can_fit(&region, &layout(PAGE_SIZE + 1, 0x10000)), Err(()));
+        
+        // Nothing fits in an empty region (even at an aligned address), or past the address space.
+        assert_eq!(HeapAlloc::can_fit(&Region::new(0x10000, 0x10000), &layout(0, PAGE_SIZE)), 
+            Err(()));
+        assert_eq!(HeapAlloc::can_fit(&Region::new(0x10000, 0x10000), &layout(PAGE_SIZE, 1)), 
+            Err(()));
+        assert_eq!(HeapAlloc::can_fit(&Region::new_sized(usize::MAX - PAGE_SIZE * 2 + 1, 
+            PAGE_SIZE), &layout(PAGE_SIZE * 2, PAGE_SIZE)), Err(()));
+        
+        // A heap which is exactly the size of an allocation gives all of it, without leaving an 
+        // empty free region.
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 4);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let total = heap.stats().unwrap().free_bytes;
+            let ptr = heap.internal_alloc(&layout(total, PAGE_SIZE), false, true, true);
+            assert!(!ptr.is_null());
+            
+            let stats = heap.stats().unwrap();
+            assert_eq!((stats.free_bytes, stats.free_blocks, stats.max_free), (0, 0, 0));
+            assert!(heap.check_integrity().is_ok());
+            
+            heap.internal_dealloc(ptr).unwrap();
+            assert_eq!(heap.stats().unwrap().free_blocks, 1);
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Allocate and free memory in a private heap (which doesn't touch the page tables), and make
+    /// sure the kernel heap is not affected.
+    fn test_fresh_heap() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 8);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let kernel_allocs = super::HEAP_ALLOC.num_allocs();
+            
+            // Every allocation takes at least a page, and they are in the allocation region.
+            let layout = Layout::from_size_align_unchecked(16, PAGE_SIZE);
+            let first = heap.internal_alloc(&layout, false, true, true);
+            let second = heap.internal_alloc(&layout, false, true, true);
+            assert_eq!(heap.num_allocs(), 2);
+            assert_eq!(second as usize, first as usize + PAGE_SIZE);
+            assert!(scratch.includes(first as usize) && scratch.includes(second as usize));
+            
+            // Free the first one, and make sure it's reused.
+            heap.internal_dealloc(first).unwrap();
+            assert_eq!(heap.internal_alloc(&layout, false, true, true), first);
+            
+            heap.internal_dealloc(first).unwrap();
+            heap.internal_dealloc(second).unwrap();
+            assert_eq!(heap.num_allocs(), 0);
+            assert_eq!(super::HEAP_ALLOC.num_allocs(), kernel_allocs);
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Grow an allocation within it's page (the pointer should not change), and then force it to 
+    /// move by growing past an allocation which is right after it (the contents should be copied).
+    fn test_realloc() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 8);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            
+            // Fill a small allocation with a pattern.
+            let layout = Layout::from_size_align_unchecked(16, 8);
+            let ptr = heap.internal_alloc(&layout, false, true, true);
+            for i in 0..16 {
+                *ptr.add(i) = i as u8;
+            }
+            
+            // Growing within the page keeps the pointer.
+            let grown = heap.internal_realloc(ptr, &layout, 100);
+            assert_eq!(grown, ptr);
+            assert_eq!(heap.find_used(ptr).map(|region| region.size), Some(PAGE_SIZE));
+            
+            // Block the next page, and grow past it.
+            let blocker = heap.internal_alloc(&layout, false, true, true);
+            assert_eq!(blocker as usize, ptr as usize + PAGE_SIZE);
+            let layout = Layout::from_size_align_unchecked(100, 8);
+            let moved = heap.internal_realloc(ptr, &layout, PAGE_SIZE * 2);
+            assert_ne!(moved, ptr);
+            assert!(heap.find_used(ptr).is_none());
+            for i in 0..16 {
+                assert_eq!(*moved.add(i), i as u8);
+            }
+            assert_eq!(heap.num_allocs(), 2);
+            
+            heap.internal_dealloc(moved).unwrap();
+            heap.internal_dealloc(blocker).unwrap();
+            assert_eq!(heap.num_allocs(), 0);
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Allocate with an alignment larger than a page in a private heap, and make sure the padding 
+    /// is given back to the free list (and everything is merged after it's freed).
+    fn test_large_alignment() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        const ALIGN: usize = 0x4000;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 32);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            let initial = heap.stats().unwrap();
+            
+            // Take the first page, so the next allocation is (most likely) not aligned.
+            let small = heap.internal_alloc(&Layout::from_size_align_unchecked(16, 8), 
+                false, true, true);
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE, ALIGN);
+            let aligned = heap.internal_alloc(&layout, false, true, true);
+            assert_eq!(aligned as usize % ALIGN, 0);
+            assert_eq!(heap.find_used(aligned).map(|region| region.addr), Some(aligned as usize));
+            
+            // Only the allocations are used (the padding is free).
+            let stats = heap.stats().unwrap();
+            assert_eq!(stats.used_bytes, PAGE_SIZE * 2);
+            assert_eq!(stats.free_bytes, initial.free_bytes - PAGE_SIZE * 2);
+            
+            heap.internal_dealloc(aligned).unwrap();
+            heap.internal_dealloc(small).unwrap();
+            assert_eq!(heap.stats().unwrap(), initial);
+            assert!(heap.free_list.as_ref().unwrap().is_merged());
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// Allocate from the kernel heap with 16KB and 2MB alignments, and make sure they're freed with
+    /// kfree (and the free list is merged again).
+    fn test_kmalloc_aligned() {
+        unsafe {
+            let initial = super::stats().unwrap();
+            
+            for align in [0x4000, 0x200000].iter() {
+                let ptr = super::kmalloc_aligned(100, *align, false, true, true);
+                assert_eq!(ptr as usize % *align, 0);
+                *ptr = 0xCD;
+                assert_eq!(super::num_allocs(), initial.num_allocs + 1);
+                
+                super::kfree(ptr);
+                assert_eq!(super::num_allocs(), initial.num_allocs);
+            }
+            
+            assert_eq!(super::stats().unwrap().free_bytes, initial.free_bytes);
+            assert!(super::HEAP_ALLOC.free_list.as_ref().unwrap().is_merged());
+        }
+    }
+    
+    /// Grow an allocation which is followed by free memory, and make sure it's extended in place
+    /// (the pointer and contents are kept, and the free region shrinks by the extension).
+    fn test_realloc_in_place() {
+        use core::alloc::Layout;
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            let scratch = crate::mem::test::scratch(PAGE_SIZE * 8);
+            let mut heap = crate::mem::test::fresh_heap(&scratch);
+            
+            let layout = Layout::from_size_align_unchecked(PAGE_SIZE, 8);
+            let ptr = heap.internal_alloc(&layout, false, true, true);
+            *ptr = 0xAB;
+            let before = heap.stats().unwrap();
+            
+            // Grow it by two pages (the rest of the heap is right after it).
+            let grown = heap.internal_realloc(ptr, &layout, PAGE_SIZE * 3);
+            assert_eq!(grown, ptr);
+            assert_eq!(*grown, 0xAB);
+            assert_eq!(heap.find_used(ptr).map(|region| region.size), Some(PAGE_SIZE * 3));
+            
+            let after = heap.stats().unwrap();
+            assert_eq!(after.free_bytes, before.free_bytes - PAGE_SIZE * 2);
+            assert_eq!(after.free_blocks, before.free_blocks);
+            assert_eq!(after.num_allocs, 1);
+            
+            // Freeing it gives everything back.
+            heap.internal_dealloc(ptr).unwrap();
+            assert_eq!(heap.stats().unwrap().free_bytes, before.free_bytes + PAGE_SIZE);
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// The line of the allocation which was made by the fake handler (0 until it's made), and True
+    /// once the process is done (if it didn't panic).
+    static mut ISR_LINE: u32 = 0;
+    static mut ISR_DONE: bool = false;
+    
+    /// A process which acts as if it's running in an interrupt handler, and uses both the heap and
+    /// the atomic cache.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn fake_handler(_args: *const crate::proc::process::Args) -> usize {
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            super::FAKE_ISR_PID = crate::proc::scheduler::current_pid();
+            
+            // The atomic pages are not counted.
+            let page = super::kmalloc_atomic(PAGE_SIZE);
+            assert!(!page.is_null());
+            super::kfree(page);
+            
+            // It panics here with the heap-debug feature.
+            ISR_LINE = line!() + 1;
+            let ptr = super::kmalloc(PAGE_SIZE, false, true, true);
+            super::kfree(ptr);
+            
+            super::FAKE_ISR_PID = None;
+            ISR_DONE = true;
+        }
+        0
+    }
+    
+    /// Use the heap from a fake interrupt handler, and make sure it's counted with the caller's 
+    /// location (and that it panics with the heap-debug feature).
+    fn test_isr_check() {
+        use crate::proc::scheduler;
+        
+        unsafe {
+            let uses = super::ISR_USES;
+            super::ISR_CALLER = None;
+            let mut args = crate::proc::process::Args::new();
+            let handle = scheduler::spawn_with_flags(fake_handler, &mut args, "isr_alloc_test",
+                crate::proc::process::SpawnFlags::NONE);
+            
+            // Wait (for up to 2 seconds) for the scheduler to remove it.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while scheduler::find_handle(handle, |_| ()).is_some() 
+                && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert!(scheduler::find_handle(handle, |_| ()).is_none());
+            super::FAKE_ISR_PID = None;
+            super::ISR_REPORTED = false;
+            
+            // The allocation was the first use (and the free, if it didn't panic).
+            let (after, caller) = super::isr_uses();
+            assert_eq!(caller.map(|caller| caller.line()), Some(ISR_LINE));
+            assert!(caller.unwrap().file().ends_with("mod.rs"));
+            if cfg!(feature = "heap-debug") {
+                assert!(!ISR_DONE);
+                assert_eq!(scheduler::last_failure(), 
+                    Some((handle.pid(), scheduler::PANIC_EXIT_CODE)));
+            } else {
+                assert!(ISR_DONE);
+                assert_eq!(after, uses + 2);
+            }
+            
+            // The fake handler is over, so the heap is not counted anymore.
+            let ptr = super::kmalloc(crate::mem::vmm::PAGE_SIZE, false, true, true);
+            super::kfree(ptr);
+            assert_eq!(super::isr_uses().0, after);
+        }
+    }
+    
+    /// Take every page of the atomic cache, and make sure the next allocation fails (until one is 
+    /// freed), and that the pages are always zeroed when they're handed out.
+    fn test_kmalloc_atomic() {
+        use alloc::vec::Vec;
+        use crate::mem::vmm::PAGE_SIZE;
+        use super::atomic;
+        
+        unsafe {
+            let failures = atomic::failures();
+            let pages: Vec<*mut u8> = (0..atomic::num_free())
+                .map(|_| super::kmalloc_atomic(PAGE_SIZE)).collect();
+            assert!(pages.iter().all(|page| !page.is_null() && *page as usize % PAGE_SIZE == 0));
+            assert_eq!(atomic::num_free(), 0);
+            
+            // Dirty the pages, the cache is empty now.
+            for page in pages.iter() {
+                crate::olibc::memset::memset(*page, 0xAB, PAGE_SIZE);
+            }
+            assert!(super::kmalloc_atomic(8).is_null());
+            assert_eq!(atomic::failures(), failures + 1);
+            
+            // A freed page is zeroed, and handed out again.
+            super::kfree(pages[0]);
+            assert!(super::kmalloc_atomic(PAGE_SIZE + 1).is_null());
+            let reused = super::kmalloc_atomic(16);
+            assert_eq!(reused, pages[0]);
+            assert!((0..PAGE_SIZE).all(|idx| *reused.add(idx) == 0));
+            assert_eq!(atomic::failures(), failures + 2);
+            
+            // The invalid frees are rejected.
+            assert_eq!(atomic::free(reused.add(8)), Err(super::DeallocError::Unaligned));
+            for page in pages.iter() {
+                assert_eq!(atomic::free(*page), Ok(()));
+            }
+            assert_eq!(atomic::free(pages[1]), Err(super::DeallocError::NotAllocated));
+            assert_eq!(atomic::num_free(), atomic::NUM_PAGES);
+        }
     }
 }
@@ -0,0 +1,325 @@
+//! A sub-module which implements a small object (slab) layer in front of the heap. The heap rounds
+//! every allocation up to a page, so the small objects are carved from pages which are taken from
+//! the heap instead. Each page holds objects of a single size class, and the free objects of each
+//! class are kept in a linked list (the next pointer is stored in the free object itself). Every
+//! slab page starts with a header which has it's class and a bitmap of the used objects (so the
+//! invalid frees can be detected). The addresses of the slab pages are kept sorted in a directory
+//! page, so a free can find out if a pointer belongs to a slab.
+//!
+//! The slab pages are kept once they're carved (they're reused by the same class, and never given
+//! back to the heap).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::alloc::Layout;
+use crate::mem::vmm::PAGE_SIZE;
+use crate::proc::mutex::Mutex;
+use super::DeallocError;
+
+/// The sizes of the objects in each class (in bytes).
+pub const SIZE_CLASSES: [usize; 7] = [16, 32, 64, 128, 256, 512, 1024];
+
+/// The number of size classes.
+pub const NUM_CLASSES: usize = SIZE_CLASSES.len();
+
+/// The most slab pages which can be tracked (the directory is a single page).
+pub const MAX_PAGES: usize = PAGE_SIZE / core::mem::size_of::<usize>();
+
+/// The number of words in the bitmap of each page (enough for the smallest class).
+const BITMAP_WORDS: usize = PAGE_SIZE / SIZE_CLASSES[0] / 64;
+
+/// A structure which represents the header at the start of every slab page.
+#[repr(C)]
+struct SlabHeader {
+    class: usize,                              // The index of the size class.
+    in_use: usize,                             // The number of allocated objects.
+    used: [u64; BITMAP_WORDS],                 // A bit for each object (set if it's allocated).
+}
+
+/// A structure which represents the slab layer of a heap.
+pub struct SlabCache {
+    free: [usize; NUM_CLASSES],                // The first free object of each class (0 if none).
+    pages: *mut usize,                         // The directory (sorted slab page addresses).
+    num_pages: usize,                          // The number of pages in the directory.
+    num_objects: usize,                        // The number of allocated objects.
+    mutex: Mutex,                              // To keep the lists memory safe.
+}
+
+/// A function which finds the size class for a layout. The objects are aligned to their size, so
+/// the class should be large enough for the size and the alignment.
+///
+/// # Parameters
+/// `layout` : The size and alignment requested.
+///
+/// # Returns
+/// Some(class_idx), or None if it's too large for the slabs.
+pub fn class_of(layout: &Layout) -> Option<usize> {
+    let needed = core::cmp::max(layout.size(), layout.align());
+    SIZE_CLASSES.iter().position(|size| *size >= needed)
+}
+
+/// A function which finds the offset of the first object in a page of a class (right after the
+/// header, aligned to the object size).
+///
+/// # Parameters
+/// `class` : The index of the size class.
+///
+/// # Returns
+/// The offset from the start of the page.
+fn first_offset(class: usize) -> usize {
+    crate::mem::align::align_higher(core::mem::size_of::<SlabHeader>(), SIZE_CLASSES[class])
+}
+
+/// A function which calculates the number of objects in a page of a class.
+///
+/// # Parameters
+/// `class` : The index of the size class.
+///
+/// # Returns
+/// The number of objects in each page.
+pub fn objects_per_page(class: usize) -> usize {
+    (PAGE_SIZE - first_offset(class)) / SIZE_CLASSES[class]
+}
+
+impl SlabCache {
+    /// A constructor which creates an empty slab layer (without a directory).
+    pub const fn new() -> Self {
+        SlabCache {
+            free: [0; NUM_CLASSES],
+            pages: core::ptr::null_mut(),
+            num_pages: 0,
+            num_objects: 0,
+            mutex: Mutex::new(),
+        }
+    }
+
+    /// A method which checks if the directory was set.
+    ///
+    /// # Returns
+    /// True if it has a directory, False otherwise.
+    pub fn has_directory(&self) -> bool {
+        !self.pages.is_null()
+    }
+
+    /// A method which sets the page which is used as the directory (it should be zeroed).
+    ///
+    /// # Parameters
+    /// `page` : The address of the page.
+    ///
+    /// # Returns
+    /// Ok if it was set, Err if there already is a directory.
+    pub unsafe fn set_directory(&mut self, page: *mut u8) -> Result<(), ()> {
+        let were_enabled = self.mutex.lock_irqsave();
+        let result = if self.pages.is_null() {
+            self.pages = page as *mut usize;
+            Ok(())
+        } else {
+            Err(())
+        };
+        self.mutex.unlock_irqrestore(were_enabled);
+
+        result
+    }
+
+    /// A method which checks if the directory is full (so no more pages can be added).
+    ///
+    /// # Returns
+    /// True if it's full, False otherwise.
+    pub fn is_full(&self) -> bool {
+        self.num_pages == MAX_PAGES
+    }
+
+    /// A method which checks if the slab layer is locked (by anyone).
+    ///
+    /// # Returns
+    /// True if it's locked, False otherwise.
+    pub fn is_locked(&self) -> bool {
+        self.mutex.is_locked()
+    }
+
+    /// A simple getter for the number of slab pages.
+    ///
+    /// # Returns
+    /// The number of pages which were carved into objects.
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    /// A simple getter for the number of allocated objects.
+    ///
+    /// # Returns
+    /// The number of objects which were not freed yet.
+    pub fn num_objects(&self) -> usize {
+        self.num_objects
+    }
+
+    /// A method which allocates an object from the free list of a class. The first word of the
+    /// object (which held the free list link) is cleared, but the rest is not zeroed.
+    ///
+    /// # Parameters
+    /// `class` : The index of the size class.
+    ///
+    /// # Returns
+    /// Some(object_ptr), or None if the class doesn't have any free objects.
+    pub unsafe fn alloc(&mut self, class: usize) -> Option<*mut u8> {
+        let were_enabled = self.mutex.lock_irqsave();
+
+        let object = self.free[class];
+        if object == 0 {
+            self.mutex.unlock_irqrestore(were_enabled);
+            return None;
+        }
+
+        // Take it off the list (clearing the link, so it doesn't leak into the object), and mark it
+        // as used.
+        self.free[class] = *(object as *const usize);
+        *(object as *mut usize) = 0;
+        let (header, idx) = SlabCache::locate(object);
+        (*header).used[idx / 64] |= 1 << (idx % 64);
+        (*header).in_use += 1;
+        self.num_objects += 1;
+
+        self.mutex.unlock_irqrestore(were_enabled);
+
+        Some(object as *mut u8)
+    }
+
+    /// A method which carves a new page into the objects of a class, and adds them to it's free
+    /// list. The page should be zeroed, and aligned to PAGE_SIZE.
+    ///
+    /// # Parameters
+    /// `page` : The address of the page (taken from the heap).
+    /// `class` : The index of the size class.
+    ///
+    /// # Returns
+    /// Ok if it was added, Err if there is no directory or it's full.
+    pub unsafe fn add_page(&mut self, page: *mut u8, class: usize) -> Result<(), ()> {
+        let were_enabled = self.mutex.lock_irqsave();
+
+        if self.pages.is_null() || self.is_full() {
+            self.mutex.unlock_irqrestore(were_enabled);
+            return Err(());
+        }
+
+        // Insert it to the directory (keeping it sorted).
+        let addr = page as usize;
+        let directory = core::slice::from_raw_parts_mut(self.pages, self.num_pages + 1);
+        let idx = directory[..self.num_pages].binary_search(&addr).unwrap_or_else(|idx| idx);
+        directory[idx..].rotate_right(1);
+        directory[idx] = addr;
+        self.num_pages += 1;
+
+        // Set up the header, and push the objects (the last one first, so they're used in order).
+        let header = page as *mut SlabHeader;
+        (*header).class = class;
+        (*header).in_use = 0;
+        (*header).used = [0; BITMAP_WORDS];
+
+        let size = SIZE_CLASSES[class];
+        for idx in (0..objects_per_page(class)).rev() {
+            let object = addr + first_offset(class) + idx * size;
+            *(object as *mut usize) = self.free[class];
+            self.free[class] = object;
+        }
+
+        self.mutex.unlock_irqrestore(were_enabled);
+
+        Ok(())
+    }
+
+    /// A method which frees an object (if the pointer is in a slab page).
+    ///
+    /// # Parameters
+    /// `ptr` : The pointer which is being freed.
+    ///
+    /// # Returns
+    /// None if it's not in a slab page, Some(Ok) if it was freed, Some(Err) if it's not the start
+    /// of an object (Unaligned), or the object is already free (NotAllocated).
+    pub unsafe fn free(&mut self, ptr: *mut u8) -> Option<Result<(), DeallocError>> {
+        let were_enabled = self.mutex.lock_irqsave();
+
+        if !self.owns(ptr as usize) {
+            self.mutex.unlock_irqrestore(were_enabled);
+            return None;
+        }
+
+        let object = ptr as usize;
+        let (header, idx) = SlabCache::locate(object);
+        let class = (*header).class;
+        let offset = object % PAGE_SIZE;
+
+        // The header, the end of the page, and the addresses inside the objects were never
+        // returned by alloc.
+        let result = if offset < first_offset(class)
+            || (offset - first_offset(class)) % SIZE_CLASSES[class] != 0
+            || idx >= objects_per_page(class) {
+            Err(DeallocError::Unaligned)
+        } else if (*header).used[idx / 64] & (1 << (idx % 64)) == 0 {
+            Err(DeallocError::NotAllocated)
+        } else {
+            (*header).used[idx / 64] &= !(1 << (idx % 64));
+            (*header).in_use -= 1;
+            self.num_objects -= 1;
+
+            *(object as *mut usize) = self.free[class];
+            self.free[class] = object;
+            Ok(())
+        };
+
+        self.mutex.unlock_irqrestore(were_enabled);
+
+        Some(result)
+    }
+
+    /// A method which finds the size of the object which holds a pointer.
+    ///
+    /// # Parameters
+    /// `ptr` : The pointer to look up.
+    ///
+    /// # Returns
+    /// Some(object_size) if it's in a slab page, None otherwise.
+    pub unsafe fn object_size(&mut self, ptr: *mut u8) -> Option<usize> {
+        let were_enabled = self.mutex.lock_irqsave();
+        let size = if self.owns(ptr as usize) {
+            Some(SIZE_CLASSES[(*SlabCache::locate(ptr as usize).0).class])
+        } else {
+            None
+        };
+        self.mutex.unlock_irqrestore(were_enabled);
+
+        size
+    }
+
+    /// A helper which checks if an address is in a slab page (it should be called while locked).
+    ///
+    /// # Parameters
+    /// `addr` : The address to look up.
+    ///
+    /// # Returns
+    /// True if it's in a page in the directory, False otherwise.
+    unsafe fn owns(&self, addr: usize) -> bool {
+        if self.pages.is_null() {
+            return false;
+        }
+
+        let page = crate::mem::align::align_lower(addr, PAGE_SIZE);
+        core::slice::from_raw_parts(self.pages, self.num_pages).binary_search(&page).is_ok()
+    }
+
+    /// A helper which finds the header of the page which holds an object, and the object's index.
+    ///
+    /// # Parameters
+    /// `object` : The address of the object (it should be in a slab page).
+    ///
+    /// # Returns
+    /// A tuple with the header pointer and the object index.
+    unsafe fn locate(object: usize) -> (*mut SlabHeader, usize) {
+        let header = crate::mem::align::align_lower(object, PAGE_SIZE) as *mut SlabHeader;
+        let offset = (object % PAGE_SIZE).saturating_sub(first_offset((*header).class));
+
+        (header, offset / SIZE_CLASSES[(*header).class])
+    }
+}
@@ -0,0 +1,279 @@
+//! A sub-module which provides a bump allocator for the allocations which are needed before the
+//! kernel heap is initialized. The heap depends on the virtual memory manager, which needs frames
+//! for the new page tables, so nothing in that chain can use the heap itself. The early allocator
+//! hands out memory from a small region which is reserved in the frame allocator (while it's
+//! initialized) and stays identity mapped, so it doesn't need the page tables at all. It's only
+//! valid until the heap is initialized (it's closed by mem::init), and any use after that is a bug.
+//! The memory is never freed.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::proc::mutex::Mutex;
+use crate::mem::region::Region;
+
+/// The size of the region which is reserved for the early allocations (64KB).
+pub const EARLY_ALLOC_SIZE: usize = 0x10000;
+
+/// The global early allocator (set by frame_alloc::init).
+static mut EARLY_ALLOCATOR: Option<EarlyAlloc> = None;
+
+/// A mutex for the safe access to the global early allocator.
+static mut EARLY_ALLOCATOR_MUTEX: Mutex = Mutex::new();
+
+/// An enum which represents the reasons an early allocation could fail.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EarlyAllocError {
+    NotInitialized,                     // The region was not reserved yet.
+    Closed,                             // The heap was already initialized (use the heap).
+    Full,                               // There is not enough space left in the region.
+    InvalidAlign,                       // The alignment was not a power of two.
+}
+
+/// A structure which represents a bump allocator over a region.
+pub struct EarlyAlloc {
+    region: Region,                     // The region which the allocations are made from.
+    next: usize,                        // The address of the first free byte in the region.
+    closed: bool,                       // True if no more allocations are accepted.
+}
+
+impl EarlyAlloc {
+    /// A constructor which creates an empty allocator over a region (which should be mapped).
+    ///
+    /// # Parameters
+    /// `region` : The region which the allocations are made from.
+    ///
+    /// # Returns
+    /// The newly created allocator.
+    pub fn new(region: &Region) -> Self {
+        EarlyAlloc {
+            region: *region,
+            next: region.addr,
+            closed: false,
+        }
+    }
+
+    /// A method which allocates a zeroed block of memory from the region.
+    ///
+    /// # Parameters
+    /// `size` : The size of the allocation in bytes.
+    /// `align` : The alignment of the allocation (a power of two).
+    ///
+    /// # Returns
+    /// Ok(pointer) to the allocated block, or the reason if it was not possible.
+    pub fn alloc(&mut self, size: usize, align: usize) -> Result<*mut u8, EarlyAllocError> {
+        if self.closed {
+            return Err(EarlyAllocError::Closed);
+        }
+
+        if !align.is_power_of_two() {
+            return Err(EarlyAllocError::InvalidAlign);
+        }
+
+        // Bump the pointer (make sure it doesn't overflow, or go past the region).
+        let addr = crate::mem::align::align_higher(self.next, align);
+        let end_addr = match addr.checked_add(size) {
+            Some(end_addr) if end_addr <= self.region.end_addr() => end_addr,
+            _ => return Err(EarlyAllocError::Full),
+        };
+
+        self.next = end_addr;
+        unsafe { core::ptr::write_bytes(addr as *mut u8, 0, size); }
+        Ok(addr as *mut u8)
+    }
+
+    /// A method which stops any further allocations (the allocated blocks are still valid).
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// A getter for the state of the allocator.
+    ///
+    /// # Returns
+    /// True if it's closed, False otherwise.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// A getter for the region which the allocations are made from.
+    ///
+    /// # Returns
+    /// A copy of the region.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// A getter for the number of bytes which were handed out (including the alignment padding).
+    ///
+    /// # Returns
+    /// The number of used bytes.
+    pub fn used(&self) -> usize {
+        self.next - self.region.addr
+    }
+}
+
+/// A function which initializes the global early allocator over a region. It is called by the
+/// frame allocator once the region is reserved, and the region should be identity mapped.
+///
+/// # Parameters
+/// `region` : The physical region which is reserved for the early allocations.
+pub unsafe fn init(region: &Region) {
+    EARLY_ALLOCATOR = Some(EarlyAlloc::new(region));
+    oxid_log!("Reserved 0x{:x}-0x{:x} for the early allocations.", region.addr,
+        region.end_addr());
+}
+
+/// A function which allocates a zeroed block of memory before the heap is initialized. Using it
+/// after the heap is initialized is a bug, so it panics in that case (and if it's full).
+///
+/// # Parameters
+/// `size` : The size of the allocation in bytes.
+/// `align` : The alignment of the allocation (a power of two).
+///
+/// # Returns
+/// A pointer to the allocated block (which is never freed).
+pub fn alloc(size: usize, align: usize) -> *mut u8 {
+    match try_alloc(size, align) {
+        Ok(ptr) => ptr,
+        Err(EarlyAllocError::Closed) => panic!("Early allocation after the heap was initialized."),
+        Err(err) => panic!("Early allocation of {} bytes failed ({:?}).", size, err),
+    }
+}
+
+/// A function which allocates a zeroed block of memory before the heap is initialized, without
+/// panicking on the failures.
+///
+/// # Parameters
+/// `size` : The size of the allocation in bytes.
+/// `align` : The alignment of the allocation (a power of two).
+///
+/// # Returns
+/// Ok(pointer) to the allocated block, or the reason if it was not possible.
+pub fn try_alloc(size: usize, align: usize) -> Result<*mut u8, EarlyAllocError> {
+    unsafe {
+        let allocator = match &mut (EARLY_ALLOCATOR) {
+            Some(allocator) => allocator,
+            None => return Err(EarlyAllocError::NotInitialized),
+        };
+
+        EARLY_ALLOCATOR_MUTEX.lock();
+        let result = allocator.alloc(size, align);
+        EARLY_ALLOCATOR_MUTEX.unlock();
+
+        result
+    }
+}
+
+/// A function which closes the global early allocator (called by mem::init once the heap is
+/// initialized). Any allocation after this point panics.
+pub fn close() {
+    unsafe {
+        if let Some(allocator) = &mut (EARLY_ALLOCATOR) {
+            EARLY_ALLOCATOR_MUTEX.lock();
+            allocator.close();
+            EARLY_ALLOCATOR_MUTEX.unlock();
+
+            oxid_log!("Closed the early allocator ({} of {} bytes used).", allocator.used(),
+                allocator.region().size);
+        }
+    }
+}
+
+/// A function which checks if the global early allocator was closed.
+///
+/// # Returns
+/// True if it's closed (or was never initialized), False otherwise.
+pub fn is_closed() -> bool {
+    unsafe { (&EARLY_ALLOCATOR).as_ref().map_or(true, |allocator| allocator.is_closed()) }
+}
+
+/// A getter for the region of the global early allocator.
+///
+/// # Returns
+/// Some(region) if it was initialized, None otherwise.
+pub fn region() -> Option<Region> {
+    unsafe { (&EARLY_ALLOCATOR).as_ref().map(|allocator| allocator.region()) }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{EarlyAlloc, EarlyAllocError};
+    use crate::mem::vmm::PAGE_SIZE;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_bump();
+        test_full();
+        test_closed();
+        test_kernel_instance();
+    }
+
+    /// Allocate a few blocks from a private instance, and make sure they're aligned, zeroed, and
+    /// don't overlap.
+    fn test_bump() {
+        let scratch = crate::mem::test::scratch(PAGE_SIZE);
+        unsafe { core::ptr::write_bytes(scratch.addr as *mut u8, 0xAB, scratch.size); }
+        let mut early = EarlyAlloc::new(&scratch);
+
+        let first = early.alloc(3, 1).unwrap() as usize;
+        let second = early.alloc(16, 16).unwrap() as usize;
+        assert_eq!(first, scratch.addr);
+        assert_eq!(second % 16, 0);
+        assert!(second >= first + 3);
+        assert_eq!(early.used(), second + 16 - scratch.addr);
+        assert_eq!(unsafe { *(second as *const u8) }, 0);
+        assert_eq!(early.alloc(8, 3), Err(EarlyAllocError::InvalidAlign));
+
+        crate::mem::test::free_scratch(&scratch);
+    }
+
+    /// Make sure an instance can be filled exactly, and fails after that.
+    fn test_full() {
+        let scratch = crate::mem::test::scratch(PAGE_SIZE);
+        let mut early = EarlyAlloc::new(&scratch);
+
+        assert!(early.alloc(PAGE_SIZE / 2, 8).is_ok());
+        assert_eq!(early.alloc(PAGE_SIZE, 8), Err(EarlyAllocError::Full));
+        assert_eq!(early.alloc(usize::MAX, 1), Err(EarlyAllocError::Full));
+        assert!(early.alloc(PAGE_SIZE / 2, 8).is_ok());
+        assert_eq!(early.alloc(1, 1), Err(EarlyAllocError::Full));
+
+        crate::mem::test::free_scratch(&scratch);
+    }
+
+    /// Make sure a closed instance doesn't hand out any memory.
+    fn test_closed() {
+        let scratch = crate::mem::test::scratch(PAGE_SIZE);
+        let mut early = EarlyAlloc::new(&scratch);
+
+        assert!(early.alloc(8, 8).is_ok());
+        early.close();
+        assert!(early.is_closed());
+        assert_eq!(early.alloc(8, 8), Err(EarlyAllocError::Closed));
+        assert_eq!(early.used(), 8);
+
+        crate::mem::test::free_scratch(&scratch);
+    }
+
+    /// Make sure the kernel's instance was closed once the heap was initialized, it's frames are
+    /// reserved, and it doesn't overlap the heap.
+    fn test_kernel_instance() {
+        assert!(super::is_closed());
+        assert_eq!(super::try_alloc(8, 8), Err(EarlyAllocError::Closed));
+
+        let region = super::region().unwrap();
+        assert_eq!(region.size, super::EARLY_ALLOC_SIZE);
+        let used = crate::mem::frame_alloc::is_used(crate::mem::addr::PhysAddr::new(region.addr));
+        assert_eq!(used, Ok(true));
+
+        let report = unsafe { crate::mem::audit::audit() };
+        assert_eq!(report.early_overlaps, 0);
+    }
+}
@@ -9,17 +9,21 @@
 
 use crate::mem::bitwise::BitWise;   // To allow setting or reading bit by bit.
 use crate::mem::region::Region;       // To get and utilize memory regions.
+use crate::multiboot2::mem_map::{MemMap, MemMapEntType};
+use crate::multiboot2::efi_mem_map::EfiMemMap;
 
 /// Represents how many bits are present in each bit field.
 const NUM_BITS_PER_FIELD: usize = core::mem::size_of::<usize>() * 8;
 
-/// A structure which represents the main bitmap. It will be initialized by a starting address of 
-/// the free memory, and the size of it. It will use as many bits as necessary to manage the bits.
+/// A structure which represents the main bitmap. It will be initialized by the region of the memory
+/// which it manages, and the address of it's bits. It will use as many bits as necessary to manage
+/// the frames. A second level summary (a bit per field of the map, which is set if the field has a
+/// free frame) is kept after the bits, so the allocations jump directly to a field which has one.
 pub struct BitMap {
     frames_start: usize,            // The starting address of the first allocatable frame.
     frames_count: usize,            // The number of frames managed by this bit field.
     map: &'static mut [usize],      // The actual bit field utilized.
-    first_free: usize,               // The first map idx which has a free bit (optimization).
+    summary: &'static mut [usize],  // A bit per field of the map (set if it has a free frame).
 }
 
 /// An enum which represents the results for the allocation of the bitmap allocator.
@@ -28,52 +32,45 @@ pub enum BitMapResult {
     AlreadyUsed,                    // The result when the wanted frame was already used.
     Full,                           // The result when there are no more free frames.
     InvalidFrameNum,                // When an invalid frame number was passed.
+    Freed,                          // The result when the frames were successfuly deallocated.
+    WasAlreadyFree,                 // The result when a frame was not allocated (double free).
+}
+
+/// An iterator over the ranges of the used frames in a bitmap (the consecutive used frames are
+/// coalesced into a single region). It doesn't allocate, so it can be used before the heap is
+/// initialized (and from the panic handler).
+pub struct UsedRanges<'a> {
+    bitmap: &'a BitMap,             // The bitmap which we're iterating over.
+    next_frame: usize,              // The frame number where the search for the next range starts.
 }
 
 impl BitMap {
-    /// A function which initializes a bitmap by getting a start address, and a size. This is 
-    /// extremely unsafe since it assumes that the start address and whatever is within the range
-    /// is accessible to use. Please make sure that memory addresses 0 to the address that you get 
-    /// from the frame_alloc::get_first_frame_addr() are all identity mapped before calling.
+    /// A function which initializes a bitmap which manages the frames of a region, and keeps it's
+    /// bits at a given address. This is extremely unsafe since it assumes that the storage is
+    /// accessible to use (identity mapped), and it's not used for anything else. The storage is not
+    /// marked used by the bitmap, so if it's within the managed region, the caller should mark it.
     ///
     /// # Parameters
-    /// `usable_region` : The area after the kernel where the frames will be allocated.).
+    /// `managed_region` : The physical memory which is managed by the bitmap (it can start at 0).
+    /// `storage_addr` : The address where the bits (and the summary) are stored (it needs
+    /// storage_size bytes).
     ///
     /// # Returns
     /// The newly created bitmap which should be already purged.
-    pub unsafe fn new(usable_region: &Region) -> Self {
-        // Create a new region with aligned start and end addresses.
-        let aligned_usable_region = 
-            Region::new_aligned(usable_region.addr, usable_region.end_addr(), super::ALIGNMENT);
-        
-        // Define the start and end addresses
-        let aligned_start_addr: usize = aligned_usable_region.addr;
-        let aligned_end_addr: usize = aligned_usable_region.end_addr();
-    
-        // Calculate the size of the bitmap (how many bytes we need to represent every frame).
-        let mut bitmap_size: usize = ((aligned_end_addr - aligned_start_addr) 
-            / super::FRAME_SIZE) / 8;
-        
-        // Check for when the number of frames is less than 8 (a byte).
-        if bitmap_size == 0 {
-            bitmap_size = 1;
-        }
-        
-        // Calculate the address of the first allocatable physical frame while considering the 
-        // bit map (which we know the size of right now).
-        let start_frames: usize = 
-            crate::mem::align::align_higher(aligned_start_addr + bitmap_size, super::ALIGNMENT);
-            
-        // Calculate how many frames we have considering the bitmap.
-        let num_frames: usize = (aligned_end_addr - start_frames) / super::FRAME_SIZE;
+    pub unsafe fn new(managed_region: &Region, storage_addr: usize) -> Self {
+        // Create a new region with aligned start and end addresses, and count it's frames.
+        let aligned_region = Region::new_aligned(managed_region.addr, managed_region.end_addr(),
+            super::ALIGNMENT);
+        let num_frames: usize = aligned_region.size / super::FRAME_SIZE;
+        let num_fields = div_round_up(num_frames, NUM_BITS_PER_FIELD);
         
-        // Create and return the bitmap.
+        // Create and return the bitmap (the summary is right after the bits).
         let mut to_return = BitMap {
-            frames_start: start_frames,         // Store the frame count, and start addr.
+            frames_start: aligned_region.addr,  // Store the frame count, and start addr.
             frames_count: num_frames,           // Convert the raw pointer to a static slice.
-            map: core::slice::from_raw_parts_mut(aligned_start_addr as *mut usize
-                , num_frames / NUM_BITS_PER_FIELD),
-            first_free: 0,                       // All is free now.
+            map: core::slice::from_raw_parts_mut(storage_addr as *mut usize, num_fields),
+            summary: core::slice::from_raw_parts_mut((storage_addr as *mut usize).add(num_fields),
+                div_round_up(num_fields, NUM_BITS_PER_FIELD)),
         };
         
         // Purge and return the newly created bitmap.
@@ -81,16 +78,49 @@ impl BitMap {
         to_return
     }
     
+    /// A function which initializes a bitmap which keeps it's bits at the start of a region, and
+    /// manages the frames after them (it's used for the private bitmaps, such as in the tests).
+    ///
+    /// # Parameters
+    /// `region` : The region which has the bitmap and it's frames.
+    ///
+    /// # Returns
+    /// The newly created bitmap which should be already purged.
+    pub unsafe fn new_in_place(region: &Region) -> Self {
+        let aligned_region = Region::new_aligned(region.addr, region.end_addr(), super::ALIGNMENT);
+        
+        // The bits are sized for the whole region (so they're enough for the frames after them).
+        let storage_size = core::cmp::max(1, storage_size(aligned_region.size / super::FRAME_SIZE));
+        let frames_start =
+            crate::mem::align::align_higher(aligned_region.addr + storage_size, super::ALIGNMENT);
+        let frames_end = core::cmp::max(frames_start, aligned_region.end_addr());
+        
+        BitMap::new(&Region::new(frames_start, frames_end), aligned_region.addr)
+    }
+    
     /// A function which purges the bitmap and basically deallocates all the memory. It does not 
     /// actually purge the memory, just the allocation bitmap of it. It should be called after the 
-    /// kernel is identity mapped up to frames_start.
+    /// kernel is identity mapped up to frames_start. The bits after the last frame (in the last
+    /// bit field) are set, so they're never handed out.
     pub fn purge(&mut self) {
         // Go through every item, and purge the fields.
         for i in 0..self.map.len() {
             self.map[i] = 0;
         }
         
-        self.first_free = 0;
+        // Mark the bits which don't have a frame as used.
+        let tail_bits = self.frames_count % NUM_BITS_PER_FIELD;
+        if let (Some(last), true) = (self.map.last_mut(), tail_bits != 0) {
+            *last = !low_bits(tail_bits);
+        }
+        
+        // Rebuild the summary (the last field might be full if it only has the tail bits).
+        for i in 0..self.summary.len() {
+            self.summary[i] = 0;
+        }
+        for i in 0..self.map.len() {
+            self.update_summary(i);
+        }
     }
     
     /// A method which finds the first available free frame, and allocates it. It then returns the 
@@ -99,18 +129,12 @@ impl BitMap {
     /// # Returns
     /// BitMapResult::Allocated(frame_number) if successful. BitMapResult::Full otherwise.
     pub fn alloc(&mut self) -> BitMapResult {
-        // Go through every bit field (Starting from last free), and check if it has an empty one.       
-        for i in self.first_free..self.map.len() {
-            // Check if even one of the bits is empty.
-            if has_free(&self.map[i]) {
-                // Get the empty bit's number. We already checked, so it should have one empty.
-                let free_bit_num = get_free(&self.map[i]).unwrap();
-                
-                // Set the free bit to used.
+        // Jump to the first field which has a free bit (the full ones are skipped by the summary).
+        if let Some(i) = self.first_free_field() {
+            if let Some(free_bit_num) = get_free(&self.map[i]) {
+                // Set the free bit to used, and update the summary (if the field is full now).
                 self.map[i].set_bit(free_bit_num);
-                
-                // Store the new first free index (since we searched to find it).
-                self.first_free = i;
+                self.update_summary(i);
                 
                 // Calculate the frame number for the given free bit, and return it.
                 return BitMapResult::Allocated((i * NUM_BITS_PER_FIELD) + free_bit_num);
@@ -132,7 +156,7 @@ impl BitMap {
     /// BitMapResult::AlreadyUsed if the frame was already used.
     /// BitMapResult::InvalidFrameNum if the passed frame number is out of range.
     pub fn alloc_frame_num(&mut self, frame_num: usize) -> BitMapResult {
-        // Check if the frame_num is invalid.
+        // Check if the frame_num is invalid (the bits after the last frame are always set).
         if frame_num >= self.frames_count {
             return BitMapResult::InvalidFrameNum;
         }
@@ -145,8 +169,9 @@ impl BitMap {
         
         // Check if the frame is already used. Otherwise, set it.
         if self.map[map_idx].is_clear(bit_num) {
-            // Set it, and return the success result.
+            // Set it, update the summary, and return the success result.
             self.map[map_idx].set_bit(bit_num);
+            self.update_summary(map_idx);
             BitMapResult::Allocated(frame_num)
         } else {
             // If we get here, the frame number is already used.
@@ -155,26 +180,332 @@ impl BitMap {
     }
     
     /// A method which deallocates the given frame with the frame number. It simply resets
-    /// the value in the bitfield corresponding to the frame number.
+    /// the value in the bitfield corresponding to the frame number. A frame which is already free
+    /// is left alone (freeing it twice means two owners thought they had it).
     ///
     /// # Parameters
     /// `frame_num` : The number of the frame which we want to deallocate.
-    pub fn dealloc(&mut self, frame_num: usize) {
+    ///
+    /// # Returns
+    /// BitMapResult::Freed if it was deallocated, BitMapResult::WasAlreadyFree if it was not
+    /// allocated, or BitMapResult::InvalidFrameNum if it's out of range.
+    pub fn dealloc(&mut self, frame_num: usize) -> BitMapResult {
         // Check if the frame_num is valid.
-        if frame_num < self.frames_count {
-            // Calculate the index within the map.
-            let map_idx = frame_num / NUM_BITS_PER_FIELD;
+        if frame_num >= self.frames_count {
+            return BitMapResult::InvalidFrameNum;
+        }
+        
+        // Calculate the index within the map, and the bit number.
+        let map_idx = frame_num / NUM_BITS_PER_FIELD;
+        let bit_idx = frame_num % NUM_BITS_PER_FIELD;
+        if !self.map[map_idx].is_set(bit_idx) {
+            return BitMapResult::WasAlreadyFree;
+        }
+        
+        // Free it, and mark it's field as having a free frame.
+        self.map[map_idx].clear_bit(bit_idx);
+        self.update_summary(map_idx);
+        
+        BitMapResult::Freed
+    }
+    
+    /// A method which finds a run of free frames which starts at an aligned frame, and allocates
+    /// all of them. The map is checked a whole field at a time (the bits of the run are masked), so
+    /// a long run is not checked bit by bit.
+    ///
+    /// # Parameters
+    /// `count` : The number of frames in the run.
+    /// `align_frames` : The alignment of the first frame's physical address (in frames, a power 
+    /// of 2).
+    ///
+    /// # Returns
+    /// BitMapResult::Allocated(frame_number) of the first frame if successful. 
+    /// BitMapResult::Full if there is no free run which is long enough.
+    /// BitMapResult::InvalidFrameNum if the count is zero, or the alignment is not a power of 2.
+    pub fn alloc_contiguous(&mut self, count: usize, align_frames: usize) -> BitMapResult {
+        if count == 0 || !align_frames.is_power_of_two() {
+            return BitMapResult::InvalidFrameNum;
+        }
+        
+        // The fields before the first free one are full, so start from it.
+        let first_field = match self.first_free_field() {
+            Some(field) => field,
+            None => return BitMapResult::Full,
+        };
+        let mut start = self.align_frame(first_field * NUM_BITS_PER_FIELD, align_frames);
+        while start + count <= self.frames_count {
+            match self.first_used(start, start + count) {
+                // Skip past the used frame (to the next aligned one).
+                Some(used) => start = self.align_frame(used + 1, align_frames),
+                None => {
+                    self.write_range(start, start + count, true);
+                    return BitMapResult::Allocated(start);
+                },
+            }
+        }
+        
+        // If we get here, there was no run which was long enough.
+        BitMapResult::Full
+    }
+    
+    /// A method which deallocates a run of frames (which was allocated with alloc_contiguous). If
+    /// any of them is out of range, or any of them is already free, nothing is deallocated.
+    ///
+    /// # Parameters
+    /// `frame_num` : The number of the first frame in the run.
+    /// `count` : The number of frames in the run.
+    ///
+    /// # Returns
+    /// BitMapResult::Freed if the run was deallocated, BitMapResult::WasAlreadyFree if any of its
+    /// frames was not allocated, or BitMapResult::InvalidFrameNum if the count is zero or the run
+    /// is out of range.
+    pub fn dealloc_contiguous(&mut self, frame_num: usize, count: usize) -> BitMapResult {
+        if count == 0 || frame_num + count > self.frames_count {
+            return BitMapResult::InvalidFrameNum;
+        }
+        
+        // A free frame means the run was not allocated as a whole (or it's freed twice).
+        if self.first_free(frame_num, frame_num + count).is_some() {
+            return BitMapResult::WasAlreadyFree;
+        }
+        
+        self.write_range(frame_num, frame_num + count, false);
+        BitMapResult::Freed
+    }
+    
+    /// A method which marks every frame which is not fully inside an available entry of the memory
+    /// map as used (the ACPI tables, the defective RAM, the MMIO holes, and the gaps between the
+    /// entries), so it's never handed out.
+    ///
+    /// # Parameters
+    /// `mem_map` : The memory map (from the multiboot2 information).
+    ///
+    /// # Returns
+    /// The number of frames which were reserved (the ones which were already used are not counted).
+    pub fn reserve_unavailable(&mut self, mem_map: MemMap) -> usize {
+        self.reserve_unless(|| mem_map
+            .filter(|entry| entry.ent_type == MemMapEntType::Available as u32)
+            .map(|entry| (entry.base_addr as usize, (entry.base_addr + entry.length) as usize)))
+    }
+    
+    /// A method which marks every frame which is not fully inside a usable descriptor of the EFI
+    /// memory map as used (the runtime services, the boot services data, the ACPI tables, and the
+    /// gaps between the descriptors), so it's never handed out.
+    ///
+    /// # Parameters
+    /// `efi_map` : The EFI memory map (from the multiboot2 information).
+    /// `boot_services_active` : True if the boot services were not terminated (their code is kept).
+    ///
+    /// # Returns
+    /// The number of frames which were reserved (the ones which were already used are not counted).
+    pub fn reserve_unavailable_efi(&mut self, efi_map: EfiMemMap, boot_services_active: bool) 
+        -> usize {
+        self.reserve_unless(|| efi_map
+            .filter(|desc| desc.is_usable(boot_services_active))
+            .map(|desc| (desc.phys_start as usize, desc.phys_end() as usize)))
+    }
+    
+    /// A helper which marks every frame which is not fully inside an available range as used. It 
+    /// goes through the ranges between the available runs (so it doesn't check every frame against
+    /// every range), and the ranges can be unsorted and overlapping.
+    ///
+    /// # Parameters
+    /// `available` : Returns the start and end (physical) addresses of the available ranges.
+    ///
+    /// # Returns
+    /// The number of frames which were reserved (the ones which were already used are not counted).
+    fn reserve_unless<I>(&mut self, available: impl Fn() -> I) -> usize 
+        where I: Iterator<Item = (usize, usize)> {
+        // The whole frames inside each available range (the frame numbers of the first one, and 
+        // the one after the last one), without the ones which don't have any.
+        let frames_start = self.frames_start;
+        let frames_count = self.frames_count;
+        let runs = || available().map(|(start, end)| {
+            let first = div_round_up(start.saturating_sub(frames_start), super::FRAME_SIZE);
+            let last = end.saturating_sub(frames_start) / super::FRAME_SIZE;
+            (first.min(frames_count), last.min(frames_count))
+        }).filter(|(first, last)| first < last);
+        
+        let mut reserved = 0;
+        let mut frame_num = 0;
+        while frame_num < frames_count {
+            // Skip to the end of the furthest run which includes this frame.
+            let run_end = runs().filter(|(first, last)| (*first..*last).contains(&frame_num))
+                .map(|(_, last)| last).max();
+            
+            // Otherwise, reserve everything up to the next run.
+            frame_num = match run_end {
+                Some(run_end) => run_end,
+                None => {
+                    let next_run = runs().map(|(first, _)| first).filter(|first| *first > frame_num)
+                        .min().unwrap_or(frames_count);
+                    reserved += self.write_range(frame_num, next_run, true);
+                    next_run
+                },
+            };
+        }
+        
+        reserved
+    }
+    
+    /// A helper which finds the first frame (at or after a given one) whose physical address is
+    /// aligned.
+    ///
+    /// # Parameters
+    /// `frame_num` : The number of the frame which we start from.
+    /// `align_frames` : The alignment (in frames, a power of 2).
+    ///
+    /// # Returns
+    /// The number of the aligned frame.
+    fn align_frame(&self, frame_num: usize, align_frames: usize) -> usize {
+        let first_phys = self.frames_start / super::FRAME_SIZE;
+        crate::mem::align::align_higher(first_phys + frame_num, align_frames) - first_phys
+    }
+    
+    /// A helper which finds the first used frame in a range. It checks a field at a time.
+    ///
+    /// # Parameters
+    /// `from` : The first frame number in the range.
+    /// `to` : The frame number after the last one in the range.
+    ///
+    /// # Returns
+    /// Some(frame_number) of the first used frame, or None if the whole range is free.
+    fn first_used(&self, from: usize, to: usize) -> Option<usize> {
+        let mut frame_num = from;
+        while frame_num < to {
+            let bit_num = frame_num % NUM_BITS_PER_FIELD;
+            let len = core::cmp::min(NUM_BITS_PER_FIELD - bit_num, to - frame_num);
+            
+            // Only keep the bits which are in the range.
+            let used = (self.map[frame_num / NUM_BITS_PER_FIELD] >> bit_num) & low_bits(len);
+            if used != 0 {
+                return Some(frame_num + used.trailing_zeros() as usize);
+            }
+            
+            frame_num += len;
+        }
         
-            // Calculate what is the idx within map, and the bit number, and then free it.
-            self.map[map_idx].clear_bit(frame_num % NUM_BITS_PER_FIELD);
+        None
+    }
+    
+    /// A helper which finds the first free frame in a range. It checks a field at a time.
+    ///
+    /// # Parameters
+    /// `from` : The first frame number in the range.
+    /// `to` : The frame number after the last one in the range.
+    ///
+    /// # Returns
+    /// Some(frame_number) of the first free frame, or None if the whole range is used.
+    fn first_free(&self, from: usize, to: usize) -> Option<usize> {
+        let mut frame_num = from;
+        while frame_num < to {
+            let bit_num = frame_num % NUM_BITS_PER_FIELD;
+            let len = core::cmp::min(NUM_BITS_PER_FIELD - bit_num, to - frame_num);
             
-            // If the first free index is larger than map_idx, update it.
-            if self.first_free > map_idx {
-                self.first_free = map_idx;
+            // Only keep the (inverted) bits which are in the range.
+            let free = (!self.map[frame_num / NUM_BITS_PER_FIELD] >> bit_num) & low_bits(len);
+            if free != 0 {
+                return Some(frame_num + free.trailing_zeros() as usize);
             }
+            
+            frame_num += len;
+        }
+        
+        None
+    }
+    
+    /// A helper which marks every frame in a range as used (or free). It writes a field at a time.
+    ///
+    /// # Parameters
+    /// `from` : The first frame number in the range.
+    /// `to` : The frame number after the last one in the range.
+    /// `used` : True to mark them as used, false to mark them as free.
+    ///
+    /// # Returns
+    /// The number of frames which were changed (the ones which were already marked are not counted).
+    fn write_range(&mut self, from: usize, to: usize, used: bool) -> usize {
+        let mut changed = 0;
+        let mut frame_num = from;
+        while frame_num < to {
+            let bit_num = frame_num % NUM_BITS_PER_FIELD;
+            let len = core::cmp::min(NUM_BITS_PER_FIELD - bit_num, to - frame_num);
+            let mask = low_bits(len) << bit_num;
+            
+            let field = &mut self.map[frame_num / NUM_BITS_PER_FIELD];
+            let new_field = if used { *field | mask } else { *field & !mask };
+            changed += (*field ^ new_field).count_ones() as usize;
+            *field = new_field;
+            self.update_summary(frame_num / NUM_BITS_PER_FIELD);
+            frame_num += len;
+        }
+        
+        changed
+    }
+    
+    /// A helper which finds the first field of the map which has a free bit (using the summary).
+    ///
+    /// # Returns
+    /// Some(index) of the field, or None if every frame is used.
+    fn first_free_field(&self) -> Option<usize> {
+        self.summary.iter().position(|summary_field| *summary_field != 0).map(|summary_idx|
+            summary_idx * NUM_BITS_PER_FIELD + self.summary[summary_idx].trailing_zeros() as usize)
+    }
+    
+    /// A helper which updates the summary bit of a field of the map (it's set if the field has a
+    /// free bit). It should be called whenever the field is changed.
+    ///
+    /// # Parameters
+    /// `map_idx` : The index of the field within the map.
+    fn update_summary(&mut self, map_idx: usize) {
+        let summary_field = &mut self.summary[map_idx / NUM_BITS_PER_FIELD];
+        if has_free(&self.map[map_idx]) {
+            summary_field.set_bit(map_idx % NUM_BITS_PER_FIELD);
+        } else {
+            summary_field.clear_bit(map_idx % NUM_BITS_PER_FIELD);
+        }
+    }
+    
+    /// A method which checks if a given frame number is currently marked as used.
+    ///
+    /// # Parameters
+    /// `frame_num` : The number of the frame which we're checking.
+    ///
+    /// # Returns
+    /// Ok(true) if used, Ok(false) if free, Err if the frame number is out of range.
+    pub fn is_used(&self, frame_num: usize) -> Result<bool, ()> {
+        if frame_num < self.frames_count {
+            Ok(self.map[frame_num / NUM_BITS_PER_FIELD].is_set(frame_num % NUM_BITS_PER_FIELD))
+        } else {
+            Err(())
         }
     }
     
+    /// A method which counts the number of frames which are currently marked as used.
+    ///
+    /// # Returns
+    /// The number of used frames.
+    pub fn used_count(&self) -> usize {
+        // The bits after the last frame are set, but they're not frames.
+        let tail_bits = self.map.len() * NUM_BITS_PER_FIELD - self.frames_count;
+        self.map.iter().map(|field| field.count_ones() as usize).sum::<usize>() - tail_bits
+    }
+    
+    /// A method which creates an iterator over the ranges of the used frames (in order).
+    ///
+    /// # Returns
+    /// The iterator which returns a region for every run of used frames.
+    pub fn used_ranges(&self) -> UsedRanges {
+        UsedRanges { bitmap: self, next_frame: 0 }
+    }
+    
+    /// A simple getter for the number of frames which are managed by this bitmap.
+    ///
+    /// # Returns
+    /// The total number of frames.
+    pub fn total_count(&self) -> usize {
+        self.frames_count
+    }
+    
     /// A method which converts a given physical memory address to a frame number.
     ///
     /// # Parameters
@@ -183,14 +514,8 @@ impl BitMap {
     /// # Returns
     /// Result::Ok containing the frame number if everything went as expected.
     /// Result::Err if the address it not within range. 
-    pub fn addr_to_frame(&self, addr: usize) -> Result<usize, ()> {
-        // Check for invalid address passed (out of range).
-        if addr < self.frames_start 
-            || addr > self.frames_start + (self.frames_count * super::FRAME_SIZE) {
-            Err(())                                                 // Error, invalid address.
-        } else {        
-            Ok((addr - self.frames_start) / super::FRAME_SIZE)      // Calculate the frame number.
-        }
+    pub const fn addr_to_frame(&self, addr: usize) -> Result<usize, ()> {
+        addr_to_frame(self.frames_start, self.frames_count, addr)
     }
     
     /// A method which converts a given frame number to it's starting physical address.
@@ -201,23 +526,99 @@ impl BitMap {
     /// # Returns
     /// Result::Ok containing the address if everything went as expected.
     /// Result::Err if the frame number is out of range. 
-    pub fn frame_to_addr(&self, frame_num: usize) -> Result<usize, ()> {
-        // Check for invalid frame number passed (out of range).
-        if frame_num < self.frames_count {
-            Ok(self.frames_start + (frame_num * super::FRAME_SIZE)) // Find the addr and return it.
-        } else {        
-            Err(())                                                 // Error, invalid frame number.
-        }
+    pub const fn frame_to_addr(&self, frame_num: usize) -> Result<usize, ()> {
+        frame_to_addr(self.frames_start, self.frames_count, frame_num)
     }
     
     /// A function which calculates the region which is mappable by this bitmap. It starts at the 
-    /// end of the bitmap (aligned), and ends at the end of the last frame. 
+    /// first managed frame, and ends at the end of the last frame.
     ///
     /// # Returns
-    /// A region which represents the usable memory area.
+    /// A region which represents the managed memory area.
     pub fn get_mappable_region(&self) -> Region {
         Region::new_sized(self.frames_start, self.frames_count * super::FRAME_SIZE)
     }
+    
+    /// A simple getter for the memory which holds the bits of the bitmap (and it's summary).
+    ///
+    /// # Returns
+    /// The region of the storage.
+    pub fn storage_region(&self) -> Region {
+        let size = (self.map.len() + self.summary.len()) * core::mem::size_of::<usize>();
+        Region::new_sized(self.map.as_ptr() as usize, size)
+    }
+}
+
+// Implement the iterator trait for the used ranges, so they can be printed using a simple for loop.
+impl Iterator for UsedRanges<'_> {
+    /// Define the type of the item used in the iterator (in this case it's the physical regions).
+    type Item = Region;
+    
+    /// A function which finds the next run of used frames.
+    ///
+    /// # Returns
+    /// A Some(Region) with the frames of the run, None if there are no more used frames.
+    fn next(&mut self) -> Option<Region> {
+        let count = self.bitmap.frames_count;
+        let start = self.bitmap.first_used(self.next_frame, count)?;
+        let end = self.bitmap.first_free(start, count).unwrap_or(count);
+        self.next_frame = end;
+        
+        Some(Region::new_sized(self.bitmap.frames_start + start * super::FRAME_SIZE,
+            (end - start) * super::FRAME_SIZE))
+    }
+}
+
+/// A function which calculates the number of bytes which are needed to store the bits of a number
+/// of frames (and the summary of the fields). It's rounded up to whole bit fields, so the frames at
+/// the end of the memory have a bit too.
+///
+/// # Parameters
+/// `num_frames` : The number of frames which are managed.
+///
+/// # Returns
+/// The size of the storage in bytes.
+pub fn storage_size(num_frames: usize) -> usize {
+    let num_fields = div_round_up(num_frames, NUM_BITS_PER_FIELD);
+    (num_fields + div_round_up(num_fields, NUM_BITS_PER_FIELD)) * core::mem::size_of::<usize>()
+}
+
+/// A function which converts a physical address to the number of the frame which includes it. The
+/// address after the last frame is not in any of them.
+///
+/// # Parameters
+/// `frames_start` : The starting address of the first frame.
+/// `frames_count` : The number of frames.
+/// `addr` : The physical address which we want to convert.
+///
+/// # Returns
+/// Result::Ok containing the frame number, or Result::Err if the address is not within range.
+const fn addr_to_frame(frames_start: usize, frames_count: usize, addr: usize) -> Result<usize, ()> {
+    // Check for invalid address passed (out of range).
+    if addr < frames_start || addr - frames_start >= frames_count * super::FRAME_SIZE {
+        Err(())                                                 // Error, invalid address.
+    } else {
+        Ok((addr - frames_start) / super::FRAME_SIZE)           // Calculate the frame number.
+    }
+}
+
+/// A function which converts a frame number to it's starting physical address.
+///
+/// # Parameters
+/// `frames_start` : The starting address of the first frame.
+/// `frames_count` : The number of frames.
+/// `frame_num` : The frame number which we want to get the address of.
+///
+/// # Returns
+/// Result::Ok containing the address, or Result::Err if the frame number is out of range.
+const fn frame_to_addr(frames_start: usize, frames_count: usize, frame_num: usize)
+    -> Result<usize, ()> {
+    // Check for invalid frame number passed (out of range).
+    if frame_num < frames_count {
+        Ok(frames_start + (frame_num * super::FRAME_SIZE))      // Find the addr and return it.
+    } else {
+        Err(())                                                 // Error, invalid frame number.
+    }
 }
 
 /// A function which checks if a given bitfield (with the size of usize) has a free bit. This is 
@@ -234,7 +635,9 @@ fn has_free(bitfield: &usize) -> bool {
 }
 
 /// A function which finds and returns the number of the free bit in the current bitfield. For 
-/// example, if the first bit is set to free, it will just return 0.
+/// example, if the first bit is set to free, it will just return 0. The lowest free bit is the 
+/// number of trailing ones (the trailing zeros of the inverted bitfield), so it's a single 
+/// instruction instead of a loop (it's called on every frame allocation).
 ///
 /// # Parameters
 /// `bitfield` : The current chunk of the bitmap which we're getting the free bit from.
@@ -243,15 +646,35 @@ fn has_free(bitfield: &usize) -> bool {
 /// The bit number for the first available free bit. If none of them are free it returns None.
 #[inline]
 fn get_free(bitfield: &usize) -> Option<usize> {
-    // Go through every single bit and check for free ones.
-    for bit_num in 0..(core::mem::size_of::<usize>() * 8) {
-        // Check if the particular bit is unused.
-        if bitfield.is_clear(bit_num) {
-            return Some(bit_num);
-        }
-    }
-        
-    None                          // If we get here, none of the bits were available/empty.
+    let bit_num = (!*bitfield).trailing_zeros() as usize;
+    
+    // It's the number of bits if none of them were available/empty.
+    if bit_num < NUM_BITS_PER_FIELD { Some(bit_num) } else { None }
+}
+
+/// A function which divides two numbers, and rounds the result up.
+///
+/// # Parameters
+/// `num` : The dividend.
+/// `divisor` : The divisor (it should not be zero).
+///
+/// # Returns
+/// The smallest integer which is larger than or equal to num / divisor.
+#[inline]
+fn div_round_up(num: usize, divisor: usize) -> usize {
+    (num + divisor - 1) / divisor
+}
+
+/// A function which creates a bitfield where only the lowest bits are set.
+///
+/// # Parameters
+/// `len` : The number of bits which are set (up to the size of the bitfield).
+///
+/// # Returns
+/// The bitfield with the lowest len bits set.
+#[inline]
+fn low_bits(len: usize) -> usize {
+    if len >= NUM_BITS_PER_FIELD { usize::MAX } else { (1 << len) - 1 }
 }
 
 
@@ -261,11 +684,469 @@ fn get_free(bitfield: &usize) -> Option<usize> {
 /// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
 #[cfg(feature = "unit-test")]
 pub mod test {
+    use crate::multiboot2::efi_mem_map::{EfiMemMap, EfiMemType};
+    
     /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
     /// sub module. 
     pub fn run() {
         test_has_free();
         test_get_free();
+        test_fresh_bitmap();
+        test_separate_storage();
+        test_contiguous();
+        test_mem_map_holes();
+        test_mem_map_unaligned();
+        test_efi_mem_map();
+        test_tail_frames();
+        test_double_free();
+        test_summary();
+        test_used_ranges();
+        test_translation_bounds();
+    }
+    
+    /// Free a frame twice (and a frame which was never allocated), and make sure the second free is
+    /// reported without changing the bitmap.
+    fn test_double_free() {
+        use super::BitMapResult;
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * super::super::FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        
+        assert!(matches!(bitmap.alloc_frame_num(7), BitMapResult::Allocated(7)));
+        assert!(matches!(bitmap.alloc_frame_num(70), BitMapResult::Allocated(70)));
+        assert!(matches!(bitmap.dealloc(7), BitMapResult::Freed));
+        assert!(matches!(bitmap.dealloc(7), BitMapResult::WasAlreadyFree));
+        assert!(matches!(bitmap.dealloc(8), BitMapResult::WasAlreadyFree));
+        assert!(matches!(bitmap.dealloc(129), BitMapResult::InvalidFrameNum));
+        
+        // The other frame is still used, and the freed one is handed out again.
+        assert_eq!(bitmap.used_count(), 1);
+        assert_eq!((bitmap.is_used(7), bitmap.is_used(70)), (Ok(false), Ok(true)));
+        assert!(matches!(bitmap.alloc(), BitMapResult::Allocated(0)));
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// A helper which makes sure the summary of a bitmap matches it's fields (a bit is set only
+    /// for the fields which have a free frame, and the bits after the last field are never set).
+    ///
+    /// # Parameters
+    /// `bitmap` : The bitmap which is checked.
+    fn check_summary(bitmap: &super::BitMap) {
+        use crate::mem::bitwise::BitWise;
+        use super::NUM_BITS_PER_FIELD;
+        
+        for (i, field) in bitmap.map.iter().enumerate() {
+            let summary_bit = bitmap.summary[i / NUM_BITS_PER_FIELD].is_set(i % NUM_BITS_PER_FIELD);
+            assert_eq!(summary_bit, super::has_free(field));
+        }
+        assert_eq!(bitmap.summary.iter().map(|field| field.count_ones() as usize).sum::<usize>(),
+            bitmap.map.iter().filter(|field| super::has_free(field)).count());
+    }
+    
+    /// Fill a private bitmap, and then allocate and free random frames while it's mostly full. The
+    /// summary should stay consistent, and alloc should always hand out the lowest free frame.
+    fn test_summary() {
+        use super::BitMapResult;
+        
+        // Manage 300 frames (a few fields, and the last one has tail bits).
+        let scratch = crate::mem::test::scratch(300 * super::super::FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        let total = bitmap.total_count();
+        check_summary(&bitmap);
+        
+        // Fill it, and free every 16th frame.
+        while let BitMapResult::Allocated(_) = bitmap.alloc() {}
+        assert_eq!(bitmap.used_count(), total);
+        check_summary(&bitmap);
+        for frame_num in (0..total).step_by(16) {
+            assert!(matches!(bitmap.dealloc(frame_num), BitMapResult::Freed));
+        }
+        check_summary(&bitmap);
+        
+        // The allocations are more likely than the frees, so it stays mostly full (a simple LCG
+        // is used, so the sequence is the same on every run).
+        let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+        for _ in 0..2000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let frame_num = (seed >> 33) as usize % total;
+            match (seed >> 29) % 4 {
+                0 | 1 => {
+                    let lowest = (0..total).find(|&frame| bitmap.is_used(frame) == Ok(false));
+                    match bitmap.alloc() {
+                        BitMapResult::Allocated(allocated) => assert_eq!(Some(allocated), lowest),
+                        _ => assert_eq!(lowest, None),
+                    }
+                },
+                2 => { bitmap.alloc_frame_num(frame_num); },
+                _ => { bitmap.dealloc(frame_num); },
+            }
+            check_summary(&bitmap);
+        }
+        
+        // A run which is freed (and the purge) update the summary too.
+        (total - 70..total).for_each(|frame_num| { bitmap.alloc_frame_num(frame_num); });
+        check_summary(&bitmap);
+        assert!(matches!(bitmap.dealloc_contiguous(total - 70, 70), BitMapResult::Freed));
+        check_summary(&bitmap);
+        bitmap.purge();
+        check_summary(&bitmap);
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Translate the addresses around the start and the end of the frames (and the frame numbers
+    /// around the last one), and make sure only the ones which are within the frames are accepted.
+    fn test_translation_bounds() {
+        use super::super::FRAME_SIZE;
+        const START: usize = 0x10_0000;
+        const COUNT: usize = 10;
+        const END: usize = START + COUNT * FRAME_SIZE;
+        
+        // They're const, so they can be checked at compile time too.
+        const FIRST: Result<usize, ()> = super::addr_to_frame(START, COUNT, START);
+        assert_eq!(FIRST, Ok(0));
+        
+        assert_eq!(super::addr_to_frame(START, COUNT, START - 1), Err(()));
+        assert_eq!(super::addr_to_frame(START, COUNT, START + FRAME_SIZE - 1), Ok(0));
+        assert_eq!(super::addr_to_frame(START, COUNT, END - FRAME_SIZE), Ok(COUNT - 1));
+        assert_eq!(super::addr_to_frame(START, COUNT, END - 1), Ok(COUNT - 1));
+        assert_eq!(super::addr_to_frame(START, COUNT, END), Err(()));
+        assert_eq!(super::addr_to_frame(START, COUNT, END + 1), Err(()));
+        assert_eq!(super::addr_to_frame(START, COUNT, usize::MAX), Err(()));
+        
+        assert_eq!(super::frame_to_addr(START, COUNT, 0), Ok(START));
+        assert_eq!(super::frame_to_addr(START, COUNT, COUNT - 1), Ok(END - FRAME_SIZE));
+        assert_eq!(super::frame_to_addr(START, COUNT, COUNT), Err(()));
+        assert_eq!(super::frame_to_addr(START, COUNT, COUNT + 1), Err(()));
+        
+        // The frames which start at 0 (and an empty bitmap) have no addresses before them.
+        assert_eq!(super::addr_to_frame(0, COUNT, 0), Ok(0));
+        assert_eq!(super::addr_to_frame(0, COUNT, COUNT * FRAME_SIZE), Err(()));
+        assert_eq!(super::addr_to_frame(START, 0, START), Err(()));
+        assert_eq!(super::frame_to_addr(START, 0, 0), Err(()));
+    }
+    
+    /// Mark isolated frames and runs (including one which crosses a field, and one at the end) as
+    /// used in a private bitmap, and make sure they're returned as coalesced ranges. A fully used
+    /// map should be a single range (without the bits after the last frame).
+    fn test_used_ranges() {
+        use super::BitMapResult;
+        use super::super::FRAME_SIZE;
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        let start = bitmap.get_mappable_region().addr;
+        let total = bitmap.total_count();
+        assert!(bitmap.used_ranges().next().is_none());
+        
+        // Two isolated frames, a run across the first field, and one which ends at the last frame.
+        for frame_num in [0, 2].iter().chain([60, 61, 62, 63, 64, 65].iter()) {
+            assert!(matches!(bitmap.alloc_frame_num(*frame_num), BitMapResult::Allocated(_)));
+        }
+        for frame_num in total - 3..total {
+            assert!(matches!(bitmap.alloc_frame_num(frame_num), BitMapResult::Allocated(_)));
+        }
+        
+        let expected = [(0, 1), (2, 1), (60, 6), (total - 3, 3)];
+        let mut ranges = bitmap.used_ranges();
+        for (frame_num, count) in expected.iter() {
+            let range = ranges.next().expect("A range of used frames is missing.");
+            assert_eq!(range.addr, start + frame_num * FRAME_SIZE);
+            assert_eq!(range.size, count * FRAME_SIZE);
+        }
+        assert!(ranges.next().is_none());
+        
+        // Fill it, and it's a single range which ends at the last frame.
+        while let BitMapResult::Allocated(_) = bitmap.alloc() {}
+        let mut ranges = bitmap.used_ranges();
+        let range = ranges.next().expect("The fully used map has no ranges.");
+        assert_eq!((range.addr, range.size), (start, total * FRAME_SIZE));
+        assert!(ranges.next().is_none());
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Create bitmaps whose frame counts are not a multiple of a bit field (and ones which are),
+    /// and make sure exactly every frame is handed out (none of them are missing, and none are
+    /// after the last one).
+    fn test_tail_frames() {
+        use super::BitMapResult;
+        use super::super::FRAME_SIZE;
+        
+        for num_frames in [1, 63, 64, 65, 127].iter().copied() {
+            // The bitmap takes the first frame.
+            let scratch = crate::mem::test::scratch((num_frames + 1) * FRAME_SIZE);
+            let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+            assert_eq!((bitmap.total_count(), bitmap.used_count()), (num_frames, 0));
+            
+            let mut given = 0;
+            while let BitMapResult::Allocated(frame_num) = bitmap.alloc() {
+                assert_eq!(frame_num, given);
+                given += 1;
+            }
+            assert_eq!(given, num_frames);
+            assert!(matches!(bitmap.alloc(), BitMapResult::Full));
+            assert!(matches!(bitmap.alloc_frame_num(num_frames), BitMapResult::InvalidFrameNum));
+            assert_eq!(bitmap.used_count(), num_frames);
+            
+            // The bits after the last frame are still set after a purge.
+            bitmap.purge();
+            assert_eq!(bitmap.used_count(), 0);
+            assert!(matches!(bitmap.alloc_contiguous(num_frames, 1), BitMapResult::Allocated(0)));
+            assert!(matches!(bitmap.alloc_contiguous(1, 1), BitMapResult::Full));
+            
+            crate::mem::test::free_scratch(&scratch);
+        }
+    }
+    
+    /// A helper which writes a synthetic EFI memory map tag into a buffer (the descriptors are 48
+    /// bytes, larger than EfiMemDesc like on the real firmware), and parses it.
+    ///
+    /// # Parameters
+    /// `buffer` : The buffer for the tag (2 words for the header, and 6 for each descriptor).
+    /// `descs` : The type, the physical address and the number of pages of each descriptor.
+    ///
+    /// # Returns
+    /// The parsed EFI memory map.
+    pub fn efi_map(buffer: &mut [u64], descs: &[(EfiMemType, u64, u64)]) -> EfiMemMap {
+        assert_eq!(buffer.len(), 2 + 6 * descs.len());
+        buffer[0] = 17 | ((buffer.len() as u64 * 8) << 32);
+        buffer[1] = 48 | (1 << 32);
+        for (idx, (desc_type, phys_start, num_pages)) in descs.iter().enumerate() {
+            let desc_type = *desc_type as u32 as u64;
+            buffer[2 + idx * 6..8 + idx * 6].copy_from_slice(&[desc_type, *phys_start, 0, 
+                *num_pages, 0, 0]);
+        }
+        
+        unsafe { EfiMemMap::new(buffer.as_ptr() as usize, buffer.len() * 8) }
+    }
+    
+    /// Reserve the frames of a synthetic EFI memory map, and make sure the runtime services and
+    /// the boot services data are never given out (and the boot services code only once they're
+    /// terminated).
+    fn test_efi_mem_map() {
+        use super::BitMapResult;
+        use super::super::FRAME_SIZE;
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * FRAME_SIZE);
+        let start = crate::mem::test::fresh_bitmap(&scratch).get_mappable_region().addr as u64;
+        let frame = FRAME_SIZE as u64;
+        
+        // Frames 0-19 are free, 20-29 are the runtime services, 30-39 are the boot services data,
+        // 40-49 are the boot services code, 50-99 are the loader's, and the rest are not in it.
+        let mut map_buffer: [u64; 2 + 6 * 6] = [0; 2 + 6 * 6];
+        let descs = [(EfiMemType::Conventional, start, 20), 
+            (EfiMemType::RuntimeServicesCode, start + 20 * frame, 5),
+            (EfiMemType::RuntimeServicesData, start + 25 * frame, 5),
+            (EfiMemType::BootServicesData, start + 30 * frame, 10),
+            (EfiMemType::BootServicesCode, start + 40 * frame, 10),
+            (EfiMemType::LoaderData, start + 50 * frame, 50)];
+        let efi_map = efi_map(&mut map_buffer, &descs);
+        assert_eq!(efi_map.count(), 6);
+        
+        // Once the boot services are terminated, their code can be used.
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        assert_eq!(bitmap.reserve_unavailable_efi(efi_map, false), 20 + 29);
+        let mut given = 0;
+        while let BitMapResult::Allocated(frame_num) = bitmap.alloc() {
+            assert!(frame_num < 20 || (40..100).contains(&frame_num));
+            given += 1;
+        }
+        assert_eq!(given, 80);
+        
+        // While they're active, it's kept as well.
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        assert_eq!(bitmap.reserve_unavailable_efi(efi_map, true), 30 + 29);
+        assert_eq!(bitmap.is_used(45), Ok(true));
+        assert_eq!(bitmap.is_used(50), Ok(false));
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Reserve the frames of a synthetic memory map (with a reserved hole, and a gap at the end),
+    /// and make sure the frames inside them are never given out.
+    fn test_mem_map_holes() {
+        use super::BitMapResult;
+        use super::super::FRAME_SIZE;
+        use crate::multiboot2::mem_map::{MemMap, MemMapEntType};
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        let start = bitmap.get_mappable_region().addr as u64;
+        let frame = FRAME_SIZE as u64;
+        
+        // Frames 0-19 are available, 20-29 are reserved, 30-99 are available, and the rest are not
+        // in the map (the header and 3 entries, of 24 bytes each).
+        let mut map_buffer: [u64; 11] = [0; 11];
+        map_buffer[0] = 6 | ((map_buffer.len() as u64 * 8) << 32);
+        map_buffer[1] = 24;
+        map_buffer[2..5].copy_from_slice(&[start, 20 * frame, MemMapEntType::Available as u64]);
+        map_buffer[5..8].copy_from_slice(&[start + 20 * frame, 10 * frame, 
+            MemMapEntType::ReservedMem as u64]);
+        map_buffer[8..11].copy_from_slice(&[start + 30 * frame, 70 * frame, 
+            MemMapEntType::Available as u64]);
+        let mem_map = unsafe { MemMap::new(map_buffer.as_ptr() as usize, map_buffer.len() * 8) };
+        
+        // A frame which was already used is not counted.
+        assert!(matches!(bitmap.alloc_frame_num(25), BitMapResult::Allocated(25)));
+        assert_eq!(bitmap.reserve_unavailable(mem_map), 9 + 29);
+        
+        // Allocate everything, only the available frames are given out.
+        let mut given = 0;
+        while let BitMapResult::Allocated(frame_num) = bitmap.alloc() {
+            assert!(frame_num < 20 || (30..100).contains(&frame_num));
+            given += 1;
+        }
+        assert_eq!(given, 90);
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Reserve the frames of a synthetic memory map whose entries are unsorted, overlapping, and 
+    /// not aligned to the frames, and make sure only the whole frames inside them are available.
+    fn test_mem_map_unaligned() {
+        use super::super::FRAME_SIZE;
+        use crate::multiboot2::mem_map::{MemMap, MemMapEntType};
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        let start = bitmap.get_mappable_region().addr as u64;
+        let frame = FRAME_SIZE as u64;
+        
+        // Frames 11-29 (the start of 10 is not), 0-14 (overlapping it), and 41-49 (the start of 40 
+        // and the end of 50 are not) are available.
+        let available = MemMapEntType::Available as u64;
+        let mut map_buffer: [u64; 11] = [0; 11];
+        map_buffer[0] = 6 | ((map_buffer.len() as u64 * 8) << 32);
+        map_buffer[1] = 24;
+        map_buffer[2..5].copy_from_slice(&[start + 10 * frame + 1, 20 * frame - 1, available]);
+        map_buffer[5..8].copy_from_slice(&[start, 15 * frame, available]);
+        map_buffer[8..11].copy_from_slice(&[start + 40 * frame + 1, 10 * frame, available]);
+        let mem_map = unsafe { MemMap::new(map_buffer.as_ptr() as usize, map_buffer.len() * 8) };
+        
+        assert_eq!(bitmap.reserve_unavailable(mem_map), 11 + 79);
+        for frame_num in 0..129 {
+            let available = frame_num < 30 || (41..50).contains(&frame_num);
+            assert_eq!(bitmap.is_used(frame_num), Ok(!available));
+        }
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Create a bitmap which manages the memory from 0, and keeps it's bits somewhere else. The
+    /// frame numbers should be the physical frame numbers.
+    fn test_separate_storage() {
+        use super::BitMapResult;
+        const FRAME_SIZE: usize = super::super::FRAME_SIZE;
+        
+        // Manage 100 frames from address 0 (the bits are kept in a scratch region).
+        let scratch = crate::mem::test::scratch(FRAME_SIZE);
+        let managed = crate::mem::region::Region::new(0, 100 * FRAME_SIZE);
+        let mut bitmap = unsafe { super::BitMap::new(&managed, scratch.addr) };
+        assert_eq!(bitmap.total_count(), 100);
+        assert_eq!(bitmap.used_count(), 0);
+        assert_eq!(bitmap.storage_region().addr, scratch.addr);
+        assert_eq!(bitmap.storage_region().size, super::storage_size(100));
+        assert_eq!(super::storage_size(100), 3 * core::mem::size_of::<usize>());
+        
+        // The first frame is at 0, and the addresses are not shifted.
+        assert_eq!(bitmap.addr_to_frame(0), Ok(0));
+        assert_eq!(bitmap.addr_to_frame(7 * FRAME_SIZE + 0x10), Ok(7));
+        assert!(matches!(bitmap.alloc(), BitMapResult::Allocated(0)));
+        assert_eq!(bitmap.frame_to_addr(99), Ok(99 * FRAME_SIZE));
+        assert!(matches!(bitmap.alloc_frame_num(100), BitMapResult::InvalidFrameNum));
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Allocate and free frames in a private bitmap, and make sure the global one is not affected.
+    fn test_fresh_bitmap() {
+        use super::BitMapResult;
+        
+        // Manage 130 frames (the bitmap itself takes the first one).
+        let scratch = crate::mem::test::scratch(130 * super::super::FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        let global_used = super::super::used_count();
+        
+        // The frames are given out in order, starting after the bitmap.
+        match bitmap.alloc() {
+            BitMapResult::Allocated(frame_num) => {
+                assert_eq!(frame_num, 0);
+                assert_eq!(bitmap.frame_to_addr(0), Ok(bitmap.get_mappable_region().addr));
+            },
+            _ => panic!("Could not allocate a frame in the fresh bitmap."),
+        }
+        assert!(matches!(bitmap.alloc_frame_num(5), BitMapResult::Allocated(5)));
+        assert!(matches!(bitmap.alloc_frame_num(5), BitMapResult::AlreadyUsed));
+        assert_eq!(bitmap.used_count(), 2);
+        
+        // Free them, and make sure the global allocator didn't change.
+        assert!(matches!(bitmap.dealloc(0), BitMapResult::Freed));
+        assert!(matches!(bitmap.dealloc(5), BitMapResult::Freed));
+        assert_eq!(bitmap.used_count(), 0);
+        assert_eq!(super::super::used_count(), global_used);
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Allocate runs of frames in a private bitmap (around a used frame, and across a field), and
+    /// make sure they're adjacent and reused after they're freed.
+    fn test_contiguous() {
+        use super::BitMapResult;
+        use super::super::FRAME_SIZE;
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * FRAME_SIZE);
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        
+        // The frames before the used one are not enough, so the run starts after it.
+        assert!(matches!(bitmap.alloc_frame_num(3), BitMapResult::Allocated(3)));
+        let start = match bitmap.alloc_contiguous(8, 1) {
+            BitMapResult::Allocated(frame_num) => frame_num,
+            _ => panic!("Could not allocate 8 contiguous frames."),
+        };
+        assert_eq!(start, 4);
+        let first_addr = bitmap.frame_to_addr(start).unwrap();
+        for i in 0..8 {
+            assert_eq!(bitmap.frame_to_addr(start + i), Ok(first_addr + i * FRAME_SIZE));
+            assert_eq!(bitmap.is_used(start + i), Ok(true));
+        }
+        
+        // A run can cross a field, but it can't be longer than the free frames.
+        assert!(matches!(bitmap.alloc_contiguous(64, 1), BitMapResult::Allocated(12)));
+        assert!(matches!(bitmap.alloc_contiguous(64, 1), BitMapResult::Full));
+        assert_eq!(bitmap.used_count(), 73);
+        
+        // Free the first run, and make sure it's reused (freeing it twice is rejected).
+        assert!(matches!(bitmap.dealloc_contiguous(start, 8), BitMapResult::Freed));
+        assert_eq!(bitmap.is_used(start), Ok(false));
+        assert!(matches!(bitmap.dealloc_contiguous(start, 8), BitMapResult::WasAlreadyFree));
+        assert!(matches!(bitmap.alloc_contiguous(8, 1), BitMapResult::Allocated(4)));
+        
+        // The aligned runs start at an aligned physical address.
+        bitmap.purge();
+        match bitmap.alloc_contiguous(8, 8) {
+            BitMapResult::Allocated(frame_num) => {
+                assert_eq!(bitmap.frame_to_addr(frame_num).unwrap() % (8 * FRAME_SIZE), 0);
+                assert!(frame_num < 8);
+            },
+            _ => panic!("Could not allocate 8 aligned frames."),
+        }
+        
+        // The invalid requests are rejected (and out of range or partly free runs are not freed).
+        assert!(matches!(bitmap.alloc_contiguous(0, 1), BitMapResult::InvalidFrameNum));
+        assert!(matches!(bitmap.alloc_contiguous(1, 3), BitMapResult::InvalidFrameNum));
+        assert!(matches!(bitmap.dealloc_contiguous(0, 1000), BitMapResult::InvalidFrameNum));
+        assert!(matches!(bitmap.dealloc_contiguous(0, 128), BitMapResult::WasAlreadyFree));
+        assert_eq!(bitmap.used_count(), 8);
+        
+        crate::mem::test::free_scratch(&scratch);
     }
     
     /// Unit tests for the has_free function.
@@ -292,5 +1173,16 @@ pub mod test {
         // Check it when the 10th bit is free.
         let test_three: usize = 0b0111111111;
         assert_eq!(super::get_free(&test_three), Some(9));
-    } 
+        
+        // Check it when all of the bits are free (the first one is given).
+        assert_eq!(super::get_free(&0), Some(0));
+        
+        // Check it when only the top bit is free.
+        assert_eq!(super::get_free(&(usize::MAX >> 1)), Some(super::NUM_BITS_PER_FIELD - 1));
+        
+        // Check the alternating patterns (the bits are numbered from the lowest one).
+        assert_eq!(super::get_free(&0xAAAAAAAAAAAAAAAA), Some(0));
+        assert_eq!(super::get_free(&0x5555555555555555), Some(1));
+        assert_eq!(super::get_free(&0x7FFFFFFFFFFFFFFE), Some(0));
+    }
 }
@@ -7,10 +7,24 @@
 
 use crate::multiboot2::MultibootInfo; // To find out where to put the bit field, and memory size.
 use crate::mem::region::Region;       // To allow using memory regions.
+use crate::multiboot2::mem_map::{MemMap, MemMapEntType};
+use crate::multiboot2::mem_info::MemInfo;
+
+/// The start of the Extended BIOS Data Area (EBDA), which is followed by the legacy hole.
+pub const LOW_HOLE_START: usize = 0x9FC00;
+
+/// The end of the legacy hole (the video memory and the BIOS ROMs) which is not usable RAM.
+pub const LOW_HOLE_END: usize = 0x100000;
+
+/// The address where the upper memory starts (as reported by the basic memory information).
+const UPPER_MEM_START: usize = 0x100000;
+
+/// The flag of the ELF sections which occupy memory while the kernel runs (SHF_ALLOC).
+const SHF_ALLOC: u64 = 0x2;
 
 /// A function which calculates where the kernel ends. It uses the parsed elf symbols table in the 
-/// multiboot2 information header. Additionally, it checks the address of multiboot2 header and 
-/// takes that into consideration (includes it as the "kernel").
+/// multiboot2 information header. Additionally, it checks the address of multiboot2 header and the
+/// boot modules, and takes them into consideration (includes them as the "kernel").
 ///
 /// # Parameters
 /// `mb_info` : The multiboot information reference (already parsed).
@@ -37,12 +51,35 @@ pub fn get_kernel_end(mb_info: &MultibootInfo) -> usize {
         curr_kernel_end = mb_end;
     }
     
+    // The boot modules (such as the initrd) should not be overwritten either.
+    for module in mb_info.module_tags.as_slice() {
+        if module.end > curr_kernel_end {
+            curr_kernel_end = module.end;
+        }
+    }
+    
     // Now we found where we can start mapping from.
     curr_kernel_end
 }
 
+/// A function which finds the memory which is used by the kernel's ELF sections. The sections which
+/// are not loaded (such as the symbols and the debug information) are skipped.
+///
+/// # Parameters
+/// `mb_info` : The multiboot information reference (already parsed).
+///
+/// # Returns
+/// An iterator over the regions of the loaded sections (empty if there is no ELF symbols tag).
+pub fn get_kernel_sections(mb_info: &MultibootInfo) -> impl Iterator<Item = Region> {
+    mb_info.elf_symbols_tag.into_iter().flatten()
+        .filter(|sym| sym.sh_flags & SHF_ALLOC != 0 && sym.sh_size != 0)
+        .map(|sym| Region::new_sized(sym.sh_addr, sym.sh_size as usize))
+}
+
 /// A function which finds the size of the memory from the multiboot info structure. It basically 
-/// returns the maximum address of this system.
+/// returns the maximum address of this system. The full memory map is preferred, and the basic
+/// memory information is only used if the memory map is not present. If the two disagree, a
+/// warning is logged.
 ///
 /// # Parameters
 /// `mb_info` : The multiboot information reference (already parsed).
@@ -50,25 +87,74 @@ pub fn get_kernel_end(mb_info: &MultibootInfo) -> usize {
 /// # Returns
 /// The size of memory which is the last addressable physical address.
 pub fn get_mem_end(mb_info: &MultibootInfo) -> usize {
-    // Define a value for the address of the end of memory.
+    let (mem_end, tags_disagree) = calc_mem_end(mb_info.mem_map_tag, mb_info.mem_info_tag);
+    
+    // Let the user know, since some BIOSes report the memory inconsistently.
+    if tags_disagree {
+        oxid_warn!("The memory map and basic memory info tags disagree. Using the memory map.");
+    }
+    
+    mem_end
+}
+
+/// A function which calculates the end of the usable memory from the memory tags. Only the 
+/// available entries of the memory map are considered. It also checks the memory map against the
+/// basic memory information (if both are present).
+///
+/// # Parameters
+/// `mem_map` : The memory map tag (if it was passed).
+/// `mem_info` : The basic memory information tag (if it was passed).
+///
+/// # Returns
+/// The end of the usable memory, and True if the tags disagreed (False otherwise).
+pub fn calc_mem_end(mem_map: Option<MemMap>, mem_info: Option<MemInfo>) -> (usize, bool) {
+    // Calculate the end of the lower and upper memory based on the basic memory information.
+    let info_ends = mem_info.map(|info| (info.mem_lower as usize * 1024
+        , UPPER_MEM_START + info.mem_upper as usize * 1024));
+    
+    // If there is no memory map, fall back to the basic memory information.
+    let mem_map = match mem_map {
+        Some(mem_map) => mem_map,
+        None => return (info_ends.map(|(_, upper_end)| upper_end).unwrap_or(0), false),
+    };
+    
+    // Define a value for the address of the end of memory, and the ends of the lower and upper
+    // memory (the available entries which start at 0, and include 1MB).
     let mut curr_mem_end: usize = 0;
+    let mut lower_end: usize = 0;
+    let mut upper_end: usize = 0;
 
-    // Go through the multiboot memory map, and check the largest memory end.
-    let mem_map = mb_info.mem_map_tag.unwrap();
-    for map in mem_map {
-        // If the base address + length is larger than the current end, update it.
+    // Go through the available entries in the memory map, and check the largest memory end.
+    for map in mem_map.filter(|map| map.ent_type == MemMapEntType::Available as u32) {
+        let map_start = map.base_addr as usize;
         let curr_map_end = (map.base_addr + map.length) as usize;
+        
+        // If the base address + length is larger than the current end, update it.
         if curr_map_end > curr_mem_end {
             curr_mem_end = curr_map_end;
         }
+        
+        // Store the ends which can be compared with the basic memory information.
+        if map_start == 0 {
+            lower_end = curr_map_end;
+        } else if map_start <= UPPER_MEM_START && curr_map_end > UPPER_MEM_START {
+            upper_end = curr_map_end;
+        }
     }
     
+    // Check if the basic memory information agrees with the memory map.
+    let tags_disagree = match info_ends {
+        Some(ends) => ends != (lower_end, upper_end),
+        None => false,
+    };
+    
     // Now we found where we can start map to.
-    curr_mem_end
+    (curr_mem_end, tags_disagree)
 }
 
 /// A function which creates a memory region representing the end of the kernel to the end of the 
-/// memory. It does not do any alignment by itself and uses the data passed to it.
+/// memory. It does not do any alignment by itself and uses the data passed to it. The EBDA and
+/// the legacy hole below 1MB are always excluded (regardless of what the tags claim).
 ///
 /// # Parameters
 /// `mb_info` : The multiboot information reference (already parsed).
@@ -77,7 +163,42 @@ pub fn get_mem_end(mb_info: &MultibootInfo) -> usize {
 /// A region which represents the usable physical memory after the kernel.
 pub fn get_usable_region(mb_info: &MultibootInfo) -> Region {
     // Simply calculate the region and return it.
-    Region::new(get_kernel_end(mb_info), get_mem_end(mb_info))
+    exclude_low_hole(get_kernel_end(mb_info), get_mem_end(mb_info))
+}
+
+/// A function which creates a region from the given addresses, and makes sure that it does not
+/// overlap the EBDA and the legacy hole (0x9FC00 - 0xFFFFF). Since the region is a single span,
+/// it is moved to start at 1MB if it would include the hole.
+///
+/// # Parameters
+/// `start_addr` : The start of the usable memory.
+/// `end_addr` : The end of the usable memory.
+///
+/// # Returns
+/// The region which does not include the hole.
+pub fn exclude_low_hole(start_addr: usize, end_addr: usize) -> Region {
+    // If the region starts before the end of the hole, move it after the hole.
+    let start_addr = if start_addr < LOW_HOLE_END && end_addr > LOW_HOLE_START {
+        LOW_HOLE_END
+    } else {
+        start_addr
+    };
+    
+    // Make sure it's never negative.
+    Region::new(start_addr, core::cmp::max(start_addr, end_addr))
+}
+
+/// A function which creates a memory region representing all of the physical memory (from 0 to the
+/// end of the memory). It's the region which is managed by the frame allocator, and the frames
+/// which are used by the kernel (and the holes) are marked used in it.
+///
+/// # Parameters
+/// `mb_info` : The multiboot information reference (already parsed).
+///
+/// # Returns
+/// A region which starts at address 0, and ends where the memory ends.
+pub fn get_managed_region(mb_info: &MultibootInfo) -> Region {
+    Region::new(0, get_mem_end(mb_info))
 }
 
 /// A function which creates a memory region representing the adderss used by the kernel and it's 
@@ -92,3 +213,75 @@ pub fn get_kernel_region(mb_info: &MultibootInfo) -> Region {
     // Simply calculate the region and return it.
     Region::new(0, get_kernel_end(mb_info))
 }
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::multiboot2::mem_map::{MemMap, MemMapEntType};
+    use crate::multiboot2::mem_info::MemInfo;
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_prefer_mem_map();
+        test_fallback_mem_info();
+        test_low_hole();
+    }
+    
+    /// Make sure the memory map is used when the tags contradict each other, and only the
+    /// available entries are used.
+    fn test_prefer_mem_map() {
+        // The memory map (the header and 4 entries, of 24 bytes each, the last one is reserved).
+        let mut map_buffer: [u64; 14] = [0; 14];
+        map_buffer[0] = 6 | ((map_buffer.len() as u64 * 8) << 32);
+        map_buffer[1] = 24;
+        map_buffer[2..5].copy_from_slice(&[0x0, 0x9FC00, MemMapEntType::Available as u64]);
+        map_buffer[5..8].copy_from_slice(&[0x9FC00, 0x60400, MemMapEntType::ReservedMem as u64]);
+        map_buffer[8..11].copy_from_slice(&[0x100000, 0x7F00000, MemMapEntType::Available as u64]);
+        map_buffer[11..14].copy_from_slice(&[0xFFFC0000, 0x40000, MemMapEntType::ReservedMem as u64]);
+        
+        // The basic memory information claims 640KB of lower memory, and 256MB of upper memory.
+        let info_buffer: [u32; 4] = [4, 16, 640, 256 * 1024];
+        
+        unsafe {
+            let mem_map = MemMap::new(map_buffer.as_ptr() as usize, map_buffer.len() * 8);
+            let mem_info = MemInfo::new(info_buffer.as_ptr() as usize);
+            
+            // The memory map wins, and the disagreement is reported.
+            assert_eq!(super::calc_mem_end(Some(mem_map), Some(mem_info)), (0x8000000, true));
+            
+            // Without the basic memory information, there is nothing to disagree with.
+            assert_eq!(super::calc_mem_end(Some(mem_map), None), (0x8000000, false));
+        }
+    }
+    
+    /// Make sure the basic memory information is used if there is no memory map.
+    fn test_fallback_mem_info() {
+        let info_buffer: [u32; 4] = [4, 16, 639, 127 * 1024];
+        
+        unsafe {
+            let mem_info = MemInfo::new(info_buffer.as_ptr() as usize);
+            assert_eq!(super::calc_mem_end(None, Some(mem_info)), (0x8000000, false));
+        }
+        
+        assert_eq!(super::calc_mem_end(None, None), (0, false));
+    }
+    
+    /// Make sure the EBDA and the legacy hole are never included in the usable region.
+    fn test_low_hole() {
+        // A region which starts in the lower memory is moved after the hole.
+        let region = super::exclude_low_hole(0x8000, 0x8000000);
+        assert_eq!(region.addr, super::LOW_HOLE_END);
+        assert_eq!(region.end_addr(), 0x8000000);
+        
+        // A region which only starts inside the hole is also moved.
+        assert_eq!(super::exclude_low_hole(0x9FC00, 0x200000).addr, super::LOW_HOLE_END);
+        
+        // Regions which don't overlap the hole are not changed.
+        assert_eq!(super::exclude_low_hole(0x200000, 0x8000000).addr, 0x200000);
+        assert_eq!(super::exclude_low_hole(0x1000, 0x9F000).end_addr(), 0x9F000);
+    }
+}
@@ -8,48 +8,234 @@
 
 pub mod bitmap;                       // The actual bitmap allocator.
 pub mod mem_info;                     // To get the kernel memory regions.      
+pub mod refcount;                     // The number of mappings which share each frame.
 
 use crate::proc::mutex::Mutex;        // For the safe access to the global frame allocator.
 use crate::mem::region::Region;       // To get and utilize memory regions.
+use crate::mem::addr::PhysAddr;       // The addresses of the frames.
 use crate::multiboot2::MultibootInfo; // To find out where to put the bit field, and memory size.
+use crate::multiboot2::mem_map::MemMap;           // To reserve the unavailable frames.
+use crate::multiboot2::efi_mem_map::EfiMemMap;
 use bitmap::{BitMap, BitMapResult};   // For the actual allocation and deallocation.
 
 pub const ALIGNMENT: usize = 0x1000;  // The alignment for the memory addresses (4K aligned).
 pub const FRAME_SIZE: usize = 0x1000; // The size of the physical frame (same size as page size).
 
+/// The maximum number of ranges which can be reserved with reserve_range.
+pub const MAX_RESERVED_RANGES: usize = 16;
+
+/// A structure which owns the global bitmap and the mutex which protects it. The bitmap can only be
+/// reached through with, so every access to it (including the translations) is in a critical
+/// section.
+pub struct LockedBitMap {
+    mutex: Mutex,               // The mutex which is held while the bitmap is used.
+    bitmap: Option<BitMap>,     // The bitmap (None until the allocator is initialized).
+}
+
+impl LockedBitMap {
+    /// A constructor which creates an allocator which is not initialized yet.
+    ///
+    /// # Returns
+    /// The newly created (empty) allocator.
+    pub const fn new() -> Self {
+        LockedBitMap { mutex: Mutex::new(), bitmap: None }
+    }
+    
+    /// A method which sets (or replaces) the bitmap.
+    ///
+    /// # Parameters
+    /// `bitmap` : The initialized bitmap.
+    pub fn set(&mut self, bitmap: BitMap) {
+        let were_enabled = self.mutex.lock_irqsave();
+        self.bitmap = Some(bitmap);
+        self.mutex.unlock_irqrestore(were_enabled);
+    }
+    
+    /// A method which calls a function with the bitmap while the mutex is held. The interrupts are
+    /// disabled (and restored after), so it can be used from the interrupt handlers too.
+    ///
+    /// # Parameters
+    /// `func` : The function which is called with the bitmap.
+    ///
+    /// # Returns
+    /// Some with the function's result, or None if the bitmap is not initialized.
+    pub fn with<R>(&mut self, func: impl FnOnce(&mut BitMap) -> R) -> Option<R> {
+        let were_enabled = self.mutex.lock_irqsave();
+        let result = self.bitmap.as_mut().map(func);
+        self.mutex.unlock_irqrestore(were_enabled);
+        result
+    }
+    
+    /// A version of with which doesn't spin if the mutex is already held, so it can be used where
+    /// spinning would deadlock (such as the panic handler, if it interrupted the holder).
+    ///
+    /// # Parameters
+    /// `func` : The function which is called with the bitmap.
+    ///
+    /// # Returns
+    /// Some with the function's result, or None if the mutex is held (or it's not initialized).
+    pub fn try_with<R>(&mut self, func: impl FnOnce(&mut BitMap) -> R) -> Option<R> {
+        let were_enabled = unsafe { crate::arch::interrupts::save_and_disable() };
+        if !self.mutex.try_lock() {
+            unsafe { crate::arch::interrupts::restore(were_enabled); }
+            return None;
+        }
+        
+        let result = self.bitmap.as_mut().map(func);
+        self.mutex.unlock_irqrestore(were_enabled);
+        result
+    }
+}
+
 /// A static frame allocator which we can use to allocate globally.
-static mut FRAME_ALLOCATOR: Option<BitMap> = None;
+static mut FRAME_ALLOCATOR: LockedBitMap = LockedBitMap::new();
 
-/// Create a new mutex for the frame allocator to allow safe access.
-static mut FRAME_ALLOCATOR_MUTEX: Mutex = Mutex::new();
+/// The ranges which were reserved with reserve_range (None is an empty slot). The frames which are
+/// out of the bitmap's range can't be marked in it, so this list is what keeps them from being
+/// allocated. It's only accessed in the frame allocator's critical section.
+static mut RESERVED_RANGES: [Option<Region>; MAX_RESERVED_RANGES] = [None; MAX_RESERVED_RANGES];
 
 /// An enum which represents the result of a frame allocation.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FrameAllocResult {
-    Ok(usize),          // When the allocation went as expected.
+    Ok(PhysAddr),       // When the allocation went as expected.
     Success,            // When a general operation was successful.
     Full,               // When the memory is full.
     InvalidAddr,        // When an invalid address was passed.
-    Err,                // Other errors occured in allocating.
+    AlreadyFree,        // When a frame which was not allocated was deallocated (double free).
+    Referenced,         // When a frame which is shared or pinned was deallocated (use ref_dec).
+    AlreadyUsed,        // When the requested frame was already allocated.
+    Reserved,           // When the requested frame is reserved (such as device memory).
+    NotInitialized,     // When the allocator is not initialized yet (the early boot can fall back).
+    Internal(&'static str), // Other errors occured in allocating (with what went wrong).
+}
+
+/// An enum which represents the memory map which the unavailable frames were reserved from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MapSource {
+    Efi,                // The EFI memory map (it's preferred when it's passed).
+    Legacy,             // The legacy memory map.
+    Missing,            // No memory map was passed (nothing was reserved).
 }
 
 /// A function which initializes the bitmap frame allocator code. It calculates where the kernel
-/// and memory end, and where the bitmap will be and initializes it.
+/// and memory end, and where the bitmap will be and initializes it. The bitmap manages all of the
+/// physical memory (from 0), and the frames which are used by the kernel, the boot information,
+/// and the bitmap itself are marked used.
 ///
 /// # Parameters
 /// `mb_info` : The multiboot information reference (already parsed).
 pub unsafe fn init(mb_info: &MultibootInfo) {
-    // Get the usable memory area after the kernel.
-    let usable_region = mem_info::get_usable_region(mb_info);
+    // Get all of the memory, and the usable memory area after the kernel (for the bitmap).
+    let managed_region = mem_info::get_managed_region(mb_info);
+    let usable_region = mem_info::exclude_low_hole(mem_info::get_kernel_end(mb_info),
+        managed_region.end_addr());
     
     // Log the findings with the start and end address of the memory region.
     oxid_log!("Obtained the usable memory. kernel_end=0x{:x}, mem_end=0x{:x}"
         , usable_region.addr, usable_region.end_addr());
 
-    // Initialize and set the global frame allocator.
-    FRAME_ALLOCATOR = Some(bitmap::BitMap::new(&usable_region));
+    // Initialize and set the global frame allocator (the bits are stored after the kernel).
+    let storage_addr = crate::mem::align::align_higher(usable_region.addr, ALIGNMENT);
+    let bitmap = bitmap::BitMap::new(&managed_region, storage_addr);
+    let storage_region = bitmap.storage_region();
+    FRAME_ALLOCATOR.set(bitmap);
     oxid_log!("Initialized the frame allocator.");
+    
+    // Mark the frames which are already used (the kernel, the boot information, and the bitmap).
+    if let Some(marked) = FRAME_ALLOCATOR.with(|allocator|
+        reserve_boot_frames(allocator, mb_info, &storage_region)) {
+        oxid_log!("Marked {} frames which are used by the kernel and the boot information.",
+            marked);
+    }
+    
+    // Let the user know how we were booted (the EFI tags are only passed by the UEFI loaders).
+    match mb_info.efi64_sys_table_tag {
+        Some(sys_table) => oxid_log!("Booted via UEFI. system_table=0x{:x}, boot_services={}", 
+            sys_table.ptr, if mb_info.efi_boot_services { "active" } else { "terminated" }),
+        None if mb_info.is_uefi() => oxid_log!("Booted via UEFI (without a system table)."),
+        None => oxid_log!("Booted via BIOS."),
+    }
+    
+    // Mark the frames which are not available in the memory map (runtime services, ACPI, 
+    // defective, holes) as used.
+    let reserved = FRAME_ALLOCATOR.with(|allocator| reserve_from_maps(allocator,
+        mb_info.mem_map_tag, mb_info.efi_mem_map_tag, mb_info.efi_boot_services));
+    if let Some(reserved) = reserved {
+        match reserved {
+            (MapSource::Efi, reserved) => oxid_log!(
+                "Reserved {} frames which are not usable in the EFI memory map.", reserved),
+            (MapSource::Legacy, reserved) => oxid_log!(
+                "Reserved {} frames which are not available in the legacy memory map.", reserved),
+            (MapSource::Missing, _) => 
+                oxid_warn!("There is no memory map, none of the frames were reserved."),
+        }
+    }
+    
+    // Mark the frames which are used for memory mapped I/O as used.
+    for region in crate::mem::mmio::reserved_regions().iter().flatten() {
+        reserve_range(PhysAddr::new(region.addr), region.size);
+    }
+    
+    // Reserve the region for the early allocations at the start of the frames (it stays identity
+    // mapped, so it can be used before the heap and the page tables are ready).
+    let early_region = Region::new_sized(get_mappable_region().addr, 
+        crate::mem::early_alloc::EARLY_ALLOC_SIZE);
+    reserve_region(&early_region);
+    crate::mem::early_alloc::init(&early_region);
+}
+
+/// A function which marks the frames which are used before the allocator is initialized. It
+/// includes the first frame (the real mode interrupt table and the BIOS data), the legacy hole,
+/// the kernel's loaded ELF sections, the multiboot information, the boot modules, and the bitmap's
+/// own storage.
+///
+/// # Parameters
+/// `allocator` : The bitmap which the frames are marked in.
+/// `mb_info` : The multiboot information reference (already parsed).
+/// `storage_region` : The region which holds the bits of the bitmap.
+///
+/// # Returns
+/// The number of frames which were marked (the ones which were already used are not counted).
+pub fn reserve_boot_frames(allocator: &mut BitMap, mb_info: &MultibootInfo,
+    storage_region: &Region) -> usize {
+    let fixed = [
+        Region::new_sized(0, FRAME_SIZE),
+        Region::new(mem_info::LOW_HOLE_START, mem_info::LOW_HOLE_END),
+        Region::new_sized(mb_info.ptr, mb_info.total_size),
+        *storage_region,
+    ];
+    let modules = mb_info.module_tags.as_slice().iter()
+        .map(|module| Region::new(module.start, module.end));
+    
+    fixed.iter().copied()
+        .chain(mem_info::get_kernel_sections(mb_info))
+        .chain(modules)
+        .map(|region| mark_region(allocator, &region))
+        .sum()
 }
 
+/// A function which reserves the frames which are not usable in the memory maps. The EFI memory
+/// map is preferred when it's passed (it tells the runtime services apart from the usable memory),
+/// and the legacy one is only used otherwise.
+///
+/// # Parameters
+/// `allocator` : The bitmap which the frames are reserved in.
+/// `mem_map` : The legacy memory map (if it was passed).
+/// `efi_map` : The EFI memory map (if it was passed).
+/// `boot_services_active` : True if the EFI boot services were not terminated.
+///
+/// # Returns
+/// The map which was used, and the number of frames which were reserved.
+pub fn reserve_from_maps(allocator: &mut BitMap, mem_map: Option<MemMap>, 
+    efi_map: Option<EfiMemMap>, boot_services_active: bool) -> (MapSource, usize) {
+    match (efi_map, mem_map) {
+        (Some(efi_map), _) => 
+            (MapSource::Efi, allocator.reserve_unavailable_efi(efi_map, boot_services_active)),
+        (None, Some(mem_map)) => (MapSource::Legacy, allocator.reserve_unavailable(mem_map)),
+        (None, None) => (MapSource::Missing, 0),
+    }
+}
 
 /// A function which finds the first available free frame, and allocates it. It then returns the 
 /// starting address of the frame. It provides thread safe access for the static bitmap which should
@@ -58,38 +244,68 @@ pub unsafe fn init(mb_info: &MultibootInfo) {
 /// # Returns
 /// FrameAlloc::Ok(frame_addr) if successful. 
 /// FrameAlloc::Full if memory is full.
-/// FrameAlloc::Err for other error occured.
+/// FrameAlloc::NotInitialized if the allocator is not initialized yet.
+/// FrameAlloc::Internal for other error occured.
 pub fn alloc() -> FrameAllocResult {
-    unsafe {
-        // First, get a reference to the allocator and check if it's initialized.
-        let allocator = match &mut (FRAME_ALLOCATOR) {
-            Some(allocator) => allocator,
-            None => panic!("Frame allocator not initialized."),
+    // Allocate the frame and translate it in the same critical section. The new frame has a single
+    // reference (it's owner).
+    let result = unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let frame_num = match allocator.alloc() {
+            BitMapResult::Allocated(num) => num,
+            BitMapResult::Full => return FrameAllocResult::Full,
+            _ => return FrameAllocResult::Internal("Unexpected bitmap result."),
         };
-    
-        // Lock the mutex, allocate the frame, capture it's result, and unlock the mutex.
-        FRAME_ALLOCATOR_MUTEX.lock();
-        let alloc_result = allocator.alloc();
-        FRAME_ALLOCATOR_MUTEX.unlock();
+        refcount::set(frame_num, 1);
         
-        // Check the allocation results, get frame number and return if it's full or error occured.
-        let frame_num = match alloc_result {
+        match allocator.frame_to_addr(frame_num) {
+            Ok(addr) => FrameAllocResult::Ok(PhysAddr::new(addr)),
+            Err(()) => FrameAllocResult::Internal("The allocated frame is out of range."),
+        }
+    }) };
+    
+    let result = result.unwrap_or(FrameAllocResult::NotInitialized);
+    #[cfg(feature = "trace")]
+    if let FrameAllocResult::Ok(frame_addr) = result {
+        oxid_dbg!(Frames, "Frame allocated at 0x{:x}", frame_addr);
+    }
+    result
+}
+
+/// A function which allocates a run of physically contiguous frames (for example, for the DMA 
+/// buffers of the devices). The first frame's address is aligned to the given number of frames. It
+/// is also thread-safe (the whole run is allocated while the mutex is held).
+///
+/// # Parameters
+/// `count` : The number of frames which are allocated.
+/// `align_frames` : The alignment of the first frame's address (in frames, a power of 2).
+///
+/// # Returns
+/// FrameAlloc::Ok(frame_addr) of the first frame if successful. 
+/// FrameAlloc::Full if there is no free run which is long enough.
+/// FrameAlloc::NotInitialized if the allocator is not initialized yet.
+/// FrameAlloc::Internal if the count is zero, or the alignment is not a power of 2.
+pub fn alloc_contiguous(count: usize, align_frames: usize) -> FrameAllocResult {
+    // The whole run is allocated and translated in the same critical section.
+    let result = unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let frame_num = match allocator.alloc_contiguous(count, align_frames) {
             BitMapResult::Allocated(num) => num,
             BitMapResult::Full => return FrameAllocResult::Full,
-            _ => return FrameAllocResult::Err,
+            _ => return FrameAllocResult::Internal("Invalid count or alignment."),
         };
+        (frame_num..frame_num + count).for_each(|num| refcount::set(num, 1));
         
-        // Get the starting frame address and check for errors.
-        let frame_addr = match allocator.frame_to_addr(frame_num) {
-            Ok(addr) => addr,
-            Err(()) => panic!("Frame number error was not expected during translation."),
-        };
-                      
-        //oxid_log!("Frame allocated at 0x{:x}", frame_addr);
+        match allocator.frame_to_addr(frame_num) {
+            Ok(addr) => FrameAllocResult::Ok(PhysAddr::new(addr)),
+            Err(()) => FrameAllocResult::Internal("The allocated frame is out of range."),
+        }
+    }) };
     
-        // If we get here, everything went as expected, return the calculated address.
-        FrameAllocResult::Ok(frame_addr)
+    let result = result.unwrap_or(FrameAllocResult::NotInitialized);
+    #[cfg(feature = "trace")]
+    if let FrameAllocResult::Ok(frame_addr) = result {
+        oxid_dbg!(Frames, "{} contiguous frames allocated at 0x{:x}", count, frame_addr);
     }
+    result
 }
 
 /// A function which allocates a specified frame with an address. This function allows the kernel to 
@@ -100,44 +316,40 @@ pub fn alloc() -> FrameAllocResult {
 /// `physical_addr` : The physical address within the frame which we want to mark used.
 ///
 /// # Returns
-/// FrameAlloc::Success if successful. frame_addr is the starting address of the frame. 
+/// FrameAlloc::Ok(frame_addr) if successful. frame_addr is the starting address of the frame.
 /// FrameAlloc::InvalidAddr if the passed address is out of range.
-/// FrameAlloc::Err if the frame is used or other error occured.
-pub fn alloc_frame(physical_addr: usize) -> FrameAllocResult {
-    unsafe {
-        // First, get a reference to the allocator and check if it's initialized.
-        let allocator = match &mut (FRAME_ALLOCATOR) {
-            Some(allocator) => allocator,
-            None => panic!("Frame allocator not initialized."),
-        };
-    
-        // Get the frame number and check for invalid memory range.
-        let frame_num = match allocator.addr_to_frame(physical_addr) {
+/// FrameAlloc::AlreadyUsed if the frame is already used.
+/// FrameAlloc::Reserved if the frame overlaps a reserved range (see reserve_range).
+/// FrameAlloc::NotInitialized if the allocator is not initialized yet.
+/// FrameAlloc::Internal if any other error occured.
+pub fn alloc_frame(physical_addr: PhysAddr) -> FrameAllocResult {
+    // Translate the address, allocate the frame, and translate it back in the same critical
+    // section.
+    let result = unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let frame_start = crate::mem::align::align_lower(physical_addr.as_usize(), ALIGNMENT);
+        if RESERVED_RANGES.iter().flatten().any(|range|
+            range.addr < frame_start + FRAME_SIZE && frame_start < range.end_addr()) {
+            return FrameAllocResult::Reserved;
+        }
+        
+        let frame_num = match allocator.addr_to_frame(physical_addr.as_usize()) {
             Ok(num) => num,
             Err(()) => return FrameAllocResult::InvalidAddr,
         };
         
-        // Lock the mutex, allocate the frame, capture it's result, and unlock the mutex.
-        FRAME_ALLOCATOR_MUTEX.lock();
-        let alloc_result = allocator.alloc_frame_num(frame_num);
-        FRAME_ALLOCATOR_MUTEX.unlock();
-        
-        // Check the allocation results.
-        match alloc_result {
-            BitMapResult::AlreadyUsed => return FrameAllocResult::Err,
-            BitMapResult::InvalidFrameNum => panic!("Frame number error was not expected."),
-            _ => (),
+        match allocator.alloc_frame_num(frame_num) {
+            BitMapResult::Allocated(_) => refcount::set(frame_num, 1),
+            BitMapResult::AlreadyUsed => return FrameAllocResult::AlreadyUsed,
+            _ => return FrameAllocResult::Internal("Unexpected bitmap result."),
         }
         
-        // Get the starting frame address and check for errors.
-        let frame_addr = match allocator.frame_to_addr(frame_num) {
-            Ok(addr) => addr,
-            Err(()) => panic!("Frame number error was not expected during translation."),
-        };
+        match allocator.frame_to_addr(frame_num) {
+            Ok(addr) => FrameAllocResult::Ok(PhysAddr::new(addr)),
+            Err(()) => FrameAllocResult::Internal("The allocated frame is out of range."),
+        }
+    }) };
     
-        // If we get here, everything went as expected, return the calculated address.
-        FrameAllocResult::Ok(frame_addr)
-    }
+    result.unwrap_or(FrameAllocResult::NotInitialized)
 }
 
 /// A function which deallocates a specified frame with an address. It is also thread-safe.
@@ -146,31 +358,293 @@ pub fn alloc_frame(physical_addr: usize) -> FrameAllocResult {
 /// `physical_addr` : The physical address within the frame which we want to deallocate.
 ///
 /// # Returns
-/// FrameAlloc::Success if the frame was successfully deallocated or if it was unused.
+/// FrameAlloc::Success if the frame was successfully deallocated.
+/// FrameAlloc::AlreadyFree if the frame was not allocated (it's logged as a double free).
+/// FrameAlloc::Referenced if the frame is pinned, or other pages still reference it (it's logged, 
+/// and the frame is not deallocated).
 /// FrameAlloc::InvalidAddr if the passed address is out of range.
-/// FrameAlloc::Err if any other error occured.
-pub fn dealloc(physical_addr: usize) -> FrameAllocResult {
-    unsafe {
-        // First, get a reference to the allocator and check if it's initialized.
-        let allocator = match &mut (FRAME_ALLOCATOR) {
-            Some(allocator) => allocator,
-            None => panic!("Frame allocator not initialized."),
-        };
-    
-        // Get the frame number and check for invalid memory range.
-        let frame_num = match allocator.addr_to_frame(physical_addr) {
+/// FrameAlloc::NotInitialized if the allocator is not initialized yet.
+pub fn dealloc(physical_addr: PhysAddr) -> FrameAllocResult {
+    // Translate the address and deallocate the frame in the same critical section.
+    let result = unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let frame_num = match allocator.addr_to_frame(physical_addr.as_usize()) {
             Ok(num) => num,
             Err(()) => return FrameAllocResult::InvalidAddr,
         };
         
-        // Lock the mutex, deallocate the frame, and unlock the mutex.
-        FRAME_ALLOCATOR_MUTEX.lock();
-        allocator.dealloc(frame_num);
-        FRAME_ALLOCATOR_MUTEX.unlock();
+        // The pinned and shared frames are only freed by the last ref_dec.
+        if refcount::is_referenced(frame_num) {
+            return FrameAllocResult::Referenced;
+        }
+        
+        match allocator.dealloc(frame_num) {
+            BitMapResult::Freed => {
+                refcount::set(frame_num, 0);
+                FrameAllocResult::Success
+            },
+            BitMapResult::WasAlreadyFree => FrameAllocResult::AlreadyFree,
+            BitMapResult::InvalidFrameNum => FrameAllocResult::InvalidAddr,
+            _ => FrameAllocResult::Internal("Unexpected bitmap result."),
+        }
+    }) };
+    
+    let result = result.unwrap_or(FrameAllocResult::NotInitialized);
+    if result == FrameAllocResult::AlreadyFree {
+        oxid_warn!("Double free of the frame at 0x{:x} (it was already free).", physical_addr);
+    } else if result == FrameAllocResult::Referenced {
+        oxid_warn!("Refusing to free the frame at 0x{:x} (it's pinned or shared).", physical_addr);
+    }
+    result
+}
 
-        // If we get here, everything went as expected, return success.
+/// A function which deallocates a run of frames which was allocated with alloc_contiguous. It is 
+/// also thread-safe.
+///
+/// # Parameters
+/// `physical_addr` : The physical address of the first frame in the run.
+/// `count` : The number of frames in the run.
+///
+/// # Returns
+/// FrameAlloc::Success if the frames were deallocated.
+/// FrameAlloc::InvalidAddr if any of the frames is out of range (none of them are deallocated).
+/// FrameAlloc::AlreadyFree if any of the frames was not allocated (it's logged as a double free,
+/// and none of them are deallocated).
+/// FrameAlloc::Referenced if any of the frames is pinned or shared (none of them are deallocated).
+pub fn dealloc_contiguous(physical_addr: PhysAddr, count: usize) -> FrameAllocResult {
+    // Translate the first and the last frames, and deallocate the run in the same critical
+    // section.
+    let result = unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let last_addr = physical_addr.as_usize() + count.saturating_sub(1) * FRAME_SIZE;
+        let frame_num = match (allocator.addr_to_frame(physical_addr.as_usize()), 
+            allocator.addr_to_frame(last_addr)) {
+            (Ok(num), Ok(_)) => num,
+            _ => return FrameAllocResult::InvalidAddr,
+        };
+        
+        // The pinned and shared frames are only freed by the last ref_dec.
+        if (frame_num..frame_num + count).any(refcount::is_referenced) {
+            return FrameAllocResult::Referenced;
+        }
+        
+        match allocator.dealloc_contiguous(frame_num, count) {
+            BitMapResult::Freed => {
+                (frame_num..frame_num + count).for_each(|num| refcount::set(num, 0));
+                FrameAllocResult::Success
+            },
+            BitMapResult::WasAlreadyFree => FrameAllocResult::AlreadyFree,
+            BitMapResult::InvalidFrameNum => FrameAllocResult::InvalidAddr,
+            _ => FrameAllocResult::Internal("Unexpected bitmap result."),
+        }
+    }) };
+    
+    let result = result.unwrap_or(FrameAllocResult::NotInitialized);
+    if result == FrameAllocResult::Success {
+        oxid_dbg!(Frames, "{} contiguous frames deallocated at 0x{:x}", count, physical_addr);
+    } else if result == FrameAllocResult::AlreadyFree {
+        oxid_warn!("Double free of the {} frames at 0x{:x} (some were already free).", count,
+            physical_addr);
+    } else if result == FrameAllocResult::Referenced {
+        oxid_warn!("Refusing to free the {} frames at 0x{:x} (some are pinned or shared).", count,
+            physical_addr);
+    }
+    result
+}
+
+/// A function which marks every frame within a given region as used, so it is never handed out by 
+/// the allocator. Frames which are out of the managed range or already used are simply skipped. If
+/// the allocator is not initialized yet, nothing happens (init will reserve the regions later).
+///
+/// # Parameters
+/// `region` : The physical memory region which we want to reserve.
+pub fn reserve_region(region: &Region) {
+    unsafe { FRAME_ALLOCATOR.with(|allocator| mark_region(allocator, region)) };
+}
+
+/// A helper which marks every frame within a region as used (and pins it's count), the frames
+/// which are out of the bitmap's range are skipped. It should be called in the critical section.
+///
+/// # Parameters
+/// `allocator` : The bitmap which the frames are marked in.
+/// `region` : The physical memory region which we want to mark.
+///
+/// # Returns
+/// The number of frames which were marked (the ones which were already used are not counted).
+fn mark_region(allocator: &mut BitMap, region: &Region) -> usize {
+    // Go through every frame in the region, and mark the ones which are in range as used.
+    let mut marked = 0;
+    let mut frame_addr = crate::mem::align::align_lower(region.addr, ALIGNMENT);
+    while frame_addr < region.end_addr() {
+        if let Ok(frame_num) = allocator.addr_to_frame(frame_addr) {
+            if let BitMapResult::Allocated(_) = allocator.alloc_frame_num(frame_num) {
+                marked += 1;
+            }
+            refcount::set(frame_num, refcount::PINNED);
+        }
+        
+        frame_addr += FRAME_SIZE;
+    }
+    
+    marked
+}
+
+/// A function which gives back the frames of the page tables which were used by the bootstrap code
+/// to identity map the memory, once the kernel's page table is loaded (see vmm::init). The kernel's
+/// page table reuses the boot PML4, PDP, and PD (and the PTs of the area which is still identity
+/// mapped), so only the PTs of the area which was unmapped are removed and deallocated.
+///
+/// # Returns
+/// The number of frames which were given back (0 if the kernel's page table is not loaded yet).
+pub unsafe fn reclaim_boot_tables() -> usize {
+    use crate::arch::mem::page_tables::{PageTables, NUM_ENTRIES};
+    use crate::mem::addr::VirtAddr;
+    
+    // The identity mapped area is only known once the kernel's page table is loaded.
+    let id_map_end = crate::mem::vmm::id_map_end();
+    if id_map_end == 0 {
+        return 0;
+    }
+    
+    // Every boot PT maps the next 2MB, only the ones after the identity mapped area are empty.
+    let pt_coverage = NUM_ENTRIES * FRAME_SIZE;
+    let (pts_start, pts_end) =
+        (crate::arch::mem::get_boot_pts_start(), crate::arch::mem::get_boot_pts_end());
+    let mut reclaimed = 0;
+    for (pt_num, pt_addr) in (pts_start..pts_end).step_by(FRAME_SIZE).enumerate() {
+        let region_addr = pt_num * pt_coverage;
+        if region_addr < id_map_end {
+            continue;
+        }
+        
+        // It's only removed if the PD still points to the boot PT (and it's empty).
+        let pt_frame = PhysAddr::new(pt_addr);
+        let unlinked = VirtAddr::new(region_addr)
+            .and_then(|addr| PageTables::unlink_empty_pt(addr, pt_frame));
+        if unlinked.is_ok() && dealloc(pt_frame) == FrameAllocResult::Success {
+            reclaimed += 1;
+        }
+    }
+    
+    oxid_log!("Reclaimed {} frames of the boot page tables.", reclaimed);
+    reclaimed
+}
+
+/// A function which reserves a physical address range (such as the registers of a device), so it's
+/// never handed out by the allocator. The frames which are in the bitmap's range are marked used,
+/// and the range is recorded so the frames which are out of it can't be allocated by address
+/// either. Reserving the same range twice only records it once.
+///
+/// # Parameters
+/// `phys_addr` : The physical address where the range starts.
+/// `size` : The number of bytes in the range.
+///
+/// # Returns
+/// FrameAlloc::Success if the range was reserved.
+/// FrameAlloc::InvalidAddr if the range goes past the end of the address space.
+/// FrameAlloc::Full if there are no more free slots for the reserved ranges.
+/// FrameAlloc::NotInitialized if the allocator is not initialized yet.
+pub fn reserve_range(phys_addr: PhysAddr, size: usize) -> FrameAllocResult {
+    let range = match Region::try_new_sized(phys_addr.as_usize(), size) {
+        Ok(range) => range,
+        Err(_) => return FrameAllocResult::InvalidAddr,
+    };
+    
+    // Record the range and mark it's frames in the same critical section.
+    let result = unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let recorded = RESERVED_RANGES.iter().flatten()
+            .any(|reserved| reserved.addr == range.addr && reserved.size == range.size);
+        if !recorded {
+            match RESERVED_RANGES.iter_mut().find(|slot| slot.is_none()) {
+                Some(slot) => *slot = Some(range),
+                None => return FrameAllocResult::Full,
+            }
+        }
+        
+        mark_region(allocator, &range);
         FrameAllocResult::Success
+    }) };
+    
+    let result = result.unwrap_or(FrameAllocResult::NotInitialized);
+    if result == FrameAllocResult::Full {
+        oxid_warn!("Could not reserve the range at 0x{:x}, the list is full.", phys_addr);
     }
+    result
+}
+
+/// A function which checks if a physical address is within any of the ranges which were reserved
+/// with reserve_range (the VMM uses it to tell the device memory apart from the regular RAM).
+///
+/// # Parameters
+/// `physical_addr` : The physical address which we're checking.
+///
+/// # Returns
+/// True if the address is reserved, False otherwise (or if the allocator is not initialized).
+pub fn is_reserved(physical_addr: PhysAddr) -> bool {
+    unsafe { FRAME_ALLOCATOR.with(|_| {
+        RESERVED_RANGES.iter().flatten().any(|range| range.includes(physical_addr.as_usize()))
+    }) }.unwrap_or(false)
+}
+
+/// A function which checks if the frame which includes a given physical address is used.
+///
+/// # Parameters
+/// `physical_addr` : The physical address within the frame which we're checking.
+///
+/// # Returns
+/// Ok(true) if used, Ok(false) if free, Err if the address is not managed by the allocator.
+pub fn is_used(physical_addr: PhysAddr) -> Result<bool, ()> {
+    // Translate the address and check the bit.
+    unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        allocator.is_used(allocator.addr_to_frame(physical_addr.as_usize())?)
+    }) }.expect("Frame allocator not initialized.")
+}
+
+/// A function which returns the number of frames which are currently marked as used.
+///
+/// # Returns
+/// The number of used frames.
+pub fn used_count() -> usize {
+    unsafe { FRAME_ALLOCATOR.with(|allocator| allocator.used_count()) }
+        .expect("Frame allocator not initialized.")
+}
+
+/// A function which logs the ranges of the physical memory which are currently used (for finding
+/// the frames which are leaked). It doesn't allocate, and it doesn't spin on the allocator's mutex,
+/// so it can be called before the heap is initialized (and from the panic handler).
+///
+/// # Parameters
+/// `max_ranges` : The maximum number of ranges which are logged (the rest are only counted).
+///
+/// # Returns
+/// The number of ranges which were logged.
+pub fn dump_used(max_ranges: usize) -> usize {
+    let dumped = unsafe { FRAME_ALLOCATOR.try_with(|allocator| {
+        let mut dumped = 0;
+        for range in allocator.used_ranges().take(max_ranges) {
+            oxid_log!("Used frames: 0x{:x} - 0x{:x} ({} frames).", range.addr, range.end_addr(),
+                range.size / FRAME_SIZE);
+            dumped += 1;
+        }
+        
+        let remaining = allocator.used_ranges().skip(max_ranges).count();
+        if remaining > 0 {
+            oxid_log!("Used frames: {} more ranges were not logged.", remaining);
+        }
+        dumped
+    }) };
+    
+    dumped.unwrap_or_else(|| {
+        oxid_warn!("The frame allocator is not available, the used frames are not dumped.");
+        0
+    })
+}
+
+/// A function which returns the number of frames which are managed by the frame allocator.
+///
+/// # Returns
+/// The total number of frames.
+pub fn total_count() -> usize {
+    unsafe { FRAME_ALLOCATOR.with(|allocator| allocator.total_count()) }
+        .expect("Frame allocator not initialized.")
 }
 
 /// A function which calculates the region which is mappable by this bitmap. It starts at the 
@@ -179,16 +653,12 @@ pub fn dealloc(physical_addr: usize) -> FrameAllocResult {
 /// # Returns
 /// A region which represents the usable memory area.
 pub fn get_mappable_region() -> Region {
-    unsafe {
-        // First, get a reference to the allocator and check if it's initialized.
-        let allocator = match & (FRAME_ALLOCATOR) {
-            Some(allocator) => allocator,
-            None => panic!("Frame allocator not initialized."),
-        };
-        
-        // Then get the value from the bitmap and return it.
-        allocator.get_mappable_region()
-    }
+    unsafe { FRAME_ALLOCATOR.with(|allocator| {
+        let start = crate::mem::align::align_higher(allocator.storage_region().end_addr(),
+            ALIGNMENT);
+        let end = allocator.get_mappable_region().end_addr();
+        Region::new(start, core::cmp::max(start, end))
+    }) }.expect("Frame allocator not initialized.")
 }
 
 // Unit Tests **************************************************************************************
@@ -203,5 +673,283 @@ pub mod test {
     /// sub module. 
     pub fn run() {
         super::bitmap::test::run();
+        super::mem_info::test::run();
+        super::refcount::test::run();
+        test_contiguous();
+        test_map_precedence();
+        test_double_free();
+        test_error_variants();
+        test_interrupt_stress();
+        test_reserve_range();
+        test_kernel_frames();
+        test_dump_used();
+    }
+    
+    /// Dump the used frames of the global allocator, and make sure only the maximum number of
+    /// ranges are logged (and nothing is logged while it's held).
+    fn test_dump_used() {
+        let num_ranges = unsafe { super::FRAME_ALLOCATOR.with(|allocator|
+            allocator.used_ranges().count()) }.unwrap();
+        assert!(num_ranges > 0);
+        assert_eq!(super::dump_used(2), core::cmp::min(num_ranges, 2));
+        assert_eq!(super::dump_used(0), 0);
+        
+        let nested = unsafe { super::FRAME_ALLOCATOR.with(|_| super::dump_used(2)) };
+        assert_eq!(nested, Some(0));
+    }
+    
+    /// A helper which finds an address after the end of the memory (which is not managed).
+    fn past_end() -> super::PhysAddr {
+        let end = super::get_mappable_region().end_addr();
+        super::PhysAddr::new(crate::mem::align::align_higher(end, super::FRAME_SIZE)
+            + 16 * super::FRAME_SIZE)
+    }
+    
+    /// Make sure the frames of the kernel (it's code and data), the first frame, and the bitmap's
+    /// storage are managed, and they're reported as used. Also make sure the boot page tables are
+    /// only reclaimed once.
+    fn test_kernel_frames() {
+        use super::{FrameAllocResult, PhysAddr};
+        
+        static KERNEL_DATA: [u8; 16] = [0xAA; 16];
+        let code = PhysAddr::new(test_kernel_frames as usize);
+        let data = PhysAddr::new(KERNEL_DATA.as_ptr() as usize);
+        let storage = unsafe { super::FRAME_ALLOCATOR.with(|allocator| allocator.storage_region()) }
+            .unwrap();
+        
+        for addr in [code, data, PhysAddr::new(0), PhysAddr::new(storage.addr)].iter() {
+            assert_eq!(super::is_used(*addr), Ok(true));
+            assert_eq!(super::alloc_frame(*addr), FrameAllocResult::AlreadyUsed);
+        }
+        
+        // The free memory starts after the bitmap.
+        assert!(super::get_mappable_region().addr >= storage.end_addr());
+        assert_eq!(unsafe { super::reclaim_boot_tables() }, 0);
+    }
+    
+    /// Reserve a range which is out of the bitmap's range (after the memory), and one which is in
+    /// it. Neither of them should be allocated by address, and the frames in range are marked used.
+    fn test_reserve_range() {
+        use super::{FrameAllocResult, PhysAddr, FRAME_SIZE};
+        
+        // The memory after the end is not managed by the bitmap, so only the list keeps it.
+        let device = past_end();
+        assert!(!super::is_reserved(device));
+        assert_eq!(super::alloc_frame(device), FrameAllocResult::InvalidAddr);
+        assert_eq!(super::reserve_range(device, FRAME_SIZE), FrameAllocResult::Success);
+        assert!(super::is_reserved(device) && super::is_reserved(device + (FRAME_SIZE - 1)));
+        assert!(!super::is_reserved(device + FRAME_SIZE));
+        assert_eq!(super::alloc_frame(device), FrameAllocResult::Reserved);
+        
+        // Take two free frames, and reserve them (reserving them again is not recorded twice).
+        let frames = match super::alloc_contiguous(2, 1) {
+            FrameAllocResult::Ok(addr) => addr,
+            result => panic!("Could not allocate the frames ({:?}).", result),
+        };
+        assert_eq!(super::dealloc_contiguous(frames, 2), FrameAllocResult::Success);
+        let used = super::used_count();
+        
+        assert_eq!(super::reserve_range(frames + 0x10, FRAME_SIZE), FrameAllocResult::Success);
+        assert_eq!(super::reserve_range(frames + 0x10, FRAME_SIZE), FrameAllocResult::Success);
+        let recorded = unsafe { super::RESERVED_RANGES.iter().flatten()
+            .filter(|range| range.addr == frames.as_usize() + 0x10).count() };
+        assert_eq!(recorded, 1);
+        
+        assert_eq!(super::used_count(), used + 2);
+        assert_eq!(super::is_used(frames + FRAME_SIZE), Ok(true));
+        assert_eq!(super::alloc_frame(frames), FrameAllocResult::Reserved);
+        assert_eq!(super::alloc_frame(frames + FRAME_SIZE), FrameAllocResult::Reserved);
+        assert!(!super::is_reserved(frames + 2 * FRAME_SIZE));
+        assert_eq!(super::reserve_range(PhysAddr::new(usize::MAX - 0xFFF), FRAME_SIZE),
+            FrameAllocResult::InvalidAddr);
+    }
+    
+    /// The number of frames which are held by each side of the stress test.
+    const STRESS_SLOTS: usize = 32;
+    
+    /// The number of ticks which the hook should run for (and the deadline for it in milliseconds).
+    const STRESS_TICKS: usize = 8;
+    const STRESS_DEADLINE_MS: usize = 2000;
+    
+    /// The frames which are currently held by the stress test (the foreground loop uses the first
+    /// half, and the tick hook uses the second one). A zero means the slot is empty.
+    static mut HELD: [usize; STRESS_SLOTS * 2] = [0; STRESS_SLOTS * 2];
+    
+    /// The number of times the tick hook ran, and if a frame was ever handed out twice.
+    static mut HOOK_RUNS: usize = 0;
+    static mut DOUBLE: bool = false;
+    
+    /// A function which fills (or empties) one of the slots of the stress test. A frame which is
+    /// allocated while another slot is still holding it is recorded as handed out twice.
+    ///
+    /// # Parameters
+    /// `slot` : The index of the slot in HELD.
+    fn toggle_slot(slot: usize) {
+        use super::{FrameAllocResult, PhysAddr};
+        
+        unsafe {
+            if HELD[slot] == 0 {
+                if let FrameAllocResult::Ok(addr) = super::alloc() {
+                    if HELD.iter().any(|held| *held == addr.as_usize()) {
+                        DOUBLE = true;
+                    }
+                    HELD[slot] = addr.as_usize();
+                }
+            } else {
+                // The slot is emptied first, so the frame is never held by two slots.
+                let addr = PhysAddr::new(HELD[slot]);
+                HELD[slot] = 0;
+                if super::dealloc(addr) != FrameAllocResult::Success {
+                    DOUBLE = true;
+                }
+            }
+        }
+    }
+    
+    /// The tick hook of the stress test, it toggles all of it's slots on every tick.
+    fn stress_hook() {
+        for slot in STRESS_SLOTS..(STRESS_SLOTS * 2) {
+            toggle_slot(slot);
+        }
+        unsafe { HOOK_RUNS += 1; }
+    }
+    
+    /// Allocate and deallocate the frames from the timer interrupt, while the foreground does the
+    /// same in a loop. No frame should ever be handed out twice (or freed when it's not used).
+    fn test_interrupt_stress() {
+        let used = super::used_count();
+        unsafe {
+            HELD = [0; STRESS_SLOTS * 2];
+            HOOK_RUNS = 0;
+            DOUBLE = false;
+        }
+        
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(STRESS_DEADLINE_MS);
+        crate::time::set_tick_hook(Some(stress_hook));
+        while unsafe { core::ptr::read_volatile(&HOOK_RUNS) } < STRESS_TICKS
+            && crate::time::ticks() < deadline {
+            for slot in 0..STRESS_SLOTS {
+                toggle_slot(slot);
+            }
+        }
+        crate::time::set_tick_hook(None);
+        
+        // Release what's left on both sides.
+        for slot in 0..(STRESS_SLOTS * 2) {
+            if unsafe { HELD[slot] } != 0 {
+                toggle_slot(slot);
+            }
+        }
+        
+        assert!(unsafe { HOOK_RUNS } > 0);
+        assert!(!unsafe { DOUBLE });
+        assert_eq!(super::used_count(), used);
+    }
+    
+    /// Make sure the invalid addresses, and the frames which are already used are reported with
+    /// their own variants.
+    fn test_error_variants() {
+        use super::{FrameAllocResult, PhysAddr};
+        
+        // The frames after the end of the memory are not managed by it.
+        assert_eq!(super::alloc_frame(past_end()), FrameAllocResult::InvalidAddr);
+        assert_eq!(super::dealloc(past_end()), FrameAllocResult::InvalidAddr);
+        let end = PhysAddr::new(super::get_mappable_region().end_addr());
+        assert_eq!(super::dealloc(end), FrameAllocResult::InvalidAddr);
+        assert_eq!(super::is_used(end), Err(()));
+        assert_eq!(super::alloc_frame(PhysAddr::new(usize::MAX & !0xFFF)),
+            FrameAllocResult::InvalidAddr);
+        
+        // A frame can't be allocated by address once it's used.
+        let addr = match super::alloc() {
+            FrameAllocResult::Ok(addr) => addr,
+            result => panic!("Could not allocate a frame ({:?}).", result),
+        };
+        assert_eq!(super::alloc_frame(addr), FrameAllocResult::AlreadyUsed);
+        assert_eq!(super::dealloc(addr), FrameAllocResult::Success);
+        assert_eq!(super::alloc_frame(addr), FrameAllocResult::Ok(addr));
+        assert_eq!(super::dealloc(addr), FrameAllocResult::Success);
+    }
+    
+    /// Free a frame of the global allocator twice, and make sure the second one is reported (and
+    /// the number of used frames is not changed by it).
+    fn test_double_free() {
+        use super::FrameAllocResult;
+        
+        let addr = match super::alloc() {
+            FrameAllocResult::Ok(addr) => addr,
+            _ => panic!("Could not allocate a frame."),
+        };
+        let used = super::used_count();
+        
+        assert_eq!(super::dealloc(addr), FrameAllocResult::Success);
+        assert_eq!(super::used_count(), used - 1);
+        assert_eq!(super::dealloc(addr), FrameAllocResult::AlreadyFree);
+        assert_eq!(super::used_count(), used - 1);
+        assert_eq!(super::is_used(addr), Ok(false));
+    }
+    
+    /// Reserve the frames of synthetic memory maps which disagree, and make sure the EFI one is
+    /// preferred (and the legacy one is only used without it).
+    fn test_map_precedence() {
+        use super::{MapSource, FRAME_SIZE};
+        use crate::multiboot2::efi_mem_map::EfiMemType;
+        use crate::multiboot2::mem_map::{MemMap, MemMapEntType};
+        
+        // Manage 130 frames (the bitmap takes the first one, so 129 are left).
+        let scratch = crate::mem::test::scratch(130 * FRAME_SIZE);
+        let start = crate::mem::test::fresh_bitmap(&scratch).get_mappable_region().addr as u64;
+        let frame = FRAME_SIZE as u64;
+        
+        // The legacy map claims every frame is available (the header and a single entry).
+        let mut map_buffer: [u64; 5] = [0; 5];
+        map_buffer[0] = 6 | ((map_buffer.len() as u64 * 8) << 32);
+        map_buffer[1] = 24;
+        map_buffer[2..5].copy_from_slice(&[start, 129 * frame, MemMapEntType::Available as u64]);
+        let mem_map = unsafe { MemMap::new(map_buffer.as_ptr() as usize, map_buffer.len() * 8) };
+        
+        // The EFI map has the runtime services in the second half.
+        let mut efi_buffer: [u64; 14] = [0; 14];
+        let efi_map = super::bitmap::test::efi_map(&mut efi_buffer, 
+            &[(EfiMemType::Conventional, start, 64), 
+            (EfiMemType::RuntimeServicesData, start + 64 * frame, 65)]);
+        
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        assert_eq!(super::reserve_from_maps(&mut bitmap, Some(mem_map), Some(efi_map), false), 
+            (MapSource::Efi, 65));
+        assert_eq!((bitmap.is_used(63), bitmap.is_used(64)), (Ok(false), Ok(true)));
+        
+        let mut bitmap = crate::mem::test::fresh_bitmap(&scratch);
+        assert_eq!(super::reserve_from_maps(&mut bitmap, Some(mem_map), None, false), 
+            (MapSource::Legacy, 0));
+        assert_eq!(super::reserve_from_maps(&mut bitmap, None, None, true), 
+            (MapSource::Missing, 0));
+        assert_eq!(bitmap.used_count(), 0);
+        
+        crate::mem::test::free_scratch(&scratch);
+    }
+    
+    /// Allocate an aligned run of frames from the global allocator, and make sure every frame in
+    /// it is used until the run is deallocated.
+    fn test_contiguous() {
+        use super::{FrameAllocResult, FRAME_SIZE};
+        
+        let addr = match super::alloc_contiguous(8, 8) {
+            FrameAllocResult::Ok(addr) => addr,
+            _ => panic!("Could not allocate 8 contiguous frames."),
+        };
+        assert_eq!(addr.as_usize() % (8 * FRAME_SIZE), 0);
+        
+        let frames = || (0..8).map(|i| super::PhysAddr::new(addr.as_usize() + i * FRAME_SIZE));
+        assert!(frames().all(|frame| super::is_used(frame) == Ok(true)));
+        
+        assert_eq!(super::dealloc_contiguous(addr, 8), FrameAllocResult::Success);
+        assert!(frames().all(|frame| super::is_used(frame) == Ok(false)));
+        assert_eq!(super::dealloc_contiguous(addr, 8), FrameAllocResult::AlreadyFree);
+        
+        // The invalid requests are rejected (the first frame is managed now, so use an address
+        // past the end of the memory instead).
+        assert!(matches!(super::alloc_contiguous(0, 1), FrameAllocResult::Internal(_)));
+        assert_eq!(super::dealloc_contiguous(past_end(), 8), FrameAllocResult::InvalidAddr);
     }
 }
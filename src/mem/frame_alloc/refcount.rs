@@ -0,0 +1,229 @@
+//! A sub-module which counts the references to the frames (the number of pages which are mapped to
+//! them), so the frames which are shared by a few mappings (such as the copy-on-write pages, and
+//! the shared program text) are only deallocated once the last one of them is unmapped. It keeps a
+//! u16 per frame in a side table (indexed by the frame numbers of the bitmap), which is allocated
+//! from the heap once it's ready. The frames which were already used before that (the kernel's
+//! region, the early allocations, and the first page tables) are pinned, so they're never
+//! deallocated through it.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::mem::addr::PhysAddr;
+
+/// The count of the frames which are never deallocated by unmapping them. A count which reaches it
+/// stays pinned as well.
+pub const PINNED: u16 = u16::MAX;
+
+/// The reference counts of the frames (None until init is called).
+static mut COUNTS: Option<&'static mut [u16]> = None;
+
+/// A function which allocates the table of the counts from the heap, and pins every frame which is
+/// already used. It should be called right after the heap is initialized.
+pub unsafe fn init() {
+    let num_frames = super::total_count();
+    let table = crate::mem::dyn_alloc::kmalloc_zeroed(num_frames * core::mem::size_of::<u16>(),
+        false, true, true) as *mut u16;
+    if table.is_null() {
+        oxid_warn!("Could not allocate the frame reference counts, the frames are not shared.");
+        return;
+    }
+
+    // The table's own frames (and everything before it) are pinned.
+    let counts = core::slice::from_raw_parts_mut(table, num_frames);
+    super::FRAME_ALLOCATOR.with(move |allocator| {
+        for (frame_num, count) in counts.iter_mut().enumerate() {
+            if allocator.is_used(frame_num) == Ok(true) {
+                *count = PINNED;
+            }
+        }
+        COUNTS = Some(counts);
+    });
+
+    oxid_log!("Initialized the frame reference counts ({} frames).", num_frames);
+}
+
+/// A function which sets the count of a frame. It's called by the frame allocator (in it's critical
+/// section) when the frame is allocated, reserved, or deallocated.
+///
+/// # Parameters
+/// `frame_num` : The number of the frame in the bitmap.
+/// `count` : The new count (1 when it's allocated, PINNED if it's reserved, 0 when it's freed).
+pub(super) fn set(frame_num: usize, count: u16) {
+    unsafe {
+        if let Some(entry) = COUNTS.as_mut().and_then(|counts| counts.get_mut(frame_num)) {
+            *entry = count;
+        }
+    }
+}
+
+/// A function which checks if a frame can't be deallocated directly, since it's pinned or other
+/// pages still reference it (those should be unmapped, and only the last ref_dec frees it). It's
+/// called by the frame allocator (in it's critical section) before the frame is deallocated.
+///
+/// # Parameters
+/// `frame_num` : The number of the frame in the bitmap.
+///
+/// # Returns
+/// True if the count is PINNED or more than 1, false otherwise (or if it's not counted).
+pub(super) fn is_referenced(frame_num: usize) -> bool {
+    unsafe {
+        COUNTS.as_ref().and_then(|counts| counts.get(frame_num))
+            .map_or(false, |&count| count == PINNED || count > 1)
+    }
+}
+
+/// A function which applies a change to the count of the frame which includes an address.
+///
+/// # Parameters
+/// `physical_addr` : The physical address within the frame.
+/// `change` : The function which returns the new count from the current one.
+///
+/// # Returns
+/// Some with the new count, or None if the counts are not initialized (or it's out of range).
+fn update(physical_addr: PhysAddr, change: impl FnOnce(u16) -> u16) -> Option<u16> {
+    unsafe {
+        super::FRAME_ALLOCATOR.with(|allocator| {
+            let frame_num = allocator.addr_to_frame(physical_addr.as_usize()).ok()?;
+            let entry = COUNTS.as_mut()?.get_mut(frame_num)?;
+            *entry = change(*entry);
+            Some(*entry)
+        }).flatten()
+    }
+}
+
+/// A function which adds a reference to a frame (it should be called before another page is mapped
+/// to a frame which is already mapped).
+///
+/// # Parameters
+/// `physical_addr` : The physical address within the frame.
+///
+/// # Returns
+/// The new number of references (PINNED if it's pinned), or 0 if the frame is not counted.
+pub fn ref_inc(physical_addr: PhysAddr) -> usize {
+    update(physical_addr, |count| if count == PINNED { PINNED } else { count + 1 })
+        .map_or(0, |count| count as usize)
+}
+
+/// A function which removes a reference to a frame (it's called when a page is unmapped). The
+/// frame should only be deallocated if there are no references left.
+///
+/// # Parameters
+/// `physical_addr` : The physical address within the frame.
+///
+/// # Returns
+/// The number of references which are left (PINNED if it's pinned). It's 0 if it was the last one,
+/// or if the frame is not counted (the counts are not initialized, or it's out of range).
+pub fn ref_dec(physical_addr: PhysAddr) -> usize {
+    update(physical_addr, |count| if count == PINNED { PINNED } else { count.saturating_sub(1) })
+        .map_or(0, |count| count as usize)
+}
+
+/// A simple getter for the number of references to a frame.
+///
+/// # Parameters
+/// `physical_addr` : The physical address within the frame.
+///
+/// # Returns
+/// Some with the number of references (PINNED if it's pinned), or None if it's not counted.
+pub fn ref_count(physical_addr: PhysAddr) -> Option<usize> {
+    update(physical_addr, |count| count).map(|count| count as usize)
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::mem::addr::{PhysAddr, VirtAddr};
+    use crate::mem::frame_alloc::FrameAllocResult;
+    use super::PINNED;
+
+    /// The pages which the shared frame is mapped at (in the unused higher half).
+    const FIRST_PAGE: usize = 0xFFFF_FE00_0030_0000;
+    const SECOND_PAGE: usize = 0xFFFF_FE00_0030_1000;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_shared_frame();
+        test_pinned();
+        test_referenced_dealloc();
+    }
+
+    /// Map the same frame at two pages, and make sure it's only deallocated once both of them are
+    /// unmapped.
+    fn test_shared_frame() {
+        let frame = match crate::mem::frame_alloc::alloc() {
+            FrameAllocResult::Ok(addr) => addr,
+            _ => panic!("Could not allocate a frame."),
+        };
+        assert_eq!(super::ref_count(frame), Some(1));
+
+        let (first, second) = (VirtAddr::new(FIRST_PAGE).unwrap(),
+            VirtAddr::new(SECOND_PAGE).unwrap());
+        unsafe {
+            assert!(crate::mem::vmm::lazy_map(first, frame, false, true, true).is_ok());
+            assert_eq!(super::ref_inc(frame), 2);
+            assert!(crate::mem::vmm::lazy_map(second, frame, false, true, true).is_ok());
+
+            // Both pages see the same frame.
+            *first.as_mut_ptr::<usize>() = 0xC0FFEE;
+            assert_eq!(*second.as_ptr::<usize>(), 0xC0FFEE);
+
+            assert!(crate::mem::vmm::unmap(first).is_ok());
+            assert_eq!(super::ref_count(frame), Some(1));
+            assert_eq!(crate::mem::frame_alloc::is_used(frame), Ok(true));
+
+            assert!(crate::mem::vmm::unmap(second).is_ok());
+            assert_eq!(super::ref_count(frame), Some(0));
+            assert_eq!(crate::mem::frame_alloc::is_used(frame), Ok(false));
+        }
+    }
+
+    /// Make sure the frames which were used before the counts were initialized are pinned (the
+    /// first frame after the bitmap is in the early region, and the first frame is the kernel's),
+    /// and the ones after the memory are not counted.
+    fn test_pinned() {
+        let early = PhysAddr::new(crate::mem::frame_alloc::get_mappable_region().addr);
+        for frame in [early, PhysAddr::new(0)].iter() {
+            assert_eq!(super::ref_count(*frame), Some(PINNED as usize));
+            assert_eq!(super::ref_inc(*frame), PINNED as usize);
+            assert_eq!(super::ref_dec(*frame), PINNED as usize);
+        }
+
+        let past_end = crate::mem::frame_alloc::get_mappable_region().end_addr() + 0x10000;
+        assert_eq!(super::ref_count(PhysAddr::new(past_end)), None);
+        assert_eq!(super::ref_dec(PhysAddr::new(past_end)), 0);
+    }
+
+    /// Deallocate a pinned frame and a shared frame directly, and make sure both are refused (they
+    /// stay used, and keep their counts) until the shared one's last reference is left.
+    fn test_referenced_dealloc() {
+        use crate::mem::frame_alloc::{alloc, dealloc, dealloc_contiguous, is_used};
+
+        let early = PhysAddr::new(crate::mem::frame_alloc::get_mappable_region().addr);
+        assert_eq!(dealloc(early), FrameAllocResult::Referenced);
+        assert_eq!(dealloc_contiguous(early, 1), FrameAllocResult::Referenced);
+        assert_eq!(is_used(early), Ok(true));
+        assert_eq!(super::ref_count(early), Some(PINNED as usize));
+
+        let frame = match alloc() {
+            FrameAllocResult::Ok(addr) => addr,
+            _ => panic!("Could not allocate a frame."),
+        };
+        assert_eq!(super::ref_inc(frame), 2);
+        assert_eq!(dealloc(frame), FrameAllocResult::Referenced);
+        assert_eq!(is_used(frame), Ok(true));
+        assert_eq!(super::ref_count(frame), Some(2));
+
+        // Once it's not shared, it's freed as usual.
+        assert_eq!(super::ref_dec(frame), 1);
+        assert_eq!(dealloc(frame), FrameAllocResult::Success);
+        assert_eq!(is_used(frame), Ok(false));
+        assert_eq!(super::ref_count(frame), Some(0));
+    }
+}
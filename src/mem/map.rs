@@ -4,17 +4,249 @@
 //! `Author` : Ardalan Ahanchi
 //! `Date` : March 2021
 
+use crate::multiboot2::boot_cmd::BootCmd;
+use super::region::Region;
+use super::align::align_higher;
+use super::frame_alloc::FRAME_SIZE;
+
 /// The address where the kernel ends (also end of id mapped area, set by mem::mod.rs).
 pub static mut KERNEL_END_ADDR: usize = 0;
 
-/// The maximum address which can be used to store heap metadata (set it at the end of 4GB mark).
-pub const KERNEL_HEAP_METADATA_END_ADDR: usize = 0x100000000;
+/// The default end of the heap metadata, used if it's not tuned (set it at the end of 4GB mark).
+pub const DEFAULT_HEAP_METADATA_END_ADDR: usize = 0x100000000;
 
 /// The maximum address covered in the heap (Set it at the end of lower half).
-pub const KERNEL_HEAP_END_ADDR: usize = 0x7FFFFFFFFFFF;
+pub const MAX_HEAP_END_ADDR: usize = crate::mem::MAX_USER_ADDR;
+
+/// The minimum size of the heap arena which can be requested with `heap_mb=` (1MB).
+pub const MIN_HEAP_SIZE: usize = 0x100000;
+
+/// The minimum size of the heap metadata which can be requested with `heap_meta_kb=` (64KB).
+pub const MIN_HEAP_METADATA_SIZE: usize = 0x10000;
+
+/// A struct which holds the heap geometry of the kernel address space. The metadata starts at the 
+/// end of the kernel, and the heap arena starts right after the metadata.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemLayout {
+    pub heap_metadata_start: usize,     // The start of the heap metadata (the kernel's end).
+    pub heap_metadata_end: usize,       // The end of the metadata (and the start of the heap).
+    pub heap_end: usize,                // The end of the heap arena.
+}
+
+/// The live layout which is used by the memory code (set by mem::init).
+static mut LAYOUT: MemLayout = MemLayout { 
+    heap_metadata_start: 0, 
+    heap_metadata_end: DEFAULT_HEAP_METADATA_END_ADDR, 
+    heap_end: MAX_HEAP_END_ADDR,
+};
+
+impl MemLayout {
+    /// A method which returns the region used for the heap metadata.
+    ///
+    /// # Returns
+    /// The metadata region.
+    pub fn metadata_region(&self) -> Region {
+        Region::new(self.heap_metadata_start, self.heap_metadata_end)
+    }
+    
+    /// A method which returns the region used for the heap arena.
+    ///
+    /// # Returns
+    /// The heap region.
+    pub fn heap_region(&self) -> Region {
+        Region::new(self.heap_metadata_end, self.heap_end)
+    }
+}
+
+/// A function which parses a size option from the command line (such as `heap_mb=64`).
+///
+/// # Parameters
+/// `value` : The value of the option (a decimal number).
+/// `unit` : The number of bytes in each unit of the option (such as 1MB for heap_mb).
+/// `min` : The minimum accepted size in bytes.
+///
+/// # Returns
+/// Ok(size) in bytes if it was valid, Err if it's not a number, too small, or it overflowed.
+pub fn parse_size(value: &str, unit: usize, min: usize) -> Result<usize, ()> {
+    let size = value.trim().parse::<usize>().map_err(|_| ())?
+        .checked_mul(unit).ok_or(())?;
+    
+    if size < min {
+        return Err(());
+    }
+    
+    Ok(size)
+}
+
+/// A function which computes the heap geometry based on the requested sizes. The sizes are 
+/// clamped to the available memory, and the heap is clamped so it never goes past the lower half.
+///
+/// # Parameters
+/// `kernel_end` : The address where the kernel ends (start of the metadata).
+/// `avail_mem` : The amount of usable physical memory in bytes.
+/// `heap_size` : The requested size of the heap arena (None for the default).
+/// `metadata_size` : The requested size of the heap metadata (None for the default).
+///
+/// # Returns
+/// The computed layout, and True if any of the requested sizes had to be clamped.
+pub fn compute_layout(kernel_end: usize, avail_mem: usize, heap_size: Option<usize>, 
+    metadata_size: Option<usize>) -> (MemLayout, bool) {
+    let mut clamped = false;
+    
+    // Calculate where the metadata ends (it can't be bigger than the memory).
+    let heap_metadata_end = match metadata_size {
+        Some(size) => {
+            if size > avail_mem {
+                clamped = true;
+            }
+            
+            let size = size.min(avail_mem).max(MIN_HEAP_METADATA_SIZE);
+            align_higher(kernel_end + size, FRAME_SIZE)
+        },
+        None => DEFAULT_HEAP_METADATA_END_ADDR,
+    };
+    
+    // Calculate where the heap ends (it can't be bigger than the memory, or pass the lower half).
+    let heap_end = match heap_size {
+        Some(size) => {
+            let max_size = avail_mem.min(MAX_HEAP_END_ADDR - heap_metadata_end);
+            if size > max_size {
+                clamped = true;
+            }
+            
+            let size = size.min(max_size).max(MIN_HEAP_SIZE);
+            align_higher(heap_metadata_end + size, FRAME_SIZE).min(MAX_HEAP_END_ADDR)
+        },
+        None => MAX_HEAP_END_ADDR,
+    };
+    
+    (MemLayout { heap_metadata_start: kernel_end, heap_metadata_end, heap_end }, clamped)
+}
+
+/// A function which initializes the live layout from the `heap_mb=` and `heap_meta_kb=` kernel
+/// command line options. Invalid options are ignored, and oversized ones are clamped.
+///
+/// # Parameters
+/// `kernel_end` : The address where the kernel ends (start of the metadata).
+/// `avail_mem` : The amount of usable physical memory in bytes.
+/// `cmd` : The kernel command line (if it was passed).
+pub unsafe fn init(kernel_end: usize, avail_mem: usize, cmd: Option<&BootCmd>) {
+    // Parse the size options (ignore them if they're not valid).
+    let parse = |key: &str, unit: usize, min: usize| {
+        let value = cmd.and_then(|cmd| cmd.get(key))?;
+        match parse_size(value, unit, min) {
+            Ok(size) => Some(size),
+            Err(()) => {
+                oxid_warn!("Invalid {} option passed \"{}\" (minimum is {}). Ignoring it.", 
+                    key, value, min / unit);
+                None
+            },
+        }
+    };
+    
+    let heap_size = parse("heap_mb", 0x100000, MIN_HEAP_SIZE);
+    let metadata_size = parse("heap_meta_kb", 0x400, MIN_HEAP_METADATA_SIZE);
+    
+    // Compute the layout and let the user know if it didn't fit.
+    let (layout, clamped) = compute_layout(kernel_end, avail_mem, heap_size, metadata_size);
+    if clamped {
+        oxid_warn!("The requested heap size does not fit in memory. It was clamped.");
+    }
+    
+    oxid_log!("Heap geometry: metadata=0x{:x}-0x{:x}, heap=0x{:x}-0x{:x}", 
+        layout.heap_metadata_start, layout.heap_metadata_end, layout.heap_metadata_end, 
+        layout.heap_end);
+    
+    KERNEL_END_ADDR = kernel_end;
+    LAYOUT = layout;
+}
+
+/// A simple getter for the live memory layout.
+///
+/// # Returns
+/// A copy of the current heap geometry.
+pub fn layout() -> MemLayout {
+    unsafe { LAYOUT }
+}
+
+/// A function which returns the region where the page tables are accessed (it depends on the 
+/// PML4 entry which was chosen by arch::mem::page_tables, so it's only final after vmm::init).
+///
+/// # Returns
+/// The virtual memory region of the page tables.
+pub fn page_tables_region() -> Region {
+    crate::arch::mem::page_tables::window().region()
+}
+
+// Unit Tests **************************************************************************************
 
-/// The starting address where the page tables are stored (set by arch::mem::page_tables).
-pub const PAGE_TABLES_START_ADDR: usize = crate::arch::mem::page_tables::PAGE_TABLES_VM_START;
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{MAX_HEAP_END_ADDR, MIN_HEAP_SIZE, MIN_HEAP_METADATA_SIZE};
+    use super::DEFAULT_HEAP_METADATA_END_ADDR;
 
-/// The ending address where the page tables are stored (set by arch::mem::page_tables).
-pub const PAGE_TABLES_END_ADDR: usize = crate::arch::mem::page_tables::PAGE_TABLES_VM_END;
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_parse_size();
+        test_default_layout();
+        test_tuned_layout();
+        test_clamped_layout();
+    }
+    
+    /// Check the parsing and validation of the size options.
+    fn test_parse_size() {
+        assert_eq!(super::parse_size("64", 0x100000, MIN_HEAP_SIZE), Ok(64 * 0x100000));
+        assert_eq!(super::parse_size("128", 0x400, MIN_HEAP_METADATA_SIZE), Ok(128 * 0x400));
+        assert_eq!(super::parse_size("0", 0x100000, MIN_HEAP_SIZE), Err(()));
+        assert_eq!(super::parse_size("16", 0x400, MIN_HEAP_METADATA_SIZE), Err(()));
+        assert_eq!(super::parse_size("-4", 0x400, MIN_HEAP_METADATA_SIZE), Err(()));
+        assert_eq!(super::parse_size("lots", 0x100000, MIN_HEAP_SIZE), Err(()));
+        assert_eq!(super::parse_size("18446744073709551615", 0x100000, MIN_HEAP_SIZE), Err(()));
+    }
+    
+    /// Make sure the default layout matches the old fixed geometry.
+    fn test_default_layout() {
+        let (layout, clamped) = super::compute_layout(0x200000, 0x8000000, None, None);
+        assert!(!clamped);
+        assert_eq!(layout.heap_metadata_start, 0x200000);
+        assert_eq!(layout.heap_metadata_end, DEFAULT_HEAP_METADATA_END_ADDR);
+        assert_eq!(layout.heap_end, MAX_HEAP_END_ADDR);
+    }
+    
+    /// Check that the requested sizes are used (and page aligned).
+    fn test_tuned_layout() {
+        let (layout, clamped) = super::compute_layout(0x200000, 0x8000000, 
+            Some(16 * 0x100000), Some(0x10800));
+        assert!(!clamped);
+        assert_eq!(layout.heap_metadata_end, 0x200000 + 0x11000);
+        assert_eq!(layout.heap_end, layout.heap_metadata_end + 16 * 0x100000);
+        assert_eq!(layout.metadata_region().size, 0x11000);
+        assert_eq!(layout.heap_region().size, 16 * 0x100000);
+    }
+    
+    /// Check that oversized requests are clamped instead of producing overlapping regions.
+    fn test_clamped_layout() {
+        // A heap which is bigger than the memory.
+        let (layout, clamped) = super::compute_layout(0x200000, 0x8000000, 
+            Some(0x10000000), None);
+        assert!(clamped);
+        assert_eq!(layout.heap_region().size, 0x8000000);
+        
+        // Metadata which is bigger than the memory.
+        let (layout, clamped) = super::compute_layout(0x200000, 0x8000000, 
+            Some(MIN_HEAP_SIZE), Some(0x10000000));
+        assert!(clamped);
+        assert_eq!(layout.metadata_region().size, 0x8000000);
+        assert!(layout.heap_metadata_end <= layout.heap_region().addr);
+        
+        // A heap which would go past the lower half.
+        let (layout, clamped) = super::compute_layout(MAX_HEAP_END_ADDR - 0x400000, usize::MAX, 
+            Some(0x10000000), Some(MIN_HEAP_METADATA_SIZE));
+        assert!(clamped);
+        assert!(layout.heap_end <= MAX_HEAP_END_ADDR);
+        assert!(layout.heap_metadata_end <= layout.heap_end);
+    }
+}
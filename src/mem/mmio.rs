@@ -0,0 +1,141 @@
+//! A sub-module which keeps track of the physical address ranges which are used for memory mapped
+//! I/O. These ranges are identity mapped by the kernel, but they are not regular RAM. So the frame
+//! allocator should never hand them out, and the VMM should never unmap (and free) them unless it
+//! is explicitly forced to.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::mem::region::Region;           // To represent the reserved ranges.
+use crate::mem::addr::PhysAddr;           // To reserve the ranges in the frame allocator.
+use crate::proc::mutex::Mutex;            // For safe access to the reserved list.
+
+/// The maximum number of MMIO ranges which can be reserved.
+const MAX_RESERVED: usize = 16;
+
+/// The legacy VGA window (graphics and text mode buffers), which is always reserved.
+const VGA_MMIO: Region = Region { addr: 0xA0000, size: 0x20000 };
+
+/// The list of the reserved MMIO regions (None means the slot is unused).
+static mut RESERVED_MMIO: [Option<Region>; MAX_RESERVED] = {
+    let mut reserved: [Option<Region>; MAX_RESERVED] = [None; MAX_RESERVED];
+    reserved[0] = Some(VGA_MMIO);
+    reserved
+};
+
+/// The mutex for the reserved list to allow registering from different drivers safely.
+static mut RESERVED_MMIO_MUTEX: Mutex = Mutex::new();
+
+/// A function which reserves a physical memory region for memory mapped I/O. If the frame allocator
+/// is already initialized, the region is reserved in it right away (see
+/// frame_alloc::reserve_range). Otherwise, it will be reserved when the frame allocator is
+/// initialized.
+///
+/// # Parameters
+/// `region` : The physical memory region which is used by a device.
+///
+/// # Returns
+/// Ok if the region was reserved, Err if there are no more free slots.
+pub fn reserve_mmio(region: Region) -> Result<(), ()> {
+    unsafe {
+        // Find an empty slot in the list and store the region in it.
+        RESERVED_MMIO_MUTEX.lock();
+        let slot = RESERVED_MMIO.iter_mut().find(|slot| slot.is_none());
+        let stored = match slot {
+            Some(slot) => { *slot = Some(region); true },
+            None => false,
+        };
+        RESERVED_MMIO_MUTEX.unlock();
+
+        // If there was no space left, let the caller know.
+        if !stored {
+            oxid_warn!("Could not reserve MMIO region at 0x{:x}, the list is full.", region.addr);
+            return Err(());
+        }
+    }
+
+    // Make sure the frames are never handed out by the frame allocator.
+    crate::mem::frame_alloc::reserve_range(PhysAddr::new(region.addr), region.size);
+    Ok(())
+}
+
+/// A function which checks if a given physical address is within any of the reserved regions.
+///
+/// # Parameters
+/// `addr` : The physical address which we're checking.
+///
+/// # Returns
+/// True if the address is used for memory mapped I/O, False otherwise.
+pub fn is_reserved(addr: usize) -> bool {
+    unsafe {
+        RESERVED_MMIO.iter().flatten().any(|region| region.includes(addr))
+    }
+}
+
+/// A function which returns a copy of the currently reserved regions.
+///
+/// # Returns
+/// The list of reserved regions (None is an empty slot).
+pub fn reserved_regions() -> [Option<Region>; MAX_RESERVED] {
+    unsafe { RESERVED_MMIO }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::mem::frame_alloc::{self, FrameAllocResult};
+    use crate::mem::addr::{PhysAddr, VirtAddr};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_vga_reserved();
+        test_alloc_skips_reserved();
+        test_unmap_rejected();
+    }
+
+    /// Make sure the default VGA range is reserved.
+    fn test_vga_reserved() {
+        assert!(super::is_reserved(0xA0000));
+        assert!(super::is_reserved(0xb8000));
+        assert!(super::is_reserved(0xBFFFF));
+        assert!(!super::is_reserved(0xC0000));
+    }
+
+    /// Allocate many frames and make sure none of them are in a reserved region.
+    fn test_alloc_skips_reserved() {
+        const NUM_FRAMES: usize = 64;
+        let mut frames: [PhysAddr; NUM_FRAMES] = [PhysAddr::new(0); NUM_FRAMES];
+
+        // Allocate the frames and check every one of them.
+        for frame in frames.iter_mut() {
+            *frame = match frame_alloc::alloc() {
+                FrameAllocResult::Ok(addr) => addr,
+                _ => panic!("Could not allocate a frame for the MMIO test."),
+            };
+
+            assert!(!super::is_reserved(frame.as_usize()));
+        }
+
+        // Give the frames back.
+        for frame in frames.iter() {
+            frame_alloc::dealloc(*frame);
+        }
+    }
+
+    /// Make sure unmapping the VGA text buffer is refused, and it stays mapped.
+    fn test_unmap_rejected() {
+        let page = VirtAddr::new(0xb8000).unwrap();
+        unsafe {
+            assert!(crate::mem::vmm::unmap(page).is_err());
+            assert!(crate::mem::vmm::unmap_range(page, 0x2000).is_err());
+        }
+
+        assert_eq!(crate::mem::vmm::virt_to_phys(page), Ok(PhysAddr::new(0xb8000)));
+    }
+}
@@ -5,6 +5,7 @@
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Feb 2021
 
+pub mod addr;
 pub mod dyn_alloc;
 pub mod frame_alloc;
 pub mod align;
@@ -13,29 +14,69 @@ pub mod vmm;
 pub mod page_fault;
 pub mod region;
 pub mod map;
+pub mod mmio;
+pub mod audit;
+pub mod early_alloc;
 
 use crate::multiboot2::MultibootInfo;
-use region::Region;
+
+#[allow(unused_imports)]
+pub use mmio::reserve_mmio;
+
+use crate::arch::mem::page_tables::VIRT_ADDR_WIDTH;
+
+/// The highest canonical address in the lower half (the end of the user address space).
+pub const MAX_USER_ADDR: usize = (1 << (VIRT_ADDR_WIDTH - 1)) - 1;
+
+/// The lowest canonical address in the higher half (the start of the kernel address space).
+pub const MIN_KERNEL_ADDR: usize = !MAX_USER_ADDR;
 
 /// A function which initializes the bitmap memory section of the kernel. It initializes the frame 
 /// allocator, page tables, and identity maps the correct amount of memory.
 ///
+/// The memory is brought up in phases, and each one can only use the ones before it:
+/// 1. The frame allocator (it's bitmap is at the end of the kernel, in the boot identity map). It
+///    manages all of the memory, and marks the kernel's frames used. It also reserves the early
+///    allocation region, so `early_alloc` can be used from this point.
+/// 2. The memory map (the heap geometry, it starts after the early region).
+/// 3. The virtual memory manager (it identity maps everything up to the heap metadata, including
+///    the early region, and needs frames for the new tables). Once it's page table is loaded, the
+///    boot page tables of the area which was unmapped are given back to the frame allocator.
+/// 4. The heap (it needs the page tables to map it's pages). Once it's ready, the early allocator
+///    is closed, and everything else should use the heap.
+/// 5. The frame reference counts (their table is on the heap). The frames which were used before
+///    them are pinned.
+///
 /// # Parameters
 /// `mb_info` : The multiboot information reference (already parsed).
 pub unsafe fn init(mb_info: &MultibootInfo) {
     // First initialize the frame allocator using the multiboot information.
     frame_alloc::init(&mb_info);
     
-    // Get the mappable physical memory from the frame allocator and initialize the KERNEL_END_ADDR.
-    map::KERNEL_END_ADDR = frame_alloc::get_mappable_region().addr;
+    // Get the mappable physical memory from the frame allocator, and compute the heap geometry 
+    // (which can be tuned from the command line).
+    // The early region is at the start of the mappable memory, so the kernel ends after it.
+    let mappable = frame_alloc::get_mappable_region();
+    let early_end = early_alloc::region().map_or(mappable.addr, |region| region.end_addr());
+    map::init(early_end, mappable.end_addr() - early_end, mb_info.boot_cmd_tag.as_ref());
     
     // Initialize the virtual mem manager and identity map everything up to the the usable region.
     vmm::init(map::KERNEL_END_ADDR);
+    frame_alloc::reclaim_boot_tables();
     
     // Initialize the kernel dynamic memory allocator (heap).
-    let metadata_mem = Region::new(map::KERNEL_END_ADDR, map::KERNEL_HEAP_METADATA_END_ADDR);
-    let heap_mem = Region::new(map::KERNEL_HEAP_METADATA_END_ADDR, map::KERNEL_HEAP_END_ADDR);
-    dyn_alloc::init(&metadata_mem, &heap_mem);
+    let layout = map::layout();
+    dyn_alloc::init(&layout.metadata_region(), &[layout.heap_region()]);
+    early_alloc::close();
+    frame_alloc::refcount::init();
+    
+    // Choose how the heap finds free regions (it's first fit unless `heap_fit=best` is passed).
+    if let Some(name) = mb_info.boot_cmd_tag.as_ref().and_then(|cmd| cmd.get("heap_fit")) {
+        match dyn_alloc::FitStrategy::from_name(name) {
+            Some(strategy) => dyn_alloc::set_strategy(strategy),
+            None => oxid_warn!("Invalid heap_fit option passed \"{}\". Ignoring it.", name),
+        }
+    }
 }
 
 // Unit Tests **************************************************************************************
@@ -44,13 +85,83 @@ pub unsafe fn init(mb_info: &MultibootInfo) {
 /// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
 #[cfg(feature = "unit-test")]
 pub mod test {
+    use super::region::Region;
+    use super::dyn_alloc::{HeapAlloc, HeapMapper};
+    use super::frame_alloc::bitmap::BitMap;
+
     /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
     /// sub module. 
     pub fn run() {
         super::align::test::run();
         super::frame_alloc::test::run();
         super::bitwise::test::run();
+        super::addr::test::run();
         super::vmm::test::run();
         super::dyn_alloc::test::run();
+        super::mmio::test::run();
+        super::audit::test::run();
+        super::map::test::run();
+        super::region::test::run();
+        super::early_alloc::test::run();
+    }
+    
+    /// A mapper for the test heaps which doesn't touch the page tables (the scratch regions are
+    /// already mapped).
+    pub const NO_MAPPER: HeapMapper = HeapMapper {
+        map: |_, _, _, _, _| Ok(()),
+        unmap: |_, _| Ok(()),
+        grow_meta: || Err(()),
+        translate: crate::mem::vmm::virt_to_phys,
+    };
+    
+    /// A helper which allocates a scratch region from the kernel heap (for the private instances).
+    ///
+    /// # Parameters
+    /// `size` : The size of the region in bytes.
+    ///
+    /// # Returns
+    /// The allocated region (which is mapped and writable).
+    pub fn scratch(size: usize) -> Region {
+        unsafe { Region::new_sized(super::dyn_alloc::kmalloc(size, false, true, true) as usize, size) }
+    }
+    
+    /// A helper which gives a scratch region back to the kernel heap.
+    ///
+    /// # Parameters
+    /// `scratch` : The region which was returned by scratch().
+    pub fn free_scratch(scratch: &Region) {
+        unsafe { super::dyn_alloc::kfree(scratch.addr as *mut u8); }
+    }
+    
+    /// A helper which creates a private heap over a scratch region, independent of the kernel heap.
+    /// The first quarter of the region is used for the metadata, and the rest is allocated. It
+    /// never touches the page tables.
+    ///
+    /// # Parameters
+    /// `scratch` : The region which is used by the heap (it should be page aligned).
+    ///
+    /// # Returns
+    /// The initialized heap.
+    pub fn fresh_heap(scratch: &Region) -> HeapAlloc {
+        let meta_size = scratch.size / 4;
+        let mut heap = HeapAlloc::new_with_mapper(NO_MAPPER);
+        unsafe {
+            heap.init(&Region::new_sized(scratch.addr, meta_size),
+                &[Region::new(scratch.addr + meta_size, scratch.end_addr())]);
+        }
+        
+        heap
+    }
+    
+    /// A helper which creates a private bitmap frame allocator over a scratch region, independent
+    /// of the global frame allocator. The bitmap is stored at the start of the region.
+    ///
+    /// # Parameters
+    /// `scratch` : The region which is managed by the bitmap.
+    ///
+    /// # Returns
+    /// The initialized (purged) bitmap.
+    pub fn fresh_bitmap(scratch: &Region) -> BitMap {
+        unsafe { BitMap::new_in_place(scratch) }
     }
 }
@@ -8,7 +8,9 @@
 /// A function which is called by the low-level page_fault handler. It checks the error codes passed
 /// (such as the permissions and the context of the interrupt), and handles the fault accordingly.
 /// If it's just a page that is not present, it allocates a frame of memory, and maps it to the 
-/// page address which caused the fault in the first place.
+/// page address which caused the fault in the first place. The lazy heap allocations are mapped 
+/// this way when they're first touched (see dyn_alloc::kmalloc). The guard pages are never mapped
+/// (see check_guard).
 ///
 /// # Parameters
 /// `page_addr` : The virtual address of the page which caused the fault.
@@ -34,15 +36,27 @@ pub unsafe fn page_fault(page_addr: usize, present: bool, _write: bool, user: bo
     if user {
         panic!("Users can not allocate new frames.");
     } else {
+        // An access which ran off the end of a guarded allocation is never mapped.
+        check_guard(page_addr);
+        
         // Check if the kernel should be mapping pages here. Basically, the kernel can map pages 
-        // using page faults if it's either in the area before the heap, or the area reserved for 
-        // the page tables.
-        if page_addr < super::map::KERNEL_HEAP_METADATA_END_ADDR 
-            || (page_addr >= super::map::PAGE_TABLES_START_ADDR 
-            && page_addr < super::map::PAGE_TABLES_END_ADDR) {
-            // In such cases, we can map the page.
-            crate::mem::vmm::map(page_addr, user, true, false)
-                .expect("Could not map address during page fault.");
+        // using page faults if it's either in the area before the heap, the area reserved for 
+        // the page tables, or a lazy heap region (which is only found if the heap is not locked).
+        let lazy = if crate::mem::dyn_alloc::in_arena(page_addr) {
+            crate::mem::dyn_alloc::find_lazy(page_addr)
+        } else {
+            None
+        };
+        
+        if page_addr < super::map::layout().heap_metadata_end 
+            || super::map::page_tables_region().includes(page_addr) || lazy.is_some() {
+            // In such cases, we can map the page (the faulting address is always canonical).
+            let is_no_exec = lazy.unwrap_or(false);
+            let page = super::addr::VirtAddr::new(page_addr);
+            if page.and_then(|page| crate::mem::vmm::map(page, user, true, is_no_exec)).is_err() {
+                crate::mem::dyn_alloc::log_stats();
+                panic!("Could not map address during page fault.");
+            }
         } else {
             panic!("Invalid memory access. Please allocate the memory first.");
         }
@@ -50,4 +64,24 @@ pub unsafe fn page_fault(page_addr: usize, present: bool, _write: bool, user: bo
     }    
 }
 
-
+/// A function which checks if a faulting address is in a guard page (below the running process' 
+/// stack, or around another guarded heap allocation), and panics with a message which shows what 
+/// overflowed. It's also called by the double fault handler, since a stack which overflowed into 
+/// it's guard page can't take the page fault itself.
+///
+/// # Parameters
+/// `page_addr` : The virtual address of the page which caused the fault.
+pub unsafe fn check_guard(page_addr: usize) {
+    // The running process is checked first (it doesn't need the heap's lock).
+    let stack = crate::proc::scheduler::with_current(|pcb| (pcb.pid, pcb.stack_end as usize));
+    if let Some((pid, stack_end)) = stack {
+        if page_addr + super::vmm::PAGE_SIZE == stack_end {
+            panic!("Stack overflow in PID {} (guard page at 0x{:x}).", pid, page_addr);
+        }
+    }
+    
+    if let Some(region) = crate::mem::dyn_alloc::find_guard(page_addr) {
+        panic!("Accessed the guard page at 0x{:x}, past the allocation at 0x{:x} ({} bytes).", 
+            page_addr, region.addr, region.size);
+    }
+}
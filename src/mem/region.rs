@@ -6,6 +6,13 @@
 
 #![allow(dead_code)]
 
+/// An enum which represents the reasons a region could not be created.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RegionError {
+    EndBeforeStart,                            // The end address was lower than the start.
+    Overflow,                                  // The region goes past the end of the address space.
+}
+
 /// A structure which represents a memory region.
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Region {
@@ -34,8 +41,25 @@ impl Region {
         }
     }
     
+    /// A fallible version of new, which can be used when the addresses are not trusted.
+    ///
+    /// # Parameters
+    /// `start_addr` : The starting address of this region (lower).
+    /// `end_addr` : The ending address of this region (higher).
+    ///
+    /// # Returns
+    /// Ok(region) with the calculated size, Err if the end is before the start.
+    pub fn try_new(start_addr: usize, end_addr: usize) -> Result<Self, RegionError> {
+        if end_addr < start_addr {
+            return Err(RegionError::EndBeforeStart);
+        }
+        
+        Ok(Region::new(start_addr, end_addr))
+    }
+    
     /// A simple default constructor for the region with an address and a size. In this case, it 
-    /// literally just sets addr and size and returns a new region.
+    /// literally just sets addr and size and returns a new region. The region should not go past
+    /// the end of the address space.
     ///
     /// # Parameters
     /// `addr` : The starting of this region.
@@ -44,12 +68,32 @@ impl Region {
     /// # Returns
     /// The newly created region with the given properties.
     pub fn new_sized(addr: usize, size: usize) -> Self {
+        // Catch the sizes which wrapped around (such as an end before the start).
+        debug_assert!(addr.checked_add(size).is_some(), "Region 0x{:x} + 0x{:x} overflows.", 
+            addr, size);
+        
         Region {
             addr: addr,
             size: size,
         }
     }
     
+    /// A fallible version of new_sized, which can be used when the size is not trusted.
+    ///
+    /// # Parameters
+    /// `addr` : The starting of this region.
+    /// `size` : The number of bytes in this region.
+    ///
+    /// # Returns
+    /// Ok(region) with the given properties, Err if it goes past the end of the address space.
+    pub fn try_new_sized(addr: usize, size: usize) -> Result<Self, RegionError> {
+        if addr.checked_add(size).is_none() {
+            return Err(RegionError::Overflow);
+        }
+        
+        Ok(Region::new_sized(addr, size))
+    }
+    
     /// A constructor which creates a new aligned region from a start and end address. It 
     /// aligns the start_addr to become higher, and aligns the end_addr to be lower, it then 
     /// calculates the size and sets it. The start address should be lower than the end address.
@@ -69,10 +113,10 @@ impl Region {
         // Make sure the addresses are correct.
         assert!(aligned_start_addr <= aligned_end_addr);
         
-        // Calculate the size and return the new region.
+        // Calculate the size and return the new (aligned) region.
         Region {
-            addr: start_addr,
-            size: (end_addr - start_addr),
+            addr: aligned_start_addr,
+            size: (aligned_end_addr - aligned_start_addr),
         }
     }
     
@@ -85,6 +129,16 @@ impl Region {
         self.addr + self.size
     }
     
+    /// A method which checks if this region has no bytes in it (such as what's left after an 
+    /// exact fit).
+    ///
+    /// # Returns
+    /// True if the size is 0, False otherwise.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+    
     /// A method which determines if this region includes a given address or not.
     ///
     /// # Parameters
@@ -94,10 +148,11 @@ impl Region {
     /// True if the addr is within this region. False otherwise.
     #[inline]
     pub fn includes(&self, addr: usize) -> bool {
-        self.addr >= addr && self.addr < self.end_addr()
+        addr >= self.addr && addr < self.end_addr()
     }
     
-    /// A method which determines if this region can include another region inside of it.
+    /// A method which determines if this region can include another region inside of it. The end 
+    /// addresses are exclusive, so a region fits in itself.
     ///
     /// # Parameters
     /// `other_region` : The region which we're trying to fit inside this one.
@@ -106,8 +161,66 @@ impl Region {
     /// True if it can fit, False otherwise.
     #[inline]
     pub fn fits(&self, other_region: &Region) -> bool {
-        // Make sure it includes the start and end addresses of other_region.
-        self.includes(other_region.addr) 
-            && self.includes(other_region.end_addr())
+        // Make sure it starts and ends within this region (the end is not a part of it).
+        other_region.addr >= self.addr && other_region.end_addr() <= self.end_addr()
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{Region, RegionError};
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_try_new();
+        test_is_empty();
+        test_new_aligned();
+        test_fits();
+    }
+    
+    /// Check the boundaries of includes and fits (the end address is exclusive).
+    fn test_fits() {
+        let region = Region::new(0x1000, 0x3000);
+        assert!(region.includes(0x1000) && region.includes(0x2FFF));
+        assert!(!region.includes(0x3000) && !region.includes(0xFFF));
+        
+        assert!(region.fits(&region));
+        assert!(region.fits(&Region::new(0x2000, 0x3000)));
+        assert!(region.fits(&Region::new(0x3000, 0x3000)));
+        assert!(!region.fits(&Region::new(0x2000, 0x3001)));
+        assert!(!region.fits(&Region::new(0xFFF, 0x2000)));
+    }
+    
+    /// Make sure the fallible constructors reject the regions which would wrap around.
+    fn test_try_new() {
+        let region = Region::try_new(0x1000, 0x3000).unwrap();
+        assert_eq!((region.addr, region.size), (0x1000, 0x2000));
+        assert_eq!(Region::try_new(0x3000, 0x1000).err(), Some(RegionError::EndBeforeStart));
+        assert_eq!(Region::try_new(usize::MAX, 0).err(), Some(RegionError::EndBeforeStart));
+        
+        assert_eq!(Region::try_new_sized(0x1000, 0x2000).unwrap().end_addr(), 0x3000);
+        assert_eq!(Region::try_new_sized(usize::MAX - 0xFFF, 0x1000).err(), 
+            Some(RegionError::Overflow));
+        assert!(Region::try_new_sized(usize::MAX - 0xFFF, 0xFFF).is_ok());
+    }
+    
+    /// Check the empty regions.
+    fn test_is_empty() {
+        assert!(Region::new(0x2000, 0x2000).is_empty());
+        assert!(Region::try_new(0x2000, 0x2000).unwrap().is_empty());
+        assert!(!Region::new_sized(0x2000, 1).is_empty());
+        assert!(Region::default().is_empty());
+    }
+    
+    /// Make sure the aligned regions actually use the aligned addresses.
+    fn test_new_aligned() {
+        let region = Region::new_aligned(0x1001, 0x5FFF, 0x1000);
+        assert_eq!((region.addr, region.end_addr()), (0x2000, 0x5000));
+        assert!(Region::new_aligned(0x1001, 0x1FFF, 0x1000).is_empty());
     }
 }
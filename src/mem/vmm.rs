@@ -6,14 +6,23 @@
 
 #![allow(dead_code)]
 
-use crate::arch::mem::page_tables::PageTables;
+use crate::arch::mem::page_tables::{PageMapping, PageTables};
+use crate::arch::mem::tlb;
 use crate::mem::frame_alloc::FrameAllocResult;
+use crate::mem::region::Region;
+use crate::mem::addr::{PhysAddr, VirtAddr};
 
 /// The size of each virtual page (same as the frame size).
 pub const PAGE_SIZE: usize = super::frame_alloc::FRAME_SIZE;
 
-/// The amount of memory which was identity mapped by the bootstrap code.
-const BOOT_ID_MAPPED_SIZE: usize = 0x40000000;
+/// The size of the huge pages, which are used for large identity mappings (if supported).
+pub const HUGE_PAGE_SIZE: usize = crate::arch::mem::page_tables::HUGE_PAGE_SIZE;
+
+/// The amount of memory which was identity mapped by the bootstrap code (set in init).
+static mut BOOT_ID_MAPPED_SIZE: usize = 0;
+
+/// The end of the area which is identity mapped by the kernel page table (set in init).
+static mut ID_MAP_END: usize = 0;
 
 /// Holds the address and information of the kernel page table.
 static mut KERNEL_PAGE_TABLE: PageTables = PageTables::new();
@@ -24,18 +33,43 @@ static mut KERNEL_PAGE_TABLE: PageTables = PageTables::new();
 /// # Parameters
 /// `id_map_end` : The end of the identity mapped area after the kernel and frame allocator bitmap.
 pub unsafe fn init(id_map_end: usize) {
+    // Find out how much was actually mapped by the bootstrap code, and compare it to the RAM.
+    BOOT_ID_MAPPED_SIZE = crate::arch::mem::get_boot_id_mapped_size();
+    let mem_end = crate::mem::frame_alloc::get_mappable_region().end_addr();
+    oxid_log!("Boot code identity mapped 0x{:x} bytes (memory ends at 0x{:x}).", 
+        BOOT_ID_MAPPED_SIZE, mem_end);
+    
+    if BOOT_ID_MAPPED_SIZE == 0 || BOOT_ID_MAPPED_SIZE % PAGE_SIZE != 0 {
+        panic!("Invalid boot identity mapped size 0x{:x}.", BOOT_ID_MAPPED_SIZE);
+    }
+    
+    // Memory past the boot mappings is only reachable once the kernel page table maps it.
+    if BOOT_ID_MAPPED_SIZE < mem_end {
+        oxid_warn!("Boot code only identity mapped 0x{:x} of 0x{:x} bytes of memory.", 
+            BOOT_ID_MAPPED_SIZE, mem_end);
+    }
+    
+    // The kernel (and the bitmap) have to be reachable through the boot mappings.
+    let boot_extra = match boot_extra_region(id_map_end, BOOT_ID_MAPPED_SIZE) {
+        Ok(region) => region,
+        Err(()) => panic!("The identity mapped area (0x{:x}) is larger than the boot mapped \
+            area (0x{:x}).", id_map_end, BOOT_ID_MAPPED_SIZE),
+    };
+    
     // Setup the kernel page table.
     KERNEL_PAGE_TABLE.setup_kernel_pagetable();
     
     oxid_log!("Kernel page table was set-up. Identity mapping from 0x0 to 0x{:x}", id_map_end);
 
     // Identity map everything up to the id_map_end.
-    if lazy_identity_map_range(0, id_map_end, false, true, false).is_err() {
+    if lazy_identity_map_range(PhysAddr::new(0), id_map_end, false, true, false).is_err() {
         panic!("Error identity mapping.");
     }
+    ID_MAP_END = id_map_end;
     
-    // Unmap every address which was previously identity mapped (by bootstrap code).
-    if lazy_unmap_range(id_map_end, BOOT_ID_MAPPED_SIZE - id_map_end).is_err() {
+    // Unmap every address which was previously identity mapped (by bootstrap code), if any.
+    if !boot_extra.is_empty() && VirtAddr::new(boot_extra.addr)
+        .and_then(|addr| lazy_unmap_range(addr, boot_extra.size)).is_err() {
         panic!("Error unmapping the extra kernel identity mapped area.");
     };
 
@@ -43,6 +77,39 @@ pub unsafe fn init(id_map_end: usize) {
     KERNEL_PAGE_TABLE.load();
 }
 
+/// A function which calculates the area which was identity mapped by the bootstrap code, but is 
+/// not needed by the kernel (so it should be unmapped).
+///
+/// # Parameters
+/// `id_map_end` : The end of the identity mapped area which is needed by the kernel.
+/// `boot_mapped` : The number of bytes which were identity mapped by the bootstrap code.
+///
+/// # Returns
+/// Ok(region) with the extra area (might be empty), Err if the kernel needs more than was mapped.
+pub fn boot_extra_region(id_map_end: usize, boot_mapped: usize) -> Result<Region, ()> {
+    if id_map_end > boot_mapped {
+        return Err(());
+    }
+    
+    Ok(Region::new(id_map_end, boot_mapped))
+}
+
+/// A simple getter for the number of bytes which were identity mapped by the bootstrap code.
+///
+/// # Returns
+/// The size of the boot identity mapped area (0 before init).
+pub fn boot_id_mapped_size() -> usize {
+    unsafe { BOOT_ID_MAPPED_SIZE }
+}
+
+/// A simple getter for the end of the area which is identity mapped by the kernel page table.
+///
+/// # Returns
+/// The end address of the identity mapped area (0 before init).
+pub fn id_map_end() -> usize {
+    unsafe { ID_MAP_END }
+}
+
 /// A wrapper for the architecture dependent map function. This is done to abstract the hardware 
 /// implementation of the map function. It maps a given page address (starting address) to a given
 /// frame address. It additionally sets the required permissions and creates new tables if needed.
@@ -58,10 +125,15 @@ pub unsafe fn init(id_map_end: usize) {
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn lazy_map(page_addr: usize, frame_addr: usize, is_user: bool, is_writable: bool,
+#[cfg_attr(feature = "tlb-check", track_caller)]
+pub unsafe fn lazy_map(page_addr: VirtAddr, frame_addr: PhysAddr, is_user: bool, is_writable: bool,
     is_no_exec: bool) -> Result<(), ()> {
     // Simply call the architecture dependent code.
-    PageTables::map(page_addr, frame_addr, is_user, is_writable, is_no_exec)
+    PageTables::map(page_addr, frame_addr, is_user, is_writable, is_no_exec)?;
+    
+    #[cfg(feature = "tlb-check")]
+    crate::debug::tlb_check::track(page_addr);
+    Ok(())
 }
 
 /// A wrapper for the lazy_map function which performs it with a certain range of memory. It is very 
@@ -79,7 +151,7 @@ pub unsafe fn lazy_map(page_addr: usize, frame_addr: usize, is_user: bool, is_wr
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn lazy_map_range(page_addr: usize, frame_addr: usize, size: usize, is_user: bool, 
+pub unsafe fn lazy_map_range(page_addr: VirtAddr, frame_addr: PhysAddr, size: usize, is_user: bool, 
     is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
     // Go through every page and map it. If error occurs, return the Err.
     for page_num in 0..get_num_pages(size) {
@@ -104,9 +176,14 @@ pub unsafe fn lazy_map_range(page_addr: usize, frame_addr: usize, size: usize, i
 /// # Returns
 /// Ok if the given page was unmapped, Err if invalid address or non-existant page.
 #[inline(always)]
-pub unsafe fn lazy_unmap(page_addr: usize) -> Result<(), ()> { 
+#[cfg_attr(feature = "tlb-check", track_caller)]
+pub unsafe fn lazy_unmap(page_addr: VirtAddr) -> Result<(), ()> { 
     // Simply call the architecture dependent code.
-    PageTables::unmap(page_addr)
+    PageTables::unmap(page_addr)?;
+    
+    #[cfg(feature = "tlb-check")]
+    crate::debug::tlb_check::forget(page_addr);
+    Ok(())
 }
 
 /// A wrapper for the laz_unmap function which performs it with a certain range of memory. It is very 
@@ -120,17 +197,40 @@ pub unsafe fn lazy_unmap(page_addr: usize) -> Result<(), ()> {
 /// # Returns
 /// Ok if the given pages were unmapped, Err if invalid address or non-existant page.
 #[inline(always)]
-pub unsafe fn lazy_unmap_range(page_addr: usize, size: usize) -> Result<(), ()> {
-    // Go through every page and map it. If error occurs, return the Err.
-    for page_num in 0..get_num_pages(size) {
-        // Calculate the offset from the page and frame addresses.
-        let offset = page_num * PAGE_SIZE;
+pub unsafe fn lazy_unmap_range(page_addr: VirtAddr, size: usize) -> Result<(), ()> {
+    // If the range is large, skip the single page invalidations and flush the TLB at the end.
+    let num_pages = get_num_pages(size);
+    let invalidate = !tlb::needs_flush(num_pages);
+    
+    // Go through every page and unmap it. If error occurs, stop and return the Err.
+    let size = num_pages * PAGE_SIZE;
+    let mut offset = 0;
+    let mut result = Ok(());
+    while offset < size && result.is_ok() {
+        let addr = page_addr + offset;
         
-        // Do the actual opreation with the offsets.
-        lazy_unmap(page_addr + offset)?;
+        // Unmap the huge pages which are fully covered at once (instead of splitting them).
+        if addr.is_aligned(HUGE_PAGE_SIZE) && size - offset >= HUGE_PAGE_SIZE 
+            && PageTables::query(addr).map(|(_, page_size)| page_size) == Ok(HUGE_PAGE_SIZE) {
+            result = PageTables::unmap_1g(addr);
+            offset += HUGE_PAGE_SIZE;
+            continue;
+        }
+        
+        result = if invalidate {
+            PageTables::unmap(addr)
+        } else {
+            PageTables::unmap_no_invalidate(addr)
+        };
+        offset += PAGE_SIZE;
+        
+        #[cfg(feature = "tlb-check")]
+        crate::debug::tlb_check::forget(addr);
     }
     
-    Ok(())
+    // Flush even if it failed half way, since some pages might be unmapped already.
+    tlb::flush_if_needed(num_pages);
+    result
 }
 
 /// A wrapper for the map function which performs identity mapping for a certain physical 
@@ -146,15 +246,16 @@ pub unsafe fn lazy_unmap_range(page_addr: usize, size: usize) -> Result<(), ()>
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn lazy_identity_map(frame_addr: usize, is_user: bool, 
+pub unsafe fn lazy_identity_map(frame_addr: PhysAddr, is_user: bool, 
     is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
     // Simply call the map function with only the frame address.
-    lazy_map(frame_addr, frame_addr, is_user, is_writable, is_no_exec)
+    lazy_map(frame_addr.to_identity()?, frame_addr, is_user, is_writable, is_no_exec)
 }
 
 /// A wrapper for the identity mapping which performs it with a certain range of memory. It is 
 /// very similar to it. However, it also accepts a size. It does not mark the frame in the
-/// frame allocator at all.
+/// frame allocator at all. If the CPU supports it, 1GB pages are used for the parts of the range 
+/// which are aligned and unused (which saves a lot of page tables).
 ///
 /// # Parameters
 /// `frame_addr` : The starting address of the frame we're identity mapping.
@@ -166,15 +267,25 @@ pub unsafe fn lazy_identity_map(frame_addr: usize, is_user: bool,
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn lazy_identity_map_range(frame_addr: usize, size: usize, is_user: bool, 
+pub unsafe fn lazy_identity_map_range(frame_addr: PhysAddr, size: usize, is_user: bool, 
     is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
     // Go through every page and map it. If error occurs, return the Err.
-    for page_num in 0..get_num_pages(size) {
-        // Calculate the offset from the page and frame addresses.
-        let offset = page_num * PAGE_SIZE;
+    let size = get_num_pages(size) * PAGE_SIZE;
+    let mut offset = 0;
+    while offset < size {
+        let addr = frame_addr + offset;
+        
+        // Try a huge page if the rest of the range covers it (falls back if it's not possible).
+        if addr.is_aligned(HUGE_PAGE_SIZE) && size - offset >= HUGE_PAGE_SIZE 
+            && PageTables::map_1g(addr.to_identity()?, addr, is_user, is_writable, is_no_exec)
+                .is_ok() {
+            offset += HUGE_PAGE_SIZE;
+            continue;
+        }
         
         // Do the actual opreation with the offsets.
-        lazy_identity_map(frame_addr + offset, is_user, is_writable, is_no_exec)?;
+        lazy_identity_map(addr, is_user, is_writable, is_no_exec)?;
+        offset += PAGE_SIZE;
     }
     
     Ok(())
@@ -182,7 +293,8 @@ pub unsafe fn lazy_identity_map_range(frame_addr: usize, size: usize, is_user: b
 
 /// A function which allocates a frame, and then maps it in pt. It maps a given page address 
 /// (starting address) to a frame obtained from frame_alloc. It additionally sets the required 
-/// permissions and creates new tables if needed.
+/// permissions and creates new tables if needed. The frame is scrubbed before it's used, so 
+/// nothing is leaked from it's previous owner (for example, another process).
 ///
 /// # Parameters
 /// `page_addr` : The starting address of the wanted page.
@@ -193,21 +305,59 @@ pub unsafe fn lazy_identity_map_range(frame_addr: usize, size: usize, is_user: b
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn map(page_addr: usize, is_user: bool, is_writable: bool,
+#[cfg_attr(feature = "tlb-check", track_caller)]
+pub unsafe fn map(page_addr: VirtAddr, is_user: bool, is_writable: bool,
     is_no_exec: bool) -> Result<(), ()> {
     // Allocate a new frame, and check the results.
     let new_frame_addr = match crate::mem::frame_alloc::alloc() {
         FrameAllocResult::Ok(addr) => addr,
         FrameAllocResult::Full => panic!("Could not allocate frame. All frames are full."),
-        _ => panic!("Could not allocate frame. Unknown error."),
+        result => {
+            oxid_err!("Could not allocate a frame for page 0x{:x} ({:?}).", page_addr, result);
+            return Err(());
+        },
     };
 
-    // Map the allocated frame to the page address in the kernel page table.
-    #[cfg(feature = "show-page-faults")]
-    oxid_warn!("Mapping page 0x{:x} to frame 0x{:x}", page_addr, new_frame_addr);
+    oxid_dbg!(Vmm, "Allocated frame 0x{:x} for page 0x{:x}", new_frame_addr, page_addr);
+    
+    // Map it as writable first, so it can be zeroed through the page.
+    let page_addr = page_addr.align_down(PAGE_SIZE);
+    PageTables::map(page_addr, new_frame_addr, is_user, true, is_no_exec)?;
+    crate::olibc::memset::memset(page_addr.as_mut_ptr(), 0, PAGE_SIZE);
+    
+    // The read-only pages are mapped again with the real permissions.
+    if !is_writable {
+        PageTables::unmap(page_addr)?;
+        PageTables::map(page_addr, new_frame_addr, is_user, false, is_no_exec)?;
+    }
+    
+    #[cfg(feature = "tlb-check")]
+    crate::debug::tlb_check::track(page_addr);
+    Ok(())
+}
+
+/// A function which changes the permission of a mapped page (the frame is kept). If it's made 
+/// read-only, the kernel can't write to it either (CR0.WP is set).
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're changing.
+/// `is_writable` : True if R/W, False if it's read-only.
+///
+/// # Returns
+/// Ok if it was changed, Err if invalid address or non-existant page.
+#[cfg_attr(feature = "tlb-check", track_caller)]
+pub unsafe fn protect(page_addr: VirtAddr, is_writable: bool) -> Result<(), ()> {
+    PageTables::protect(page_addr, is_writable)?;
     
-    // Simply call the architecture dependent code with the new frame address.
-    PageTables::map(page_addr, new_frame_addr, is_user, is_writable, is_no_exec)
+    // Make sure a downgrade is actually enforced (it's not if the page was not invalidated).
+    #[cfg(feature = "tlb-check")]
+    {
+        crate::debug::tlb_check::track(page_addr);
+        if !is_writable {
+            crate::debug::tlb_check::verify_read_only(page_addr);
+        }
+    }
+    Ok(())
 }
 
 /// A wrapper for the map function which performs it with a certain range of memory. It is very 
@@ -223,7 +373,7 @@ pub unsafe fn map(page_addr: usize, is_user: bool, is_writable: bool,
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn map_range(page_addr: usize, size: usize, is_user: bool, 
+pub unsafe fn map_range(page_addr: VirtAddr, size: usize, is_user: bool, 
     is_writable: bool, is_no_exec: bool) -> Result<(), ()> { 
     // Go through every page and map it. If error occurs, return the Err.
     for page_num in 0..get_num_pages(size) {
@@ -238,7 +388,50 @@ pub unsafe fn map_range(page_addr: usize, size: usize, is_user: bool,
 }
 
 /// A function which unmaps a given page. It first obtains it's physical address, and then 
-/// unmaps it from the page table.
+/// unmaps it from the page table. Pages which are mapped to reserved MMIO regions are refused.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're unmapping.
+///
+/// # Returns
+/// Ok if the given page was unmapped, Err if invalid address, reserved, or non-existant page.
+#[inline(always)]
+#[cfg_attr(feature = "tlb-check", track_caller)]
+pub unsafe fn unmap(page_addr: VirtAddr) -> Result<(), ()> {
+    internal_unmap(page_addr, false, true)
+}
+
+/// A wrapper for the unmap function which performs it with a certain range of memory. It is very 
+/// similar to it. However, it also accepts a size. If any of the pages are reserved for MMIO, 
+/// nothing will be unmapped.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're unmapping.
+/// `size` : The number of bytes starting from page_addr. 
+///
+/// # Returns
+/// Ok if the given pages were unmapped, Err if invalid address, reserved, or non-existant page.
+#[inline(always)]
+pub unsafe fn unmap_range(page_addr: VirtAddr, size: usize) -> Result<(), ()> {
+    internal_unmap_range(page_addr, size, false, false)
+}
+
+/// A version of unmap_range which skips the pages that are not mapped (instead of failing). It's 
+/// used for the lazy heap regions, where only the pages which were accessed are mapped.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're unmapping.
+/// `size` : The number of bytes starting from page_addr. 
+///
+/// # Returns
+/// Ok if the mapped pages were unmapped, Err if invalid address or reserved.
+#[inline(always)]
+pub unsafe fn unmap_present_range(page_addr: VirtAddr, size: usize) -> Result<(), ()> {
+    internal_unmap_range(page_addr, size, false, true)
+}
+
+/// A version of unmap which also unmaps the pages that are mapped to reserved MMIO regions. The
+/// frames of the reserved regions are never given back to the frame allocator.
 ///
 /// # Parameters
 /// `page_addr` : The address of the page which we're unmapping.
@@ -246,43 +439,127 @@ pub unsafe fn map_range(page_addr: usize, size: usize, is_user: bool,
 /// # Returns
 /// Ok if the given page was unmapped, Err if invalid address or non-existant page.
 #[inline(always)]
-pub unsafe fn unmap(page_addr: usize) -> Result<(), ()> {
+#[cfg_attr(feature = "tlb-check", track_caller)]
+pub unsafe fn force_unmap(page_addr: VirtAddr) -> Result<(), ()> {
+    internal_unmap(page_addr, true, true)
+}
+
+/// A version of unmap_range which also unmaps the pages that are mapped to reserved MMIO regions.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're unmapping.
+/// `size` : The number of bytes starting from page_addr. 
+///
+/// # Returns
+/// Ok if the given pages were unmapped, Err if invalid address or non-existant page.
+#[inline(always)]
+pub unsafe fn force_unmap_range(page_addr: VirtAddr, size: usize) -> Result<(), ()> {
+    internal_unmap_range(page_addr, size, true, false)
+}
+
+/// A function which checks if a frame is device memory (reserved for MMIO, or through
+/// frame_alloc::reserve_range), so it's never given back to the frame allocator.
+///
+/// # Parameters
+/// `physical_addr` : The physical address which we're checking.
+///
+/// # Returns
+/// True if it's reserved, False if it's regular RAM.
+fn is_reserved(physical_addr: PhysAddr) -> bool {
+    crate::mem::mmio::is_reserved(physical_addr.as_usize())
+        || crate::mem::frame_alloc::is_reserved(physical_addr)
+}
+
+/// The internal implementation of unmap. It obtains the physical address of the page, checks if
+/// it's reserved, deallocates the frame (if it's regular RAM), and then unmaps the page.
+///
+/// # Parameters
+/// `page_addr` : The address of the page which we're unmapping.
+/// `force` : True if reserved MMIO pages should be unmapped as well.
+/// `invalidate` : True if the page should be invalidated in the TLB (False if it will be flushed).
+///
+/// # Returns
+/// Ok if the given page was unmapped, Err otherwise.
+#[cfg_attr(feature = "tlb-check", track_caller)]
+unsafe fn internal_unmap(page_addr: VirtAddr, force: bool, invalidate: bool) -> Result<(), ()> {
     // Get the physical address first.
     let physical_addr = virt_to_phys(page_addr)?;
     
-    // Deallocate it from the frame allocator.
-    crate::mem::frame_alloc::dealloc(physical_addr);
+    // Check if the page is used for MMIO (never give those frames to the frame allocator).
+    if is_reserved(physical_addr) {
+        if !force {
+            oxid_warn!("Refusing to unmap page 0x{:x} (reserved MMIO).", page_addr);
+            return Err(());
+        }
+    } else if crate::mem::frame_alloc::refcount::ref_dec(physical_addr) == 0 {
+        // Deallocate it from the frame allocator once no other page is mapped to it (a double free
+        // means another page has it too).
+        if let FrameAllocResult::AlreadyFree = crate::mem::frame_alloc::dealloc(physical_addr) {
+            oxid_warn!("Unmapped page 0x{:x}, but it's frame 0x{:x} was already free.", page_addr,
+                physical_addr);
+        }
+    }
 
     // Unmap it from the page table.
-    PageTables::unmap(page_addr)
+    if invalidate {
+        PageTables::unmap(page_addr)?;
+    } else {
+        PageTables::unmap_no_invalidate(page_addr)?;
+    }
+    
+    #[cfg(feature = "tlb-check")]
+    crate::debug::tlb_check::forget(page_addr);
+    Ok(())
 }
 
-/// A wrapper for the unmap function which performs it with a certain range of memory. It is very 
-/// similar to it. However, it also accepts a size.
+/// The internal implementation of unmap_range. It checks the whole range for reserved pages 
+/// before unmapping anything (unless forced), so a refused range is left untouched.
 ///
 /// # Parameters
 /// `page_addr` : The address of the page which we're unmapping.
 /// `size` : The number of bytes starting from page_addr. 
+/// `force` : True if reserved MMIO pages should be unmapped as well.
+/// `skip_absent` : True if the pages which are not mapped should be skipped.
 ///
 /// # Returns
-/// Ok if the given pages were unmapped, Err if invalid address or non-existant page.
-#[inline(always)]
-pub unsafe fn unmap_range(page_addr: usize, size: usize) -> Result<(), ()> {
-    // Go through every page and map it. If error occurs, return the Err.
-    for page_num in 0..get_num_pages(size) {
-        // Calculate the offset from the page and frame addresses.
-        let offset = page_num * PAGE_SIZE;
-        
-        // Do the actual opreation with the offsets.
-        unmap(page_addr + offset)?;
+/// Ok if the given pages were unmapped, Err otherwise.
+unsafe fn internal_unmap_range(page_addr: VirtAddr, size: usize, force: bool, skip_absent: bool) 
+    -> Result<(), ()> {
+    // Make sure none of the pages are reserved before touching the page table.
+    if !force {
+        for page_num in 0..get_num_pages(size) {
+            if let Ok(physical_addr) = virt_to_phys(page_addr + page_num * PAGE_SIZE) {
+                if is_reserved(physical_addr) {
+                    oxid_warn!("Refusing to unmap range at 0x{:x} (reserved MMIO).", page_addr);
+                    return Err(());
+                }
+            }
+        }
     }
+
+    // If the range is large, skip the single page invalidations and flush the TLB at the end.
+    let num_pages = get_num_pages(size);
+    let invalidate = !tlb::needs_flush(num_pages);
     
-    Ok(())
+    // Go through every page and unmap it. If error occurs, stop and return the Err.
+    let result = (0..num_pages).try_for_each(|page_num| {
+        let page = page_addr + page_num * PAGE_SIZE;
+        if skip_absent && virt_to_phys(page).is_err() {
+            return Ok(());
+        }
+        
+        internal_unmap(page, force, invalidate)
+    });
+    
+    // Flush even if it failed half way, since some pages might be unmapped already.
+    tlb::flush_if_needed(num_pages);
+    result
 }
 
 /// Function identity mapping for a certain physical address. It also marks it used in the frame 
 /// allocator. If it fails, it means that the caller has to make sure the pages aren't being used
-/// elsewhere.
+/// elsewhere. The device memory should be reserved first (see frame_alloc::reserve_range), it's
+/// mapped without being allocated.
 ///
 /// # Parameters
 /// `frame_addr` : The starting address of the frame we're identity mapping.
@@ -293,13 +570,13 @@ pub unsafe fn unmap_range(page_addr: usize, size: usize) -> Result<(), ()> {
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn identity_map(frame_addr: usize, is_user: bool, 
+pub unsafe fn identity_map(frame_addr: PhysAddr, is_user: bool, 
     is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
-    // Mark the frame used in the frame allocator. Return if err.
+    // Mark the frame used in the frame allocator (the reserved frames already are). Return if err.
     match crate::mem::frame_alloc::alloc_frame(frame_addr) {
-        FrameAllocResult::Success => {
+        FrameAllocResult::Ok(_) | FrameAllocResult::Reserved => {
             // Simply call the lazy map function with only the frame address.
-            lazy_map(frame_addr, frame_addr, is_user, is_writable, is_no_exec)
+            lazy_map(frame_addr.to_identity()?, frame_addr, is_user, is_writable, is_no_exec)
         }
         
         _ => Err(())
@@ -319,7 +596,7 @@ pub unsafe fn identity_map(frame_addr: usize, is_user: bool,
 /// # Returns
 /// Ok if everything went as expected, Err otherwise.
 #[inline(always)]
-pub unsafe fn identity_map_range(frame_addr: usize, size: usize, is_user: bool, 
+pub unsafe fn identity_map_range(frame_addr: PhysAddr, size: usize, is_user: bool, 
     is_writable: bool, is_no_exec: bool) -> Result<(), ()> {
     // Go through every page and map it. If error occurs, return the Err.
     for page_num in 0..get_num_pages(size) {
@@ -343,11 +620,78 @@ pub unsafe fn identity_map_range(frame_addr: usize, size: usize, is_user: bool,
 /// # Returns
 /// Ok(frame_addr) if everything went as expected, Err otherwise.
 #[inline(always)]
-pub fn virt_to_phys(page_addr: usize) -> Result<usize, ()> {
+pub fn virt_to_phys(page_addr: VirtAddr) -> Result<PhysAddr, ()> {
     // Simply call the architecture dependent code.
     PageTables::virt_to_phys(page_addr)
 }
 
+/// A wrapper for the architecture dependent mapping lookup. It reads the PT entry of a regular page
+/// (including it's permissions).
+///
+/// # Parameters
+/// `page_addr` : The address which we're looking up.
+///
+/// # Returns
+/// Ok(mapping) of the page which includes it, Err if it's not mapped by a regular page.
+#[inline(always)]
+pub fn mapping(page_addr: VirtAddr) -> Result<PageMapping, ()> {
+    PageTables::mapping(page_addr)
+}
+
+/// A wrapper for the architecture dependent out-of-context translation. It translates a virtual 
+/// address using any page table (which doesn't have to be loaded), such as a new address space.
+///
+/// # Parameters
+/// `pml4_addr` : The physical address of the page table (such as the address in CR3).
+/// `page_addr` : The address of the page which we're translating.
+///
+/// # Returns
+/// Ok(frame_addr) if it's mapped in the given page table, Err otherwise.
+pub fn virt_to_phys_in(pml4_addr: PhysAddr, page_addr: VirtAddr) -> Result<PhysAddr, ()> {
+    // Simply call the architecture dependent code.
+    PageTables::at(pml4_addr).virt_to_phys_in(page_addr)
+}
+
+/// A wrapper for the architecture dependent frame window. It temporarily maps any physical frame 
+/// (at a reserved page), and calls a closure with it's contents. It can't be nested.
+///
+/// # Parameters
+/// `frame_addr` : The address of the frame.
+/// `func` : The closure which is called with the contents of the frame.
+///
+/// # Returns
+/// Ok(result) of the closure, Err if the frame could not be mapped.
+pub unsafe fn with_phys_frame<R>(frame_addr: PhysAddr, func: impl FnOnce(&[u8; PAGE_SIZE]) -> R) 
+    -> Result<R, ()> {
+    // Simply call the architecture dependent code.
+    PageTables::with_frame(frame_addr, func)
+}
+
+/// A wrapper for the architecture dependent query function. It finds the mapping (and it's size)
+/// which includes a given address.
+///
+/// # Parameters
+/// `page_addr` : The address which we're looking up.
+///
+/// # Returns
+/// Ok((frame_addr, page_size)) for the page which includes it, Err if it's not mapped.
+#[inline(always)]
+pub fn query(page_addr: VirtAddr) -> Result<(PhysAddr, usize), ()> {
+    // Simply call the architecture dependent code.
+    PageTables::query(page_addr)
+}
+
+/// A wrapper for the architecture dependent page table walk. It calls the closures for every 
+/// table and every mapped page in the lower half of the currently loaded page table.
+///
+/// # Parameters
+/// `on_table` : Called with the physical address of every table which is present.
+/// `on_page` : Called with the page address and frame address of every present page.
+pub unsafe fn walk(on_table: &mut dyn FnMut(usize), on_page: &mut dyn FnMut(usize, usize)) {
+    // Simply call the architecture dependent code.
+    PageTables::walk(on_table, on_page)
+}
+
 /// A function which calculates the number of pages needed to map a certain amount of memory.
 ///
 /// # Parameters
@@ -373,11 +717,15 @@ pub mod test {
     /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
     /// sub module. 
     pub fn run() {
+        test_boot_extra_region();
+        test_huge_pages();
+        
         // Some examples to test paging and the handling of page faults.
         /* 
         unsafe {
             
             use crate::arch::mem::page_tables::PageTables;
+            use crate::mem::addr::{PhysAddr, VirtAddr};
             
             // These tests break the kernel, and they will cause issues when page faults are checked 
             // by the kernel (since they are accessing memory where they shouldn't). 
@@ -388,20 +736,87 @@ pub mod test {
             assert_eq!(123456, *(0xFBCDEFABC as *mut usize));
             
             // Unmap the page which was just mapped by the page fault.
-            PageTables::unmap(0xFBCDEFABC).expect("Unmapping test failed.");
+            let page = VirtAddr::new(0xFBCDEFABC).unwrap();
+            PageTables::unmap(page).expect("Unmapping test failed.");
             
             // Translate a page which was identity mapped by the kernel and check the results.
-            let physical_addr = PageTables::translate(0x6060).expect("Translation failed.");
-            assert_eq!(0x6060, physical_addr);
+            let physical_addr = PageTables::virt_to_phys(VirtAddr::new(0x6060).unwrap())
+                .expect("Translation failed.");
+            assert_eq!(PhysAddr::new(0x6060), physical_addr);
             
             // Then try to translate an unmapped page which should result in an error.
-            if PageTables::translate(0xFBCDEFABC).is_ok() {
+            if PageTables::virt_to_phys(page).is_ok() {
                 panic!("Translation test failed. The page should be unmapped.");
             };
             
         };
         */
     }
+    
+    /// Map, translate, split, and unmap 1GB pages (only if the CPU supports them, such as QEMU with
+    /// `-cpu max`). The pages alias the first GB of physical memory in the unused higher half.
+    fn test_huge_pages() {
+        use super::{HUGE_PAGE_SIZE, PAGE_SIZE};
+        use crate::arch::mem::page_tables::PageTables;
+        use crate::mem::addr::{PhysAddr, VirtAddr};
+        
+        if ! crate::arch::proc::cpu::has_1g_pages() {
+            return;
+        }
+        
+        // A value which can be read through the identity mapping, and the aliases.
+        static MARKER: usize = 0xC0FFEE;
+        let marker_addr = &MARKER as *const usize as usize;
+        let alias = VirtAddr::new(0xFFFF_FE80_0000_0000).unwrap();
+        let frame = PhysAddr::new(0);
+        
+        unsafe {
+            // Map and translate a huge page.
+            assert!(PageTables::map_1g(alias, frame, false, false, true).is_ok());
+            assert_eq!(super::query(alias + 0x12345), Ok((frame, HUGE_PAGE_SIZE)));
+            assert_eq!(super::virt_to_phys(alias + 0x3FFF_FFFF), Ok(PhysAddr::new(0x3FFF_FFFF)));
+            assert_eq!(*(alias + marker_addr).as_ptr::<usize>(), 0xC0FFEE);
+            
+            // It can't be mapped twice, or with unaligned addresses.
+            assert!(PageTables::map_1g(alias, frame, false, false, true).is_err());
+            assert!(PageTables::map_1g(alias + HUGE_PAGE_SIZE, frame + PAGE_SIZE, false, false, 
+                true).is_err());
+            
+            // Unmap it as a whole.
+            assert!(super::lazy_unmap_range(alias, HUGE_PAGE_SIZE).is_ok());
+            assert!(super::virt_to_phys(alias).is_err());
+            
+            // Map another one, and unmap a single page inside it (which splits it).
+            let split = alias + HUGE_PAGE_SIZE;
+            assert!(PageTables::map_1g(split, frame, false, false, true).is_ok());
+            assert!(super::lazy_unmap(split + 0x200000).is_ok());
+            assert!(super::virt_to_phys(split + 0x200000).is_err());
+            assert_eq!(super::query(split + 0x201000), Ok((PhysAddr::new(0x201000), PAGE_SIZE)));
+            assert_eq!(super::virt_to_phys(split + 0x1FFFFF), Ok(PhysAddr::new(0x1FFFFF)));
+            assert_eq!(*(split + marker_addr).as_ptr::<usize>(), 0xC0FFEE);
+            
+            // Unmap the rest of it.
+            assert!(super::lazy_unmap_range(split, 0x200000).is_ok());
+            assert!(super::lazy_unmap_range(split + 0x201000, HUGE_PAGE_SIZE - 0x201000).is_ok());
+            assert!(super::query(split + 0x201000).is_err());
+        }
+    }
+    
+    /// Check the area which is unmapped after the boot, for boot mappings of different sizes.
+    fn test_boot_extra_region() {
+        use super::boot_extra_region;
+        
+        // The usual case (1 GiB was mapped, the kernel needs less).
+        let extra = boot_extra_region(0x800000, 0x40000000).unwrap();
+        assert_eq!((extra.addr, extra.end_addr()), (0x800000, 0x40000000));
+        
+        // A larger boot mapping is cleaned up completely.
+        assert_eq!(boot_extra_region(0x800000, 0x80000000).unwrap().end_addr(), 0x80000000);
+        
+        // Nothing extra, or not enough was mapped.
+        let extra = boot_extra_region(0x200000, 0x200000).unwrap();
+        assert_eq!((extra.addr, extra.size), (0x200000, 0));
+        assert_eq!(boot_extra_region(0x40000000, 0x40000000).unwrap().size, 0);
+        assert!(boot_extra_region(0x400000, 0x200000).is_err());
+    }
 }
-
-
@@ -0,0 +1,109 @@
+//! A struct which represents the kernel command line which was passed by the boot loader. It also
+//! provides some helpers to read the `key=value` options and flags from it. It's definition is
+//! directly derived from the multiboot2 specifications, which can be found at
+//! https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+//!
+//! Author: Ardalan Ahanchi
+//! Date: Mar 2021
+
+/// The size of the tag header (type and size) before the command line string starts.
+const HEADER_SIZE: usize = 8;
+
+/// A structure which holds the command line string (without the null terminator).
+#[derive(Copy, Clone)]
+pub struct BootCmd {
+    cmd: &'static str,          // The command line string (lives in the multiboot info).
+}
+
+impl BootCmd {
+    /// The default constructor which parses the information restored at the given address, and
+    /// initializes a new BootCmd struct and returns it. If the string is not valid UTF-8, an empty
+    /// command line will be used instead.
+    ///
+    /// # Parameters
+    /// `addr` : The address where this tag starts (it should be 8 byte aligned).
+    /// `size` : The size of the tag (including the header and the null terminator).
+    ///
+    /// # Returns
+    /// The parsed command line struct.
+    pub unsafe fn new(addr: usize, size: usize) -> Self {
+        // Calculate the length of the string (ignore the null terminator).
+        let len = size.saturating_sub(HEADER_SIZE + 1);
+        let bytes = core::slice::from_raw_parts((addr + HEADER_SIZE) as *const u8, len);
+
+        BootCmd::from_str(core::str::from_utf8(bytes).unwrap_or(""))
+    }
+
+    /// A constructor which creates a command line from a given string.
+    ///
+    /// # Parameters
+    /// `cmd` : The command line string.
+    ///
+    /// # Returns
+    /// The newly created command line struct.
+    pub const fn from_str(cmd: &'static str) -> Self {
+        BootCmd { cmd }
+    }
+
+    /// A simple getter for the full command line string.
+    ///
+    /// # Returns
+    /// The command line which was passed to the kernel.
+    pub fn as_str(&self) -> &'static str {
+        self.cmd
+    }
+
+    /// A method which finds the value of an option which is passed as `key=value`. If the key is
+    /// passed more than once, the last value will be returned.
+    ///
+    /// # Parameters
+    /// `key` : The name of the option.
+    ///
+    /// # Returns
+    /// Some(value) if the option was passed, None otherwise.
+    pub fn get(&self, key: &str) -> Option<&'static str> {
+        // Go through every space separated option and check the ones which have a value.
+        self.cmd.split_whitespace()
+            .filter_map(|opt| {
+                let mut parts = opt.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if name == key => Some(value),
+                    _ => None,
+                }
+            })
+            .last()
+    }
+
+    /// A method which finds the value of an option which takes the rest of the command line (so
+    /// the value can include spaces). It should be the last option, and only the first occurrence
+    /// is used.
+    ///
+    /// # Parameters
+    /// `key` : The name of the option.
+    ///
+    /// # Returns
+    /// Some(value) if the option was passed, None otherwise.
+    pub fn get_rest(&self, key: &str) -> Option<&'static str> {
+        let cmd = self.cmd;
+        
+        // Find the option, and return everything after the = sign.
+        cmd.split_whitespace()
+            .find(|opt| opt.len() > key.len() && opt.starts_with(key) 
+                && opt.as_bytes()[key.len()] == b'=')
+            .map(|opt| {
+                let offset = opt.as_ptr() as usize - cmd.as_ptr() as usize + key.len() + 1;
+                cmd[offset..].trim_end()
+            })
+    }
+
+    /// A method which checks if a flag (an option without a value) was passed.
+    ///
+    /// # Parameters
+    /// `flag` : The name of the flag.
+    ///
+    /// # Returns
+    /// True if the flag was passed, False otherwise.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.cmd.split_whitespace().any(|opt| opt == flag)
+    }
+}
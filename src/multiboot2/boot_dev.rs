@@ -5,6 +5,9 @@
 //! Author: Ardalan Ahanchi
 //! Date: Jan 2021
 
+/// The value which is used when there is no partition (or sub-partition).
+const NO_PARTITION: u32 = 0xFFFFFFFF;
+
 /// The base structure which indicates which BIOS boot device the bootloader found the OS on.
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
@@ -31,4 +34,28 @@ impl BootDev {
         // In this case, it returns a copy of it.
         *(addr as *const BootDev)
     }
+    
+    /// A simple getter for the BIOS drive number (for example, 0x80 for the first hard disk).
+    ///
+    /// # Returns
+    /// The drive number.
+    pub fn drive(&self) -> u32 {
+        self.bios_dev
+    }
+    
+    /// A simple getter for the top level partition number.
+    ///
+    /// # Returns
+    /// Some(partition) if the OS was loaded from a partition, None otherwise.
+    pub fn partition(&self) -> Option<u32> {
+        if self.partition == NO_PARTITION { None } else { Some(self.partition) }
+    }
+    
+    /// A simple getter for the sub-partition number.
+    ///
+    /// # Returns
+    /// Some(sub_partition) if the OS was loaded from a sub-partition, None otherwise.
+    pub fn sub_partition(&self) -> Option<u32> {
+        if self.sub_partition == NO_PARTITION { None } else { Some(self.sub_partition) }
+    }
 }
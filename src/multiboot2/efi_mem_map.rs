@@ -0,0 +1,149 @@
+//! A struct which represents the EFI memory map tag in the multiboot info structure (it's only
+//! passed when the kernel is booted via UEFI). It's a copy of the map which was returned by the
+//! firmware's boot services, and unlike the legacy memory map, it tells the runtime services
+//! regions apart from the usable memory. It's definition is directly derived from the multiboot2
+//! specifications, which can be found at
+//! https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+//! and the descriptors are as defined in the UEFI specifications.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// The size of the pages which are used by the descriptors (regardless of the kernel's page size).
+pub const EFI_PAGE_SIZE: u64 = 0x1000;
+
+/// A strcture which represents the EFI memory map as it is used by the outside programs. It is
+/// used to provide an interface to the descriptors.
+#[derive(Copy, Clone)]
+pub struct EfiMemMap {
+    num_descs: usize,
+    curr_desc_idx: usize,
+    desc_size: usize,
+    descs_addr: usize,
+}
+
+/// The memory accurate representation of the EFI memory map tag, it is as defined in the multiboot2
+/// specifications. It will be followed with the descriptors.
+#[repr(C, packed)]
+struct EfiMemMapRepr {
+    tag_type: u32,              // Type of the tag.
+    tag_size: u32,              // The size of the tag in bytes.
+    pub desc_size: u32,         // The size of each descriptor (can be larger than EfiMemDesc).
+    pub desc_ver: u32,          // The version of the descriptors (currently 1).
+}
+
+impl EfiMemMap {
+    /// The default constructor which parses the information restored at the given address, and
+    /// initializes a new EfiMemMap struct and returns it.
+    ///
+    /// # Parameters
+    /// `addr` : The address where this tag starts (it should be 8 byte aligned).
+    /// `size` : The total size of the tag (including the headers).
+    ///
+    /// # Returns
+    /// The parsed EFI memory map struct.
+    pub unsafe fn new(addr: usize, size: usize) -> Self {
+        let mem_map = &*(addr as *const EfiMemMapRepr);
+        let header_size = core::mem::size_of::<EfiMemMapRepr>();
+
+        // The descriptors are read with the size the firmware used (it's never smaller than ours,
+        // a broken size is treated as an empty map).
+        let desc_size = mem_map.desc_size as usize;
+        let num_descs = if desc_size >= core::mem::size_of::<EfiMemDesc>() {
+            (size - header_size) / desc_size
+        } else {
+            0
+        };
+
+        EfiMemMap { num_descs, curr_desc_idx: 0, desc_size, descs_addr: addr + header_size }
+    }
+}
+
+// Implement the iterator trait for the descriptors, so we can go over them using a simple for loop.
+impl Iterator for EfiMemMap {
+    /// Define the type of the item used in the iterator (in this case it's the descriptors).
+    type Item = EfiMemDesc;
+
+    /// A function which proceeds to the next value in the iterator.
+    ///
+    /// # Returns
+    /// A Some(EfiMemDesc) if the next is in range, None otherwise.
+    fn next(&mut self) -> Option<EfiMemDesc> {
+        if self.curr_desc_idx < self.num_descs {
+            let curr_desc = unsafe { *((self.descs_addr + self.curr_desc_idx * self.desc_size)
+                as *const EfiMemDesc) };
+
+            self.curr_desc_idx += 1;
+            Some(curr_desc)
+        } else {
+            None
+        }
+    }
+}
+
+/// Represents each descriptor in the EFI memory map (which will follow the EfiMemMapRepr). It's
+/// definition is based on the UEFI standard.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct EfiMemDesc {
+    pub desc_type: u32,         // Variety of region. As defined by EfiMemType.
+    padding: u32,               // Aligns the addresses to 8 bytes.
+    pub phys_start: u64,        // Starting physical address (4K aligned).
+    pub virt_start: u64,        // Starting virtual address (only used by the runtime services).
+    pub num_pages: u64,         // The size of the region in 4K pages.
+    pub attributes: u64,        // The capabilities of the region (caching, runtime, etc.).
+}
+
+impl EfiMemDesc {
+    /// A simple getter for the end of the region (exclusive).
+    ///
+    /// # Returns
+    /// The physical address after the last byte of the region.
+    pub fn phys_end(&self) -> u64 {
+        self.phys_start + self.num_pages * EFI_PAGE_SIZE
+    }
+
+    /// A method which checks if the region can be used by the kernel. The memory which was used
+    /// by the loader and the boot services is usable once the boot services are terminated, but
+    /// the runtime services regions (and the rest of the types) are always preserved.
+    ///
+    /// # Parameters
+    /// `boot_services_active` : True if the boot services were not terminated by the loader.
+    ///
+    /// # Returns
+    /// True if it can be allocated, False otherwise.
+    pub fn is_usable(&self, boot_services_active: bool) -> bool {
+        let desc_type = self.desc_type;
+        if desc_type == EfiMemType::Conventional as u32
+            || desc_type == EfiMemType::LoaderCode as u32
+            || desc_type == EfiMemType::LoaderData as u32 {
+            true
+        } else {
+            desc_type == EfiMemType::BootServicesCode as u32 && !boot_services_active
+        }
+    }
+}
+
+/// Each type for the EFI memory descriptors. This corresponds to the desc_type in the struct
+/// EfiMemDesc. It's values are defined by the UEFI standard.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EfiMemType {
+    Reserved = 0,               // Memory which is not usable.
+    LoaderCode = 1,             // The code of the loader (usable).
+    LoaderData = 2,             // The data of the loader (usable).
+    BootServicesCode = 3,       // The code of the boot services (usable once they're terminated).
+    BootServicesData = 4,       // The data of the boot services (preserved).
+    RuntimeServicesCode = 5,    // The code of the runtime services (always preserved).
+    RuntimeServicesData = 6,    // The data of the runtime services (always preserved).
+    Conventional = 7,           // Free memory.
+    Unusable = 8,               // Memory which has errors.
+    AcpiReclaim = 9,            // Usable memory holding ACPI information.
+    AcpiNvs = 10,               // Memory which has to be preserved for the firmware.
+    Mmio = 11,                  // Memory mapped I/O for the firmware.
+    MmioPortSpace = 12,         // Memory mapped I/O ports.
+    PalCode = 13,               // Memory used by the processor's firmware.
+    Persistent = 14,            // Non-volatile memory.
+}
@@ -0,0 +1,39 @@
+//! A struct which represents the EFI 64-bit pointer tags in the multiboot info structure (the
+//! system table pointer, and the image handle pointer). They're only passed when the kernel is
+//! booted via UEFI, and they're kept for using the runtime services later. It's definition is
+//! directly derived from the multiboot2 specifications, which can be found at
+//! https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// A structure which represents a pointer which was passed by the EFI firmware.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct EfiPtr {
+    pub ptr: u64,               // The physical address of the table (or the handle).
+}
+
+/// The memory accurate representation of the tag, it is as defined in the multiboot2
+/// specifications.
+#[repr(C, packed)]
+struct EfiPtrRepr {
+    tag_type: u32,              // Type of the tag.
+    tag_size: u32,              // The size of the tag in bytes.
+    pub ptr: u64,               // The physical address.
+}
+
+impl EfiPtr {
+    /// The default constructor which parses the information restored at the given address, and
+    /// initializes a new EfiPtr struct and returns it.
+    ///
+    /// # Parameters
+    /// `addr` : The address where this tag starts (it should be 8 byte aligned).
+    ///
+    /// # Returns
+    /// The parsed pointer.
+    pub unsafe fn new(addr: usize) -> Self {
+        EfiPtr { ptr: (*(addr as *const EfiPtrRepr)).ptr }
+    }
+}
@@ -7,17 +7,24 @@
 #![allow(dead_code)]
 
 mod tag;
-mod mem_info;
-mod boot_dev;
+pub mod mem_info;
+pub mod boot_dev;
 mod elf_symbols;
-mod mem_map;
+pub mod mem_map;
+pub mod boot_cmd;
+pub mod modules;
+pub mod efi_ptr;
+pub mod efi_mem_map;
 
 #[allow(unused_imports)]
 use tag::{Tag, TagType};
 
+use crate::olibc::bounded::BoundedVec;
+
+/// The maximum number of boot modules which are kept (the rest are ignored).
+pub const MAX_MODULES: usize = 8;
+
 /*
-pub mod boot_cmd;
-pub mod modules;
 pub mod boot_loader;
 pub mod apm_table;
 pub mod vbe_info;
@@ -42,6 +49,12 @@ pub struct MultibootInfo {
     pub boot_dev_tag: Option<boot_dev::BootDev>,
     pub elf_symbols_tag: Option<elf_symbols::ElfSymbols>,
     pub mem_map_tag: Option<mem_map::MemMap>,
+    pub boot_cmd_tag: Option<boot_cmd::BootCmd>,
+    pub module_tags: BoundedVec<modules::Module, MAX_MODULES>,
+    pub efi64_sys_table_tag: Option<efi_ptr::EfiPtr>,     // Only passed when booted via UEFI.
+    pub efi64_img_handle_tag: Option<efi_ptr::EfiPtr>,
+    pub efi_mem_map_tag: Option<efi_mem_map::EfiMemMap>,
+    pub efi_boot_services: bool,                          // True if they were not terminated.
 }
 
 impl MultibootInfo {
@@ -71,6 +84,12 @@ impl MultibootInfo {
                 boot_dev_tag: None,
                 elf_symbols_tag: None,
                 mem_map_tag: None,
+                boot_cmd_tag: None,
+                module_tags: BoundedVec::new(),
+                efi64_sys_table_tag: None,
+                efi64_img_handle_tag: None,
+                efi_mem_map_tag: None,
+                efi_boot_services: false,
         };
         
         // Store the current pointer for parsing.
@@ -102,6 +121,16 @@ impl MultibootInfo {
         
         Ok(parsed_info)
     }
+    
+    /// A method which checks if the kernel was booted via UEFI (the loader passed any of the EFI
+    /// tags).
+    ///
+    /// # Returns
+    /// True if it was booted via UEFI, False if it was booted via BIOS.
+    pub fn is_uefi(&self) -> bool {
+        self.efi64_sys_table_tag.is_some() || self.efi_mem_map_tag.is_some() 
+            || self.efi_boot_services
+    }
 }
 
 
@@ -142,6 +171,12 @@ unsafe fn parse_tag(info: &mut MultibootInfo, addr: usize, tag_h: &tag::Tag) {
         tag::TagType::BootDev => { info.boot_dev_tag = Some(boot_dev::BootDev::new(addr)); },
         tag::TagType::ElfSymbols => { info.elf_symbols_tag = Some(elf_symbols::ElfSymbols::new(addr)); },
         tag::TagType::MemMap => { info.mem_map_tag = Some(mem_map::MemMap::new(addr, tag_h.tag_size as usize)); },
+        tag::TagType::BootCmd => { info.boot_cmd_tag = Some(boot_cmd::BootCmd::new(addr, tag_h.tag_size as usize)); },
+        tag::TagType::Modules => { info.module_tags.push(modules::Module::new(addr, tag_h.tag_size as usize)); },
+        tag::TagType::Efi64SysTablePtr => { info.efi64_sys_table_tag = Some(efi_ptr::EfiPtr::new(addr)); },
+        tag::TagType::EFI64ImgHandlePtr => { info.efi64_img_handle_tag = Some(efi_ptr::EfiPtr::new(addr)); },
+        tag::TagType::EFIMemMap => { info.efi_mem_map_tag = Some(efi_mem_map::EfiMemMap::new(addr, tag_h.tag_size as usize)); },
+        tag::TagType::EFIBootServicesNT => { info.efi_boot_services = true; },
         _ => {}
     }
 }
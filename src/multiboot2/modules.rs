@@ -0,0 +1,70 @@
+//! A struct which represents a boot module (a file which the boot loader loaded into memory along
+//! with the kernel). Each module has it's own tag, and a string which is usually it's path. It's 
+//! definition is directly derived from the multiboot2 specifications, which can be found at
+//! https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+//!
+//! Author: Ardalan Ahanchi
+//! Date: Mar 2021
+
+/// The size of the tag header (type, size, start, and end) before the string starts.
+const HEADER_SIZE: usize = 16;
+
+/// The base structure which is at the beginning of the modules tag.
+#[repr(C, packed)]
+struct ModuleHeader {
+    tag_type: u32,              // Type of the tag (3 for modules).
+    tag_size: u32,              // The size of the tag in bytes (including the string).
+    mod_start: u32,             // The physical address where the module starts.
+    mod_end: u32,               // The physical address where the module ends.
+}
+
+/// A structure which holds the location and the string of a module.
+#[derive(Copy, Clone)]
+pub struct Module {
+    pub start: usize,           // The physical address where the module starts.
+    pub end: usize,             // The physical address where the module ends (exclusive).
+    cmd: &'static str,          // The string of the module (lives in the multiboot info).
+}
+
+impl Module {
+    /// The default constructor which parses the information restored at the given address, and
+    /// initializes a new Module struct and returns it. If the string is not valid UTF-8, an empty
+    /// string will be used instead.
+    ///
+    /// # Parameters
+    /// `addr` : The address where this tag starts (it should be 8 byte aligned).
+    /// `size` : The size of the tag (including the header and the null terminator).
+    ///
+    /// # Returns
+    /// The parsed module struct.
+    pub unsafe fn new(addr: usize, size: usize) -> Self {
+        let header = &*(addr as *const ModuleHeader);
+        
+        // Calculate the length of the string (ignore the null terminator).
+        let len = size.saturating_sub(HEADER_SIZE + 1);
+        let bytes = core::slice::from_raw_parts((addr + HEADER_SIZE) as *const u8, len);
+        
+        Module {
+            start: header.mod_start as usize,
+            end: header.mod_end as usize,
+            cmd: core::str::from_utf8(bytes).unwrap_or(""),
+        }
+    }
+    
+    /// A simple getter for the string of the module (as passed to the boot loader).
+    ///
+    /// # Returns
+    /// The string (usually the path of the module).
+    pub fn cmd(&self) -> &'static str {
+        self.cmd
+    }
+    
+    /// A method which returns the contents of the module. The modules are loaded in the lower 
+    /// memory, which is identity mapped.
+    ///
+    /// # Returns
+    /// The bytes of the module.
+    pub unsafe fn as_slice(&self) -> &'static [u8] {
+        core::slice::from_raw_parts(self.start as *const u8, self.end.saturating_sub(self.start))
+    }
+}
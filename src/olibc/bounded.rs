@@ -0,0 +1,328 @@
+//! A sub-module which provides fixed capacity replacements for Vec and String. They never allocate
+//! memory, so they can be safely used in interrupt handlers (or anywhere the heap can't be used).
+//! When they are full, new elements are dropped (truncated), or an error is returned by the try_
+//! variants.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::mem::MaybeUninit;
+
+/// A vector with a fixed capacity of N elements which is stored inline (no heap allocations).
+#[derive(Copy, Clone)]
+pub struct BoundedVec<T: Copy, const N: usize> {
+    buffer: [MaybeUninit<T>; N],               // The storage for the elements.
+    len: usize,                                // The number of initialized elements.
+}
+
+impl<T: Copy, const N: usize> BoundedVec<T, N> {
+    /// A constructor which creates an empty vector.
+    ///
+    /// # Returns
+    /// The newly created vector with a length of 0.
+    pub const fn new() -> Self {
+        BoundedVec {
+            // An array of uninitialized elements doesn't need to be initialized.
+            buffer: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            len: 0,
+        }
+    }
+
+    /// A method which adds an element to the end of the vector. If the vector is full, it returns
+    /// the value back to the caller.
+    ///
+    /// # Parameters
+    /// `value` : The element which we're adding.
+    ///
+    /// # Returns
+    /// Ok if it was added, Err(value) if the vector is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        self.buffer[self.len] = MaybeUninit::new(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// A method which adds an element to the end of the vector. If the vector is full, the element
+    /// is simply dropped.
+    ///
+    /// # Parameters
+    /// `value` : The element which we're adding.
+    pub fn push(&mut self, value: T) {
+        let _ = self.try_push(value);
+    }
+
+    /// A method which removes the last element of the vector and returns it.
+    ///
+    /// # Returns
+    /// Some(element) if the vector was not empty, None otherwise.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        Some(unsafe { self.buffer[self.len].assume_init() })
+    }
+
+    /// A method which removes all the elements from the vector.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// A simple getter for the number of elements in the vector.
+    ///
+    /// # Returns
+    /// The number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// A simple getter for the maximum number of elements in the vector.
+    ///
+    /// # Returns
+    /// The capacity (N).
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// A method which checks if the vector has no elements.
+    ///
+    /// # Returns
+    /// True if empty, False otherwise.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// A method which checks if the vector can't hold any more elements.
+    ///
+    /// # Returns
+    /// True if full, False otherwise.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// A method which returns the initialized elements as a slice.
+    ///
+    /// # Returns
+    /// A slice with all the elements in the vector.
+    pub fn as_slice(&self) -> &[T] {
+        // The first len elements are always initialized.
+        unsafe { core::slice::from_raw_parts(self.buffer.as_ptr() as *const T, self.len) }
+    }
+
+    /// A method which returns the initialized elements as a mutable slice.
+    ///
+    /// # Returns
+    /// A mutable slice with all the elements in the vector.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // The first len elements are always initialized.
+        unsafe { core::slice::from_raw_parts_mut(self.buffer.as_mut_ptr() as *mut T, self.len) }
+    }
+}
+
+/// A string with a fixed capacity of N bytes (UTF-8) which is stored inline.
+#[derive(Copy, Clone)]
+pub struct BoundedString<const N: usize> {
+    bytes: BoundedVec<u8, N>,                  // The UTF-8 encoded characters.
+}
+
+impl<const N: usize> BoundedString<N> {
+    /// A constructor which creates an empty string.
+    ///
+    /// # Returns
+    /// The newly created string with a length of 0.
+    pub const fn new() -> Self {
+        BoundedString {
+            bytes: BoundedVec::new(),
+        }
+    }
+
+    /// A method which adds a character to the end of the string. Multi-byte characters are only
+    /// added if all of their bytes fit, so the string is always valid UTF-8.
+    ///
+    /// # Parameters
+    /// `character` : The character which we're adding.
+    ///
+    /// # Returns
+    /// Ok if it was added, Err if there was not enough space.
+    pub fn try_push(&mut self, character: char) -> Result<(), ()> {
+        // Encode the character and make sure all of it fits.
+        let mut encoded: [u8; 4] = [0; 4];
+        let encoded = character.encode_utf8(&mut encoded).as_bytes();
+        if self.bytes.len() + encoded.len() > N {
+            return Err(());
+        }
+
+        for byte in encoded {
+            self.bytes.push(*byte);
+        }
+
+        Ok(())
+    }
+
+    /// A method which adds a character to the end of the string. If it doesn't fit, it is dropped.
+    ///
+    /// # Parameters
+    /// `character` : The character which we're adding.
+    pub fn push(&mut self, character: char) {
+        let _ = self.try_push(character);
+    }
+
+    /// A method which adds as many characters of a string as possible (whole characters only).
+    ///
+    /// # Parameters
+    /// `string` : The string which we're adding.
+    ///
+    /// # Returns
+    /// Ok if all of it was added, Err if it was truncated.
+    pub fn push_str(&mut self, string: &str) -> Result<(), ()> {
+        for character in string.chars() {
+            self.try_push(character)?;
+        }
+
+        Ok(())
+    }
+
+    /// A method which removes the last character of the string and returns it.
+    ///
+    /// # Returns
+    /// Some(character) if the string was not empty, None otherwise.
+    pub fn pop(&mut self) -> Option<char> {
+        let character = self.as_str().chars().last()?;
+
+        // Remove all the bytes of the character.
+        for _ in 0..character.len_utf8() {
+            self.bytes.pop();
+        }
+
+        Some(character)
+    }
+
+    /// A method which removes all the characters from the string.
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    /// A simple getter for the number of bytes in the string.
+    ///
+    /// # Returns
+    /// The length in bytes (not characters).
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// A method which checks if the string has no characters.
+    ///
+    /// # Returns
+    /// True if empty, False otherwise.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// A method which returns the contents of the string.
+    ///
+    /// # Returns
+    /// The string slice with all the characters.
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever added, so it's always valid UTF-8.
+        unsafe { core::str::from_utf8_unchecked(self.bytes.as_slice()) }
+    }
+}
+
+impl<const N: usize> core::fmt::Write for BoundedString<N> {
+    /// Allows formatting into the string (the output is truncated if it doesn't fit).
+    fn write_str(&mut self, string: &str) -> core::fmt::Result {
+        let _ = self.push_str(string);
+        Ok(())
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{BoundedVec, BoundedString};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_vec_capacity();
+        test_vec_pop_clear();
+        test_string_truncation();
+        test_string_multi_byte();
+    }
+
+    /// Make sure the vector stops accepting elements at it's capacity.
+    fn test_vec_capacity() {
+        let mut vec: BoundedVec<usize, 3> = BoundedVec::new();
+        assert!(vec.is_empty());
+        assert_eq!(vec.capacity(), 3);
+
+        for i in 0..3 {
+            assert_eq!(vec.try_push(i), Ok(()));
+        }
+
+        // It's full, so the next elements are rejected (or dropped).
+        assert!(vec.is_full());
+        assert_eq!(vec.try_push(3), Err(3));
+        vec.push(4);
+        assert_eq!(vec.as_slice(), &[0, 1, 2]);
+    }
+
+    /// Check the pop and clear operations.
+    fn test_vec_pop_clear() {
+        let mut vec: BoundedVec<char, 4> = BoundedVec::new();
+        vec.push('a');
+        vec.push('b');
+
+        assert_eq!(vec.pop(), Some('b'));
+        assert_eq!(vec.len(), 1);
+
+        vec.clear();
+        assert_eq!(vec.pop(), None);
+        assert!(vec.is_empty());
+    }
+
+    /// Make sure strings are truncated at their capacity.
+    fn test_string_truncation() {
+        let mut string: BoundedString<5> = BoundedString::new();
+        assert_eq!(string.push_str("hello world"), Err(()));
+        assert_eq!(string.as_str(), "hello");
+
+        string.push('!');
+        assert_eq!(string.as_str(), "hello");
+    }
+
+    /// Make sure multi-byte characters are never split.
+    fn test_string_multi_byte() {
+        let mut string: BoundedString<4> = BoundedString::new();
+        assert_eq!(string.try_push('a'), Ok(()));
+        assert_eq!(string.try_push('€'), Ok(()));           // 3 bytes, exactly fits.
+        assert_eq!(string.try_push('b'), Err(()));
+        assert_eq!(string.as_str(), "a€");
+
+        // Popping removes the whole character.
+        assert_eq!(string.pop(), Some('€'));
+        assert_eq!(string.as_str(), "a");
+
+        // A character which doesn't fit is not partially added.
+        string.clear();
+        string.push_str("abc");
+        assert_eq!(string.try_push('é'), Err(()));
+        assert_eq!(string.as_str(), "abc");
+    }
+}
@@ -1,5 +1,6 @@
 //! A module which includes a set of c library functions which are absolutely needed to run 
-//! the core rust library. They are specifically needed by rustc, and core.
+//! the core rust library. They are specifically needed by rustc, and core. It also includes some
+//! basic containers which don't need a heap.
 //!
 //! `Author` : Ardalan Ahanchi
 //! `Date` : Jan 2021
@@ -8,3 +9,17 @@ pub mod memcmp;
 pub mod memcpy;
 pub mod memset;
 pub mod memmove;
+pub mod bounded;
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        super::bounded::test::run();
+    }
+}
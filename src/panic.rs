@@ -12,20 +12,43 @@ use core::alloc::Layout;
 pub extern fn rust_eh_personality() {}
 
 
-/// The primary panic handler. By default, it runs the halt instruction, and print the panic
-/// information to the console. More information can be found at:
+/// The primary panic handler. If the panic happened in a regular process (not the IDLE process, a 
+/// kernel service, or a foreground program which runs in the keyboard's bottom half), the process is 
+/// removed and the rest of the system keeps running. Otherwise, it prints the panic information 
+/// to the console, records it for the next boot (see debug::crashlog), and runs the halt 
+/// instruction. More information can be found at:
 /// https://doc.rust-lang.org/nomicon/panic-handler.html
 #[panic_handler]
 #[no_mangle]
 pub extern fn oxid_panic(_info: &PanicInfo) -> ! {
-    // Print the error message.
+    // The writer might have been locked when the panic happened, so unlock it before printing.
+    if let Some(console) = unsafe { crate::console::CONSOLE.as_mut() } {
+        unsafe { console.force_unlock(); }
+    }
+    
+    // If it belongs to a regular process, only that process is stopped. It might have panicked in 
+    // an exception handler (such as a page fault), so the scheduler removes it on the next tick. 
+    // The hardware interrupt handlers can't be left without their EOI, so they stop the system.
+    if !crate::proc::exec::in_foreground() && !crate::arch::interrupts::handlers::in_irq() 
+        && crate::proc::scheduler::can_recover_current() {
+        oxid_err!("Process PID={} panicked: {}", crate::proc::scheduler::current_pid().unwrap_or(0),
+            _info);
+        crate::debug::crashlog::on_panic(_info, false);
+        crate::proc::scheduler::exit_with_code(crate::proc::scheduler::PANIC_EXIT_CODE);
+        loop{ unsafe { crate::arch::proc::halt(); }}
+    }
+    
+    // Print the error message, and keep a record of it for the next boot.
     oxid_err!("{}", _info);
+    crate::debug::crashlog::on_panic(_info, true);
     
-    // Halt the system.
+    // Halt the system (the watchdog would catch the halt loop otherwise).
+    crate::proc::watchdog::disarm();
     loop{ unsafe { crate::arch::proc::halt(); }}
 }
 
 #[alloc_error_handler]
 fn heap_allocation_err(_layout: Layout) -> ! {
+    crate::mem::dyn_alloc::log_stats();
     panic!("Error allocating memory");
 }
@@ -0,0 +1,300 @@
+//! A module which shuts down (or reboots) the system in an orderly way. The regular processes are
+//! asked to exit, and the ones which don't exit in time are killed. Then the shutdown hooks which 
+//! were registered by the subsystems (for example, the work queue) are run in the reverse order of
+//! their registration, a summary is printed, and the hardware is reset or powered off.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+use crate::olibc::bounded::BoundedVec;
+use crate::proc::pid::ProcHandle;
+use crate::proc::process::{PCB, ProcessStatus, SpawnFlags};
+use crate::proc::scheduler::{self, KillResult};
+
+/// The time which the processes have to exit on their own (in milliseconds).
+pub const EXIT_TIMEOUT_MS: usize = 3000;
+
+/// The time which the killed processes have to be removed by the scheduler (in milliseconds).
+const KILL_TIMEOUT_MS: usize = 500;
+
+/// The time between a shutdown request and the start of the sequence (in milliseconds).
+pub const SHUTDOWN_GRACE_MS: usize = 500;
+
+/// The maximum number of shutdown hooks.
+pub const MAX_HOOKS: usize = 16;
+
+/// The maximum number of processes which are stopped.
+const MAX_STOPPED: usize = 64;
+
+/// The functions which are called during shutdown (in the reverse order).
+static mut HOOKS: BoundedVec<fn(), MAX_HOOKS> = BoundedVec::new();
+
+/// The summary of the last shutdown sequence (None if it never ran).
+static mut LAST_SUMMARY: Option<ShutdownSummary> = None;
+
+/// The function which replaces the hardware reset or power off in the tests.
+#[cfg(feature = "unit-test")]
+static mut TEST_HARDWARE: Option<fn(bool)> = None;
+
+/// The summary of a shutdown sequence.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ShutdownSummary {
+    pub exited: usize,          // The number of processes which exited on their own.
+    pub killed: usize,          // The number of processes which had to be killed.
+    pub hooks: usize,           // The number of shutdown hooks which were run.
+}
+
+/// A function which registers a hook which is called during shutdown (for example, to flush a 
+/// cache or unmount a filesystem). The hooks are called in the reverse order of their registration,
+/// so the subsystems which were initialized last are shut down first.
+///
+/// # Parameters
+/// `hook` : The function which is called.
+///
+/// # Returns
+/// Ok if it was registered, Err if there are too many hooks.
+pub fn on_shutdown(hook: fn()) -> Result<(), ()> {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let result = HOOKS.try_push(hook).map_err(|_| ());
+        crate::arch::interrupts::restore(were_enabled);
+        
+        result
+    }
+}
+
+/// A function which shuts down (or reboots) the system in an orderly way. It doesn't return (unless 
+/// the tests replaced the hardware call). It waits for the processes to exit, so it has to be 
+/// called from a process (see request_shutdown for the interrupt handlers).
+///
+/// # Parameters
+/// `reboot` : True if the system should be rebooted, False if it should be powered off.
+pub fn orderly_shutdown(reboot: bool) {
+    oxid_warn!("The system is {}.", if reboot { "rebooting" } else { "shutting down" });
+    
+    let (exited, killed) = stop_processes();
+    let hooks = run_hooks();
+    
+    let summary = ShutdownSummary { exited, killed, hooks };
+    unsafe { LAST_SUMMARY = Some(summary); }
+    oxid_log!("Shutdown: {} processes exited, {} were killed, {} hooks were run.", summary.exited,
+        summary.killed, summary.hooks);
+    
+    #[cfg(feature = "unit-test")]
+    if let Some(hardware) = unsafe { TEST_HARDWARE } {
+        hardware(reboot);
+        return;
+    }
+    
+    if reboot {
+        crate::arch::io::power::reboot();
+    } else {
+        crate::arch::io::power::poweroff();
+    }
+}
+
+/// A function which requests an orderly shutdown (or reboot). It's safe to be called from the 
+/// interrupt handlers (such as a power button), since the sequence runs on the work queue after 
+/// a short grace period.
+///
+/// # Parameters
+/// `reboot` : True if the system should be rebooted, False if it should be powered off.
+///
+/// # Returns
+/// Ok if it was requested, Err if the work queue is full (or shutting down).
+pub fn request_shutdown(reboot: bool) -> Result<(), ()> {
+    crate::proc::workqueue::queue_work_delayed(SHUTDOWN_GRACE_MS, shutdown_work, reboot as usize)
+}
+
+/// The work function which runs the shutdown sequence (queued by request_shutdown).
+///
+/// # Parameters
+/// `reboot` : 1 if the system should be rebooted, 0 if it should be powered off.
+fn shutdown_work(reboot: usize) {
+    orderly_shutdown(reboot != 0);
+}
+
+/// A function which asks the regular processes (not the kernel services, or the caller) to exit, 
+/// waits for them, and kills the ones which are still running after the timeout.
+///
+/// # Returns
+/// A tuple with the number of processes which exited, and the number which were killed.
+fn stop_processes() -> (usize, usize) {
+    let caller = scheduler::current_pid();
+    
+    // Find the processes (the list is collected without allocating, since interrupts are off).
+    let mut targets: BoundedVec<ProcHandle, MAX_STOPPED> = BoundedVec::new();
+    scheduler::for_each(&mut |pcb: &PCB| {
+        if Some(pcb.pid) != caller && !pcb.flags.contains(SpawnFlags::KERNEL_SERVICE) 
+            && pcb.status == ProcessStatus::Started {
+            let _ = targets.try_push(pcb.handle());
+        }
+    });
+    
+    for handle in targets.as_slice() {
+        scheduler::request_termination(*handle);
+    }
+    wait_for_removal(&targets, EXIT_TIMEOUT_MS);
+    
+    // Escalate for the processes which refused to exit.
+    let (mut refused, mut killed) = (0, 0);
+    for handle in targets.as_slice().iter().filter(|handle| is_running(**handle)) {
+        refused += 1;
+        match scheduler::kill_pid(*handle) {
+            KillResult::Killed => killed += 1,
+            KillResult::NotKillable => oxid_warn!("Process PID={} can't be killed, leaving it.", 
+                handle.pid()),
+            KillResult::NotFound => (),
+        }
+    }
+    wait_for_removal(&targets, KILL_TIMEOUT_MS);
+    
+    (targets.len() - refused, killed)
+}
+
+/// A helper which waits for some processes to be removed by the scheduler.
+///
+/// # Parameters
+/// `handles` : The handles of the processes which we're waiting for.
+/// `timeout_ms` : The maximum time to wait (in milliseconds).
+fn wait_for_removal(handles: &BoundedVec<ProcHandle, MAX_STOPPED>, timeout_ms: usize) {
+    let deadline = crate::time::ticks() + crate::time::ms_to_ticks(timeout_ms);
+    while handles.as_slice().iter().any(|handle| scheduler::find_handle(*handle, |_| ()).is_some()) 
+        && crate::time::ticks() < deadline {
+        unsafe { crate::arch::proc::wait_for_interrupt(); }
+    }
+}
+
+/// A helper which checks if a process is still running (and not just waiting to be removed).
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+///
+/// # Returns
+/// True if it's still running, False otherwise.
+fn is_running(handle: ProcHandle) -> bool {
+    scheduler::find_handle(handle, |pcb: &PCB| pcb.status == ProcessStatus::Started)
+        .unwrap_or(false)
+}
+
+/// A function which runs the shutdown hooks (the last registered first).
+///
+/// # Returns
+/// The number of hooks which were run.
+fn run_hooks() -> usize {
+    // Copy the hooks, so they can register hooks (or print) while they are run.
+    let hooks = unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let hooks = HOOKS;
+        crate::arch::interrupts::restore(were_enabled);
+        
+        hooks
+    };
+    
+    for hook in hooks.as_slice().iter().rev() {
+        hook();
+    }
+    
+    hooks.len()
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::olibc::bounded::BoundedVec;
+    use crate::proc::process::{Args, SpawnFlags};
+    use crate::proc::scheduler;
+    
+    /// The steps of the sequence in the order they happened (1, 2 for the hooks, 3 for hardware).
+    static mut STEPS: BoundedVec<u8, 8> = BoundedVec::new();
+    
+    /// The argument which was passed to the hardware call (None if it wasn't reached).
+    static mut HARDWARE_REBOOT: Option<bool> = None;
+    
+    /// Set when the cooperative process saw the request.
+    static mut COOPERATIVE_EXITED: bool = false;
+    
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module.
+    pub fn run() {
+        test_orderly_shutdown();
+    }
+    
+    /// The first hook which is registered (it should run last).
+    fn first_hook() {
+        unsafe { STEPS.push(1); }
+    }
+    
+    /// The second hook which is registered (it should run first).
+    fn second_hook() {
+        unsafe { STEPS.push(2); }
+    }
+    
+    /// Replaces the hardware reset and power off.
+    fn test_hardware(reboot: bool) {
+        unsafe {
+            STEPS.push(3);
+            HARDWARE_REBOOT = Some(reboot);
+        }
+    }
+    
+    /// A process which exits once it's asked to.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn cooperative_process(_args: *const Args) -> usize {
+        while !scheduler::termination_requested() {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+        unsafe { COOPERATIVE_EXITED = true; }
+        0
+    }
+    
+    /// A process which ignores the requests to exit.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn stubborn_process(_args: *const Args) -> usize {
+        loop {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+    }
+    
+    /// Run the sequence with a cooperative and a stubborn process, and make sure the stubborn one 
+    /// is killed, the hooks run in the reverse order, and the hardware call is reached last.
+    fn test_orderly_shutdown() {
+        unsafe {
+            // Replace the real hooks and the hardware call.
+            let saved_hooks = super::HOOKS;
+            super::HOOKS.clear();
+            super::TEST_HARDWARE = Some(test_hardware);
+            super::on_shutdown(first_hook).unwrap();
+            super::on_shutdown(second_hook).unwrap();
+            
+            let mut args = Args::new();
+            let cooperative = scheduler::spawn_with_flags(cooperative_process, 
+                &mut args as *mut Args, "coop_test", SpawnFlags::NONE);
+            let stubborn = scheduler::spawn_with_flags(stubborn_process, &mut args as *mut Args, 
+                "stubborn_test", SpawnFlags::NONE);
+            
+            super::orderly_shutdown(true);
+            
+            // Both are gone, but only the stubborn one had to be killed.
+            assert!(COOPERATIVE_EXITED);
+            assert!(scheduler::find_handle(cooperative, |_| ()).is_none());
+            assert!(scheduler::find_handle(stubborn, |_| ()).is_none());
+            let summary = super::LAST_SUMMARY.unwrap();
+            assert_eq!((summary.exited, summary.killed, summary.hooks), (1, 1, 2));
+            
+            // The hooks ran in the reverse order, and the hardware call was last.
+            assert_eq!(STEPS.as_slice(), &[2, 1, 3]);
+            assert_eq!(HARDWARE_REBOOT, Some(true));
+            
+            super::TEST_HARDWARE = None;
+            super::HOOKS = saved_hooks;
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! A sub-module which provides the cooperative cancellation of the long-running kernel operations
+//! (disk scans, sweeps, printing large files). Killing the process in the middle of one of them
+//! would leave it's state half changed, so every process has a token which is cancelled when it's
+//! interrupted (Ctrl+C, see io::term::interrupt) or killed. The long operations take a token
+//! (usually the running process's, see current), and poll it at the safe points (for example,
+//! between two sectors). Once it's cancelled, they undo what they started (free their buffers, and
+//! release their locks) and return Cancelled. A program which was cancelled should exit with
+//! INTERRUPTED_STATUS, and the terminal reports it as interrupted.
+//!
+//! A token can also have a deadline (in ticks), it counts as cancelled once the deadline passes.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// The exit status of a program which was cancelled (128 + SIGINT, like the other shells).
+pub const INTERRUPTED_STATUS: i32 = 130;
+
+/// The deadline of the tokens which don't have one.
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// The error which is returned by an operation which was cancelled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cancelled;
+
+/// A structure which represents the cancellation of an operation. It can be cancelled from any
+/// context (including the interrupt handlers), and it's polled by the operation.
+pub struct CancelToken {
+    cancelled: AtomicBool,          // True once it was cancelled.
+    deadline: AtomicU64,            // The tick which it's cancelled at (NO_DEADLINE if none).
+}
+
+impl CancelToken {
+    /// A constructor which creates a token which is not cancelled (and has no deadline).
+    ///
+    /// # Returns
+    /// The newly created token.
+    pub const fn new() -> Self {
+        CancelToken { cancelled: AtomicBool::new(false), deadline: AtomicU64::new(NO_DEADLINE) }
+    }
+
+    /// A method which cancels the operations which poll the token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// A method which sets (or removes) the deadline of the token.
+    ///
+    /// # Parameters
+    /// `deadline` : The tick which it's cancelled at (None to remove it).
+    pub fn set_deadline(&self, deadline: Option<u64>) {
+        self.deadline.store(deadline.unwrap_or(NO_DEADLINE), Ordering::SeqCst);
+    }
+
+    /// A method which clears the cancellation and the deadline, so the token can be used for the
+    /// next operation.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.deadline.store(NO_DEADLINE, Ordering::SeqCst);
+    }
+
+    /// A method which checks if the token was cancelled (or it's deadline passed).
+    ///
+    /// # Returns
+    /// True if the operation should stop, False otherwise.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || crate::time::ticks() >= self.deadline.load(Ordering::SeqCst)
+    }
+
+    /// A method which checks the token at a safe point (so it can be used with the ? operator).
+    ///
+    /// # Returns
+    /// Ok if the operation can continue, Err(Cancelled) if it should stop.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() { Err(Cancelled) } else { Ok(()) }
+    }
+}
+
+/// The token which is used before the scheduler is initialized (it's never cancelled).
+static NEVER: CancelToken = CancelToken::new();
+
+/// A function which returns the token of the running process. It's only valid while the process
+/// is running, so it should not be kept after the operation is done.
+///
+/// # Returns
+/// The token of the running process.
+pub fn current() -> &'static CancelToken {
+    crate::proc::scheduler::with_current(|pcb| &pcb.cancel as *const CancelToken)
+        .map(|token| unsafe { &*token })
+        .unwrap_or(&NEVER)
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::vec::Vec;
+    use crate::proc::process::Args;
+    use super::{CancelToken, Cancelled, INTERRUPTED_STATUS};
+
+    /// The number of steps of the fake operation, and the step which it's interrupted at.
+    const FAKE_STEPS: usize = 64;
+    const INTERRUPT_STEP: usize = 20;
+
+    /// The number of buffers which the fake operation currently holds.
+    static mut HELD: usize = 0;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_token();
+        test_partial_cleanup();
+        test_interrupted();
+    }
+
+    /// A long fake operation which allocates a buffer on every step (and frees them all at the
+    /// end). It polls the token between the steps, and frees what it allocated once it's cancelled.
+    ///
+    /// # Parameters
+    /// `token` : The token which is polled.
+    /// `interrupt_at` : The step which the running process is interrupted at (like Ctrl+C).
+    ///
+    /// # Returns
+    /// Ok with the number of steps, or Err(Cancelled) if it was cancelled.
+    fn fake_operation(token: &CancelToken, interrupt_at: Option<usize>)
+        -> Result<usize, Cancelled> {
+        let mut buffers: Vec<*mut u8> = Vec::new();
+        let mut result = Ok(FAKE_STEPS);
+        for step in 0..FAKE_STEPS {
+            if interrupt_at == Some(step) {
+                let handle = crate::proc::scheduler::current_handle().unwrap();
+                assert!(crate::proc::scheduler::cancel(handle));
+            }
+
+            if let Err(cancelled) = token.check() {
+                result = Err(cancelled);
+                break;
+            }
+
+            buffers.push(unsafe { crate::mem::dyn_alloc::kmalloc(256, false, true, true) });
+            unsafe { HELD += 1; }
+        }
+
+        // Both a finished and a cancelled operation release everything they hold.
+        for buffer in buffers {
+            unsafe {
+                crate::mem::dyn_alloc::kfree(buffer);
+                HELD -= 1;
+            }
+        }
+        result
+    }
+
+    /// A program which runs the fake operation with the running process's token, and interrupts
+    /// itself in the middle of it.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    ///
+    /// # Returns
+    /// The exit code (INTERRUPTED_STATUS if it was cancelled).
+    extern "sysv64" fn fake_program(_args: *const Args) -> usize {
+        match fake_operation(super::current(), Some(INTERRUPT_STEP)) {
+            Ok(_) => 0,
+            Err(Cancelled) => INTERRUPTED_STATUS as usize,
+        }
+    }
+
+    /// Cancel a token, give it a deadline, and make sure it's reset for the next operation.
+    fn test_token() {
+        let token = CancelToken::new();
+        assert_eq!(token.check(), Ok(()));
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check(), Err(Cancelled));
+
+        token.reset();
+        token.set_deadline(Some(crate::time::ticks() + crate::time::ms_to_ticks(60_000)));
+        assert!(!token.is_cancelled());
+        token.set_deadline(Some(crate::time::ticks()));
+        assert!(token.is_cancelled());
+        token.set_deadline(None);
+        assert!(!token.is_cancelled());
+    }
+
+    /// Run the fake operation to completion, and then interrupt it in the middle. It should stop
+    /// right at the interrupted step, and release everything it held.
+    fn test_partial_cleanup() {
+        let allocs = crate::mem::dyn_alloc::num_allocs();
+        let token = super::current();
+        token.reset();
+
+        assert_eq!(fake_operation(token, None), Ok(FAKE_STEPS));
+        assert_eq!(fake_operation(token, Some(INTERRUPT_STEP)), Err(Cancelled));
+        assert!(token.is_cancelled());
+        token.reset();
+
+        assert_eq!(unsafe { HELD }, 0);
+        assert_eq!(crate::mem::dyn_alloc::num_allocs(), allocs);
+    }
+
+    /// Run the fake program from the terminal, interrupt it in the middle, and make sure it's
+    /// reported as interrupted (and the token is reset for the next program).
+    fn test_interrupted() {
+        crate::demo::register("fakeop", fake_program);
+
+        let mut output = alloc::string::String::new();
+        crate::io::term::start_capture();
+        crate::io::term::type_line("fakeop\n");
+        crate::io::term::capture_output(&mut output);
+
+        assert!(output.contains("fakeop: interrupted"));
+        assert_eq!(crate::io::term::last_status(), INTERRUPTED_STATUS);
+        assert!(!super::current().is_cancelled());
+        assert_eq!(unsafe { HELD }, 0);
+    }
+}
@@ -0,0 +1,268 @@
+//! A sub-module which executes programs from a command line. It tokenizes the command line, finds
+//! the program, validates the arguments, and then either spawns it as a new process or runs it 
+//! inline (in the caller's context). The terminal (and future shell features) should use it 
+//! instead of looking up the programs directly.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::proc::process::{Args, ARGS_MAX, ResourceLimits, SpawnFlags};
+use crate::proc::scheduler::SpawnError;
+
+/// The maximum number of arguments (including the program name).
+pub const MAX_ARGS: usize = 16;
+
+/// A set of flags which change how a program is executed. They can be combined with the | operator.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ExecFlags(u8);
+
+impl ExecFlags {
+    /// Run the program inline (and wait for it to finish).
+    pub const NONE: ExecFlags = ExecFlags(0);
+    
+    /// Spawn the program as a new process (in the background).
+    pub const BACKGROUND: ExecFlags = ExecFlags(1 << 0);
+    
+    /// Copy the output of the spawned process to the kernel log (see debug::klog).
+    pub const LOG_OUTPUT: ExecFlags = ExecFlags(1 << 1);
+    
+    /// A method which checks if all the given flags are set.
+    ///
+    /// # Parameters
+    /// `other` : The flags which we're checking.
+    ///
+    /// # Returns
+    /// True if all of them are set, False otherwise.
+    #[inline]
+    pub fn contains(&self, other: ExecFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for ExecFlags {
+    type Output = ExecFlags;
+    
+    fn bitor(self, other: ExecFlags) -> ExecFlags {
+        ExecFlags(self.0 | other.0)
+    }
+}
+
+/// An enum which represents the result of a successful execution.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExecResult {
+    Spawned(usize),             // It was spawned in the background (with the given PID).
+    Exited(i32),                // It was run inline, and exited with the given code.
+}
+
+/// An enum which represents the reason an execution failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExecError {
+    Empty,                      // There was no program in the command line.
+    NotFound,                   // The program does not exist.
+    TooLong,                    // The command line is longer than ARGS_MAX bytes.
+    TooManyArgs,                // There are more than MAX_ARGS arguments.
+    TooManyChildren,            // The caller can't spawn any more processes.
+    BadArgs,                    // The arguments were refused by the spawner.
+}
+
+/// True while a program is running inline (foreground programs run in the keyboard's bottom half).
+static mut INLINE: bool = false;
+
+/// The PID of the process which is running the inline program, and the code it exited with.
+static mut INLINE_PID: Option<usize> = None;
+static mut INLINE_EXIT_CODE: i32 = 0;
+
+/// A function which executes a command line. The first word is the program, and the rest are it's
+/// arguments (separated by whitespace).
+///
+/// # Parameters
+/// `cmdline` : The command line which we're executing.
+/// `flags` : The flags which change how it's executed.
+///
+/// # Returns
+/// The PID if it was spawned, or the exit code if it was run inline. Err if it was not executed.
+pub fn exec(cmdline: &str, flags: ExecFlags) -> Result<ExecResult, ExecError> {
+    exec_with_limits(cmdline, flags, None)
+}
+
+/// A version of exec which sets the resource limits of the spawned process (instead of inheriting
+/// them from the caller). The limits are not used when the program is run inline.
+///
+/// # Parameters
+/// `cmdline` : The command line which we're executing.
+/// `flags` : The flags which change how it's executed.
+/// `limits` : The resource limits of the new process (inherited if None).
+///
+/// # Returns
+/// The PID if it was spawned, or the exit code if it was run inline. Err if it was not executed.
+pub fn exec_with_limits(cmdline: &str, flags: ExecFlags, limits: Option<ResourceLimits>) 
+    -> Result<ExecResult, ExecError> {
+    // Tokenize it (and normalize the spacing, since the arguments are split by a single space).
+    let tokens: Vec<&str> = cmdline.split_whitespace().collect();
+    if tokens.is_empty() {
+        return Err(ExecError::Empty);
+    }
+    
+    let joined: String = tokens.join(" ");
+    if joined.len() > ARGS_MAX {
+        return Err(ExecError::TooLong);
+    }
+    
+    if tokens.len() > MAX_ARGS {
+        return Err(ExecError::TooManyArgs);
+    }
+    
+    // Find the program in the registry (there are no executable files yet).
+    let program_main = crate::demo::get_main(tokens[0]).ok_or(ExecError::NotFound)?;
+    
+    unsafe {
+        // Allocate the arguments on the heap (the interrupt stacks are small).
+        let args_ptr = crate::mem::dyn_alloc::kmalloc_zeroed(core::mem::size_of::<Args>(), 
+            false, true, false) as *mut Args;
+        crate::mem::dyn_alloc::set_tag(args_ptr as *mut u8, crate::mem::dyn_alloc::AllocTag::Args);
+        *args_ptr = Args::new();
+        (*args_ptr).set_args(&joined);
+        
+        let result = if flags.contains(ExecFlags::BACKGROUND) {
+            // Spawn a new process with a unique name (it copies the arguments).
+            let spawn_flags = if flags.contains(ExecFlags::LOG_OUTPUT) { 
+                SpawnFlags::LOG_OUTPUT 
+            } else { 
+                SpawnFlags::NONE 
+            };
+            
+            let name = crate::demo::instance_name(tokens[0])
+                .unwrap_or_else(|| String::from(tokens[0]));
+            crate::proc::scheduler::try_spawn(program_main, args_ptr, &name, spawn_flags, limits)
+                .map(|handle| ExecResult::Spawned(handle.pid()))
+                .map_err(|error| match error {
+                    SpawnError::BadArgs(_) => ExecError::BadArgs,
+                    _ => ExecError::TooManyChildren,
+                })
+        } else {
+            // Just run the program.
+            let (was_inline, prev_pid, prev_code) = (INLINE, INLINE_PID, INLINE_EXIT_CODE);
+            INLINE = true;
+            INLINE_PID = crate::proc::scheduler::current_pid();
+            INLINE_EXIT_CODE = 0;
+            crate::proc::cancel::current().reset();
+            let returned = program_main(args_ptr);
+            let code = if returned != 0 { returned as i32 } else { INLINE_EXIT_CODE };
+            INLINE = was_inline;
+            INLINE_PID = prev_pid;
+            INLINE_EXIT_CODE = prev_code;
+            
+            // The escape key asks the thread running it to exit, and Ctrl+C cancels it's operations
+            // (they're only meant for the program).
+            crate::proc::scheduler::clear_termination_request();
+            crate::proc::cancel::current().reset();
+            
+            Ok(ExecResult::Exited(code))
+        };
+        
+        crate::mem::dyn_alloc::kfree(args_ptr as *mut u8);
+        result
+    }
+}
+
+/// A function which checks if a program is running inline. The foreground programs run in the 
+/// keyboard's bottom half in the kworker thread, so their failures don't belong to that thread.
+///
+/// # Returns
+/// True if a program is running inline, False otherwise.
+pub fn in_foreground() -> bool {
+    unsafe { INLINE }
+}
+
+/// A function which ends a program with an exit code. A spawned process exits right away, but an 
+/// inline program can't exit the thread which runs it, so the code is only recorded (it's returned
+/// by exec, unless the program returns a non-zero code), and the program should return right after
+/// it. The programs can also just return their exit code from main.
+///
+/// # Parameters
+/// `code` : The exit code (non-zero if the program failed).
+pub fn exit_program(code: i32) {
+    let pid = crate::proc::scheduler::current_pid();
+    if unsafe { INLINE && INLINE_PID == pid } {
+        unsafe { INLINE_EXIT_CODE = code; }
+    } else {
+        crate::proc::scheduler::exit_with_code(code);
+    }
+}
+
+/// A function which abandons the foreground program (it's called by the watchdog when it restarts
+/// the kworker thread). The arguments of the program are leaked, since it never returned.
+pub fn abort_foreground() {
+    unsafe { 
+        INLINE = false; 
+        INLINE_PID = None;
+    }
+    crate::proc::scheduler::clear_termination_request();
+    crate::proc::cancel::current().reset();
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::string::String;
+    use crate::proc::process::ARGS_MAX;
+    use super::{exec, ExecError, ExecFlags, ExecResult, MAX_ARGS};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_errors();
+        test_inline();
+        test_background();
+    }
+
+    /// Make sure the invalid command lines are rejected with the right errors.
+    fn test_errors() {
+        assert_eq!(exec("", ExecFlags::NONE), Err(ExecError::Empty));
+        assert_eq!(exec("   ", ExecFlags::BACKGROUND), Err(ExecError::Empty));
+        assert_eq!(exec("no_such_program", ExecFlags::NONE), Err(ExecError::NotFound));
+        
+        // Too many arguments.
+        let mut many = String::from("echo");
+        for _ in 0..MAX_ARGS {
+            many.push_str(" a");
+        }
+        assert_eq!(exec(&many, ExecFlags::NONE), Err(ExecError::TooManyArgs));
+        
+        // A command line which doesn't fit in the arguments.
+        let mut long = String::from("echo ");
+        for _ in 0..ARGS_MAX {
+            long.push('x');
+        }
+        assert_eq!(exec(&long, ExecFlags::NONE), Err(ExecError::TooLong));
+    }
+    
+    /// Run a program inline (the extra spaces are ignored).
+    fn test_inline() {
+        assert!(!super::in_foreground());
+        assert_eq!(exec("  echo   exec inline test ", ExecFlags::NONE), Ok(ExecResult::Exited(0)));
+        assert!(!super::in_foreground());
+    }
+    
+    /// Spawn a program, and wait for it to finish.
+    fn test_background() {
+        let pid = match exec("echo exec background test", ExecFlags::BACKGROUND) {
+            Ok(ExecResult::Spawned(pid)) => pid,
+            other => panic!("Unexpected exec result {:?}", other),
+        };
+        
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+        while crate::proc::scheduler::find(pid, |_| ()).is_some() 
+            && crate::time::ticks() < deadline {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+        assert!(crate::proc::scheduler::find(pid, |_| ()).is_none());
+    }
+}
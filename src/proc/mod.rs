@@ -5,5 +5,34 @@
 //! `Date` : Jan 2021
 
 pub mod mutex; 		// For syncrhonization.
+pub mod seqlock;    // For the statistics written by the interrupts.
 pub mod process;    // For multi-processing.
+pub mod pid;        // For the process IDs (and their generations).
 pub mod scheduler;  // The main scheduler.
+pub mod workqueue;  // For deferred work.
+pub mod exec;       // For executing programs.
+pub mod watchdog;   // For the processes which keep the interrupts disabled.
+pub mod cancel;     // For stopping the long operations cooperatively.
+
+pub use process::InputMode;
+pub use scheduler::set_input_mode;
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        super::seqlock::test::run();
+        super::pid::test::run();
+        super::process::test::run();
+        super::scheduler::test::run();
+        super::workqueue::test::run();
+        super::exec::test::run();
+        super::watchdog::test::run();
+        super::cancel::test::run();
+    }
+}
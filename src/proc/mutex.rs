@@ -43,12 +43,73 @@ impl Mutex {
             // Call the spinlock to perform the action.
             crate::arch::proc::sync::spin_lock(&mut self.state as *mut u8);
         }
+        
+        // Let the scheduler know, so it doesn't switch away while the mutex is held.
+        crate::proc::scheduler::lock_acquired();
+    }
+    
+    /// A method which tries to lock this mutex without spinning. It doesn't change the interrupts, 
+    /// so the caller should disable them first. It can be used where spinning would deadlock (for
+    /// example, in an interrupt handler which might have interrupted the holder).
+    ///
+    /// # Returns
+    /// True if it was locked, False if it's already held.
+    pub fn try_lock(&mut self) -> bool {
+        // The state is a byte which is either 0 or 1, so it can be swapped like a boolean.
+        let locked = unsafe { crate::arch::proc::sync::atomic_bool_lock_cmpxchg(
+            &self.state as *const u8 as *const bool, false, true) };
+        
+        if locked {
+            crate::proc::scheduler::lock_acquired();
+        }
+        
+        locked
+    }
+    
+    /// A method which unlocks a mutex which was locked with try_lock. Unlike unlock, the interrupts
+    /// are left alone.
+    pub fn release(&mut self) {
+        self.state = UNLOCKED;
+        crate::proc::scheduler::lock_released();
+    }
+    
+    /// A version of lock which saves the state of the interrupts, so they can be restored by 
+    /// unlock_irqrestore (instead of always enabling them like unlock). It should be used by the 
+    /// code which can be called from the interrupt handlers, since enabling the interrupts in a
+    /// handler allows it to be interrupted again (before it's EOI is sent).
+    ///
+    /// # Returns
+    /// True if the interrupts were enabled before it was locked, False otherwise.
+    pub fn lock_irqsave(&mut self) -> bool {
+        let were_enabled = unsafe { crate::arch::interrupts::save_and_disable() };
+        self.lock();
+        were_enabled
+    }
+    
+    /// A method which unlocks a mutex which was locked with lock_irqsave, and restores the 
+    /// interrupts to their state before it was locked.
+    ///
+    /// # Parameters
+    /// `were_enabled` : The value returned from lock_irqsave().
+    pub fn unlock_irqrestore(&mut self, were_enabled: bool) {
+        self.release();
+        unsafe { crate::arch::interrupts::restore(were_enabled); }
+    }
+    
+    /// A method which checks if the mutex is currently held (by anyone). It can be used to avoid
+    /// spinning where it would deadlock.
+    ///
+    /// # Returns
+    /// True if it's locked, False otherwise.
+    pub fn is_locked(&self) -> bool {
+        self.state == LOCKED
     }
     
     /// A function which unlocks a mutex, it allows other threads to access it as well.
     /// it simply changes the locked variable in the struct.
     pub fn unlock(&mut self) {
         self.state = UNLOCKED;
+        crate::proc::scheduler::lock_released();
         
         // Re-enable interrupts to allow switching.
         unsafe { crate::arch::interrupts::enable(); }
@@ -0,0 +1,179 @@
+//! A sub-module which allocates the process IDs. The PIDs of the removed processes are reused (the
+//! lowest free one is given out first), so a PID alone can't tell a process apart from an older
+//! one which had the same number. Every PID is paired with a generation which is bumped each time
+//! it's given out, and the pair is used as a handle. The scheduler checks the generation of a
+//! handle before acting on it, so a stale handle (of a process which was already removed) is never
+//! applied to the new process which got it's PID.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// The number of PIDs (including the IDLE process's PID, which is never given out).
+pub const MAX_PIDS: usize = 1024;
+
+/// The PID which is reserved for the IDLE process.
+pub const IDLE_PID: usize = 0;
+
+/// A structure which identifies a single process (a PID, and the generation it was given out in).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ProcHandle {
+    pid: usize,                     // The process ID.
+    gen: u16,                       // The generation of the PID when it was given out.
+}
+
+impl ProcHandle {
+    /// A constructor which creates a handle (only used by the process control blocks, every other
+    /// handle is given out by a PidTable).
+    ///
+    /// # Parameters
+    /// `pid` : The process ID.
+    /// `gen` : The generation of the PID.
+    ///
+    /// # Returns
+    /// The newly created handle.
+    pub(super) fn new(pid: usize, gen: u16) -> Self {
+        ProcHandle { pid, gen }
+    }
+
+    /// A simple getter for the PID of the handle.
+    ///
+    /// # Returns
+    /// The process ID.
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    /// A simple getter for the generation of the handle.
+    ///
+    /// # Returns
+    /// The generation of the PID.
+    pub fn gen(&self) -> u16 {
+        self.gen
+    }
+}
+
+/// A structure which keeps the state of every PID (if it's used, and it's last generation).
+pub struct PidTable {
+    used: [u64; MAX_PIDS / 64],     // A bit for each PID (set if it's given out).
+    gens: [u16; MAX_PIDS],          // The generation each PID was last given out in.
+}
+
+impl PidTable {
+    /// A constructor which creates a table where only the IDLE process's PID is used.
+    ///
+    /// # Returns
+    /// The newly created table.
+    pub const fn new() -> Self {
+        let mut used = [0; MAX_PIDS / 64];
+        used[IDLE_PID / 64] = 1 << (IDLE_PID % 64);
+
+        PidTable { used, gens: [0; MAX_PIDS] }
+    }
+
+    /// A method which gives out the lowest free PID, and bumps it's generation.
+    ///
+    /// # Returns
+    /// Some(handle) of the new process, or None if all the PIDs are used.
+    pub fn alloc(&mut self) -> Option<ProcHandle> {
+        let (word_idx, word) = self.used.iter().enumerate().find(|(_, word)| **word != u64::MAX)?;
+        let pid = word_idx * 64 + (!word).trailing_zeros() as usize;
+
+        self.used[word_idx] |= 1 << (pid % 64);
+        self.gens[pid] = self.gens[pid].wrapping_add(1);
+        Some(ProcHandle { pid, gen: self.gens[pid] })
+    }
+
+    /// A method which frees the PID of a handle (so it can be given out again).
+    ///
+    /// # Parameters
+    /// `handle` : The handle of the process which was removed.
+    ///
+    /// # Returns
+    /// True if it was freed, False if the handle is stale (or the PID was not used).
+    pub fn release(&mut self, handle: ProcHandle) -> bool {
+        if !self.is_current(handle) || handle.pid == IDLE_PID {
+            return false;
+        }
+
+        self.used[handle.pid / 64] &= !(1 << (handle.pid % 64));
+        true
+    }
+
+    /// A method which finds the handle of the process which currently has a PID.
+    ///
+    /// # Parameters
+    /// `pid` : The process ID.
+    ///
+    /// # Returns
+    /// Some(handle) if the PID is used, None otherwise.
+    pub fn current(&self, pid: usize) -> Option<ProcHandle> {
+        if pid >= MAX_PIDS || self.used[pid / 64] & (1 << (pid % 64)) == 0 {
+            return None;
+        }
+
+        Some(ProcHandle { pid, gen: self.gens[pid] })
+    }
+
+    /// A method which checks if a handle belongs to the process which currently has it's PID.
+    ///
+    /// # Parameters
+    /// `handle` : The handle.
+    ///
+    /// # Returns
+    /// True if it's current, False if it's stale.
+    pub fn is_current(&self, handle: ProcHandle) -> bool {
+        self.current(handle.pid) == Some(handle)
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{PidTable, MAX_PIDS};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_reuse();
+        test_exhausted();
+    }
+
+    /// Give out a few PIDs, free one and make sure it's given out again with a new generation (so
+    /// the old handle is stale).
+    fn test_reuse() {
+        let mut table = PidTable::new();
+        let first = table.alloc().unwrap();
+        let second = table.alloc().unwrap();
+        assert_eq!((first.pid(), second.pid()), (1, 2));
+        assert_eq!(table.current(0).map(|idle| idle.gen()), Some(0));
+        assert!(table.current(3).is_none() && table.current(MAX_PIDS).is_none());
+
+        assert!(table.release(first));
+        assert!(!table.release(first));
+        assert!(!table.is_current(first));
+
+        let reused = table.alloc().unwrap();
+        assert_eq!(reused.pid(), first.pid());
+        assert_eq!(reused.gen(), first.gen().wrapping_add(1));
+        assert!(table.is_current(reused) && !table.is_current(first));
+        assert!(!table.release(first));
+        assert!(table.is_current(reused));
+        assert!(!table.release(table.current(0).unwrap()));
+    }
+
+    /// Use up every PID, and make sure the next one fails until one is freed.
+    fn test_exhausted() {
+        let mut table = PidTable::new();
+        let handles: alloc::vec::Vec<_> = (1..MAX_PIDS).map(|_| table.alloc().unwrap()).collect();
+        assert_eq!(handles.last().unwrap().pid(), MAX_PIDS - 1);
+        assert!(table.alloc().is_none());
+
+        assert!(table.release(handles[500]));
+        assert_eq!(table.alloc().map(|handle| handle.pid()), Some(handles[500].pid()));
+    }
+}
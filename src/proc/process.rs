@@ -6,17 +6,39 @@
 #![allow(dead_code)]
 
 use alloc::string::String;
-use alloc::vec::Vec;
 use crate::arch::proc::process::scheduling;
+use crate::mem::dyn_alloc::AllocTag;
+use crate::proc::pid::ProcHandle;
+use crate::proc::cancel::CancelToken;
 
 /// Holds the size of the stack which will be allocated.
 pub const STACK_SIZE: usize = 0x1000;
 
+/// The pattern which the new stacks are filled with (to find out how much of them was used).
+pub const STACK_PATTERN: u8 = 0xAB;
+
+/// The percentage of the stack usage after which a warning is logged.
+pub const STACK_WARN_PERCENT: usize = 75;
+
 /// Holds the size of the context (from architecture dependent code).
 pub const CONTEXT_SIZE: usize = scheduling::context_size();
 
-/// Maximum size of arguments in bytes.
-const ARGS_MAX: usize = 1024;
+/// Maximum size of arguments in bytes (the command line and the environment together).
+pub const ARGS_MAX: usize = 1024;
+
+/// The magic number at the start of the arguments ("OXAR").
+pub const ARGS_MAGIC: u32 = 0x4F58_4152;
+
+/// The version of the layout of the arguments. It should be increased whenever Args is changed, so
+/// the programs which were built against the old layout refuse them instead of misreading them.
+pub const ARGS_VERSION: u16 = 1;
+
+/// The size of the arguments structure in bytes (it's locked by the assertions below Args).
+pub const ARGS_SIZE: usize = 1048;
+
+/// The type for the main functions of the processes. They're passed their arguments, and return
+/// their exit code (non-zero if they failed).
+pub type MainFn = extern "sysv64" fn(*const Args) -> usize;
 
 /// The current status of the process.
 #[derive(PartialEq, Eq)] 
@@ -25,11 +47,109 @@ pub enum ProcessStatus {
     Exited,                     // Finished execution.
 }
 
+/// How the keyboard input is delivered to a process (see io::keyboard::discipline).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InputMode {
+    Cooked,                     // Complete lines (echoed and edited by the terminal).
+    Raw,                        // Every key right away (without echo).
+}
+
+/// A set of flags which change how a process is treated. They are passed when spawning it, and can
+/// be combined with the | operator.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SpawnFlags(u8);
+
+impl SpawnFlags {
+    /// No special treatment (a regular process).
+    pub const NONE: SpawnFlags = SpawnFlags(0);
+    
+    /// An essential kernel thread which provides a service to the rest of the kernel.
+    pub const KERNEL_SERVICE: SpawnFlags = SpawnFlags(1 << 0);
+    
+    /// The process can't be killed (from the terminal or by it's PID).
+    pub const NO_KILL: SpawnFlags = SpawnFlags(1 << 1);
+    
+    /// The process should be preferred by the scheduler (reserved, it's not used yet).
+    pub const HIGH_PRIORITY: SpawnFlags = SpawnFlags(1 << 2);
+    
+    /// The output of the process is also copied to the kernel log (see debug::klog).
+    pub const LOG_OUTPUT: SpawnFlags = SpawnFlags(1 << 3);
+    
+    /// A method which checks if all the given flags are set.
+    ///
+    /// # Parameters
+    /// `other` : The flags which we're checking.
+    ///
+    /// # Returns
+    /// True if all of them are set, False otherwise.
+    #[inline]
+    pub fn contains(&self, other: SpawnFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+    
+    /// A method which clears the given flags.
+    ///
+    /// # Parameters
+    /// `other` : The flags which we're clearing.
+    #[inline]
+    pub fn remove(&mut self, other: SpawnFlags) {
+        self.0 &= !other.0;
+    }
+}
+
+impl core::ops::BitOr for SpawnFlags {
+    type Output = SpawnFlags;
+    
+    fn bitor(self, other: SpawnFlags) -> SpawnFlags {
+        SpawnFlags(self.0 | other.0)
+    }
+}
+
+/// The limits on the resources which a process can use. They are inherited from the parent when
+/// spawning (unless they are given explicitly).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ResourceLimits {
+    pub max_heap_bytes: usize,      // The maximum bytes allocated with kmalloc_tagged.
+    pub max_children: usize,        // The maximum number of live children.
+}
+
+impl ResourceLimits {
+    /// No limits (used for the kernel threads).
+    pub const UNLIMITED: ResourceLimits = ResourceLimits {
+        max_heap_bytes: usize::MAX,
+        max_children: usize::MAX,
+    };
+    
+    /// The default limits of the programs started from the terminal.
+    pub const DEFAULT: ResourceLimits = ResourceLimits {
+        max_heap_bytes: 0x400000,
+        max_children: 16,
+    };
+}
+
 /// Process control block. Holds all the information about a process.
 pub struct PCB {
     pub pid: usize,                 // The process ID.
+    pub gen: u16,                   // The generation of the PID (see proc::pid).
     pub name: String,               // Name of the process.
+    pub cwd: String,                // The current directory (relative paths start from it).
     pub status: ProcessStatus,      // Current status (Started, Exited, Waiting, etc.).
+    pub flags: SpawnFlags,          // The flags which were passed when it was spawned.
+    pub locks_held: usize,          // The number of mutexes currently held (delays switching).
+    pub cpu_ticks: u64,             // The number of timer ticks it was running for.
+    pub logged_bytes: usize,        // The number of output bytes copied to the kernel log.
+    pub out_credit: usize,          // The bytes it can print before it's throttled (see console).
+    pub out_tick: u64,              // The tick which the output credit was last refilled at.
+    pub exit_code: i32,             // The exit code (non-zero if it failed).
+    pub parent: Option<ProcHandle>, // The process which spawned it (None if the kernel).
+    pub live_children: usize,       // The number of children which were not removed yet.
+    pub heap_bytes: usize,          // The bytes allocated with kmalloc_tagged (and not freed).
+    pub limits: ResourceLimits,     // The limits on the resources it can use.
+    pub input_mode: InputMode,      // How it reads the keyboard input (cooked by default).
+    pub term_requested: bool,       // True if it was asked to exit (for example, on shutdown).
+    pub woken: bool,                // True if a timeout woke it up (see scheduler::take_wakeup).
+    pub cancel: CancelToken,        // Cancelled when it's interrupted or killed (see proc::cancel).
+    pub stuck: bool,                // True if the watchdog caught it with the interrupts disabled.
     pub stack_end: *mut u8,         // The pointer to the process stack end (low addr).
     pub context: *mut u8,           // The pointer to process context.
     pub args: Args,                 // Pointer to the arguments.
@@ -45,26 +165,52 @@ impl PCB {
     /// # Parameters
     /// `pid` : Process ID that is used for this PCB.
     /// `name` : Name of the process used for user identification.
+    /// `flags` : The flags which change how the process is treated.
     /// `prev` : The PCB that is scheduled before this one.
     /// `next` : The PCB that will be scheduled after this one.
     ///
     /// # Returns
     /// A pointer to the allocated process control block.
-    pub unsafe fn alloc(pid: usize, name: &str, 
+    pub unsafe fn alloc(pid: usize, name: &str, flags: SpawnFlags,
         prev: *mut PCB, next: *mut PCB) -> *mut PCB {
         // Allocate memory for a new PCB.
-        let mut pcb: *mut PCB = crate::mem::dyn_alloc::kmalloc(
+        let mut pcb: *mut PCB = crate::mem::dyn_alloc::kmalloc_zeroed(
             core::mem::size_of::<PCB>(), 
             false, true, false) as *mut PCB;
+        crate::mem::dyn_alloc::set_tag(pcb as *mut u8, AllocTag::Pcb);
     
         // Initialize all the fields and allocate memory as needed.
         (*pcb).pid = pid;
+        (*pcb).gen = 0;
         (*pcb).name = String::from(name);
+        (*pcb).cwd = String::from(crate::fs::path::ROOT);
         (*pcb).status = ProcessStatus::Started;
-        (*pcb).stack_end = crate::mem::dyn_alloc::kmalloc(STACK_SIZE, 
+        (*pcb).flags = flags;
+        (*pcb).locks_held = 0;
+        (*pcb).cpu_ticks = 0;
+        (*pcb).logged_bytes = 0;
+        (*pcb).out_credit = crate::console::OUTPUT_BURST;
+        (*pcb).out_tick = crate::time::ticks();
+        (*pcb).exit_code = 0;
+        (*pcb).parent = None;
+        (*pcb).live_children = 0;
+        (*pcb).heap_bytes = 0;
+        (*pcb).limits = ResourceLimits::UNLIMITED;
+        (*pcb).input_mode = InputMode::Cooked;
+        (*pcb).term_requested = false;
+        (*pcb).woken = false;
+        (*pcb).cancel = CancelToken::new();
+        (*pcb).stuck = false;
+        
+        // The stack and the context can't be mapped lazily (a fault on them can't be handled). The
+        // stack has unmapped guard pages, so an overflow faults instead of corrupting the heap.
+        (*pcb).stack_end = crate::mem::dyn_alloc::kmalloc_guarded(STACK_SIZE, 
             false, true, false);
-        (*pcb).context = crate::mem::dyn_alloc::kmalloc(CONTEXT_SIZE, 
+        crate::mem::dyn_alloc::set_tag((*pcb).stack_end, AllocTag::Stack);
+        crate::olibc::memset::memset((*pcb).stack_end, STACK_PATTERN as i32, STACK_SIZE);
+        (*pcb).context = crate::mem::dyn_alloc::kmalloc_zeroed(CONTEXT_SIZE, 
             false, true, false);
+        crate::mem::dyn_alloc::set_tag((*pcb).context, AllocTag::Context);
         (*pcb).args = Args::new();
         (*pcb).prev = prev;
         (*pcb).next = next;
@@ -72,37 +218,94 @@ impl PCB {
         return pcb;
     }
     
+    /// A method which calculates the maximum number of bytes which were ever used in the stack (the
+    /// high-water mark). It scans the whole stack, so it should only be called when needed.
+    ///
+    /// # Returns
+    /// The number of bytes used.
+    pub fn stack_usage(&self) -> usize {
+        unsafe { stack_high_water(self.stack_end, STACK_SIZE) }
+    }
+    
+    /// A simple getter for the handle of the process (it's PID, and the generation of it).
+    ///
+    /// # Returns
+    /// The handle of the process.
+    pub fn handle(&self) -> ProcHandle {
+        ProcHandle::new(self.pid, self.gen)
+    }
+    
     /// Destructor which deletes a given PCB and deallocates the stack and the 
-    /// context as it was initialized previously. 
+    /// context as it was initialized previously. It's called by the scheduler in the timer 
+    /// interrupt, so the frees are deferred until the heap is used outside of it.
     ///
     /// # Parameters
     /// `pcb` : A pointer to the process control block to deallocate.
     pub unsafe fn free(pcb: *mut PCB) {
         // TODO: Find out why freeing causes a problem and avoid leak.
         //crate::mem::dyn_alloc::kfree((*pcb).stack_end);
-        crate::mem::dyn_alloc::kfree((*pcb).context);        
-        crate::mem::dyn_alloc::kfree(pcb as *mut u8);
+        crate::mem::dyn_alloc::kfree_deferred((*pcb).context);
+        crate::mem::dyn_alloc::kfree_deferred(pcb as *mut u8);
+    }
+}
+
+/// A function which finds the high-water mark of a stack which was filled with STACK_PATTERN. It
+/// scans from the low end (the stack grows down) until it finds the first modified byte.
+///
+/// # Parameters
+/// `stack_end` : The lowest address of the stack.
+/// `size` : The size of the stack in bytes.
+///
+/// # Returns
+/// The number of bytes (from the high end) which were used.
+pub unsafe fn stack_high_water(stack_end: *const u8, size: usize) -> usize {
+    let mut untouched: usize = 0;
+    while untouched < size && *stack_end.add(untouched) == STACK_PATTERN {
+        untouched += 1;
     }
+    
+    size - untouched
 }
 
-/// A structure for passing arguments to processes.
+/// A structure for passing arguments to processes. It's copied into the PCBs and passed to the
+/// programs as a raw pointer, so it's layout is part of the ABI of the programs. It starts with a
+/// header (the magic, the version, and the size) which is checked before a program is started, and
+/// the programs read it through Args::parse. The buffer holds the command line (the arguments are
+/// separated by a single space), followed by the environment (NAME=value entries, each one is
+/// terminated by a '\0').
+#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct Args {
+    magic: u32,                     // Always ARGS_MAGIC.
+    version: u16,                   // The version of the layout (ARGS_VERSION).
+    size: u16,                      // The size of the structure (ARGS_SIZE).
     buffer: [u8; ARGS_MAX],         // To hold the characters.
-    len: usize,                     // To hold the number of them.
+    len: usize,                     // The number of characters in the command line.
+    env_len: usize,                 // The number of characters in the environment (after it).
+}
+
+// Lock the layout of the arguments (changing it needs a new ARGS_VERSION).
+const _: () = assert!(core::mem::size_of::<Args>() == ARGS_SIZE);
+const _: () = assert!(core::mem::offset_of!(Args, magic) == 0);
+const _: () = assert!(core::mem::offset_of!(Args, version) == 4);
+const _: () = assert!(core::mem::offset_of!(Args, size) == 6);
+const _: () = assert!(core::mem::offset_of!(Args, buffer) == 8);
+const _: () = assert!(core::mem::offset_of!(Args, len) == 8 + ARGS_MAX);
+const _: () = assert!(core::mem::offset_of!(Args, env_len) == 16 + ARGS_MAX);
+
+/// An enum which represents the reason the arguments of a program were rejected.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArgsError {
+    Null,                       // The pointer is null (or not aligned).
+    BadMagic(u32),              // It does not start with ARGS_MAGIC.
+    VersionMismatch(u16),       // It has a different layout version.
+    BadSize(u16),               // It's size is not ARGS_SIZE.
+    BadLength,                  // The lengths are past the end of the buffer.
+    NotUtf8,                    // The contents are not valid UTF-8.
 }
 
 impl Args {
-    /// A function that returns the list of arguments.
-    ///
-    /// # Returns
-    /// A vector of strings which represent the arguments.
-    pub fn get_args(&self) -> Vec<String> {
-        let to_ret = String::from(core::str::from_utf8(&self.buffer[0..self.len]).unwrap());
-        to_ret.split(' ').map(|st| String::from(st)).collect()
-    }
-    
-    /// A function that saves the list of arguments.
+    /// A function that saves the list of arguments (it's appended to the current ones).
     ///
     /// # Parameters
     /// `args` : All the arguments to be added.
@@ -113,11 +316,234 @@ impl Args {
         }
     }
     
-    /// Default constructor which creates an empty args structure.
+    /// A function that adds a variable to the environment (after the command line, so it should
+    /// be called after set_args).
+    ///
+    /// # Parameters
+    /// `name` : The name of the variable.
+    /// `value` : It's value.
+    pub fn set_env(&mut self, name: &str, value: &str) {
+        for ch in name.bytes().chain(Some(b'=')).chain(value.bytes()).chain(Some(b'\0')) {
+            self.buffer[self.len + self.env_len] = ch;
+            self.env_len += 1;
+        }
+    }
+    
+    /// A method which checks the header and the lengths of the arguments.
+    ///
+    /// # Returns
+    /// Ok if they can be passed to a program, Err with the reason otherwise.
+    pub fn validate(&self) -> Result<(), ArgsError> {
+        if self.magic != ARGS_MAGIC {
+            return Err(ArgsError::BadMagic(self.magic));
+        }
+        
+        if self.version != ARGS_VERSION {
+            return Err(ArgsError::VersionMismatch(self.version));
+        }
+        
+        if self.size as usize != ARGS_SIZE {
+            return Err(ArgsError::BadSize(self.size));
+        }
+        
+        match self.len.checked_add(self.env_len) {
+            Some(total) if total <= ARGS_MAX => Ok(()),
+            _ => Err(ArgsError::BadLength),
+        }
+    }
+    
+    /// A function which checks the arguments which were passed to a program, and returns a view of
+    /// them. The pointer which is passed to main stays valid until the program exits.
+    ///
+    /// # Parameters
+    /// `ptr` : The pointer which was passed to main.
+    ///
+    /// # Returns
+    /// Ok with the view of the arguments, or Err with the reason they were rejected.
+    pub fn parse<'a>(ptr: *const Args) -> Result<ArgView<'a>, ArgsError> {
+        if ptr.is_null() || ptr as usize % core::mem::align_of::<Args>() != 0 {
+            return Err(ArgsError::Null);
+        }
+        
+        let args: &'a Args = unsafe { &*ptr };
+        args.validate()?;
+        
+        let argv = core::str::from_utf8(&args.buffer[..args.len])
+            .map_err(|_| ArgsError::NotUtf8)?;
+        let env = core::str::from_utf8(&args.buffer[args.len..args.len + args.env_len])
+            .map_err(|_| ArgsError::NotUtf8)?;
+        Ok(ArgView { argv, env })
+    }
+    
+    /// Default constructor which creates an empty args structure (with a valid header).
     pub const fn new() -> Self {
         Self {
+           magic: ARGS_MAGIC,
+           version: ARGS_VERSION,
+           size: ARGS_SIZE as u16,
            buffer: [0; ARGS_MAX],
            len: 0, 
+           env_len: 0,
         }
     }
 }
+
+/// A structure which is the checked view of the arguments of a program.
+#[derive(Copy, Clone)]
+pub struct ArgView<'a> {
+    argv: &'a str,                  // The command line.
+    env: &'a str,                   // The environment entries.
+}
+
+impl<'a> ArgView<'a> {
+    /// A method which returns an iterator over the arguments (the first one is the program).
+    ///
+    /// # Returns
+    /// The iterator over the arguments.
+    pub fn argv(&self) -> Argv<'a> {
+        Argv { inner: self.argv.split(' ') }
+    }
+    
+    /// A method which returns an iterator over the environment variables.
+    ///
+    /// # Returns
+    /// The iterator over the (name, value) pairs.
+    pub fn env(&self) -> Env<'a> {
+        Env { inner: self.env.split_terminator('\0') }
+    }
+    
+    /// A method which finds the value of an environment variable.
+    ///
+    /// # Parameters
+    /// `name` : The name of the variable.
+    ///
+    /// # Returns
+    /// Some with it's value, None if it's not set.
+    pub fn var(&self, name: &str) -> Option<&'a str> {
+        self.env().find(|(var, _)| *var == name).map(|(_, value)| value)
+    }
+}
+
+/// An iterator over the arguments of a program (the empty ones are skipped).
+#[derive(Clone)]
+pub struct Argv<'a> {
+    inner: core::str::Split<'a, char>,
+}
+
+impl<'a> Iterator for Argv<'a> {
+    type Item = &'a str;
+    
+    fn next(&mut self) -> Option<&'a str> {
+        self.inner.by_ref().find(|arg| !arg.is_empty())
+    }
+}
+
+/// An iterator over the environment variables of a program, as (name, value) pairs.
+#[derive(Clone)]
+pub struct Env<'a> {
+    inner: core::str::SplitTerminator<'a, char>,
+}
+
+impl<'a> Iterator for Env<'a> {
+    type Item = (&'a str, &'a str);
+    
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.next().map(|entry| match entry.find('=') {
+            Some(idx) => (&entry[..idx], &entry[idx + 1..]),
+            None => (entry, ""),
+        })
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use alloc::vec::Vec;
+    use super::{Args, ArgsError, ARGS_MAGIC, ARGS_SIZE, ARGS_VERSION, STACK_PATTERN};
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_high_water();
+        test_args_header();
+        test_args_rejected();
+        test_args_iterators();
+    }
+
+    /// Check the high-water mark on a synthetic stack.
+    fn test_high_water() {
+        let mut stack: [u8; 256] = [STACK_PATTERN; 256];
+        unsafe {
+            assert_eq!(super::stack_high_water(stack.as_ptr(), stack.len()), 0);
+            
+            // The deepest modified byte determines the usage.
+            stack[200] = 0;
+            stack[100] = 0;
+            assert_eq!(super::stack_high_water(stack.as_ptr(), stack.len()), 156);
+            
+            stack[0] = 0;
+            assert_eq!(super::stack_high_water(stack.as_ptr(), stack.len()), 256);
+        }
+    }
+    
+    /// Make sure the new arguments have a valid header (the layout itself is locked at compile
+    /// time by the assertions below Args).
+    fn test_args_header() {
+        let args = Args::new();
+        assert_eq!((args.magic, args.version, args.size as usize),
+            (ARGS_MAGIC, ARGS_VERSION, ARGS_SIZE));
+        assert_eq!(core::mem::size_of::<Args>(), ARGS_SIZE);
+        assert_eq!(args.validate(), Ok(()));
+    }
+    
+    /// Break the header (and the lengths) of the arguments, and make sure they're rejected.
+    fn test_args_rejected() {
+        assert_eq!(Args::parse(core::ptr::null()).err(), Some(ArgsError::Null));
+        
+        let mut args = Args::new();
+        args.version = ARGS_VERSION + 1;
+        assert_eq!(Args::parse(&args).err(), Some(ArgsError::VersionMismatch(ARGS_VERSION + 1)));
+        
+        let mut args = Args::new();
+        args.magic = 0;
+        assert_eq!(Args::parse(&args).err(), Some(ArgsError::BadMagic(0)));
+        
+        let mut args = Args::new();
+        args.size -= 8;
+        assert_eq!(Args::parse(&args).err(), Some(ArgsError::BadSize(ARGS_SIZE as u16 - 8)));
+        
+        let mut args = Args::new();
+        args.len = super::ARGS_MAX;
+        args.env_len = 1;
+        assert_eq!(Args::parse(&args).err(), Some(ArgsError::BadLength));
+        
+        let mut args = Args::new();
+        args.set_args("echo ");
+        args.buffer[args.len] = 0xFF;
+        args.len += 1;
+        assert_eq!(Args::parse(&args).err(), Some(ArgsError::NotUtf8));
+    }
+    
+    /// Parse a command line with an environment, and go over both of them.
+    fn test_args_iterators() {
+        let mut args = Args::new();
+        args.set_args("echo  hello world");
+        args.set_env("PWD", "/initrd");
+        args.set_env("EMPTY", "");
+        args.set_env("EQ", "a=b");
+        
+        let view = Args::parse(&args).unwrap();
+        assert_eq!(view.argv().collect::<Vec<&str>>(), ["echo", "hello", "world"]);
+        assert_eq!(view.env().collect::<Vec<(&str, &str)>>(),
+            [("PWD", "/initrd"), ("EMPTY", ""), ("EQ", "a=b")]);
+        assert_eq!(view.var("EQ"), Some("a=b"));
+        assert_eq!(view.var("HOME"), None);
+        
+        let empty = Args::new();
+        let view = Args::parse(&empty).unwrap();
+        assert_eq!((view.argv().count(), view.env().count()), (0, 0));
+    }
+}
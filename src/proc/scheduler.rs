@@ -7,16 +7,20 @@
 #![allow(dead_code)]
 
 use crate::arch::proc::process::scheduling;
+use crate::proc::pid::{PidTable, ProcHandle};
 use crate::proc::process::*;
+use crate::proc::seqlock::SeqLock;
+use alloc::string::String;
 
-/// Holds the current process which is linked to the rest of processes.
-pub static mut PROC: *mut PCB = core::ptr::null_mut();
+/// Holds the current process which is linked to the rest of processes. It should only be accessed
+/// through with_current (and the other accessors) outside of this module.
+static mut PROC: *mut PCB = core::ptr::null_mut();
 
 /// Process ID used for the IDLE process.
-const IDLE_PID: usize = 0;
+const IDLE_PID: usize = crate::proc::pid::IDLE_PID;
 
-/// Holds the PID which will be assigned to the next task.
-static mut CURR_PID: usize = 1;
+/// Holds the PIDs which are used (a PID is freed when it's process is removed, and reused later).
+static mut PIDS: PidTable = PidTable::new();
 
 /// Holds how many "ticks" each task runs for.
 const MAX_TICKS: usize = 10;
@@ -24,6 +28,34 @@ const MAX_TICKS: usize = 10;
 /// Holds the current tick.
 static mut CURR_TICK: usize = 0;
 
+/// Holds the maximum number of extra ticks given to a process which is holding a mutex.
+const MAX_DEFERRED_TICKS: usize = 4;
+
+/// Holds the number of extra ticks which were given to the current process.
+static mut DEFERRED_TICKS: usize = 0;
+
+/// True if a switch was due while the timer was nested in another handler (see exit_handler).
+static mut SWITCH_PENDING: bool = false;
+
+/// A structure which represents the aggregate counters of the scheduler.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SchedCounters {
+    pub switches: usize,                       // The number of context switches.
+    pub lock_overruns: usize,                  // Switched away while still holding a mutex.
+    pub reaped: usize,                         // The number of exited processes which were removed.
+    pub deferred: usize,                       // Switches which waited for a nested handler.
+}
+
+/// Holds the aggregate counters (they are only written by schedule, in the timer interrupt).
+static COUNTERS: SeqLock<SchedCounters> = SeqLock::new(SchedCounters { switches: 0, 
+    lock_overruns: 0, reaped: 0, deferred: 0 });
+
+/// The exit code of the processes which panicked.
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// Holds the PID and exit code of the last process which exited with a non-zero code.
+static mut LAST_FAILURE: Option<(usize, i32)> = None;
+
 /// The high level scheduling algorithm which is called by the architecture 
 /// dependent code to schedule the next task. It checks if it's time to context 
 /// switch, and if it is, it gets the context of the next task, and replaces 
@@ -47,10 +79,34 @@ pub unsafe fn schedule(context: *mut u8) {
     if CURR_TICK < MAX_TICKS {
         CURR_TICK += 1;
         return;
-    } else {
-        CURR_TICK = 0;
     }
     
+    // The timer interrupted another handler (on the same stack), switching now would resume that 
+    // handler on the next process. The outermost handler does the switch when it returns.
+    if crate::arch::interrupts::handlers::int_depth() > 1 {
+        if !SWITCH_PENDING {
+            SWITCH_PENDING = true;
+            COUNTERS.write(|counters| counters.deferred += 1);
+        }
+        return;
+    }
+    
+    // If it's holding a mutex, give it an extra tick to release it (so the others don't spin on 
+    // it with the interrupts disabled). If it keeps it for too long, switch anyway.
+    if (*PROC).status == ProcessStatus::Started && (*PROC).locks_held > 0 {
+        if DEFERRED_TICKS < MAX_DEFERRED_TICKS {
+            DEFERRED_TICKS += 1;
+            return;
+        }
+        
+        // The console might be held, so report it later.
+        COUNTERS.write(|counters| counters.lock_overruns += 1);
+        let _ = crate::proc::workqueue::queue_work(report_lock_overrun, (*PROC).pid);
+    }
+    
+    CURR_TICK = 0;
+    DEFERRED_TICKS = 0;
+    
     // Check it's current status.
     match (*PROC).status {
         // If it has already started.
@@ -60,14 +116,41 @@ pub unsafe fn schedule(context: *mut u8) {
             
             // Go to the next process.
             PROC = (*PROC).next;
+            COUNTERS.write(|counters| counters.switches += 1);
             
             // Set the context of CPU to the current context.
             scheduling::set_context(context, (*PROC).context);
+            
+            // If it was killed while it was not running, remove it right away.
+            if (*PROC).status == ProcessStatus::Exited {
+                CURR_TICK = MAX_TICKS;
+                schedule(context);
+            }
         },
         
         // If the process has finished execution, remove it and shedule the next process.
         ProcessStatus::Exited => { 
-            oxid_log!("Removed process PID={} from the scheduler.", (*PROC).pid);
+            let stack_used = (*PROC).stack_usage();
+            warn_stack_usage((*PROC).pid, stack_used);
+            oxid_log!("Removed process PID={} from the scheduler. code={} stack={}/{}", (*PROC).pid, 
+                (*PROC).exit_code, stack_used, STACK_SIZE);
+            
+            COUNTERS.write(|counters| counters.reaped += 1);
+            
+            // Remember the failures (so they can be reported).
+            if (*PROC).exit_code != 0 {
+                LAST_FAILURE = Some(((*PROC).pid, (*PROC).exit_code));
+            }
+            
+            // The parent can spawn another child (if it's still the same process).
+            if let Some(parent) = (*PROC).parent {
+                with_handle_mut(parent, |pcb: &mut PCB| {
+                    pcb.live_children = pcb.live_children.saturating_sub(1)
+                });
+            }
+            
+            // The PID can be given out again (the handles of this process become stale).
+            PIDS.release((*PROC).handle());
             
             crate::olibc::memcpy::memcpy((*PROC).context, context, CONTEXT_SIZE);
             
@@ -95,6 +178,70 @@ pub unsafe fn schedule(context: *mut u8) {
    
 }
 
+/// A simple getter which checks if a switch is waiting for the outermost handler to return.
+///
+/// # Returns
+/// True if it's pending, False otherwise.
+pub fn switch_pending() -> bool {
+    unsafe { SWITCH_PENDING }
+}
+
+/// A function which does the switch which was deferred by schedule. It's called by the outermost
+/// interrupt handler right before it returns (with the interrupts disabled).
+///
+/// # Parameters
+/// `context` : The context which is restored when the handler returns.
+pub unsafe fn schedule_pending(context: *mut u8) {
+    SWITCH_PENDING = false;
+    CURR_TICK = MAX_TICKS;
+    schedule(context);
+}
+
+/// A function which is called on every timer tick (before schedule), and counts the tick for the 
+/// process which was running (for the CPU usage statistics).
+#[inline]
+pub unsafe fn account_tick() {
+    with_current(|pcb: &mut PCB| pcb.cpu_ticks += 1);
+}
+
+/// A function which is called by the mutexes when they are locked. It counts the locks held by 
+/// the current process.
+#[inline]
+pub fn lock_acquired() {
+    // The mutexes are used before the scheduler is initialized (then it's simply ignored).
+    with_current(|pcb: &mut PCB| pcb.locks_held += 1);
+}
+
+/// A function which is called by the mutexes when they are unlocked.
+#[inline]
+pub fn lock_released() {
+    with_current(|pcb: &mut PCB| pcb.locks_held = pcb.locks_held.saturating_sub(1));
+}
+
+/// A simple getter for the number of times a process was switched away while holding a mutex.
+///
+/// # Returns
+/// The number of overruns.
+pub fn lock_overruns() -> usize {
+    COUNTERS.read().lock_overruns
+}
+
+/// A function which reads all the aggregate counters at once (without blocking).
+///
+/// # Returns
+/// A consistent copy of the counters.
+pub fn counters() -> SchedCounters {
+    COUNTERS.read()
+}
+
+/// A function which logs that a process held a mutex for too long. It is run from the work queue.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+fn report_lock_overrun(pid: usize) {
+    oxid_warn!("Process PID={} held a mutex for more than {} extra ticks, switched away anyway.",
+        pid, MAX_DEFERRED_TICKS);
+}
 
 /// A function which spawns a new process with a certain starting point, and name. 
 /// It creates a new process control blocks, and adds it at the end of scheduled
@@ -104,20 +251,115 @@ pub unsafe fn schedule(context: *mut u8) {
 /// `starting_ponit`: The function which will be called when executing.
 /// `args` : The command line arguments passed.
 /// `proc_name` : The name of the process.
-pub unsafe fn spawn(starting_point: extern "sysv64" fn(*const Args), args: *mut Args
+pub unsafe fn spawn(starting_point: MainFn, args: *mut Args
     , proc_name: &str) {
-    oxid_log!("Spawning a new process. PID={}", CURR_PID);
+    spawn_with_flags(starting_point, args, proc_name, SpawnFlags::NONE);
+}
+
+/// A function which spawns a new process with the given flags. It is used for the kernel threads
+/// which should be treated differently (for example, essential services which can't be killed).
+///
+/// # Parameters
+/// `starting_ponit`: The function which will be called when executing.
+/// `args` : The command line arguments passed.
+/// `proc_name` : The name of the process.
+/// `flags` : The flags which change how the process is treated.
+///
+/// # Returns
+/// The handle of the new process.
+pub unsafe fn spawn_with_flags(starting_point: MainFn, args: *mut Args
+    , proc_name: &str, flags: SpawnFlags) -> ProcHandle {
+    try_spawn(starting_point, args, proc_name, flags, None)
+        .expect("Could not spawn the process (too many children, or no free PIDs).")
+}
+
+/// An enum which represents the reason spawning a process failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SpawnError {
+    TooManyChildren,    // The parent already has max_children live children.
+    NoPids,             // All the PIDs are used.
+    BadArgs(ArgsError), // The arguments have an invalid header (see Args::validate).
+}
+
+/// A function which spawns a new process with the given flags and resource limits. If it's called
+/// by a process (with the interrupts enabled), that process becomes the parent, so it's children
+/// limit is enforced. Otherwise it's spawned by the kernel (for example, the terminal which runs 
+/// in the keyboard's bottom half with the interrupts disabled).
+///
+/// # Parameters
+/// `starting_ponit`: The function which will be called when executing.
+/// `args` : The command line arguments passed.
+/// `proc_name` : The name of the process.
+/// `flags` : The flags which change how the process is treated.
+/// `limits` : The resource limits (inherited from the parent if None).
+///
+/// # Returns
+/// The handle of the new process, or Err if the parent has too many children (or there are no
+/// free PIDs, or the arguments are invalid).
+pub unsafe fn try_spawn(starting_point: MainFn, args: *mut Args
+    , proc_name: &str, flags: SpawnFlags, limits: Option<ResourceLimits>) 
+    -> Result<ProcHandle, SpawnError> {
+    // Refuse to start a program with arguments it can't read (built with a different layout).
+    if let Err(error) = Args::parse(args) {
+        oxid_warn!("Could not spawn {}, it's arguments are invalid ({:?}).", proc_name, error);
+        return Err(SpawnError::BadArgs(error));
+    }
+    
+    let parent = if crate::arch::interrupts::are_enabled() { current_handle() } else { None };
+    
+    // Count the child in the parent (if there is space), and find the limits which are inherited.
+    let inherited = match parent {
+        Some(parent) => {
+            let reserved = with_current(|pcb: &mut PCB| {
+                if pcb.live_children >= pcb.limits.max_children {
+                    return Err(SpawnError::TooManyChildren);
+                }
+                
+                pcb.live_children += 1;
+                Ok(pcb.limits)
+            }).unwrap_or(Ok(ResourceLimits::UNLIMITED));
+            
+            if reserved.is_err() {
+                oxid_warn!("Process PID={} can't spawn more than it's limit of children.", 
+                    parent.pid());
+            }
+            reserved?
+        },
+        None => ResourceLimits::UNLIMITED,
+    };
+    
+    // Reserve a PID for it (and give back the parent's reservation if there are none left).
+    let were_enabled = crate::arch::interrupts::save_and_disable();
+    let handle = PIDS.alloc();
+    crate::arch::interrupts::restore(were_enabled);
     
-    // Create a new PCB and put it at the end of the linked list.
-    let new_pcb: *mut PCB = PCB::alloc(CURR_PID, proc_name, 
-        (*PROC).prev, PROC);
+    let handle = match handle {
+        Some(handle) => handle,
+        None => {
+            if let Some(parent) = parent {
+                with_handle_mut(parent, |pcb: &mut PCB| {
+                    pcb.live_children = pcb.live_children.saturating_sub(1)
+                });
+            }
+            
+            oxid_warn!("Could not spawn {}, all the PIDs are used.", proc_name);
+            return Err(SpawnError::NoPids);
+        },
+    };
+    
+    oxid_log!("Spawning a new process. PID={} gen={} ({})", handle.pid(), handle.gen(), 
+        proc_name);
+    
+    // Create a new PCB (it's linked after it's initialized).
+    let new_pcb: *mut PCB = PCB::alloc(handle.pid(), proc_name, flags, 
+        core::ptr::null_mut(), core::ptr::null_mut());
+    (*new_pcb).gen = handle.gen();
         
-    // Copy the arguments to it.
+    // Copy the arguments to it, and start in the current directory of the parent.
     (*new_pcb).args = *args;
-        
-    // Add the PCB at the end of list right before the current process.
-    (*(*PROC).prev).next = new_pcb;
-    (*PROC).prev = new_pcb;
+    (*new_pcb).cwd = current_cwd();
+    (*new_pcb).parent = parent;
+    (*new_pcb).limits = limits.unwrap_or(inherited);
     
     // Calculate the pointer stack start address (high-address).
     let stack_start = (((*new_pcb).stack_end as usize) + STACK_SIZE) as *mut u8;
@@ -125,16 +367,24 @@ pub unsafe fn spawn(starting_point: extern "sysv64" fn(*const Args), args: *mut
     // Initialize the stack and starting point.
     scheduling::init_context(starting_point, exit, stack_start, 
         (*new_pcb).context, &(*new_pcb).args);
+        
+    // Add the PCB at the end of list right before the current process.
+    let were_enabled = crate::arch::interrupts::save_and_disable();
+    (*new_pcb).prev = (*PROC).prev;
+    (*new_pcb).next = PROC;
+    (*(*PROC).prev).next = new_pcb;
+    (*PROC).prev = new_pcb;
+    crate::arch::interrupts::restore(were_enabled);
     
-    // Increase the PID for the new process.
-    CURR_PID += 1;
+    Ok(handle)
 }
 
 /// A function which initializes the scheduler by creating an adle process idle process.
 /// and storing it.
 pub unsafe fn init() {
     // Create an idle process and make it self referencing (for now).    
-    PROC = PCB::alloc(IDLE_PID, "IDLE", 0x0 as *mut PCB, 0x0 as *mut PCB);
+    PROC = PCB::alloc(IDLE_PID, "IDLE", SpawnFlags::KERNEL_SERVICE | SpawnFlags::NO_KILL,
+        0x0 as *mut PCB, 0x0 as *mut PCB);
     
     // Reference itself.
     (*PROC).prev = PROC;
@@ -149,40 +399,1130 @@ pub unsafe fn init() {
 }
 
 /// A function which sets the status of the currently running process to exited. This should be 
-/// called at the end of a processes execution timeline. However it is automatically called, so 
-/// no explicit call is needed.
-pub fn exit() {
+/// called at the end of a processes execution timeline. However it is automatically called (with
+/// the value which the main function returned), so no explicit call is needed.
+///
+/// # Parameters
+/// `code` : The exit code which was returned (non-zero if the process failed).
+pub extern "sysv64" fn exit(code: usize) {
     oxid_log!("Exiting Process.");
-    unsafe {
-        // If we're not in the IDLE process, set the status to exited.
-        if (*PROC).pid != IDLE_PID {
-            (*PROC).status = ProcessStatus::Exited;
+    exit_with_code(code as i32);
+}
+
+/// A function which sets the status of the currently running process to exited with a given exit
+/// code, and waits for the scheduler to remove it (on the next tick). It should not be called from
+/// a hardware interrupt handler (see mark_exited).
+///
+/// # Parameters
+/// `code` : The exit code (non-zero if the process failed).
+pub fn exit_with_code(code: i32) {
+    mark_exited(code);
+    
+    // Yield until it's removed.
+    loop {
+        unsafe {
+            crate::arch::interrupts::enable();
+            crate::arch::proc::wait_for_interrupt();
+        }
+    }
+}
+
+/// A function which sets the status of the currently running process to exited, and makes sure
+/// the scheduler switches away from it on the next tick. It returns right away, so it can be used
+/// from the interrupt handlers.
+///
+/// # Parameters
+/// `code` : The exit code (non-zero if the process failed).
+fn mark_exited(code: i32) {
+    // If we're not in the IDLE process, set the status to exited.
+    with_current(|pcb: &mut PCB| {
+        if pcb.pid != IDLE_PID {
+            pcb.exit_code = code;
+            pcb.status = ProcessStatus::Exited;
+            unsafe { CURR_TICK = MAX_TICKS; }
         }
+    });
+}
+
+/// A function which checks if a failure (such as a panic) of the current process can be recovered 
+/// from by removing it. The IDLE process and the kernel services are essential, so they can't be.
+///
+/// # Returns
+/// True if the current process can be removed, False if the whole system should stop.
+pub fn can_recover_current() -> bool {
+    with_current(|pcb: &mut PCB| is_recoverable(pcb)).unwrap_or(false)
+}
+
+/// A helper which checks if a failure of a given process can be recovered from.
+///
+/// # Parameters
+/// `pcb` : The process control block of the process.
+///
+/// # Returns
+/// True if it's not the IDLE process or a kernel service, False otherwise.
+fn is_recoverable(pcb: &PCB) -> bool {
+    pcb.pid != IDLE_PID && !pcb.flags.contains(SpawnFlags::KERNEL_SERVICE)
+}
+
+/// A function which calls a given function with the process control block of the running process. 
+/// The function is called while the interrupts are disabled, so it should not allocate memory or 
+/// print.
+///
+/// # Parameters
+/// `func` : The function which is called with the process control block.
+///
+/// # Returns
+/// Some with the function's result, None if the scheduler is not initialized yet.
+pub fn with_current<R>(func: impl FnOnce(&mut PCB) -> R) -> Option<R> {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let result = PROC.as_mut().map(func);
+        crate::arch::interrupts::restore(were_enabled);
         
-        // TODO: Better handling of EOI for when process hangs. 
-        crate::arch::io::end_of_interrupt(); 
+        result
+    }
+}
+
+/// A function which flags the running process as stuck (it's called by the watchdog, in the NMI).
+///
+/// # Returns
+/// Some((pid, stack_start, recoverable)), or None if the scheduler is not initialized yet.
+pub fn flag_stuck() -> Option<(usize, *mut u8, bool)> {
+    with_current(|pcb: &mut PCB| {
+        pcb.stuck = true;
+        (pcb.pid, ((pcb.stack_end as usize) + STACK_SIZE) as *mut u8, is_recoverable(pcb))
+    })
+}
+
+/// A simple getter for the PID of the running process.
+///
+/// # Returns
+/// Some(pid), or None if the scheduler is not initialized yet.
+pub fn current_pid() -> Option<usize> {
+    with_current(|pcb: &mut PCB| pcb.pid)
+}
+
+/// A simple getter for the handle of the running process.
+///
+/// # Returns
+/// Some(handle), or None if the scheduler is not initialized yet.
+pub fn current_handle() -> Option<ProcHandle> {
+    with_current(|pcb: &mut PCB| pcb.handle())
+}
+
+/// A function which finds the handle of the process which currently has a PID (for example, when
+/// the PID was typed by the user). The handle should be kept instead of the PID, since the PID can
+/// be given to another process once this one is removed.
+///
+/// # Parameters
+/// `pid` : The process ID.
+///
+/// # Returns
+/// Some(handle) if a process has the PID, None otherwise.
+pub fn handle_of(pid: usize) -> Option<ProcHandle> {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let handle = PIDS.current(pid);
+        crate::arch::interrupts::restore(were_enabled);
         
-        // Put the system in low power mode for now.
-        crate::arch::proc::halt();
+        handle
     }
 }
 
-/// A function which kills the currently running process.
+/// A simple getter for the name of the running process.
+///
+/// # Returns
+/// Some(name), or None if the scheduler is not initialized yet.
+pub fn current_name() -> Option<String> {
+    // The name never changes after spawning, so it can be copied without disabling the interrupts.
+    unsafe { PROC.as_ref().map(|pcb: &PCB| pcb.name.clone()) }
+}
+
+/// A simple getter for the arguments of the running process.
+///
+/// # Returns
+/// Some(args), or None if the scheduler is not initialized yet.
+pub fn current_args() -> Option<Args> {
+    with_current(|pcb: &mut PCB| pcb.args)
+}
+
+/// A simple getter for the last process which exited with a non-zero exit code.
+///
+/// # Returns
+/// Some((pid, code)) if a process failed, None otherwise.
+pub fn last_failure() -> Option<(usize, i32)> {
+    unsafe { LAST_FAILURE }
+}
+
+/// An enum which represents the result of killing a process.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KillResult {
+    Killed,             // When the process was killed.
+    NotFound,           // When there is no process with the given PID.
+    NotKillable,        // When the process was spawned with the NO_KILL flag.
+}
+
+/// A function which kills the currently running process. It can be called from the interrupt 
+/// handlers, so the process is only marked (and it's removed on the next tick).
 pub fn kill() {
-    unsafe {
-        // If we're not in the IDLE process, set the status to exited.
-        if (*PROC).pid != IDLE_PID {
-            oxid_warn!("Killing Process PID={}", (*PROC).pid);
-            exit();
+    let (pid, killable) = match with_current(|pcb: &mut PCB| (pcb.pid, 
+        !pcb.flags.contains(SpawnFlags::NO_KILL))) {
+        Some(current) => current,
+        None => return,
+    };
+    
+    // If we're not in the IDLE process, set the status to exited.
+    if pid == IDLE_PID {
+        oxid_err!("No process to kill.")
+    } else if !killable {
+        oxid_err!("Process PID={} ({}) is a kernel service and can't be killed.", pid,
+            current_name().unwrap_or_default());
+    } else {
+        oxid_warn!("Killing Process PID={}", pid);
+        mark_exited(0);
+    }
+}
+
+/// A function which kills a process by it's handle. The process is removed by the scheduler before
+/// it runs again. Processes which were spawned with the NO_KILL flag are never killed. A stale 
+/// handle (of a process which was already removed) is never applied to the process which got the
+/// same PID later.
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+///
+/// # Returns
+/// The result of the operation (Killed, NotFound, or NotKillable).
+pub fn kill_pid(handle: ProcHandle) -> KillResult {
+    let result = with_handle_mut(handle, |pcb: &mut PCB| {
+        if pcb.flags.contains(SpawnFlags::NO_KILL) {
+            KillResult::NotKillable
         } else {
-            oxid_err!("No process to kill.")
+            pcb.cancel.cancel();
+            pcb.status = ProcessStatus::Exited;
+            KillResult::Killed
+        }
+    }).unwrap_or(KillResult::NotFound);
+    
+    if result == KillResult::Killed {
+        oxid_warn!("Killing Process PID={}", handle.pid());
+    }
+    
+    result
+}
+
+/// A function which asks a process to exit (cooperatively). The process should check it with 
+/// termination_requested, and exit on it's own. It can be called from the interrupt handlers.
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+///
+/// # Returns
+/// True if the process was found, False otherwise (or if the handle is stale).
+pub fn request_termination(handle: ProcHandle) -> bool {
+    with_handle_mut(handle, |pcb: &mut PCB| pcb.term_requested = true).is_some()
+}
+
+/// A function which cancels the long operations of a process (see proc::cancel). It's used when
+/// the process is interrupted (Ctrl+C), and it can be called from the interrupt handlers.
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+///
+/// # Returns
+/// True if the process was found, False otherwise (or if the handle is stale).
+pub fn cancel(handle: ProcHandle) -> bool {
+    with_handle_mut(handle, |pcb: &mut PCB| pcb.cancel.cancel()).is_some()
+}
+
+/// A function which checks if the running process was asked to exit (see request_termination).
+///
+/// # Returns
+/// True if it should exit, False otherwise.
+pub fn termination_requested() -> bool {
+    with_current(|pcb: &mut PCB| pcb.term_requested).unwrap_or(false)
+}
+
+/// A function which clears the termination request of the running process. It's used when the
+/// process keeps running after the code which was asked to exit returns (such as the foreground 
+/// programs in the kworker thread).
+pub fn clear_termination_request() {
+    with_current(|pcb: &mut PCB| pcb.term_requested = false);
+}
+
+/// A function which wakes up a process (it's wakeup flag is set, see take_wakeup). It's used by the
+/// timeouts (see time::timeout), so it can be called from the interrupt handlers.
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+///
+/// # Returns
+/// True if it was woken up, False if the handle is stale.
+pub fn wake(handle: ProcHandle) -> bool {
+    with_handle_mut(handle, |pcb: &mut PCB| pcb.woken = true).is_some()
+}
+
+/// A function which checks if the running process was woken up (see wake), and clears it.
+///
+/// # Returns
+/// True if it was woken up since the last call, False otherwise.
+pub fn take_wakeup() -> bool {
+    with_current(|pcb: &mut PCB| core::mem::replace(&mut pcb.woken, false)).unwrap_or(false)
+}
+
+/// A test hook which clears some of the flags of a process (so it can be killed by the tests).
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+/// `flags` : The flags which are cleared.
+///
+/// # Returns
+/// Ok if the process was found, Err otherwise.
+#[cfg(feature = "unit-test")]
+pub fn clear_flags(handle: ProcHandle, flags: SpawnFlags) -> Result<(), ()> {
+    with_handle_mut(handle, |pcb: &mut PCB| pcb.flags.remove(flags)).ok_or(())
+}
+
+/// A function which calls a given function for every process in the scheduler (starting from the
+/// current one). The interrupts are disabled while going through the processes, so the function
+/// should not allocate memory or print (since the mutexes enable the interrupts).
+///
+/// # Parameters
+/// `func` : The function which is called with each process control block.
+pub fn for_each(func: &mut dyn FnMut(&PCB)) {
+    unsafe {
+        // Make sure the list doesn't change while we're going through it.
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        
+        let mut curr: *mut PCB = PROC;
+        loop {
+            func(&*curr);
+            curr = (*curr).next;
+            
+            if curr == PROC {
+                break;
+            }
         }
+        
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which finds a process with a given PID and calls a function with it. The function
+/// is called while the interrupts are disabled, so it should not allocate memory or print.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+/// `func` : The function which is called with the process control block.
+///
+/// # Returns
+/// Some with the function's result if the process exists, None otherwise.
+pub fn find<R>(pid: usize, func: impl FnOnce(&PCB) -> R) -> Option<R> {
+    with_pcb_mut(pid, |pcb: &mut PCB| func(pcb))
+}
+
+/// A function which finds a process by it's handle and calls a function with it. The function is
+/// called while the interrupts are disabled, so it should not allocate memory or print.
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+/// `func` : The function which is called with the process control block.
+///
+/// # Returns
+/// Some with the function's result if the process exists, None otherwise (or if it's stale).
+pub fn find_handle<R>(handle: ProcHandle, func: impl FnOnce(&PCB) -> R) -> Option<R> {
+    with_handle_mut(handle, |pcb: &mut PCB| func(pcb))
+}
+
+/// A helper which finds a process by it's handle and calls a function which can modify it. The 
+/// function is only called if the generation matches (the handle is not stale).
+///
+/// # Parameters
+/// `handle` : The handle of the process.
+/// `func` : The function which is called with the process control block.
+///
+/// # Returns
+/// Some with the function's result if the process exists, None otherwise.
+fn with_handle_mut<R>(handle: ProcHandle, func: impl FnOnce(&mut PCB) -> R) -> Option<R> {
+    with_pcb_mut(handle.pid(), |pcb: &mut PCB| {
+        if pcb.gen == handle.gen() { Some(func(pcb)) } else { None }
+    }).flatten()
+}
+
+/// A helper which finds a process with a given PID and calls a function which can modify it. The
+/// function is called while the interrupts are disabled.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+/// `func` : The function which is called with the process control block.
+///
+/// # Returns
+/// Some with the function's result if the process exists, None otherwise.
+fn with_pcb_mut<R>(pid: usize, func: impl FnOnce(&mut PCB) -> R) -> Option<R> {
+    unsafe {
+        // Make sure the list doesn't change while we're going through it.
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        
+        let mut result: Option<R> = None;
+        let mut curr: *mut PCB = PROC;
+        loop {
+            if (*curr).pid == pid {
+                result = Some(func(&mut *curr));
+                break;
+            }
+            
+            curr = (*curr).next;
+            if curr == PROC {
+                break;
+            }
+        }
+        
+        crate::arch::interrupts::restore(were_enabled);
+        result
+    }
+}
+
+/// A function which charges a heap allocation to the running process (see kmalloc_tagged).
+///
+/// # Parameters
+/// `size` : The number of bytes which are allocated.
+///
+/// # Returns
+/// Ok if it's within the process' max_heap_bytes limit (or there is no process), Err otherwise.
+pub fn charge_heap(size: usize) -> Result<(), ()> {
+    with_current(|pcb: &mut PCB| {
+        match pcb.heap_bytes.checked_add(size) {
+            Some(total) if total <= pcb.limits.max_heap_bytes => {
+                pcb.heap_bytes = total;
+                Ok(())
+            },
+            _ => Err(()),
+        }
+    }).unwrap_or(Ok(()))
+}
+
+/// A function which gives back the heap bytes which were charged to the running process.
+///
+/// # Parameters
+/// `size` : The number of bytes which were freed.
+pub fn release_heap(size: usize) {
+    with_current(|pcb: &mut PCB| pcb.heap_bytes = pcb.heap_bytes.saturating_sub(size));
+}
+
+/// A function which checks if the output of the running process should be copied to the kernel log.
+///
+/// # Returns
+/// True if it was spawned with the LOG_OUTPUT flag, False otherwise.
+pub fn current_logs_output() -> bool {
+    with_current(|pcb: &mut PCB| pcb.flags.contains(SpawnFlags::LOG_OUTPUT)).unwrap_or(false)
+}
+
+/// A function which takes a part of the running process' kernel log budget (each process can only
+/// log up to klog::PROC_LOG_LIMIT bytes).
+///
+/// # Parameters
+/// `len` : The number of bytes which we want to log.
+///
+/// # Returns
+/// Some((pid, granted)) with the number of bytes which can be logged, None if it's used up.
+pub fn take_log_budget(len: usize) -> Option<(usize, usize)> {
+    with_current(|pcb: &mut PCB| {
+        let left = crate::debug::klog::PROC_LOG_LIMIT.saturating_sub(pcb.logged_bytes);
+        let granted = len.min(left);
+        pcb.logged_bytes += granted;
+        (pcb.pid, granted)
+    }).filter(|(_, granted)| *granted > 0)
+}
+
+/// A function which refills the output credit of the running process (by OUTPUT_BYTES_PER_TICK for
+/// every tick since it was last refilled, up to OUTPUT_BURST), and checks if there is any left.
+///
+/// # Returns
+/// True if it can print (or the scheduler is not initialized yet), False if it should wait.
+pub fn has_output_credit() -> bool {
+    use crate::console::{OUTPUT_BURST, OUTPUT_BYTES_PER_TICK};
+    
+    let now = crate::time::ticks();
+    with_current(|pcb: &mut PCB| {
+        let elapsed = now.saturating_sub(pcb.out_tick) as usize;
+        let refill = elapsed.saturating_mul(OUTPUT_BYTES_PER_TICK);
+        pcb.out_credit = pcb.out_credit.saturating_add(refill).min(OUTPUT_BURST);
+        pcb.out_tick = now;
+        pcb.out_credit > 0
+    }).unwrap_or(true)
+}
+
+/// A function which takes the bytes which were printed from the running process' output credit.
+///
+/// # Parameters
+/// `len` : The number of bytes which were printed.
+pub fn charge_output(len: usize) {
+    with_current(|pcb: &mut PCB| pcb.out_credit = pcb.out_credit.saturating_sub(len));
+}
+
+/// A function which gives up the rest of the running process' time slice. It waits for the next 
+/// timer tick, which switches to the next process. It returns right away if the interrupts are
+/// disabled (since the tick would never arrive).
+pub fn yield_now() {
+    unsafe {
+        if !crate::arch::interrupts::are_enabled() {
+            return;
+        }
+        
+        CURR_TICK = MAX_TICKS;
+        crate::arch::proc::wait_for_interrupt();
+    }
+}
+
+/// A function which returns the current directory of the running process.
+///
+/// # Returns
+/// A copy of the current directory.
+pub fn current_cwd() -> String {
+    // Only the process itself changes it's directory, so the list doesn't need to be locked.
+    unsafe { PROC.as_ref().map(|pcb: &PCB| pcb.cwd.clone()) }
+        .unwrap_or_else(|| String::from(crate::fs::path::ROOT))
+}
+
+/// A function which sets how the running process reads the keyboard input.
+///
+/// # Parameters
+/// `mode` : The new input mode.
+pub fn set_input_mode(mode: InputMode) {
+    with_current(|pcb: &mut PCB| pcb.input_mode = mode);
+}
+
+/// A simple getter for the input mode of a process. It can be called from the interrupt handlers.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+///
+/// # Returns
+/// Some(mode) if the process exists, None otherwise.
+pub fn input_mode(pid: usize) -> Option<InputMode> {
+    find(pid, |pcb: &PCB| pcb.input_mode)
+}
+
+/// A function which changes the current directory of a process. The path should be absolute and
+/// normalized (see fs::path).
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+/// `cwd` : The new current directory.
+///
+/// # Returns
+/// Ok if the process was found, Err otherwise.
+pub fn set_cwd(pid: usize, cwd: String) -> Result<(), ()> {
+    // Swap it in while the interrupts are disabled, and free the old one after (it locks the heap).
+    let mut cwd = cwd;
+    let result = with_pcb_mut(pid, |pcb: &mut PCB| core::mem::swap(&mut pcb.cwd, &mut cwd))
+        .ok_or(());
+    drop(cwd);
+    
+    result
+}
+
+/// A function which finds the stack usage (high-water mark) of a given process. It scans the whole
+/// stack, so it should only be called on demand. A warning is logged if it's almost full.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+///
+/// # Returns
+/// Some((used, total)) in bytes if the process exists, None otherwise.
+pub fn stack_usage(pid: usize) -> Option<(usize, usize)> {
+    // Find the usage (and print the warning after the interrupts are enabled again).
+    find(pid, |pcb: &PCB| pcb.stack_usage()).map(|used| {
+        warn_stack_usage(pid, used);
+        (used, STACK_SIZE)
+    })
+}
+
+/// A function which logs a warning if a process used more than STACK_WARN_PERCENT of it's stack.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+/// `used` : The number of bytes of the stack which were used.
+fn warn_stack_usage(pid: usize, used: usize) {
+    if used * 100 > STACK_SIZE * STACK_WARN_PERCENT {
+        oxid_warn!("Process PID={} used {} of {} bytes of it's stack.", pid, used, STACK_SIZE);
     }
 }
 
 /// A function which puts the system into low power mode for ever (idle).
 ///
 /// `_args` : The arguments passed
-pub extern "sysv64" fn idle(_args: *const Args) {
-    unsafe { loop { crate::arch::proc::halt() }};
+pub extern "sysv64" fn idle(_args: *const Args) -> usize {
+    unsafe { loop { crate::arch::proc::halt() } }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::proc::process::{Args, ArgsError, ARGS_VERSION, ResourceLimits, SpawnFlags};
+    use crate::proc::process::STACK_SIZE;
+    use crate::proc::mutex::Mutex;
+    use super::KillResult;
+
+    /// The stack usage which was reported by the test process (0 until it's done).
+    static mut REPORTED_USAGE: usize = 0;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_stack_usage();
+        test_no_kill();
+        test_lock_inversion();
+        test_panic_recovery();
+        test_current_accessors();
+        test_heap_limit();
+        test_children_limit();
+        test_bad_args();
+        test_exit_eoi();
+        test_returned_code();
+        test_nested_switch();
+        test_stale_handle();
+    }
+
+    /// A recursive function which uses a known amount of the stack (at least 128 bytes per call).
+    ///
+    /// # Parameters
+    /// `depth` : The number of calls remaining.
+    ///
+    /// # Returns
+    /// A value which depends on the buffers (so they are not optimized away).
+    fn recurse(depth: usize) -> usize {
+        let buffer = core::hint::black_box([depth as u8; 128]);
+        if depth == 0 {
+            return buffer[0] as usize;
+        }
+        
+        recurse(depth - 1) + buffer[127] as usize
+    }
+
+    /// The main function of the test process. It recurses, and reports it's own stack usage.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn deep_process(_args: *const Args) -> usize {
+        core::hint::black_box(recurse(8));
+        
+        unsafe {
+            let (used, _) = super::stack_usage(super::current_pid().unwrap()).unwrap();
+            REPORTED_USAGE = used;
+        }
+        0
+    }
+
+    /// Spawn a process with a known recursion depth, and make sure it's stack usage is reported
+    /// within the expected band.
+    fn test_stack_usage() {
+        unsafe {
+            let mut args = Args::new();
+            super::spawn(deep_process, &mut args as *mut Args, "stack_test");
+            
+            // Wait (for up to a second) for the process to report it's usage.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+            while REPORTED_USAGE == 0 && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            
+            // 9 frames of at least 128 bytes, which still fit in the stack.
+            assert!(REPORTED_USAGE >= 9 * 128);
+            assert!(REPORTED_USAGE < STACK_SIZE);
+            
+            // The current process' usage is reported as well.
+            assert!(super::stack_usage(super::current_pid().unwrap()).is_some());
+            assert_eq!(super::stack_usage(usize::MAX), None);
+        }
+    }
+
+    /// A process which never exits (it waits for interrupts forever).
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn service_process(_args: *const Args) -> usize {
+        loop {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+    }
+
+    /// Spawn a process which can't be killed, make sure killing it fails, and then make sure it can
+    /// be killed after the flag is cleared.
+    fn test_no_kill() {
+        unsafe {
+            let mut args = Args::new();
+            let handle = super::spawn_with_flags(service_process, &mut args as *mut Args,
+                "no_kill_test", SpawnFlags::KERNEL_SERVICE | SpawnFlags::NO_KILL);
+            
+            // It can't be killed, and the IDLE process can't be killed either.
+            assert_eq!(super::kill_pid(handle), KillResult::NotKillable);
+            let idle = super::handle_of(super::IDLE_PID).unwrap();
+            assert_eq!(super::kill_pid(idle), KillResult::NotKillable);
+            assert_eq!(super::handle_of(usize::MAX), None);
+            
+            // Clear the flag and kill it.
+            assert_eq!(super::clear_flags(handle, SpawnFlags::NO_KILL), Ok(()));
+            assert_eq!(super::kill_pid(handle), KillResult::Killed);
+            
+            // Wait (for up to 2 seconds) for the scheduler to remove it.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while super::find_handle(handle, |_| ()).is_some() && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert!(super::find_handle(handle, |_| ()).is_none());
+        }
+    }
+    
+    /// The mutex which the test processes compete for, and a second one used to re-enable the 
+    /// interrupts while it's held (as nested locks do).
+    static mut CONTENDED: Mutex = Mutex::new();
+    static mut INNER: Mutex = Mutex::new();
+    
+    /// The number of ticks which the holder keeps the contended mutex for.
+    const HOLD_TICKS: u64 = 2;
+    
+    /// The progress of the test processes.
+    static mut HOLDER_LOCKED: bool = false;
+    static mut HOLDER_DONE: bool = false;
+    static mut SPINNER_DONE: bool = false;
+    
+    /// The process which holds the mutex across timer ticks (with the interrupts enabled).
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn holder(_args: *const Args) -> usize {
+        unsafe {
+            CONTENDED.lock();
+            HOLDER_LOCKED = true;
+            
+            // The inner unlock enables the interrupts, so the timer keeps ticking.
+            INNER.lock();
+            INNER.unlock();
+            
+            let end = crate::time::ticks() + HOLD_TICKS;
+            while crate::time::ticks() < end {
+                core::hint::spin_loop();
+            }
+            
+            CONTENDED.unlock();
+            HOLDER_DONE = true;
+        }
+        0
+    }
+    
+    /// The process which needs the mutex (it spins with the interrupts disabled while waiting).
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn spinner(_args: *const Args) -> usize {
+        unsafe {
+            // Wait for the holder to take the mutex first.
+            while !core::ptr::read_volatile(&HOLDER_LOCKED) {
+                core::hint::spin_loop();
+            }
+            
+            CONTENDED.lock();
+            CONTENDED.unlock();
+            SPINNER_DONE = true;
+        }
+        0
+    }
+    
+    /// Spawn a process which holds a mutex across ticks, and one which needs it, and make sure 
+    /// both of them finish (the holder is not switched away while holding it).
+    fn test_lock_inversion() {
+        unsafe {
+            HOLDER_LOCKED = false;
+            HOLDER_DONE = false;
+            SPINNER_DONE = false;
+            let prev_overruns = super::lock_overruns();
+            
+            let mut args = Args::new();
+            super::spawn(holder, &mut args as *mut Args, "lock_holder");
+            super::spawn(spinner, &mut args as *mut Args, "lock_spinner");
+            
+            // Wait (for up to a second) for both of them to finish.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+            while !(HOLDER_DONE && SPINNER_DONE) && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            
+            assert!(HOLDER_DONE && SPINNER_DONE);
+            assert_eq!(super::lock_overruns(), prev_overruns);
+        }
+    }
+    
+    /// A process which panics.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn panicking_process(_args: *const Args) -> usize {
+        panic!("boom");
+    }
+    
+    /// Make sure a panicking process is removed (and reported) without stopping the system, and
+    /// that the kernel services are never recovered.
+    fn test_panic_recovery() {
+        unsafe {
+            let mut args = Args::new();
+            let handle = super::spawn_with_flags(panicking_process, &mut args as *mut Args,
+                "panic_test", SpawnFlags::NONE);
+            
+            // Wait (for up to 2 seconds) for the scheduler to remove it.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while super::find_handle(handle, |_| ()).is_some() && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert!(super::find_handle(handle, |_| ()).is_none());
+            assert_eq!(super::last_failure(), Some((handle.pid(), super::PANIC_EXIT_CODE)));
+            
+            // The IDLE process and the services can't be recovered.
+            assert_eq!(super::find(super::IDLE_PID, super::is_recoverable), Some(false));
+            let service = super::spawn_with_flags(service_process, &mut args as *mut Args,
+                "panic_service_test", SpawnFlags::KERNEL_SERVICE | SpawnFlags::NO_KILL);
+            assert_eq!(super::find_handle(service, super::is_recoverable), Some(false));
+            
+            // A regular process can be.
+            assert_eq!(super::clear_flags(service, SpawnFlags::KERNEL_SERVICE 
+                | SpawnFlags::NO_KILL), Ok(()));
+            assert_eq!(super::find_handle(service, super::is_recoverable), Some(true));
+            assert_eq!(super::kill_pid(service), KillResult::Killed);
+        }
+    }
+    
+    /// The values which were read by the accessor test process (the PID is 0 until it's done).
+    static mut ACCESSOR_PID: usize = 0;
+    static mut ACCESSOR_NAME_OK: bool = false;
+    static mut ACCESSOR_ARGS_OK: bool = false;
+    
+    /// A process which reads it's own information through the accessors.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn accessor_process(_args: *const Args) -> usize {
+        unsafe {
+            ACCESSOR_NAME_OK = super::current_name().as_deref() == Some("accessor_test");
+            ACCESSOR_ARGS_OK = super::current_args().map_or(false, |args| Args::parse(&args)
+                .map_or(false, |view| view.argv().eq(["accessor_test", "42"].iter().copied())));
+            ACCESSOR_PID = super::current_pid().unwrap_or(usize::MAX);
+        }
+        0
+    }
+    
+    /// Make sure the accessors return None before the scheduler is initialized, and the values of
+    /// the running process after.
+    fn test_current_accessors() {
+        unsafe {
+            // Pretend the scheduler is not initialized (with the interrupts disabled).
+            let were_enabled = crate::arch::interrupts::save_and_disable();
+            let saved = super::PROC;
+            super::PROC = core::ptr::null_mut();
+            let before = (super::current_pid().is_none(), super::current_args().is_none(),
+                super::with_current(|_| ()).is_none(), super::can_recover_current());
+            let name_before = super::current_name().is_none();
+            super::PROC = saved;
+            crate::arch::interrupts::restore(were_enabled);
+            assert_eq!(before, (true, true, true, false));
+            assert!(name_before);
+            
+            // After it's initialized, they return the running process.
+            assert!(super::current_pid().is_some());
+            assert!(super::current_name().is_some());
+            
+            // And a spawned process sees it's own values.
+            let mut args = Args::new();
+            args.set_args("accessor_test 42");
+            let handle = super::spawn_with_flags(accessor_process, &mut args as *mut Args,
+                "accessor_test", SpawnFlags::NONE);
+            
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+            while ACCESSOR_PID == 0 && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            
+            assert_eq!(ACCESSOR_PID, handle.pid());
+            assert!(ACCESSOR_NAME_OK);
+            assert!(ACCESSOR_ARGS_OK);
+            assert_ne!(super::current_pid(), Some(handle.pid()));
+        }
+    }
+    
+    /// The results of the heap limit test process (it's done when HEAP_DONE is set).
+    static mut HEAP_DONE: bool = false;
+    static mut HEAP_SMALL_OK: bool = false;
+    static mut HEAP_LARGE_FAILED: bool = false;
+    
+    /// A process which allocates within it's heap limit, and then over it.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn heap_process(_args: *const Args) -> usize {
+        use crate::mem::dyn_alloc::{kmalloc_tagged, kfree_tagged, AllocError};
+        use crate::mem::vmm::PAGE_SIZE;
+        
+        unsafe {
+            let small = kmalloc_tagged(PAGE_SIZE, false, true, true);
+            HEAP_SMALL_OK = small.is_ok();
+            HEAP_LARGE_FAILED = kmalloc_tagged(4 * PAGE_SIZE, false, true, true) 
+                == Err(AllocError::HeapLimitExceeded);
+            
+            if let Ok(ptr) = small {
+                kfree_tagged(ptr);
+            }
+            HEAP_DONE = true;
+        }
+        0
+    }
+    
+    /// Spawn a process with a tiny heap limit, and make sure only it's oversized allocation fails.
+    fn test_heap_limit() {
+        unsafe {
+            let mut args = Args::new();
+            let limits = ResourceLimits { max_heap_bytes: 2 * crate::mem::vmm::PAGE_SIZE, 
+                max_children: 0 };
+            let pid = super::try_spawn(heap_process, &mut args as *mut Args, "heap_limit_test",
+                SpawnFlags::NONE, Some(limits));
+            assert!(pid.is_ok());
+            
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+            while !HEAP_DONE && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            
+            assert!(HEAP_SMALL_OK);
+            assert!(HEAP_LARGE_FAILED);
+            
+            // The rest of the system can still allocate.
+            let ptr = crate::mem::dyn_alloc::kmalloc(4 * crate::mem::vmm::PAGE_SIZE, false, true,
+                true);
+            assert!(!ptr.is_null());
+            crate::mem::dyn_alloc::kfree(ptr);
+        }
+    }
+    
+    /// The results of the children limit test process (it's done when SPAWNER_DONE is set).
+    static mut SPAWNER_DONE: bool = false;
+    static mut SPAWNED_COUNT: usize = 0;
+    static mut SPAWN_REFUSED: bool = false;
+    static mut SPAWN_AFTER_REAP: bool = false;
+    
+    /// A short lived child process.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn short_child(_args: *const Args) -> usize {
+        crate::time::sleep_ms(100);
+        0
+    }
+    
+    /// A process which spawns children in a loop until it's refused, and tries again after they
+    /// are removed.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn spawner_process(_args: *const Args) -> usize {
+        unsafe {
+            let mut args = Args::new();
+            for _ in 0..8 {
+                match super::try_spawn(short_child, &mut args as *mut Args, "limit_child", 
+                    SpawnFlags::NONE, None) {
+                    Ok(_) => SPAWNED_COUNT += 1,
+                    Err(super::SpawnError::TooManyChildren) => {
+                        SPAWN_REFUSED = true;
+                        break;
+                    },
+                    Err(_) => break,
+                }
+            }
+            
+            // Wait for the children to be removed, and spawn again.
+            crate::time::sleep_ms(500);
+            SPAWN_AFTER_REAP = super::try_spawn(short_child, &mut args as *mut Args, 
+                "limit_child", SpawnFlags::NONE, None).is_ok();
+            SPAWNER_DONE = true;
+        }
+        0
+    }
+    
+    /// Spawn a process which tries to spawn too many children, and make sure it's limited.
+    fn test_children_limit() {
+        unsafe {
+            let mut args = Args::new();
+            let limits = ResourceLimits { max_heap_bytes: usize::MAX, max_children: 3 };
+            assert!(super::try_spawn(spawner_process, &mut args as *mut Args, "spawner_test",
+                SpawnFlags::NONE, Some(limits)).is_ok());
+            
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while !SPAWNER_DONE && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            
+            assert_eq!(SPAWNED_COUNT, 3);
+            assert!(SPAWN_REFUSED);
+            assert!(SPAWN_AFTER_REAP);
+        }
+    }
+    
+    /// Spawn a program with arguments from a newer layout, and make sure it's refused.
+    fn test_bad_args() {
+        unsafe {
+            let mut args = Args::new();
+            let version = (&mut args as *mut Args as *mut u8).add(4) as *mut u16;
+            *version = ARGS_VERSION + 1;
+            
+            assert_eq!(super::try_spawn(short_child, &mut args as *mut Args, "bad_args_test",
+                SpawnFlags::NONE, None).err(),
+                Some(super::SpawnError::BadArgs(ArgsError::VersionMismatch(ARGS_VERSION + 1))));
+        }
+    }
+    
+    /// A process which exits right away (with a non-zero code).
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn exiting_process(_args: *const Args) -> usize {
+        super::exit_with_code(3);
+        0
+    }
+    
+    /// A process which returns a non-zero exit code from it's main function.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn returning_process(_args: *const Args) -> usize {
+        5
+    }
+    
+    /// Make sure the code which is returned by a process is passed to the exit point (and recorded
+    /// as it's exit code).
+    fn test_returned_code() {
+        unsafe {
+            let mut args = Args::new();
+            let handle = super::spawn_with_flags(returning_process, &mut args as *mut Args,
+                "return_test", SpawnFlags::NONE);
+            
+            // Wait (for up to 1 second) for the scheduler to remove it.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+            while super::find_handle(handle, |_| ()).is_some() && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert!(super::find_handle(handle, |_| ()).is_none());
+            assert_eq!(super::last_failure(), Some((handle.pid(), 5)));
+        }
+    }
+    
+    /// Make sure that exiting processes don't send extra EOIs, and that the IRQs are never left
+    /// in service (so the keyboard keeps working after many processes exit).
+    fn test_exit_eoi() {
+        unsafe {
+            let mut args = Args::new();
+            for _ in 0..16 {
+                let handle = super::spawn_with_flags(exiting_process, &mut args as *mut Args,
+                    "exit_eoi_test", SpawnFlags::NONE);
+                
+                // Wait (for up to 1 second) for the scheduler to remove it.
+                let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+                while super::find_handle(handle, |_| ()).is_some() 
+                    && crate::time::ticks() < deadline {
+                    crate::arch::proc::wait_for_interrupt();
+                }
+                assert!(super::find_handle(handle, |_| ()).is_none());
+                assert_eq!(super::last_failure(), Some((handle.pid(), 3)));
+            }
+            
+            // Outside of the handlers, every IRQ should be acknowledged exactly once.
+            let were_enabled = crate::arch::interrupts::save_and_disable();
+            let in_service = crate::arch::interrupts::pic::in_service();
+            let (entered, eois) = crate::arch::interrupts::handlers::irq_counts();
+            crate::arch::interrupts::restore(were_enabled);
+            
+            assert_eq!(in_service & (1 << crate::arch::io::ps2_keyboard::IRQ_NUM), 0);
+            assert_eq!(entered, eois);
+        }
+    }
+    
+    /// Set if the timer switched away while the nested test handler was still running.
+    static mut SWITCHED_IN_HANDLER: bool = false;
+    
+    /// Set if the timer deferred it's switch while the nested test handler was running.
+    static mut DEFERRED_IN_HANDLER: bool = false;
+    
+    /// A trap handler (the interrupts stay enabled) which waits for the timer to want a switch.
+    ///
+    /// # Parameters
+    /// `_info` : The context of the interrupted code (not used).
+    unsafe fn nested_handler(_info: *const crate::arch::interrupts::handlers::context::Context) {
+        let before = super::counters();
+        super::CURR_TICK = super::MAX_TICKS;
+        
+        // Wait (for up to 1 second) for the next tick.
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        while super::counters().deferred == before.deferred && crate::time::ticks() < deadline {
+            crate::arch::proc::wait_for_interrupt();
+        }
+        
+        DEFERRED_IN_HANDLER = super::counters().deferred > before.deferred;
+        SWITCHED_IN_HANDLER = super::counters().switches != before.switches;
+    }
+    
+    /// Raise a trap from a process, and make sure the timer doesn't switch while it's handled (the
+    /// switch happens once the handler returns instead).
+    fn test_nested_switch() {
+        use crate::arch::interrupts::vectors::{alloc_vector, free_vector, VectorClass};
+        use crate::arch::interrupts::handlers;
+        
+        unsafe {
+            let vector = alloc_vector(VectorClass::System, "nested_test", nested_handler).unwrap();
+            handlers::register_trap(vector, nested_handler);
+            
+            let before = super::counters();
+            crate::arch::interrupts::raise_interrupt(vector);
+            let after = super::counters();
+            assert_eq!(free_vector(vector), Ok(()));
+            
+            assert!(DEFERRED_IN_HANDLER);
+            assert!(!SWITCHED_IN_HANDLER);
+            assert!(after.switches > before.switches);
+            assert!(!super::switch_pending());
+            assert_eq!(handlers::int_depth(), 0);
+        }
+    }
+    
+    /// Kill a process, and make sure it's PID is given to the next one with a new generation. The
+    /// old handle is stale, so it can't be used to kill (or signal) the new process.
+    fn test_stale_handle() {
+        unsafe {
+            let mut args = Args::new();
+            let old = super::spawn_with_flags(service_process, &mut args as *mut Args,
+                "stale_old_test", SpawnFlags::NONE);
+            assert_eq!(super::handle_of(old.pid()), Some(old));
+            assert_eq!(super::kill_pid(old), KillResult::Killed);
+            
+            // Wait (for up to 2 seconds) for the scheduler to remove it (and free it's PID).
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(2000);
+            while super::handle_of(old.pid()).is_some() && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+            assert_eq!(super::handle_of(old.pid()), None);
+            
+            // The lowest free PID is given out first, so the new process gets the same one.
+            let new = super::spawn_with_flags(service_process, &mut args as *mut Args,
+                "stale_new_test", SpawnFlags::NONE);
+            assert_eq!(new.pid(), old.pid());
+            assert_ne!(new.gen(), old.gen());
+            assert_eq!(super::handle_of(old.pid()), Some(new));
+            
+            // The stale handle doesn't reach the new process.
+            assert_eq!(super::kill_pid(old), KillResult::NotFound);
+            assert!(!super::request_termination(old));
+            assert!(super::find_handle(old, |_| ()).is_none());
+            assert_eq!(super::find_handle(new, |pcb| pcb.term_requested), Some(false));
+            
+            assert_eq!(super::kill_pid(new), KillResult::Killed);
+        }
+    }
 }
@@ -0,0 +1,212 @@
+//! A module which implements a sequence lock for small statistics which are written from the
+//! interrupt handlers and read everywhere else. The writer makes the sequence odd while it changes
+//! the data, and even again when it's done. The readers never block or disable the interrupts, they
+//! copy the data and simply retry if the sequence was odd or changed while they were copying it. So
+//! a read always returns all the fields from the same write (they are never mixed).
+//!
+//! There can only be a single writer at a time. The writes disable the interrupts (so a writer is
+//! never interrupted by a reader or another writer on the same CPU), but two writers on different
+//! CPUs would corrupt the data. This is checked in the debug builds.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// A structure which represents a sequence lock which protects a copyable value.
+pub struct SeqLock<T: Copy> {
+    seq: AtomicUsize,                          // Odd while a write is in progress.
+    data: UnsafeCell<T>,                       // The protected value.
+}
+
+// The readers only copy the data, and the writes are serialized (see the module documentation).
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// The main constructor which creates a sequence lock with an initial value.
+    ///
+    /// # Parameters
+    /// `data` : The initial value.
+    ///
+    /// # Returns
+    /// The created sequence lock.
+    pub const fn new(data: T) -> Self {
+        SeqLock {
+            seq: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// A method which changes the value. It should only be called by a single writer (such as the
+    /// timer interrupt), and the interrupts are disabled while the value is changed.
+    ///
+    /// # Parameters
+    /// `update` : The function which changes the value (it should be short).
+    pub fn write<F: FnOnce(&mut T)>(&self, update: F) {
+        unsafe {
+            let were_enabled = crate::arch::interrupts::save_and_disable();
+
+            // An odd sequence means another writer is in the middle of a write.
+            let seq = self.seq.load(Ordering::Relaxed);
+            debug_assert!(seq % 2 == 0, "A sequence lock was written by more than one writer.");
+
+            // Make it odd before touching the data, and even once it's done.
+            self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+            fence(Ordering::Release);
+            update(&mut *self.data.get());
+            self.seq.store(seq.wrapping_add(2), Ordering::Release);
+
+            crate::arch::interrupts::restore(were_enabled);
+        }
+    }
+
+    /// A method which reads a copy of the value. It never blocks, it retries if a write happened
+    /// while it was copying.
+    ///
+    /// # Returns
+    /// A copy of the value (from a single write).
+    pub fn read(&self) -> T {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                core::hint::spin_loop();
+                continue;
+            }
+
+            // The copy might be torn, but then the sequence has changed and it's discarded.
+            let data = unsafe { core::ptr::read_volatile(self.data.get()) };
+            fence(Ordering::Acquire);
+
+            if self.seq.load(Ordering::Relaxed) == before {
+                return data;
+            }
+        }
+    }
+
+    /// A simple getter for the sequence number (the number of writes times two).
+    ///
+    /// # Returns
+    /// The current sequence number.
+    pub fn sequence(&self) -> usize {
+        self.seq.load(Ordering::Acquire)
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::SeqLock;
+    use crate::proc::process::Args;
+
+    /// The number of writes done by the test writer.
+    const NUM_WRITES: u64 = 100_000;
+
+    /// The value which is shared by the test processes (the second field is always twice the first).
+    static SHARED: SeqLock<(u64, u64)> = SeqLock::new((0, 0));
+
+    /// The progress of the test processes.
+    static mut WRITER_DONE: bool = false;
+    static mut READER_DONE: bool = false;
+    static mut READS: usize = 0;
+    static mut MIXED_READS: usize = 0;
+    static mut BACKWARD_READS: usize = 0;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_single_thread();
+        test_concurrent_reads();
+    }
+
+    /// Make sure the reads return the latest write, and that each write moves the sequence by two.
+    fn test_single_thread() {
+        let lock: SeqLock<(u32, u32)> = SeqLock::new((1, 2));
+        assert_eq!(lock.read(), (1, 2));
+        assert_eq!(lock.sequence(), 0);
+
+        lock.write(|value| *value = (3, 6));
+        assert_eq!(lock.read(), (3, 6));
+        assert_eq!(lock.sequence(), 2);
+    }
+
+    /// The process which updates the shared value as fast as it can.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn writer(_args: *const Args) -> usize {
+        for count in 1..=NUM_WRITES {
+            SHARED.write(|value| {
+                value.0 = count;
+                value.1 = count * 2;
+            });
+        }
+
+        unsafe { WRITER_DONE = true; }
+        0
+    }
+
+    /// The process which reads the shared value until the writer is done, and counts the reads
+    /// which broke the invariant (or went backwards).
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn reader(_args: *const Args) -> usize {
+        unsafe {
+            let mut last: u64 = 0;
+            loop {
+                let done = core::ptr::read_volatile(&WRITER_DONE);
+                let (first, second) = SHARED.read();
+
+                READS += 1;
+                if second != first * 2 {
+                    MIXED_READS += 1;
+                }
+                if first < last {
+                    BACKWARD_READS += 1;
+                }
+                last = first;
+
+                if done {
+                    break;
+                }
+            }
+
+            READER_DONE = true;
+        }
+        0
+    }
+
+    /// Spawn a writer and a reader which run at the same time, and make sure the reader never sees
+    /// a value which was mixed from two writes.
+    fn test_concurrent_reads() {
+        unsafe {
+            WRITER_DONE = false;
+            READER_DONE = false;
+            READS = 0;
+            MIXED_READS = 0;
+            BACKWARD_READS = 0;
+
+            let mut args = Args::new();
+            crate::proc::scheduler::spawn(reader, &mut args as *mut Args, "seq_reader");
+            crate::proc::scheduler::spawn(writer, &mut args as *mut Args, "seq_writer");
+
+            // Wait (for up to five seconds) for both of them to finish.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(5000);
+            while !(WRITER_DONE && READER_DONE) && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+
+            assert!(WRITER_DONE && READER_DONE);
+            assert!(READS > 0);
+            assert_eq!(MIXED_READS, 0);
+            assert_eq!(BACKWARD_READS, 0);
+            assert_eq!(SHARED.read(), (NUM_WRITES, NUM_WRITES * 2));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! A sub-module which implements a watchdog for the processes which never reach a scheduling point
+//! (for example, a program which disables the interrupts and spins). Such a process can't be
+//! preempted or killed, since the timer interrupt never arrives. The PIT is also routed as a
+//! non-maskable interrupt (see arch::interrupts::ioapic), so the watchdog counts the ticks which
+//! passed since the last timer interrupt. Once they go over the threshold, the process is flagged
+//! (ps marks it with a '!'), and it's interrupted context is reset to a trampoline which exits it.
+//!
+//! A foreground program runs inline in the kworker thread, so the kworker is restarted instead (the
+//! program's frames are abandoned, and the mutexes it held stay locked). The IDLE process, the
+//! other kernel services, and the IRQ handlers are only flagged.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// The default time which a process can keep the interrupts disabled (in milliseconds).
+pub const DEFAULT_THRESHOLD_MS: usize = 2000;
+
+/// The exit code of the processes which were terminated by the watchdog.
+pub const STUCK_EXIT_CODE: i32 = 102;
+
+/// True while the watchdog handles the NMIs.
+static mut ARMED: bool = false;
+
+/// The number of ticks without a timer interrupt which are tolerated.
+static mut THRESHOLD_TICKS: usize = 0;
+
+/// The number of ticks (NMIs) since the last timer interrupt.
+static mut SINCE_TICK: usize = 0;
+
+/// The number of times a process was caught.
+static mut CAUGHT: usize = 0;
+
+/// The PID of the last process which was caught.
+static mut LAST_CAUGHT: Option<usize> = None;
+
+/// A function which starts the watchdog. It should be called after the time keeping code and the
+/// work queue are initialized.
+pub fn init() {
+    set_threshold_ms(DEFAULT_THRESHOLD_MS);
+
+    unsafe {
+        use crate::arch::interrupts::ioapic;
+
+        if ioapic::init().is_err() || ioapic::route_nmi(ioapic::PIT_PIN,
+            crate::arch::proc::cpu::apic_id()).is_err() {
+            oxid_warn!("There is no I/O APIC, the watchdog is disabled.");
+            return;
+        }
+
+        SINCE_TICK = 0;
+        ARMED = true;
+    }
+
+    oxid_log!("Started the watchdog (threshold={}ms).", DEFAULT_THRESHOLD_MS);
+}
+
+/// A function which stops the watchdog (for example, before the system is halted on purpose).
+pub fn disarm() {
+    unsafe {
+        if ARMED {
+            ARMED = false;
+            crate::arch::interrupts::ioapic::mask(crate::arch::interrupts::ioapic::PIT_PIN);
+        }
+    }
+}
+
+/// A simple getter which checks if the watchdog is running.
+///
+/// # Returns
+/// True if it's armed, False otherwise (there is no NMI source).
+pub fn is_armed() -> bool {
+    unsafe { ARMED }
+}
+
+/// A function which changes the time a process can keep the interrupts disabled.
+///
+/// # Parameters
+/// `ms` : The threshold in milliseconds.
+pub fn set_threshold_ms(ms: usize) {
+    // The NMI handler can't read the time keeping state (it might interrupt it's writer).
+    let ticks = core::cmp::max(crate::time::ms_to_ticks(ms) as usize, 1);
+    unsafe { THRESHOLD_TICKS = ticks; }
+}
+
+/// A function which is called by the timer interrupt (the scheduling point).
+#[inline]
+pub fn tick() {
+    unsafe { SINCE_TICK = 0; }
+}
+
+/// A simple getter for the number of times a process was caught.
+///
+/// # Returns
+/// The number of stuck processes.
+pub fn caught() -> usize {
+    unsafe { CAUGHT }
+}
+
+/// A simple getter for the last process which was caught.
+///
+/// # Returns
+/// The PID, or None if nothing was caught.
+pub fn last_caught() -> Option<usize> {
+    unsafe { LAST_CAUGHT }
+}
+
+/// A function which is called by the NMI handler on every tick. It never blocks or prints (it can
+/// interrupt anything, including the code which holds the console).
+///
+/// # Parameters
+/// `context` : The interrupted context (it's changed if the process is terminated).
+///
+/// # Returns
+/// True if the NMI was handled, False if the watchdog is not armed.
+pub unsafe fn nmi(context: *mut u8) -> bool {
+    if !ARMED {
+        return false;
+    }
+
+    SINCE_TICK += 1;
+    if SINCE_TICK <= THRESHOLD_TICKS {
+        return true;
+    }
+
+    // Count again from the start (so it's not caught on every tick if it can't be terminated).
+    SINCE_TICK = 0;
+
+    let (pid, stack_start, recoverable) = match crate::proc::scheduler::flag_stuck() {
+        Some(stuck) => stuck,
+        None => return true,
+    };
+    CAUGHT += 1;
+    LAST_CAUGHT = Some(pid);
+
+    // The IRQ handlers can't be left without their EOI, and the other handlers which were 
+    // interrupted can't be abandoned either (only the NMI itself should be running).
+    use crate::arch::interrupts::handlers;
+    if handlers::in_irq() || handlers::int_depth() > 1 {
+        return true;
+    }
+
+    use crate::arch::proc::process::scheduling::redirect_context;
+    if crate::proc::exec::in_foreground() && crate::proc::workqueue::worker_pid() == Some(pid) {
+        redirect_context(context, restart_worker, stack_start);
+    } else if recoverable {
+        redirect_context(context, exit_stuck, stack_start);
+    }
+
+    true
+}
+
+/// The trampoline which exits a stuck process (it runs in the process, with interrupts enabled).
+extern "sysv64" fn exit_stuck() {
+    report();
+    crate::proc::scheduler::exit_with_code(STUCK_EXIT_CODE);
+}
+
+/// The trampoline which restarts the kworker thread when the foreground program it was running got
+/// stuck. The keyboard is given back to the terminal.
+extern "sysv64" fn restart_worker() {
+    crate::proc::exec::abort_foreground();
+    if crate::io::keyboard::discipline::owner() == crate::proc::workqueue::worker_pid() {
+        crate::io::keyboard::grab(false);
+    }
+
+    report();
+    crate::proc::workqueue::kworker(core::ptr::null());
+}
+
+/// A helper which reports the last caught process (from the work queue, since the console might be
+/// held by it).
+fn report() {
+    if let Some(pid) = last_caught() {
+        let _ = crate::proc::workqueue::queue_work(report_work, pid);
+    }
+}
+
+/// The work function which reports a stuck process.
+///
+/// # Parameters
+/// `pid` : The process ID of the process.
+fn report_work(pid: usize) {
+    oxid_warn!("Process PID={} kept the interrupts disabled for too long, it was terminated.", pid);
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::proc::process::{Args, SpawnFlags};
+    use crate::proc::exec::{exec, ExecFlags, ExecResult};
+    use crate::proc::scheduler;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_masked_spinner();
+    }
+
+    /// A process which disables the interrupts and spins forever.
+    ///
+    /// # Parameters
+    /// `_args` : The arguments passed (not used).
+    extern "sysv64" fn masked_spinner(_args: *const Args) -> usize {
+        unsafe { crate::arch::interrupts::disable(); }
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Spawn a process which spins with the interrupts disabled, and make sure it's caught and
+    /// terminated (and a foreground program can still run after it).
+    fn test_masked_spinner() {
+        if !super::is_armed() {
+            oxid_warn!("The watchdog is not armed, skipping it's test.");
+            return;
+        }
+
+        unsafe {
+            let caught = super::caught();
+            super::set_threshold_ms(200);
+
+            let mut args = Args::new();
+            let handle = scheduler::spawn_with_flags(masked_spinner, &mut args as *mut Args,
+                "masked_spinner", SpawnFlags::NONE);
+
+            // Wait (for up to five seconds) for it to be removed.
+            let deadline = crate::time::ticks() + crate::time::ms_to_ticks(5000);
+            while scheduler::find_handle(handle, |_| ()).is_some() 
+                && crate::time::ticks() < deadline {
+                crate::arch::proc::wait_for_interrupt();
+            }
+
+            super::set_threshold_ms(super::DEFAULT_THRESHOLD_MS);
+            assert!(scheduler::find_handle(handle, |_| ()).is_none());
+            assert_eq!(super::caught(), caught + 1);
+            assert_eq!(super::last_caught(), Some(handle.pid()));
+            assert_eq!(scheduler::last_failure(), Some((handle.pid(), super::STUCK_EXIT_CODE)));
+
+            // The rest of the system is still responsive.
+            assert_eq!(exec("echo watchdog test", ExecFlags::NONE), Ok(ExecResult::Exited(0)));
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! A sub-module which implements a simple work queue for deferred work. Interrupt handlers (or any
+//! code which can't block or allocate) can queue a function with an argument, and a dedicated
+//! kernel thread (kworker) will run it later in process context, with interrupts enabled. The queue
+//! is a fixed size ring buffer, so queueing never allocates. If it's full, the work is dropped and
+//! counted. Work can also be delayed by a number of milliseconds (based on the timer ticks).
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use crate::arch::interrupts;
+use crate::proc::process::{Args, SpawnFlags};
+
+/// The maximum number of work items which can be waiting in the queue.
+pub const QUEUE_SIZE: usize = 64;
+
+/// The maximum number of delayed work items which can be waiting for their deadline.
+pub const DELAYED_SIZE: usize = 16;
+
+/// The type of the functions which can be queued (they are passed the queued argument).
+pub type WorkFn = fn(usize);
+
+/// A structure which represents a single deferred function call.
+#[derive(Copy, Clone)]
+struct Work {
+    func: WorkFn,                              // The function which will be called.
+    arg: usize,                                // The argument passed to the function.
+}
+
+/// A structure which represents a work item which should only run after a given tick.
+#[derive(Copy, Clone)]
+struct DelayedWork {
+    deadline: u64,                             // The tick after which it can run.
+    work: Work,                                // The actual work.
+}
+
+/// The ring buffer which holds the pending work (in FIFO order).
+static mut QUEUE: [Option<Work>; QUEUE_SIZE] = [None; QUEUE_SIZE];
+
+/// The index of the next work item to be run.
+static mut HEAD: usize = 0;
+
+/// The number of work items currently in the queue.
+static mut COUNT: usize = 0;
+
+/// The delayed work items which are waiting for their deadline.
+static mut DELAYED: [Option<DelayedWork>; DELAYED_SIZE] = [None; DELAYED_SIZE];
+
+/// The number of work items which were dropped since the queue (or delayed list) was full.
+static mut DROPPED: usize = 0;
+
+/// If false, no new work will be accepted (the queue is being shut down).
+static mut ACCEPTING: bool = true;
+
+/// The PID of the kworker thread (None until the work queue is initialized).
+static mut WORKER_PID: Option<usize> = None;
+
+/// A function which initializes the work queue by spawning the kworker kernel thread (which can't
+/// be killed). It should be called after the scheduler is initialized.
+pub unsafe fn init() {
+    oxid_log!("Initializing the work queue.");
+
+    // The arguments are copied by the scheduler, so they can live on the stack.
+    let mut args = Args::new();
+    WORKER_PID = Some(crate::proc::scheduler::spawn_with_flags(kworker, &mut args as *mut Args, 
+        "kworker", SpawnFlags::KERNEL_SERVICE | SpawnFlags::NO_KILL).pid());
+    
+    // Drain the queue when the system shuts down.
+    let _ = crate::power::on_shutdown(shutdown_hook);
+}
+
+/// The shutdown hook which shuts down the work queue (see power::on_shutdown).
+fn shutdown_hook() {
+    let discarded = shutdown();
+    if discarded > 0 {
+        oxid_log!("Discarded {} delayed work items.", discarded);
+    }
+}
+
+/// A function which queues a function to be called later by the kworker thread. It is safe to be
+/// called from interrupt handlers, since it never blocks or allocates.
+///
+/// # Parameters
+/// `func` : The function which will be called.
+/// `arg` : The argument which will be passed to the function.
+///
+/// # Returns
+/// Ok if it was queued, Err if the queue was full (the work is dropped) or is shut down.
+pub fn queue_work(func: WorkFn, arg: usize) -> Result<(), ()> {
+    unsafe {
+        // Disable the interrupts, since this can be called from both contexts.
+        let were_enabled = interrupts::save_and_disable();
+        let result = push(Work { func, arg });
+        interrupts::restore(were_enabled);
+
+        result
+    }
+}
+
+/// A function which queues a function to be called by the kworker thread after a given delay. The
+/// resolution is a timer tick, so the actual delay can be longer.
+///
+/// # Parameters
+/// `ms` : The minimum delay before running the function (in milliseconds).
+/// `func` : The function which will be called.
+/// `arg` : The argument which will be passed to the function.
+///
+/// # Returns
+/// Ok if it was queued, Err if there were no free slots (the work is dropped) or is shut down.
+pub fn queue_work_delayed(ms: usize, func: WorkFn, arg: usize) -> Result<(), ()> {
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(ms);
+
+        // Find a free slot for it.
+        let result = match DELAYED.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) if ACCEPTING => {
+                *slot = Some(DelayedWork { deadline, work: Work { func, arg } });
+                Ok(())
+            },
+            _ => {
+                DROPPED += 1;
+                Err(())
+            },
+        };
+
+        interrupts::restore(were_enabled);
+        result
+    }
+}
+
+/// A simple getter for the PID of the kworker thread.
+///
+/// # Returns
+/// The PID, or None if the work queue is not initialized.
+pub fn worker_pid() -> Option<usize> {
+    unsafe { WORKER_PID }
+}
+
+/// A simple getter for the number of work items which were dropped (queue full or shut down).
+///
+/// # Returns
+/// The number of dropped work items.
+pub fn dropped() -> usize {
+    unsafe { DROPPED }
+}
+
+/// A simple getter for the number of work items which are waiting to be run.
+///
+/// # Returns
+/// The number of pending work items (not including the delayed ones).
+pub fn pending() -> usize {
+    unsafe { COUNT }
+}
+
+/// A function which moves the delayed work items whose deadline has passed to the queue.
+pub fn promote_delayed() {
+    unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        let now = crate::time::ticks();
+
+        for slot in DELAYED.iter_mut() {
+            if let Some(delayed) = *slot {
+                // If it's due, move it (if the queue is full, try again later).
+                if delayed.deadline <= now && push(delayed.work).is_ok() {
+                    *slot = None;
+                }
+            }
+        }
+
+        interrupts::restore(were_enabled);
+    }
+}
+
+/// A function which runs all the pending work items in FIFO order. The interrupts are only
+/// disabled while removing an item, so the work itself runs with interrupts enabled.
+///
+/// # Returns
+/// The number of work items which were run.
+pub fn run_pending() -> usize {
+    let mut ran: usize = 0;
+
+    // Run them one by one (new ones might be added while running).
+    while let Some(work) = unsafe {
+        let were_enabled = interrupts::save_and_disable();
+        let work = pop();
+        interrupts::restore(were_enabled);
+        work
+    } {
+        (work.func)(work.arg);
+        ran += 1;
+    }
+
+    ran
+}
+
+/// A function which shuts down the work queue. It stops accepting new work, runs the pending work
+/// in the caller's context, and discards the delayed work which is not due yet.
+///
+/// # Returns
+/// The number of delayed work items which were discarded.
+pub fn shutdown() -> usize {
+    unsafe {
+        // Promote whatever is due, and stop accepting new work.
+        promote_delayed();
+        let were_enabled = interrupts::save_and_disable();
+        ACCEPTING = false;
+        interrupts::restore(were_enabled);
+
+        // Drain the immediate work.
+        run_pending();
+
+        // Discard the rest of the delayed work.
+        let were_enabled = interrupts::save_and_disable();
+        let mut discarded: usize = 0;
+        for slot in DELAYED.iter_mut().filter(|slot| slot.is_some()) {
+            *slot = None;
+            discarded += 1;
+        }
+        interrupts::restore(were_enabled);
+
+        if discarded > 0 {
+            oxid_warn!("Work queue shut down, discarded {} delayed work items.", discarded);
+        }
+
+        discarded
+    }
+}
+
+/// The main function of the kworker kernel thread. It promotes the due delayed work, runs all the
+/// pending work, and then waits for the next interrupt (which might have queued more work).
+///
+/// # Parameters
+/// `_args` : The arguments passed (not used).
+pub extern "sysv64" fn kworker(_args: *const Args) -> usize {
+    loop {
+        promote_delayed();
+        run_pending();
+
+        unsafe { crate::arch::proc::wait_for_interrupt(); }
+    }
+}
+
+/// A helper which adds a work item at the end of the ring buffer. Interrupts must be disabled.
+///
+/// # Parameters
+/// `work` : The work item which we're adding.
+///
+/// # Returns
+/// Ok if it was added, Err if the queue is full (the work is dropped) or is shut down.
+unsafe fn push(work: Work) -> Result<(), ()> {
+    if COUNT == QUEUE_SIZE || !ACCEPTING {
+        DROPPED += 1;
+        return Err(());
+    }
+
+    QUEUE[(HEAD + COUNT) % QUEUE_SIZE] = Some(work);
+    COUNT += 1;
+    Ok(())
+}
+
+/// A helper which removes the work item at the start of the ring buffer. Interrupts must be
+/// disabled.
+///
+/// # Returns
+/// Some(work) if there was any pending work, None otherwise.
+unsafe fn pop() -> Option<Work> {
+    if COUNT == 0 {
+        return None;
+    }
+
+    let work = QUEUE[HEAD].take();
+    HEAD = (HEAD + 1) % QUEUE_SIZE;
+    COUNT -= 1;
+    work
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use crate::arch::interrupts;
+
+    /// The order in which the test work items were run, and if the interrupts were enabled.
+    static mut RAN: [(usize, bool); 4] = [(0, false); 4];
+    static mut RAN_COUNT: usize = 0;
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_fifo_from_interrupt();
+        test_overflow();
+    }
+
+    /// A work function which records it's argument and the interrupt state.
+    fn record(arg: usize) {
+        unsafe {
+            if RAN_COUNT < RAN.len() {
+                RAN[RAN_COUNT] = (arg, interrupts::are_enabled());
+            }
+            RAN_COUNT += 1;
+        }
+    }
+
+    /// A work function which does nothing (used to fill the queue).
+    fn nothing(_arg: usize) {}
+
+    /// Queue work with the interrupts disabled (as an interrupt handler would), and make sure it
+    /// runs in order with the interrupts enabled.
+    fn test_fifo_from_interrupt() {
+        unsafe {
+            RAN_COUNT = 0;
+
+            // Simulate the interrupt context.
+            interrupts::disable();
+            for arg in 1..=3 {
+                assert_eq!(super::queue_work(record, arg), Ok(()));
+            }
+            assert!(!interrupts::are_enabled());
+            interrupts::enable();
+
+            // Wait for the kworker to run them in process context.
+            wait_until(|| RAN_COUNT >= 3);
+            assert_eq!(RAN_COUNT, 3);
+            assert_eq!(RAN[0], (1, true));
+            assert_eq!(RAN[1], (2, true));
+            assert_eq!(RAN[2], (3, true));
+        }
+    }
+
+    /// Fill up the queue and make sure the extra work is dropped and counted.
+    fn test_overflow() {
+        unsafe {
+            // Keep the kworker from draining it while we fill it up.
+            interrupts::disable();
+            let prev_dropped = super::dropped();
+            let free_slots = super::QUEUE_SIZE - super::pending();
+
+            for _ in 0..free_slots {
+                assert_eq!(super::queue_work(nothing, 0), Ok(()));
+            }
+            assert_eq!(super::queue_work(nothing, 0), Err(()));
+            assert_eq!(super::dropped(), prev_dropped + 1);
+            interrupts::enable();
+
+            wait_until(|| super::pending() == 0);
+            assert_eq!(super::pending(), 0);
+        }
+    }
+
+    /// A helper which waits (for up to a second) until a condition is true.
+    ///
+    /// # Parameters
+    /// `cond` : The condition which we're waiting for.
+    fn wait_until(cond: impl Fn() -> bool) {
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        while !cond() && crate::time::ticks() < deadline {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+    }
+}
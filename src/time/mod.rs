@@ -0,0 +1,376 @@
+//! A module which provides precise short delays (busy waits) for drivers. It chooses the best 
+//! available backend when it's initialized: a calibrated TSC spin (preferred), polling the PIT's 
+//! channel 2 (which works before anything is calibrated), or a crude loop as the last resort. All
+//! of them are behind delay_us, so the callers don't need to care which one is used.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+pub mod timeout;            // For the timeouts which are expired by the timer ticks.
+
+use crate::arch::time::{pit, tsc};
+use crate::proc::seqlock::SeqLock;
+
+/// The number of microseconds used to calibrate the TSC.
+const CALIBRATION_US: usize = 10_000;
+
+/// The number of iterations of the crude loop which are assumed to take a microsecond.
+const LOOPS_PER_US: usize = 100;
+
+/// The maximum error (percentage) which is accepted by the boot self-test.
+const MAX_ERROR_PERCENT: usize = 25;
+
+/// The delay used by the boot self-test (in microseconds).
+const SELF_TEST_US: usize = 1000;
+
+/// The number of timer ticks which the boot self-test is measured over (a single delay is much 
+/// shorter than a tick).
+const SELF_TEST_TICKS: usize = 10;
+
+/// The interval which the tick counter is polled at while waiting for a tick (in microseconds).
+const TICK_POLL_US: usize = 100;
+
+/// An enum which represents the backends which can be used for delays.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Backend {
+    Tsc,                        // Spin on the calibrated time stamp counter.
+    Pit,                        // Poll the channel 2 of the PIT.
+    Loop,                       // Spin a guessed number of iterations.
+}
+
+/// The length of a timer tick (PIT channel 0 at it's default 18.2 Hz) in microseconds.
+pub const TICK_US: usize = 54925;
+
+/// The number of seconds in a day (the RTC time of day wraps around after it).
+const SECONDS_PER_DAY: u64 = 86400;
+
+/// A structure which represents the time keeping state. The fields are always read together (so a 
+/// tick never changes in the middle of a conversion).
+#[derive(Copy, Clone, Debug)]
+pub struct TimeState {
+    pub ticks: u64,                            // The number of ticks since the timer was started.
+    pub tick_us: u64,                          // The length of a tick in microseconds.
+    pub boot_rtc: u32,                         // The RTC time of day at boot (seconds).
+}
+
+impl TimeState {
+    /// A method which calculates the time since the timer was started.
+    ///
+    /// # Returns
+    /// The uptime in milliseconds.
+    pub fn uptime_ms(&self) -> u64 {
+        self.ticks * self.tick_us / 1000
+    }
+}
+
+/// The backend which is currently used (PIT until the TSC is calibrated).
+static mut BACKEND: Backend = Backend::Pit;
+
+/// The time keeping state (it's only written by the timer interrupt, and once by init).
+static STATE: SeqLock<TimeState> = SeqLock::new(TimeState { ticks: 0, tick_us: TICK_US as u64, 
+    boot_rtc: 0 });
+
+/// The number of TSC cycles in a microsecond (set by calibration).
+static mut TSC_PER_US: u64 = 0;
+
+/// A function which is called by the timer interrupt on every tick (only used by the unit tests, to
+/// race the interrupt handlers against the code they interrupt).
+#[cfg(feature = "unit-test")]
+static mut TICK_HOOK: Option<fn()> = None;
+
+/// A function which chooses the backend for the delays. It calibrates the TSC against the PIT if 
+/// it's supported (the self-test is run later, with the bootdiag option, see self_test).
+pub unsafe fn init() {
+    // Remember when we booted (for the wall clock).
+    let boot_rtc = crate::arch::io::cmos::rtc_seconds_of_day();
+    STATE.write(|state| state.boot_rtc = boot_rtc);
+    
+    // Check if the PIT works first (it's needed for calibration).
+    if pit::delay_us(1).is_err() {
+        BACKEND = Backend::Loop;
+        oxid_warn!("The PIT did not respond. Using a loop for delays (imprecise).");
+        return;
+    }
+    
+    // Calibrate the TSC if it's supported (if the PIT stops responding, fall back to the loop).
+    if tsc::is_supported() {
+        let start = tsc::read();
+        if pit::delay_us(CALIBRATION_US).is_err() {
+            BACKEND = Backend::Loop;
+            oxid_warn!("The PIT stopped responding during calibration. Using a loop for delays \
+                (imprecise).");
+            return;
+        }
+        let end = tsc::read();
+        
+        TSC_PER_US = (end - start) / CALIBRATION_US as u64;
+        if TSC_PER_US > 0 {
+            BACKEND = Backend::Tsc;
+        }
+    }
+    
+    oxid_log!("Initialized the delay timer. backend={:?}, tsc_per_us={}", BACKEND, TSC_PER_US);
+}
+
+/// A function which busy waits for (at least) a given number of microseconds.
+///
+/// # Parameters
+/// `us` : The number of microseconds to wait.
+pub fn delay_us(us: usize) {
+    unsafe {
+        match BACKEND {
+            // Spin until enough cycles have passed.
+            Backend::Tsc => {
+                let end = tsc::read() + us as u64 * TSC_PER_US;
+                while tsc::read() < end {
+                    core::hint::spin_loop();
+                }
+            },
+            
+            // Poll the PIT, if it stops responding, fall back to the loop.
+            Backend::Pit => {
+                if pit::delay_us(us).is_err() {
+                    BACKEND = Backend::Loop;
+                    delay_us(us);
+                }
+            },
+            
+            // Spin the guessed number of iterations.
+            Backend::Loop => {
+                for _ in 0..(us * LOOPS_PER_US) {
+                    core::hint::spin_loop();
+                }
+            },
+        }
+    }
+}
+
+/// A function which waits (at least) a given number of milliseconds, and halts the CPU between the
+/// timer ticks. The ticks only advance if the interrupts are enabled, so it busy waits otherwise.
+/// A process is woken up by a timeout (see timeout), so nothing is left pointing to it's stack if 
+/// it's removed while it sleeps.
+///
+/// # Parameters
+/// `ms` : The number of milliseconds to wait.
+pub fn sleep_ms(ms: usize) {
+    if !unsafe { crate::arch::interrupts::are_enabled() } {
+        delay_us(ms * 1000);
+        return;
+    }
+    
+    if ms_to_ticks(ms) == 0 {
+        return;
+    }
+    
+    // Wait for the timeout to wake us up (the ticks are polled if there are too many timeouts, or 
+    // if the scheduler is not initialized yet).
+    let timeout = crate::proc::scheduler::current_handle().and_then(|handle| 
+        timeout::Timeout::new(ms, timeout::TimeoutTarget::WakeProcess(handle)).ok());
+    match timeout {
+        Some(timeout) => {
+            while !timeout.has_fired() {
+                unsafe { crate::arch::proc::wait_for_interrupt(); }
+            }
+            crate::proc::scheduler::take_wakeup();
+        },
+        None => {
+            let deadline = ticks() + ms_to_ticks(ms);
+            while ticks() < deadline {
+                unsafe { crate::arch::proc::wait_for_interrupt(); }
+            }
+        },
+    }
+}
+
+/// A function which is called by the timer interrupt on every tick. It also runs the targets of the
+/// expired timeouts.
+#[inline]
+pub fn tick() {
+    STATE.write(|state| state.ticks += 1);
+    timeout::expire(ticks());
+    
+    #[cfg(feature = "unit-test")]
+    if let Some(hook) = unsafe { TICK_HOOK } {
+        hook();
+    }
+}
+
+/// A function which sets (or removes) the function which is called on every tick. It's only used
+/// by the unit tests.
+///
+/// # Parameters
+/// `hook` : The function which is called in the timer interrupt (None to remove it).
+#[cfg(feature = "unit-test")]
+pub fn set_tick_hook(hook: Option<fn()>) {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        TICK_HOOK = hook;
+        crate::arch::interrupts::restore(were_enabled);
+    }
+}
+
+/// A simple getter for the number of timer ticks since the timer was started.
+///
+/// # Returns
+/// The number of ticks.
+#[inline]
+pub fn ticks() -> u64 {
+    STATE.read().ticks
+}
+
+/// A function which reads all the time keeping state at once (without blocking).
+///
+/// # Returns
+/// A consistent copy of the state.
+pub fn state() -> TimeState {
+    STATE.read()
+}
+
+/// A function which calculates the time since the timer was started.
+///
+/// # Returns
+/// The uptime in milliseconds.
+pub fn uptime_ms() -> u64 {
+    STATE.read().uptime_ms()
+}
+
+/// A function which calculates the current time of day, based on the RTC at boot and the uptime.
+///
+/// # Returns
+/// The number of seconds since midnight.
+pub fn time_of_day() -> u32 {
+    let state = STATE.read();
+    ((state.boot_rtc as u64 + state.uptime_ms() / 1000) % SECONDS_PER_DAY) as u32
+}
+
+/// A function which converts a number of milliseconds to timer ticks (rounded up).
+///
+/// # Parameters
+/// `ms` : The number of milliseconds.
+///
+/// # Returns
+/// The number of ticks which take at least that long.
+#[inline]
+pub fn ms_to_ticks(ms: usize) -> u64 {
+    let tick_us = STATE.read().tick_us;
+    (ms as u64 * 1000 + tick_us - 1) / tick_us
+}
+
+/// A function which converts a number of TSC cycles to microseconds (based on the calibration).
+///
+/// # Parameters
+/// `cycles` : The number of TSC cycles.
+///
+/// # Returns
+/// The number of microseconds, or None if the TSC is not calibrated.
+pub fn cycles_to_us(cycles: u64) -> Option<u64> {
+    unsafe {
+        if TSC_PER_US == 0 { None } else { Some(cycles / TSC_PER_US) }
+    }
+}
+
+/// A simple getter for the backend which is currently used.
+///
+/// # Returns
+/// The backend used by delay_us.
+pub fn backend() -> Backend {
+    unsafe { BACKEND }
+}
+
+/// A function which measures delay_us against the timer ticks and logs the error. A single delay
+/// is much shorter than a tick, so it's repeated for SELF_TEST_TICKS ticks. If the error is too
+/// large, it fails loudly (and falls back to the PIT if the TSC was used). It needs the timer 
+/// ticks, so it should be called after the scheduler is initialized (see bootdiag).
+///
+/// # Returns
+/// The error of the measured delay (percentage).
+pub unsafe fn self_test() -> usize {
+    // Start right after a tick (so a partial tick is not counted).
+    let start = match wait_for_tick(ticks()) {
+        Some(start) => start,
+        None => {
+            oxid_warn!("Delay self-test skipped (the timer ticks are not running).");
+            return 0;
+        },
+    };
+    
+    // Do the delays, and see how many ticks have passed (the last one is counted as half a tick).
+    let tick_us = state().tick_us as usize;
+    let expected_us = SELF_TEST_TICKS * tick_us / SELF_TEST_US * SELF_TEST_US;
+    for _ in 0..(expected_us / SELF_TEST_US) {
+        delay_us(SELF_TEST_US);
+    }
+    let elapsed_us = (ticks() - start) as usize * tick_us + tick_us / 2;
+    
+    // Calculate the error percentage.
+    let error = (core::cmp::max(elapsed_us, expected_us) - core::cmp::min(elapsed_us, expected_us))
+        * 100 / expected_us;
+    
+    if error > MAX_ERROR_PERCENT {
+        oxid_err!("Delay self-test failed. {}x delay_us({}) took {}us ({}% error).", 
+            expected_us / SELF_TEST_US, SELF_TEST_US, elapsed_us, error);
+        if BACKEND == Backend::Tsc {
+            oxid_err!("Using the PIT for delays.");
+            BACKEND = Backend::Pit;
+        }
+    } else {
+        oxid_log!("Delay self-test passed. {}x delay_us({}) took {}us ({}% error).", 
+            expected_us / SELF_TEST_US, SELF_TEST_US, elapsed_us, error);
+    }
+    
+    error
+}
+
+/// A helper which waits for the tick counter to pass a given tick (for up to a few ticks).
+///
+/// # Parameters
+/// `tick` : The tick which we're waiting to pass.
+///
+/// # Returns
+/// Some(current_tick), or None if the ticks are not advancing.
+fn wait_for_tick(tick: u64) -> Option<u64> {
+    for _ in 0..(TICK_US * 4 / TICK_POLL_US) {
+        let current = ticks();
+        if current != tick {
+            return Some(current);
+        }
+        
+        delay_us(TICK_POLL_US);
+    }
+    
+    None
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests. 
+#[cfg(feature = "unit-test")]
+pub mod test {
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this 
+    /// sub module. 
+    pub fn run() {
+        test_delay_ordering();
+        super::timeout::test::run();
+    }
+    
+    /// Make sure a longer delay actually takes longer (measured with the TSC).
+    fn test_delay_ordering() {
+        // Only possible when there is a TSC to measure with.
+        if !crate::arch::time::tsc::is_supported() {
+            return;
+        }
+        
+        let start = crate::arch::time::tsc::read();
+        super::delay_us(100);
+        let short = crate::arch::time::tsc::read() - start;
+        
+        let start = crate::arch::time::tsc::read();
+        super::delay_us(2000);
+        let long = crate::arch::time::tsc::read() - start;
+        
+        assert!(long > short);
+    }
+}
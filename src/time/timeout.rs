@@ -0,0 +1,420 @@
+//! A sub-module which provides the timeouts for the drivers and the processes. A timeout runs it's
+//! target once, on the first timer tick after it's deadline. The targets are a fixed set of actions
+//! which are done by this module (waking up a process, posting work to the work queue, or setting a
+//! flag), so none of the owner's code runs in the timer interrupt, and the processes are only
+//! reached through their handles (a removed process is never woken up). A timeout can be cancelled,
+//! and the outcome is always certain: either the target already ran, or it never will. Both the
+//! expiry and the cancellation are done with the interrupts disabled, so they can't overlap.
+//!
+//! The timeouts are kept in a fixed size table (arming one never allocates). The table is passed
+//! the current tick when it expires the timeouts, so the tests can use their own table and inject
+//! the ticks in any order.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::proc::pid::ProcHandle;
+use crate::proc::workqueue::{self, WorkFn};
+
+/// The maximum number of timeouts which can be armed at the same time.
+pub const MAX_TIMEOUTS: usize = 32;
+
+/// A flag which is set by a timeout (it can be read and set from any context).
+pub struct AtomicFlag(AtomicBool);
+
+impl AtomicFlag {
+    /// A constructor which creates a flag which is not set.
+    ///
+    /// # Returns
+    /// The newly created flag.
+    pub const fn new() -> Self {
+        AtomicFlag(AtomicBool::new(false))
+    }
+
+    /// A method which sets the flag.
+    pub fn set(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// A simple getter for the flag.
+    ///
+    /// # Returns
+    /// True if it's set, False otherwise.
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// A method which clears the flag, and returns it's previous value.
+    ///
+    /// # Returns
+    /// True if it was set, False otherwise.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// An enum which represents what is done when a timeout expires.
+#[derive(Copy, Clone, Debug)]
+pub enum TimeoutTarget {
+    WakeProcess(ProcHandle),        // Wake up a process (see scheduler::wake).
+    PostWork(WorkFn, usize),        // Queue a function (and it's argument) to the kworker thread.
+    SetFlag(*const AtomicFlag),     // Set a flag (it should outlive the timeout).
+}
+
+/// An enum which represents the outcome of cancelling a timeout.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CancelResult {
+    Cancelled,                      // The target did not run (and it never will).
+    Fired,                          // The target already ran.
+}
+
+/// A structure which represents an armed timeout in the table.
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    deadline: u64,                  // The tick which the target runs at (or after).
+    target: TimeoutTarget,          // What is done when it expires.
+}
+
+/// A structure which keeps the armed timeouts. Every slot has a generation which is bumped each
+/// time it's armed, so a cancellation never reaches a newer timeout in the same slot.
+pub struct TimeoutTable {
+    entries: [Option<Entry>; MAX_TIMEOUTS], // The armed timeouts.
+    gens: [u32; MAX_TIMEOUTS],              // The generation each slot was last armed in.
+    fired: usize,                           // The number of targets which ran.
+}
+
+impl TimeoutTable {
+    /// A constructor which creates a table without any timeouts.
+    ///
+    /// # Returns
+    /// The newly created table.
+    pub const fn new() -> Self {
+        TimeoutTable { entries: [None; MAX_TIMEOUTS], gens: [0; MAX_TIMEOUTS], fired: 0 }
+    }
+
+    /// A method which arms a timeout in the first free slot.
+    ///
+    /// # Parameters
+    /// `deadline` : The tick which the target runs at (or after).
+    /// `target` : What is done when it expires.
+    ///
+    /// # Returns
+    /// Some((slot, generation)) of the timeout, or None if the table is full.
+    pub fn arm(&mut self, deadline: u64, target: TimeoutTarget) -> Option<(usize, u32)> {
+        let slot = self.entries.iter().position(|entry| entry.is_none())?;
+
+        self.gens[slot] = self.gens[slot].wrapping_add(1);
+        self.entries[slot] = Some(Entry { deadline, target });
+        Some((slot, self.gens[slot]))
+    }
+
+    /// A method which cancels a timeout (if it's still armed).
+    ///
+    /// # Parameters
+    /// `slot` : The slot of the timeout.
+    /// `gen` : The generation of the timeout.
+    ///
+    /// # Returns
+    /// Cancelled if it was still armed, Fired if it already expired.
+    pub fn cancel(&mut self, slot: usize, gen: u32) -> CancelResult {
+        if !self.is_pending(slot, gen) {
+            return CancelResult::Fired;
+        }
+
+        self.entries[slot] = None;
+        CancelResult::Cancelled
+    }
+
+    /// A method which checks if a timeout is still armed.
+    ///
+    /// # Parameters
+    /// `slot` : The slot of the timeout.
+    /// `gen` : The generation of the timeout.
+    ///
+    /// # Returns
+    /// True if it did not expire (and it was not cancelled), False otherwise.
+    pub fn is_pending(&self, slot: usize, gen: u32) -> bool {
+        self.entries[slot].is_some() && self.gens[slot] == gen
+    }
+
+    /// A method which runs the targets of the timeouts whose deadline has passed, and frees their
+    /// slots. The work which can't be queued (the queue is full) stays armed, and it's tried again
+    /// on the next call.
+    ///
+    /// # Parameters
+    /// `now` : The current tick.
+    ///
+    /// # Returns
+    /// The number of targets which ran.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let mut fired: usize = 0;
+        for slot in self.entries.iter_mut() {
+            if let Some(entry) = *slot {
+                if entry.deadline <= now && fire(entry.target) {
+                    *slot = None;
+                    fired += 1;
+                }
+            }
+        }
+
+        self.fired += fired;
+        fired
+    }
+
+    /// A simple getter for the number of armed timeouts.
+    ///
+    /// # Returns
+    /// The number of used slots.
+    pub fn num_armed(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+}
+
+/// The timeouts of the system (they're expired by the timer interrupt).
+static mut TIMEOUTS: TimeoutTable = TimeoutTable::new();
+
+/// A timeout which is armed in the table of the system. It's cancelled when it's dropped (if it did
+/// not expire yet), so a flag it points to can be dropped right after it.
+#[must_use]
+pub struct Timeout {
+    slot: usize,                    // The slot of the timeout.
+    gen: u32,                       // The generation of the slot when it was armed.
+}
+
+impl Timeout {
+    /// A constructor which arms a timeout.
+    ///
+    /// # Parameters
+    /// `ms` : The minimum time before the target runs (in milliseconds).
+    /// `target` : What is done when it expires.
+    ///
+    /// # Returns
+    /// The armed timeout, or Err if there are too many armed timeouts.
+    pub fn new(ms: usize, target: TimeoutTarget) -> Result<Self, ()> {
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(ms);
+        with_table(|table| table.arm(deadline, target))
+            .map(|(slot, gen)| Timeout { slot, gen })
+            .ok_or(())
+    }
+
+    /// A method which cancels the timeout.
+    ///
+    /// # Returns
+    /// Cancelled if the target never ran (and it never will), or Fired if it already ran.
+    pub fn cancel(self) -> CancelResult {
+        let result = with_table(|table| table.cancel(self.slot, self.gen));
+        core::mem::forget(self);
+        result
+    }
+
+    /// A method which checks if the timeout expired (and it's target ran).
+    ///
+    /// # Returns
+    /// True if it expired, False otherwise.
+    pub fn has_fired(&self) -> bool {
+        !with_table(|table| table.is_pending(self.slot, self.gen))
+    }
+}
+
+impl Drop for Timeout {
+    fn drop(&mut self) {
+        with_table(|table| table.cancel(self.slot, self.gen));
+    }
+}
+
+/// A function which runs the targets of the expired timeouts. It's called by the timer interrupt on
+/// every tick.
+///
+/// # Parameters
+/// `now` : The current tick.
+///
+/// # Returns
+/// The number of targets which ran.
+pub fn expire(now: u64) -> usize {
+    with_table(|table| table.expire(now))
+}
+
+/// A simple getter for the number of armed timeouts (and the number of targets which ran).
+///
+/// # Returns
+/// A tuple of (armed, fired).
+pub fn stats() -> (usize, usize) {
+    with_table(|table| (table.num_armed(), table.fired))
+}
+
+/// A helper which calls a function with the table of the system (with the interrupts disabled).
+///
+/// # Parameters
+/// `func` : The function which is called with the table.
+///
+/// # Returns
+/// The function's result.
+fn with_table<R>(func: impl FnOnce(&mut TimeoutTable) -> R) -> R {
+    unsafe {
+        let were_enabled = crate::arch::interrupts::save_and_disable();
+        let result = func(&mut TIMEOUTS);
+        crate::arch::interrupts::restore(were_enabled);
+
+        result
+    }
+}
+
+/// A helper which runs the target of an expired timeout.
+///
+/// # Parameters
+/// `target` : What is done.
+///
+/// # Returns
+/// True if it ran, False if it should be tried again (the work queue is full).
+fn fire(target: TimeoutTarget) -> bool {
+    match target {
+        TimeoutTarget::WakeProcess(handle) => {
+            crate::proc::scheduler::wake(handle);
+            true
+        },
+        TimeoutTarget::PostWork(func, arg) => workqueue::queue_work(func, arg).is_ok(),
+        TimeoutTarget::SetFlag(flag) => {
+            unsafe { (*flag).set(); }
+            true
+        },
+    }
+}
+
+// Unit Tests **************************************************************************************
+
+/// The main test function which will call all the unit tests. This code will only be compiled
+/// in the test configuration. Add the unit-test feature to cargo to run all the unit tests.
+#[cfg(feature = "unit-test")]
+pub mod test {
+    use super::{AtomicFlag, CancelResult, Timeout, TimeoutTable, TimeoutTarget, MAX_TIMEOUTS};
+
+    /// The flag which is set by the test timeouts.
+    static FLAG: AtomicFlag = AtomicFlag::new();
+
+    /// The number of times the posted work ran, and it's last argument.
+    static mut POSTED: (usize, usize) = (0, 0);
+
+    /// The "main" function for the unit tests. It basically calls all the other unit tests in this
+    /// sub module.
+    pub fn run() {
+        test_fire_then_cancel();
+        test_cancel_then_fire();
+        test_targets();
+        test_full();
+        test_system_table();
+    }
+
+    /// A work function which counts the runs, and records it's argument.
+    fn posted(arg: usize) {
+        unsafe { POSTED = (POSTED.0 + 1, arg); }
+    }
+
+    /// Inject the ticks up to the deadline, and then cancel it. The target should run exactly once
+    /// (the later ticks don't run it again), and the cancellation should report it.
+    fn test_fire_then_cancel() {
+        let mut table = TimeoutTable::new();
+        FLAG.take();
+
+        let (slot, gen) = table.arm(10, TimeoutTarget::SetFlag(&FLAG)).unwrap();
+        assert_eq!(table.expire(9), 0);
+        assert!(table.is_pending(slot, gen) && !FLAG.is_set());
+
+        assert_eq!(table.expire(10), 1);
+        assert!(FLAG.take());
+        assert_eq!(table.expire(11), 0);
+        assert_eq!(table.expire(1000), 0);
+        assert!(!FLAG.is_set());
+
+        assert_eq!(table.cancel(slot, gen), CancelResult::Fired);
+        assert_eq!(table.num_armed(), 0);
+    }
+
+    /// Cancel it before the deadline, and then inject the ticks past it. The target should never
+    /// run, and a stale cancellation should not reach a newer timeout in the same slot.
+    fn test_cancel_then_fire() {
+        let mut table = TimeoutTable::new();
+        FLAG.take();
+
+        let (slot, gen) = table.arm(10, TimeoutTarget::SetFlag(&FLAG)).unwrap();
+        assert_eq!(table.expire(5), 0);
+        assert_eq!(table.cancel(slot, gen), CancelResult::Cancelled);
+        assert_eq!(table.expire(10), 0);
+        assert_eq!(table.expire(1000), 0);
+        assert!(!FLAG.is_set());
+
+        // The slot is reused with a new generation.
+        let (reused, new_gen) = table.arm(20, TimeoutTarget::SetFlag(&FLAG)).unwrap();
+        assert_eq!(reused, slot);
+        assert_eq!(table.cancel(slot, gen), CancelResult::Fired);
+        assert!(table.is_pending(reused, new_gen));
+        assert_eq!(table.expire(20), 1);
+        assert!(FLAG.take());
+    }
+
+    /// Expire a wakeup of the running process, and posted work (which runs in the kworker).
+    fn test_targets() {
+        let mut table = TimeoutTable::new();
+        let handle = crate::proc::scheduler::current_handle().unwrap();
+        crate::proc::scheduler::take_wakeup();
+
+        table.arm(3, TimeoutTarget::WakeProcess(handle)).unwrap();
+        let runs = unsafe { POSTED.0 };
+        table.arm(4, TimeoutTarget::PostWork(posted, 77)).unwrap();
+        assert_eq!(table.expire(3), 1);
+        assert!(crate::proc::scheduler::take_wakeup());
+        assert!(!crate::proc::scheduler::take_wakeup());
+        assert_eq!(table.expire(4), 1);
+
+        // Wait (for up to a second) for the kworker to run it.
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        while unsafe { POSTED.0 } == runs && crate::time::ticks() < deadline {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+        assert_eq!(unsafe { POSTED }, (runs + 1, 77));
+        assert_eq!(table.expire(1000), 0);
+    }
+
+    /// Fill the table, and make sure the next timeout is rejected until one is cancelled.
+    fn test_full() {
+        let mut table = TimeoutTable::new();
+        let timeouts: alloc::vec::Vec<_> = (0..MAX_TIMEOUTS)
+            .map(|idx| table.arm(idx as u64, TimeoutTarget::SetFlag(&FLAG)).unwrap()).collect();
+        assert!(table.arm(0, TimeoutTarget::SetFlag(&FLAG)).is_none());
+
+        let (slot, gen) = timeouts[7];
+        assert_eq!(table.cancel(slot, gen), CancelResult::Cancelled);
+        assert_eq!(table.arm(0, TimeoutTarget::SetFlag(&FLAG)).map(|(slot, _)| slot), Some(7));
+    }
+
+    /// Use the timeouts of the system, a long one is cancelled (or dropped) before it expires, and
+    /// a short one expires on the next tick.
+    fn test_system_table() {
+        FLAG.take();
+
+        let long = Timeout::new(60_000, TimeoutTarget::SetFlag(&FLAG)).unwrap();
+        assert!(!long.has_fired());
+        assert_eq!(long.cancel(), CancelResult::Cancelled);
+
+        let dropped = Timeout::new(60_000, TimeoutTarget::SetFlag(&FLAG)).unwrap();
+        let (slot, gen) = (dropped.slot, dropped.gen);
+        drop(dropped);
+        assert!(!super::with_table(|table| table.is_pending(slot, gen)));
+
+        let short = Timeout::new(0, TimeoutTarget::SetFlag(&FLAG)).unwrap();
+        let deadline = crate::time::ticks() + crate::time::ms_to_ticks(1000);
+        while !short.has_fired() && crate::time::ticks() < deadline {
+            unsafe { crate::arch::proc::wait_for_interrupt(); }
+        }
+        assert!(FLAG.take());
+        assert_eq!(short.cancel(), CancelResult::Fired);
+        assert!(!FLAG.is_set());
+
+        // The sleeps are woken up by a timeout (and they take at least as long as requested).
+        let start = crate::time::ticks();
+        crate::time::sleep_ms(100);
+        assert!(crate::time::ticks() - start >= crate::time::ms_to_ticks(100));
+    }
+}
@@ -0,0 +1,20 @@
+//! A module which holds the version metadata of the kernel. The git hash and build date are 
+//! embedded at build time by the build script (build.rs). The enabled features are in the 
+//! features module.
+//!
+//! `Author` : Ardalan Ahanchi
+//! `Date` : Mar 2021
+
+#![allow(dead_code)]
+
+/// The name of the kernel.
+pub const NAME: &str = "Oxid OS";
+
+/// The version of the kernel (from Cargo.toml).
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The short hash of the commit which was built ("unknown" if git was not available).
+pub const GIT_HASH: &str = env!("OXID_GIT_HASH");
+
+/// The date and time (UTC) of the build.
+pub const BUILD_DATE: &str = env!("OXID_BUILD_DATE");